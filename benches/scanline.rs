@@ -0,0 +1,57 @@
+// Times scanline rendering with the background and window enabled, on DMG and CGB.
+// Run with `cargo bench --features internals --bench scanline`.
+use std::hint::black_box;
+use std::time::Instant;
+
+use retroboy::emulator::{self, initialize_screenless_emulator, Emulator, Mode};
+use retroboy::gpu::scanline::write_scanline;
+
+const FRAMES: u32 = 2000;
+const VISIBLE_SCANLINES: u8 = 144;
+
+fn setup_emulator(mode: Mode) -> Emulator {
+    let mut emulator = initialize_screenless_emulator();
+    emulator::set_mode(&mut emulator, mode);
+    emulator.memory.in_bios = false;
+
+    // Fill tile data, both tile maps and the CGB attribute maps with varied bytes.
+    for (index, byte) in emulator.gpu.video_ram.iter_mut().enumerate() {
+        *byte = (index as u32).wrapping_mul(2654435761).rotate_right(13) as u8;
+    }
+
+    emulator.gpu.registers.lcdc = 0b11110011;
+    emulator.gpu.registers.palettes.bgp = 0b11100100;
+    emulator.gpu.registers.scx = 3;
+    emulator.gpu.registers.scy = 5;
+    emulator.gpu.registers.wx = 87;
+    emulator.gpu.registers.wy = 72;
+    emulator
+}
+
+fn bench(name: &str, mode: Mode) {
+    let mut emulator = setup_emulator(mode);
+    let start = Instant::now();
+
+    for _ in 0..FRAMES {
+        emulator.gpu.registers.wly = 0;
+        for ly in 0..VISIBLE_SCANLINES {
+            emulator.gpu.registers.ly = ly;
+            write_scanline(black_box(&mut emulator));
+            if ly >= emulator.gpu.registers.wy {
+                emulator.gpu.registers.wly += 1;
+            }
+        }
+    }
+
+    let elapsed = start.elapsed();
+    let scanlines = FRAMES * VISIBLE_SCANLINES as u32;
+    println!("{}: {:.0} ns per scanline, {:.1} us per frame", name,
+        elapsed.as_nanos() as f64 / scanlines as f64,
+        elapsed.as_micros() as f64 / FRAMES as f64);
+    black_box(&emulator.gpu.frame_buffer);
+}
+
+fn main() {
+    bench("DMG background and window", Mode::DMG);
+    bench("CGB background and window", Mode::CGB);
+}
@@ -0,0 +1,98 @@
+// Runs every ROM given (or found in the given directories) headlessly and prints a compatibility report per game.
+// Run with `cargo run --release --example compat_report -- [--seconds N] [--cgb] path/to/roms`.
+//
+// Output is tab-separated so it can be pasted into a spreadsheet or diffed between builds.
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use retroboy::prelude::*;
+
+const DEFAULT_SECONDS: u32 = 30;
+
+fn is_rom(path: &Path) -> bool {
+    matches!(path.extension().and_then(|extension| extension.to_str()), Some("gb" | "gbc"))
+}
+
+fn collect_roms(path: PathBuf, roms: &mut Vec<PathBuf>) -> Result<(), String> {
+    if path.is_dir() {
+        let entries = fs::read_dir(&path).map_err(|err| format!("Unable to read {}: {}", path.display(), err))?;
+        let mut children: Vec<PathBuf> = entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect();
+        children.sort();
+        for child in children {
+            if child.is_dir() || is_rom(&child) {
+                collect_roms(child, roms)?;
+            }
+        }
+    }
+    else {
+        roms.push(path);
+    }
+    Ok(())
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value { "yes" } else { "no" }
+}
+
+fn describe_unmapped_writes(report: &CompatibilityReport) -> String {
+    report.unmapped_writes.iter()
+        .map(|write| format!("{:04X}", write.address))
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+fn run() -> Result<(), String> {
+    let mut seconds = DEFAULT_SECONDS;
+    let mut model = HardwareModel::DMG;
+    let mut roms = Vec::new();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--seconds" => {
+                seconds = args.next()
+                    .and_then(|value| value.parse().ok())
+                    .ok_or("--seconds needs a number")?;
+            }
+            "--cgb" => model = HardwareModel::CGB,
+            _ => collect_roms(PathBuf::from(arg), &mut roms)?
+        }
+    }
+
+    if roms.is_empty() {
+        return Err("Usage: compat_report [--seconds N] [--cgb] <rom or directory>...".to_string());
+    }
+
+    println!("rom\ttitle\tgame code\tlcd frames\tdistinct frames\taudio\tunmapped writes\tverdict");
+
+    for rom_path in roms {
+        let report = fs::read(&rom_path)
+            .map_err(|err| err.to_string())
+            .and_then(|rom| run_compatibility_check(&rom, model, seconds).map_err(|err| err.to_string()));
+
+        match report {
+            Ok(report) => println!("{}\t{}\t{}\t{}/{}\t{}\t{}\t{}\t{}",
+                rom_path.display(),
+                report.title,
+                yes_no(report.reached_game_code),
+                report.lcd_enabled_frames,
+                report.frames_run,
+                report.distinct_frames,
+                yes_no(report.audio_produced),
+                describe_unmapped_writes(&report),
+                if report.looks_playable() { "ok" } else { "check" }),
+            Err(message) => println!("{}\t\t\t\t\t\t\terror: {}", rom_path.display(), message.replace('\n', " "))
+        }
+    }
+
+    Ok(())
+}
+
+fn main() {
+    if let Err(message) = run() {
+        eprintln!("{}", message);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,197 @@
+// A desktop frontend built on minifb, showing savestates, rewind, fast forward and color correction.
+// Run with `cargo run --release --features minifb --example minifb -- path/to/rom.gb`.
+// minifb has no audio output, so the game plays silently.
+//
+// Arrow keys: D-pad    Z/X: B/A    Enter: Start    Backspace: Select
+// F5: save state       F8: load state (kept next to the ROM as <rom>.state)
+// Hold R: rewind       Hold Tab: fast forward       C: cycle color correction
+use std::collections::VecDeque;
+use std::env;
+use std::fs;
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use minifb::{Key as WindowKey, KeyRepeat, Scale, Window, WindowOptions};
+use retroboy::prelude::*;
+
+const SCREEN_WIDTH: usize = 160;
+const SCREEN_HEIGHT: usize = 144;
+const FAST_FORWARD_SPEED: u32 = 4;
+// A snapshot every other frame for about ten seconds.
+const REWIND_INTERVAL_FRAMES: u32 = 2;
+const REWIND_CAPACITY: usize = 300;
+
+const KEY_MAP: [(WindowKey, Key); 8] = [
+    (WindowKey::Up, Key::Up),
+    (WindowKey::Down, Key::Down),
+    (WindowKey::Left, Key::Left),
+    (WindowKey::Right, Key::Right),
+    (WindowKey::Enter, Key::Start),
+    (WindowKey::Backspace, Key::Select),
+    (WindowKey::Z, Key::B),
+    (WindowKey::X, Key::A)
+];
+
+/*
+    Snapshots taken while playing, newest last. Once full, the oldest snapshot's
+    buffer is reused for the next capture, so rewinding allocates nothing after the
+    first ten seconds.
+*/
+struct RewindBuffer {
+    snapshots: VecDeque<StateSnapshot>,
+    frames_since_capture: u32
+}
+
+impl RewindBuffer {
+    fn new() -> RewindBuffer {
+        RewindBuffer {
+            snapshots: VecDeque::with_capacity(REWIND_CAPACITY),
+            frames_since_capture: 0
+        }
+    }
+
+    fn clear(&mut self) {
+        self.snapshots.clear();
+        self.frames_since_capture = 0;
+    }
+
+    fn record_frame(&mut self, game_boy: &GameBoy) {
+        self.frames_since_capture += 1;
+        if self.frames_since_capture < REWIND_INTERVAL_FRAMES {
+            return;
+        }
+        self.frames_since_capture = 0;
+
+        let mut snapshot = if self.snapshots.len() == REWIND_CAPACITY {
+            self.snapshots.pop_front().unwrap_or_default()
+        }
+        else {
+            StateSnapshot::new()
+        };
+        game_boy.clone_state(&mut snapshot);
+        self.snapshots.push_back(snapshot);
+    }
+
+    // Steps back one snapshot, keeping the oldest one so rewinding stops there.
+    fn rewind(&mut self, game_boy: &mut GameBoy) -> Result<(), String> {
+        let snapshot = if self.snapshots.len() > 1 { self.snapshots.pop_back() } else { None };
+        match snapshot.as_ref().or(self.snapshots.back()) {
+            Some(snapshot) => game_boy.restore_state(snapshot).map_err(|err| err.to_string()),
+            None => Ok(())
+        }
+    }
+}
+
+fn next_color_correction(color_correction: ColorCorrection) -> ColorCorrection {
+    match color_correction {
+        ColorCorrection::Raw => ColorCorrection::Gbc,
+        ColorCorrection::Gbc => ColorCorrection::Gba,
+        ColorCorrection::Gba => ColorCorrection::Raw
+    }
+}
+
+// minifb wants 0RGB pixels packed into u32s.
+fn copy_screen(screen: &[u8], buffer: &mut [u32]) {
+    for (pixel, rgba) in buffer.iter_mut().zip(screen.chunks_exact(4)) {
+        *pixel = u32::from_be_bytes([0, rgba[0], rgba[1], rgba[2]]);
+    }
+}
+
+fn update_input(window: &Window, game_boy: &mut GameBoy) {
+    for (window_key, key) in KEY_MAP {
+        if window.is_key_down(window_key) {
+            game_boy.press(key);
+        }
+        else {
+            game_boy.release(key);
+        }
+    }
+}
+
+fn run() -> Result<(), String> {
+    let rom_path = env::args().nth(1).ok_or("Usage: minifb <rom.gb>")?;
+    let state_path = format!("{}.state", rom_path);
+    let rom = fs::read(&rom_path).map_err(|err| format!("Unable to read {}: {}", rom_path, err))?;
+
+    let mut game_boy = GameBoy::new(HardwareModel::CGB);
+    let header = game_boy.insert_cartridge(&rom).map_err(|err| format!("Unable to load {}: {}", rom_path, err))?;
+
+    let window_options = WindowOptions { scale: Scale::X4, ..WindowOptions::default() };
+    let mut window = Window::new(&format!("RetroBoy - {}", header.title), SCREEN_WIDTH, SCREEN_HEIGHT, window_options)
+        .map_err(|err| err.to_string())?;
+    // Frames are paced below rather than by minifb, which would cap fast forward at the display's rate.
+    window.set_target_fps(0);
+
+    let mut buffer = vec![0u32; SCREEN_WIDTH * SCREEN_HEIGHT];
+    let mut rewind_buffer = RewindBuffer::new();
+    let mut color_correction = ColorCorrection::Raw;
+    let mut pacer = game_boy.frame_pacer();
+    let start = Instant::now();
+
+    while window.is_open() && !window.is_key_down(WindowKey::Escape) {
+        let frames = match pacer.next_step(start.elapsed().as_secs_f64() * 1000.0) {
+            PacingStep::Sleep { millis } => {
+                thread::sleep(Duration::from_secs_f64(millis / 1000.0));
+                continue;
+            }
+            PacingStep::RunFrames { frames } => frames
+        };
+
+        if window.is_key_pressed(WindowKey::F5, KeyRepeat::No) {
+            match fs::write(&state_path, game_boy.save_state()) {
+                Ok(()) => println!("Saved state to {}", state_path),
+                Err(err) => eprintln!("Unable to save state: {}", err)
+            }
+        }
+
+        if window.is_key_pressed(WindowKey::F8, KeyRepeat::No) {
+            match fs::read(&state_path).and_then(|state| game_boy.load_state(&state)) {
+                Ok(()) => {
+                    // Snapshots taken before loading would rewind into a different timeline.
+                    rewind_buffer.clear();
+                    println!("Loaded state from {}", state_path);
+                }
+                Err(err) => eprintln!("Unable to load state: {}", err)
+            }
+        }
+
+        if window.is_key_pressed(WindowKey::C, KeyRepeat::No) {
+            color_correction = next_color_correction(color_correction);
+            game_boy.set_color_correction(color_correction);
+            println!("Color correction: {:?}", color_correction);
+        }
+
+        update_input(&window, &mut game_boy);
+
+        if window.is_key_down(WindowKey::R) {
+            // Restoring a snapshot doesn't redraw the screen, so run a frame from it to show where it is.
+            for _ in 0..frames {
+                rewind_buffer.rewind(&mut game_boy)?;
+            }
+            game_boy.run_frame();
+        }
+        else {
+            let speed = if window.is_key_down(WindowKey::Tab) { FAST_FORWARD_SPEED } else { 1 };
+            for _ in 0..frames * speed {
+                game_boy.run_frame();
+                rewind_buffer.record_frame(&game_boy);
+            }
+        }
+
+        // Nothing plays the samples, so drop them before they pile up.
+        game_boy.audio_samples();
+
+        copy_screen(game_boy.screen(), &mut buffer);
+        window.update_with_buffer(&buffer, SCREEN_WIDTH, SCREEN_HEIGHT).map_err(|err| err.to_string())?;
+    }
+
+    Ok(())
+}
+
+fn main() {
+    if let Err(message) = run() {
+        eprintln!("{}", message);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,277 @@
+// A minimal SDL2 frontend: drop a ROM on the window (or pass its path) to play it.
+// Run with `cargo run --release --features sdl --example sdl -- [rom.gb] [--frames N]`.
+// With --frames it exits after running that many frames, printing anything the game
+// sent over the link port, so it doubles as a smoke test for test ROMs.
+// Controllers SDL recognises work alongside the keyboard.
+use std::collections::VecDeque;
+use std::env;
+use std::fs;
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use retroboy::prelude::*;
+use sdl2::audio::{AudioCallback, AudioSpecDesired};
+use sdl2::controller::{Axis, Button};
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::PixelFormatEnum;
+
+const SCREEN_WIDTH: u32 = 160;
+const SCREEN_HEIGHT: u32 = 144;
+const WINDOW_SCALE: u32 = 4;
+const SAMPLE_RATE: i32 = 44100;
+const AUDIO_BUFFER_FRAMES: u16 = 1024;
+// Samples beyond this are dropped so audio can't fall further and further behind the picture.
+const MAX_QUEUED_SAMPLES: usize = AUDIO_BUFFER_FRAMES as usize * 2 * 4;
+
+type SampleQueue = Arc<Mutex<VecDeque<f32>>>;
+
+// Plays interleaved stereo samples queued by the main loop, with silence on underruns.
+struct Speaker {
+    samples: SampleQueue
+}
+
+impl AudioCallback for Speaker {
+    type Channel = f32;
+
+    fn callback(&mut self, output: &mut [f32]) {
+        let mut samples = self.samples.lock().unwrap();
+        for sample in output.iter_mut() {
+            *sample = samples.pop_front().unwrap_or(0.0);
+        }
+    }
+}
+
+struct Options {
+    rom_path: Option<String>,
+    frames: Option<u64>
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut options = Options { rom_path: None, frames: None };
+    let mut args = env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        if arg == "--frames" {
+            let frames = args.next().and_then(|value| value.parse().ok()).ok_or("--frames expects a number")?;
+            options.frames = Some(frames);
+        }
+        else {
+            options.rom_path = Some(arg);
+        }
+    }
+
+    Ok(options)
+}
+
+fn map_keycode(keycode: Keycode) -> Option<Key> {
+    match keycode {
+        Keycode::Up => Some(Key::Up),
+        Keycode::Down => Some(Key::Down),
+        Keycode::Left => Some(Key::Left),
+        Keycode::Right => Some(Key::Right),
+        Keycode::Return => Some(Key::Start),
+        Keycode::Backspace | Keycode::RShift => Some(Key::Select),
+        Keycode::Z => Some(Key::B),
+        Keycode::X => Some(Key::A),
+        _ => None
+    }
+}
+
+// SDL names controller buttons after an Xbox pad, so A is the south face button.
+fn map_controller_button(button: Button) -> Option<GamepadButton> {
+    match button {
+        Button::A => Some(GamepadButton::South),
+        Button::B => Some(GamepadButton::East),
+        Button::X => Some(GamepadButton::West),
+        Button::Y => Some(GamepadButton::North),
+        Button::Back => Some(GamepadButton::Select),
+        Button::Guide => Some(GamepadButton::Home),
+        Button::Start => Some(GamepadButton::Start),
+        Button::LeftStick => Some(GamepadButton::LeftStick),
+        Button::RightStick => Some(GamepadButton::RightStick),
+        Button::LeftShoulder => Some(GamepadButton::LeftShoulder),
+        Button::RightShoulder => Some(GamepadButton::RightShoulder),
+        Button::DPadUp => Some(GamepadButton::DPadUp),
+        Button::DPadDown => Some(GamepadButton::DPadDown),
+        Button::DPadLeft => Some(GamepadButton::DPadLeft),
+        Button::DPadRight => Some(GamepadButton::DPadRight),
+        _ => None
+    }
+}
+
+fn insert_cartridge(game_boy: &mut GameBoy, path: &str) -> Result<String, String> {
+    let rom = fs::read(path).map_err(|err| format!("Unable to read {}: {}", path, err))?;
+    let header = game_boy.insert_cartridge(&rom).map_err(|err| format!("Unable to load {}: {}", path, err))?;
+    Ok(header.title)
+}
+
+fn run() -> Result<(), String> {
+    let options = parse_options()?;
+
+    let sdl = sdl2::init()?;
+    let video = sdl.video()?;
+    let audio = sdl.audio()?;
+    let game_controller = sdl.game_controller()?;
+
+    let window = video
+        .window("RetroBoy", SCREEN_WIDTH * WINDOW_SCALE, SCREEN_HEIGHT * WINDOW_SCALE)
+        .position_centered()
+        .resizable()
+        .build()
+        .map_err(|err| err.to_string())?;
+    let mut canvas = window.into_canvas().build().map_err(|err| err.to_string())?;
+    canvas.set_logical_size(SCREEN_WIDTH, SCREEN_HEIGHT).map_err(|err| err.to_string())?;
+    let texture_creator = canvas.texture_creator();
+    let mut texture = texture_creator
+        .create_texture_streaming(PixelFormatEnum::RGBA32, SCREEN_WIDTH, SCREEN_HEIGHT)
+        .map_err(|err| err.to_string())?;
+
+    let samples: SampleQueue = Arc::new(Mutex::new(VecDeque::new()));
+    let desired_spec = AudioSpecDesired {
+        freq: Some(SAMPLE_RATE),
+        channels: Some(2),
+        samples: Some(AUDIO_BUFFER_FRAMES)
+    };
+    let mut sample_rate = SAMPLE_RATE;
+    let speaker = audio.open_playback(None, &desired_spec, |spec| {
+        sample_rate = spec.freq;
+        Speaker { samples: samples.clone() }
+    })?;
+    speaker.resume();
+
+    let mut game_boy = GameBoy::new(HardwareModel::CGB);
+    game_boy.set_sample_rate(sample_rate as u32);
+    let mut cartridge_inserted = false;
+
+    if let Some(path) = &options.rom_path {
+        let title = insert_cartridge(&mut game_boy, path)?;
+        canvas.window_mut().set_title(&format!("RetroBoy - {}", title)).map_err(|err| err.to_string())?;
+        cartridge_inserted = true;
+    }
+
+    // Controllers stop sending events once they're dropped, so they're kept open here.
+    let mut controllers = Vec::new();
+    let gamepad_mapping = GamepadMapping::default();
+    let mut pressed_buttons: Vec<GamepadButton> = Vec::new();
+    let mut left_stick = (0.0, 0.0);
+    let mut gamepad_state = JoypadState::default();
+
+    let mut pacer = game_boy.frame_pacer();
+    let mut event_pump = sdl.event_pump()?;
+    let start = Instant::now();
+    let mut frames_run = 0;
+
+    'running: loop {
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => break 'running,
+                Event::KeyDown { keycode: Some(keycode), repeat: false, .. } => {
+                    if let Some(key) = map_keycode(keycode) {
+                        game_boy.press(key);
+                    }
+                }
+                Event::KeyUp { keycode: Some(keycode), .. } => {
+                    if let Some(key) = map_keycode(keycode) {
+                        game_boy.release(key);
+                    }
+                }
+                Event::ControllerDeviceAdded { which, .. } => match game_controller.open(which) {
+                    Ok(controller) => controllers.push(controller),
+                    Err(err) => eprintln!("Unable to open controller: {}", err)
+                },
+                Event::ControllerButtonDown { button, .. } => {
+                    if let Some(button) = map_controller_button(button) {
+                        pressed_buttons.push(button);
+                    }
+                }
+                Event::ControllerButtonUp { button, .. } => {
+                    if let Some(button) = map_controller_button(button) {
+                        pressed_buttons.retain(|pressed| *pressed != button);
+                    }
+                }
+                Event::ControllerAxisMotion { axis, value, .. } => match axis {
+                    Axis::LeftX => left_stick.0 = value as f32 / i16::MAX as f32,
+                    Axis::LeftY => left_stick.1 = value as f32 / i16::MAX as f32,
+                    _ => {}
+                },
+                Event::DropFile { filename, .. } => match insert_cartridge(&mut game_boy, &filename) {
+                    Ok(title) => {
+                        canvas.window_mut().set_title(&format!("RetroBoy - {}", title)).map_err(|err| err.to_string())?;
+                        samples.lock().unwrap().clear();
+                        pacer.reset();
+                        cartridge_inserted = true;
+                    }
+                    Err(message) => eprintln!("{}", message)
+                },
+                _ => {}
+            }
+        }
+
+        let current_gamepad_state = gamepad_mapping.joypad_state(&pressed_buttons, left_stick);
+        for (key, pressed) in key_changes(gamepad_state, current_gamepad_state) {
+            if pressed {
+                game_boy.press(key);
+            }
+            else {
+                game_boy.release(key);
+            }
+        }
+        gamepad_state = current_gamepad_state;
+
+        if !cartridge_inserted {
+            canvas.clear();
+            canvas.present();
+            thread::sleep(Duration::from_millis(16));
+            continue;
+        }
+
+        let frames = match pacer.next_step(start.elapsed().as_secs_f64() * 1000.0) {
+            PacingStep::Sleep { millis } => {
+                thread::sleep(Duration::from_secs_f64(millis / 1000.0));
+                continue;
+            }
+            PacingStep::RunFrames { frames } => frames
+        };
+
+        for _ in 0..frames {
+            game_boy.run_frame();
+            frames_run += 1;
+        }
+
+        let (left, right) = game_boy.audio_samples();
+        {
+            let mut samples = samples.lock().unwrap();
+            for (left, right) in left.into_iter().zip(right) {
+                samples.push_back(left);
+                samples.push_back(right);
+            }
+            let excess = samples.len().saturating_sub(MAX_QUEUED_SAMPLES);
+            samples.drain(..excess);
+        }
+
+        texture.update(None, game_boy.screen(), (SCREEN_WIDTH * 4) as usize).map_err(|err| err.to_string())?;
+        canvas.copy(&texture, None, None)?;
+        canvas.present();
+
+        let serial_output = game_boy.take_serial_output();
+        if !serial_output.is_empty() {
+            print!("{}", serial_output);
+        }
+
+        if options.frames.is_some_and(|frames| frames_run >= frames) {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+fn main() {
+    if let Err(message) = run() {
+        eprintln!("{}", message);
+        process::exit(1);
+    }
+}
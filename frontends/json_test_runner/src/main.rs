@@ -1,10 +1,12 @@
-use retroboy::emulator::{self, initialize_screenless_emulator};
+use retroboy::emulator::{self, enable_processor_test_mode, initialize_screenless_emulator, Emulator};
 use retroboy::cpu::{BusActivityEntry, BusActivityType};
 use serde::Deserialize;
+use std::env;
 use std::fs;
 use std::io;
 use std::path::Path;
 use std::path::PathBuf;
+use std::process;
 use std::result::Result;
 
 #[derive(Debug, Deserialize)]
@@ -22,11 +24,15 @@ struct JsonCpuState {
     l: u8,
     pc: u16,
     sp: u16,
+    // Only in the SingleStepTests vectors.
+    #[serde(default)]
+    ime: Option<u8>,
     ram: Vec<RamEntry>,
 }
 
+// Addresses and values are null on cycles without bus activity in some versions of the vectors.
 #[derive(Debug, Deserialize)]
-struct CycleEntry(u16, u8, String);
+struct CycleEntry(Option<u16>, Option<u8>, String);
 
 #[derive(Debug, Deserialize)]
 struct JsonCpuTest {
@@ -36,8 +42,12 @@ struct JsonCpuTest {
     cycles: Vec<Option<CycleEntry>>,
 }
 
+// Used when no directory is passed on the command line.
 const JSON_CPU_TESTS_PATH: &str = "../../../GameboyCPUTests/v2";
 
+// Failures printed per file, so one broken opcode doesn't flood the output.
+const MAX_REPORTED_FAILURES: usize = 5;
+
 fn list_files_in_path(path: &Path) -> io::Result<Vec<PathBuf>> {
     let mut files = Vec::new();
 
@@ -84,46 +94,70 @@ fn is_json_file(file: &PathBuf) -> bool {
     file.to_string_lossy().ends_with(".json")
 }
 
-fn collect_json_test_files() -> Result<Vec<PathBuf>, io::Error> {
-    let path = Path::new(JSON_CPU_TESTS_PATH);
+fn collect_json_test_files(path: &Path) -> Result<Vec<PathBuf>, io::Error> {
     let files = list_files_in_path(path)?;
     let json_files: Vec<PathBuf> = files.into_iter().filter(is_json_file).collect();
     let sorted_files = sort_files_by_hex_value(json_files);
     Ok(sorted_files)
 }
 
+/*
+    Two versions of the vectors are supported: adtennant/GameboyCPUTests, which
+    marks bus activity as "read" or "write" and leaves idle cycles null, and
+    SingleStepTests/sm83, which uses "r-m", "-wm" and "---" for idle cycles.
+*/
+fn as_expected_activity(entry: &Option<CycleEntry>) -> Option<(u16, u8, BusActivityType)> {
+    let CycleEntry(address, value, activity) = entry.as_ref()?;
+    let activity_type = match activity.as_str() {
+        "read" | "r-m" => BusActivityType::Read,
+        "write" | "-wm" => BusActivityType::Write,
+        _ => return None
+    };
+    Some(((*address)?, (*value)?, activity_type))
+}
+
 fn bus_activity_matches(
-    expected_cycles: &Vec<Option<CycleEntry>>,
-    actual_cycles: &Vec<Option<BusActivityEntry>>
+    expected_cycles: &[Option<CycleEntry>],
+    actual_cycles: &[Option<BusActivityEntry>]
 ) -> bool {
+    expected_cycles.len() == actual_cycles.len() &&
     expected_cycles.iter().zip(actual_cycles.iter()).all(|(maybe_expected_cycle, maybe_actual_cycle)| {
-        match (maybe_expected_cycle, maybe_actual_cycle) {
-            (Some(expected_cycle), Some(actual_cycle)) => {
-                let expected_address = expected_cycle.0;
-                let expected_value = expected_cycle.1;
-                let expected_activity = &expected_cycle.2;
-
-                let actual_address = actual_cycle.address;
-                let actual_value = actual_cycle.value;
-                let actual_activity = &actual_cycle.activity_type;
-
-                expected_address == actual_address &&
-                expected_value == actual_value &&
-                match (expected_activity.as_str(), actual_activity) {
-                    ("read", BusActivityType::Read) | ("write", BusActivityType::Write) => true,
-                    _ => false,
-                }
-            }
+        match (as_expected_activity(maybe_expected_cycle), maybe_actual_cycle) {
+            (Some((expected_address, expected_value, expected_activity)), Some(actual_cycle)) =>
+                expected_address == actual_cycle.address &&
+                expected_value == actual_cycle.value &&
+                expected_activity == actual_cycle.activity_type,
             (None, None) => true,
             _ => false,
         }
     })
 }
 
-fn run_cpu_test(test: &JsonCpuTest) {
+fn registers_match(emulator: &Emulator, expected: &JsonCpuState) -> bool {
+    let registers = &emulator.cpu.registers;
+    registers.a == expected.a &&
+        registers.b == expected.b &&
+        registers.c == expected.c &&
+        registers.d == expected.d &&
+        registers.e == expected.e &&
+        registers.f == expected.f &&
+        registers.h == expected.h &&
+        registers.l == expected.l &&
+        registers.program_counter == expected.pc &&
+        registers.stack_pointer == expected.sp &&
+        expected.ime.is_none_or(|ime| emulator.cpu.interrupts.enabled == (ime != 0))
+}
+
+fn find_ram_mismatch(emulator: &Emulator, expected: &JsonCpuState) -> Option<String> {
+    expected.ram.iter()
+        .find(|entry| emulator.memory.processor_test_ram[entry.0 as usize] != entry.1)
+        .map(|entry| format!("RAM at {:04X} is {:02X}, expected {:02X}", entry.0, emulator.memory.processor_test_ram[entry.0 as usize], entry.1))
+}
+
+fn run_cpu_test(test: &JsonCpuTest) -> Result<(), String> {
     let mut emulator = initialize_screenless_emulator();
 
-    emulator.processor_test_mode = true;
+    enable_processor_test_mode(&mut emulator);
     
     emulator.cpu.registers.a = test.initial.a;
     emulator.cpu.registers.b = test.initial.b;
@@ -135,48 +169,129 @@ fn run_cpu_test(test: &JsonCpuTest) {
     emulator.cpu.registers.l = test.initial.l;
     emulator.cpu.registers.program_counter = test.initial.pc;
     emulator.cpu.registers.stack_pointer = test.initial.sp;
+    emulator.cpu.interrupts.enabled = test.initial.ime.is_some_and(|ime| ime != 0);
 
     for entry in &test.initial.ram {
         emulator.memory.processor_test_ram[entry.0 as usize] = entry.1;
     }
 
-    emulator.cpu.registers.opcode = emulator.memory.processor_test_ram[(test.initial.pc - 1) as usize];
+    emulator.cpu.registers.opcode = emulator.memory.processor_test_ram[test.initial.pc.wrapping_sub(1) as usize];
 
     emulator::step(&mut emulator);
 
-    let test_failed = emulator.cpu.registers.a != test.r#final.a ||
-        emulator.cpu.registers.b != test.r#final.b ||
-        emulator.cpu.registers.c != test.r#final.c ||
-        emulator.cpu.registers.d != test.r#final.d ||
-        emulator.cpu.registers.e != test.r#final.e ||
-        emulator.cpu.registers.f != test.r#final.f ||
-        emulator.cpu.registers.h != test.r#final.h ||
-        emulator.cpu.registers.l != test.r#final.l ||
-        emulator.cpu.registers.program_counter != test.r#final.pc ||
-        emulator.cpu.registers.stack_pointer != test.r#final.sp ||
-        !bus_activity_matches(&test.cycles, &emulator.cpu.opcode_bus_activity);
-
-    if test_failed {
-        panic!("Test {} failed: CPU state: {:?}, Expected: {:?}", test.name, emulator.cpu.registers, test.r#final);
+    if !registers_match(&emulator, &test.r#final) {
+        return Err(format!("Test {} failed: CPU state: {:?}, Expected: {:?}", test.name, emulator.cpu.registers, test.r#final));
+    }
+    if let Some(mismatch) = find_ram_mismatch(&emulator, &test.r#final) {
+        return Err(format!("Test {} failed: {}", test.name, mismatch));
+    }
+    if !bus_activity_matches(&test.cycles, &emulator.cpu.opcode_bus_activity) {
+        return Err(format!("Test {} failed: bus activity: {:?}, Expected: {:?}", test.name, emulator.cpu.opcode_bus_activity, test.cycles));
+    }
+
+    Ok(())
+}
+
+// Runs every test in the file and returns how many there were and how many failed.
+fn run_test_file(path: &Path) -> io::Result<(usize, usize)> {
+    let tests = read_json_tests(path)?;
+    let mut failures = 0;
+
+    for test in &tests {
+        if let Err(message) = run_cpu_test(test) {
+            failures += 1;
+            if failures <= MAX_REPORTED_FAILURES {
+                println!("{}", message);
+            }
+        }
     }
+
+    Ok((tests.len(), failures))
 }
 
-fn main() -> io::Result<()> {
-    let json_test_files = collect_json_test_files()?;
+// Runs every file and returns the total number of tests and failures.
+fn run_test_files(json_test_files: &[PathBuf]) -> (usize, usize) {
+    let mut total_tests = 0;
+    let mut total_failures = 0;
 
     for json_test_file in json_test_files {
-        match read_json_tests(&json_test_file) {
-            Ok(tests) => {
-                for test in tests {
-                    run_cpu_test(&test);
-                }
+        match run_test_file(json_test_file) {
+            Ok((tests, failures)) => {
+                total_tests += tests;
+                total_failures += failures;
+                println!("Ran {} opcode tests in file: {} ({} failed)", tests, json_test_file.display(), failures);
             }
             Err(e) => {
-                println!("Error reading JSON tests: {}", e);
+                // A file that can't be read or parsed counts as a failed test, so it can't go unnoticed.
+                total_tests += 1;
+                total_failures += 1;
+                println!("Error reading JSON tests in {}: {}", json_test_file.display(), e);
             }
         }
-        println!("Successfully ran opcode tests in file: {}", json_test_file.display());
+    }
+
+    (total_tests, total_failures)
+}
+
+// Usage: json_test_runner [directory of per-opcode JSON files]
+fn main() -> io::Result<()> {
+    let tests_path = env::args().nth(1).unwrap_or_else(|| JSON_CPU_TESTS_PATH.to_string());
+    let json_test_files = collect_json_test_files(Path::new(&tests_path))?;
+    let (total_tests, total_failures) = run_test_files(&json_test_files);
+
+    println!("{} of {} tests passed", total_tests - total_failures, total_tests);
+    if total_failures > 0 {
+        process::exit(1);
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // LD B, n8 from a hand-written vector in the GameboyCPUTests format.
+    const LOAD_IMMEDIATE_TEST: &str = r#"{
+        "name": "06 0000",
+        "initial": { "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 0, "h": 0, "l": 0, "pc": 257, "sp": 0, "ram": [[256, 6], [257, 66], [258, 0]] },
+        "final": { "a": 0, "b": 66, "c": 0, "d": 0, "e": 0, "f": 0, "h": 0, "l": 0, "pc": 259, "sp": 0, "ram": [[256, 6], [257, 66], [258, 0]] },
+        "cycles": [[257, 66, "read"], [258, 0, "read"]]
+    }"#;
+
+    #[test]
+    fn should_pass_matching_vector_in_either_format() {
+        let test: JsonCpuTest = serde_json::from_str(LOAD_IMMEDIATE_TEST).unwrap();
+        assert_eq!(run_cpu_test(&test), Ok(()));
+
+        let single_step_test: JsonCpuTest = serde_json::from_str(&LOAD_IMMEDIATE_TEST.replace("\"read\"", "\"r-m\"")).unwrap();
+        assert_eq!(run_cpu_test(&single_step_test), Ok(()));
+    }
+
+    #[test]
+    fn should_report_registers_ram_and_bus_activity_mismatches() {
+        let wrong_register: JsonCpuTest = serde_json::from_str(&LOAD_IMMEDIATE_TEST.replace("\"b\": 66", "\"b\": 67")).unwrap();
+        assert!(run_cpu_test(&wrong_register).is_err());
+
+        let wrong_ram: JsonCpuTest = serde_json::from_str(&LOAD_IMMEDIATE_TEST.replace("[258, 0]] },\n        \"cycles", "[258, 1]] },\n        \"cycles")).unwrap();
+        assert!(run_cpu_test(&wrong_ram).unwrap_err().contains("RAM at 0102"));
+
+        let missing_cycle: JsonCpuTest = serde_json::from_str(&LOAD_IMMEDIATE_TEST.replace(", [258, 0, \"read\"]]", "]")).unwrap();
+        assert!(run_cpu_test(&missing_cycle).unwrap_err().contains("bus activity"));
+    }
+
+    #[test]
+    fn should_count_unreadable_files_as_failures() {
+        let directory = env::temp_dir().join(format!("json_test_runner_{}", process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let valid_file = directory.join("06.json");
+        let invalid_file = directory.join("07.json");
+        fs::write(&valid_file, format!("[{}]", LOAD_IMMEDIATE_TEST)).unwrap();
+        fs::write(&invalid_file, "[{").unwrap();
+
+        let totals = run_test_files(&[valid_file, invalid_file, directory.join("missing.json")]);
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(totals, (3, 2));
+    }
+}
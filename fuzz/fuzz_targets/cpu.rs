@@ -0,0 +1,31 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use retroboy::builder::EmulatorBuilder;
+use retroboy::emulator;
+
+const ENTRY_POINT: usize = 0x100;
+const HEADER_END: usize = 0x150;
+const ROM_SIZE: usize = 0x8000;
+const MAX_INSTRUCTIONS: usize = 10000;
+
+/*
+    Places the input after the cartridge header and jumps over the header to it,
+    so the CPU decodes an arbitrary instruction stream from a ROM-only cartridge.
+*/
+fuzz_target!(|data: &[u8]| {
+    let mut rom = vec![0; ROM_SIZE];
+    // JP 0x0150
+    rom[ENTRY_POINT..ENTRY_POINT + 3].copy_from_slice(&[0xC3, 0x50, 0x01]);
+
+    let length = data.len().min(ROM_SIZE - HEADER_END);
+    rom[HEADER_END..HEADER_END + length].copy_from_slice(&data[..length]);
+
+    let Ok((mut emulator, _)) = EmulatorBuilder::new().with_rom(&rom).skip_boot_rom().build() else {
+        return;
+    };
+
+    for _ in 0..MAX_INSTRUCTIONS {
+        emulator::step(&mut emulator);
+    }
+});
@@ -0,0 +1,33 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use retroboy::builder::EmulatorBuilder;
+use retroboy::mmu;
+
+const HEADER_SIZE: usize = 0x150;
+const BANK_SIZE: usize = 0x4000;
+
+/*
+    The first bytes are laid over a cartridge header (cartridge type, ROM and RAM
+    sizes, etc.) and the rest are read as (address high, address low, value)
+    triples, covering banking register writes and reads across the whole bus.
+*/
+fuzz_target!(|data: &[u8]| {
+    if data.len() < HEADER_SIZE {
+        return;
+    }
+
+    let (header, accesses) = data.split_at(HEADER_SIZE);
+    let mut rom = vec![0; BANK_SIZE * 2];
+    rom[..HEADER_SIZE].copy_from_slice(header);
+
+    let Ok((mut emulator, _)) = EmulatorBuilder::new().with_rom(&rom).skip_boot_rom().build() else {
+        return;
+    };
+
+    for access in accesses.chunks_exact(3) {
+        let address = u16::from_be_bytes([access[0], access[1]]);
+        mmu::write_byte(&mut emulator, address, access[2]);
+        mmu::read_byte(&mut emulator, address);
+    }
+});
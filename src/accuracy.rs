@@ -0,0 +1,89 @@
+use crate::emulator::Emulator;
+
+/*
+    Groups the behaviors that cost noticeable host time behind one setting, so
+    frontends on slow devices can trade fidelity for speed. There's no profile
+    above Balanced yet, as cycle-level behaviors such as a pixel FIFO or the OAM
+    corruption bug aren't emulated. Fast also leaves VRAM open to the CPU during
+    pixel transfer, as it was before the PPU's lock was emulated.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccuracyProfile {
+    Fast,
+    Balanced
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccuracySettings {
+    pub profile: AccuracyProfile,
+    // Ramps DAC output when a channel's DAC is switched on or off.
+    pub dac_charging: bool,
+    // Ramps NR51 panning changes rather than applying them instantly.
+    pub panning_ramp: bool,
+    // Clocks the hardware straight through HALT waits rather than re-running HALT every M-cycle.
+    pub idle_skip: bool,
+    // Draws every sprite on a line instead of the first ten. Only allowed in the Fast profile, see below.
+    pub remove_sprite_limit: bool,
+    // Blocks CPU access to VRAM, and to palette RAM on the CGB, while the PPU fetches from it. See gpu.rs.
+    pub vram_conflicts: bool
+}
+
+pub fn as_accuracy_settings(profile: AccuracyProfile) -> AccuracySettings {
+    match profile {
+        AccuracyProfile::Fast => AccuracySettings { profile, dac_charging: false, panning_ramp: false, idle_skip: true, remove_sprite_limit: false, vram_conflicts: false },
+        AccuracyProfile::Balanced => AccuracySettings { profile, dac_charging: true, panning_ramp: true, idle_skip: false, remove_sprite_limit: false, vram_conflicts: true }
+    }
+}
+
+pub fn initialize_accuracy() -> AccuracySettings {
+    as_accuracy_settings(AccuracyProfile::Balanced)
+}
+
+pub fn set_accuracy_profile(emulator: &mut Emulator, profile: AccuracyProfile) {
+    emulator.accuracy = as_accuracy_settings(profile);
+}
+
+/*
+    Games flicker sprites to work around the ten-per-line limit, which some players
+    would rather not see. Lifting the limit isn't faithful and can show sprites a
+    game meant to hide, so it's an enhancement players opt into on top of the Fast
+    profile. Changing profile turns it back off.
+*/
+pub fn set_sprite_limit_removed(emulator: &mut Emulator, removed: bool) {
+    emulator.accuracy.remove_sprite_limit = removed && emulator.accuracy.profile == AccuracyProfile::Fast;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::initialize_screenless_emulator;
+    use super::*;
+
+    #[test]
+    fn should_default_to_balanced_profile() {
+        let emulator = initialize_screenless_emulator();
+        assert_eq!(emulator.accuracy.profile, AccuracyProfile::Balanced);
+        assert!(emulator.accuracy.dac_charging);
+    }
+
+    #[test]
+    fn should_disable_expensive_behaviors_in_fast_profile() {
+        let mut emulator = initialize_screenless_emulator();
+        set_accuracy_profile(&mut emulator, AccuracyProfile::Fast);
+        assert!(!emulator.accuracy.dac_charging);
+        assert!(!emulator.accuracy.panning_ramp);
+        assert!(emulator.accuracy.idle_skip);
+        assert!(!emulator.accuracy.vram_conflicts);
+    }
+
+    #[test]
+    fn should_only_remove_sprite_limit_in_fast_profile() {
+        let mut emulator = initialize_screenless_emulator();
+        set_sprite_limit_removed(&mut emulator, true);
+        assert!(!emulator.accuracy.remove_sprite_limit);
+
+        set_accuracy_profile(&mut emulator, AccuracyProfile::Fast);
+        assert!(!emulator.accuracy.remove_sprite_limit);
+        set_sprite_limit_removed(&mut emulator, true);
+        assert!(emulator.accuracy.remove_sprite_limit);
+    }
+}
@@ -1,9 +1,11 @@
 use utils::{calculate_left_stereo_sample, calculate_right_stereo_sample};
 use crate::apu::envelope::should_disable_dac;
+use crate::apu::filter::{initialize_filter, FilterState, HighPassFilter};
 use crate::apu::noise::{initialize_noise_channel, reset_noise_channel, NoiseChannel};
 use crate::apu::wave::{initialize_wave_channel, reset_wave_channel, WaveChannel};
 use crate::apu::pulse::{initialize_pulse_channel, reset_pulse_channel, PulseChannel};
-use crate::apu::utils::{bounded_wrapping_add, as_dac_output};
+use crate::apu::register_log::{initialize_register_log, RegisterLogState};
+use crate::apu::utils::{as_dac_output, as_panning_gains, bounded_wrapping_add, ramp_panning_gains, PanningGains};
 use crate::emulator::{in_color_bios, is_cgb, Emulator};
 use crate::utils::{get_bit, get_t_cycle_increment, is_bit_set};
 
@@ -11,6 +13,7 @@ use crate::utils::{get_bit, get_t_cycle_increment, is_bit_set};
 pub struct ApuState {
     pub enabled: bool,
     pub sound_panning: u8,
+    pub panning_gains: PanningGains,
     pub master_volume: u8,
     pub channel1: PulseChannel,
     pub channel2: PulseChannel,
@@ -18,7 +21,7 @@ pub struct ApuState {
     pub channel4: NoiseChannel,
     pub divider_apu: u8,
     pub last_divider_time: u8,
-    pub audio_buffer_clock: u8,
+    pub sample_phase: u32,
     pub channel_clock: u8,
     pub left_sample_queue: Vec<f32>,
     pub right_sample_queue: Vec<f32>,
@@ -26,13 +29,18 @@ pub struct ApuState {
     pub summed_channel2_sample: f32,
     pub summed_channel3_sample: f32,
     pub summed_channel4_sample: f32,
-    pub enqueue_rate: u32
+    pub enqueue_rate: u32,
+    pub sample_rate: u32,
+    pub audio_buffer_size: usize,
+    pub filter: FilterState,
+    pub register_log: RegisterLogState
 }
 
 pub fn initialize_apu() -> ApuState {
     ApuState {
         enabled: false,
         sound_panning: 0,
+        panning_gains: as_panning_gains(0),
         master_volume: 0,
         channel1: initialize_pulse_channel(),
         channel2: initialize_pulse_channel(),
@@ -40,7 +48,7 @@ pub fn initialize_apu() -> ApuState {
         channel4: initialize_noise_channel(),
         divider_apu: 0,
         last_divider_time: 0,
-        audio_buffer_clock: 0,
+        sample_phase: 0,
         channel_clock: 0,
         left_sample_queue: Vec::new(),
         right_sample_queue: Vec::new(),
@@ -48,7 +56,11 @@ pub fn initialize_apu() -> ApuState {
         summed_channel2_sample: 0.0,
         summed_channel3_sample: 0.0,
         summed_channel4_sample: 0.0,
-        enqueue_rate: CPU_RATE / DEFAULT_SAMPLE_RATE
+        enqueue_rate: CPU_RATE / DEFAULT_SAMPLE_RATE,
+        sample_rate: DEFAULT_SAMPLE_RATE,
+        audio_buffer_size: DEFAULT_AUDIO_BUFFER_SIZE,
+        filter: initialize_filter(CPU_RATE / DEFAULT_SAMPLE_RATE),
+        register_log: initialize_register_log()
     }
 }
 
@@ -69,12 +81,25 @@ const CH3_DAC_ENABLED_INDEX: u8 = 7;
 const APU_ENABLED_INDEX: u8 = 7;
 const MAX_DIV_APU_STEPS: u8 = 7;
 
-const CPU_RATE: u32 = 4194304;
+pub const CPU_RATE: u32 = 4194304;
 const DEFAULT_SAMPLE_RATE: u32 = 44100;
-const MAX_AUDIO_BUFFER_SIZE: usize = 512;
+const MIN_SAMPLE_RATE: u32 = 1000;
+// At most one sample can be due per M-cycle.
+const MAX_SAMPLE_RATE: u32 = CPU_RATE / 4;
+
+/*
+    Audio-synced frontends step the emulator until the buffer holds this many
+    samples per channel, then play it. Smaller buffers lower latency but leave less
+    slack before the host runs dry and stutters.
+*/
+pub const DEFAULT_AUDIO_BUFFER_SIZE: usize = 512;
+pub const MIN_AUDIO_BUFFER_SIZE: usize = 64;
+pub const MAX_AUDIO_BUFFER_SIZE: usize = 16384;
 
 const CHANNEL_STEP_RATE: u8 = 4;
 
+const PANNING_RAMP_SECONDS: f32 = 0.002;
+
 fn should_step_div_apu(emulator: &mut Emulator) -> bool {
     let double_speed_mode = emulator.speed_switch.cgb_double_speed;
     let bit_to_check = if double_speed_mode { 5 } else { 4 };
@@ -112,8 +137,39 @@ fn step_div_apu(emulator: &mut Emulator) {
 }
 
 pub fn audio_buffers_full(emulator: &mut Emulator) -> bool {
-    emulator.apu.left_sample_queue.len() >= MAX_AUDIO_BUFFER_SIZE
-    && emulator.apu.right_sample_queue.len() >= MAX_AUDIO_BUFFER_SIZE
+    emulator.apu.left_sample_queue.len() >= emulator.apu.audio_buffer_size
+    && emulator.apu.right_sample_queue.len() >= emulator.apu.audio_buffer_size
+}
+
+pub fn set_audio_buffer_size(emulator: &mut Emulator, samples: usize) {
+    emulator.apu.audio_buffer_size = samples.clamp(MIN_AUDIO_BUFFER_SIZE, MAX_AUDIO_BUFFER_SIZE);
+}
+
+fn samples_to_millis(emulator: &Emulator, samples: usize) -> f64 {
+    samples as f64 * 1000.0 / emulator.apu.sample_rate as f64
+}
+
+// How long a full buffer takes to play, which is the latency audio syncing adds.
+pub fn get_audio_buffer_latency_millis(emulator: &Emulator) -> f64 {
+    samples_to_millis(emulator, emulator.apu.audio_buffer_size)
+}
+
+pub fn queued_sample_count(emulator: &Emulator) -> usize {
+    emulator.apu.left_sample_queue.len().min(emulator.apu.right_sample_queue.len())
+}
+
+pub fn get_queued_audio_millis(emulator: &Emulator) -> f64 {
+    samples_to_millis(emulator, queued_sample_count(emulator))
+}
+
+// Moves the oldest queued samples into as much of the buffers as they fill, returning how many were moved.
+pub fn drain_audio_samples(emulator: &mut Emulator, left: &mut [f32], right: &mut [f32]) -> usize {
+    let count = left.len().min(right.len()).min(queued_sample_count(emulator));
+    left[..count].copy_from_slice(&emulator.apu.left_sample_queue[..count]);
+    right[..count].copy_from_slice(&emulator.apu.right_sample_queue[..count]);
+    emulator.apu.left_sample_queue.drain(..count);
+    emulator.apu.right_sample_queue.drain(..count);
+    count
 }
 
 pub fn clear_audio_buffers(emulator: &mut Emulator) {
@@ -129,33 +185,26 @@ pub fn get_right_sample_queue(emulator: &Emulator) -> &[f32] {
     &emulator.apu.right_sample_queue.as_slice()
 }
 
-fn calculate_sample_weight(steps_per_enqueue: u8, steps_since_enqueue: u8) -> f32 {
-    let step_index = steps_per_enqueue - steps_since_enqueue;
-    ((step_index as f32).ln() + 1.0) / ((steps_per_enqueue as f32).ln() + 1.0)
-}
-
-fn generate_dac_output(summed_channel_sample: f32, steps_since_enqueue: u8) -> f32 {
-    let avg_channel_sample = summed_channel_sample / steps_since_enqueue as f32;
-    as_dac_output(avg_channel_sample)
+fn digital_outputs(emulator: &Emulator) -> [f32; 4] {
+    [pulse::digital_output(&emulator.apu.channel1),
+        pulse::digital_output(&emulator.apu.channel2),
+        wave::digital_output(emulator),
+        noise::digital_output(&emulator.apu.channel4)]
 }
 
-fn track_digital_outputs(emulator: &mut Emulator, weight: f32) {
-    let channel1_output = pulse::digital_output(&emulator.apu.channel1);
-    let channel2_output = pulse::digital_output(&emulator.apu.channel2);
-    let channel3_output = wave::digital_output(&emulator);
-    let channel4_output = noise::digital_output(&emulator.apu.channel4);
-
-    emulator.apu.summed_channel1_sample += channel1_output * weight;
-    emulator.apu.summed_channel2_sample += channel2_output * weight;
-    emulator.apu.summed_channel3_sample += channel3_output * weight;
-    emulator.apu.summed_channel4_sample += channel4_output * weight;
+// Channel outputs are summed weighted by how many T-cycles they were held for.
+fn add_to_summed_samples(emulator: &mut Emulator, outputs: [f32; 4], t_cycles: f32) {
+    emulator.apu.summed_channel1_sample += outputs[0] * t_cycles;
+    emulator.apu.summed_channel2_sample += outputs[1] * t_cycles;
+    emulator.apu.summed_channel3_sample += outputs[2] * t_cycles;
+    emulator.apu.summed_channel4_sample += outputs[3] * t_cycles;
 }
 
-fn clear_summed_samples(emulator: &mut Emulator) {
-    emulator.apu.summed_channel1_sample = 0.0;
-    emulator.apu.summed_channel2_sample = 0.0;
-    emulator.apu.summed_channel3_sample = 0.0;
-    emulator.apu.summed_channel4_sample = 0.0;
+fn set_summed_samples(emulator: &mut Emulator, outputs: [f32; 4], t_cycles: f32) {
+    emulator.apu.summed_channel1_sample = outputs[0] * t_cycles;
+    emulator.apu.summed_channel2_sample = outputs[1] * t_cycles;
+    emulator.apu.summed_channel3_sample = outputs[2] * t_cycles;
+    emulator.apu.summed_channel4_sample = outputs[3] * t_cycles;
 }
 
 fn enqueue_left_sample(emulator: &mut Emulator,
@@ -165,14 +214,17 @@ fn enqueue_left_sample(emulator: &mut Emulator,
     channel4_dac_output: f32) {
     let left_master_volume = (emulator.apu.master_volume & 0b01110000) >> 4;
 
-    let left_sample = calculate_left_stereo_sample(emulator.apu.sound_panning,
+    let left_sample = calculate_left_stereo_sample(&emulator.apu.panning_gains,
         left_master_volume,
         channel1_dac_output,
         channel2_dac_output,
         channel3_dac_output,
         channel4_dac_output);
 
-    emulator.apu.left_sample_queue.push(left_sample);
+    let filter = &mut emulator.apu.filter;
+    let filtered_sample = filter::high_pass(filter.high_pass_filter, filter.charge_factor, &mut filter.left_capacitor, left_sample);
+
+    emulator.apu.left_sample_queue.push(filtered_sample);
 }
 
 fn enqueue_right_sample(emulator: &mut Emulator,
@@ -182,14 +234,62 @@ fn enqueue_right_sample(emulator: &mut Emulator,
     channel4_dac_output: f32) {
     let right_master_volume = emulator.apu.master_volume & 0b111;
 
-    let right_sample = calculate_right_stereo_sample(emulator.apu.sound_panning,
+    let right_sample = calculate_right_stereo_sample(&emulator.apu.panning_gains,
         right_master_volume,
         channel1_dac_output,
         channel2_dac_output,
         channel3_dac_output,
         channel4_dac_output);
 
-    emulator.apu.right_sample_queue.push(right_sample);
+    let filter = &mut emulator.apu.filter;
+    let filtered_sample = filter::high_pass(filter.high_pass_filter, filter.charge_factor, &mut filter.right_capacitor, right_sample);
+
+    emulator.apu.right_sample_queue.push(filtered_sample);
+}
+
+fn generate_dac_output(summed_channel_sample: f32, t_cycles_per_sample: f32) -> f32 {
+    as_dac_output(summed_channel_sample / t_cycles_per_sample)
+}
+
+fn enqueue_sample(emulator: &mut Emulator) {
+    let t_cycles_per_sample = CPU_RATE as f32 / emulator.apu.sample_rate as f32;
+
+    let dac_enabled = [emulator.apu.channel1.dac_enabled,
+        emulator.apu.channel2.dac_enabled,
+        emulator.apu.channel3.dac_enabled,
+        emulator.apu.channel4.dac_enabled];
+
+    let dac_outputs = [generate_dac_output(emulator.apu.summed_channel1_sample, t_cycles_per_sample),
+        generate_dac_output(emulator.apu.summed_channel2_sample, t_cycles_per_sample),
+        generate_dac_output(emulator.apu.summed_channel3_sample, t_cycles_per_sample),
+        generate_dac_output(emulator.apu.summed_channel4_sample, t_cycles_per_sample)];
+
+    let [channel1_dac_output, channel2_dac_output, channel3_dac_output, channel4_dac_output] = if emulator.accuracy.dac_charging {
+        filter::charge_dacs(&mut emulator.apu.filter, dac_enabled, dac_outputs)
+    }
+    else {
+        dac_outputs
+    };
+
+    if emulator.accuracy.panning_ramp {
+        let ramp_step = emulator.apu.enqueue_rate as f32 / (CPU_RATE as f32 * PANNING_RAMP_SECONDS);
+        ramp_panning_gains(&mut emulator.apu.panning_gains, emulator.apu.sound_panning, ramp_step);
+    }
+    else {
+        emulator.apu.panning_gains = as_panning_gains(emulator.apu.sound_panning);
+    }
+
+    enqueue_left_sample(emulator,
+        channel1_dac_output,
+        channel2_dac_output,
+        channel3_dac_output,
+        channel4_dac_output);
+
+    enqueue_right_sample(emulator,
+        channel1_dac_output,
+        channel2_dac_output,
+        channel3_dac_output,
+        channel4_dac_output);
 }
 
 fn enqueue_audio_samples(emulator: &mut Emulator) {
@@ -204,36 +304,28 @@ fn enqueue_audio_samples(emulator: &mut Emulator) {
     */
     if !in_color_bios(emulator) {
         let cgb_double_speed = emulator.speed_switch.cgb_double_speed;
-        let t_cycle_increment = get_t_cycle_increment(cgb_double_speed);
-
-        emulator.apu.audio_buffer_clock += t_cycle_increment;
-        let steps_since_enqueue = emulator.apu.audio_buffer_clock / t_cycle_increment;
-        let steps_per_enqueue = emulator.apu.enqueue_rate as u8 + 1 / t_cycle_increment;
-
-        let weight = calculate_sample_weight(steps_per_enqueue, steps_since_enqueue);
-        track_digital_outputs(emulator, weight);
+        let t_cycle_increment = get_t_cycle_increment(cgb_double_speed) as u32;
+        let outputs = digital_outputs(emulator);
+
+        /*
+            Samples are produced directly at the output rate. The phase counts T-cycles
+            scaled by the sample rate, so a sample is due each time it passes the clock
+            rate, which keeps the rate exact instead of rounding the interval to whole
+            M-cycles. The M-cycle a sample boundary falls in is split between the two
+            samples, so each one averages exactly its share of the channel outputs.
+        */
+        emulator.apu.sample_phase += t_cycle_increment * emulator.apu.sample_rate;
+
+        if emulator.apu.sample_phase < CPU_RATE {
+            add_to_summed_samples(emulator, outputs, t_cycle_increment as f32);
+        }
+        else {
+            emulator.apu.sample_phase -= CPU_RATE;
+            let next_sample_t_cycles = emulator.apu.sample_phase as f32 / emulator.apu.sample_rate as f32;
 
-        if emulator.apu.audio_buffer_clock as u32 >= emulator.apu.enqueue_rate {
-            emulator.apu.audio_buffer_clock = 0;
-    
-            let channel1_dac_output = generate_dac_output(emulator.apu.summed_channel1_sample, steps_since_enqueue);
-            let channel2_dac_output = generate_dac_output(emulator.apu.summed_channel2_sample, steps_since_enqueue);
-            let channel3_dac_output = generate_dac_output(emulator.apu.summed_channel3_sample, steps_since_enqueue);
-            let channel4_dac_output = generate_dac_output(emulator.apu.summed_channel4_sample, steps_since_enqueue);
-
-            enqueue_left_sample(emulator,
-                channel1_dac_output,
-                channel2_dac_output,
-                channel3_dac_output,
-                channel4_dac_output);
-
-            enqueue_right_sample(emulator,
-                channel1_dac_output,
-                channel2_dac_output,
-                channel3_dac_output,
-                channel4_dac_output);
-
-            clear_summed_samples(emulator);
+            add_to_summed_samples(emulator, outputs, t_cycle_increment as f32 - next_sample_t_cycles);
+            enqueue_sample(emulator);
+            set_summed_samples(emulator, outputs, next_sample_t_cycles);
         }
     }
 }
@@ -241,9 +333,10 @@ fn enqueue_audio_samples(emulator: &mut Emulator) {
 pub fn step(emulator: &mut Emulator) {
     let double_speed_mode = emulator.speed_switch.cgb_double_speed;
     let t_cycle_increment = get_t_cycle_increment(double_speed_mode);
-    emulator.apu.channel_clock += t_cycle_increment;
     
     if emulator.apu.enabled {
+        emulator.apu.channel_clock += t_cycle_increment;
+
         if emulator.apu.channel_clock >= CHANNEL_STEP_RATE {
             let clock_cycles = emulator.apu.channel_clock;
             emulator.apu.channel_clock = 0;
@@ -262,7 +355,16 @@ pub fn step(emulator: &mut Emulator) {
 }
 
 pub fn set_sample_rate(emulator: &mut Emulator, sample_rate: u32) {
+    let sample_rate = sample_rate.clamp(MIN_SAMPLE_RATE, MAX_SAMPLE_RATE);
+    emulator.apu.sample_rate = sample_rate;
+    emulator.apu.sample_phase = 0;
     emulator.apu.enqueue_rate = CPU_RATE / sample_rate;
+    filter::configure(&mut emulator.apu.filter, emulator.apu.enqueue_rate);
+}
+
+pub fn set_high_pass_filter(emulator: &mut Emulator, high_pass_filter: HighPassFilter) {
+    emulator.apu.filter.high_pass_filter = high_pass_filter;
+    filter::configure(&mut emulator.apu.filter, emulator.apu.enqueue_rate);
 }
 
 fn in_length_period_first_half(current_divider_apu: u8) -> bool {
@@ -285,9 +387,10 @@ pub fn set_ch1_period_high(emulator: &mut Emulator, new_period_high_value: u8) {
         }
 
         if pulse::should_trigger(&emulator.apu.channel1) { 
+            let length_expired = length::is_expired(&emulator.apu.channel1.length);
             pulse::trigger(&mut emulator.apu.channel1, true);
 
-            if pulse::should_clock_length_on_trigger(&emulator.apu.channel1) && length_period_first_half {
+            if pulse::should_clock_length_on_trigger(&emulator.apu.channel1, length_expired) && length_period_first_half {
                pulse::step_length(&mut emulator.apu.channel1);
             }
         }
@@ -309,9 +412,10 @@ pub fn set_ch2_period_high(emulator: &mut Emulator, new_period_high_value: u8) {
         }
 
         if pulse::should_trigger(&emulator.apu.channel2) { 
+            let length_expired = length::is_expired(&emulator.apu.channel2.length);
             pulse::trigger(&mut emulator.apu.channel2, false);
 
-            if pulse::should_clock_length_on_trigger(&emulator.apu.channel2) && length_period_first_half {
+            if pulse::should_clock_length_on_trigger(&emulator.apu.channel2, length_expired) && length_period_first_half {
                pulse::step_length(&mut emulator.apu.channel2);
             }
         }
@@ -333,9 +437,10 @@ pub fn set_ch3_period_high(emulator: &mut Emulator, new_period_high_value: u8) {
         }
 
         if wave::should_trigger(&emulator.apu.channel3) {
+            let length_expired = length::is_expired(&emulator.apu.channel3.length);
             wave::trigger(emulator);
 
-            if wave::should_clock_length_on_trigger(&emulator.apu.channel3) && length_period_first_half {
+            if wave::should_clock_length_on_trigger(&emulator.apu.channel3, length_expired) && length_period_first_half {
                wave::step_length(&mut emulator.apu.channel3);
             }
         }
@@ -357,9 +462,10 @@ pub fn set_ch4_control(emulator: &mut Emulator, new_control_value: u8) {
         }
 
         if noise::should_trigger(&emulator.apu.channel4) {
+            let length_expired = length::is_expired(&emulator.apu.channel4.length);
             noise::trigger(&mut emulator.apu.channel4);
 
-            if noise::should_clock_length_on_trigger(&emulator.apu.channel4) && length_period_first_half {
+            if noise::should_clock_length_on_trigger(&emulator.apu.channel4, length_expired) && length_period_first_half {
                noise::step_length(&mut emulator.apu.channel4);
             }
         }
@@ -368,8 +474,13 @@ pub fn set_ch4_control(emulator: &mut Emulator, new_control_value: u8) {
 
 pub fn set_ch1_envelope_settings(emulator: &mut Emulator, new_envelope_settings: u8) {
     if emulator.apu.enabled{
+        let original_envelope_settings = emulator.apu.channel1.envelope.initial_settings;
         emulator.apu.channel1.envelope.initial_settings = new_envelope_settings;
 
+        if emulator.apu.channel1.enabled {
+            envelope::apply_zombie_mode_write(&mut emulator.apu.channel1.envelope, original_envelope_settings);
+        }
+
         let should_disable = should_disable_dac(&emulator.apu.channel1.envelope);
     
         emulator.apu.channel1.dac_enabled = !should_disable;
@@ -382,8 +493,13 @@ pub fn set_ch1_envelope_settings(emulator: &mut Emulator, new_envelope_settings:
 
 pub fn set_ch2_envelope_settings(emulator: &mut Emulator, new_envelope_settings: u8) {
     if emulator.apu.enabled {
+        let original_envelope_settings = emulator.apu.channel2.envelope.initial_settings;
         emulator.apu.channel2.envelope.initial_settings = new_envelope_settings;
 
+        if emulator.apu.channel2.enabled {
+            envelope::apply_zombie_mode_write(&mut emulator.apu.channel2.envelope, original_envelope_settings);
+        }
+
         let should_disable = should_disable_dac(&emulator.apu.channel2.envelope);
     
         emulator.apu.channel2.dac_enabled = !should_disable;
@@ -408,8 +524,13 @@ pub fn set_ch3_dac_enabled(emulator: &mut Emulator, new_dac_enabled_register_val
 
 pub fn set_ch4_envelope_settings(emulator: &mut Emulator, new_envelope_settings: u8) {
     if emulator.apu.enabled {
+        let original_envelope_settings = emulator.apu.channel4.envelope.initial_settings;
         emulator.apu.channel4.envelope.initial_settings = new_envelope_settings;
 
+        if emulator.apu.channel4.enabled {
+            envelope::apply_zombie_mode_write(&mut emulator.apu.channel4.envelope, original_envelope_settings);
+        }
+
         let should_disable = should_disable_dac(&emulator.apu.channel4.envelope);
     
         emulator.apu.channel4.dac_enabled = !should_disable;
@@ -420,6 +541,15 @@ pub fn set_ch4_envelope_settings(emulator: &mut Emulator, new_envelope_settings:
     }
 }
 
+// PCM12 and PCM34 (CGB only) read back the current output level of two channels each, one per nibble.
+pub fn get_pcm12(emulator: &Emulator) -> u8 {
+    (pulse::pcm_output(&emulator.apu.channel2) << 4) | pulse::pcm_output(&emulator.apu.channel1)
+}
+
+pub fn get_pcm34(emulator: &Emulator) -> u8 {
+    (noise::pcm_output(&emulator.apu.channel4) << 4) | wave::pcm_output(&emulator.apu.channel3)
+}
+
 pub fn get_audio_master_control(emulator: &Emulator) -> u8 {
     let apu_enabled = if emulator.apu.enabled { 1 } else { 0 };
     let mask = 0b01110000;
@@ -571,6 +701,8 @@ pub mod wave;
 pub mod noise;
 pub mod length;
 pub mod sweep;
-mod envelope;
-mod period;
+pub mod envelope;
+pub mod period;
+pub mod filter;
+pub mod register_log;
 mod utils;
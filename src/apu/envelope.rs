@@ -41,6 +41,37 @@ pub fn step(envelope: &mut Envelope) {
     }
 }
 
+fn is_running(envelope: &Envelope, is_upwards: bool) -> bool {
+    (is_upwards && envelope.current_volume < 0xF) || (!is_upwards && envelope.current_volume > 0x0)
+}
+
+/*
+    "Zombie mode": writing NRx2 while a channel is playing nudges its volume
+    without retriggering it. Some music engines rely on this to change volume
+    on the fly, so the adjustment follows the DMG/CGB-C behavior documented on
+    the gbdev wiki.
+*/
+pub fn apply_zombie_mode_write(envelope: &mut Envelope, original_settings: u8) {
+    let original_timer = original_settings & 0b00000111;
+    let original_is_upwards = is_bit_set(original_settings, ENVELOPE_DIRECTION_INDEX);
+    let new_is_upwards = is_bit_set(envelope.initial_settings, ENVELOPE_DIRECTION_INDEX);
+
+    let mut volume = envelope.current_volume;
+
+    if original_timer == 0 && is_running(envelope, original_is_upwards) {
+        volume += 1;
+    }
+    else if !original_is_upwards {
+        volume += 2;
+    }
+
+    if original_is_upwards != new_is_upwards {
+        volume = 16 - volume;
+    }
+
+    envelope.current_volume = volume & 0xF;
+}
+
 pub fn trigger(envelope: &mut Envelope) {
     let initial_timer = envelope.initial_settings & 0b00000111;
     let initial_volume = (envelope.initial_settings & 0b11110000) >> 4;
@@ -50,4 +81,52 @@ pub fn trigger(envelope: &mut Envelope) {
     
 pub fn should_disable_dac(envelope: &Envelope) -> bool {
     envelope.initial_settings & 0xF8 == 0
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn envelope_with_volume(initial_settings: u8, current_volume: u8) -> Envelope {
+        Envelope {
+            initial_settings,
+            current_volume,
+            timer: 0
+        }
+    }
+
+    #[test]
+    fn should_increment_volume_when_original_period_was_zero() {
+        let mut envelope = envelope_with_volume(0b00001000, 0x5);
+        apply_zombie_mode_write(&mut envelope, 0b00001000);
+        assert_eq!(envelope.current_volume, 0x6);
+    }
+
+    #[test]
+    fn should_add_two_to_volume_when_original_envelope_was_decreasing() {
+        let mut envelope = envelope_with_volume(0b00000011, 0x5);
+        apply_zombie_mode_write(&mut envelope, 0b00000011);
+        assert_eq!(envelope.current_volume, 0x7);
+    }
+
+    #[test]
+    fn should_leave_volume_alone_when_original_envelope_was_increasing_with_period() {
+        let mut envelope = envelope_with_volume(0b00001011, 0x5);
+        apply_zombie_mode_write(&mut envelope, 0b00001011);
+        assert_eq!(envelope.current_volume, 0x5);
+    }
+
+    #[test]
+    fn should_invert_volume_when_direction_changes() {
+        let mut envelope = envelope_with_volume(0b00000001, 0x5);
+        apply_zombie_mode_write(&mut envelope, 0b00001001);
+        assert_eq!(envelope.current_volume, 0xB);
+    }
+
+    #[test]
+    fn should_wrap_volume_to_four_bits() {
+        let mut envelope = envelope_with_volume(0b00000001, 0xF);
+        apply_zombie_mode_write(&mut envelope, 0b00000001);
+        assert_eq!(envelope.current_volume, 0x1);
+    }
+}
@@ -0,0 +1,149 @@
+use crate::apu::CPU_RATE;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HighPassFilter {
+    None,
+    Dmg,
+    Cgb
+}
+
+#[derive(Debug)]
+pub struct FilterState {
+    pub high_pass_filter: HighPassFilter,
+    pub charge_factor: f32,
+    pub dac_charge_rate: f32,
+    pub dac_charges: [f32; 4],
+    pub held_outputs: [f32; 4],
+    pub left_capacitor: f32,
+    pub right_capacitor: f32
+}
+
+// Capacitor charge factors per clock cycle, as measured on hardware.
+const DMG_CHARGE_FACTOR: f64 = 0.999958;
+const CGB_CHARGE_FACTOR: f64 = 0.998943;
+
+// Roughly how long a DAC takes to settle after being switched on or off.
+const DAC_CHARGE_MILLIS: f64 = 1.0;
+
+pub fn initialize_filter(enqueue_rate: u32) -> FilterState {
+    let mut filter = FilterState {
+        high_pass_filter: HighPassFilter::Dmg,
+        charge_factor: 0.0,
+        dac_charge_rate: 1.0,
+        dac_charges: [0.0; 4],
+        held_outputs: [0.0; 4],
+        left_capacitor: 0.0,
+        right_capacitor: 0.0
+    };
+    configure(&mut filter, enqueue_rate);
+    filter
+}
+
+pub fn configure(filter: &mut FilterState, enqueue_rate: u32) {
+    let charge_factor = match filter.high_pass_filter {
+        HighPassFilter::None => 0.0,
+        HighPassFilter::Dmg => DMG_CHARGE_FACTOR,
+        HighPassFilter::Cgb => CGB_CHARGE_FACTOR
+    };
+    filter.charge_factor = charge_factor.powi(enqueue_rate as i32) as f32;
+
+    let sample_rate = CPU_RATE as f64 / enqueue_rate as f64;
+    filter.dac_charge_rate = (1.0 - (-1000.0 / (DAC_CHARGE_MILLIS * sample_rate)).exp()) as f32;
+}
+
+/*
+    A DAC doesn't snap between silence and its output level when it's switched
+    on or off (e.g. writing NR30 or retriggering channel 3), it charges and
+    discharges. Without this, those writes produce an audible pop.
+*/
+pub fn charge_dacs(filter: &mut FilterState, dac_enabled: [bool; 4], outputs: [f32; 4]) -> [f32; 4] {
+    if filter.high_pass_filter == HighPassFilter::None {
+        return outputs;
+    }
+
+    let mut charged_outputs = [0.0; 4];
+    for index in 0..4 {
+        let target_charge = if dac_enabled[index] { 1.0 } else { 0.0 };
+        filter.dac_charges[index] += (target_charge - filter.dac_charges[index]) * filter.dac_charge_rate;
+
+        if dac_enabled[index] {
+            filter.held_outputs[index] = outputs[index];
+        }
+
+        charged_outputs[index] = filter.held_outputs[index] * filter.dac_charges[index];
+    }
+    charged_outputs
+}
+
+/*
+    The output capacitor removes any DC offset from the mixed signal, so silence
+    settles back to zero regardless of which DACs are enabled.
+*/
+pub fn high_pass(filter_model: HighPassFilter, charge_factor: f32, capacitor: &mut f32, sample: f32) -> f32 {
+    if filter_model == HighPassFilter::None {
+        sample
+    }
+    else {
+        let output = sample - *capacitor;
+        *capacitor = sample - output * charge_factor;
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENQUEUE_RATE: u32 = 95;
+
+    #[test]
+    fn should_pass_samples_through_when_filter_is_disabled() {
+        let mut filter = initialize_filter(ENQUEUE_RATE);
+        filter.high_pass_filter = HighPassFilter::None;
+        configure(&mut filter, ENQUEUE_RATE);
+
+        let mut capacitor = 0.0;
+        assert_eq!(high_pass(filter.high_pass_filter, filter.charge_factor, &mut capacitor, 0.5), 0.5);
+        assert_eq!(charge_dacs(&mut filter, [false; 4], [0.25; 4]), [0.25; 4]);
+    }
+
+    #[test]
+    fn should_remove_dc_offset_over_time() {
+        let filter = initialize_filter(ENQUEUE_RATE);
+        let mut capacitor = 0.0;
+
+        let first_output = high_pass(filter.high_pass_filter, filter.charge_factor, &mut capacitor, 1.0);
+        assert_eq!(first_output, 1.0);
+
+        let mut output = first_output;
+        for _ in 0..44100 {
+            output = high_pass(filter.high_pass_filter, filter.charge_factor, &mut capacitor, 1.0);
+        }
+        assert!(output.abs() < 0.01);
+    }
+
+    #[test]
+    fn should_discharge_faster_with_cgb_constants() {
+        let mut dmg_filter = initialize_filter(ENQUEUE_RATE);
+        let mut cgb_filter = initialize_filter(ENQUEUE_RATE);
+        cgb_filter.high_pass_filter = HighPassFilter::Cgb;
+        configure(&mut dmg_filter, ENQUEUE_RATE);
+        configure(&mut cgb_filter, ENQUEUE_RATE);
+        assert!(cgb_filter.charge_factor < dmg_filter.charge_factor);
+    }
+
+    #[test]
+    fn should_ramp_dac_output_instead_of_stepping() {
+        let mut filter = initialize_filter(ENQUEUE_RATE);
+
+        let enabled_outputs = charge_dacs(&mut filter, [false, false, true, false], [0.0, 0.0, -1.0, 0.0]);
+        assert!(enabled_outputs[2] < 0.0 && enabled_outputs[2] > -1.0);
+
+        for _ in 0..1000 {
+            charge_dacs(&mut filter, [false, false, true, false], [0.0, 0.0, -1.0, 0.0]);
+        }
+
+        let disabled_outputs = charge_dacs(&mut filter, [false; 4], [0.0; 4]);
+        assert!(disabled_outputs[2] < 0.0 && disabled_outputs[2] > -1.0);
+    }
+}
@@ -49,10 +49,6 @@ pub fn reload_wave_channel_timer_with_maximum(length: &mut Length) {
     }
 }
 
-pub fn at_max_length(length: &Length) -> bool {
-    length.timer == DEFAULT_MAX_LENGTH
-}
-
-pub fn at_max_wave_channel_length(length: &Length) -> bool {
-    length.timer == WAVE_MAX_LENGTH
+pub fn is_expired(length: &Length) -> bool {
+    length.timer == 0
 }
\ No newline at end of file
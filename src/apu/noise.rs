@@ -92,8 +92,9 @@ pub fn should_clock_length_on_enable(channel: &NoiseChannel, original_control_va
     !length_enabled(original_control_value) && length_enabled(new_control_value)
 }
 
-pub fn should_clock_length_on_trigger(channel: &NoiseChannel) -> bool {
-    length::at_max_length(&channel.length) && length_enabled(channel.control)
+// Only a length timer that was reloaded to its maximum by the trigger gets the extra clock.
+pub fn should_clock_length_on_trigger(channel: &NoiseChannel, length_expired_before_trigger: bool) -> bool {
+    length_expired_before_trigger && length_enabled(channel.control)
 }
 
 pub fn step_length(channel: &mut NoiseChannel) {
@@ -106,11 +107,19 @@ pub fn step_length(channel: &mut NoiseChannel) {
     }
 }
 
-pub fn digital_output(channel: &NoiseChannel) -> f32 {
+pub fn pcm_output(channel: &NoiseChannel) -> u8 {
     if channel.enabled {
         let amplitude = (channel.lfsr & 0x01) as u8;
-        let current_volume = channel.envelope.current_volume;
-        (amplitude * current_volume) as f32
+        amplitude * channel.envelope.current_volume
+    }
+    else {
+        0
+    }
+}
+
+pub fn digital_output(channel: &NoiseChannel) -> f32 {
+    if channel.enabled {
+        pcm_output(channel) as f32
     }
     else {
         7.5
@@ -70,8 +70,9 @@ pub fn should_clock_length_on_enable(channel: &PulseChannel, original_period_hig
     !length_enabled(original_period_high_value) && length_enabled(new_period_high_value)
 }
 
-pub fn should_clock_length_on_trigger(channel: &PulseChannel) -> bool {
-    length::at_max_length(&channel.length) && length_enabled(channel.period.high)
+// Only a length timer that was reloaded to its maximum by the trigger gets the extra clock.
+pub fn should_clock_length_on_trigger(channel: &PulseChannel, length_expired_before_trigger: bool) -> bool {
+    length_expired_before_trigger && length_enabled(channel.period.high)
 }
 
 pub fn step_length(channel: &mut PulseChannel) {
@@ -84,13 +85,22 @@ pub fn step_length(channel: &mut PulseChannel) {
     }
 }
 
-pub fn digital_output(channel: &PulseChannel) -> f32 {
-    if channel.enabled {    
+// The channel's output level as the CGB reports it in PCM12, which is 0 while the channel is off.
+pub fn pcm_output(channel: &PulseChannel) -> u8 {
+    if channel.enabled {
         let wave_duty = (channel.length.initial_settings & 0b11000000) >> 6;
         let waveform = WAVEFORMS[wave_duty as usize];
         let amplitude = get_bit(waveform, channel.wave_duty_position);
-        let current_volume = channel.envelope.current_volume;
-        (amplitude * current_volume) as f32
+        amplitude * channel.envelope.current_volume
+    }
+    else {
+        0
+    }
+}
+
+pub fn digital_output(channel: &PulseChannel) -> f32 {
+    if channel.enabled {    
+        pcm_output(channel) as f32
     }
     else {
         7.5
@@ -103,6 +113,11 @@ pub fn step_sweep(channel: &mut PulseChannel) {
     }
 }
 
+/*
+    Triggering doesn't reset the duty position, only powering the APU off does, so
+    a retriggered note picks up the waveform where it left off. Period writes
+    without a trigger only take effect once the divider next runs out.
+*/
 pub fn trigger(channel: &mut PulseChannel, with_sweep: bool) {
     if channel.dac_enabled {
         channel.enabled = true;
@@ -180,6 +195,44 @@ mod tests {
         assert_eq!(digital_output(&channel), 10.0);
     }
 
+    #[test]
+    fn should_keep_duty_position_and_reload_divider_on_trigger() {
+        let mut channel = initialize_pulse_channel();
+        enable_pulse_channel(&mut channel);
+        channel.wave_duty_position = 5;
+        channel.period.divider = 0x1F6;
+        channel.period.low = 0x00;
+        channel.period.high = 0x07;
+
+        trigger(&mut channel, false);
+
+        assert_eq!(channel.wave_duty_position, 5);
+        assert_eq!(channel.period.divider, 0x100);
+
+        channel.period.low = 0xFF;
+        trigger(&mut channel, false);
+        assert_eq!(channel.period.divider, 1);
+    }
+
+    #[test]
+    fn should_apply_period_change_at_next_divider_reload() {
+        let mut channel = initialize_pulse_channel();
+        enable_pulse_channel(&mut channel);
+        channel.period.high = 0x07;
+        channel.period.low = 0xF0;
+        trigger(&mut channel, false);
+        assert_eq!(channel.period.divider, 0x10);
+
+        channel.period.low = 0xE0;
+        step(&mut channel, 4 * 0x0F);
+        assert_eq!(channel.period.divider, 1);
+        assert_eq!(channel.wave_duty_position, 0);
+
+        step(&mut channel, 4);
+        assert_eq!(channel.period.divider, 0x20);
+        assert_eq!(channel.wave_duty_position, 1);
+    }
+
     #[test]
     fn should_produce_no_audio_output_if_channel_is_disabled() {
         let mut channel = initialize_pulse_channel();
@@ -0,0 +1,213 @@
+use crate::apu::{self, CPU_RATE};
+use crate::emulator::Emulator;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterWrite {
+    // Clock cycles since logging started.
+    pub cycle: u64,
+    pub address: u16,
+    pub value: u8
+}
+
+#[derive(Debug, Default)]
+pub struct RegisterLogState {
+    pub enabled: bool,
+    pub writes: Vec<RegisterWrite>,
+    elapsed_cycles: u64,
+    last_total_clock_cycles: u32
+}
+
+pub fn initialize_register_log() -> RegisterLogState {
+    RegisterLogState::default()
+}
+
+fn is_audio_register(address: u16) -> bool {
+    (0xFF10..=0xFF3F).contains(&address)
+}
+
+fn elapsed_cycles(emulator: &mut Emulator) -> u64 {
+    let total_clock_cycles = emulator.cpu.clock.total_clock_cycles;
+    let log = &mut emulator.apu.register_log;
+    log.elapsed_cycles += total_clock_cycles.wrapping_sub(log.last_total_clock_cycles) as u64;
+    log.last_total_clock_cycles = total_clock_cycles;
+    log.elapsed_cycles
+}
+
+// Loading a savestate rewinds the CPU clock, which must not leave a gap in the log.
+pub fn sync_clock_reference(emulator: &mut Emulator) {
+    emulator.apu.register_log.last_total_clock_cycles = emulator.cpu.clock.total_clock_cycles;
+}
+
+/*
+    Starting a log mid-game wouldn't capture registers the game set up earlier, so
+    it opens with the power, volume, panning and wave RAM state. Channel settings
+    are rewritten by games on every note, so they're picked up as the song plays.
+*/
+pub fn start_register_log(emulator: &mut Emulator) {
+    emulator.apu.register_log = RegisterLogState {
+        enabled: true,
+        writes: Vec::new(),
+        elapsed_cycles: 0,
+        last_total_clock_cycles: emulator.cpu.clock.total_clock_cycles
+    };
+
+    let mut initial_state = vec![
+        (0xFF26, apu::get_audio_master_control(emulator) & 0x80),
+        (0xFF24, emulator.apu.master_volume),
+        (0xFF25, emulator.apu.sound_panning)
+    ];
+
+    for (index, value) in emulator.apu.channel3.wave_pattern_ram.iter().enumerate() {
+        initial_state.push((0xFF30 + index as u16, *value));
+    }
+
+    for (address, value) in initial_state {
+        emulator.apu.register_log.writes.push(RegisterWrite { cycle: 0, address, value });
+    }
+}
+
+pub fn stop_register_log(emulator: &mut Emulator) {
+    emulator.apu.register_log.enabled = false;
+}
+
+pub fn record_write(emulator: &mut Emulator, address: u16, value: u8) {
+    if emulator.apu.register_log.enabled && is_audio_register(address) {
+        let cycle = elapsed_cycles(emulator);
+        emulator.apu.register_log.writes.push(RegisterWrite { cycle, address, value });
+    }
+}
+
+const VGM_SAMPLE_RATE: u64 = 44100;
+const VGM_HEADER_LENGTH: usize = 0x100;
+const VGM_VERSION: u32 = 0x171;
+const VGM_DATA_OFFSET: usize = 0x34;
+const VGM_GB_DMG_CLOCK_OFFSET: usize = 0x80;
+const VGM_GB_DMG_WRITE: u8 = 0xB3;
+const VGM_WAIT: u8 = 0x61;
+const VGM_END: u8 = 0x66;
+
+fn write_u32(buffer: &mut [u8], offset: usize, value: u32) {
+    buffer[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn push_wait(data: &mut Vec<u8>, mut samples: u64) {
+    while samples > 0 {
+        let wait = samples.min(u16::MAX as u64) as u16;
+        data.push(VGM_WAIT);
+        data.extend_from_slice(&wait.to_le_bytes());
+        samples -= wait as u64;
+    }
+}
+
+fn as_sample(cycle: u64) -> u64 {
+    cycle * VGM_SAMPLE_RATE / CPU_RATE as u64
+}
+
+/*
+    Exports the log as a VGM file, which chiptune players and trackers can import.
+    Waits are measured in 44.1kHz samples as the format requires, and the log is
+    padded out to the current time so trailing notes aren't cut off.
+*/
+pub fn export_vgm(emulator: &mut Emulator) -> Vec<u8> {
+    let end_cycle = if emulator.apu.register_log.enabled { elapsed_cycles(emulator) } else { emulator.apu.register_log.elapsed_cycles };
+    let mut data = Vec::new();
+    let mut current_sample = 0;
+
+    for write in &emulator.apu.register_log.writes {
+        let sample = as_sample(write.cycle);
+        push_wait(&mut data, sample - current_sample);
+        current_sample = sample;
+        data.extend_from_slice(&[VGM_GB_DMG_WRITE, (write.address - 0xFF10) as u8, write.value]);
+    }
+
+    let total_samples = as_sample(end_cycle).max(current_sample);
+    push_wait(&mut data, total_samples - current_sample);
+    data.push(VGM_END);
+
+    let mut vgm = vec![0; VGM_HEADER_LENGTH];
+    vgm[0..4].copy_from_slice(b"Vgm ");
+    write_u32(&mut vgm, 0x04, (VGM_HEADER_LENGTH + data.len() - 4) as u32);
+    write_u32(&mut vgm, 0x08, VGM_VERSION);
+    write_u32(&mut vgm, 0x18, total_samples as u32);
+    write_u32(&mut vgm, VGM_DATA_OFFSET, (VGM_HEADER_LENGTH - VGM_DATA_OFFSET) as u32);
+    write_u32(&mut vgm, VGM_GB_DMG_CLOCK_OFFSET, CPU_RATE);
+    vgm.extend_from_slice(&data);
+    vgm
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::initialize_screenless_emulator;
+    use crate::mmu;
+    use crate::savestate;
+    use super::*;
+
+    fn advance(emulator: &mut Emulator, cycles: u32) {
+        emulator.cpu.clock.total_clock_cycles = emulator.cpu.clock.total_clock_cycles.wrapping_add(cycles);
+    }
+
+    #[test]
+    fn should_not_log_writes_unless_started() {
+        let mut emulator = initialize_screenless_emulator();
+        mmu::write_byte(&mut emulator, 0xFF26, 0x80);
+        assert!(emulator.apu.register_log.writes.is_empty());
+    }
+
+    #[test]
+    fn should_log_audio_register_writes_with_timestamps() {
+        let mut emulator = initialize_screenless_emulator();
+        emulator.memory.in_bios = false;
+        start_register_log(&mut emulator);
+        let initial_writes = emulator.apu.register_log.writes.len();
+
+        advance(&mut emulator, 100);
+        mmu::write_byte(&mut emulator, 0xFF26, 0x80);
+        mmu::write_byte(&mut emulator, 0xC000, 0x12);
+        advance(&mut emulator, 50);
+        mmu::write_byte(&mut emulator, 0xFF12, 0xF0);
+        stop_register_log(&mut emulator);
+        mmu::write_byte(&mut emulator, 0xFF13, 0x00);
+
+        assert_eq!(emulator.apu.register_log.writes[initial_writes..], [
+            RegisterWrite { cycle: 100, address: 0xFF26, value: 0x80 },
+            RegisterWrite { cycle: 150, address: 0xFF12, value: 0xF0 }
+        ]);
+    }
+
+    #[test]
+    fn should_not_leave_gap_in_log_when_state_is_loaded() {
+        let mut emulator = initialize_screenless_emulator();
+        emulator.memory.in_bios = false;
+        let state = savestate::encode_state(&emulator);
+        advance(&mut emulator, 1000);
+        start_register_log(&mut emulator);
+        let initial_writes = emulator.apu.register_log.writes.len();
+
+        savestate::decode_state(&mut emulator, &state).unwrap();
+        advance(&mut emulator, 50);
+        mmu::write_byte(&mut emulator, 0xFF12, 0xF0);
+
+        assert_eq!(emulator.apu.register_log.writes[initial_writes..], [
+            RegisterWrite { cycle: 50, address: 0xFF12, value: 0xF0 }
+        ]);
+    }
+
+    #[test]
+    fn should_export_vgm_file() {
+        let mut emulator = initialize_screenless_emulator();
+        emulator.memory.in_bios = false;
+        start_register_log(&mut emulator);
+        advance(&mut emulator, CPU_RATE);
+        mmu::write_byte(&mut emulator, 0xFF26, 0x80);
+
+        let vgm = export_vgm(&mut emulator);
+        assert_eq!(&vgm[0..4], b"Vgm ");
+        assert_eq!(u32::from_le_bytes(vgm[0x04..0x08].try_into().unwrap()) as usize, vgm.len() - 4);
+        assert_eq!(u32::from_le_bytes(vgm[0x18..0x1C].try_into().unwrap()), 44100);
+        assert_eq!(u32::from_le_bytes(vgm[0x80..0x84].try_into().unwrap()), CPU_RATE);
+
+        // 19 initial writes at time zero, then a one second wait before the NR52 write.
+        let commands = &vgm[VGM_HEADER_LENGTH + 19 * 3..];
+        assert_eq!(commands, [VGM_WAIT, 0x44, 0xAC, VGM_GB_DMG_WRITE, 0x16, 0x80, VGM_END]);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::emulator::initialize_screenless_emulator;
+use crate::emulator::{initialize_screenless_emulator, Mode};
 use super::*;
 
 fn prep_div_apu_for_next_step(emulator: &mut Emulator, step: u8) {
@@ -43,6 +43,14 @@ fn should_not_decrement_period_divider_when_apu_is_off() {
     assert_eq!(emulator.apu.channel1.period.divider, 742); 
 }
 
+#[test]
+fn should_not_advance_channel_clock_when_apu_is_off() {
+    let mut emulator = initialize_screenless_emulator();
+    emulator.apu.enabled = false;
+    step_apu_multiple_times(&mut emulator, 255);
+    assert_eq!(emulator.apu.channel_clock, 0);
+}
+
 #[test]
 fn should_not_decrement_period_divider_if_channel_1_is_off() {
     let mut emulator = initialize_screenless_emulator();
@@ -731,3 +739,213 @@ fn should_step_channel_4_envelope_timer() {
     assert_eq!(emulator.apu.channel4.envelope.current_volume, 0b1010);
     assert_eq!(emulator.apu.channel4.envelope.timer, 0b100);
 }
+
+#[test]
+fn should_reload_expired_length_to_maximum_on_trigger() {
+    let mut emulator = initialize_screenless_emulator();
+    emulator.apu.enabled = true;
+    emulator.apu.divider_apu = 2;
+    emulator.apu.channel1.dac_enabled = true;
+    emulator.apu.channel1.length.timer = 0;
+    set_ch1_period_high(&mut emulator, 0b11000000);
+    assert_eq!(emulator.apu.channel1.length.timer, 64);
+    assert!(emulator.apu.channel1.enabled);
+}
+
+#[test]
+fn should_clock_reloaded_length_on_trigger_in_first_half_of_length_period() {
+    let mut emulator = initialize_screenless_emulator();
+    emulator.apu.enabled = true;
+    emulator.apu.divider_apu = 1;
+    emulator.apu.channel1.dac_enabled = true;
+    emulator.apu.channel1.length.timer = 0;
+    set_ch1_period_high(&mut emulator, 0b11000000);
+    assert_eq!(emulator.apu.channel1.length.timer, 63);
+    assert!(emulator.apu.channel1.enabled);
+}
+
+#[test]
+fn should_not_clock_full_length_on_trigger_if_it_was_not_reloaded() {
+    let mut emulator = initialize_screenless_emulator();
+    emulator.apu.enabled = true;
+    emulator.apu.divider_apu = 1;
+    emulator.apu.channel2.dac_enabled = true;
+    emulator.apu.channel2.period.high = 0b01000000;
+    emulator.apu.channel2.length.timer = 64;
+    set_ch2_period_high(&mut emulator, 0b11000000);
+    assert_eq!(emulator.apu.channel2.length.timer, 64);
+}
+
+#[test]
+fn should_clock_reloaded_wave_channel_length_on_trigger_in_first_half_of_length_period() {
+    let mut emulator = initialize_screenless_emulator();
+    emulator.apu.enabled = true;
+    emulator.apu.divider_apu = 3;
+    emulator.apu.channel3.dac_enabled = true;
+    emulator.apu.channel3.length.timer = 0;
+    set_ch3_period_high(&mut emulator, 0b11000000);
+    assert_eq!(emulator.apu.channel3.length.timer, 255);
+}
+
+#[test]
+fn should_clock_reloaded_noise_channel_length_on_trigger_in_first_half_of_length_period() {
+    let mut emulator = initialize_screenless_emulator();
+    emulator.apu.enabled = true;
+    emulator.apu.divider_apu = 5;
+    emulator.apu.channel4.dac_enabled = true;
+    emulator.apu.channel4.length.timer = 0;
+    set_ch4_control(&mut emulator, 0b11000000);
+    assert_eq!(emulator.apu.channel4.length.timer, 63);
+}
+
+#[test]
+fn should_disable_channel_1_if_sweep_overflows_on_trigger() {
+    let mut emulator = initialize_screenless_emulator();
+    emulator.apu.enabled = true;
+    emulator.apu.channel1.dac_enabled = true;
+    emulator.apu.channel1.period.low = 0xFF;
+    emulator.apu.channel1.sweep.initial_settings = 0b00010001;
+    set_ch1_period_high(&mut emulator, 0b10000111);
+    assert!(!emulator.apu.channel1.enabled);
+    assert_eq!(get_audio_master_control(&emulator), 0b11110000);
+}
+
+#[test]
+fn should_not_disable_channel_1_on_trigger_if_sweep_shift_is_zero() {
+    let mut emulator = initialize_screenless_emulator();
+    emulator.apu.enabled = true;
+    emulator.apu.channel1.dac_enabled = true;
+    emulator.apu.channel1.period.low = 0xFF;
+    emulator.apu.channel1.sweep.initial_settings = 0b00010000;
+    set_ch1_period_high(&mut emulator, 0b10000111);
+    assert!(emulator.apu.channel1.enabled);
+}
+
+#[test]
+fn should_adjust_volume_when_writing_envelope_while_channel_1_is_playing() {
+    let mut emulator = initialize_screenless_emulator();
+    emulator.apu.enabled = true;
+    emulator.apu.channel1.dac_enabled = true;
+    emulator.apu.channel1.enabled = true;
+    emulator.apu.channel1.envelope.initial_settings = 0b10000000;
+    emulator.apu.channel1.envelope.current_volume = 0x8;
+    set_ch1_envelope_settings(&mut emulator, 0b10000000);
+    assert_eq!(emulator.apu.channel1.envelope.current_volume, 0x9);
+}
+
+#[test]
+fn should_not_adjust_volume_when_writing_envelope_while_channel_4_is_silent() {
+    let mut emulator = initialize_screenless_emulator();
+    initialize_disabled_noise_channel(&mut emulator);
+    emulator.apu.channel4.envelope.initial_settings = 0b10000000;
+    emulator.apu.channel4.envelope.current_volume = 0x8;
+    set_ch4_envelope_settings(&mut emulator, 0b10000000);
+    assert_eq!(emulator.apu.channel4.envelope.current_volume, 0x8);
+}
+
+#[test]
+fn should_store_vin_bits_of_master_volume_without_mixing_them() {
+    let mut emulator = initialize_screenless_emulator();
+    emulator.apu.enabled = true;
+    set_master_volume(&mut emulator, 0b10001000);
+    assert_eq!(emulator.apu.master_volume, 0b10001000);
+
+    set_sample_rate(&mut emulator, CPU_RATE / 4);
+    step(&mut emulator);
+    assert_eq!(emulator.apu.left_sample_queue, vec![0.0]);
+    assert_eq!(emulator.apu.right_sample_queue, vec![0.0]);
+}
+
+fn power_cycle_with_running_length_timers(emulator: &mut Emulator) {
+    set_audio_master_control(emulator, 0x80);
+    emulator.apu.channel1.length.timer = 12;
+    emulator.apu.channel2.length.timer = 34;
+    emulator.apu.channel3.length.timer = 156;
+    emulator.apu.channel4.length.timer = 7;
+    set_audio_master_control(emulator, 0x00);
+    set_audio_master_control(emulator, 0x80);
+}
+
+#[test]
+fn should_preserve_length_timers_across_power_cycle_on_dmg() {
+    let mut emulator = initialize_screenless_emulator();
+    power_cycle_with_running_length_timers(&mut emulator);
+
+    assert_eq!(emulator.apu.channel1.length.timer, 12);
+    assert_eq!(emulator.apu.channel2.length.timer, 34);
+    assert_eq!(emulator.apu.channel3.length.timer, 156);
+    assert_eq!(emulator.apu.channel4.length.timer, 7);
+    assert_eq!(emulator.apu.channel1.length.initial_settings, 0);
+}
+
+#[test]
+fn should_clear_length_timers_across_power_cycle_on_cgb() {
+    let mut emulator = initialize_screenless_emulator();
+    emulator.mode = Mode::CGB;
+    power_cycle_with_running_length_timers(&mut emulator);
+
+    assert_eq!(emulator.apu.channel1.length.timer, 0);
+    assert_eq!(emulator.apu.channel2.length.timer, 0);
+    assert_eq!(emulator.apu.channel3.length.timer, 0);
+    assert_eq!(emulator.apu.channel4.length.timer, 0);
+}
+
+#[test]
+fn should_clear_everything_but_length_and_wave_ram_on_power_off() {
+    let mut emulator = initialize_screenless_emulator();
+    set_audio_master_control(&mut emulator, 0x80);
+    set_ch1_envelope_settings(&mut emulator, 0xF3);
+    set_ch1_period_high(&mut emulator, 0x87);
+    set_sound_panning(&mut emulator, 0xFF);
+    emulator.apu.channel3.wave_pattern_ram[0] = 0xAB;
+    emulator.apu.divider_apu = 5;
+
+    set_audio_master_control(&mut emulator, 0x00);
+
+    assert!(!emulator.apu.channel1.enabled);
+    assert_eq!(emulator.apu.channel1.envelope.initial_settings, 0);
+    assert_eq!(emulator.apu.channel1.period.high, 0);
+    assert_eq!(emulator.apu.sound_panning, 0);
+    assert_eq!(emulator.apu.divider_apu, 0);
+    assert_eq!(emulator.apu.channel3.wave_pattern_ram[0], 0xAB);
+}
+
+#[test]
+fn should_fill_audio_buffer_to_configured_size() {
+    let mut emulator = initialize_screenless_emulator();
+    emulator.apu.enabled = true;
+    set_audio_buffer_size(&mut emulator, 128);
+
+    while !audio_buffers_full(&mut emulator) {
+        step_apu_multiple_times(&mut emulator, 1);
+    }
+
+    assert_eq!(get_left_sample_queue(&emulator).len(), 128);
+    assert_eq!(get_queued_audio_millis(&emulator), get_audio_buffer_latency_millis(&emulator));
+}
+
+#[test]
+fn should_report_audio_buffer_latency_at_sample_rate() {
+    let mut emulator = initialize_screenless_emulator();
+    set_sample_rate(&mut emulator, 48000);
+    set_audio_buffer_size(&mut emulator, 480);
+    assert_eq!(get_audio_buffer_latency_millis(&emulator), 10.0);
+
+    set_audio_buffer_size(&mut emulator, 1);
+    assert_eq!(emulator.apu.audio_buffer_size, MIN_AUDIO_BUFFER_SIZE);
+}
+
+#[test]
+fn should_drain_oldest_samples_into_buffers() {
+    let mut emulator = initialize_screenless_emulator();
+    emulator.apu.left_sample_queue = vec![0.1, 0.2, 0.3];
+    emulator.apu.right_sample_queue = vec![0.4, 0.5, 0.6];
+    let mut left = [0.0; 2];
+    let mut right = [0.0; 2];
+
+    assert_eq!(drain_audio_samples(&mut emulator, &mut left, &mut right), 2);
+    assert_eq!((left, right), ([0.1, 0.2], [0.4, 0.5]));
+    assert_eq!(drain_audio_samples(&mut emulator, &mut left, &mut right), 1);
+    assert_eq!((left[0], right[0]), (0.3, 0.6));
+    assert_eq!(queued_sample_count(&emulator), 0);
+}
@@ -27,15 +27,38 @@ const CHANNEL3_RIGHT_PANNING_INDEX: u8 = 2;
 const CHANNEL2_RIGHT_PANNING_INDEX: u8 = 1;
 const CHANNEL1_RIGHT_PANNING_INDEX: u8 = 0;
 
-fn get_panned_output(sound_panning: u8, panning_bit_index: u8, output: f32) -> f32 {
-    if is_bit_set(sound_panning, panning_bit_index) {
-        output
+pub type PanningGains = [f32; 8];
+
+// Gains are indexed by their NR51 bit, fully on for channels panned to that side.
+pub fn as_panning_gains(sound_panning: u8) -> PanningGains {
+    let mut gains = [0.0; 8];
+    for (panning_bit_index, gain) in gains.iter_mut().enumerate() {
+        *gain = if is_bit_set(sound_panning, panning_bit_index as u8) { 1.0 } else { 0.0 };
     }
-    else {
-        0.0
+    gains
+}
+
+/*
+    Flipping an NR51 bit while a channel is playing instantly moves its DC level
+    between sides, which clicks. Gains slide towards the new panning over a few
+    samples instead.
+*/
+pub fn ramp_panning_gains(gains: &mut PanningGains, sound_panning: u8, ramp_step: f32) {
+    let target_gains = as_panning_gains(sound_panning);
+    for (gain, target_gain) in gains.iter_mut().zip(target_gains) {
+        if *gain < target_gain {
+            *gain = (*gain + ramp_step).min(target_gain);
+        }
+        else {
+            *gain = (*gain - ramp_step).max(target_gain);
+        }
     }
 }
 
+fn get_panned_output(panning_gains: &PanningGains, panning_bit_index: u8, output: f32) -> f32 {
+    output * panning_gains[panning_bit_index as usize]
+}
+
 fn mix_samples(channel1_output: f32,
     channel2_output: f32,
     channel3_output: f32,
@@ -48,16 +71,16 @@ fn apply_volume_reduction(sample: f32, master_volume: u8) -> f32 {
     sample * volume_reduction
 }
 
-pub fn calculate_left_stereo_sample(sound_panning: u8,
+pub fn calculate_left_stereo_sample(panning_gains: &PanningGains,
     left_master_volume: u8,
     channel1_output: f32,
     channel2_output: f32,
     channel3_output: f32,
     channel4_output: f32) -> f32 {
-    let channel1_panned_output = get_panned_output(sound_panning, CHANNEL1_LEFT_PANNING_INDEX, channel1_output);
-    let channel2_panned_output = get_panned_output(sound_panning, CHANNEL2_LEFT_PANNING_INDEX, channel2_output);
-    let channel3_panned_output = get_panned_output(sound_panning, CHANNEL3_LEFT_PANNING_INDEX, channel3_output);
-    let channel4_panned_output = get_panned_output(sound_panning, CHANNEL4_LEFT_PANNING_INDEX, channel4_output);
+    let channel1_panned_output = get_panned_output(panning_gains, CHANNEL1_LEFT_PANNING_INDEX, channel1_output);
+    let channel2_panned_output = get_panned_output(panning_gains, CHANNEL2_LEFT_PANNING_INDEX, channel2_output);
+    let channel3_panned_output = get_panned_output(panning_gains, CHANNEL3_LEFT_PANNING_INDEX, channel3_output);
+    let channel4_panned_output = get_panned_output(panning_gains, CHANNEL4_LEFT_PANNING_INDEX, channel4_output);
  
     let left_sample = mix_samples(channel1_panned_output, 
         channel2_panned_output, 
@@ -67,16 +90,16 @@ pub fn calculate_left_stereo_sample(sound_panning: u8,
     apply_volume_reduction(left_sample, left_master_volume)
 }
 
-pub fn calculate_right_stereo_sample(sound_panning: u8,
+pub fn calculate_right_stereo_sample(panning_gains: &PanningGains,
     right_master_volume: u8,
     channel1_output: f32,
     channel2_output: f32,
     channel3_output: f32,
     channel4_output: f32) -> f32 {
-    let channel1_panned_output = get_panned_output(sound_panning, CHANNEL1_RIGHT_PANNING_INDEX, channel1_output);
-    let channel2_panned_output = get_panned_output(sound_panning, CHANNEL2_RIGHT_PANNING_INDEX, channel2_output);
-    let channel3_panned_output = get_panned_output(sound_panning, CHANNEL3_RIGHT_PANNING_INDEX, channel3_output);
-    let channel4_panned_output = get_panned_output(sound_panning, CHANNEL4_RIGHT_PANNING_INDEX, channel4_output);
+    let channel1_panned_output = get_panned_output(panning_gains, CHANNEL1_RIGHT_PANNING_INDEX, channel1_output);
+    let channel2_panned_output = get_panned_output(panning_gains, CHANNEL2_RIGHT_PANNING_INDEX, channel2_output);
+    let channel3_panned_output = get_panned_output(panning_gains, CHANNEL3_RIGHT_PANNING_INDEX, channel3_output);
+    let channel4_panned_output = get_panned_output(panning_gains, CHANNEL4_RIGHT_PANNING_INDEX, channel4_output);
  
     let right_sample = mix_samples(channel1_panned_output, 
         channel2_panned_output, 
@@ -97,7 +120,7 @@ mod tests {
         let channel3_output = -0.15 as f32;
         let channel4_output = 1.0 as f32;
 
-        calculate_left_stereo_sample(sound_panning,
+        calculate_left_stereo_sample(&as_panning_gains(sound_panning),
             master_volume,
             channel1_output,
             channel2_output,
@@ -112,7 +135,7 @@ mod tests {
         let channel3_output = -0.15 as f32;
         let channel4_output = 1.0 as f32;
 
-        calculate_right_stereo_sample(sound_panning,
+        calculate_right_stereo_sample(&as_panning_gains(sound_panning),
             master_volume,
             channel1_output,
             channel2_output,
@@ -147,4 +170,17 @@ mod tests {
         let right_stereo_sample = mix_right_samples(right_master_volume);
         assert_eq!(right_stereo_sample, 0.16875);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn should_ramp_panning_gains_towards_new_panning() {
+        let mut gains = as_panning_gains(0b00000001);
+        ramp_panning_gains(&mut gains, 0b00010000, 0.25);
+        assert_eq!(gains[0], 0.75);
+        assert_eq!(gains[4], 0.25);
+
+        for _ in 0..3 {
+            ramp_panning_gains(&mut gains, 0b00010000, 0.25);
+        }
+        assert_eq!(gains, as_panning_gains(0b00010000));
+    }
+}
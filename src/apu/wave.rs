@@ -60,8 +60,9 @@ pub fn should_clock_length_on_enable(channel: &WaveChannel, original_period_high
     !length_enabled(original_period_high_value) && length_enabled(new_period_high_value)
 }
 
-pub fn should_clock_length_on_trigger(channel: &WaveChannel) -> bool {
-    length::at_max_wave_channel_length(&channel.length) && length_enabled(channel.period.high)
+// Only a length timer that was reloaded to its maximum by the trigger gets the extra clock.
+pub fn should_clock_length_on_trigger(channel: &WaveChannel, length_expired_before_trigger: bool) -> bool {
+    length_expired_before_trigger && length_enabled(channel.period.high)
 }
 
 pub fn step_length(channel: &mut WaveChannel) {
@@ -82,22 +83,36 @@ pub fn write_to_wave_ram(channel: &mut WaveChannel, localized_address: u8, new_v
     channel.wave_pattern_ram[localized_address as usize] = new_value;
 }
 
-pub fn digital_output(emulator: &Emulator) -> f32 {
-    if emulator.apu.channel3.enabled {
-        let localized_address = emulator.apu.channel3.wave_position / 2;
-        let byte_offset = emulator.apu.channel3.wave_position % 2;
-    
-        let byte = read_from_wave_ram(&emulator.apu.channel3, localized_address);
+fn output_level(channel: &WaveChannel) -> u8 {
+    (channel.volume & 0b01100000) >> 5
+}
+
+// Muting the channel with the output level reads back as 0, like a disabled channel.
+pub fn pcm_output(channel: &WaveChannel) -> u8 {
+    if channel.enabled {
+        let localized_address = channel.wave_position / 2;
+        let byte_offset = channel.wave_position % 2;
+
+        let byte = read_from_wave_ram(channel, localized_address);
         let sample = if byte_offset == 0 { (byte & 0xF0) >> 4 } else { byte & 0xF };
-    
-        let output_level = (emulator.apu.channel3.volume & 0b01100000) >> 5;
-        match output_level {
-            0b01 => sample as f32,
-            0b10 => (sample >> 1) as f32,
-            0b11 => (sample >> 2) as f32,
-            _ => 7.5
+
+        match output_level(channel) {
+            0b01 => sample,
+            0b10 => sample >> 1,
+            0b11 => sample >> 2,
+            _ => 0
         }
     }
+    else {
+        0
+    }
+}
+
+pub fn digital_output(emulator: &Emulator) -> f32 {
+    let channel = &emulator.apu.channel3;
+    if channel.enabled && output_level(channel) != 0 {
+        pcm_output(channel) as f32
+    }
     else {
         7.5
     }
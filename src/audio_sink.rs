@@ -0,0 +1,183 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/*
+    Where frontends send the samples taken from the emulator. The emulator should
+    produce samples at the sink's rate (see GameBoy::set_sample_rate), so sinks only
+    buffer and never resample.
+*/
+pub trait AudioSink {
+    fn sample_rate(&self) -> u32;
+    fn push_samples(&mut self, left: &[f32], right: &[f32]);
+    // How long the samples pushed so far will take to play out.
+    fn latency_millis(&self) -> f64;
+}
+
+// Samples played after an underrun fade out by this factor, so the output doesn't click or hold a DC offset.
+const UNDERRUN_FADE: f32 = 0.995;
+
+/*
+    A queue of stereo samples between the emulator and an audio callback, for
+    implementing sinks on top of callback-based audio APIs. When the callback runs
+    dry it fades out the last sample and then waits until half the target latency is
+    queued again before resuming, instead of playing scattered samples as they
+    trickle in. Pushing more than twice the target latency drops the oldest samples
+    so latency can't keep growing when the emulator runs ahead of the device.
+*/
+#[derive(Debug)]
+pub struct SampleBuffer {
+    samples: VecDeque<(f32, f32)>,
+    sample_rate: u32,
+    target_samples: usize,
+    buffering: bool,
+    last_sample: (f32, f32),
+    pub underruns: u64
+}
+
+pub type SharedSampleBuffer = Arc<Mutex<SampleBuffer>>;
+
+impl SampleBuffer {
+    pub fn new(sample_rate: u32, target_latency_millis: u32) -> SampleBuffer {
+        let target_samples = (sample_rate as u64 * target_latency_millis as u64 / 1000).max(1) as usize;
+        SampleBuffer {
+            samples: VecDeque::with_capacity(target_samples * 2),
+            sample_rate,
+            target_samples,
+            buffering: true,
+            last_sample: (0.0, 0.0),
+            underruns: 0
+        }
+    }
+
+    pub fn shared(sample_rate: u32, target_latency_millis: u32) -> SharedSampleBuffer {
+        Arc::new(Mutex::new(SampleBuffer::new(sample_rate, target_latency_millis)))
+    }
+
+    pub fn queued_samples(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+        self.buffering = true;
+    }
+
+    fn next_sample(&mut self) -> (f32, f32) {
+        if self.buffering && self.samples.len() >= self.target_samples / 2 {
+            self.buffering = false;
+        }
+
+        if !self.buffering {
+            if let Some(sample) = self.samples.pop_front() {
+                self.last_sample = sample;
+                return sample;
+            }
+            self.buffering = true;
+            self.underruns += 1;
+        }
+
+        self.last_sample = (self.last_sample.0 * UNDERRUN_FADE, self.last_sample.1 * UNDERRUN_FADE);
+        self.last_sample
+    }
+
+    /*
+        Fills an interleaved output buffer with the given number of channels. Mono
+        devices get both channels mixed, and channels past the second are left silent.
+    */
+    pub fn fill(&mut self, output: &mut [f32], channels: usize) {
+        for frame in output.chunks_mut(channels.max(1)) {
+            let (left, right) = self.next_sample();
+            match frame {
+                [mono] => *mono = (left + right) / 2.0,
+                [first, second, rest @ ..] => {
+                    *first = left;
+                    *second = right;
+                    rest.fill(0.0);
+                }
+                [] => {}
+            }
+        }
+    }
+}
+
+impl AudioSink for SampleBuffer {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn push_samples(&mut self, left: &[f32], right: &[f32]) {
+        self.samples.extend(left.iter().copied().zip(right.iter().copied()));
+        let excess = self.samples.len().saturating_sub(self.target_samples * 2);
+        self.samples.drain(..excess);
+    }
+
+    fn latency_millis(&self) -> f64 {
+        self.samples.len() as f64 * 1000.0 / self.sample_rate as f64
+    }
+}
+
+impl AudioSink for SharedSampleBuffer {
+    fn sample_rate(&self) -> u32 {
+        self.lock().map(|buffer| buffer.sample_rate).unwrap_or(0)
+    }
+
+    fn push_samples(&mut self, left: &[f32], right: &[f32]) {
+        if let Ok(mut buffer) = self.lock() {
+            buffer.push_samples(left, right);
+        }
+    }
+
+    fn latency_millis(&self) -> f64 {
+        self.lock().map(|buffer| buffer.latency_millis()).unwrap_or(0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_wait_for_half_the_target_latency_before_playing() {
+        let mut buffer = SampleBuffer::new(1000, 10);
+        let mut output = [1.0; 4];
+
+        buffer.push_samples(&[0.5; 4], &[0.25; 4]);
+        buffer.fill(&mut output, 2);
+        assert_eq!(output, [0.0; 4]);
+
+        buffer.push_samples(&[0.5], &[0.25]);
+        buffer.fill(&mut output, 2);
+        assert_eq!(output, [0.5, 0.25, 0.5, 0.25]);
+        assert_eq!(buffer.queued_samples(), 3);
+    }
+
+    #[test]
+    fn should_fade_out_and_count_underruns() {
+        let mut buffer = SampleBuffer::new(1000, 2);
+        buffer.push_samples(&[1.0], &[1.0]);
+
+        let mut output = [0.0; 6];
+        buffer.fill(&mut output, 2);
+        assert_eq!(&output[..2], &[1.0, 1.0]);
+        assert!(output[2] < 1.0 && output[2] > 0.9);
+        assert!(output[4] < output[2]);
+        assert_eq!(buffer.underruns, 1);
+    }
+
+    #[test]
+    fn should_drop_oldest_samples_past_twice_the_target_latency() {
+        let mut buffer = SampleBuffer::new(1000, 10);
+        let samples: Vec<f32> = (0..30).map(|index| index as f32).collect();
+        buffer.push_samples(&samples, &samples);
+
+        assert_eq!(buffer.queued_samples(), 20);
+        assert_eq!(buffer.latency_millis(), 20.0);
+
+        let mut output = [0.0; 1];
+        buffer.fill(&mut output, 1);
+        assert_eq!(output, [10.0]);
+    }
+}
+
+#[cfg(feature = "cpal")]
+pub mod cpal_sink;
@@ -0,0 +1,100 @@
+use std::io::{self, Error, ErrorKind};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, OutputCallbackInfo, SampleFormat, SizedSample, Stream, StreamConfig};
+
+use crate::audio_sink::{AudioSink, SampleBuffer, SharedSampleBuffer};
+
+/*
+    Plays audio on the host's default output device through cpal. The device runs
+    at its own preferred rate, which frontends should pass on to the emulator so no
+    resampling is needed. The stream stops when the sink is dropped.
+*/
+pub struct CpalSink {
+    buffer: SharedSampleBuffer,
+    sample_rate: u32,
+    stream_errors: Arc<AtomicU64>,
+    _stream: Stream
+}
+
+fn as_io_error(error: cpal::Error) -> Error {
+    Error::other(format!("Audio output failed: {}", error))
+}
+
+fn build_stream<T>(device: &cpal::Device, config: StreamConfig, buffer: SharedSampleBuffer, stream_errors: Arc<AtomicU64>) -> io::Result<Stream>
+where
+    T: SizedSample + FromSample<f32>
+{
+    let channels = config.channels as usize;
+    let mut mixed = Vec::new();
+
+    device.build_output_stream(
+        config,
+        move |output: &mut [T], _: &OutputCallbackInfo| {
+            // Only grows on the first callbacks, so the audio thread doesn't keep allocating.
+            mixed.resize(output.len(), 0.0);
+            match buffer.lock() {
+                Ok(mut buffer) => buffer.fill(&mut mixed, channels),
+                Err(_) => mixed.fill(0.0)
+            }
+            for (sample, value) in output.iter_mut().zip(&mixed) {
+                *sample = T::from_sample(*value);
+            }
+        },
+        move |_| {
+            stream_errors.fetch_add(1, Ordering::Relaxed);
+        },
+        None
+    ).map_err(as_io_error)
+}
+
+pub fn open_default_output(target_latency_millis: u32) -> io::Result<CpalSink> {
+    let device = cpal::default_host()
+        .default_output_device()
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, "No audio output device is available."))?;
+    let supported_config = device.default_output_config().map_err(as_io_error)?;
+    let sample_format = supported_config.sample_format();
+    let config = supported_config.config();
+    let sample_rate = config.sample_rate;
+    let buffer = SampleBuffer::shared(sample_rate, target_latency_millis);
+    let stream_errors = Arc::new(AtomicU64::new(0));
+
+    let stream = match sample_format {
+        SampleFormat::F32 => build_stream::<f32>(&device, config, buffer.clone(), stream_errors.clone()),
+        SampleFormat::I16 => build_stream::<i16>(&device, config, buffer.clone(), stream_errors.clone()),
+        SampleFormat::U16 => build_stream::<u16>(&device, config, buffer.clone(), stream_errors.clone()),
+        SampleFormat::I32 => build_stream::<i32>(&device, config, buffer.clone(), stream_errors.clone()),
+        other => Err(Error::new(ErrorKind::Unsupported, format!("Unsupported audio sample format: {}", other)))
+    }?;
+    stream.play().map_err(as_io_error)?;
+
+    Ok(CpalSink { buffer, sample_rate, stream_errors, _stream: stream })
+}
+
+impl CpalSink {
+    // Times the device ran out of samples, e.g. because the emulator fell behind.
+    pub fn underruns(&self) -> u64 {
+        self.buffer.lock().map(|buffer| buffer.underruns).unwrap_or(0)
+    }
+
+    // Errors the device reported while playing, e.g. because it was unplugged.
+    pub fn stream_errors(&self) -> u64 {
+        self.stream_errors.load(Ordering::Relaxed)
+    }
+}
+
+impl AudioSink for CpalSink {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn push_samples(&mut self, left: &[f32], right: &[f32]) {
+        self.buffer.push_samples(left, right);
+    }
+
+    fn latency_millis(&self) -> f64 {
+        self.buffer.latency_millis()
+    }
+}
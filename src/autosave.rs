@@ -0,0 +1,192 @@
+use std::io;
+
+use crate::emulator::Emulator;
+use crate::save_slots::{SaveSlotManager, SaveStorage};
+
+pub const DEFAULT_AUTOSAVE_INTERVAL_MILLIS: f64 = 5000.0;
+
+/*
+    Battery RAM is only written out when it has changed since the last flush, either
+    once the interval has passed or as soon as the game disables cartridge RAM. Games
+    usually disable RAM right after writing a save, which makes it the best moment to
+    flush, so a crash or power loss straight after saving doesn't lose progress.
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AutosavePolicy {
+    // Flushing on a timer is disabled when this is None.
+    pub interval_millis: Option<f64>,
+    pub flush_on_ram_disable: bool
+}
+
+impl Default for AutosavePolicy {
+    fn default() -> Self {
+        AutosavePolicy {
+            interval_millis: Some(DEFAULT_AUTOSAVE_INTERVAL_MILLIS),
+            flush_on_ram_disable: true
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct AutosaveState {
+    pub policy: AutosavePolicy,
+    dirty: bool,
+    ram_disabled: bool,
+    last_flush_millis: Option<f64>
+}
+
+pub fn initialize_autosave() -> AutosaveState {
+    AutosaveState::default()
+}
+
+pub fn set_autosave_policy(emulator: &mut Emulator, policy: AutosavePolicy) {
+    emulator.autosave.policy = policy;
+}
+
+#[cfg(any(test, feature = "internals"))]
+pub fn is_dirty(emulator: &Emulator) -> bool {
+    emulator.autosave.dirty
+}
+
+fn has_battery(emulator: &Emulator) -> bool {
+    emulator.memory.cartridge_mapper.get_cartridge().header.has_battery
+}
+
+pub fn record_ram_write(emulator: &mut Emulator) {
+    if has_battery(emulator) {
+        emulator.autosave.dirty = true;
+    }
+}
+
+// Most mappers disable RAM by writing anything other than $A to $0000-$1FFF.
+pub fn record_rom_write(emulator: &mut Emulator, address: u16, value: u8) {
+    if address < 0x2000 && value & 0xF != 0xA && emulator.autosave.dirty {
+        emulator.autosave.ram_disabled = true;
+    }
+}
+
+pub fn flush_due(emulator: &Emulator, now_millis: f64) -> bool {
+    let autosave = &emulator.autosave;
+    if !autosave.dirty {
+        return false;
+    }
+
+    let disabled = autosave.policy.flush_on_ram_disable && autosave.ram_disabled;
+    let interval_elapsed = autosave.policy.interval_millis.is_some_and(|interval| {
+        autosave.last_flush_millis.is_none_or(|last_flush| now_millis - last_flush >= interval)
+    });
+    disabled || interval_elapsed
+}
+
+// Writes battery RAM to storage, whether or not it's due (e.g. when the frontend is closing).
+pub fn flush<S: SaveStorage>(emulator: &mut Emulator, manager: &mut SaveSlotManager<S>, now_millis: f64) -> io::Result<()> {
+    manager.save_battery(emulator)?;
+
+    let autosave = &mut emulator.autosave;
+    autosave.dirty = false;
+    autosave.ram_disabled = false;
+    autosave.last_flush_millis = Some(now_millis);
+    Ok(())
+}
+
+// Called by frontends regularly (e.g. once a frame). Returns whether battery RAM was flushed.
+pub fn autosave<S: SaveStorage>(emulator: &mut Emulator, manager: &mut SaveSlotManager<S>, now_millis: f64) -> io::Result<bool> {
+    if emulator.autosave.last_flush_millis.is_none() && !emulator.autosave.dirty {
+        // Start the interval from the first call rather than flushing straight after the first write.
+        emulator.autosave.last_flush_millis = Some(now_millis);
+    }
+
+    if !flush_due(emulator, now_millis) {
+        return Ok(false);
+    }
+
+    flush(emulator, manager, now_millis)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::initialize_screenless_emulator;
+    use crate::mmu;
+    use crate::mmu::constants::*;
+    use crate::mmu::effects::empty_cartridge_effects;
+    use crate::mmu::test_utils::*;
+    use crate::save_slots::SaveSlotManager;
+    use super::*;
+
+    #[derive(Default)]
+    struct CountingStorage {
+        writes: usize
+    }
+
+    impl SaveStorage for CountingStorage {
+        fn list(&self, _: &str) -> io::Result<Vec<String>> {
+            Ok(Vec::new())
+        }
+
+        fn read(&self, _: &str) -> io::Result<Option<Vec<u8>>> {
+            Ok(None)
+        }
+
+        fn write(&mut self, _: &str, _: &[u8]) -> io::Result<()> {
+            self.writes += 1;
+            Ok(())
+        }
+
+        fn delete(&mut self, _: &str) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn setup(cartridge_type: u8) -> (Emulator, SaveSlotManager<CountingStorage>) {
+        let mut emulator = initialize_screenless_emulator();
+        let rom = build_rom(cartridge_type, ROM_SIZE_64KB, RAM_SIZE_8KB);
+        let header = mmu::load_rom_buffer(&mut emulator.memory, rom, empty_cartridge_effects()).unwrap();
+        emulator.memory.in_bios = false;
+        (emulator, SaveSlotManager::new(CountingStorage::default(), &header))
+    }
+
+    #[test]
+    fn should_flush_when_game_disables_ram_after_writing() {
+        let (mut emulator, mut manager) = setup(CART_TYPE_MBC1_WITH_RAM_PLUS_BATTERY);
+        set_autosave_policy(&mut emulator, AutosavePolicy { interval_millis: None, flush_on_ram_disable: true });
+
+        mmu::write_byte(&mut emulator, 0x0000, 0x0A);
+        mmu::write_byte(&mut emulator, 0xA000, 0x42);
+        assert!(!autosave(&mut emulator, &mut manager, 0.0).unwrap());
+
+        mmu::write_byte(&mut emulator, 0x0000, 0x00);
+        assert!(autosave(&mut emulator, &mut manager, 0.0).unwrap());
+        assert!(!autosave(&mut emulator, &mut manager, 0.0).unwrap());
+        assert_eq!(manager.storage.writes, 1);
+    }
+
+    #[test]
+    fn should_flush_dirty_ram_once_interval_elapses() {
+        let (mut emulator, mut manager) = setup(CART_TYPE_MBC1_WITH_RAM_PLUS_BATTERY);
+        set_autosave_policy(&mut emulator, AutosavePolicy { interval_millis: Some(1000.0), flush_on_ram_disable: false });
+
+        assert!(!autosave(&mut emulator, &mut manager, 0.0).unwrap());
+        mmu::write_byte(&mut emulator, 0x0000, 0x0A);
+        mmu::write_byte(&mut emulator, 0xA000, 0x42);
+        assert!(!autosave(&mut emulator, &mut manager, 500.0).unwrap());
+        assert!(autosave(&mut emulator, &mut manager, 1000.0).unwrap());
+
+        // Nothing has changed since, so there's nothing to flush.
+        assert!(!autosave(&mut emulator, &mut manager, 5000.0).unwrap());
+        assert_eq!(manager.storage.writes, 1);
+    }
+
+    #[test]
+    fn should_ignore_cartridges_without_battery() {
+        let (mut emulator, mut manager) = setup(CART_TYPE_MBC1_WITH_RAM);
+
+        mmu::write_byte(&mut emulator, 0x0000, 0x0A);
+        mmu::write_byte(&mut emulator, 0xA000, 0x42);
+        mmu::write_byte(&mut emulator, 0x0000, 0x00);
+
+        assert!(!is_dirty(&emulator));
+        assert!(!autosave(&mut emulator, &mut manager, 60000.0).unwrap());
+        assert_eq!(manager.storage.writes, 0);
+    }
+}
@@ -0,0 +1,197 @@
+use crate::emulator::{as_mode, Emulator, HardwareModel, Mode};
+use crate::apu::period;
+use crate::mmu;
+use crate::rom_database;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PostBootRegisters {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub divider: u8
+}
+
+const HEADER_CHECKSUM_ADDRESS: u16 = 0x14D;
+const CGB_FLAG_ADDRESS: u16 = 0x143;
+
+const FLAG_ZERO: u8 = 0x80;
+const FLAG_HALF_CARRY: u8 = 0x20;
+const FLAG_CARRY: u8 = 0x10;
+
+/*
+    Register values left behind by each boot ROM, from the Pan Docs power up sequence.
+    Games read A (and B on AGB, C on SGB) to detect the hardware they run on. The
+    DMG and MGB boot ROMs leave the half-carry and carry flags set unless the header
+    checksum is zero. DIV isn't documented for SGB and CGB models since it depends on
+    how long the boot ROM ran, so it starts from zero there.
+*/
+pub fn post_boot_registers(model: HardwareModel, header_checksum: u8) -> PostBootRegisters {
+    let checksum_flags = if header_checksum == 0 { 0 } else { FLAG_HALF_CARRY | FLAG_CARRY };
+
+    match model {
+        HardwareModel::DMG0 => PostBootRegisters { a: 0x01, f: 0x00, b: 0xFF, c: 0x13, d: 0x00, e: 0xC1, h: 0x84, l: 0x03, divider: 0x18 },
+        HardwareModel::DMG => PostBootRegisters { a: 0x01, f: FLAG_ZERO | checksum_flags, b: 0x00, c: 0x13, d: 0x00, e: 0xD8, h: 0x01, l: 0x4D, divider: 0xAB },
+        HardwareModel::MGB => PostBootRegisters { a: 0xFF, f: FLAG_ZERO | checksum_flags, b: 0x00, c: 0x13, d: 0x00, e: 0xD8, h: 0x01, l: 0x4D, divider: 0xAB },
+        HardwareModel::SGB => PostBootRegisters { a: 0x01, f: 0x00, b: 0x00, c: 0x14, d: 0x00, e: 0x00, h: 0xC0, l: 0x60, divider: 0x00 },
+        HardwareModel::SGB2 => PostBootRegisters { a: 0xFF, f: 0x00, b: 0x00, c: 0x14, d: 0x00, e: 0x00, h: 0xC0, l: 0x60, divider: 0x00 },
+        HardwareModel::CGB => PostBootRegisters { a: 0x11, f: FLAG_ZERO, b: 0x00, c: 0x00, d: 0xFF, e: 0x56, h: 0x00, l: 0x0D, divider: 0x00 },
+        HardwareModel::AGB => PostBootRegisters { a: 0x11, f: 0x00, b: 0x01, c: 0x00, d: 0xFF, e: 0x56, h: 0x00, l: 0x0D, divider: 0x00 }
+    }
+}
+
+fn apply_cpu_registers(emulator: &mut Emulator, registers: &PostBootRegisters) {
+    let cpu_registers = &mut emulator.cpu.registers;
+    cpu_registers.a = registers.a;
+    cpu_registers.f = registers.f;
+    cpu_registers.b = registers.b;
+    cpu_registers.c = registers.c;
+    cpu_registers.d = registers.d;
+    cpu_registers.e = registers.e;
+    cpu_registers.h = registers.h;
+    cpu_registers.l = registers.l;
+    cpu_registers.stack_pointer = 0xFFFE;
+    cpu_registers.program_counter = 0x0100;
+}
+
+fn apply_io_registers(emulator: &mut Emulator, model: HardwareModel, divider: u8) {
+    let io_registers: [(u16, u8); 21] = [
+        (0xFF26, 0x80),
+        (0xFF10, 0x80),
+        (0xFF11, 0xBF),
+        (0xFF12, 0xF3),
+        (0xFF13, 0xFF),
+        (0xFF16, 0x3F),
+        (0xFF17, 0x00),
+        (0xFF1A, 0x7F),
+        (0xFF1B, 0xFF),
+        (0xFF1C, 0x9F),
+        (0xFF20, 0xFF),
+        (0xFF21, 0x00),
+        (0xFF22, 0x00),
+        (0xFF24, 0x77),
+        (0xFF25, 0xF3),
+        (0xFF05, 0x00),
+        (0xFF06, 0x00),
+        (0xFF07, 0xF8),
+        (0xFF47, 0xFC),
+        (0xFF40, 0x91),
+        (0xFFFF, 0x00)
+    ];
+
+    for (address, value) in io_registers {
+        mmu::write_byte(emulator, address, value);
+    }
+
+    // The boot chime leaves channel 1 enabled with its envelope decayed to silence.
+    // The SGB boot ROM doesn't play it, as the chime comes from the SNES instead.
+    let played_boot_chime = !matches!(model, HardwareModel::SGB | HardwareModel::SGB2);
+    emulator.apu.channel1.enabled = played_boot_chime;
+    emulator.apu.channel1.envelope.current_volume = 0;
+    // The chime was triggered with NR13 at 0xFF, and the APU counts the period down from there.
+    period::trigger(&mut emulator.apu.channel1.period);
+
+    emulator.interrupts.flags = 0x01;
+    emulator.timers.divider = divider;
+}
+
+/*
+    Puts the emulator in the state the boot ROM would have left it in, so games
+    can start immediately at 0x100. Must be called after the ROM is loaded, as a
+    few values depend on the cartridge header.
+*/
+pub fn apply_post_boot_state(emulator: &mut Emulator) {
+    let model = emulator.model;
+    let header_checksum = emulator.memory.cartridge_mapper.read_rom(HEADER_CHECKSUM_ADDRESS);
+    let registers = post_boot_registers(model, header_checksum);
+
+    emulator.memory.in_bios = false;
+    apply_cpu_registers(emulator, &registers);
+    apply_io_registers(emulator, model, registers.divider);
+
+    if as_mode(model) == Mode::CGB {
+        let cgb_flag = emulator.memory.cartridge_mapper.read_rom(CGB_FLAG_ADDRESS);
+        // Cartridges without CGB support run in DMG compatibility mode.
+        mmu::write_byte(emulator, 0xFF4C, if cgb_flag & 0x80 != 0 { cgb_flag } else { 0x04 });
+        rom_database::apply_compatibility_palette(emulator);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::initialize_screenless_emulator;
+    use crate::mmu::constants::*;
+    use crate::mmu::effects::empty_cartridge_effects;
+    use crate::mmu::test_utils::*;
+    use super::*;
+
+    fn setup_emulator(model: HardwareModel, header_checksum: u8, cgb_flag: u8) -> Emulator {
+        let mut emulator = initialize_screenless_emulator();
+        emulator.mode = as_mode(model);
+        emulator.model = model;
+        let mut rom = build_rom(CART_TYPE_MBC1, ROM_SIZE_64KB, RAM_SIZE_0KB);
+        rom[HEADER_CHECKSUM_ADDRESS as usize] = header_checksum;
+        rom[CGB_FLAG_ADDRESS as usize] = cgb_flag;
+        mmu::load_rom_buffer(&mut emulator.memory, rom, empty_cartridge_effects()).unwrap();
+        apply_post_boot_state(&mut emulator);
+        emulator
+    }
+
+    #[test]
+    fn should_start_execution_at_cartridge_entry_point() {
+        let emulator = setup_emulator(HardwareModel::DMG, 0x33, 0x00);
+        assert!(!emulator.memory.in_bios);
+        assert_eq!(emulator.cpu.registers.program_counter, 0x0100);
+        assert_eq!(emulator.cpu.registers.stack_pointer, 0xFFFE);
+    }
+
+    #[test]
+    fn should_set_flags_from_header_checksum_on_dmg() {
+        let emulator = setup_emulator(HardwareModel::DMG, 0x33, 0x00);
+        assert_eq!(emulator.cpu.registers.a, 0x01);
+        assert_eq!(emulator.cpu.registers.f, 0xB0);
+
+        let emulator = setup_emulator(HardwareModel::DMG, 0x00, 0x00);
+        assert_eq!(emulator.cpu.registers.f, 0x80);
+    }
+
+    #[test]
+    fn should_identify_hardware_through_initial_a_and_b_registers() {
+        assert_eq!(setup_emulator(HardwareModel::MGB, 0x33, 0x00).cpu.registers.a, 0xFF);
+        assert_eq!(setup_emulator(HardwareModel::CGB, 0x33, 0x80).cpu.registers.a, 0x11);
+        assert_eq!(setup_emulator(HardwareModel::CGB, 0x33, 0x80).cpu.registers.b, 0x00);
+        assert_eq!(setup_emulator(HardwareModel::AGB, 0x33, 0x80).cpu.registers.b, 0x01);
+        assert_eq!(setup_emulator(HardwareModel::SGB, 0x33, 0x00).cpu.registers.c, 0x14);
+        assert_eq!(setup_emulator(HardwareModel::SGB2, 0x33, 0x00).cpu.registers.a, 0xFF);
+    }
+
+    #[test]
+    fn should_not_leave_channel_1_enabled_on_sgb() {
+        let mut emulator = setup_emulator(HardwareModel::SGB, 0x33, 0x00);
+        assert_eq!(mmu::read_byte(&mut emulator, 0xFF26), 0xF0);
+    }
+
+    #[test]
+    fn should_initialize_io_registers() {
+        let mut emulator = setup_emulator(HardwareModel::DMG, 0x33, 0x00);
+        assert_eq!(mmu::read_byte(&mut emulator, 0xFF40), 0x91);
+        assert_eq!(mmu::read_byte(&mut emulator, 0xFF47), 0xFC);
+        assert_eq!(mmu::read_byte(&mut emulator, 0xFF04), 0xAB);
+        assert_eq!(mmu::read_byte(&mut emulator, 0xFF24), 0x77);
+        assert_eq!(mmu::read_byte(&mut emulator, 0xFF25), 0xF3);
+        assert_eq!(mmu::read_byte(&mut emulator, 0xFF26), 0xF1);
+    }
+
+    #[test]
+    fn should_enter_compatibility_mode_for_dmg_cartridges_on_cgb() {
+        let mut emulator = setup_emulator(HardwareModel::CGB, 0x33, 0x00);
+        assert_eq!(mmu::read_byte(&mut emulator, 0xFF4C), 0x04);
+
+        let mut emulator = setup_emulator(HardwareModel::CGB, 0x33, 0x80);
+        assert_eq!(mmu::read_byte(&mut emulator, 0xFF4C), 0x80);
+    }
+}
@@ -0,0 +1,191 @@
+use std::io;
+
+use crate::breakpoints::condition::{condition_holds, parse_condition, Condition};
+use crate::cpu;
+use crate::emulator::Emulator;
+use crate::symbols;
+
+/*
+    Address breakpoints for the debugger. A breakpoint pauses the emulator before the
+    instruction at its address executes, and can be limited to a ROM or RAM bank and
+    to a condition over registers and memory, so hot routines only stop in the case
+    being debugged. Resuming from a breakpoint runs the instruction it stopped on
+    rather than stopping on it again.
+*/
+#[derive(Debug)]
+pub struct Breakpoint {
+    pub id: u32,
+    pub address: u16,
+    pub bank: Option<u16>,
+    pub condition: Option<Condition>,
+    pub hit_count: u32
+}
+
+#[derive(Debug, Default)]
+pub struct BreakpointState {
+    pub breakpoints: Vec<Breakpoint>,
+    pub hit: Option<u32>,
+    next_id: u32,
+    resuming_from: Option<u16>
+}
+
+pub fn initialize_breakpoints() -> BreakpointState {
+    BreakpointState::default()
+}
+
+pub fn add_breakpoint(emulator: &mut Emulator, address: u16, bank: Option<u16>, condition: Option<&str>) -> io::Result<u32> {
+    let condition = condition.map(parse_condition).transpose()?;
+    let state = &mut emulator.breakpoints;
+    state.next_id += 1;
+    state.breakpoints.push(Breakpoint { id: state.next_id, address, bank, condition, hit_count: 0 });
+    Ok(state.next_id)
+}
+
+// Breaks at a label from the loaded symbol file, only in the bank the label was assembled to.
+pub fn add_symbol_breakpoint(emulator: &mut Emulator, name: &str, condition: Option<&str>) -> io::Result<u32> {
+    let (bank, address) = symbols::address_of(&emulator.symbols, name)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("Unknown symbol: {}", name)))?;
+    add_breakpoint(emulator, address, Some(bank), condition)
+}
+
+pub fn remove_breakpoint(emulator: &mut Emulator, id: u32) -> bool {
+    let breakpoints = &mut emulator.breakpoints.breakpoints;
+    let length = breakpoints.len();
+    breakpoints.retain(|breakpoint| breakpoint.id != id);
+    breakpoints.len() != length
+}
+
+#[cfg(feature = "internals")]
+pub fn clear_breakpoints(emulator: &mut Emulator) {
+    emulator.breakpoints.breakpoints.clear();
+}
+
+pub fn take_breakpoint_hit(emulator: &mut Emulator) -> Option<u32> {
+    emulator.breakpoints.hit.take()
+}
+
+// Lets the instruction the emulator is stopped on run without stopping on it again, e.g. after moving execution there.
+pub fn skip_current_breakpoint(emulator: &mut Emulator) {
+    emulator.breakpoints.resuming_from = Some(emulator.cpu.registers.program_counter.wrapping_sub(1));
+}
+
+fn find_hit(emulator: &mut Emulator, address: u16) -> Option<usize> {
+    for index in 0..emulator.breakpoints.breakpoints.len() {
+        let breakpoint = &emulator.breakpoints.breakpoints[index];
+        if breakpoint.address != address {
+            continue;
+        }
+
+        if let Some(bank) = breakpoint.bank {
+            if symbols::current_bank(emulator, address) != bank {
+                continue;
+            }
+        }
+
+        // The condition is taken out while it's evaluated, as evaluating it reads memory through the emulator.
+        let condition = emulator.breakpoints.breakpoints[index].condition.take();
+        let holds = condition.as_ref().is_none_or(|condition| condition_holds(emulator, condition));
+        emulator.breakpoints.breakpoints[index].condition = condition;
+
+        if holds {
+            return Some(index);
+        }
+    }
+    None
+}
+
+// Called before each instruction, returning true if the emulator stopped on a breakpoint instead of running it.
+pub fn step(emulator: &mut Emulator) -> bool {
+    if emulator.breakpoints.breakpoints.is_empty() || emulator.cpu.halted {
+        return false;
+    }
+
+    let address = cpu::current_instruction_address(emulator);
+    if emulator.breakpoints.resuming_from.take() == Some(address) {
+        return false;
+    }
+
+    match find_hit(emulator, address) {
+        Some(index) => {
+            let breakpoint = &mut emulator.breakpoints.breakpoints[index];
+            breakpoint.hit_count += 1;
+            emulator.breakpoints.hit = Some(breakpoint.id);
+            emulator.breakpoints.resuming_from = Some(address);
+            emulator.pause.paused = true;
+            true
+        },
+        None => false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::{self, enable_processor_test_mode, initialize_screenless_emulator};
+    use crate::pause;
+    use super::*;
+
+    fn setup_emulator(program: &[u8]) -> Emulator {
+        let mut emulator = initialize_screenless_emulator();
+        enable_processor_test_mode(&mut emulator);
+        emulator.memory.processor_test_ram[0x100..0x100 + program.len()].copy_from_slice(program);
+        emulator.cpu.registers.program_counter = 0x101;
+        emulator.cpu.registers.opcode = program[0];
+        emulator
+    }
+
+    fn run_until_paused(emulator: &mut Emulator, max_steps: usize) {
+        for _ in 0..max_steps {
+            if pause::is_paused(emulator) {
+                return;
+            }
+            emulator::step(emulator);
+        }
+    }
+
+    #[test]
+    fn should_stop_before_instruction_at_breakpoint() {
+        // ld a,$05; inc a; jr -3
+        let mut emulator = setup_emulator(&[0x3E, 0x05, 0x3C, 0x18, 0xFD]);
+        let id = add_breakpoint(&mut emulator, 0x102, None, None).unwrap();
+
+        run_until_paused(&mut emulator, 10);
+        assert_eq!(take_breakpoint_hit(&mut emulator), Some(id));
+        assert_eq!(emulator.cpu.registers.a, 0x05);
+
+        pause::resume(&mut emulator);
+        run_until_paused(&mut emulator, 10);
+        assert_eq!(emulator.cpu.registers.a, 0x06);
+        assert_eq!(emulator.breakpoints.breakpoints[0].hit_count, 2);
+    }
+
+    #[test]
+    fn should_only_stop_when_condition_holds() {
+        let mut emulator = setup_emulator(&[0x3E, 0x05, 0x3C, 0x18, 0xFD]);
+        add_breakpoint(&mut emulator, 0x102, None, Some("A == 0x10")).unwrap();
+
+        run_until_paused(&mut emulator, 100);
+        assert!(pause::is_paused(&emulator));
+        assert_eq!(emulator.cpu.registers.a, 0x10);
+        assert_eq!(emulator.breakpoints.breakpoints[0].hit_count, 1);
+    }
+
+    #[test]
+    fn should_reject_invalid_condition() {
+        let mut emulator = initialize_screenless_emulator();
+        assert!(add_breakpoint(&mut emulator, 0x150, None, Some("A ==")).is_err());
+        assert!(emulator.breakpoints.breakpoints.is_empty());
+    }
+
+    #[test]
+    fn should_not_stop_after_breakpoint_removed() {
+        let mut emulator = setup_emulator(&[0x3E, 0x05, 0x3C, 0x18, 0xFD]);
+        let id = add_breakpoint(&mut emulator, 0x102, None, None).unwrap();
+        assert!(remove_breakpoint(&mut emulator, id));
+        assert!(!remove_breakpoint(&mut emulator, id));
+
+        run_until_paused(&mut emulator, 10);
+        assert!(!pause::is_paused(&emulator));
+    }
+}
+
+pub mod condition;
@@ -0,0 +1,348 @@
+use std::io;
+
+use crate::cpu;
+use crate::emulator::Emulator;
+use crate::mmu;
+
+/*
+    A tiny expression language for breakpoint conditions, e.g. "A == 0x3E && [0xC0A0] > 5".
+    Expressions can use the CPU registers (A, B, C, D, E, H, L, F, AF, BC, DE, HL, SP, PC),
+    bytes in memory ([address]), numbers in decimal or hex (0x3E or $3E), arithmetic,
+    bitwise and comparison operators, and parentheses. Comparisons and logical operators
+    evaluate to 1 or 0, and a condition holds when it evaluates to anything but 0.
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegisterOperand {
+    A, B, C, D, E, H, L, F, AF, BC, DE, HL, SP, PC
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOperator {
+    Not,
+    Negate
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOperator {
+    Or,
+    And,
+    BitwiseOr,
+    BitwiseXor,
+    BitwiseAnd,
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Add,
+    Subtract
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Condition {
+    Value(i64),
+    Register(RegisterOperand),
+    Memory(Box<Condition>),
+    Unary(UnaryOperator, Box<Condition>),
+    Binary(BinaryOperator, Box<Condition>, Box<Condition>)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Number(i64),
+    Identifier(String),
+    Operator(&'static str),
+    OpenParenthesis,
+    CloseParenthesis,
+    OpenBracket,
+    CloseBracket
+}
+
+// Longer operators come first so "<=" isn't read as "<" followed by "=".
+const OPERATORS: [&str; 15] = ["==", "!=", "<=", ">=", "&&", "||", "<", ">", "&", "|", "^", "+", "-", "!", "~"];
+
+fn invalid_condition_error(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid breakpoint condition: {}", message))
+}
+
+fn parse_number(text: &str) -> io::Result<i64> {
+    let result = if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")).or_else(|| text.strip_prefix('$')) {
+        i64::from_str_radix(hex, 16)
+    }
+    else {
+        text.parse::<i64>()
+    };
+    result.map_err(|_| invalid_condition_error(format!("invalid number \"{}\"", text)))
+}
+
+fn tokenize(text: &str) -> io::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut remaining = text.trim_start();
+
+    while let Some(character) = remaining.chars().next() {
+        let length = if character.is_ascii_alphanumeric() || character == '$' || character == '_' {
+            let length = remaining.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '$')).unwrap_or(remaining.len());
+            let word = &remaining[..length];
+            if character.is_ascii_digit() || character == '$' {
+                tokens.push(Token::Number(parse_number(word)?));
+            }
+            else {
+                tokens.push(Token::Identifier(word.to_ascii_uppercase()));
+            }
+            length
+        }
+        else {
+            let token = match character {
+                '(' => Some(Token::OpenParenthesis),
+                ')' => Some(Token::CloseParenthesis),
+                '[' => Some(Token::OpenBracket),
+                ']' => Some(Token::CloseBracket),
+                _ => OPERATORS.iter().find(|operator| remaining.starts_with(**operator)).map(|operator| Token::Operator(operator))
+            };
+            let token = token.ok_or_else(|| invalid_condition_error(format!("unexpected character '{}'", character)))?;
+            let length = match token {
+                Token::Operator(operator) => operator.len(),
+                _ => 1
+            };
+            tokens.push(token);
+            length
+        };
+        remaining = remaining[length..].trim_start();
+    }
+
+    Ok(tokens)
+}
+
+fn as_register(name: &str) -> Option<RegisterOperand> {
+    match name {
+        "A" => Some(RegisterOperand::A),
+        "B" => Some(RegisterOperand::B),
+        "C" => Some(RegisterOperand::C),
+        "D" => Some(RegisterOperand::D),
+        "E" => Some(RegisterOperand::E),
+        "H" => Some(RegisterOperand::H),
+        "L" => Some(RegisterOperand::L),
+        "F" => Some(RegisterOperand::F),
+        "AF" => Some(RegisterOperand::AF),
+        "BC" => Some(RegisterOperand::BC),
+        "DE" => Some(RegisterOperand::DE),
+        "HL" => Some(RegisterOperand::HL),
+        "SP" => Some(RegisterOperand::SP),
+        "PC" => Some(RegisterOperand::PC),
+        _ => None
+    }
+}
+
+// Binary operators by precedence, from loosest to tightest binding.
+const PRECEDENCE_LEVELS: [&[(&str, BinaryOperator)]; 8] = [
+    &[("||", BinaryOperator::Or)],
+    &[("&&", BinaryOperator::And)],
+    &[("|", BinaryOperator::BitwiseOr)],
+    &[("^", BinaryOperator::BitwiseXor)],
+    &[("&", BinaryOperator::BitwiseAnd)],
+    &[("==", BinaryOperator::Equal), ("!=", BinaryOperator::NotEqual)],
+    &[("<", BinaryOperator::Less), ("<=", BinaryOperator::LessOrEqual), (">", BinaryOperator::Greater), (">=", BinaryOperator::GreaterOrEqual)],
+    &[("+", BinaryOperator::Add), ("-", BinaryOperator::Subtract)]
+];
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> io::Result<()> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => Err(invalid_condition_error(format!("expected {:?} but found {:?}", expected, token))),
+            None => Err(invalid_condition_error(format!("expected {:?} but reached the end", expected)))
+        }
+    }
+
+    fn parse_binary(&mut self, level: usize) -> io::Result<Condition> {
+        if level == PRECEDENCE_LEVELS.len() {
+            return self.parse_unary();
+        }
+
+        let mut left = self.parse_binary(level + 1)?;
+        while let Some(Token::Operator(symbol)) = self.peek() {
+            let operator = match PRECEDENCE_LEVELS[level].iter().find(|(candidate, _)| candidate == symbol) {
+                Some((_, operator)) => *operator,
+                None => break
+            };
+            self.position += 1;
+            let right = self.parse_binary(level + 1)?;
+            left = Condition::Binary(operator, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> io::Result<Condition> {
+        match self.peek() {
+            Some(Token::Operator("!")) => {
+                self.position += 1;
+                Ok(Condition::Unary(UnaryOperator::Not, Box::new(self.parse_unary()?)))
+            },
+            Some(Token::Operator("-")) => {
+                self.position += 1;
+                Ok(Condition::Unary(UnaryOperator::Negate, Box::new(self.parse_unary()?)))
+            },
+            _ => self.parse_primary()
+        }
+    }
+
+    fn parse_primary(&mut self) -> io::Result<Condition> {
+        match self.next() {
+            Some(Token::Number(value)) => Ok(Condition::Value(value)),
+            Some(Token::Identifier(name)) => as_register(&name)
+                .map(Condition::Register)
+                .ok_or_else(|| invalid_condition_error(format!("unknown register \"{}\"", name))),
+            Some(Token::OpenParenthesis) => {
+                let condition = self.parse_binary(0)?;
+                self.expect(Token::CloseParenthesis)?;
+                Ok(condition)
+            },
+            Some(Token::OpenBracket) => {
+                let address = self.parse_binary(0)?;
+                self.expect(Token::CloseBracket)?;
+                Ok(Condition::Memory(Box::new(address)))
+            },
+            Some(token) => Err(invalid_condition_error(format!("unexpected {:?}", token))),
+            None => Err(invalid_condition_error("unexpected end of condition".to_string()))
+        }
+    }
+}
+
+pub fn parse_condition(text: &str) -> io::Result<Condition> {
+    let mut parser = Parser { tokens: tokenize(text)?, position: 0 };
+    let condition = parser.parse_binary(0)?;
+    match parser.peek() {
+        None => Ok(condition),
+        Some(token) => Err(invalid_condition_error(format!("unexpected {:?}", token)))
+    }
+}
+
+fn read_register(emulator: &Emulator, register: RegisterOperand) -> i64 {
+    let registers = &emulator.cpu.registers;
+    let pair = |high: u8, low: u8| ((high as i64) << 8) | low as i64;
+    match register {
+        RegisterOperand::A => registers.a as i64,
+        RegisterOperand::B => registers.b as i64,
+        RegisterOperand::C => registers.c as i64,
+        RegisterOperand::D => registers.d as i64,
+        RegisterOperand::E => registers.e as i64,
+        RegisterOperand::H => registers.h as i64,
+        RegisterOperand::L => registers.l as i64,
+        RegisterOperand::F => registers.f as i64,
+        RegisterOperand::AF => pair(registers.a, registers.f),
+        RegisterOperand::BC => pair(registers.b, registers.c),
+        RegisterOperand::DE => pair(registers.d, registers.e),
+        RegisterOperand::HL => pair(registers.h, registers.l),
+        RegisterOperand::SP => registers.stack_pointer as i64,
+        RegisterOperand::PC => cpu::current_instruction_address(emulator) as i64
+    }
+}
+
+fn as_flag(value: bool) -> i64 {
+    value as i64
+}
+
+pub fn evaluate(emulator: &mut Emulator, condition: &Condition) -> i64 {
+    match condition {
+        Condition::Value(value) => *value,
+        Condition::Register(register) => read_register(emulator, *register),
+        Condition::Memory(address) => {
+            let address = evaluate(emulator, address) as u16;
+            mmu::read_byte(emulator, address) as i64
+        },
+        Condition::Unary(operator, operand) => {
+            let value = evaluate(emulator, operand);
+            match operator {
+                UnaryOperator::Not => as_flag(value == 0),
+                UnaryOperator::Negate => value.wrapping_neg()
+            }
+        },
+        Condition::Binary(BinaryOperator::And, left, right) =>
+            as_flag(evaluate(emulator, left) != 0 && evaluate(emulator, right) != 0),
+        Condition::Binary(BinaryOperator::Or, left, right) =>
+            as_flag(evaluate(emulator, left) != 0 || evaluate(emulator, right) != 0),
+        Condition::Binary(operator, left, right) => {
+            let left = evaluate(emulator, left);
+            let right = evaluate(emulator, right);
+            match operator {
+                BinaryOperator::BitwiseOr => left | right,
+                BinaryOperator::BitwiseXor => left ^ right,
+                BinaryOperator::BitwiseAnd => left & right,
+                BinaryOperator::Equal => as_flag(left == right),
+                BinaryOperator::NotEqual => as_flag(left != right),
+                BinaryOperator::Less => as_flag(left < right),
+                BinaryOperator::LessOrEqual => as_flag(left <= right),
+                BinaryOperator::Greater => as_flag(left > right),
+                BinaryOperator::GreaterOrEqual => as_flag(left >= right),
+                BinaryOperator::Add => left.wrapping_add(right),
+                BinaryOperator::Subtract => left.wrapping_sub(right),
+                BinaryOperator::And | BinaryOperator::Or => unreachable!()
+            }
+        }
+    }
+}
+
+pub fn condition_holds(emulator: &mut Emulator, condition: &Condition) -> bool {
+    evaluate(emulator, condition) != 0
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::initialize_screenless_emulator;
+    use super::*;
+
+    fn evaluate_text(emulator: &mut Emulator, text: &str) -> i64 {
+        evaluate(emulator, &parse_condition(text).unwrap())
+    }
+
+    #[test]
+    fn should_respect_operator_precedence() {
+        let mut emulator = initialize_screenless_emulator();
+        assert_eq!(evaluate_text(&mut emulator, "1 + 2 == 3 && 4 > 2"), 1);
+        assert_eq!(evaluate_text(&mut emulator, "0x10 | 0x01 & 0x03"), 0x11);
+        assert_eq!(evaluate_text(&mut emulator, "(1 || 0) + 1"), 2);
+        assert_eq!(evaluate_text(&mut emulator, "!0 + -1"), 0);
+    }
+
+    #[test]
+    fn should_evaluate_registers_and_memory() {
+        let mut emulator = initialize_screenless_emulator();
+        emulator.memory.in_bios = false;
+        emulator.cpu.registers.a = 0x3E;
+        emulator.cpu.registers.h = 0xC0;
+        emulator.cpu.registers.l = 0xA0;
+        mmu::write_byte(&mut emulator, 0xC0A0, 6);
+
+        assert_eq!(evaluate_text(&mut emulator, "A == 0x3E && [0xC0A0] > 5"), 1);
+        assert_eq!(evaluate_text(&mut emulator, "[hl] == $06"), 1);
+        assert_eq!(evaluate_text(&mut emulator, "HL - 0xC000"), 0xA0);
+        assert_eq!(evaluate_text(&mut emulator, "a != 0x3E || [HL + 1] != 0"), 0);
+    }
+
+    #[test]
+    fn should_reject_malformed_conditions() {
+        assert!(parse_condition("A ==").is_err());
+        assert!(parse_condition("[0xC000").is_err());
+        assert!(parse_condition("X == 1").is_err());
+        assert!(parse_condition("A = 1").is_err());
+        assert!(parse_condition("1 2").is_err());
+        assert!(parse_condition("0xZZ").is_err());
+    }
+}
@@ -0,0 +1,261 @@
+use std::io;
+
+use crate::boot;
+use crate::clock::SharedClock;
+use crate::hle_boot::{self, HleBootOptions};
+use crate::emulator::{self, initialize_emulator, AccuracyProfile, CartridgeEffects, CartridgeHeader, Emulator, HardwareModel};
+#[cfg(feature = "internals")]
+use crate::emulator::Mode;
+use crate::mmu::effects::empty_cartridge_effects;
+use crate::patches;
+use crate::game_config::GameConfigs;
+use crate::rom_database::{self, RomDatabase};
+
+pub struct EmulatorBuilder {
+    render: fn(&[u8]),
+    render_scanline: Option<fn(u8, &[u8])>,
+    model: HardwareModel,
+    sample_rate: Option<u32>,
+    audio_buffer_size: Option<usize>,
+    accuracy_profile: AccuracyProfile,
+    extra_cpu_cycles_per_frame: u32,
+    rom: Option<(Vec<u8>, Box<dyn CartridgeEffects>)>,
+    patches: Vec<Vec<u8>>,
+    rom_database: RomDatabase,
+    game_configs: GameConfigs,
+    clock: Option<SharedClock>,
+    skip_boot_rom: bool,
+    hle_boot: Option<HleBootOptions>
+}
+
+impl Default for EmulatorBuilder {
+    fn default() -> Self {
+        EmulatorBuilder::new()
+    }
+}
+
+impl EmulatorBuilder {
+    pub fn new() -> EmulatorBuilder {
+        EmulatorBuilder {
+            render: |_| {},
+            render_scanline: None,
+            model: HardwareModel::DMG,
+            sample_rate: None,
+            audio_buffer_size: None,
+            accuracy_profile: AccuracyProfile::Balanced,
+            extra_cpu_cycles_per_frame: 0,
+            rom: None,
+            patches: Vec::new(),
+            rom_database: RomDatabase::new(),
+            game_configs: GameConfigs::new(),
+            clock: None,
+            skip_boot_rom: false,
+            hle_boot: None
+        }
+    }
+
+    #[cfg(feature = "internals")]
+    pub fn with_render(mut self, render: fn(&[u8])) -> EmulatorBuilder {
+        self.render = render;
+        self
+    }
+
+    // Called with each line as soon as it's drawn, on top of the render callback. See gpu.rs.
+    #[cfg(feature = "internals")]
+    pub fn with_scanline_render(mut self, render_scanline: fn(u8, &[u8])) -> EmulatorBuilder {
+        self.render_scanline = Some(render_scanline);
+        self
+    }
+
+    #[cfg(feature = "internals")]
+    pub fn with_mode(mut self, mode: Mode) -> EmulatorBuilder {
+        self.model = if mode == Mode::CGB { HardwareModel::CGB } else { HardwareModel::DMG };
+        self
+    }
+
+    pub fn with_hardware_model(mut self, model: HardwareModel) -> EmulatorBuilder {
+        self.model = model;
+        self
+    }
+
+    pub fn with_sample_rate(mut self, sample_rate: u32) -> EmulatorBuilder {
+        self.sample_rate = Some(sample_rate);
+        self
+    }
+
+    // Samples per channel audio-synced frontends wait for before playing. See apu.rs.
+    pub fn with_audio_buffer_size(mut self, samples: usize) -> EmulatorBuilder {
+        self.audio_buffer_size = Some(samples);
+        self
+    }
+
+    #[cfg(any(test, feature = "internals"))]
+    pub fn with_accuracy_profile(mut self, accuracy_profile: AccuracyProfile) -> EmulatorBuilder {
+        self.accuracy_profile = accuracy_profile;
+        self
+    }
+
+    // Overclocking, see overclock.rs. A game config for the ROM can still override it.
+    pub fn with_extra_cpu_cycles_per_frame(mut self, cycles: u32) -> EmulatorBuilder {
+        self.extra_cpu_cycles_per_frame = cycles;
+        self
+    }
+
+    pub fn with_rom(mut self, rom: &[u8]) -> EmulatorBuilder {
+        self.rom = Some((rom.to_vec(), empty_cartridge_effects()));
+        self
+    }
+
+    pub fn with_rom_and_effects(mut self, rom: &[u8], cartridge_effects: Box<dyn CartridgeEffects>) -> EmulatorBuilder {
+        self.rom = Some((rom.to_vec(), cartridge_effects));
+        self
+    }
+
+    // IPS or BPS patch applied to the ROM before it's loaded. Patches stack in the order they're added.
+    #[cfg(any(test, feature = "internals"))]
+    pub fn with_patch(mut self, patch: &[u8]) -> EmulatorBuilder {
+        self.patches.push(patch.to_vec());
+        self
+    }
+
+    // Entries consulted before the bundled ones when the ROM is loaded. See rom_database.rs.
+    pub fn with_rom_database(mut self, rom_database: RomDatabase) -> EmulatorBuilder {
+        self.rom_database = rom_database;
+        self
+    }
+
+    // Per-game settings applied when the ROM is loaded, on top of the ones given here. See game_config.rs.
+    pub fn with_game_configs(mut self, game_configs: GameConfigs) -> EmulatorBuilder {
+        self.game_configs = game_configs;
+        self
+    }
+
+    // Time source for cartridge clocks, in place of the system clock. See clock.rs.
+    pub fn with_clock(mut self, clock: SharedClock) -> EmulatorBuilder {
+        self.clock = Some(clock);
+        self
+    }
+
+    // Starts at the cartridge entry point with the registers the model's boot ROM leaves behind.
+    pub fn skip_boot_rom(mut self) -> EmulatorBuilder {
+        self.skip_boot_rom = true;
+        self
+    }
+
+    // Boots without running boot ROM code, still checking the header and optionally showing the logo. See hle_boot.rs.
+    pub fn hle_boot(mut self, options: HleBootOptions) -> EmulatorBuilder {
+        self.hle_boot = Some(options);
+        self
+    }
+
+    pub fn build(self) -> io::Result<(Emulator, Option<CartridgeHeader>)> {
+        let mut emulator = initialize_emulator(self.render);
+        emulator::set_scanline_render(&mut emulator, self.render_scanline);
+        emulator::set_hardware_model(&mut emulator, self.model);
+        emulator::set_accuracy_profile(&mut emulator, self.accuracy_profile);
+        emulator::set_extra_cpu_cycles_per_frame(&mut emulator, self.extra_cpu_cycles_per_frame);
+
+        if let Some(sample_rate) = self.sample_rate {
+            emulator::set_sample_rate(&mut emulator, sample_rate);
+        }
+
+        if let Some(samples) = self.audio_buffer_size {
+            emulator::set_audio_buffer_size(&mut emulator, samples);
+        }
+
+        emulator.rom_database.user_entries = self.rom_database;
+        emulator.game_config.configs = self.game_configs;
+
+        if let Some(clock) = self.clock {
+            emulator::set_clock(&mut emulator, clock);
+        }
+
+        let header = match self.rom {
+            Some((mut rom, cartridge_effects)) => {
+                for patch in &self.patches {
+                    rom = patches::apply_patch(&rom, patch)?;
+                }
+                Some(rom_database::load_rom(&mut emulator, rom, cartridge_effects)?)
+            },
+            None => None
+        };
+
+        if self.skip_boot_rom {
+            boot::apply_post_boot_state(&mut emulator);
+        }
+        else if let (Some(options), Some(_)) = (self.hle_boot, &header) {
+            hle_boot::start(&mut emulator, options);
+        }
+
+        Ok((emulator, header))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::Mode;
+    use crate::mmu::constants::*;
+    use crate::mmu::test_utils::*;
+    use super::*;
+
+    #[test]
+    fn should_build_emulator_running_boot_rom_by_default() {
+        let rom = build_rom(CART_TYPE_MBC1, ROM_SIZE_64KB, RAM_SIZE_0KB);
+        let (emulator, header) = EmulatorBuilder::new().with_rom(&rom).build().unwrap();
+        assert!(emulator.memory.in_bios);
+        assert!(header.is_some());
+    }
+
+    #[test]
+    fn should_build_emulator_with_post_boot_state() {
+        let rom = build_rom(CART_TYPE_MBC1, ROM_SIZE_64KB, RAM_SIZE_0KB);
+        let (emulator, _) = EmulatorBuilder::new()
+            .with_rom(&rom)
+            .with_hardware_model(HardwareModel::AGB)
+            .skip_boot_rom()
+            .build()
+            .unwrap();
+        assert!(!emulator.memory.in_bios);
+        assert!(emulator.mode == Mode::CGB);
+        assert_eq!(emulator.model, HardwareModel::AGB);
+        assert_eq!(emulator.cpu.registers.b, 0x01);
+    }
+
+    #[test]
+    fn should_keep_running_after_skipping_boot_rom() {
+        let rom = build_rom(CART_TYPE_MBC1, ROM_SIZE_64KB, RAM_SIZE_0KB);
+        for model in [HardwareModel::DMG, HardwareModel::SGB, HardwareModel::CGB] {
+            let (mut emulator, _) = EmulatorBuilder::new().with_rom(&rom).with_hardware_model(model).skip_boot_rom().build().unwrap();
+            for _ in 0..1000 {
+                emulator::step(&mut emulator);
+            }
+            assert!(emulator.cpu.registers.program_counter > 0x0100);
+        }
+    }
+
+    #[test]
+    fn should_build_emulator_with_accuracy_profile() {
+        let (emulator, _) = EmulatorBuilder::new()
+            .with_accuracy_profile(AccuracyProfile::Fast)
+            .build()
+            .unwrap();
+        assert_eq!(emulator.accuracy.profile, AccuracyProfile::Fast);
+    }
+
+    #[test]
+    fn should_apply_patches_before_loading_rom() {
+        let rom = build_rom(CART_TYPE_MBC1, ROM_SIZE_64KB, RAM_SIZE_0KB);
+        let mut patch = b"PATCH".to_vec();
+        patch.extend_from_slice(&[0x00, 0x01, 0x34, 0x00, 0x04, b'H', b'A', b'C', b'K']);
+        patch.extend_from_slice(b"EOF");
+
+        let (_, header) = EmulatorBuilder::new().with_rom(&rom).with_patch(&patch).build().unwrap();
+        assert_eq!(header.unwrap().title, "HACK");
+        assert!(EmulatorBuilder::new().with_rom(&rom).with_patch(b"BPS1").build().is_err());
+    }
+
+    #[test]
+    fn should_fail_to_build_with_invalid_rom() {
+        assert!(EmulatorBuilder::new().with_rom(&[0; 0x10]).build().is_err());
+    }
+}
@@ -0,0 +1,90 @@
+use crate::emulator::Emulator;
+
+/*
+    Sees every access the CPU makes on the bus, with the value read or written and
+    the T-cycle it happened on. It's meant for co-simulating hardware that sits on
+    the cartridge bus (flash carts, cheat devices) and for logging complete bus
+    traces to compare against a logic analyzer capture. OAM DMA and HDMA transfers
+    aren't CPU accesses and aren't reported.
+
+    Calling out on every access is expensive, so this is only built with the
+    "bus-observer" feature.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BusPhase {
+    OpcodeFetch,
+    Read,
+    Write
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BusAccess {
+    pub address: u16,
+    pub value: u8,
+    pub phase: BusPhase,
+    // Total T-cycles the CPU had run when the access completed.
+    pub t_cycle: u32
+}
+
+pub trait BusObserver: Send {
+    fn observe(&mut self, access: BusAccess);
+}
+
+pub fn set_bus_observer(emulator: &mut Emulator, observer: Option<Box<dyn BusObserver>>) {
+    emulator.bus_observer = observer;
+}
+
+pub fn notify(emulator: &mut Emulator, address: u16, value: u8, phase: BusPhase) {
+    let t_cycle = emulator.cpu.clock.total_clock_cycles;
+    if let Some(observer) = emulator.bus_observer.as_mut() {
+        observer.observe(BusAccess { address, value, phase, t_cycle });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::builder::EmulatorBuilder;
+    use crate::cpu;
+    use crate::mmu::constants::*;
+    use crate::mmu::test_utils::*;
+    use super::*;
+
+    struct RecordingObserver {
+        accesses: Arc<Mutex<Vec<BusAccess>>>
+    }
+
+    impl BusObserver for RecordingObserver {
+        fn observe(&mut self, access: BusAccess) {
+            self.accesses.lock().unwrap().push(access);
+        }
+    }
+
+    #[test]
+    fn should_report_fetches_reads_and_writes() {
+        let mut rom = build_rom(CART_TYPE_ROM_ONLY, ROM_SIZE_64KB, RAM_SIZE_0KB);
+        // LD (HL), A with HL = 0xC000 after the NOP at the entry point.
+        rom[0x100..0x102].copy_from_slice(&[0x00, 0x77]);
+        let (mut emulator, _) = EmulatorBuilder::new().with_rom(&rom).skip_boot_rom().build().unwrap();
+        emulator.cpu.registers.h = 0xC0;
+        emulator.cpu.registers.l = 0x00;
+        emulator.cpu.registers.a = 0x5A;
+
+        let accesses = Arc::new(Mutex::new(Vec::new()));
+        set_bus_observer(&mut emulator, Some(Box::new(RecordingObserver { accesses: accesses.clone() })));
+        for _ in 0..3 {
+            cpu::opcodes::step(&mut emulator);
+        }
+
+        let accesses = accesses.lock().unwrap();
+        let summary: Vec<(u16, u8, BusPhase)> = accesses.iter().map(|access| (access.address, access.value, access.phase)).collect();
+        assert_eq!(summary, vec![
+            (0x0100, 0x00, BusPhase::OpcodeFetch),
+            (0x0101, 0x77, BusPhase::OpcodeFetch),
+            (0xC000, 0x5A, BusPhase::Write),
+            (0x0102, 0x00, BusPhase::OpcodeFetch)
+        ]);
+        assert!(accesses[2].t_cycle > accesses[1].t_cycle);
+    }
+}
@@ -0,0 +1,191 @@
+use crate::emulator::Emulator;
+use crate::symbols;
+
+/*
+    Best-effort call stack for the debugger, rebuilt from the calls, restarts and
+    interrupts the CPU takes. Games don't always return the way they were called:
+    some pop the return address and jump elsewhere, reset the stack pointer, or
+    "push" an address and "ret" to it as a jump. Frames are tied to the stack pointer
+    at the time of the call so those cases don't leave the stack out of sync:
+
+    - A return only pops the frame whose return address it's reading, discarding
+      any deeper frames that were abandoned, and leaves the stack alone otherwise.
+    - A call discards frames at or below its own stack pointer, which have been
+      overwritten by the time the stack reaches that deep again.
+
+    Tracking is off unless a debugger turns it on.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallKind {
+    Call,
+    Restart,
+    Interrupt
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallFrame {
+    pub kind: CallKind,
+    pub target: u16,
+    pub return_address: u16,
+    // Where the return address was pushed.
+    pub stack_pointer: u16
+}
+
+#[derive(Debug, Default)]
+pub struct CallStackState {
+    pub enabled: bool,
+    pub frames: Vec<CallFrame>
+}
+
+const MAX_FRAMES: usize = 256;
+
+pub fn initialize_call_stack() -> CallStackState {
+    CallStackState::default()
+}
+
+pub fn set_call_stack_enabled(emulator: &mut Emulator, enabled: bool) {
+    emulator.call_stack.enabled = enabled;
+    emulator.call_stack.frames.clear();
+}
+
+// The innermost frame comes last.
+#[cfg(any(test, feature = "internals"))]
+pub fn call_stack(emulator: &Emulator) -> &[CallFrame] {
+    &emulator.call_stack.frames
+}
+
+pub fn record_call(emulator: &mut Emulator, kind: CallKind, return_address: u16) {
+    if !emulator.call_stack.enabled {
+        return;
+    }
+
+    let target = emulator.cpu.registers.program_counter;
+    let stack_pointer = emulator.cpu.registers.stack_pointer;
+    let frames = &mut emulator.call_stack.frames;
+
+    while frames.last().is_some_and(|frame| frame.stack_pointer <= stack_pointer) {
+        frames.pop();
+    }
+
+    if frames.len() >= MAX_FRAMES {
+        frames.remove(0);
+    }
+
+    frames.push(CallFrame { kind, target, return_address, stack_pointer });
+}
+
+// Called with the stack pointer the return address was popped from.
+pub fn record_return(emulator: &mut Emulator, stack_pointer: u16) {
+    if !emulator.call_stack.enabled {
+        return;
+    }
+
+    let frames = &mut emulator.call_stack.frames;
+    if let Some(index) = frames.iter().rposition(|frame| frame.stack_pointer == stack_pointer) {
+        frames.truncate(index);
+    }
+}
+
+// Describes each frame from innermost to outermost, using labels from the symbol file where there are any.
+pub fn describe_call_stack(emulator: &Emulator) -> Vec<String> {
+    let describe = |address: u16| {
+        symbols::describe_current_address(emulator, address)
+            .unwrap_or_else(|| format!("{:#06X}", address))
+    };
+
+    emulator.call_stack.frames.iter().rev()
+        .map(|frame| {
+            let kind = match frame.kind {
+                CallKind::Call => "call",
+                CallKind::Restart => "rst",
+                CallKind::Interrupt => "interrupt"
+            };
+            format!("{} ({} from {})", describe(frame.target), kind, describe(frame.return_address))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::{self, enable_processor_test_mode, initialize_screenless_emulator};
+    use super::*;
+
+    fn setup_emulator(program: &[(u16, &[u8])]) -> Emulator {
+        let mut emulator = initialize_screenless_emulator();
+        enable_processor_test_mode(&mut emulator);
+        for (address, bytes) in program {
+            let start = *address as usize;
+            emulator.memory.processor_test_ram[start..start + bytes.len()].copy_from_slice(bytes);
+        }
+        emulator.cpu.registers.program_counter = 0x101;
+        emulator.cpu.registers.opcode = emulator.memory.processor_test_ram[0x100];
+        emulator.cpu.registers.stack_pointer = 0xFFFE;
+        set_call_stack_enabled(&mut emulator, true);
+        emulator
+    }
+
+    fn run_steps(emulator: &mut Emulator, steps: usize) {
+        for _ in 0..steps {
+            emulator::step(emulator);
+        }
+    }
+
+    #[test]
+    fn should_track_nested_calls_and_returns() {
+        // call $0200; nop ... $0200: rst $08; ret ... $0008: ret
+        let mut emulator = setup_emulator(&[(0x100, &[0xCD, 0x00, 0x02, 0x00]), (0x200, &[0xCF, 0xC9]), (0x08, &[0xC9])]);
+
+        run_steps(&mut emulator, 2);
+        assert_eq!(call_stack(&emulator), [
+            CallFrame { kind: CallKind::Call, target: 0x200, return_address: 0x103, stack_pointer: 0xFFFC },
+            CallFrame { kind: CallKind::Restart, target: 0x08, return_address: 0x201, stack_pointer: 0xFFFA }
+        ]);
+
+        run_steps(&mut emulator, 1);
+        assert_eq!(call_stack(&emulator).len(), 1);
+        run_steps(&mut emulator, 1);
+        assert!(call_stack(&emulator).is_empty());
+    }
+
+    #[test]
+    fn should_record_interrupt_entries() {
+        let mut emulator = setup_emulator(&[(0x100, &[0x00, 0x00]), (0x40, &[0xD9])]);
+        emulator.cpu.interrupts.enabled = true;
+        emulator.interrupts.enabled = 0x1;
+        emulator.interrupts.flags = 0x1;
+
+        run_steps(&mut emulator, 1);
+        let frame = call_stack(&emulator)[0];
+        assert_eq!(frame.kind, CallKind::Interrupt);
+        assert_eq!(frame.target, 0x40);
+
+        run_steps(&mut emulator, 1);
+        assert!(call_stack(&emulator).is_empty());
+    }
+
+    #[test]
+    fn should_discard_frames_abandoned_by_manual_stack_manipulation() {
+        // call $0200 ... $0200: pop hl; jp $0300 ... $0300: call $0400 ... $0400: ret
+        let mut emulator = setup_emulator(&[
+            (0x100, &[0xCD, 0x00, 0x02]),
+            (0x200, &[0xE1, 0xC3, 0x00, 0x03]),
+            (0x300, &[0xCD, 0x00, 0x04]),
+            (0x400, &[0xC9])
+        ]);
+
+        run_steps(&mut emulator, 4);
+        assert_eq!(call_stack(&emulator).len(), 1);
+        assert_eq!(call_stack(&emulator)[0].target, 0x400);
+
+        run_steps(&mut emulator, 1);
+        assert!(call_stack(&emulator).is_empty());
+    }
+
+    #[test]
+    fn should_not_track_calls_when_disabled() {
+        let mut emulator = setup_emulator(&[(0x100, &[0xCD, 0x00, 0x02])]);
+        set_call_stack_enabled(&mut emulator, false);
+        run_steps(&mut emulator, 1);
+        assert!(call_stack(&emulator).is_empty());
+    }
+}
@@ -0,0 +1,88 @@
+use core::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/*
+    Where cartridge real-time clocks (MBC3 and TAMA5) get the time from, in
+    milliseconds since the UNIX epoch. The system clock is the default. Tests, replays
+    and netplay sessions can swap in a clock they control, so games that read the RTC
+    behave the same on every run.
+*/
+pub trait Clock: Send + Sync {
+    fn current_time_millis(&self) -> f64;
+}
+
+pub type SharedClock = Arc<dyn Clock>;
+
+impl Debug for dyn Clock {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Clock")
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    #[cfg(target_arch = "wasm32")]
+    fn current_time_millis(&self) -> f64 {
+        crate::wasm::api::current_time_millis()
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn current_time_millis(&self) -> f64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|duration| duration.as_secs_f64() * 1000.0)
+            .unwrap_or(0.0)
+    }
+}
+
+pub fn system_clock() -> SharedClock {
+    Arc::new(SystemClock)
+}
+
+// Only moves when told to. Clones share the same time, so a test can keep one to advance the emulator's.
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    millis: Arc<AtomicU64>
+}
+
+impl ManualClock {
+    pub fn new(millis: f64) -> ManualClock {
+        ManualClock { millis: Arc::new(AtomicU64::new(millis.to_bits())) }
+    }
+
+    pub fn shared(&self) -> SharedClock {
+        Arc::new(self.clone())
+    }
+
+    pub fn set_millis(&self, millis: f64) {
+        self.millis.store(millis.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn advance_millis(&self, millis: f64) {
+        self.set_millis(self.current_time_millis() + millis);
+    }
+}
+
+impl Clock for ManualClock {
+    fn current_time_millis(&self) -> f64 {
+        f64::from_bits(self.millis.load(Ordering::Relaxed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_share_time_between_manual_clock_clones() {
+        let clock = ManualClock::new(1000.0);
+        let shared = clock.shared();
+
+        clock.advance_millis(500.0);
+
+        assert_eq!(shared.current_time_millis(), 1500.0);
+    }
+}
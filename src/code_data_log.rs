@@ -0,0 +1,179 @@
+#[cfg(any(test, feature = "internals"))]
+use std::io;
+
+use crate::emulator::Emulator;
+
+/*
+    Code/data logger for disassembly projects and coverage measurements. It keeps one
+    byte of flags for every byte of ROM, marking whether the CPU fetched it as part of
+    an instruction or read it as data. Offsets are into the ROM file rather than the
+    address space, so code in each bank is told apart. The exported file is the flags
+    array on its own, the same layout Mesen and FCEUX-style CDL tools use for the Game
+    Boy, with bit 0 for code and bit 1 for data.
+*/
+pub const CDL_CODE: u8 = 0x01;
+pub const CDL_DATA: u8 = 0x02;
+
+const ROM_BANK_SIZE: usize = 0x4000;
+
+#[derive(Debug, Default)]
+pub struct CodeDataLogState {
+    pub enabled: bool,
+    pub flags: Vec<u8>
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg(any(test, feature = "internals"))]
+pub struct BankCoverage {
+    pub code_bytes: usize,
+    pub data_bytes: usize,
+    pub unused_bytes: usize
+}
+
+pub fn initialize_code_data_log() -> CodeDataLogState {
+    CodeDataLogState::default()
+}
+
+fn rom_size(emulator: &Emulator) -> usize {
+    emulator.memory.cartridge_mapper.get_cartridge().rom.len()
+}
+
+// Starts logging, keeping anything logged so far for the same ROM.
+pub fn start_code_data_log(emulator: &mut Emulator) {
+    let size = rom_size(emulator);
+    let log = &mut emulator.code_data_log;
+    if log.flags.len() != size {
+        log.flags = vec![0; size];
+    }
+    log.enabled = true;
+}
+
+pub fn stop_code_data_log(emulator: &mut Emulator) {
+    emulator.code_data_log.enabled = false;
+}
+
+#[cfg(feature = "internals")]
+pub fn reset_code_data_log(emulator: &mut Emulator) {
+    emulator.code_data_log.flags.fill(0);
+}
+
+// Continues a log exported from an earlier session, e.g. to add coverage from another playthrough.
+#[cfg(any(test, feature = "internals"))]
+pub fn load_code_data_log(emulator: &mut Emulator, cdl: &[u8]) -> io::Result<()> {
+    let size = rom_size(emulator);
+    if cdl.len() != size {
+        return Err(io::Error::new(io::ErrorKind::InvalidData,
+            format!("CDL file is {} bytes but the ROM is {} bytes", cdl.len(), size)));
+    }
+    emulator.code_data_log.flags = cdl.to_vec();
+    Ok(())
+}
+
+pub fn export_code_data_log(emulator: &Emulator) -> Vec<u8> {
+    emulator.code_data_log.flags.clone()
+}
+
+fn as_rom_offset(emulator: &Emulator, address: u16) -> Option<usize> {
+    let in_bios = emulator.memory.in_bios
+        && (address < 0x100 || (0x200..=0x8FF).contains(&address));
+    match address {
+        _ if in_bios => None,
+        0x0000..=0x3FFF => Some(address as usize),
+        0x4000..=0x7FFF => {
+            let bank = emulator.memory.cartridge_mapper.get_rom_bank() as usize;
+            Some(bank * ROM_BANK_SIZE + (address as usize & (ROM_BANK_SIZE - 1)))
+        },
+        _ => None
+    }
+}
+
+// Called before the CPU reads from the bus, while the boot ROM is still known to be mapped.
+pub fn record_access(emulator: &mut Emulator, address: u16, flag: u8) {
+    if !emulator.code_data_log.enabled || emulator.processor_test_mode {
+        return;
+    }
+
+    if let Some(offset) = as_rom_offset(emulator, address) {
+        if let Some(flags) = emulator.code_data_log.flags.get_mut(offset) {
+            *flags |= flag;
+        }
+    }
+}
+
+#[cfg(any(test, feature = "internals"))]
+pub fn bank_coverage(emulator: &Emulator) -> Vec<BankCoverage> {
+    emulator.code_data_log.flags.chunks(ROM_BANK_SIZE)
+        .map(|bank| {
+            let code_bytes = bank.iter().filter(|flags| *flags & CDL_CODE != 0).count();
+            let data_bytes = bank.iter().filter(|flags| *flags & CDL_DATA != 0).count();
+            let unused_bytes = bank.iter().filter(|flags| **flags == 0).count();
+            BankCoverage { code_bytes, data_bytes, unused_bytes }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::initialize_screenless_emulator;
+    use crate::mmu;
+    use crate::mmu::constants::*;
+    use crate::mmu::effects::empty_cartridge_effects;
+    use crate::mmu::test_utils::*;
+    use super::*;
+
+    fn setup_emulator(program: &[(usize, &[u8])]) -> Emulator {
+        let mut emulator = initialize_screenless_emulator();
+        let mut rom = build_rom(CART_TYPE_MBC5, ROM_SIZE_64KB, RAM_SIZE_0KB);
+        for (offset, bytes) in program {
+            rom[*offset..*offset + bytes.len()].copy_from_slice(bytes);
+        }
+        mmu::load_rom_buffer(&mut emulator.memory, rom, empty_cartridge_effects()).unwrap();
+        emulator.memory.in_bios = false;
+        emulator.cpu.registers.program_counter = 0x150;
+        emulator.cpu.registers.opcode = 0x00;
+        start_code_data_log(&mut emulator);
+        emulator
+    }
+
+    fn step(emulator: &mut Emulator, steps: usize) {
+        for _ in 0..steps {
+            crate::emulator::step(emulator);
+        }
+    }
+
+    #[test]
+    fn should_flag_instructions_as_code_and_reads_as_data() {
+        // ld a,[$0200]; nop
+        let mut emulator = setup_emulator(&[(0x150, &[0xFA, 0x00, 0x02, 0x00])]);
+        step(&mut emulator, 3);
+
+        let cdl = export_code_data_log(&emulator);
+        assert_eq!(&cdl[0x150..0x154], &[CDL_CODE, CDL_CODE, CDL_CODE, CDL_CODE]);
+        assert_eq!(cdl[0x200], CDL_DATA);
+        assert_eq!(cdl[0x201], 0);
+    }
+
+    #[test]
+    fn should_log_banked_code_at_its_rom_offset() {
+        // ld a,$02; ld [$2000],a; jp $4000 ... bank 2: nop
+        let mut emulator = setup_emulator(&[(0x150, &[0x3E, 0x02, 0xEA, 0x00, 0x20, 0xC3, 0x00, 0x40])]);
+        step(&mut emulator, 5);
+
+        let cdl = export_code_data_log(&emulator);
+        assert_eq!(cdl[2 * ROM_BANK_SIZE], CDL_CODE);
+        assert_eq!(cdl[ROM_BANK_SIZE], 0);
+
+        let coverage = bank_coverage(&emulator);
+        assert_eq!(coverage.len(), 4);
+        assert_eq!(coverage[1].unused_bytes, ROM_BANK_SIZE);
+        assert!(coverage[2].code_bytes > 0);
+    }
+
+    #[test]
+    fn should_reject_log_for_different_rom_size() {
+        let mut emulator = setup_emulator(&[]);
+        assert!(load_code_data_log(&mut emulator, &[0; 16]).is_err());
+        assert!(load_code_data_log(&mut emulator, &vec![CDL_CODE; 0x10000]).is_ok());
+        assert_eq!(bank_coverage(&emulator)[0].code_bytes, ROM_BANK_SIZE);
+    }
+}
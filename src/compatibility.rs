@@ -0,0 +1,131 @@
+use std::collections::HashSet;
+use std::io;
+
+use crate::builder::EmulatorBuilder;
+use crate::core::Core;
+use crate::cycles::{T_CYCLES_PER_FRAME, T_CYCLES_PER_SECOND};
+use crate::emulator::{self, HardwareModel, UnmappedWrite};
+use crate::unmapped_writes;
+
+// The entry point and header, which every cartridge jumps over to reach its own code.
+const HEADER_END_ADDRESS: u16 = 0x0150;
+// Anything quieter is treated as silence.
+const AUDIO_THRESHOLD: f32 = 0.01;
+// The boot ROM leaves the DACs on, and the high-pass filter takes a few frames to remove their offset.
+const AUDIO_SETTLE_FRAMES: u32 = 10;
+
+/*
+    What a game did over a headless run, for spotting broken games across a ROM
+    collection without playing each one. The checks are heuristics, as a game
+    waiting on a title screen looks much like one that hung after drawing it.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompatibilityReport {
+    pub title: String,
+    pub frames_run: u32,
+    // Whether the CPU was ever found past the cartridge header.
+    pub reached_game_code: bool,
+    // Frames that ended with the LCD on. Games that switch it off and hang never get it back on.
+    pub lcd_enabled_frames: u32,
+    pub distinct_frames: usize,
+    pub audio_produced: bool,
+    pub unmapped_writes: Vec<UnmappedWrite>
+}
+
+impl CompatibilityReport {
+    // Games that draw more than one screen and make sound are usually at least getting to their menus.
+    pub fn looks_playable(&self) -> bool {
+        self.reached_game_code && self.lcd_enabled_frames > 0 && self.distinct_frames > 1 && self.audio_produced
+    }
+}
+
+fn is_game_code(address: u16) -> bool {
+    address >= HEADER_END_ADDRESS
+}
+
+// Runs a ROM from the end of the boot ROM for the given number of emulated seconds.
+pub fn run_compatibility_check(rom: &[u8], model: HardwareModel, seconds: u32) -> io::Result<CompatibilityReport> {
+    let (mut emulator, header) = EmulatorBuilder::new()
+        .with_hardware_model(model)
+        .with_rom(rom)
+        .skip_boot_rom()
+        .build()?;
+    let title = header.map(|header| header.title).unwrap_or_default();
+
+    emulator::set_frame_hashing(&mut emulator, true);
+    unmapped_writes::set_unmapped_write_tracking(&mut emulator, true);
+
+    let frames_run = (seconds as u64 * T_CYCLES_PER_SECOND as u64 / T_CYCLES_PER_FRAME as u64) as u32;
+    let mut reached_game_code = false;
+    let mut lcd_enabled_frames = 0;
+    let mut audio_produced = false;
+    let mut frame_hashes = HashSet::new();
+
+    for frame in 0..frames_run {
+        emulator.run_frame();
+
+        reached_game_code |= is_game_code(emulator.cpu.registers.program_counter);
+        if emulator.gpu.registers.lcdc & 0x80 != 0 {
+            lcd_enabled_frames += 1;
+        }
+
+        let (left, right) = emulator.take_audio_samples();
+        if frame >= AUDIO_SETTLE_FRAMES {
+            audio_produced |= left.iter().chain(&right).any(|sample| sample.abs() > AUDIO_THRESHOLD);
+        }
+
+        if let Some(hash) = emulator::get_frame_hash(&emulator) {
+            frame_hashes.insert(hash);
+        }
+    }
+
+    Ok(CompatibilityReport {
+        title,
+        frames_run,
+        reached_game_code,
+        lcd_enabled_frames,
+        distinct_frames: frame_hashes.len(),
+        audio_produced,
+        unmapped_writes: unmapped_writes::get_unmapped_writes(&emulator)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mmu::constants::*;
+    use crate::mmu::test_utils::*;
+    use super::*;
+
+    fn build_rom_with_code(code: &[u8]) -> Vec<u8> {
+        let mut rom = build_rom(CART_TYPE_ROM_ONLY, ROM_SIZE_64KB, RAM_SIZE_0KB);
+        // JP 0x0150
+        rom[0x100..0x103].copy_from_slice(&[0xC3, 0x50, 0x01]);
+        rom[0x150..0x150 + code.len()].copy_from_slice(code);
+        rom
+    }
+
+    #[test]
+    fn should_report_what_the_game_did() {
+        // LD A, 0x11; LDH (0x40), A; LD A, 0xC0; LDH (0x56), A; JR -2
+        let rom = build_rom_with_code(&[0x3E, 0x11, 0xE0, 0x40, 0x3E, 0xC0, 0xE0, 0x56, 0x18, 0xFE]);
+        let report = run_compatibility_check(&rom, HardwareModel::DMG, 1).unwrap();
+
+        assert_eq!(report.frames_run, 59);
+        assert!(report.reached_game_code);
+        assert_eq!(report.lcd_enabled_frames, 0);
+        assert!(!report.audio_produced);
+        assert!(!report.looks_playable());
+        assert_eq!(report.unmapped_writes, vec![UnmappedWrite { address: 0xFF56, count: 1, last_value: 0xC0 }]);
+    }
+
+    #[test]
+    fn should_not_count_a_hang_in_the_entry_point_as_game_code() {
+        // DI; JR -2 at the entry point itself.
+        let mut rom = build_rom(CART_TYPE_ROM_ONLY, ROM_SIZE_64KB, RAM_SIZE_0KB);
+        rom[0x100..0x103].copy_from_slice(&[0xF3, 0x18, 0xFE]);
+        let report = run_compatibility_check(&rom, HardwareModel::DMG, 1).unwrap();
+
+        assert!(!report.reached_game_code);
+        assert_eq!(report.lcd_enabled_frames, report.frames_run);
+    }
+}
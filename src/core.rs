@@ -0,0 +1,178 @@
+use std::io;
+
+use crate::apu;
+use crate::cycles::T_CYCLES_PER_FRAME;
+use crate::emulator::{self, is_cgb, Emulator, HardwareModel};
+use crate::gpu::constants::{GB_SCREEN_HEIGHT, GB_SCREEN_WIDTH};
+use crate::keys::{self, Key};
+use crate::mmu::effects::empty_cartridge_effects;
+use crate::pause;
+use crate::rom_database;
+use crate::savestate;
+
+const CGB_FLAG_ADDRESS: usize = 0x143;
+
+/*
+    The surface a frontend needs to drive an emulated system: load a ROM, run it a
+    frame at a time, collect the picture and sound it produced and feed it input.
+    Frontends written against this trait rather than the Game Boy emulator itself
+    can host other cores later without changes.
+*/
+pub trait Core {
+    type Button: Copy;
+
+    fn load_rom(&mut self, rom: &[u8]) -> io::Result<()>;
+    fn run_frame(&mut self);
+    // RGBA pixels, row by row.
+    fn frame_buffer(&self) -> &[u8];
+    fn screen_size(&self) -> (u32, u32);
+    // Samples produced since the last call, as left and right channels.
+    fn take_audio_samples(&mut self) -> (Vec<f32>, Vec<f32>);
+    fn set_button(&mut self, button: Self::Button, pressed: bool);
+    fn save_state(&self) -> Vec<u8>;
+    fn load_state(&mut self, state: &[u8]) -> io::Result<()>;
+}
+
+// Frontends using a core can't pick a model, so cartridges with CGB support get a CGB to run on.
+fn select_hardware_model(emulator: &mut Emulator, rom: &[u8]) {
+    let supports_cgb = rom.get(CGB_FLAG_ADDRESS).is_some_and(|flag| flag & 0x80 != 0);
+    if supports_cgb && !is_cgb(emulator) {
+        emulator::set_hardware_model(emulator, HardwareModel::CGB);
+    }
+}
+
+impl Core for Emulator {
+    type Button = Key;
+
+    fn load_rom(&mut self, rom: &[u8]) -> io::Result<()> {
+        select_hardware_model(self, rom);
+        rom_database::load_rom(self, rom.to_vec(), empty_cartridge_effects())?;
+        Ok(())
+    }
+
+    fn run_frame(&mut self) {
+        let frame_count = self.gpu.frame_count;
+        let start_clock_cycles = self.cpu.clock.total_clock_cycles;
+
+        // Capped at one frame's worth of cycles, so running a frame with the LCD off still returns.
+        while self.gpu.frame_count == frame_count
+            && !pause::is_paused(self)
+            && self.cpu.clock.total_clock_cycles.wrapping_sub(start_clock_cycles) < T_CYCLES_PER_FRAME {
+            emulator::step(self);
+        }
+    }
+
+    fn frame_buffer(&self) -> &[u8] {
+        &self.gpu.frame_buffer
+    }
+
+    fn screen_size(&self) -> (u32, u32) {
+        (GB_SCREEN_WIDTH, GB_SCREEN_HEIGHT)
+    }
+
+    fn take_audio_samples(&mut self) -> (Vec<f32>, Vec<f32>) {
+        let samples = (apu::get_left_sample_queue(self).to_vec(), apu::get_right_sample_queue(self).to_vec());
+        apu::clear_audio_buffers(self);
+        samples
+    }
+
+    fn set_button(&mut self, button: Key, pressed: bool) {
+        if pressed {
+            keys::handle_key_press(self, &button);
+        }
+        else {
+            keys::handle_key_release(self, &button);
+        }
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        savestate::encode_state(self)
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> io::Result<()> {
+        savestate::decode_state(self, state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mmu;
+    use crate::mmu::constants::*;
+    use crate::mmu::test_utils::*;
+    use super::*;
+
+    fn run_frames<C: Core>(core: &mut C, frames: usize) {
+        for _ in 0..frames {
+            core.run_frame();
+        }
+    }
+
+    #[test]
+    fn should_run_cgb_cartridges_in_cgb_mode() {
+        let mut rom = build_rom(CART_TYPE_MBC1, ROM_SIZE_64KB, RAM_SIZE_0KB);
+        let mut core = emulator::initialize_screenless_emulator();
+        core.load_rom(&rom).unwrap();
+        assert!(!is_cgb(&core));
+
+        rom[CGB_FLAG_ADDRESS] = 0x80;
+        core.load_rom(&rom).unwrap();
+        assert!(is_cgb(&core));
+        assert_eq!(core.model, HardwareModel::CGB);
+    }
+
+    #[test]
+    fn should_run_a_single_frame() {
+        let mut core = setup_emulator(CART_TYPE_MBC1, RAM_SIZE_0KB);
+        run_frames(&mut core, 2);
+        let frame_count = core.gpu.frame_count;
+        core.run_frame();
+        assert_eq!(core.gpu.frame_count, frame_count + 1);
+        assert_eq!(core.frame_buffer().len(), 160 * 144 * 4);
+        assert_eq!(core.screen_size(), (160, 144));
+    }
+
+    #[test]
+    fn should_return_from_frame_with_lcd_off() {
+        let mut core = setup_emulator(CART_TYPE_MBC1, RAM_SIZE_0KB);
+        mmu::write_byte(&mut core, 0xFF40, 0x00);
+        let frame_count = core.gpu.frame_count;
+        core.run_frame();
+        assert_eq!(core.gpu.frame_count, frame_count);
+    }
+
+    #[test]
+    fn should_drain_audio_samples() {
+        let mut core = setup_emulator(CART_TYPE_MBC1, RAM_SIZE_0KB);
+        core.run_frame();
+        let (left, right) = core.take_audio_samples();
+        assert!(!left.is_empty());
+        assert_eq!(left.len(), right.len());
+        assert!(core.take_audio_samples().0.is_empty());
+    }
+
+    #[test]
+    fn should_restore_saved_state() {
+        let mut core = setup_emulator(CART_TYPE_MBC1, RAM_SIZE_0KB);
+        core.set_button(Key::A, true);
+        let state = core.save_state();
+        run_frames(&mut core, 2);
+        core.load_state(&state).unwrap();
+        assert_eq!(core.save_state(), state);
+    }
+
+    #[test]
+    fn should_run_independent_instances_on_separate_threads() {
+        let handles: Vec<_> = (0..2).map(|_| std::thread::spawn(|| {
+            let mut core = setup_emulator(CART_TYPE_MBC1, RAM_SIZE_0KB);
+            run_frames(&mut core, 5);
+            core.save_state()
+        })).collect();
+
+        let states: Vec<Vec<u8>> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+        assert_eq!(states[0], states[1]);
+
+        let mut core = setup_emulator(CART_TYPE_MBC1, RAM_SIZE_0KB);
+        run_frames(&mut core, 5);
+        assert_eq!(core.save_state(), states[0]);
+    }
+}
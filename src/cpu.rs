@@ -18,14 +18,14 @@ pub struct Registers {
 #[derive(Debug)]
 pub struct Clock {
     pub instruction_clock_cycles: u8,
-    total_clock_cycles: u32
+    pub total_clock_cycles: u32
 }
 
 #[derive(Debug)]
 pub struct Interrupts {
-    enable_delay: u8,
-    disable_delay: u8,
-    enabled: bool
+    pub enable_delay: u8,
+    pub disable_delay: u8,
+    pub enabled: bool
 }
 
 #[derive(Debug)]
@@ -104,22 +104,36 @@ pub fn initialize_cpu() -> CpuState {
     }
 }
 
+// The opcode has already been fetched, so the program counter is one past it.
+pub fn current_instruction_address(emulator: &Emulator) -> u16 {
+    emulator.cpu.registers.program_counter.wrapping_sub(1)
+}
+
 pub fn read_next_instruction_byte(emulator: &mut Emulator) -> u8 {
-    let byte = microops::read_byte_from_memory(emulator, emulator.cpu.registers.program_counter);
-    emulator.cpu.registers.program_counter += 1;
+    let byte = microops::fetch_instruction_byte(emulator, emulator.cpu.registers.program_counter);
+    emulator.cpu.registers.program_counter = emulator.cpu.registers.program_counter.wrapping_add(1);
     byte
 }
 
 pub fn read_next_instruction_word(emulator: &mut Emulator) -> u16 {
-    let word = microops::read_word_from_memory(emulator, emulator.cpu.registers.program_counter);
-    emulator.cpu.registers.program_counter += 2;
+    let word = microops::fetch_instruction_word(emulator, emulator.cpu.registers.program_counter);
+    emulator.cpu.registers.program_counter = emulator.cpu.registers.program_counter.wrapping_add(2);
     word
 }
 
-pub fn handle_illegal_opcode(opcode: u8) {
-    panic!("Encountered illegal opcode {:#04X}", opcode);
+/*
+    Illegal opcodes lock up the CPU until the console is reset, while the rest of the
+    hardware keeps running. The opcode is fetched again on every step with interrupts
+    disabled, so nothing can resume execution, but a game hitting one doesn't take the
+    frontend down with it.
+*/
+pub fn handle_illegal_opcode(emulator: &mut Emulator) {
+    emulator.cpu.interrupts.enabled = false;
+    emulator.cpu.interrupts.enable_delay = 0;
+    emulator.cpu.registers.program_counter = emulator.cpu.registers.program_counter.wrapping_sub(1);
 }
 
+#[cfg(feature = "internals")]
 pub fn at_end_of_boot_rom(cpu_state: &mut CpuState) -> bool {
     cpu_state.registers.program_counter == 0x100
 }
@@ -132,4 +146,5 @@ mod jumps;
 pub mod interrupts;
 pub mod timers;
 pub mod hdma;
-pub mod opcodes;
\ No newline at end of file
+pub mod opcodes;
+pub mod idle_skip;
\ No newline at end of file
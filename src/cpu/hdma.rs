@@ -120,8 +120,9 @@ fn transfer_block(emulator: &mut Emulator, source: u16, destination: u16) {
     for _ in (0..BLOCK_SIZE).step_by(2) {
         for _ in 0..2 {
             let offset = emulator.hdma.offset;
-            let source_byte = mmu::read_byte(emulator, source + offset);
-            mmu::write_byte(emulator, destination + offset, source_byte);
+            let source_byte = mmu::read_byte(emulator, source.wrapping_add(offset));
+            // The destination wraps around within VRAM.
+            mmu::write_byte(emulator, 0x8000 | (destination.wrapping_add(offset) & 0x1FFF), source_byte);
             emulator.hdma.offset += 1;
         }
 
@@ -0,0 +1,106 @@
+use crate::cpu::interrupts;
+use crate::cpu::microops;
+use crate::emulator::Emulator;
+use crate::overclock;
+
+const HALT_OPCODE: u8 = 0x76;
+
+// One scanline, so run_cycles overshoots and LCD-off frames stay bounded.
+const MAX_IDLE_MACHINE_CYCLES: u32 = 114;
+
+/*
+    Games spend much of each frame halted, waiting for VBlank. Stepping through that
+    one HALT at a time re-runs the opcode and re-fetches it from the bus every
+    M-cycle. When nothing can wake the CPU yet, this leaves the CPU out and clocks
+    the timers, PPU, APU and serial port until an interrupt is requested, a frame
+    ends or a scanline's worth of cycles has passed. The hardware isn't fast-forwarded
+    in bulk: it still advances M-cycle by M-cycle, so the emulated state is the same
+    and only the CPU's work is saved. The CPU's repeated fetches of the HALT opcode
+    aren't seen by the code/data log or a bus observer.
+*/
+pub fn can_skip(emulator: &Emulator) -> bool {
+    let cpu = &emulator.cpu;
+    emulator.accuracy.idle_skip
+        && cpu.halted
+        && !cpu.halt_bug
+        && cpu.registers.opcode == HALT_OPCODE
+        && cpu.interrupts.enable_delay == 0
+        && cpu.interrupts.disable_delay == 0
+        && !emulator.hdma.in_progress
+        && !emulator.processor_test_mode
+        && !overclock::is_running(emulator)
+        && !interrupts::interrupts_fired(emulator)
+}
+
+// Returns false without running anything when the CPU has to step through HALT itself.
+pub fn step(emulator: &mut Emulator) -> bool {
+    if !can_skip(emulator) {
+        return false;
+    }
+
+    let frame_count = emulator.gpu.frame_count;
+    emulator.cpu.clock.instruction_clock_cycles = 0;
+
+    for _ in 0..MAX_IDLE_MACHINE_CYCLES {
+        microops::step_one_machine_cycle(emulator);
+        if interrupts::interrupts_fired(emulator) || emulator.gpu.frame_count != frame_count {
+            break;
+        }
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::accuracy::{set_accuracy_profile, AccuracyProfile};
+    use crate::builder::EmulatorBuilder;
+    use crate::core::Core;
+    use crate::emulator;
+    use crate::mmu::constants::*;
+    use crate::mmu::test_utils::*;
+    use super::*;
+
+    fn setup_emulator(idle_skip: bool) -> Emulator {
+        let mut rom = build_rom(CART_TYPE_ROM_ONLY, ROM_SIZE_64KB, RAM_SIZE_0KB);
+        // VBlank handler: INC B, RETI.
+        rom[0x40..0x42].copy_from_slice(&[0x04, 0xD9]);
+        // LD A, 1; LDH (IE), A; EI; HALT; JR -3 back to HALT.
+        rom[0x100..0x108].copy_from_slice(&[0x3E, 0x01, 0xE0, 0xFF, 0xFB, 0x76, 0x18, 0xFD]);
+        let (mut emulator, _) = EmulatorBuilder::new().with_rom(&rom).skip_boot_rom().build().unwrap();
+        emulator.cpu.registers.b = 0;
+        emulator.interrupts.flags = 0;
+        emulator.accuracy.idle_skip = idle_skip;
+        emulator
+    }
+
+    #[test]
+    fn should_match_halting_one_step_at_a_time() {
+        let mut stepped = setup_emulator(false);
+        let mut skipped = setup_emulator(true);
+
+        for _ in 0..3 {
+            stepped.run_frame();
+            skipped.run_frame();
+        }
+
+        assert!(skipped.cpu.registers.b >= 2);
+        assert_eq!(skipped.cpu.registers.b, stepped.cpu.registers.b);
+        assert_eq!(skipped.cpu.registers.program_counter, stepped.cpu.registers.program_counter);
+        assert_eq!(skipped.cpu.clock.total_clock_cycles, stepped.cpu.clock.total_clock_cycles);
+        assert_eq!(skipped.gpu.registers.ly, stepped.gpu.registers.ly);
+    }
+
+    #[test]
+    fn should_only_skip_in_fast_profile_by_default() {
+        let mut emulator = setup_emulator(false);
+        for _ in 0..8 {
+            emulator::step(&mut emulator);
+        }
+        assert!(emulator.cpu.halted);
+        assert!(!can_skip(&emulator));
+
+        set_accuracy_profile(&mut emulator, AccuracyProfile::Fast);
+        assert!(can_skip(&emulator));
+    }
+}
@@ -82,7 +82,7 @@ pub fn step(emulator: &mut Emulator) -> bool {
                 turn_off_interrupt_flag(emulator, &interrupt_type);
                 let isr_address = get_interrupt_isr(&interrupt_type);
                 microops::step_machine_cycles(emulator, 2);
-                jumps::restart(emulator, isr_address as u16);
+                jumps::call_interrupt_handler(emulator, isr_address as u16);
                 true
             },
             None => false
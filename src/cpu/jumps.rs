@@ -1,6 +1,7 @@
 use crate::cpu::{read_next_instruction_byte, read_next_instruction_word};
 use crate::cpu::loads;
 use crate::cpu::microops;
+use crate::call_stack::{self, CallKind};
 use crate::emulator::Emulator;
 
 fn conditional_jump(emulator: &mut Emulator, new_address: u16, condition: bool) {
@@ -27,6 +28,7 @@ pub fn call(emulator: &mut Emulator) {
     let program_counter = emulator.cpu.registers.program_counter;
     loads::push_word_to_stack(emulator, program_counter);
     emulator.cpu.registers.program_counter = word;
+    call_stack::record_call(emulator, CallKind::Call, program_counter);
 }
 
 pub fn conditional_call_using_immediate_word(emulator: &mut Emulator, condition: bool) {
@@ -35,10 +37,12 @@ pub fn conditional_call_using_immediate_word(emulator: &mut Emulator, condition:
         let program_counter = emulator.cpu.registers.program_counter;
         loads::push_word_to_stack(emulator, program_counter);
         emulator.cpu.registers.program_counter = word;
+        call_stack::record_call(emulator, CallKind::Call, program_counter);
     }
 }
 
 pub fn stack_return(emulator: &mut Emulator) {
+    call_stack::record_return(emulator, emulator.cpu.registers.stack_pointer);
     let word = loads::pop_word_from_stack(emulator);
     emulator.cpu.registers.program_counter = word;
     microops::step_one_machine_cycle(emulator);
@@ -51,7 +55,17 @@ pub fn conditional_stack_return(emulator: &mut Emulator, condition: bool) {
     }
 }
 
-pub fn restart(emulator: &mut Emulator, new_address: u16) {
-    loads::push_word_to_stack(emulator, emulator.cpu.registers.program_counter);
+fn push_and_jump(emulator: &mut Emulator, new_address: u16, kind: CallKind) {
+    let program_counter = emulator.cpu.registers.program_counter;
+    loads::push_word_to_stack(emulator, program_counter);
     emulator.cpu.registers.program_counter = new_address;
+    call_stack::record_call(emulator, kind, program_counter);
+}
+
+pub fn restart(emulator: &mut Emulator, new_address: u16) {
+    push_and_jump(emulator, new_address, CallKind::Restart);
+}
+
+pub fn call_interrupt_handler(emulator: &mut Emulator, isr_address: u16) {
+    push_and_jump(emulator, isr_address, CallKind::Interrupt);
 }
@@ -30,9 +30,9 @@ pub fn load_immediate_value_in_memory(emulator: &mut Emulator, register_pair: Re
 
 pub fn push_word_to_stack(emulator: &mut Emulator, word: u16) {
     microops::step_one_machine_cycle(emulator);
-    emulator.cpu.registers.stack_pointer = emulator.cpu.registers.stack_pointer - 1;
+    emulator.cpu.registers.stack_pointer = emulator.cpu.registers.stack_pointer.wrapping_sub(1);
     microops::store_byte_in_memory(emulator, emulator.cpu.registers.stack_pointer, (word >> 8) as u8);
-    emulator.cpu.registers.stack_pointer = emulator.cpu.registers.stack_pointer - 1;
+    emulator.cpu.registers.stack_pointer = emulator.cpu.registers.stack_pointer.wrapping_sub(1);
     microops::store_byte_in_memory(emulator, emulator.cpu.registers.stack_pointer, (word & 0xFF) as u8);
 }
 
@@ -43,9 +43,9 @@ pub fn push_register_pair_to_stack(emulator: &mut Emulator, register_pair: Regis
 
 pub fn pop_word_from_stack(emulator: &mut Emulator) -> u16 {
     let first_byte = microops::read_byte_from_memory(emulator, emulator.cpu.registers.stack_pointer) as u16;
-    emulator.cpu.registers.stack_pointer = emulator.cpu.registers.stack_pointer + 1;
+    emulator.cpu.registers.stack_pointer = emulator.cpu.registers.stack_pointer.wrapping_add(1);
     let second_byte = microops::read_byte_from_memory(emulator, emulator.cpu.registers.stack_pointer) as u16;
-    emulator.cpu.registers.stack_pointer = emulator.cpu.registers.stack_pointer + 1;
+    emulator.cpu.registers.stack_pointer = emulator.cpu.registers.stack_pointer.wrapping_add(1);
     (second_byte << 8) + first_byte
 }
 
@@ -1,7 +1,12 @@
 use crate::{mmu, utils};
+#[cfg(feature = "bus-observer")]
+use crate::bus_observer::{self, BusPhase};
+use crate::code_data_log::{self, CDL_CODE, CDL_DATA};
 use crate::cpu::{BusActivityEntry, BusActivityType, Register, RegisterPair, CpuState};
 use crate::emulator::Emulator;
 use crate::emulator;
+use crate::frame_metadata;
+use crate::io_trace::{self, IoAccessKind};
 use crate::utils::get_t_cycle_increment;
 
 pub fn step_one_machine_cycle(emulator: &mut Emulator) {
@@ -53,6 +58,24 @@ fn record_bus_write(emulator: &mut Emulator, address: u16, value: u8) {
 }
 
 pub fn read_byte_from_memory(emulator: &mut Emulator, address: u16) -> u8 {
+    code_data_log::record_access(emulator, address, CDL_DATA);
+    let byte = read_bus_byte(emulator, address);
+    io_trace::record_access(emulator, IoAccessKind::Read, address, byte);
+    frame_metadata::record_read(emulator, address);
+    #[cfg(feature = "bus-observer")]
+    bus_observer::notify(emulator, address, byte, BusPhase::Read);
+    byte
+}
+
+pub fn fetch_instruction_byte(emulator: &mut Emulator, address: u16) -> u8 {
+    code_data_log::record_access(emulator, address, CDL_CODE);
+    let byte = read_bus_byte(emulator, address);
+    #[cfg(feature = "bus-observer")]
+    bus_observer::notify(emulator, address, byte, BusPhase::OpcodeFetch);
+    byte
+}
+
+fn read_bus_byte(emulator: &mut Emulator, address: u16) -> u8 {
     step_one_machine_cycle(emulator);
     let byte = mmu::read_byte(emulator, address);
 
@@ -63,15 +86,18 @@ pub fn read_byte_from_memory(emulator: &mut Emulator, address: u16) -> u8 {
     byte
 }
 
-pub fn read_word_from_memory(emulator: &mut Emulator, address: u16) -> u16 {
-    let first_byte = read_byte_from_memory(emulator, address);
-    let second_byte = read_byte_from_memory(emulator, address + 1);
+pub fn fetch_instruction_word(emulator: &mut Emulator, address: u16) -> u16 {
+    let first_byte = fetch_instruction_byte(emulator, address);
+    let second_byte = fetch_instruction_byte(emulator, address.wrapping_add(1));
     utils::as_word(first_byte, second_byte)
 }
 
 pub fn store_byte_in_memory(emulator: &mut Emulator, address: u16, byte: u8) {
     step_one_machine_cycle(emulator);
     mmu::write_byte(emulator, address, byte);
+    io_trace::record_access(emulator, IoAccessKind::Write, address, byte);
+    #[cfg(feature = "bus-observer")]
+    bus_observer::notify(emulator, address, byte, BusPhase::Write);
     
     if emulator.processor_test_mode {
         record_bus_write(emulator, address, byte);
@@ -81,7 +107,7 @@ pub fn store_byte_in_memory(emulator: &mut Emulator, address: u16, byte: u8) {
 pub fn store_word_in_memory(emulator: &mut Emulator, address: u16, word: u16) {
     let (first_byte, second_byte) = utils::as_bytes(word);
     store_byte_in_memory(emulator, address, first_byte);
-    store_byte_in_memory(emulator, address + 1, second_byte);
+    store_byte_in_memory(emulator, address.wrapping_add(1), second_byte);
 }
 
 pub fn read_from_register(cpu_state: &CpuState, register: &Register) -> u8 {
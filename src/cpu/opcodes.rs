@@ -6,13 +6,14 @@ use crate::cpu::hdma;
 use crate::cpu::interrupts;
 use crate::cpu::jumps;
 use crate::cpu::loads;
+use crate::debug_hooks;
 use crate::emulator::Emulator;
 use crate::speed_switch;
 
 fn emulate_halt_bug(cpu: &mut CpuState) {
     // Mimics halt bug behavior, which runs the instruction after HALT twice.
     if !cpu.halted && cpu.halt_bug {
-        cpu.registers.program_counter -= 1;
+        cpu.registers.program_counter = cpu.registers.program_counter.wrapping_sub(1);
         cpu.halt_bug = false;
     }
 }
@@ -265,8 +266,10 @@ fn execute_opcode(mut emulator: &mut Emulator) {
             microops::set_flag_n(&mut emulator.cpu, false);
             microops::set_flag_h(&mut emulator.cpu, false);
         },
-        0x40 =>
-            loads::load_source_register_in_destination_register(&mut emulator.cpu, Register::B, Register::B),
+        0x40 => {
+            loads::load_source_register_in_destination_register(&mut emulator.cpu, Register::B, Register::B);
+            debug_hooks::handle_breakpoint(emulator);
+        },
         0x41 =>
             loads::load_source_register_in_destination_register(&mut emulator.cpu, Register::C, Register::B),
         0x42 =>
@@ -305,8 +308,10 @@ fn execute_opcode(mut emulator: &mut Emulator) {
             loads::load_source_register_in_destination_register(&mut emulator.cpu, Register::B, Register::D),
         0x51 =>
             loads::load_source_register_in_destination_register(&mut emulator.cpu, Register::C, Register::D),
-        0x52 =>
-            loads::load_source_register_in_destination_register(&mut emulator.cpu, Register::D, Register::D),
+        0x52 => {
+            loads::load_source_register_in_destination_register(&mut emulator.cpu, Register::D, Register::D);
+            debug_hooks::handle_message(emulator);
+        },
         0x53 =>
             loads::load_source_register_in_destination_register(&mut emulator.cpu, Register::E, Register::D),
         0x54 =>
@@ -407,7 +412,7 @@ fn execute_opcode(mut emulator: &mut Emulator) {
             }
             else {
                 emulator.cpu.halted = true;
-                emulator.cpu.registers.program_counter -= 1;
+                emulator.cpu.registers.program_counter = emulator.cpu.registers.program_counter.wrapping_sub(1);
             }
         },
         0x77 => {
@@ -741,7 +746,7 @@ fn execute_opcode(mut emulator: &mut Emulator) {
         0xD2 =>
             jumps::conditional_jump_using_immediate_word(emulator, !microops::is_c_flag_set(&emulator.cpu)),
         0xD3 =>
-            handle_illegal_opcode(opcode),
+            handle_illegal_opcode(emulator),
         0xD4 =>
             jumps::conditional_call_using_immediate_word(emulator, !microops::is_c_flag_set(&emulator.cpu)),
         0xD5 =>
@@ -761,11 +766,11 @@ fn execute_opcode(mut emulator: &mut Emulator) {
         0xDA =>
             jumps::conditional_jump_using_immediate_word(emulator, microops::is_c_flag_set(&emulator.cpu)),
         0xDB =>
-            handle_illegal_opcode(opcode),
+            handle_illegal_opcode(emulator),
         0xDC =>
             jumps::conditional_call_using_immediate_word(emulator, microops::is_c_flag_set(&emulator.cpu)),
         0xDD =>
-            handle_illegal_opcode(opcode),
+            handle_illegal_opcode(emulator),
         0xDE => {
             let value = read_next_instruction_byte(emulator);
             alu::subtract_value_and_carry_from_register(&mut emulator.cpu, Register::A, value);
@@ -783,9 +788,9 @@ fn execute_opcode(mut emulator: &mut Emulator) {
             loads::load_source_register_in_memory(emulator, Register::A, address);
         },
         0xE3 =>
-            handle_illegal_opcode(opcode),
+            handle_illegal_opcode(emulator),
         0xE4 =>
-            handle_illegal_opcode(opcode),
+            handle_illegal_opcode(emulator),
         0xE5 =>
             loads::push_register_pair_to_stack(emulator, REGISTER_HL),
         0xE6 => {
@@ -818,11 +823,11 @@ fn execute_opcode(mut emulator: &mut Emulator) {
             loads::load_source_register_in_memory(emulator, Register::A, address);
         },
         0xEB =>
-            handle_illegal_opcode(opcode),
+            handle_illegal_opcode(emulator),
         0xEC =>
-            handle_illegal_opcode(opcode),
+            handle_illegal_opcode(emulator),
         0xED =>
-            handle_illegal_opcode(opcode),
+            handle_illegal_opcode(emulator),
         0xEE => {
             let value = read_next_instruction_byte(emulator);
             alu::logical_xor_with_register(&mut emulator.cpu, Register::A, value);
@@ -845,7 +850,7 @@ fn execute_opcode(mut emulator: &mut Emulator) {
             emulator.cpu.interrupts.disable_delay = 2;
         },
         0xF4 =>
-            handle_illegal_opcode(opcode),
+            handle_illegal_opcode(emulator),
         0xF5 =>
             loads::push_register_pair_to_stack(emulator, REGISTER_AF),
         0xF6 => {
@@ -881,9 +886,9 @@ fn execute_opcode(mut emulator: &mut Emulator) {
             emulator.cpu.interrupts.enable_delay = 2;
         },
         0xFC =>
-            handle_illegal_opcode(opcode),
+            handle_illegal_opcode(emulator),
         0xFD =>
-            handle_illegal_opcode(opcode),
+            handle_illegal_opcode(emulator),
         0xFE => {
             let value = read_next_instruction_byte(emulator);
             alu::compare_value_with_register(&mut emulator.cpu, Register::A, value);
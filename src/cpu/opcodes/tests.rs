@@ -1,6 +1,6 @@
 use super::*;
 use crate::cpu::{BusActivityEntry, BusActivityType};
-use crate::emulator::{initialize_screenless_emulator, Mode};
+use crate::emulator::{enable_processor_test_mode, initialize_screenless_emulator, Mode};
 use crate::mmu;
 use crate::mmu::constants::*;
 use crate::mmu::effects::empty_cartridge_effects;
@@ -405,10 +405,25 @@ fn logical_xors_register_and_register_a() {
 }
 
 #[test]
-#[should_panic(expected = "Encountered illegal opcode 0xFC")]
-fn panics_on_illegal_opcode() {
+fn locks_up_on_illegal_opcode() {
     let mut emulator: Emulator = init_emulator_with_test_instructions(vec![0xFC]);
+    emulator.cpu.interrupts.enabled = true;
+    emulator.interrupts.enabled = 0x1;
+    emulator.interrupts.flags = 0x1;
+    for _ in 0..10 {
+        step(&mut emulator);
+    }
+    assert_eq!(emulator.cpu.registers.program_counter, 0x01);
+    assert_eq!(emulator.cpu.registers.opcode, 0xFC);
+    assert_eq!(emulator.interrupts.flags, 0x1);
+}
+
+#[test]
+fn wraps_program_counter_past_end_of_address_space() {
+    let mut emulator: Emulator = init_emulator_with_test_instructions(vec![0x00]);
+    emulator.cpu.registers.program_counter = 0xFFFF;
     step(&mut emulator);
+    assert_eq!(emulator.cpu.registers.program_counter, 0x0000);
 }
 
 #[test]
@@ -1453,7 +1468,7 @@ fn toggles_cgb_double_speed_mode() {
 fn records_bus_activity_for_loading_immediate_byte_into_register_b() {
     let mut emulator = initialize_screenless_emulator();
 
-    emulator.processor_test_mode = true;
+    enable_processor_test_mode(&mut emulator);
     emulator.memory.processor_test_ram[0x00] = 0x06;
     emulator.memory.processor_test_ram[0x01] = 0xA1;
 
@@ -1477,7 +1492,7 @@ fn records_bus_activity_for_loading_immediate_byte_into_register_b() {
 fn records_bus_activity_for_jump_to_address_nn() {
     let mut emulator: Emulator = initialize_screenless_emulator();
 
-    emulator.processor_test_mode = true;
+    enable_processor_test_mode(&mut emulator);
     emulator.memory.processor_test_ram[0x00] = 0xC3;
     emulator.memory.processor_test_ram[0x01] = 0xAA;
     emulator.memory.processor_test_ram[0x02] = 0x54;
@@ -1502,4 +1517,275 @@ fn records_bus_activity_for_jump_to_address_nn() {
 
     let opcode_read = BusActivityEntry { address: 0x54AA, value: 0x0, activity_type: BusActivityType::Read };
     assert_eq!(bus_activity[3], Some(opcode_read));
-}
\ No newline at end of file
+}
+
+/*
+    Machine cycles per opcode from the SM83 opcode table, with conditional branches
+    not taken. Zero marks opcodes that aren't timed here: STOP, HALT, the CB prefix
+    and the opcodes that lock up the CPU.
+*/
+const OPCODE_MACHINE_CYCLES: [u8; 256] = [
+    1, 3, 2, 2, 1, 1, 2, 1, 5, 2, 2, 2, 1, 1, 2, 1,
+    0, 3, 2, 2, 1, 1, 2, 1, 3, 2, 2, 2, 1, 1, 2, 1,
+    2, 3, 2, 2, 1, 1, 2, 1, 2, 2, 2, 2, 1, 1, 2, 1,
+    2, 3, 2, 2, 3, 3, 3, 1, 2, 2, 2, 2, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    2, 2, 2, 2, 2, 2, 0, 2, 1, 1, 1, 1, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    2, 3, 3, 4, 3, 4, 2, 4, 2, 4, 3, 0, 3, 6, 2, 4,
+    2, 3, 3, 0, 3, 4, 2, 4, 2, 4, 3, 0, 3, 0, 2, 4,
+    3, 3, 2, 0, 0, 4, 2, 4, 4, 1, 4, 0, 0, 0, 2, 4,
+    3, 3, 2, 1, 0, 4, 2, 4, 3, 2, 4, 1, 0, 0, 2, 4
+];
+
+const ZERO_FLAG: u8 = 0x80;
+const CARRY_FLAG: u8 = 0x10;
+
+// Conditional branches, with flags that take the branch, flags that don't, and the machine cycles when taken.
+const BRANCH_TAKEN_MACHINE_CYCLES: [(u8, u8, u8, u8); 16] = [
+    (0x20, 0, ZERO_FLAG, 3), (0x28, ZERO_FLAG, 0, 3), (0x30, 0, CARRY_FLAG, 3), (0x38, CARRY_FLAG, 0, 3),
+    (0xC0, 0, ZERO_FLAG, 5), (0xC8, ZERO_FLAG, 0, 5), (0xD0, 0, CARRY_FLAG, 5), (0xD8, CARRY_FLAG, 0, 5),
+    (0xC2, 0, ZERO_FLAG, 4), (0xCA, ZERO_FLAG, 0, 4), (0xD2, 0, CARRY_FLAG, 4), (0xDA, CARRY_FLAG, 0, 4),
+    (0xC4, 0, ZERO_FLAG, 6), (0xCC, ZERO_FLAG, 0, 6), (0xD4, 0, CARRY_FLAG, 6), (0xDC, CARRY_FLAG, 0, 6)
+];
+
+fn cb_opcode_machine_cycles(cb_opcode: u8) -> u8 {
+    let uses_hl_address = cb_opcode & 0x07 == 0x06;
+    let is_bit_test = (0x40..0x80).contains(&cb_opcode);
+    match (uses_hl_address, is_bit_test) {
+        (false, _) => 2,
+        (true, true) => 3,
+        (true, false) => 4
+    }
+}
+
+// Runs one instruction with zeroed operands from address 0 and returns the T-cycles it took.
+fn measure_instruction_cycles(instruction: &[u8], flags: u8) -> u8 {
+    let mut emulator = initialize_screenless_emulator();
+    enable_processor_test_mode(&mut emulator);
+    emulator.memory.processor_test_ram[..instruction.len()].copy_from_slice(instruction);
+    step(&mut emulator);
+
+    emulator.cpu.registers.f = flags;
+    emulator.cpu.registers.stack_pointer = 0x8000;
+    emulator.cpu.registers.h = 0x90;
+    step(&mut emulator);
+    emulator.cpu.clock.instruction_clock_cycles
+}
+
+#[test]
+fn should_take_reference_cycles_for_each_opcode() {
+    let not_taken_flags = |opcode: u8| BRANCH_TAKEN_MACHINE_CYCLES.iter()
+        .find(|(branch, _, _, _)| *branch == opcode)
+        .map_or(0, |(_, _, not_taken_flags, _)| *not_taken_flags);
+
+    let mismatches: Vec<(u8, u8, u8)> = (0..=0xFF)
+        .filter(|opcode| OPCODE_MACHINE_CYCLES[*opcode as usize] != 0)
+        .map(|opcode| (opcode, OPCODE_MACHINE_CYCLES[opcode as usize] * 4, measure_instruction_cycles(&[opcode], not_taken_flags(opcode))))
+        .filter(|(_, expected, actual)| expected != actual)
+        .collect();
+
+    assert_eq!(mismatches, vec![], "(opcode, expected T-cycles, actual T-cycles)");
+}
+
+#[test]
+fn should_take_reference_cycles_when_branch_is_taken() {
+    let mismatches: Vec<(u8, u8, u8)> = BRANCH_TAKEN_MACHINE_CYCLES.iter()
+        .map(|(opcode, taken_flags, _, machine_cycles)| (*opcode, machine_cycles * 4, measure_instruction_cycles(&[*opcode], *taken_flags)))
+        .filter(|(_, expected, actual)| expected != actual)
+        .collect();
+
+    assert_eq!(mismatches, vec![], "(opcode, expected T-cycles, actual T-cycles)");
+}
+
+#[test]
+fn should_take_reference_cycles_for_each_cb_opcode() {
+    let mismatches: Vec<(u8, u8, u8)> = (0..=0xFF)
+        .map(|cb_opcode| (cb_opcode, cb_opcode_machine_cycles(cb_opcode) * 4, measure_instruction_cycles(&[0xCB, cb_opcode], 0)))
+        .filter(|(_, expected, actual)| expected != actual)
+        .collect();
+
+    assert_eq!(mismatches, vec![], "(CB opcode, expected T-cycles, actual T-cycles)");
+}
+
+const SUBTRACT_FLAG: u8 = 0x40;
+const HALF_CARRY_FLAG: u8 = 0x20;
+
+fn init_processor_test_emulator() -> Emulator {
+    let mut emulator = initialize_screenless_emulator();
+    enable_processor_test_mode(&mut emulator);
+    emulator
+}
+
+// Executes the instruction at address 0 as if it had just been prefetched, so one emulator can run it many times.
+fn execute_instruction(emulator: &mut Emulator, instruction: &[u8]) {
+    emulator.memory.processor_test_ram[..instruction.len()].copy_from_slice(instruction);
+    emulator.cpu.registers.opcode = instruction[0];
+    emulator.cpu.registers.program_counter = 1;
+    step(emulator);
+}
+
+// DAA as SameBoy implements it, which matches hardware for every input, returning A and F.
+fn reference_daa(a: u8, flags: u8) -> (u8, u8) {
+    let mut result = a as i16;
+    if flags & SUBTRACT_FLAG != 0 {
+        if flags & HALF_CARRY_FLAG != 0 {
+            result = (result - 0x06) & 0xFF;
+        }
+        if flags & CARRY_FLAG != 0 {
+            result -= 0x60;
+        }
+    }
+    else {
+        if flags & HALF_CARRY_FLAG != 0 || (result & 0x0F) > 0x09 {
+            result += 0x06;
+        }
+        if flags & CARRY_FLAG != 0 || result > 0x9F {
+            result += 0x60;
+        }
+    }
+
+    let mut result_flags = flags & (SUBTRACT_FLAG | CARRY_FLAG);
+    if result & 0xFF == 0 {
+        result_flags |= ZERO_FLAG;
+    }
+    if result & 0x100 != 0 {
+        result_flags |= CARRY_FLAG;
+    }
+    (result as u8, result_flags)
+}
+
+#[test]
+fn should_adjust_every_value_like_hardware_daa() {
+    let mut emulator = init_processor_test_emulator();
+    let mut mismatches = Vec::new();
+
+    for a in 0..=0xFF {
+        for flags in (0..=0xF0).step_by(0x10) {
+            emulator.cpu.registers.a = a;
+            emulator.cpu.registers.f = flags;
+            execute_instruction(&mut emulator, &[0x27]);
+
+            let actual = (emulator.cpu.registers.a, emulator.cpu.registers.f);
+            if actual != reference_daa(a, flags) {
+                mismatches.push((a, flags, actual));
+            }
+        }
+    }
+
+    assert_eq!(mismatches, vec![], "(A, F before, A and F after)");
+}
+
+#[test]
+fn should_set_flags_from_low_byte_when_adding_offset_to_stack_pointer() {
+    let mut emulator = init_processor_test_emulator();
+    let mut mismatches = Vec::new();
+
+    for stack_pointer in (0x0000..=0x00FFu16).chain(0xFF00..=0xFFFF) {
+        for offset in 0..=0xFF {
+            let expected_sum = stack_pointer.wrapping_add_signed(offset as i8 as i16);
+            let mut expected_flags = 0;
+            if (stack_pointer & 0xF) + (offset as u16 & 0xF) > 0xF {
+                expected_flags |= HALF_CARRY_FLAG;
+            }
+            if (stack_pointer & 0xFF) + offset as u16 > 0xFF {
+                expected_flags |= CARRY_FLAG;
+            }
+
+            // ADD SP, r8
+            emulator.cpu.registers.stack_pointer = stack_pointer;
+            emulator.cpu.registers.f = ZERO_FLAG | SUBTRACT_FLAG;
+            execute_instruction(&mut emulator, &[0xE8, offset as u8]);
+            let add_result = (emulator.cpu.registers.stack_pointer, emulator.cpu.registers.f);
+
+            // LD HL, SP + r8
+            emulator.cpu.registers.stack_pointer = stack_pointer;
+            emulator.cpu.registers.f = ZERO_FLAG | SUBTRACT_FLAG;
+            execute_instruction(&mut emulator, &[0xF8, offset as u8]);
+            let load_result = (microops::read_from_register_pair(&mut emulator.cpu, &REGISTER_HL), emulator.cpu.registers.f);
+
+            if add_result != (expected_sum, expected_flags) || load_result != (expected_sum, expected_flags) {
+                mismatches.push((stack_pointer, offset, add_result, load_result));
+            }
+        }
+    }
+
+    assert_eq!(mismatches, vec![], "(SP, offset, ADD SP result and flags, LD HL result and flags)");
+}
+
+// RLA, RRA and RL/RR on every register and (HL), with whether they rotate left and whether Z can be set.
+const ROTATE_THROUGH_CARRY_INSTRUCTIONS: [(&[u8], bool, bool); 4] = [
+    (&[0x17], true, false),
+    (&[0x1F], false, false),
+    (&[0xCB, 0x10], true, true),
+    (&[0xCB, 0x18], false, true)
+];
+
+#[test]
+fn should_rotate_every_value_through_carry() {
+    let mut emulator = init_processor_test_emulator();
+    let mut mismatches = Vec::new();
+
+    for (instruction, left, sets_zero) in ROTATE_THROUGH_CARRY_INSTRUCTIONS {
+        let targets = if instruction.len() == 1 { 7..=7 } else { 0..=7 };
+
+        for target in targets {
+            let instruction: Vec<u8> = instruction.iter().enumerate()
+                .map(|(index, byte)| if index == 1 { byte + target } else { *byte })
+                .collect();
+
+            for value in 0..=0xFF {
+                for carry in [0, CARRY_FLAG] {
+                    let carry_in = (carry != 0) as u8;
+                    let (expected_value, carry_out) = if left {
+                        (value << 1 | carry_in, value & 0x80 != 0)
+                    }
+                    else {
+                        (carry_in << 7 | value >> 1, value & 0x01 != 0)
+                    };
+                    let mut expected_flags = if carry_out { CARRY_FLAG } else { 0 };
+                    if sets_zero && expected_value == 0 {
+                        expected_flags |= ZERO_FLAG;
+                    }
+
+                    emulator.cpu.registers.h = 0x90;
+                    emulator.cpu.registers.l = 0x00;
+                    match target {
+                        0 => emulator.cpu.registers.b = value,
+                        1 => emulator.cpu.registers.c = value,
+                        2 => emulator.cpu.registers.d = value,
+                        3 => emulator.cpu.registers.e = value,
+                        4 => emulator.cpu.registers.h = value,
+                        5 => emulator.cpu.registers.l = value,
+                        6 => emulator.memory.processor_test_ram[0x9000] = value,
+                        _ => emulator.cpu.registers.a = value
+                    }
+                    emulator.cpu.registers.f = carry | ZERO_FLAG | SUBTRACT_FLAG | HALF_CARRY_FLAG;
+                    execute_instruction(&mut emulator, &instruction);
+
+                    let actual_value = match target {
+                        0 => emulator.cpu.registers.b,
+                        1 => emulator.cpu.registers.c,
+                        2 => emulator.cpu.registers.d,
+                        3 => emulator.cpu.registers.e,
+                        4 => emulator.cpu.registers.h,
+                        5 => emulator.cpu.registers.l,
+                        6 => emulator.memory.processor_test_ram[0x9000],
+                        _ => emulator.cpu.registers.a
+                    };
+                    let actual = (actual_value, emulator.cpu.registers.f);
+                    if actual != (expected_value, expected_flags) {
+                        mismatches.push((instruction.clone(), value, carry, actual));
+                    }
+                }
+            }
+        }
+    }
+
+    assert_eq!(mismatches, vec![], "(instruction, value, carry flag, value and flags after)");
+}
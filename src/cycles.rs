@@ -0,0 +1,106 @@
+use crate::utils::get_t_cycle_increment;
+
+/*
+    Conversions between the units frontends and tools deal with. T-cycles are
+    counted at the base 4.19MHz clock, which the PPU and APU keep running at in
+    CGB double speed mode, so a frame is always 70224 T-cycles. Double speed only
+    changes how many T-cycles an M-cycle (one CPU bus access) takes, from 4 to 2.
+*/
+pub const T_CYCLES_PER_SECOND: u32 = 4194304;
+pub const T_CYCLES_PER_FRAME: u32 = 70224;
+
+pub fn t_cycles_per_m_cycle(double_speed: bool) -> u32 {
+    get_t_cycle_increment(double_speed) as u32
+}
+
+pub fn m_cycles_to_t_cycles(m_cycles: u64, double_speed: bool) -> u64 {
+    m_cycles * t_cycles_per_m_cycle(double_speed) as u64
+}
+
+// Rounds down to whole M-cycles.
+pub fn t_cycles_to_m_cycles(t_cycles: u64, double_speed: bool) -> u64 {
+    t_cycles / t_cycles_per_m_cycle(double_speed) as u64
+}
+
+pub fn frames_to_t_cycles(frames: u64) -> u64 {
+    frames * T_CYCLES_PER_FRAME as u64
+}
+
+pub fn t_cycles_to_frames(t_cycles: u64) -> f64 {
+    t_cycles as f64 / T_CYCLES_PER_FRAME as f64
+}
+
+pub fn t_cycles_to_millis(t_cycles: u64) -> f64 {
+    t_cycles as f64 * 1000.0 / T_CYCLES_PER_SECOND as f64
+}
+
+// The APU produces samples at exactly the sample rate, so sample intervals aren't a whole number of T-cycles.
+pub fn t_cycles_per_sample(sample_rate: u32) -> f64 {
+    T_CYCLES_PER_SECOND as f64 / sample_rate.max(1) as f64
+}
+
+// Samples produced over this many T-cycles, counting from when the sample rate was set.
+pub fn t_cycles_to_samples(t_cycles: u64, sample_rate: u32) -> u64 {
+    t_cycles * sample_rate as u64 / T_CYCLES_PER_SECOND as u64
+}
+
+// Rounds up to the T-cycle the last of the samples is produced on.
+pub fn samples_to_t_cycles(samples: u64, sample_rate: u32) -> u64 {
+    (samples * T_CYCLES_PER_SECOND as u64).div_ceil(sample_rate.max(1) as u64)
+}
+
+pub fn samples_per_frame(sample_rate: u32) -> f64 {
+    T_CYCLES_PER_FRAME as f64 / t_cycles_per_sample(sample_rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::apu;
+    use crate::emulator::{self, initialize_screenless_emulator};
+    use super::*;
+
+    #[test]
+    fn should_halve_m_cycle_length_in_double_speed() {
+        assert_eq!(m_cycles_to_t_cycles(10, false), 40);
+        assert_eq!(m_cycles_to_t_cycles(10, true), 20);
+        assert_eq!(t_cycles_to_m_cycles(T_CYCLES_PER_FRAME as u64, false), 17556);
+        assert_eq!(t_cycles_to_m_cycles(T_CYCLES_PER_FRAME as u64, true), 35112);
+        assert_eq!(frames_to_t_cycles(60), 4213440);
+    }
+
+    #[test]
+    fn should_convert_between_samples_and_t_cycles_at_exact_rate() {
+        assert_eq!(t_cycles_to_samples(T_CYCLES_PER_SECOND as u64, 44100), 44100);
+        assert_eq!(samples_to_t_cycles(48000, 48000), T_CYCLES_PER_SECOND as u64);
+        // 100 samples at 48kHz take 8738.13 T-cycles.
+        assert_eq!(samples_to_t_cycles(100, 48000), 8739);
+        assert!((samples_per_frame(48000) - 803.6).abs() < 0.1);
+    }
+
+    #[test]
+    fn should_run_cycle_budget_and_report_overshoot() {
+        let mut emulator = initialize_screenless_emulator();
+
+        let cycles_run = emulator::run_cycles(&mut emulator, 1001);
+
+        assert!(cycles_run.t_cycles >= 1001);
+        assert_eq!(cycles_run.overshoot, cycles_run.t_cycles - 1001);
+        // No instruction takes longer than 24 T-cycles.
+        assert!(cycles_run.overshoot < 24);
+        assert_eq!(emulator::run_cycles(&mut emulator, 0).t_cycles, 0);
+    }
+
+    #[test]
+    fn should_match_samples_produced_by_apu() {
+        let mut emulator = initialize_screenless_emulator();
+        emulator.apu.enabled = true;
+        emulator::set_sample_rate(&mut emulator, 44100);
+
+        let t_cycles = frames_to_t_cycles(1);
+        for _ in 0..t_cycles_to_m_cycles(t_cycles, false) {
+            apu::step(&mut emulator);
+        }
+
+        assert_eq!(apu::get_left_sample_queue(&emulator).len() as u64, t_cycles_to_samples(t_cycles, 44100));
+    }
+}
@@ -0,0 +1,154 @@
+use crate::cpu;
+use crate::emulator::Emulator;
+use crate::mmu;
+
+/*
+    Homebrew debugging conventions popularized by BGB and no$gmb. "ld b,b" is a
+    software breakpoint, which pauses the emulator right after the instruction.
+    "ld d,d" logs a message embedded in the code right after it:
+
+        ld d,d
+        jr .end
+        dw $6464
+        dw $0000
+        db "message"
+    .end:
+
+    Both are no-ops on hardware and commercial games occasionally contain them, so
+    the hooks are off unless a developer turns them on.
+*/
+#[derive(Debug, Default)]
+pub struct DebugHookState {
+    pub enabled: bool,
+    pub breakpoint_address: Option<u16>,
+    pub messages: Vec<String>
+}
+
+const MESSAGE_JUMP_OPCODE: u8 = 0x18;
+const MESSAGE_SIGNATURE: [u8; 4] = [0x64, 0x64, 0x00, 0x00];
+const MESSAGE_HEADER_LENGTH: u8 = 4;
+const MAX_PENDING_MESSAGES: usize = 256;
+
+pub fn initialize_debug_hooks() -> DebugHookState {
+    DebugHookState::default()
+}
+
+pub fn set_debug_hooks_enabled(emulator: &mut Emulator, enabled: bool) {
+    emulator.debug_hooks.enabled = enabled;
+}
+
+pub fn take_debug_messages(emulator: &mut Emulator) -> Vec<String> {
+    std::mem::take(&mut emulator.debug_hooks.messages)
+}
+
+#[cfg(any(test, feature = "internals"))]
+pub fn take_breakpoint_address(emulator: &mut Emulator) -> Option<u16> {
+    emulator.debug_hooks.breakpoint_address.take()
+}
+
+pub fn handle_breakpoint(emulator: &mut Emulator) {
+    if emulator.debug_hooks.enabled {
+        emulator.debug_hooks.breakpoint_address = Some(cpu::current_instruction_address(emulator));
+        emulator.pause.paused = true;
+    }
+}
+
+fn read_message(emulator: &mut Emulator) -> Option<String> {
+    let address = emulator.cpu.registers.program_counter;
+
+    if mmu::read_byte(emulator, address) != MESSAGE_JUMP_OPCODE {
+        return None;
+    }
+
+    let jump_length = mmu::read_byte(emulator, address.wrapping_add(1));
+    let message_start = address.wrapping_add(2);
+
+    let signature: Vec<u8> = (0..MESSAGE_HEADER_LENGTH as u16)
+        .map(|offset| mmu::read_byte(emulator, message_start.wrapping_add(offset)))
+        .collect();
+
+    if signature != MESSAGE_SIGNATURE || jump_length < MESSAGE_HEADER_LENGTH {
+        return None;
+    }
+
+    let text: Vec<u8> = (MESSAGE_HEADER_LENGTH as u16..jump_length as u16)
+        .map(|offset| mmu::read_byte(emulator, message_start.wrapping_add(offset)))
+        .collect();
+
+    Some(String::from_utf8_lossy(&text).into_owned())
+}
+
+pub fn handle_message(emulator: &mut Emulator) {
+    if emulator.debug_hooks.enabled {
+        if let Some(message) = read_message(emulator) {
+            let messages = &mut emulator.debug_hooks.messages;
+            if messages.len() >= MAX_PENDING_MESSAGES {
+                messages.remove(0);
+            }
+            messages.push(message);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::{self, enable_processor_test_mode, initialize_screenless_emulator};
+    use crate::pause;
+    use super::*;
+
+    fn setup_emulator(program: &[u8]) -> Emulator {
+        let mut emulator = initialize_screenless_emulator();
+        enable_processor_test_mode(&mut emulator);
+        emulator.memory.processor_test_ram[0x100..0x100 + program.len()].copy_from_slice(program);
+        emulator.cpu.registers.program_counter = 0x101;
+        emulator.cpu.registers.opcode = program[0];
+        emulator
+    }
+
+    fn message_program(text: &str) -> Vec<u8> {
+        let mut program = vec![0x52, MESSAGE_JUMP_OPCODE, (text.len() + 4) as u8];
+        program.extend_from_slice(&MESSAGE_SIGNATURE);
+        program.extend_from_slice(text.as_bytes());
+        program.push(0x00);
+        program
+    }
+
+    #[test]
+    fn should_ignore_debug_opcodes_when_disabled() {
+        let mut emulator = setup_emulator(&[0x40, 0x00]);
+        emulator::step(&mut emulator);
+        assert!(!pause::is_paused(&emulator));
+        assert_eq!(take_breakpoint_address(&mut emulator), None);
+    }
+
+    #[test]
+    fn should_pause_on_software_breakpoint() {
+        let mut emulator = setup_emulator(&[0x40, 0x00, 0x00]);
+        set_debug_hooks_enabled(&mut emulator, true);
+        emulator::step(&mut emulator);
+        assert!(pause::is_paused(&emulator));
+        assert_eq!(take_breakpoint_address(&mut emulator), Some(0x100));
+
+        pause::resume(&mut emulator);
+        emulator::step(&mut emulator);
+        assert_eq!(emulator.cpu.registers.program_counter, 0x103);
+    }
+
+    #[test]
+    fn should_log_embedded_debug_message() {
+        let mut emulator = setup_emulator(&message_program("Hello"));
+        set_debug_hooks_enabled(&mut emulator, true);
+        emulator::step(&mut emulator);
+        emulator::step(&mut emulator);
+        assert_eq!(take_debug_messages(&mut emulator), vec!["Hello".to_string()]);
+        assert_eq!(emulator.cpu.registers.program_counter, 0x100 + 13);
+    }
+
+    #[test]
+    fn should_not_log_message_without_signature() {
+        let mut emulator = setup_emulator(&[0x52, 0x00, 0x00]);
+        set_debug_hooks_enabled(&mut emulator, true);
+        emulator::step(&mut emulator);
+        assert!(take_debug_messages(&mut emulator).is_empty());
+    }
+}
@@ -0,0 +1,100 @@
+use serde_json::{json, Map, Value};
+
+use crate::emulator::{is_cgb, Emulator};
+use crate::mmu;
+
+/*
+    A readable dump of the emulator's state for web debugger UIs and bug reports,
+    which is far easier to attach to an issue and compare by eye than a binary
+    savestate. It covers what's usually needed to tell where a game went wrong:
+    the CPU registers, every I/O register as the CPU would read it, the banks that
+    are mapped in and the timers. Memory isn't included.
+
+    Only built with the "debug-json" feature, to keep serde_json out of default builds.
+*/
+fn cpu_json(emulator: &Emulator) -> Value {
+    let registers = &emulator.cpu.registers;
+    json!({
+        "a": registers.a,
+        "f": registers.f,
+        "b": registers.b,
+        "c": registers.c,
+        "d": registers.d,
+        "e": registers.e,
+        "h": registers.h,
+        "l": registers.l,
+        "sp": registers.stack_pointer,
+        "pc": registers.program_counter,
+        "opcode": registers.opcode,
+        "ime": emulator.cpu.interrupts.enabled,
+        "halted": emulator.cpu.halted,
+        "double_speed": emulator.speed_switch.cgb_double_speed,
+        "total_clock_cycles": emulator.cpu.clock.total_clock_cycles
+    })
+}
+
+// Keyed by address in hex, so the keys sort in address order.
+fn io_registers_json(emulator: &Emulator) -> Value {
+    let mut registers: Map<String, Value> = (0xFF00..=0xFF7F)
+        .map(|address| (format!("{:04X}", address), json!(mmu::read_io_register(emulator, address))))
+        .collect();
+    registers.insert("FFFF".to_string(), json!(emulator.interrupts.enabled));
+    Value::Object(registers)
+}
+
+fn banking_json(emulator: &Emulator) -> Value {
+    let mapper = &emulator.memory.cartridge_mapper;
+    json!({
+        "cartridge_type": mapper.get_cartridge().header.type_code,
+        "rom_bank": mapper.get_rom_bank(),
+        "ram_bank": mapper.get_ram_bank(),
+        "wram_bank": mmu::get_working_ram_bank(emulator),
+        "vram_bank": if is_cgb(emulator) { emulator.gpu.registers.cgb_vbk & 1 } else { 0 },
+        "boot_rom_mapped": emulator.memory.in_bios
+    })
+}
+
+fn timers_json(emulator: &Emulator) -> Value {
+    let timers = &emulator.timers;
+    json!({
+        "div": timers.divider,
+        "tima": timers.counter,
+        "tma": timers.modulo,
+        "tac": timers.control
+    })
+}
+
+pub fn debug_json(emulator: &Emulator) -> String {
+    let state = json!({
+        "model": format!("{:?}", emulator.model),
+        "frame": emulator.gpu.frame_count,
+        "cpu": cpu_json(emulator),
+        "io_registers": io_registers_json(emulator),
+        "banking": banking_json(emulator),
+        "timers": timers_json(emulator)
+    });
+    serde_json::to_string_pretty(&state).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::builder::EmulatorBuilder;
+    use crate::mmu::constants::*;
+    use crate::mmu::test_utils::*;
+    use super::*;
+
+    #[test]
+    fn should_dump_registers_and_banks() {
+        let rom = build_rom(CART_TYPE_MBC1, ROM_SIZE_64KB, RAM_SIZE_0KB);
+        let (mut emulator, _) = EmulatorBuilder::new().with_rom(&rom).skip_boot_rom().build().unwrap();
+        emulator.gpu.registers.scx = 0x12;
+
+        let state: Value = serde_json::from_str(&debug_json(&emulator)).unwrap();
+
+        assert_eq!(state["cpu"]["pc"], 0x100);
+        assert_eq!(state["io_registers"]["FF43"], 0x12);
+        assert_eq!(state["io_registers"].as_object().unwrap().len(), 129);
+        assert_eq!(state["banking"]["rom_bank"], 1);
+        assert_eq!(state["banking"]["cartridge_type"], CART_TYPE_MBC1);
+    }
+}
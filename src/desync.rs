@@ -0,0 +1,98 @@
+use crate::emulator::{self, Emulator};
+use crate::savestate::encode_state;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Desync {
+    pub instructions_executed: u64,
+    pub total_clock_cycles: u32,
+    pub program_counter: u16,
+    pub state_offset: usize,
+    pub expected: Option<u8>,
+    pub actual: Option<u8>
+}
+
+fn find_state_difference(expected: &[u8], actual: &[u8]) -> Option<(usize, Option<u8>, Option<u8>)> {
+    let length = expected.len().max(actual.len());
+    (0..length)
+        .find(|index| expected.get(*index) != actual.get(*index))
+        .map(|index| (index, expected.get(index).copied(), actual.get(index).copied()))
+}
+
+/*
+    Runs two emulators in lockstep and compares their serialized states after
+    every instruction, which makes timing refactors reviewable by running the
+    old and new implementations side by side. Both emulators must have the same
+    cartridge loaded. Returns the first point where the states diverge, with the
+    clock and program counter taken from the reference emulator.
+*/
+pub fn find_desync(reference: &mut Emulator, candidate: &mut Emulator, max_instructions: u64) -> Option<Desync> {
+    for instructions_executed in 0..=max_instructions {
+        if instructions_executed > 0 {
+            emulator::step(reference);
+            emulator::step(candidate);
+        }
+
+        let expected_state = encode_state(reference);
+        let actual_state = encode_state(candidate);
+
+        if let Some((state_offset, expected, actual)) = find_state_difference(&expected_state, &actual_state) {
+            return Some(Desync {
+                instructions_executed,
+                total_clock_cycles: reference.cpu.clock.total_clock_cycles,
+                program_counter: reference.cpu.registers.program_counter,
+                state_offset,
+                expected,
+                actual
+            });
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::builder::EmulatorBuilder;
+    use crate::mmu::constants::*;
+    use crate::mmu::test_utils::*;
+    use super::*;
+
+    fn build_emulator(rom: &[u8]) -> Emulator {
+        let (emulator, _) = EmulatorBuilder::new()
+            .with_rom(rom)
+            .skip_boot_rom()
+            .build()
+            .unwrap();
+        emulator
+    }
+
+    #[test]
+    fn should_not_report_desync_for_identical_emulators() {
+        let rom = build_rom(CART_TYPE_MBC1, ROM_SIZE_64KB, RAM_SIZE_0KB);
+        let mut reference = build_emulator(&rom);
+        let mut candidate = build_emulator(&rom);
+        assert_eq!(find_desync(&mut reference, &mut candidate, 100), None);
+    }
+
+    #[test]
+    fn should_report_first_diverging_instruction() {
+        let rom = build_rom(CART_TYPE_MBC1, ROM_SIZE_64KB, RAM_SIZE_0KB);
+        let mut modified_rom = rom.clone();
+        // INC A in place of the sixth NOP.
+        modified_rom[0x105] = 0x3C;
+
+        let mut reference = build_emulator(&rom);
+        let mut candidate = build_emulator(&modified_rom);
+
+        let desync = find_desync(&mut reference, &mut candidate, 100).unwrap();
+        assert_eq!(desync.instructions_executed, 6);
+        assert_eq!(desync.program_counter, 0x106);
+    }
+
+    #[test]
+    fn should_find_difference_in_state_length() {
+        assert_eq!(find_state_difference(&[1, 2], &[1, 2]), None);
+        assert_eq!(find_state_difference(&[1, 2], &[1, 3]), Some((1, Some(2), Some(3))));
+        assert_eq!(find_state_difference(&[1, 2], &[1, 2, 3]), Some((2, None, Some(3))));
+    }
+}
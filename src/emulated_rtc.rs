@@ -0,0 +1,127 @@
+use crate::clock::{ManualClock, SharedClock};
+use crate::cycles::t_cycles_to_millis;
+use crate::emulator::{self, Emulator};
+
+/*
+    Cartridge clocks normally follow the host's time, so a game's clock ages by
+    however long the session took in real time, whether it was fast-forwarded,
+    paused or replayed from a recording. In emulated time, the clock only moves as
+    the console runs, optionally sped up, so a TAS or a fast-forwarded session sees
+    the same in-game time on every run.
+
+    The emulated clock starts from whatever time the previous clock showed, so
+    switching modes doesn't make the RTC jump.
+*/
+#[derive(Debug)]
+pub struct EmulatedRtcState {
+    clock: Option<ManualClock>,
+    host_clock: Option<SharedClock>,
+    pub multiplier: f64,
+    last_total_clock_cycles: u32
+}
+
+pub fn initialize_emulated_rtc() -> EmulatedRtcState {
+    EmulatedRtcState {
+        clock: None,
+        host_clock: None,
+        multiplier: 1.0,
+        last_total_clock_cycles: 0
+    }
+}
+
+pub fn is_enabled(emulator: &Emulator) -> bool {
+    emulator.emulated_rtc.clock.is_some()
+}
+
+// The clock emulated time was switched away from, or the current one when it's off.
+pub fn host_clock(emulator: &Emulator) -> SharedClock {
+    emulator.emulated_rtc.host_clock.clone().unwrap_or_else(|| emulator.clock.clone())
+}
+
+// Advances the RTC by emulated time times the multiplier, or goes back to the previous clock when None.
+pub fn set_emulated_rtc(emulator: &mut Emulator, multiplier: Option<f64>) {
+    match multiplier {
+        Some(multiplier) => {
+            emulator.emulated_rtc.multiplier = multiplier.max(0.0);
+            if !is_enabled(emulator) {
+                let clock = ManualClock::new(emulator.clock.current_time_millis());
+                emulator.emulated_rtc.host_clock = Some(emulator.clock.clone());
+                emulator.emulated_rtc.clock = Some(clock.clone());
+                sync_clock_reference(emulator);
+                emulator::set_clock(emulator, clock.shared());
+            }
+        },
+        None => {
+            emulator.emulated_rtc.clock = None;
+            if let Some(host_clock) = emulator.emulated_rtc.host_clock.take() {
+                emulator::set_clock(emulator, host_clock);
+            }
+        }
+    }
+}
+
+// Loading a savestate rewinds the CPU clock, which must not move the RTC backwards or forwards.
+pub fn sync_clock_reference(emulator: &mut Emulator) {
+    emulator.emulated_rtc.last_total_clock_cycles = emulator.cpu.clock.total_clock_cycles;
+}
+
+pub fn step(emulator: &mut Emulator) {
+    let total_clock_cycles = emulator.cpu.clock.total_clock_cycles;
+    let state = &mut emulator.emulated_rtc;
+
+    if let Some(clock) = &state.clock {
+        let elapsed_cycles = total_clock_cycles.wrapping_sub(state.last_total_clock_cycles);
+        clock.advance_millis(t_cycles_to_millis(elapsed_cycles as u64) * state.multiplier);
+        state.last_total_clock_cycles = total_clock_cycles;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::Core;
+    use crate::cycles::{T_CYCLES_PER_FRAME, T_CYCLES_PER_SECOND};
+    use crate::mmu::constants::*;
+    use crate::mmu::test_utils::*;
+    use super::*;
+
+    fn setup_emulator_with_clock(clock: &ManualClock) -> Emulator {
+        let mut emulator = setup_emulator(CART_TYPE_MBC3_TIMER_RAM_BATTERY, RAM_SIZE_8KB);
+        emulator::set_clock(&mut emulator, clock.shared());
+        emulator
+    }
+
+    fn frames_per_second() -> u32 {
+        T_CYCLES_PER_SECOND / T_CYCLES_PER_FRAME + 1
+    }
+
+    #[test]
+    fn should_advance_rtc_with_emulated_time_only() {
+        let host_clock = ManualClock::new(1000.0);
+        let mut emulator = setup_emulator_with_clock(&host_clock);
+        set_emulated_rtc(&mut emulator, Some(60.0));
+
+        host_clock.advance_millis(3_600_000.0);
+        for _ in 0..frames_per_second() {
+            emulator.run_frame();
+        }
+
+        let elapsed = emulator.clock.current_time_millis() - 1000.0;
+        assert!((60_000.0..61_000.0).contains(&elapsed), "{}", elapsed);
+        let rtc_state = emulator.memory.cartridge_mapper.get_rtc_state().unwrap();
+        assert_eq!(rtc_state.base_timestamp, 1000.0);
+    }
+
+    #[test]
+    fn should_go_back_to_host_clock_when_disabled() {
+        let host_clock = ManualClock::new(1000.0);
+        let mut emulator = setup_emulator_with_clock(&host_clock);
+        set_emulated_rtc(&mut emulator, Some(1.0));
+        emulator.run_frame();
+        set_emulated_rtc(&mut emulator, None);
+
+        host_clock.advance_millis(500.0);
+
+        assert!(!is_enabled(&emulator));
+        assert_eq!(emulator.clock.current_time_millis(), 1500.0);
+    }
+}
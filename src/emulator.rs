@@ -1,23 +1,70 @@
+use crate::accuracy::{self, initialize_accuracy, AccuracySettings};
 use crate::apu;
 use crate::apu::{initialize_apu, ApuState};
+#[cfg(feature = "bus-observer")]
+use crate::bus_observer::BusObserver;
+use crate::breakpoints::{self, initialize_breakpoints, BreakpointState};
+use crate::call_stack::{initialize_call_stack, CallStackState};
 use crate::cheats::{initialize_cheats, CheatState};
+use crate::clock::{system_clock, SharedClock};
+use crate::code_data_log::{initialize_code_data_log, CodeDataLogState};
 use crate::cpu::{self, initialize_cpu, timers, CpuState};
 use crate::cpu::interrupts::InterruptRegisters;
 use crate::cpu::timers::TimerRegisters;
 use crate::cpu::hdma::{HDMAState, initialize_hdma};
+use crate::debug_hooks::{initialize_debug_hooks, DebugHookState};
 use crate::dma;
+use crate::emulated_rtc::{self, initialize_emulated_rtc, EmulatedRtcState};
 use crate::dma::{initialize_dma, DMAState};
-use crate::gpu::{self, initialize_gpu, GpuState};
+use crate::frame_metadata::{self, initialize_frame_metadata, FrameMetadataState};
+use crate::gpu::{self, initialize_gpu, GpuState, LcdListener, NoopLcdListener};
+use crate::hle_boot::{self, initialize_hle_boot, HleBootState};
+use crate::input_polling::{self, initialize_input_polling, InputPollingState};
 use crate::keys::{initialize_keys, KeyState};
 use crate::mmu;
 use crate::mmu::{Memory, initialize_memory};
+use crate::overclock::{self, initialize_overclock, OverclockState};
+use crate::overlay::{initialize_overlay, OverlayState};
+use crate::pause::{self, initialize_pause, PauseState};
+use crate::profiler::{self, initialize_profiler, ProfilerState};
+use crate::autosave::{initialize_autosave, AutosaveState};
+use crate::replay::{self, initialize_replay, ReplayState};
+use crate::reverse_step::{self, initialize_reverse_step, ReverseStepState};
+use crate::rom_database::{self, initialize_rom_database, RomDatabaseState};
+use crate::rumble::{initialize_rumble, RumbleState};
+use crate::sensors::{self, initialize_sensors, SensorState};
 use crate::serial::{self, initialize_serial, SerialState};
 use crate::speed_switch::{initialize_speed_switch, SpeedSwitch};
-use std::cell::{Ref, RefMut};
+use crate::stats::{self, initialize_stats, EmulatorStats, StatsState};
+use crate::symbols::{initialize_symbols, SymbolTable};
+use crate::unmapped_writes::{initialize_unmapped_writes, UnmappedWriteState};
+use crate::io_trace::{initialize_io_trace, IoTraceState};
+use crate::game_config::{initialize_game_config, GameConfigState};
+use crate::watches::{initialize_watches, WatchState};
+use std::cell::RefMut;
+#[cfg(feature = "internals")]
+use std::cell::Ref;
 use std::io;
 
+pub use crate::accuracy::AccuracyProfile;
+pub use crate::apu::filter::HighPassFilter;
+pub use crate::frame_metadata::FrameMetadata;
+pub use crate::gpu::colors::ColorCorrection;
+#[cfg(feature = "internals")]
+pub use crate::gpu::FrameFormat;
+pub use crate::gpu::scanline_registers::ScanlineRegisters;
+pub use crate::hle_boot::HleBootOptions;
+pub use crate::input_polling::InputPoller;
+pub use crate::keys::JoypadState;
 pub use crate::mmu::effects::CartridgeEffects;
 pub use crate::mmu::{CartridgeHeader, RTCState};
+pub use crate::mmu::io_registers::IoRegister;
+pub use crate::savestate::Thumbnail;
+pub use crate::serial::SerialDevice;
+pub use crate::serial::barcode_boy::BarcodeScanner;
+pub use crate::snapshot::StateSnapshot;
+pub use crate::unmapped_writes::UnmappedWrite;
+pub use crate::game_config::GameConfig;
 
 #[derive(PartialEq, Eq)]
 pub enum Mode {
@@ -25,6 +72,24 @@ pub enum Mode {
     CGB
 }
 
+/*
+    The specific hardware revision being emulated. Mode still decides whether
+    CGB features are available, while the model covers the smaller differences
+    between revisions of the same family, such as the registers left behind by
+    each boot ROM.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum HardwareModel {
+    DMG0,
+    DMG,
+    MGB,
+    SGB,
+    SGB2,
+    CGB,
+    AGB
+}
+
 pub struct Emulator {
     pub cpu: CpuState,
     pub interrupts: InterruptRegisters,
@@ -37,12 +102,53 @@ pub struct Emulator {
     pub hdma: HDMAState,
     pub serial: SerialState,
     pub cheats: CheatState,
+    pub replay: ReplayState,
+    pub overlay: OverlayState,
+    pub stats: StatsState,
+    pub rumble: RumbleState,
+    pub sensors: SensorState,
+    pub accuracy: AccuracySettings,
+    pub pause: PauseState,
+    pub debug_hooks: DebugHookState,
+    pub symbols: SymbolTable,
+    pub breakpoints: BreakpointState,
+    pub call_stack: CallStackState,
+    pub code_data_log: CodeDataLogState,
+    pub watches: WatchState,
+    pub reverse_step: ReverseStepState,
+    pub profiler: ProfilerState,
+    pub autosave: AutosaveState,
+    pub overclock: OverclockState,
+    pub unmapped_writes: UnmappedWriteState,
+    pub rom_database: RomDatabaseState,
+    pub hle_boot: HleBootState,
+    pub clock: SharedClock,
+    pub emulated_rtc: EmulatedRtcState,
+    pub input_polling: InputPollingState,
+    pub frame_metadata: FrameMetadataState,
+    pub io_trace: IoTraceState,
+    pub game_config: GameConfigState,
     pub render: fn(&[u8]),
+    pub render_scanline: Option<fn(u8, &[u8])>,
+    pub lcd_listener: Box<dyn LcdListener>,
+    #[cfg(feature = "bus-observer")]
+    pub bus_observer: Option<Box<dyn BusObserver>>,
     pub mode: Mode,
+    pub model: HardwareModel,
     pub speed_switch: SpeedSwitch,
     pub processor_test_mode: bool
 }
 
+/*
+    Emulators own all of their state and keep nothing in statics, so independent
+    instances can run side by side on separate threads (e.g. for run-ahead or two
+    linked Game Boys). Host callbacks are required to be Send to keep it that way.
+*/
+const _: fn() = || {
+    fn assert_send<T: Send>() {}
+    assert_send::<Emulator>();
+};
+
 pub fn initialize_emulator(render: fn(&[u8])) -> Emulator {
     Emulator {
         cpu: initialize_cpu(),
@@ -67,8 +173,39 @@ pub fn initialize_emulator(render: fn(&[u8])) -> Emulator {
         hdma: initialize_hdma(),
         serial: initialize_serial(),
         cheats: initialize_cheats(),
+        replay: initialize_replay(),
+        overlay: initialize_overlay(),
+        stats: initialize_stats(),
+        rumble: initialize_rumble(),
+        sensors: initialize_sensors(),
+        accuracy: initialize_accuracy(),
+        pause: initialize_pause(),
+        debug_hooks: initialize_debug_hooks(),
+        symbols: initialize_symbols(),
+        breakpoints: initialize_breakpoints(),
+        call_stack: initialize_call_stack(),
+        code_data_log: initialize_code_data_log(),
+        watches: initialize_watches(),
+        reverse_step: initialize_reverse_step(),
+        profiler: initialize_profiler(),
+        autosave: initialize_autosave(),
+        overclock: initialize_overclock(),
+        unmapped_writes: initialize_unmapped_writes(),
+        rom_database: initialize_rom_database(),
+        hle_boot: initialize_hle_boot(),
+        clock: system_clock(),
+        emulated_rtc: initialize_emulated_rtc(),
+        input_polling: initialize_input_polling(),
+        frame_metadata: initialize_frame_metadata(),
+        io_trace: initialize_io_trace(),
+        game_config: initialize_game_config(),
         render,
+        render_scanline: None,
+        lcd_listener: Box::new(NoopLcdListener),
+        #[cfg(feature = "bus-observer")]
+        bus_observer: None,
         mode: Mode::DMG,
+        model: HardwareModel::DMG,
         speed_switch: initialize_speed_switch(),
         processor_test_mode: false
     }
@@ -78,6 +215,12 @@ pub fn initialize_screenless_emulator() -> Emulator {
     initialize_emulator(|_| {})
 }
 
+#[cfg(any(test, feature = "internals"))]
+pub fn enable_processor_test_mode(emulator: &mut Emulator) {
+    emulator.processor_test_mode = true;
+    emulator.memory.processor_test_ram.resize(0x10000, 0);
+}
+
 pub fn is_cgb(emulator: &Emulator) -> bool {
     emulator.mode == Mode::CGB
 }
@@ -88,18 +231,25 @@ pub fn in_color_bios(emulator: &Emulator) -> bool {
 
 pub fn load_rom(emulator: &mut RefMut<Emulator>, rom: &[u8], cartridge_effects: Box<dyn CartridgeEffects>) -> io::Result<CartridgeHeader> {
     let buffer = rom.to_vec();
-    mmu::load_rom_buffer(&mut emulator.memory, buffer, cartridge_effects)
+    rom_database::load_rom(emulator, buffer, cartridge_effects)
 }
 
+#[cfg(feature = "internals")]
 pub fn set_cartridge_ram(emulator: &mut RefMut<Emulator>, ram: &[u8]) {
     mmu::set_cartridge_ram(&mut emulator.memory, ram.to_vec());
 }
 
+#[cfg(feature = "internals")]
 pub fn get_cartridge_ram(emulator: &Ref<Emulator>) -> Vec<u8> {
     mmu::get_cartridge_ram(&emulator.memory)
 }
 
 pub fn sync(emulator: &mut Emulator) {
+    if overclock::is_running(emulator) {
+        dma::step(emulator);
+        return;
+    }
+
     timers::step(emulator);
     dma::step(emulator);
     gpu::step(emulator);
@@ -107,23 +257,158 @@ pub fn sync(emulator: &mut Emulator) {
     serial::step(emulator);
 }
 
+pub fn as_mode(model: HardwareModel) -> Mode {
+    match model {
+        HardwareModel::CGB | HardwareModel::AGB => Mode::CGB,
+        _ => Mode::DMG
+    }
+}
+
+#[cfg(feature = "internals")]
 pub fn set_mode(emulator: &mut Emulator, mode: Mode) {
+    let model = if mode == Mode::CGB { HardwareModel::CGB } else { HardwareModel::DMG };
+    set_hardware_model(emulator, model);
+}
+
+pub fn set_hardware_model(emulator: &mut Emulator, model: HardwareModel) {
+    let mode = as_mode(model);
+    let high_pass_filter = if mode == Mode::CGB { HighPassFilter::Cgb } else { HighPassFilter::Dmg };
+    emulator.model = model;
     emulator.mode = mode;
     mmu::load_bios(emulator);
+    apu::set_high_pass_filter(emulator, high_pass_filter);
+}
+
+pub fn set_high_pass_filter(emulator: &mut Emulator, high_pass_filter: HighPassFilter) {
+    apu::set_high_pass_filter(emulator, high_pass_filter);
+}
+
+pub fn set_color_correction(emulator: &mut Emulator, color_correction: ColorCorrection) {
+    emulator.gpu.registers.palettes.color_correction = color_correction;
+}
+
+#[cfg(feature = "internals")]
+pub fn set_frame_format(emulator: &mut Emulator, frame_format: FrameFormat) {
+    gpu::set_frame_format(emulator, frame_format);
+}
+
+// Called with each line as soon as it's drawn, on top of the render callback for whole frames. See gpu.rs.
+pub fn set_scanline_render(emulator: &mut Emulator, render_scanline: Option<fn(u8, &[u8])>) {
+    emulator.render_scanline = render_scanline;
+}
+
+// Where cartridge clocks get the time from. ROMs loaded later use the same clock.
+pub fn set_clock(emulator: &mut Emulator, clock: SharedClock) {
+    emulator.clock = clock.clone();
+    emulator.memory.cartridge_mapper.set_clock(clock);
+}
+
+pub fn set_frame_hashing(emulator: &mut Emulator, enabled: bool) {
+    gpu::frame_hash::set_frame_hashing(emulator, enabled);
+}
+
+pub fn get_frame_hash(emulator: &Emulator) -> Option<u64> {
+    gpu::frame_hash::get_frame_hash(emulator)
+}
+
+pub fn get_frame_metadata(emulator: &Emulator) -> FrameMetadata {
+    frame_metadata::get_frame_metadata(emulator)
+}
+
+pub fn get_lag_frame_count(emulator: &Emulator) -> u64 {
+    frame_metadata::get_lag_frame_count(emulator)
+}
+
+pub fn get_changed_scanlines(emulator: &Emulator) -> Vec<u8> {
+    gpu::changed_scanlines::get_changed_scanlines(emulator)
+}
+
+pub fn get_scanline_registers(emulator: &Emulator) -> &[ScanlineRegisters] {
+    gpu::scanline_registers::get_scanline_registers(emulator)
+}
+
+pub fn set_accuracy_profile(emulator: &mut Emulator, profile: AccuracyProfile) {
+    accuracy::set_accuracy_profile(emulator, profile);
+}
+
+pub fn set_sprite_limit_removed(emulator: &mut Emulator, removed: bool) {
+    accuracy::set_sprite_limit_removed(emulator, removed);
+}
+
+pub fn set_extra_cpu_cycles_per_frame(emulator: &mut Emulator, cycles: u32) {
+    overclock::set_extra_cycles_per_frame(emulator, cycles);
 }
 
 pub fn set_sample_rate(emulator: &mut Emulator, sample_rate: u32) {
     apu::set_sample_rate(emulator, sample_rate);
 }
 
+pub fn set_audio_buffer_size(emulator: &mut Emulator, samples: usize) {
+    apu::set_audio_buffer_size(emulator, samples);
+}
+
+pub fn get_audio_buffer_latency_millis(emulator: &Emulator) -> f64 {
+    apu::get_audio_buffer_latency_millis(emulator)
+}
+
+pub fn get_queued_audio_millis(emulator: &Emulator) -> f64 {
+    apu::get_queued_audio_millis(emulator)
+}
+
 pub fn step(emulator: &mut Emulator) {
-    cpu::opcodes::step(emulator);
+    if pause::is_paused(emulator) || breakpoints::step(emulator) {
+        return;
+    }
+
+    input_polling::step(emulator);
+
+    let frame_count = emulator.gpu.frame_count;
+    let was_halted = emulator.cpu.halted;
+    let profiled_instruction = profiler::instruction_start(emulator);
+    if !cpu::idle_skip::step(emulator) {
+        cpu::opcodes::step(emulator);
+    }
+    profiler::step(emulator, profiled_instruction);
+    stats::step(emulator, was_halted);
+    emulated_rtc::step(emulator);
+    reverse_step::step(emulator);
+    let frame_completed = emulator.gpu.frame_count != frame_count;
+    frame_metadata::step(emulator, frame_completed);
+    hle_boot::step(emulator, frame_completed);
+    sensors::step(emulator, frame_completed);
+    replay::step(emulator, frame_completed);
+    overclock::step(emulator, frame_completed);
+    pause::step(emulator, frame_completed);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CyclesRun {
+    pub t_cycles: u64,
+    // Instructions can't be split, so the budget is usually overrun by part of one. Take it off the next budget.
+    pub overshoot: u64
+}
+
+// Runs whole instructions until at least the given number of T-cycles have passed, or the emulator pauses.
+pub fn run_cycles(emulator: &mut Emulator, t_cycles: u64) -> CyclesRun {
+    let mut elapsed = 0;
+
+    while elapsed < t_cycles && !pause::is_paused(emulator) {
+        let start_clock_cycles = emulator.cpu.clock.total_clock_cycles;
+        step(emulator);
+        let step_cycles = emulator.cpu.clock.total_clock_cycles.wrapping_sub(start_clock_cycles);
+        if step_cycles == 0 {
+            break;
+        }
+        elapsed += step_cycles as u64;
+    }
+
+    CyclesRun { t_cycles: elapsed, overshoot: elapsed.saturating_sub(t_cycles) }
 }
 
 pub fn step_until_next_audio_buffer(emulator: &mut Emulator) -> (&[f32], &[f32]) {
     apu::clear_audio_buffers(emulator);
 
-    while !apu::audio_buffers_full(emulator) {
+    while !apu::audio_buffers_full(emulator) && !pause::is_paused(emulator) {
         step(emulator);
     }
 
@@ -132,3 +417,27 @@ pub fn step_until_next_audio_buffer(emulator: &mut Emulator) -> (&[f32], &[f32])
 
     (left_samples_slice, right_samples_slice)
 }
+
+/*
+    Runs just long enough to fill both buffers, for frontends driven by the audio
+    device, like an AudioWorklet asking for 128 frames per render quantum. Unlike
+    step_until_next_audio_buffer, samples produced past the end of the buffers are
+    kept for the next call rather than dropped. While paused, whatever can't be
+    filled is silence. Returns how many samples came from the emulator.
+*/
+pub fn fill_audio_buffers(emulator: &mut Emulator, left: &mut [f32], right: &mut [f32]) -> usize {
+    let frames = left.len().min(right.len());
+
+    while apu::queued_sample_count(emulator) < frames && !pause::is_paused(emulator) {
+        step(emulator);
+    }
+
+    let filled = apu::drain_audio_samples(emulator, left, right);
+    left[filled..].fill(0.0);
+    right[filled..].fill(0.0);
+    filled
+}
+
+pub fn stats(emulator: &Emulator) -> EmulatorStats {
+    stats::get_stats(emulator)
+}
@@ -0,0 +1,120 @@
+use crate::emulator::Emulator;
+use crate::keys::{self, JoypadState, Key};
+
+const JOYP_ADDRESS: u16 = 0xFF00;
+
+/*
+    Details about the last frame the PPU finished, for frontends that record or
+    stream and want to draw an input display that matches the picture exactly. The
+    joypad state has every button that was held at any point while the frame was
+    drawn, so a press and release within one frame still shows up.
+
+    A frame is lagged when the game never read JOYP while it was drawn, usually
+    because it was still busy with the previous frame's work. Input held through
+    a lagged frame has no effect, which TAS tools need to know to place inputs.
+*/
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameMetadata {
+    // Numbered the same way as the frame_number given to input pollers.
+    pub frame_number: u64,
+    pub joypad: JoypadState,
+    pub lagged: bool
+}
+
+#[derive(Debug, Default)]
+pub struct FrameMetadataState {
+    pub last_frame: FrameMetadata,
+    pub lag_frames: u64,
+    frame_joypad_mask: u8,
+    pub joypad_read: bool
+}
+
+pub fn initialize_frame_metadata() -> FrameMetadataState {
+    FrameMetadataState::default()
+}
+
+pub fn record_press(emulator: &mut Emulator, key: Key) {
+    emulator.frame_metadata.frame_joypad_mask |= JoypadState::from_keys(&[key]).mask;
+}
+
+// Called on reads by the CPU, so debuggers peeking at JOYP don't count.
+pub fn record_read(emulator: &mut Emulator, address: u16) {
+    if address == JOYP_ADDRESS {
+        emulator.frame_metadata.joypad_read = true;
+    }
+}
+
+pub fn step(emulator: &mut Emulator, frame_completed: bool) {
+    if frame_completed {
+        let held_mask = keys::joypad_state(emulator).mask;
+        let state = &mut emulator.frame_metadata;
+        state.last_frame = FrameMetadata {
+            frame_number: emulator.gpu.frame_count - 1,
+            joypad: JoypadState { mask: state.frame_joypad_mask | held_mask },
+            lagged: !state.joypad_read
+        };
+        if !state.joypad_read {
+            state.lag_frames += 1;
+        }
+        state.frame_joypad_mask = held_mask;
+        state.joypad_read = false;
+    }
+}
+
+pub fn get_lag_frame_count(emulator: &Emulator) -> u64 {
+    emulator.frame_metadata.lag_frames
+}
+
+pub fn get_frame_metadata(emulator: &Emulator) -> FrameMetadata {
+    emulator.frame_metadata.last_frame
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::builder::EmulatorBuilder;
+    use crate::core::Core;
+    use crate::mmu::constants::*;
+    use crate::mmu::test_utils::*;
+    use super::*;
+
+    fn setup_emulator() -> Emulator {
+        let rom = build_rom(CART_TYPE_ROM_ONLY, ROM_SIZE_64KB, RAM_SIZE_0KB);
+        let (emulator, _) = EmulatorBuilder::new().with_rom(&rom).skip_boot_rom().build().unwrap();
+        emulator
+    }
+
+    #[test]
+    fn should_include_keys_held_during_frame() {
+        let mut emulator = setup_emulator();
+        emulator.run_frame();
+
+        emulator.set_button(Key::A, true);
+        emulator.set_button(Key::Left, true);
+        emulator.set_button(Key::A, false);
+        emulator.run_frame();
+
+        let metadata = get_frame_metadata(&emulator);
+        assert_eq!(metadata.frame_number, emulator.gpu.frame_count - 1);
+        assert_eq!(metadata.joypad, JoypadState::from_keys(&[Key::A, Key::Left]));
+
+        emulator.run_frame();
+        assert_eq!(get_frame_metadata(&emulator).joypad, JoypadState::from_keys(&[Key::Left]));
+    }
+
+    #[test]
+    fn should_count_frames_without_joypad_reads_as_lagged() {
+        let mut rom = build_rom(CART_TYPE_ROM_ONLY, ROM_SIZE_64KB, RAM_SIZE_0KB);
+        // LDH A, (JOYP) at the entry point, then JR to itself.
+        rom[0x100..0x104].copy_from_slice(&[0xF0, 0x00, 0x18, 0xFE]);
+        let (mut emulator, _) = EmulatorBuilder::new().with_rom(&rom).skip_boot_rom().build().unwrap();
+
+        emulator.run_frame();
+        assert!(!get_frame_metadata(&emulator).lagged);
+        assert_eq!(get_lag_frame_count(&emulator), 0);
+
+        emulator.run_frame();
+        emulator.run_frame();
+        assert!(get_frame_metadata(&emulator).lagged);
+        assert_eq!(get_lag_frame_count(&emulator), 2);
+    }
+}
@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+use std::io;
+
+use crate::accuracy::{self, AccuracyProfile, AccuracySettings};
+use crate::cheats;
+use crate::emulator::Emulator;
+use crate::overclock;
+use crate::rom_database::{self, CompatibilityPalette};
+use crate::save_slots::SaveStorage;
+
+// Kept apart from save slots, which are all named after a game's title.
+const GAME_CONFIGS_KEY: &str = "game_configs.txt";
+
+/*
+    Settings a player picked for one game, applied whenever that game is loaded so
+    they don't have to be set again each session. Like ROM database entries they're
+    keyed by the CRC32 of the whole ROM, and stored as text, one game per line:
+
+    <CRC32> [accuracy=fast|balanced] [overclock=<T-cycles>] [palette=<colors>] [gameshark=<code>,...] [gamegenie=<code>,...]
+
+    The CRC32 and palette colors are hexadecimal, the overclock is the number of
+    extra T-cycles per frame in decimal, and the palette is given as in
+    rom_database.txt. A game's palette takes precedence over its database entry.
+*/
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GameConfig {
+    pub crc32: u32,
+    pub accuracy_profile: Option<AccuracyProfile>,
+    pub extra_cpu_cycles_per_frame: Option<u32>,
+    pub palette: Option<CompatibilityPalette>,
+    pub gameshark_codes: Vec<String>,
+    pub gamegenie_codes: Vec<String>
+}
+
+pub type GameConfigs = HashMap<u32, GameConfig>;
+
+// The settings the active config replaced and the cheats it added, undone when another game is loaded.
+#[derive(Debug, Default)]
+pub struct GameConfigState {
+    pub configs: GameConfigs,
+    pub active: Option<GameConfig>,
+    replaced_accuracy: Option<AccuracySettings>,
+    replaced_overclock: Option<u32>,
+    added_cheats: Vec<String>
+}
+
+pub fn initialize_game_config() -> GameConfigState {
+    GameConfigState::default()
+}
+
+fn invalid_config_error(line_number: usize, message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Invalid game config on line {}: {}", line_number, message))
+}
+
+fn parse_accuracy_profile(text: &str, line_number: usize) -> io::Result<AccuracyProfile> {
+    match text {
+        "fast" => Ok(AccuracyProfile::Fast),
+        "balanced" => Ok(AccuracyProfile::Balanced),
+        _ => Err(invalid_config_error(line_number, &format!("unknown accuracy profile {}", text)))
+    }
+}
+
+fn as_accuracy_profile_text(profile: AccuracyProfile) -> &'static str {
+    match profile {
+        AccuracyProfile::Fast => "fast",
+        AccuracyProfile::Balanced => "balanced"
+    }
+}
+
+fn parse_codes(text: &str, line_number: usize, validate: fn(&str) -> Option<String>) -> io::Result<Vec<String>> {
+    text.split(',')
+        .map(|code| match validate(code) {
+            Some(error) => Err(invalid_config_error(line_number, &error)),
+            None => Ok(code.to_string())
+        })
+        .collect()
+}
+
+fn parse_config_line(line: &str, line_number: usize) -> io::Result<GameConfig> {
+    let mut parts = line.split_whitespace();
+    let crc32 = u32::from_str_radix(parts.next().unwrap_or_default(), 16)
+        .map_err(|_| invalid_config_error(line_number, "invalid CRC32"))?;
+    let mut config = GameConfig { crc32, ..GameConfig::default() };
+
+    for part in parts {
+        let (key, value) = part.split_once('=')
+            .ok_or_else(|| invalid_config_error(line_number, "expected key=value"))?;
+        match key {
+            "accuracy" => config.accuracy_profile = Some(parse_accuracy_profile(value, line_number)?),
+            "overclock" => config.extra_cpu_cycles_per_frame = Some(value.parse()
+                .map_err(|_| invalid_config_error(line_number, "invalid overclock"))?),
+            "palette" => config.palette = Some(rom_database::parse_palette(value, line_number)
+                .map_err(|_| invalid_config_error(line_number, "invalid palette"))?),
+            "gameshark" => config.gameshark_codes = parse_codes(value, line_number, cheats::validate_gameshark_code)?,
+            "gamegenie" => config.gamegenie_codes = parse_codes(value, line_number, cheats::validate_gamegenie_code)?,
+            _ => return Err(invalid_config_error(line_number, &format!("unknown key {}", key)))
+        }
+    }
+
+    Ok(config)
+}
+
+pub fn parse_game_configs(text: &str) -> io::Result<GameConfigs> {
+    let mut configs = HashMap::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let line = line.split(';').next().unwrap_or_default().trim();
+        if !line.is_empty() {
+            let config = parse_config_line(line, index + 1)?;
+            configs.insert(config.crc32, config);
+        }
+    }
+
+    Ok(configs)
+}
+
+fn format_colors(colors: &[u16]) -> String {
+    colors.iter().map(|color| format!("{:04X}", color)).collect::<Vec<String>>().join(",")
+}
+
+fn format_config_line(config: &GameConfig) -> String {
+    let mut line = format!("{:08X}", config.crc32);
+
+    if let Some(profile) = config.accuracy_profile {
+        line += &format!(" accuracy={}", as_accuracy_profile_text(profile));
+    }
+    if let Some(cycles) = config.extra_cpu_cycles_per_frame {
+        line += &format!(" overclock={}", cycles);
+    }
+    if let Some(palette) = config.palette {
+        let colors = [palette.background, palette.obp0, palette.obp1].concat();
+        line += &format!(" palette={}", format_colors(&colors));
+    }
+    if !config.gameshark_codes.is_empty() {
+        line += &format!(" gameshark={}", config.gameshark_codes.join(","));
+    }
+    if !config.gamegenie_codes.is_empty() {
+        line += &format!(" gamegenie={}", config.gamegenie_codes.join(","));
+    }
+
+    line
+}
+
+// Ordered by CRC32 so saved files don't change between runs.
+pub fn format_game_configs(configs: &GameConfigs) -> String {
+    let mut crc32s: Vec<&u32> = configs.keys().collect();
+    crc32s.sort();
+    crc32s.iter().map(|crc32| format_config_line(&configs[crc32]) + "\n").collect()
+}
+
+pub fn load_game_configs(emulator: &mut Emulator, text: &str) -> io::Result<()> {
+    emulator.game_config.configs = parse_game_configs(text)?;
+    Ok(())
+}
+
+pub fn store_game_configs(storage: &mut impl SaveStorage, configs: &GameConfigs) -> io::Result<()> {
+    storage.write(GAME_CONFIGS_KEY, format_game_configs(configs).as_bytes())
+}
+
+// No configs have been stored yet when the storage is empty.
+pub fn restore_game_configs(storage: &impl SaveStorage) -> io::Result<GameConfigs> {
+    match storage.read(GAME_CONFIGS_KEY)? {
+        Some(text) => parse_game_configs(&String::from_utf8_lossy(&text)),
+        None => Ok(GameConfigs::new())
+    }
+}
+
+// Adds or replaces the config for a game, taking effect the next time it's loaded.
+#[cfg(feature = "internals")]
+pub fn set_game_config(emulator: &mut Emulator, config: GameConfig) {
+    emulator.game_config.configs.insert(config.crc32, config);
+}
+
+fn revert_active_config(emulator: &mut Emulator) {
+    emulator.game_config.active = None;
+
+    if let Some(settings) = emulator.game_config.replaced_accuracy.take() {
+        emulator.accuracy = settings;
+    }
+    if let Some(cycles) = emulator.game_config.replaced_overclock.take() {
+        overclock::set_extra_cycles_per_frame(emulator, cycles);
+    }
+    for code in std::mem::take(&mut emulator.game_config.added_cheats) {
+        cheats::unregister_cheat(emulator, &code);
+    }
+}
+
+// Cheats the player already registered under the same code are theirs, and are left in place when the game changes.
+fn add_config_cheat(emulator: &mut Emulator, code: &str, register: fn(&mut Emulator, &str, &str) -> Option<String>) {
+    if !emulator.cheats.registered.contains_key(code) && register(emulator, code, code).is_none() {
+        emulator.game_config.added_cheats.push(code.to_string());
+    }
+}
+
+// Called with the CRC32 of each ROM as it's loaded. Whatever the previous game's config changed is undone first.
+pub fn apply_game_config(emulator: &mut Emulator, crc32: u32) {
+    revert_active_config(emulator);
+
+    let Some(config) = emulator.game_config.configs.get(&crc32).cloned() else {
+        return;
+    };
+
+    if let Some(profile) = config.accuracy_profile {
+        emulator.game_config.replaced_accuracy = Some(emulator.accuracy);
+        accuracy::set_accuracy_profile(emulator, profile);
+    }
+    if let Some(cycles) = config.extra_cpu_cycles_per_frame {
+        emulator.game_config.replaced_overclock = Some(emulator.overclock.extra_cycles_per_frame);
+        overclock::set_extra_cycles_per_frame(emulator, cycles);
+    }
+    for code in &config.gameshark_codes {
+        add_config_cheat(emulator, code, cheats::register_gameshark_cheat);
+    }
+    for code in &config.gamegenie_codes {
+        add_config_cheat(emulator, code, cheats::register_gamegenie_cheat);
+    }
+
+    emulator.game_config.active = Some(config);
+}
+
+// The overclock to go back to once the active config is reverted.
+pub fn base_extra_cpu_cycles_per_frame(emulator: &Emulator) -> u32 {
+    emulator.game_config.replaced_overclock.unwrap_or(emulator.overclock.extra_cycles_per_frame)
+}
+
+pub fn active_palette(emulator: &Emulator) -> Option<CompatibilityPalette> {
+    emulator.game_config.active.as_ref().and_then(|config| config.palette)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::initialize_screenless_emulator;
+    use crate::mmu::constants::*;
+    use crate::mmu::effects::empty_cartridge_effects;
+    use crate::mmu::test_utils::*;
+    use crate::patches::crc32;
+    use crate::save_slots::test_utils::*;
+    use super::*;
+
+    #[test]
+    fn should_round_trip_configs_through_text() {
+        let text = "; comment\n0000BEEF accuracy=fast overclock=1000 palette=7FFF,56B5,294A,0000 gameshark=01FFC0C0,01FFC1C0\n";
+        let configs = parse_game_configs(text).unwrap();
+        let config = &configs[&0xBEEF];
+        assert_eq!(config.accuracy_profile, Some(AccuracyProfile::Fast));
+        assert_eq!(config.extra_cpu_cycles_per_frame, Some(1000));
+        assert_eq!(config.gameshark_codes, vec!["01FFC0C0", "01FFC1C0"]);
+
+        assert_eq!(parse_game_configs(&format_game_configs(&configs)).unwrap(), configs);
+        assert_eq!(parse_game_configs("BEEF overclock=fast").unwrap_err().to_string(), "Invalid game config on line 1: invalid overclock");
+    }
+
+    #[test]
+    fn should_store_and_restore_configs() {
+        let mut storage = MemorySaveStorage::default();
+        assert!(restore_game_configs(&storage).unwrap().is_empty());
+
+        let configs = parse_game_configs("0000BEEF accuracy=balanced overclock=20").unwrap();
+        store_game_configs(&mut storage, &configs).unwrap();
+        assert_eq!(restore_game_configs(&storage).unwrap(), configs);
+    }
+
+    #[test]
+    fn should_apply_config_when_rom_is_loaded() {
+        let mut emulator = initialize_screenless_emulator();
+        let rom = build_rom(CART_TYPE_MBC1, ROM_SIZE_64KB, RAM_SIZE_0KB);
+        load_game_configs(&mut emulator, &format!("{:08X} accuracy=fast overclock=500 gameshark=01FFC0C0,01FFC1C0", crc32(&rom))).unwrap();
+        cheats::register_gameshark_cheat(&mut emulator, "01FFC1C0", "01FFC1C0");
+
+        rom_database::load_rom(&mut emulator, rom, empty_cartridge_effects()).unwrap();
+
+        assert_eq!(emulator.accuracy.profile, AccuracyProfile::Fast);
+        assert_eq!(emulator.overclock.extra_cycles_per_frame, 500);
+        assert!(emulator.cheats.registered.contains_key("01FFC0C0"));
+
+        let other_rom = build_rom(CART_TYPE_ROM_ONLY, ROM_SIZE_64KB, RAM_SIZE_0KB);
+        rom_database::load_rom(&mut emulator, other_rom, empty_cartridge_effects()).unwrap();
+        assert_eq!(emulator.accuracy.profile, AccuracyProfile::Balanced);
+        assert_eq!(emulator.overclock.extra_cycles_per_frame, 0);
+        assert_eq!(emulator.cheats.registered.keys().collect::<Vec<_>>(), vec!["01FFC1C0"]);
+        assert_eq!(active_palette(&emulator), None);
+    }
+}
@@ -0,0 +1,468 @@
+use std::io;
+
+use crate::builder::EmulatorBuilder;
+#[cfg(feature = "bus-observer")]
+use crate::bus_observer::{self, BusObserver};
+use crate::clock::SharedClock;
+use crate::core::Core;
+use crate::emulated_rtc;
+use crate::game_config::{self, GameConfigs};
+use crate::emulator::{self, initialize_screenless_emulator, CartridgeEffects, CartridgeHeader, ColorCorrection, CyclesRun, Emulator, FrameMetadata, GameConfig, HardwareModel, HleBootOptions, ScanlineRegisters, StateSnapshot};
+use crate::hotswap;
+use crate::input_polling::{self, InputPoller};
+use crate::keys::Key;
+use crate::mmu;
+use crate::mmu::io_registers::{self, IoRegister};
+use crate::mmu::ram_editor;
+use crate::netplay::{self, Rollback};
+use crate::pacing::FramePacer;
+use crate::pause;
+use crate::rom_database::{self, RomDatabase};
+use crate::serial::{self, logger, SerialDevice};
+use crate::serial::barcode_boy::{self, BarcodeScanner};
+use crate::snapshot;
+use crate::unmapped_writes::{self, UnmappedWrite};
+
+/*
+    High-level entry point for frontends. It covers the usual flow of inserting a
+    cartridge, feeding input and pulling frames and audio out, without reaching
+    into the emulator's internal state. Advanced users can still get at the
+    underlying emulator with the "internals" feature.
+*/
+pub struct GameBoy {
+    emulator: Emulator,
+    model: HardwareModel,
+    sample_rate: Option<u32>,
+    audio_buffer_size: Option<usize>,
+    color_correction: ColorCorrection,
+    rom_database: RomDatabase,
+    game_configs: GameConfigs,
+    hle_boot: Option<HleBootOptions>
+}
+
+impl Default for GameBoy {
+    fn default() -> Self {
+        GameBoy::new(HardwareModel::DMG)
+    }
+}
+
+impl GameBoy {
+    pub fn new(model: HardwareModel) -> GameBoy {
+        GameBoy {
+            emulator: initialize_screenless_emulator(),
+            model,
+            sample_rate: None,
+            audio_buffer_size: None,
+            color_correction: ColorCorrection::Raw,
+            rom_database: RomDatabase::new(),
+            game_configs: GameConfigs::new(),
+            hle_boot: None
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = Some(sample_rate);
+        crate::emulator::set_sample_rate(&mut self.emulator, sample_rate);
+    }
+
+    // Trades latency against stutter for frontends that sync to audio.
+    pub fn set_audio_buffer_size(&mut self, samples: usize) {
+        self.audio_buffer_size = Some(samples);
+        emulator::set_audio_buffer_size(&mut self.emulator, samples);
+    }
+
+    pub fn audio_buffer_latency_millis(&self) -> f64 {
+        emulator::get_audio_buffer_latency_millis(&self.emulator)
+    }
+
+    // Audio produced but not yet taken with audio_samples.
+    pub fn queued_audio_millis(&self) -> f64 {
+        emulator::get_queued_audio_millis(&self.emulator)
+    }
+
+    // Header overrides and quirks for cartridges inserted from now on, in the format of rom_database.txt.
+    pub fn load_rom_database(&mut self, text: &str) -> io::Result<()> {
+        self.rom_database = rom_database::parse_rom_database(text)?;
+        Ok(())
+    }
+
+    // Per-game accuracy, overclock, palette and cheats for cartridges inserted from now on. See game_config.rs.
+    pub fn load_game_configs(&mut self, text: &str) -> io::Result<()> {
+        self.game_configs = game_config::parse_game_configs(text)?;
+        Ok(())
+    }
+
+    pub fn set_game_config(&mut self, config: GameConfig) {
+        self.game_configs.insert(config.crc32, config);
+    }
+
+    // Every game's config as text, for saving between sessions.
+    pub fn export_game_configs(&self) -> String {
+        game_config::format_game_configs(&self.game_configs)
+    }
+
+    // Boots cartridges inserted from now on without running boot ROM code, or with it again when None.
+    pub fn set_hle_boot(&mut self, options: Option<HleBootOptions>) {
+        self.hle_boot = options;
+    }
+
+    // Time source for cartridge clocks, kept across cartridges. See clock.rs.
+    pub fn set_clock(&mut self, clock: SharedClock) {
+        emulator::set_clock(&mut self.emulator, clock);
+    }
+
+    // Ticks cartridge clocks from emulated time at the given speed, or from the clock again when None. See emulated_rtc.rs.
+    pub fn set_emulated_rtc(&mut self, multiplier: Option<f64>) {
+        emulated_rtc::set_emulated_rtc(&mut self.emulator, multiplier);
+    }
+
+    // Inserting a cartridge powers the system back on, so it starts from the boot ROM.
+    pub fn insert_cartridge(&mut self, rom: &[u8]) -> io::Result<CartridgeHeader> {
+        self.insert_cartridge_with_effects(rom, mmu::effects::empty_cartridge_effects())
+    }
+
+    pub fn insert_cartridge_with_effects(&mut self, rom: &[u8], cartridge_effects: Box<dyn CartridgeEffects>) -> io::Result<CartridgeHeader> {
+        let mut builder = EmulatorBuilder::new()
+            .with_hardware_model(self.model)
+            .with_rom_database(self.rom_database.clone())
+            .with_game_configs(self.game_configs.clone())
+            .with_clock(emulated_rtc::host_clock(&self.emulator))
+            .with_extra_cpu_cycles_per_frame(game_config::base_extra_cpu_cycles_per_frame(&self.emulator))
+            .with_rom_and_effects(rom, cartridge_effects);
+
+        if let Some(sample_rate) = self.sample_rate {
+            builder = builder.with_sample_rate(sample_rate);
+        }
+
+        if let Some(samples) = self.audio_buffer_size {
+            builder = builder.with_audio_buffer_size(samples);
+        }
+
+        if let Some(options) = self.hle_boot {
+            builder = builder.hle_boot(options);
+        }
+
+        let (mut emulator, header) = builder.build()?;
+        emulator::set_color_correction(&mut emulator, self.color_correction);
+        unmapped_writes::set_unmapped_write_tracking(&mut emulator, self.emulator.unmapped_writes.enabled);
+        emulator::set_scanline_render(&mut emulator, self.emulator.render_scanline);
+        input_polling::set_input_poller(&mut emulator, self.emulator.input_polling.poller.take());
+        if let Some(device) = self.emulator.serial.device.take() {
+            serial::connect_serial_device(&mut emulator, device);
+        }
+        #[cfg(feature = "bus-observer")]
+        bus_observer::set_bus_observer(&mut emulator, self.emulator.bus_observer.take());
+        if emulated_rtc::is_enabled(&self.emulator) {
+            emulated_rtc::set_emulated_rtc(&mut emulator, Some(self.emulator.emulated_rtc.multiplier));
+        }
+        self.emulator = emulator;
+        Ok(header.expect("a ROM was given to the builder"))
+    }
+
+    // Leaves the console running with nothing in the cartridge slot.
+    pub fn eject_cartridge(&mut self) {
+        hotswap::eject_cartridge(&mut self.emulator);
+    }
+
+    // Swaps in a cartridge without turning the console off, as multicart menus and the "swap trick" do.
+    pub fn hotswap_cartridge(&mut self, rom: &[u8], cartridge_effects: Box<dyn CartridgeEffects>) -> io::Result<CartridgeHeader> {
+        hotswap::insert_cartridge(&mut self.emulator, rom, cartridge_effects, true)
+    }
+
+    // Asks for the joypad state as each frame starts, instead of using press and release. Kept across cartridges.
+    pub fn set_input_poller(&mut self, poller: Option<Box<dyn InputPoller>>) {
+        input_polling::set_input_poller(&mut self.emulator, poller);
+    }
+
+    pub fn press(&mut self, key: Key) {
+        self.emulator.set_button(key, true);
+    }
+
+    pub fn release(&mut self, key: Key) {
+        self.emulator.set_button(key, false);
+    }
+
+    pub fn run_frame(&mut self) {
+        self.emulator.run_frame();
+    }
+
+    // For frontends driven by an audio callback or vsync rather than whole frames. See emulator::run_cycles.
+    pub fn run_cycles(&mut self, t_cycles: u64) -> CyclesRun {
+        emulator::run_cycles(&mut self.emulator, t_cycles)
+    }
+
+    pub fn request_pause(&mut self) {
+        pause::request_pause(&mut self.emulator);
+    }
+
+    pub fn resume(&mut self) {
+        pause::resume(&mut self.emulator);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        pause::is_paused(&self.emulator)
+    }
+
+    // RGBA pixels, 160x144.
+    pub fn screen(&self) -> &[u8] {
+        self.emulator.frame_buffer()
+    }
+
+    // The joypad state while the last frame was drawn and whether it lagged. See frame_metadata.rs.
+    pub fn frame_metadata(&self) -> FrameMetadata {
+        emulator::get_frame_metadata(&self.emulator)
+    }
+
+    // Frames the game didn't read the joypad in, since the cartridge was inserted.
+    pub fn lag_frames(&self) -> u64 {
+        emulator::get_lag_frame_count(&self.emulator)
+    }
+
+    // Rows of the screen that changed in the last frame, for frontends that only redraw what changed.
+    pub fn changed_scanlines(&self) -> Vec<u8> {
+        emulator::get_changed_scanlines(&self.emulator)
+    }
+
+    // Called with the line index and its pixels as each line is drawn, for beam racing frontends.
+    pub fn set_scanline_render(&mut self, render_scanline: Option<fn(u8, &[u8])>) {
+        emulator::set_scanline_render(&mut self.emulator, render_scanline);
+    }
+
+    // SCX, SCY, WX and WY as each row of the last frame was drawn.
+    pub fn scanline_registers(&self) -> &[ScanlineRegisters] {
+        emulator::get_scanline_registers(&self.emulator)
+    }
+
+    pub fn audio_samples(&mut self) -> (Vec<f32>, Vec<f32>) {
+        self.emulator.take_audio_samples()
+    }
+
+    // Runs until the buffers are full, for frontends driven by an audio callback. See emulator::fill_audio_buffers.
+    pub fn fill_audio(&mut self, left: &mut [f32], right: &mut [f32]) -> usize {
+        emulator::fill_audio_buffers(&mut self.emulator, left, right)
+    }
+
+    // Text the game has printed over the link port since the last call, e.g. test ROM results.
+    pub fn take_serial_output(&mut self) -> String {
+        logger::take_serial_output(&mut self.emulator)
+    }
+
+    // Kept across cartridges, like a link cable left plugged in.
+    pub fn connect_serial_device(&mut self, device: Box<dyn SerialDevice>) {
+        serial::connect_serial_device(&mut self.emulator, device);
+    }
+
+    pub fn disconnect_serial_device(&mut self) {
+        serial::disconnect_serial_device(&mut self.emulator);
+    }
+
+    // Plugs in a Barcode Boy, returning the scanner used to feed it barcodes.
+    pub fn connect_barcode_boy(&mut self) -> BarcodeScanner {
+        let (barcode_boy, scanner) = barcode_boy::initialize_barcode_boy();
+        self.connect_serial_device(Box::new(barcode_boy));
+        scanner
+    }
+
+    pub fn cartridge_ram(&self) -> Vec<u8> {
+        mmu::get_cartridge_ram(&self.emulator.memory)
+    }
+
+    pub fn set_cartridge_ram(&mut self, ram: &[u8]) {
+        mmu::set_cartridge_ram(&mut self.emulator.memory, ram.to_vec());
+    }
+
+    // Edits a byte in an 8KB RAM bank while the game runs, as a save editor would.
+    pub fn write_cartridge_ram(&mut self, bank: u8, offset: u16, value: u8) -> io::Result<()> {
+        ram_editor::write_banked_ram(&mut self.emulator.memory, bank, offset, value)
+    }
+
+    // Records writes to I/O registers the emulator doesn't implement, to spot what a misbehaving game relies on.
+    pub fn set_unmapped_write_tracking(&mut self, enabled: bool) {
+        unmapped_writes::set_unmapped_write_tracking(&mut self.emulator, enabled);
+    }
+
+    pub fn unmapped_writes(&self) -> Vec<UnmappedWrite> {
+        unmapped_writes::get_unmapped_writes(&self.emulator)
+    }
+
+    // Every mapped I/O register with the value the CPU would read from it, for a debugger view.
+    pub fn io_registers(&mut self) -> Vec<IoRegister> {
+        io_registers::io_registers(&mut self.emulator)
+    }
+
+    // Only affects CGB colors. Takes effect from the next scanline drawn.
+    pub fn set_color_correction(&mut self, color_correction: ColorCorrection) {
+        self.color_correction = color_correction;
+        emulator::set_color_correction(&mut self.emulator, color_correction);
+    }
+
+    // Extra CPU cycles run after each frame to reduce slowdown. See overclock.rs before enabling. Kept across cartridges.
+    pub fn set_extra_cpu_cycles_per_frame(&mut self, cycles: u32) {
+        emulator::set_extra_cpu_cycles_per_frame(&mut self.emulator, cycles);
+    }
+
+    // Paces frames at the refresh rate of the model being emulated.
+    pub fn frame_pacer(&self) -> FramePacer {
+        FramePacer::for_model(self.model)
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        self.emulator.save_state()
+    }
+
+    pub fn load_state(&mut self, state: &[u8]) -> io::Result<()> {
+        self.emulator.load_state(state)
+    }
+
+    // Captures into an existing snapshot without allocating, for run-ahead, rewind or rollback.
+    pub fn clone_state(&self, snapshot: &mut StateSnapshot) {
+        snapshot::clone_state(&self.emulator, snapshot);
+    }
+
+    pub fn restore_state(&mut self, snapshot: &StateSnapshot) -> io::Result<()> {
+        snapshot::restore_state(&mut self.emulator, snapshot)
+    }
+
+    // Kept across cartridges. See bus_observer.rs.
+    #[cfg(feature = "bus-observer")]
+    pub fn set_bus_observer(&mut self, observer: Option<Box<dyn BusObserver>>) {
+        bus_observer::set_bus_observer(&mut self.emulator, observer);
+    }
+
+    // Registers, banks and timers as pretty-printed JSON, for debugger UIs and bug reports. See debug_json.rs.
+    #[cfg(feature = "debug-json")]
+    pub fn debug_json(&self) -> String {
+        crate::debug_json::debug_json(&self.emulator)
+    }
+
+    #[cfg(feature = "internals")]
+    pub fn emulator(&self) -> &Emulator {
+        &self.emulator
+    }
+
+    #[cfg(feature = "internals")]
+    pub fn emulator_mut(&mut self) -> &mut Emulator {
+        &mut self.emulator
+    }
+}
+
+impl Core for GameBoy {
+    type Button = Key;
+
+    fn load_rom(&mut self, rom: &[u8]) -> io::Result<()> {
+        self.insert_cartridge(rom)?;
+        Ok(())
+    }
+
+    fn run_frame(&mut self) {
+        GameBoy::run_frame(self);
+    }
+
+    fn frame_buffer(&self) -> &[u8] {
+        self.screen()
+    }
+
+    fn screen_size(&self) -> (u32, u32) {
+        self.emulator.screen_size()
+    }
+
+    fn take_audio_samples(&mut self) -> (Vec<f32>, Vec<f32>) {
+        self.audio_samples()
+    }
+
+    fn set_button(&mut self, button: Key, pressed: bool) {
+        self.emulator.set_button(button, pressed);
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        GameBoy::save_state(self)
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> io::Result<()> {
+        GameBoy::load_state(self, state)
+    }
+}
+
+impl Rollback for GameBoy {
+    fn clone_state(&self, snapshot: &mut StateSnapshot) {
+        GameBoy::clone_state(self, snapshot);
+    }
+
+    fn restore_state(&mut self, snapshot: &StateSnapshot) -> io::Result<()> {
+        GameBoy::restore_state(self, snapshot)
+    }
+
+    fn set_input(&mut self, mask: u8) {
+        netplay::apply_input(&mut self.emulator, mask);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mmu::constants::*;
+    use crate::mmu::test_utils::*;
+    use super::*;
+
+    fn setup_game_boy() -> GameBoy {
+        let mut game_boy = GameBoy::default();
+        let rom = build_rom(CART_TYPE_MBC1_WITH_RAM_PLUS_BATTERY, ROM_SIZE_64KB, RAM_SIZE_8KB);
+        game_boy.insert_cartridge(&rom).unwrap();
+        game_boy
+    }
+
+    #[test]
+    fn should_run_inserted_cartridge() {
+        let mut game_boy = setup_game_boy();
+        game_boy.press(Key::Start);
+        game_boy.run_frame();
+        game_boy.release(Key::Start);
+        assert_eq!(game_boy.screen().len(), 160 * 144 * 4);
+        assert!(!game_boy.audio_samples().0.is_empty());
+    }
+
+    #[test]
+    fn should_fill_fixed_size_audio_chunks() {
+        let mut game_boy = setup_game_boy();
+        let mut left = [1.0; 128];
+        let mut right = [1.0; 128];
+
+        for _ in 0..4 {
+            assert_eq!(game_boy.fill_audio(&mut left, &mut right), 128);
+            assert!(game_boy.emulator.apu.left_sample_queue.len() < 128);
+        }
+    }
+
+    #[test]
+    fn should_reject_invalid_cartridge() {
+        let mut game_boy = GameBoy::default();
+        assert!(game_boy.insert_cartridge(&[0; 0x10]).is_err());
+    }
+
+    #[test]
+    fn should_keep_color_correction_when_inserting_cartridge() {
+        let mut game_boy = GameBoy::new(HardwareModel::CGB);
+        game_boy.set_color_correction(ColorCorrection::Gba);
+        game_boy.insert_cartridge(&build_rom(CART_TYPE_ROM_ONLY, ROM_SIZE_64KB, RAM_SIZE_0KB)).unwrap();
+        assert_eq!(game_boy.emulator.gpu.registers.palettes.color_correction, ColorCorrection::Gba);
+    }
+
+    #[test]
+    fn should_keep_overclock_and_serial_device_when_inserting_cartridge() {
+        let mut game_boy = setup_game_boy();
+        game_boy.set_extra_cpu_cycles_per_frame(1000);
+        game_boy.connect_barcode_boy();
+
+        game_boy.insert_cartridge(&build_rom(CART_TYPE_ROM_ONLY, ROM_SIZE_64KB, RAM_SIZE_0KB)).unwrap();
+        assert_eq!(game_boy.emulator.overclock.extra_cycles_per_frame, 1000);
+        assert!(game_boy.emulator.serial.device.is_some());
+    }
+
+    #[test]
+    fn should_round_trip_cartridge_ram() {
+        let mut game_boy = setup_game_boy();
+        let mut ram = game_boy.cartridge_ram();
+        ram[0] = 0x42;
+        game_boy.set_cartridge_ram(&ram);
+        assert_eq!(game_boy.cartridge_ram()[0], 0x42);
+    }
+}
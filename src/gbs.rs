@@ -0,0 +1,322 @@
+use std::io;
+
+use crate::builder::EmulatorBuilder;
+use crate::emulator::{self, Emulator, HardwareModel};
+use crate::mmu;
+use crate::mmu::constants::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GbsHeader {
+    pub version: u8,
+    pub song_count: u8,
+    pub first_song: u8,
+    pub load_address: u16,
+    pub init_address: u16,
+    pub play_address: u16,
+    pub stack_pointer: u16,
+    pub timer_modulo: u8,
+    pub timer_control: u8,
+    pub title: String,
+    pub author: String,
+    pub copyright: String
+}
+
+pub struct GbsPlayer {
+    pub header: GbsHeader,
+    pub emulator: Box<Emulator>,
+    pub current_song: u8,
+    rom: Vec<u8>,
+    sample_rate: Option<u32>
+}
+
+const GBS_SIGNATURE: &[u8; 3] = b"GBS";
+const GBS_HEADER_LENGTH: usize = 0x70;
+
+const VBLANK_VECTOR: usize = 0x40;
+const TIMER_VECTOR: usize = 0x50;
+const IDLE_LOOP_ADDRESS: u16 = 0x70;
+const CGB_FLAG_ADDRESS: usize = 0x143;
+
+const TIMER_INTERRUPT_BIT: u8 = 0x04;
+const DOUBLE_SPEED_BIT: u8 = 0x80;
+
+fn invalid_gbs_error(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Invalid GBS file: {}", message))
+}
+
+fn read_word(buffer: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buffer[offset], buffer[offset + 1]])
+}
+
+fn read_text(buffer: &[u8], offset: usize) -> String {
+    let field = &buffer[offset..offset + 32];
+    let length = field.iter().position(|byte| *byte == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..length]).into_owned()
+}
+
+pub fn parse_gbs_header(buffer: &[u8]) -> io::Result<GbsHeader> {
+    if buffer.len() < GBS_HEADER_LENGTH || &buffer[0..3] != GBS_SIGNATURE {
+        return Err(invalid_gbs_error("missing GBS header"));
+    }
+
+    let header = GbsHeader {
+        version: buffer[0x03],
+        song_count: buffer[0x04],
+        first_song: buffer[0x05],
+        load_address: read_word(buffer, 0x06),
+        init_address: read_word(buffer, 0x08),
+        play_address: read_word(buffer, 0x0A),
+        stack_pointer: read_word(buffer, 0x0C),
+        timer_modulo: buffer[0x0E],
+        timer_control: buffer[0x0F],
+        title: read_text(buffer, 0x10),
+        author: read_text(buffer, 0x30),
+        copyright: read_text(buffer, 0x50)
+    };
+
+    if header.song_count == 0 {
+        return Err(invalid_gbs_error("no songs"));
+    }
+
+    // The driver lives in the first page of the ROM, so the music data must come after the cartridge header.
+    if (header.load_address as usize) < HEADER_END_ADDRESS || header.load_address >= 0x8000 {
+        return Err(invalid_gbs_error("load address out of range"));
+    }
+
+    Ok(header)
+}
+
+fn uses_timer(header: &GbsHeader) -> bool {
+    header.timer_control & TIMER_INTERRUPT_BIT != 0
+}
+
+fn uses_double_speed(header: &GbsHeader) -> bool {
+    header.timer_control & DOUBLE_SPEED_BIT != 0
+}
+
+fn write_call_and_return(rom: &mut [u8], address: usize, target: u16) {
+    let [low, high] = target.to_le_bytes();
+    rom[address..address + 4].copy_from_slice(&[0xCD, low, high, 0xD9]);
+}
+
+/*
+    A GBS file is a bare music driver with no cartridge around it. It gets wrapped in
+    an MBC5 ROM (its bank switching register at 0x2000 matches the one GBS rips
+    expect), with the RST vectors redirected relative to the load address, the
+    VBlank and timer vectors calling the play routine and an idle loop to return
+    to once init is done.
+*/
+fn build_rom(header: &GbsHeader, data: &[u8]) -> io::Result<Vec<u8>> {
+    let load_address = header.load_address as usize;
+    let rom_size = (load_address + data.len()).next_power_of_two().max(0x8000);
+    let rom_size_index = (rom_size / 0x8000).trailing_zeros() as u8;
+
+    if rom_size_index > ROM_SIZE_8MB {
+        return Err(invalid_gbs_error("music data too large"));
+    }
+
+    let mut rom = vec![0; rom_size];
+    rom[load_address..load_address + data.len()].copy_from_slice(data);
+
+    for vector in (0..0x40).step_by(8) {
+        let [low, high] = (header.load_address + vector as u16).to_le_bytes();
+        rom[vector..vector + 3].copy_from_slice(&[0xC3, low, high]);
+    }
+
+    write_call_and_return(&mut rom, VBLANK_VECTOR, header.play_address);
+    write_call_and_return(&mut rom, TIMER_VECTOR, header.play_address);
+
+    let idle_loop = IDLE_LOOP_ADDRESS as usize;
+    rom[idle_loop..idle_loop + 4].copy_from_slice(&[0xFB, 0x76, 0x18, 0xFD]);
+
+    rom[CGB_FLAG_ADDRESS] = if uses_double_speed(header) { 0x80 } else { 0x00 };
+    rom[CARTRIDGE_TYPE_ADDRESS] = CART_TYPE_MBC5_RAM;
+    rom[ROM_SIZE_ADDRESS] = rom_size_index;
+    rom[RAM_SIZE_ADDRESS] = RAM_SIZE_8KB;
+
+    Ok(rom)
+}
+
+pub fn initialize_gbs_player(buffer: &[u8]) -> io::Result<GbsPlayer> {
+    let header = parse_gbs_header(buffer)?;
+    let rom = build_rom(&header, &buffer[GBS_HEADER_LENGTH..])?;
+    let first_song = header.first_song.clamp(1, header.song_count);
+
+    let mut player = GbsPlayer {
+        header,
+        emulator: Box::new(emulator::initialize_screenless_emulator()),
+        current_song: first_song,
+        rom,
+        sample_rate: None
+    };
+    select_song(&mut player, first_song)?;
+    Ok(player)
+}
+
+pub fn set_sample_rate(player: &mut GbsPlayer, sample_rate: u32) {
+    player.sample_rate = Some(sample_rate);
+    emulator::set_sample_rate(&mut player.emulator, sample_rate);
+}
+
+fn push_return_address(emulator: &mut Emulator, address: u16) {
+    let stack_pointer = emulator.cpu.registers.stack_pointer.wrapping_sub(2);
+    let [low, high] = address.to_le_bytes();
+    mmu::write_byte(emulator, stack_pointer, low);
+    mmu::write_byte(emulator, stack_pointer.wrapping_add(1), high);
+    emulator.cpu.registers.stack_pointer = stack_pointer;
+}
+
+/*
+    Songs are numbered from 1 as in the GBS header. Each song starts from a freshly
+    powered on system, with init called with the zero-based song index in A and the
+    play routine driven by VBlank or by the timer, depending on the header.
+*/
+pub fn select_song(player: &mut GbsPlayer, song: u8) -> io::Result<()> {
+    if song == 0 || song > player.header.song_count {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Song {} is out of range", song)));
+    }
+
+    let header = &player.header;
+    let model = if uses_double_speed(header) { HardwareModel::CGB } else { HardwareModel::DMG };
+
+    let mut builder = EmulatorBuilder::new()
+        .with_hardware_model(model)
+        .with_rom(&player.rom)
+        .skip_boot_rom();
+
+    if let Some(sample_rate) = player.sample_rate {
+        builder = builder.with_sample_rate(sample_rate);
+    }
+
+    let (mut emulator, _) = builder.build()?;
+
+    if uses_double_speed(header) {
+        emulator.speed_switch.cgb_double_speed = true;
+    }
+
+    mmu::write_byte(&mut emulator, 0xFF06, header.timer_modulo);
+    mmu::write_byte(&mut emulator, 0xFF07, header.timer_control);
+    emulator.interrupts.enabled = if uses_timer(header) { 0x04 } else { 0x01 };
+    emulator.interrupts.flags = 0;
+
+    let registers = &mut emulator.cpu.registers;
+    registers.a = song - 1;
+    registers.stack_pointer = header.stack_pointer;
+    push_return_address(&mut emulator, IDLE_LOOP_ADDRESS);
+
+    // The opcode after the program counter is prefetched, so start on a NOP that fetches init.
+    emulator.cpu.registers.program_counter = header.init_address;
+    emulator.cpu.registers.opcode = 0x00;
+
+    *player.emulator = emulator;
+    player.current_song = song;
+    Ok(())
+}
+
+pub fn step_until_next_audio_buffer(player: &mut GbsPlayer) -> (&[f32], &[f32]) {
+    emulator::step_until_next_audio_buffer(&mut player.emulator)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::apu::CPU_RATE;
+    use super::*;
+
+    const LOAD_ADDRESS: u16 = 0x0400;
+    const INIT_ADDRESS: u16 = 0x0400;
+    const PLAY_ADDRESS: u16 = 0x0404;
+
+    /*
+        Init stores the song index at C000, play increments C001 and sets up channel 2
+        so the player produces sound:
+            init: ld (C000), a; ret
+            play: ld hl, C001; inc (hl); ld a, 0xF0; ldh (17), a; ld a, 0x87; ldh (19), a; ret
+    */
+    fn build_gbs(song_count: u8, timer_control: u8) -> Vec<u8> {
+        let mut buffer = vec![0; GBS_HEADER_LENGTH];
+        buffer[0..3].copy_from_slice(GBS_SIGNATURE);
+        buffer[0x03] = 1;
+        buffer[0x04] = song_count;
+        buffer[0x05] = 1;
+        buffer[0x06..0x08].copy_from_slice(&LOAD_ADDRESS.to_le_bytes());
+        buffer[0x08..0x0A].copy_from_slice(&INIT_ADDRESS.to_le_bytes());
+        buffer[0x0A..0x0C].copy_from_slice(&PLAY_ADDRESS.to_le_bytes());
+        buffer[0x0C..0x0E].copy_from_slice(&0xDFFFu16.to_le_bytes());
+        buffer[0x0E] = 0x00;
+        buffer[0x0F] = timer_control;
+        buffer[0x10..0x15].copy_from_slice(b"Tunes");
+        buffer[0x30..0x36].copy_from_slice(b"Author");
+
+        buffer.extend_from_slice(&[
+            0xEA, 0x00, 0xC0, 0xC9,
+            0x21, 0x01, 0xC0, 0x34, 0x3E, 0xF0, 0xE0, 0x17, 0x3E, 0x87, 0xE0, 0x19, 0xC9
+        ]);
+        buffer
+    }
+
+    fn run_frames(player: &mut GbsPlayer, frames: u64) {
+        let frame_count = player.emulator.gpu.frame_count;
+        while player.emulator.gpu.frame_count < frame_count + frames {
+            emulator::step(&mut player.emulator);
+        }
+    }
+
+    #[test]
+    fn should_parse_gbs_header() {
+        let header = parse_gbs_header(&build_gbs(3, 0)).unwrap();
+        assert_eq!(header.song_count, 3);
+        assert_eq!(header.load_address, LOAD_ADDRESS);
+        assert_eq!(header.play_address, PLAY_ADDRESS);
+        assert_eq!(header.title, "Tunes");
+        assert_eq!(header.author, "Author");
+        assert_eq!(header.copyright, "");
+    }
+
+    #[test]
+    fn should_reject_invalid_gbs_files() {
+        assert!(parse_gbs_header(b"GBR").is_err());
+        assert!(parse_gbs_header(&build_gbs(0, 0)).is_err());
+
+        let mut buffer = build_gbs(1, 0);
+        buffer[0x06..0x08].copy_from_slice(&0x0100u16.to_le_bytes());
+        assert!(initialize_gbs_player(&buffer).is_err());
+    }
+
+    #[test]
+    fn should_call_init_with_song_index() {
+        let mut player = initialize_gbs_player(&build_gbs(3, 0)).unwrap();
+        select_song(&mut player, 3).unwrap();
+        run_frames(&mut player, 1);
+        assert_eq!(mmu::read_byte(&mut player.emulator, 0xC000), 2);
+        assert!(select_song(&mut player, 4).is_err());
+    }
+
+    #[test]
+    fn should_call_play_once_per_frame() {
+        let mut player = initialize_gbs_player(&build_gbs(1, 0)).unwrap();
+        run_frames(&mut player, 1);
+        let play_count = mmu::read_byte(&mut player.emulator, 0xC001);
+        run_frames(&mut player, 10);
+        assert_eq!(mmu::read_byte(&mut player.emulator, 0xC001), play_count + 10);
+    }
+
+    #[test]
+    fn should_call_play_on_timer_interrupt() {
+        // 4096Hz timer overflowing every 256 ticks, so 16 calls per second. Runs a little
+        // over a second so the last overflow isn't missed.
+        let mut player = initialize_gbs_player(&build_gbs(1, 0x04)).unwrap();
+        while player.emulator.cpu.clock.total_clock_cycles < CPU_RATE + CPU_RATE / 32 {
+            emulator::step(&mut player.emulator);
+        }
+        assert_eq!(mmu::read_byte(&mut player.emulator, 0xC001), 16);
+    }
+
+    #[test]
+    fn should_produce_audio() {
+        let mut player = initialize_gbs_player(&build_gbs(1, 0)).unwrap();
+        set_sample_rate(&mut player, 44100);
+        run_frames(&mut player, 2);
+        let (left_samples, _) = step_until_next_audio_buffer(&mut player);
+        assert!(left_samples.iter().any(|sample| *sample != 0.0));
+    }
+}
@@ -0,0 +1,528 @@
+use std::collections::HashMap;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+use crate::breakpoints;
+use crate::cpu;
+use crate::emulator::{self, Emulator};
+use crate::mmu;
+use crate::pause;
+
+/*
+    A GDB remote serial protocol stub, so homebrew can be debugged with gdb or an IDE
+    attached over TCP (e.g. "target remote localhost:2159"). GDB has no built-in SM83
+    architecture, so the stub serves a target description listing the register file
+    as a, f, b, c, d, e, h, l (8 bits each) followed by sp and pc (16 bits each).
+
+    Memory is read and written through the MMU as the CPU sees it, with whichever
+    banks are mapped. Software and hardware breakpoints map onto the debugger's
+    address breakpoints. Watchpoints aren't supported.
+*/
+const TARGET_DESCRIPTION: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.retroboy.sm83">
+    <reg name="a" bitsize="8" type="uint8"/>
+    <reg name="f" bitsize="8" type="uint8"/>
+    <reg name="b" bitsize="8" type="uint8"/>
+    <reg name="c" bitsize="8" type="uint8"/>
+    <reg name="d" bitsize="8" type="uint8"/>
+    <reg name="e" bitsize="8" type="uint8"/>
+    <reg name="h" bitsize="8" type="uint8"/>
+    <reg name="l" bitsize="8" type="uint8"/>
+    <reg name="sp" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>"#;
+
+const REGISTER_COUNT: usize = 10;
+const SIGTRAP: u8 = 5;
+const SIGINT: u8 = 2;
+const INTERRUPT_BYTE: u8 = 0x03;
+// How many instructions run between checks for an interrupt from the debugger.
+const INTERRUPT_POLL_INTERVAL: u32 = 10000;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum GdbAction {
+    Reply(String),
+    Continue,
+    Step,
+    Detach
+}
+
+#[derive(Debug, Default)]
+pub struct GdbSession {
+    // Breakpoint ids by address, as GDB removes breakpoints by address.
+    breakpoints: HashMap<u16, u32>,
+    no_ack_mode: bool
+}
+
+impl GdbSession {
+    pub fn new() -> GdbSession {
+        GdbSession::default()
+    }
+}
+
+fn as_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn parse_hex_bytes(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len()).step_by(2)
+        .map(|index| u8::from_str_radix(text.get(index..index + 2)?, 16).ok())
+        .collect()
+}
+
+fn parse_hex_number(text: &str) -> Option<usize> {
+    usize::from_str_radix(text, 16).ok()
+}
+
+fn jump_to(emulator: &mut Emulator, address: u16) {
+    emulator.cpu.registers.opcode = mmu::read_byte(emulator, address);
+    emulator.cpu.registers.program_counter = address.wrapping_add(1);
+}
+
+fn read_register(emulator: &Emulator, index: usize) -> Option<Vec<u8>> {
+    let registers = &emulator.cpu.registers;
+    match index {
+        0 => Some(vec![registers.a]),
+        1 => Some(vec![registers.f]),
+        2 => Some(vec![registers.b]),
+        3 => Some(vec![registers.c]),
+        4 => Some(vec![registers.d]),
+        5 => Some(vec![registers.e]),
+        6 => Some(vec![registers.h]),
+        7 => Some(vec![registers.l]),
+        8 => Some(registers.stack_pointer.to_le_bytes().to_vec()),
+        9 => Some(cpu::current_instruction_address(emulator).to_le_bytes().to_vec()),
+        _ => None
+    }
+}
+
+fn register_size(index: usize) -> usize {
+    if index >= 8 { 2 } else { 1 }
+}
+
+fn write_register(emulator: &mut Emulator, index: usize, bytes: &[u8]) -> bool {
+    if index >= REGISTER_COUNT || bytes.len() != register_size(index) {
+        return false;
+    }
+
+    let registers = &mut emulator.cpu.registers;
+    match index {
+        0 => registers.a = bytes[0],
+        // The low nibble of F is always zero on hardware.
+        1 => registers.f = bytes[0] & 0xF0,
+        2 => registers.b = bytes[0],
+        3 => registers.c = bytes[0],
+        4 => registers.d = bytes[0],
+        5 => registers.e = bytes[0],
+        6 => registers.h = bytes[0],
+        7 => registers.l = bytes[0],
+        8 => registers.stack_pointer = u16::from_le_bytes([bytes[0], bytes[1]]),
+        _ => jump_to(emulator, u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+    true
+}
+
+fn read_all_registers(emulator: &Emulator) -> String {
+    let bytes: Vec<u8> = (0..REGISTER_COUNT)
+        .flat_map(|index| read_register(emulator, index).unwrap_or_default())
+        .collect();
+    as_hex(&bytes)
+}
+
+fn write_all_registers(emulator: &mut Emulator, data: &str) -> bool {
+    let bytes = match parse_hex_bytes(data) {
+        Some(bytes) if bytes.len() == 12 => bytes,
+        _ => return false
+    };
+    let mut offset = 0;
+    for index in 0..REGISTER_COUNT {
+        let size = register_size(index);
+        write_register(emulator, index, &bytes[offset..offset + size]);
+        offset += size;
+    }
+    true
+}
+
+fn parse_address_and_length(text: &str) -> Option<(u16, usize)> {
+    let (address, length) = text.split_once(',')?;
+    Some((parse_hex_number(address)? as u16, parse_hex_number(length)?))
+}
+
+fn read_memory(emulator: &mut Emulator, arguments: &str) -> String {
+    match parse_address_and_length(arguments) {
+        Some((address, length)) => {
+            let bytes: Vec<u8> = (0..length.min(0x10000))
+                .map(|offset| mmu::read_byte(emulator, address.wrapping_add(offset as u16)))
+                .collect();
+            as_hex(&bytes)
+        },
+        None => "E01".to_string()
+    }
+}
+
+fn write_memory(emulator: &mut Emulator, arguments: &str) -> String {
+    let parsed = arguments.split_once(':')
+        .and_then(|(location, data)| Some((parse_address_and_length(location)?, parse_hex_bytes(data)?)));
+    match parsed {
+        Some(((address, length), bytes)) if bytes.len() == length => {
+            for (offset, byte) in bytes.iter().enumerate() {
+                mmu::write_byte(emulator, address.wrapping_add(offset as u16), *byte);
+            }
+            "OK".to_string()
+        },
+        _ => "E01".to_string()
+    }
+}
+
+fn update_breakpoint(session: &mut GdbSession, emulator: &mut Emulator, arguments: &str, insert: bool) -> String {
+    let mut parts = arguments.split(',');
+    let kind = parts.next();
+    let address = parts.next().and_then(parse_hex_number);
+
+    match (kind, address) {
+        (Some("0") | Some("1"), Some(address)) => {
+            let address = address as u16;
+            if insert {
+                session.breakpoints.entry(address).or_insert_with(|| {
+                    breakpoints::add_breakpoint(emulator, address, None, None)
+                        .expect("Breakpoints without a condition can always be added.")
+                });
+            }
+            else if let Some(id) = session.breakpoints.remove(&address) {
+                breakpoints::remove_breakpoint(emulator, id);
+            }
+            "OK".to_string()
+        },
+        // Watchpoints aren't supported.
+        _ => String::new()
+    }
+}
+
+fn read_target_description(arguments: &str) -> String {
+    let range = arguments.strip_prefix("target.xml:").and_then(parse_address_and_length);
+    match range {
+        Some((offset, length)) => {
+            let offset = (offset as usize).min(TARGET_DESCRIPTION.len());
+            let end = offset.saturating_add(length).min(TARGET_DESCRIPTION.len());
+            let prefix = if end == TARGET_DESCRIPTION.len() { "l" } else { "m" };
+            format!("{}{}", prefix, &TARGET_DESCRIPTION[offset..end])
+        },
+        None => "E00".to_string()
+    }
+}
+
+pub fn stop_reply(signal: u8) -> String {
+    format!("S{:02x}", signal)
+}
+
+pub fn handle_packet(session: &mut GdbSession, emulator: &mut Emulator, packet: &str) -> GdbAction {
+    let (command, arguments) = packet.split_at(packet.chars().next().map_or(0, |c| c.len_utf8()));
+    let reply = match command {
+        "?" => stop_reply(SIGTRAP),
+        "g" => read_all_registers(emulator),
+        "G" => if write_all_registers(emulator, arguments) { "OK".to_string() } else { "E01".to_string() },
+        "p" => parse_hex_number(arguments)
+            .and_then(|index| read_register(emulator, index))
+            .map(|bytes| as_hex(&bytes))
+            .unwrap_or_else(|| "E01".to_string()),
+        "P" => {
+            let written = arguments.split_once('=')
+                .and_then(|(index, value)| Some((parse_hex_number(index)?, parse_hex_bytes(value)?)))
+                .is_some_and(|(index, bytes)| write_register(emulator, index, &bytes));
+            if written { "OK".to_string() } else { "E01".to_string() }
+        },
+        "m" => read_memory(emulator, arguments),
+        "M" => write_memory(emulator, arguments),
+        "Z" => update_breakpoint(session, emulator, arguments, true),
+        "z" => update_breakpoint(session, emulator, arguments, false),
+        "c" => {
+            if let Some(address) = parse_hex_number(arguments) {
+                jump_to(emulator, address as u16);
+            }
+            return GdbAction::Continue;
+        },
+        "s" => {
+            if let Some(address) = parse_hex_number(arguments) {
+                jump_to(emulator, address as u16);
+            }
+            return GdbAction::Step;
+        },
+        "D" => return GdbAction::Detach,
+        "k" => return GdbAction::Detach,
+        "H" => "OK".to_string(),
+        "T" => "OK".to_string(),
+        "q" | "Q" => match packet {
+            _ if packet.starts_with("qSupported") => "PacketSize=4000;qXfer:features:read+;QStartNoAckMode+".to_string(),
+            _ if packet.starts_with("qXfer:features:read:") => read_target_description(&packet["qXfer:features:read:".len()..]),
+            "QStartNoAckMode" => {
+                session.no_ack_mode = true;
+                "OK".to_string()
+            },
+            "qAttached" => "1".to_string(),
+            "qC" => "QC1".to_string(),
+            "qfThreadInfo" => "m1".to_string(),
+            "qsThreadInfo" => "l".to_string(),
+            _ => String::new()
+        },
+        _ => String::new()
+    };
+    GdbAction::Reply(reply)
+}
+
+fn checksum(data: &str) -> u8 {
+    data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte))
+}
+
+pub fn encode_packet(data: &str) -> String {
+    format!("${}#{:02x}", data, checksum(data))
+}
+
+fn read_byte(stream: &mut TcpStream) -> io::Result<u8> {
+    let mut byte = [0];
+    stream.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+// Reads the next packet, skipping acknowledgements. Returns None if the debugger sent an interrupt instead.
+// Packets with a bad checksum are NAKed and skipped until the debugger resends one intact.
+fn read_packet(session: &GdbSession, stream: &mut TcpStream) -> io::Result<Option<String>> {
+    loop {
+        loop {
+            match read_byte(stream)? {
+                b'$' => break,
+                INTERRUPT_BYTE => return Ok(None),
+                _ => continue
+            }
+        }
+
+        let mut data = Vec::new();
+        loop {
+            match read_byte(stream)? {
+                b'#' => break,
+                // Escaped bytes are XORed with 0x20.
+                b'}' => data.push(read_byte(stream)? ^ 0x20),
+                byte => data.push(byte)
+            }
+        }
+
+        let received_checksum = [read_byte(stream)?, read_byte(stream)?];
+        let data = String::from_utf8_lossy(&data).into_owned();
+        let valid = std::str::from_utf8(&received_checksum).ok()
+            .and_then(|text| u8::from_str_radix(text, 16).ok()) == Some(checksum(&data));
+
+        if !session.no_ack_mode {
+            stream.write_all(if valid { b"+" } else { b"-" })?;
+        }
+
+        if valid {
+            return Ok(Some(data));
+        }
+    }
+}
+
+fn send_packet(stream: &mut TcpStream, data: &str) -> io::Result<()> {
+    stream.write_all(encode_packet(data).as_bytes())?;
+    stream.flush()
+}
+
+fn interrupt_requested(stream: &mut TcpStream) -> io::Result<bool> {
+    stream.set_nonblocking(true)?;
+    let mut byte = [0];
+    let result = match stream.read(&mut byte) {
+        Ok(1) => Ok(byte[0] == INTERRUPT_BYTE),
+        Ok(_) => Err(io::Error::new(ErrorKind::ConnectionAborted, "Debugger disconnected.")),
+        Err(error) if error.kind() == ErrorKind::WouldBlock => Ok(false),
+        Err(error) => Err(error)
+    };
+    stream.set_nonblocking(false)?;
+    result
+}
+
+fn resume(emulator: &mut Emulator) {
+    pause::resume(emulator);
+    breakpoints::skip_current_breakpoint(emulator);
+}
+
+fn run_until_stopped(emulator: &mut Emulator, stream: &mut TcpStream) -> io::Result<u8> {
+    resume(emulator);
+    let mut steps = 0;
+    loop {
+        emulator::step(emulator);
+        if pause::is_paused(emulator) {
+            return Ok(SIGTRAP);
+        }
+
+        steps += 1;
+        if steps % INTERRUPT_POLL_INTERVAL == 0 && interrupt_requested(stream)? {
+            emulator.pause.paused = true;
+            return Ok(SIGINT);
+        }
+    }
+}
+
+fn step_instruction(emulator: &mut Emulator) -> u8 {
+    resume(emulator);
+    emulator::step(emulator);
+    emulator.pause.paused = true;
+    SIGTRAP
+}
+
+// Serves a single debugger connection until it detaches. The emulator is stopped whenever the debugger isn't running it.
+pub fn serve(emulator: &mut Emulator, mut stream: TcpStream) -> io::Result<()> {
+    let mut session = GdbSession::new();
+    emulator.pause.paused = true;
+
+    loop {
+        let packet = match read_packet(&session, &mut stream)? {
+            Some(packet) => packet,
+            None => {
+                send_packet(&mut stream, &stop_reply(SIGINT))?;
+                continue;
+            }
+        };
+
+        match handle_packet(&mut session, emulator, &packet) {
+            GdbAction::Reply(reply) => send_packet(&mut stream, &reply)?,
+            GdbAction::Continue => {
+                let signal = run_until_stopped(emulator, &mut stream)?;
+                send_packet(&mut stream, &stop_reply(signal))?;
+            },
+            GdbAction::Step => {
+                let signal = step_instruction(emulator);
+                send_packet(&mut stream, &stop_reply(signal))?;
+            },
+            GdbAction::Detach => {
+                send_packet(&mut stream, "OK")?;
+                break;
+            }
+        }
+    }
+
+    for id in session.breakpoints.values() {
+        breakpoints::remove_breakpoint(emulator, *id);
+    }
+    pause::resume(emulator);
+    Ok(())
+}
+
+// Waits for a debugger to connect, then serves it until it detaches.
+pub fn listen(emulator: &mut Emulator, address: impl ToSocketAddrs) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    let (stream, _) = listener.accept()?;
+    serve(emulator, stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::{enable_processor_test_mode, initialize_screenless_emulator};
+    use super::*;
+
+    fn setup_emulator() -> Emulator {
+        let mut emulator = initialize_screenless_emulator();
+        enable_processor_test_mode(&mut emulator);
+        emulator.cpu.registers.program_counter = 0x151;
+        emulator
+    }
+
+    fn reply(session: &mut GdbSession, emulator: &mut Emulator, packet: &str) -> String {
+        match handle_packet(session, emulator, packet) {
+            GdbAction::Reply(reply) => reply,
+            action => panic!("Expected a reply but got {:?}", action)
+        }
+    }
+
+    #[test]
+    fn should_encode_packet_with_checksum() {
+        assert_eq!(encode_packet("OK"), "$OK#9a");
+        assert_eq!(encode_packet(""), "$#00");
+    }
+
+    #[test]
+    fn should_skip_packets_with_bad_checksum() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut debugger = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut stream, _) = listener.accept().unwrap();
+
+        debugger.write_all(b"$bad#00$bad#00$OK#9a").unwrap();
+        assert_eq!(read_packet(&GdbSession::new(), &mut stream).unwrap(), Some("OK".to_string()));
+
+        let mut acknowledgements = [0; 3];
+        debugger.read_exact(&mut acknowledgements).unwrap();
+        assert_eq!(&acknowledgements, b"--+");
+    }
+
+    #[test]
+    fn should_read_and_write_registers() {
+        let mut emulator = setup_emulator();
+        let mut session = GdbSession::new();
+        emulator.cpu.registers.a = 0x12;
+        emulator.cpu.registers.stack_pointer = 0xFFFE;
+
+        assert_eq!(reply(&mut session, &mut emulator, "g"), "1200000000000000feff5001");
+        assert_eq!(reply(&mut session, &mut emulator, "p9"), "5001");
+
+        emulator.memory.processor_test_ram[0x200] = 0x3C;
+        assert_eq!(reply(&mut session, &mut emulator, "P9=0002"), "OK");
+        assert_eq!(emulator.cpu.registers.program_counter, 0x201);
+        assert_eq!(emulator.cpu.registers.opcode, 0x3C);
+
+        assert_eq!(reply(&mut session, &mut emulator, "P1=ff"), "OK");
+        assert_eq!(emulator.cpu.registers.f, 0xF0);
+    }
+
+    #[test]
+    fn should_read_and_write_memory() {
+        let mut emulator = setup_emulator();
+        let mut session = GdbSession::new();
+        assert_eq!(reply(&mut session, &mut emulator, "Mc000,3:0a0b0c"), "OK");
+        assert_eq!(reply(&mut session, &mut emulator, "mc000,4"), "0a0b0c00");
+        assert_eq!(reply(&mut session, &mut emulator, "Mc000,2:0a"), "E01");
+    }
+
+    #[test]
+    fn should_map_breakpoints_onto_debugger_breakpoints() {
+        let mut emulator = setup_emulator();
+        let mut session = GdbSession::new();
+        assert_eq!(reply(&mut session, &mut emulator, "Z0,150,1"), "OK");
+        assert_eq!(emulator.breakpoints.breakpoints[0].address, 0x150);
+        assert_eq!(reply(&mut session, &mut emulator, "z0,150,1"), "OK");
+        assert!(emulator.breakpoints.breakpoints.is_empty());
+        assert_eq!(reply(&mut session, &mut emulator, "Z2,c000,1"), "");
+    }
+
+    #[test]
+    fn should_serve_target_description_in_chunks() {
+        let mut emulator = setup_emulator();
+        let mut session = GdbSession::new();
+        let first = reply(&mut session, &mut emulator, "qXfer:features:read:target.xml:0,10");
+        assert_eq!(first, format!("m{}", &TARGET_DESCRIPTION[..0x10]));
+
+        let offset = TARGET_DESCRIPTION.len() - 4;
+        let last = reply(&mut session, &mut emulator, &format!("qXfer:features:read:target.xml:{:x},100", offset));
+        assert_eq!(last, format!("l{}", &TARGET_DESCRIPTION[offset..]));
+
+        let rest = reply(&mut session, &mut emulator, &format!("qXfer:features:read:target.xml:{:x},{:x}", offset, usize::MAX));
+        assert_eq!(rest, last);
+    }
+
+    #[test]
+    fn should_resume_on_continue_and_step() {
+        let mut emulator = setup_emulator();
+        let mut session = GdbSession::new();
+        assert_eq!(handle_packet(&mut session, &mut emulator, "c"), GdbAction::Continue);
+        assert_eq!(handle_packet(&mut session, &mut emulator, "s"), GdbAction::Step);
+        assert_eq!(handle_packet(&mut session, &mut emulator, "D"), GdbAction::Detach);
+
+        emulator.memory.processor_test_ram[0x150] = 0x3C;
+        emulator.cpu.registers.opcode = 0x3C;
+        assert_eq!(step_instruction(&mut emulator), SIGTRAP);
+        assert_eq!(emulator.cpu.registers.a, 1);
+        assert!(pause::is_paused(&emulator));
+    }
+}
@@ -1,7 +1,11 @@
 use crate::emulator::Emulator;
 use crate::emulator::Mode;
 use crate::cpu::hdma;
+use crate::overlay;
 use crate::gpu::colors::{initialize_palettes, Palettes};
+use crate::gpu::changed_scanlines::{initialize_changed_scanlines, ChangedScanlinesState};
+use crate::gpu::frame_hash::{initialize_frame_hash, FrameHashState};
+use crate::gpu::scanline_registers::{initialize_scanline_registers, ScanlineRegistersState};
 use crate::gpu::constants::{GB_SCREEN_HEIGHT, GB_SCREEN_WIDTH, BYTES_PER_COLOR};
 use crate::gpu::scanline::write_scanline;
 use crate::gpu::sprites::{collect_scanline_sprites, Sprite};
@@ -9,6 +13,28 @@ use crate::gpu::utils::{get_lcd_enabled_mode, get_window_enabled_mode};
 use crate::utils::get_t_cycle_increment;
 use crate::utils::is_bit_set;
 
+pub trait LcdListener: Send {
+    fn lcd_changed(&mut self, enabled: bool);
+}
+
+pub struct NoopLcdListener;
+
+impl LcdListener for NoopLcdListener {
+    fn lcd_changed(&mut self, _: bool) {}
+}
+
+/*
+    Indexed frames hold one byte per pixel with the palette index it was drawn
+    with, for frontends that apply their own palette or drive monochrome displays.
+    That's the shade (0 to 3) after BGP/OBP mapping on the DMG, and the entry in
+    palette RAM (0 to 63, objects from 32) on the CGB. See colors.rs.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameFormat {
+    Rgba,
+    Indexed
+}
+
 #[derive(Debug)]
 pub struct GpuRegisters {
     pub lcdc: u8,
@@ -32,9 +58,15 @@ pub struct GpuState {
     pub mode_clock: u16,
     pub registers: GpuRegisters,
     pub frame_buffer: Vec<u8>,
+    pub frame_format: FrameFormat,
+    pub indexed_frame_buffer: Vec<u8>,
+    pub frame_hash: FrameHashState,
+    pub changed_scanlines: ChangedScanlinesState,
+    pub scanline_registers: ScanlineRegistersState,
     pub sprite_buffer: Vec<Sprite>,
     pub video_ram: [u8; 0x4000],
-    pub object_attribute_memory: [u8; 0xa0]
+    pub object_attribute_memory: [u8; 0xa0],
+    pub frame_count: u64
 }
 
 const OAM_MODE: u8 = 2;
@@ -53,7 +85,15 @@ const SCANLINE_RENDER_TIME: u16 = 456;
 const FRAME_SCANLINE_COUNT: u8 = 154;
 const VBLANK_SCANLINE_COUNT: u8 = 10;
 
+/*
+    LY only reads 153 for the first M-cycle of the last scanline before it wraps
+    to 0, which is when an LYC=0 coincidence fires. An M-cycle spans half as many
+    dots in CGB double speed mode, so the reset is measured in CPU cycles.
+*/
+const LAST_SCANLINE: u8 = FRAME_SCANLINE_COUNT - 1;
+
 const STAT_INTERRUPT_LYC_CHECK_BIT: u8 = 6;
+const STAT_WRITABLE_BITS: u8 = 0b01111000;
 const OAM_MODE_STAT_SOURCE_BIT: u8 = 5;
 const VBLANK_MODE_STAT_SOURCE_BIT: u8 = 4;
 const HBLANK_MODE_STAT_SOURCE_BIT: u8 = 3;
@@ -62,6 +102,10 @@ fn initialize_blank_frame() -> Vec<u8> {
     vec![0xFF; (GB_SCREEN_WIDTH * GB_SCREEN_HEIGHT * BYTES_PER_COLOR) as usize]
 }
 
+fn initialize_blank_indexed_frame() -> Vec<u8> {
+    vec![0; (GB_SCREEN_WIDTH * GB_SCREEN_HEIGHT) as usize]
+}
+
 pub fn initialize_gpu() -> GpuState {
     GpuState {
         mode: 2,
@@ -82,9 +126,53 @@ pub fn initialize_gpu() -> GpuState {
             key0: 0
         },
         frame_buffer: initialize_blank_frame(),
+        frame_format: FrameFormat::Rgba,
+        indexed_frame_buffer: Vec::new(),
+        frame_hash: initialize_frame_hash(),
+        changed_scanlines: initialize_changed_scanlines(),
+        scanline_registers: initialize_scanline_registers(),
         sprite_buffer: Vec::new(),
         video_ram: [0; 0x4000],
-        object_attribute_memory: [0; 0xa0]
+        object_attribute_memory: [0; 0xa0],
+        frame_count: 0
+    }
+}
+
+fn render_frame(emulator: &Emulator) {
+    match emulator.gpu.frame_format {
+        FrameFormat::Rgba => (emulator.render)(&emulator.gpu.frame_buffer),
+        FrameFormat::Indexed => (emulator.render)(&emulator.gpu.indexed_frame_buffer)
+    }
+}
+
+/*
+    Hands each line to the frontend as soon as it's drawn, in the current frame
+    format, for frontends that race the beam to cut latency. Lines come in the
+    order the LCD draws them, 0 to 143, and the frame still goes to the render
+    callback once it's complete. The overlay is only drawn onto whole frames.
+*/
+fn render_scanline(emulator: &Emulator) {
+    if let Some(render_scanline) = emulator.render_scanline {
+        let ly = emulator.gpu.registers.ly;
+        let line_length = GB_SCREEN_WIDTH as usize;
+        match emulator.gpu.frame_format {
+            FrameFormat::Rgba => {
+                let start = ly as usize * line_length * BYTES_PER_COLOR as usize;
+                render_scanline(ly, &emulator.gpu.frame_buffer[start..start + line_length * BYTES_PER_COLOR as usize]);
+            },
+            FrameFormat::Indexed => {
+                let start = ly as usize * line_length;
+                render_scanline(ly, &emulator.gpu.indexed_frame_buffer[start..start + line_length]);
+            }
+        }
+    }
+}
+
+// The indexed frame buffer is only allocated once a frontend asks for it.
+pub fn set_frame_format(emulator: &mut Emulator, frame_format: FrameFormat) {
+    emulator.gpu.frame_format = frame_format;
+    if frame_format == FrameFormat::Indexed && emulator.gpu.indexed_frame_buffer.is_empty() {
+        emulator.gpu.indexed_frame_buffer = initialize_blank_indexed_frame();
     }
 }
 
@@ -149,7 +237,9 @@ pub fn step(emulator: &mut Emulator) {
                     emulator.gpu.mode_clock = 0;
                     update_mode(emulator, HBLANK_MODE);
                     hdma::set_hblank_started(emulator, true);
+                    scanline_registers::record_scanline(emulator);
                     write_scanline(emulator);
+                    render_scanline(emulator);
                 }
             }
             HBLANK_MODE => {
@@ -165,7 +255,12 @@ pub fn step(emulator: &mut Emulator) {
 
                     if emulator.gpu.registers.ly == FRAME_SCANLINE_COUNT - VBLANK_SCANLINE_COUNT - 1 {
                         update_mode(emulator, VBLANK_MODE);
-                        (emulator.render)(&emulator.gpu.frame_buffer);
+                        emulator.gpu.frame_count += 1;
+                        frame_hash::complete_frame(emulator);
+                        overlay::draw_overlay(emulator);
+                        changed_scanlines::complete_frame(emulator);
+                        scanline_registers::complete_frame(emulator);
+                        render_frame(emulator);
                         fire_vblank_interrupt(emulator);
                     }
                     else {
@@ -179,16 +274,27 @@ pub fn step(emulator: &mut Emulator) {
                 }
             }
             VBLANK_MODE => {
+                let ly = emulator.gpu.registers.ly;
+
                 if emulator.gpu.mode_clock >= SCANLINE_RENDER_TIME {
                     emulator.gpu.mode_clock = 0;
-                    emulator.gpu.registers.ly += 1;
-    
-                    if emulator.gpu.registers.ly > FRAME_SCANLINE_COUNT - 1 {
+
+                    if ly == LAST_SCANLINE || ly == 0 {
                         emulator.gpu.registers.ly = 0;
                         emulator.gpu.registers.wly = 0;
                         update_mode(emulator, OAM_MODE);
                     }
-    
+                    else {
+                        emulator.gpu.registers.ly += 1;
+                    }
+
+                    // LY already reset to 0 partway through line 153, so it has been compared.
+                    if ly != 0 {
+                        compare_ly_and_lyc(emulator);
+                    }
+                }
+                else if ly == LAST_SCANLINE && emulator.gpu.mode_clock >= get_t_cycle_increment(double_speed_mode) as u16 {
+                    emulator.gpu.registers.ly = 0;
                     compare_ly_and_lyc(emulator);
                 }
             }
@@ -198,7 +304,7 @@ pub fn step(emulator: &mut Emulator) {
 }
 
 pub fn get_cgb_bcpd(emulator: &Emulator) -> u8 {
-    if emulator.mode == Mode::CGB {
+    if emulator.mode == Mode::CGB && !vram_locked(emulator) {
         colors::get_cgb_bcpd(&emulator.gpu.registers.palettes)
     }
     else {
@@ -208,6 +314,8 @@ pub fn get_cgb_bcpd(emulator: &Emulator) -> u8 {
 
 pub fn set_cgb_bcpd(emulator: &mut Emulator, value: u8) {
     if emulator.mode == Mode::CGB {
+        // A blocked write leaves the palette alone but still advances the index.
+        let value = if vram_locked(emulator) { colors::get_cgb_bcpd(&emulator.gpu.registers.palettes) } else { value };
         colors::set_cgb_bcpd(&mut emulator.gpu.registers.palettes, value);
     }
 }
@@ -228,7 +336,7 @@ pub fn set_cgb_bcps(emulator: &mut Emulator, value: u8) {
 }
 
 pub fn get_cgb_ocpd(emulator: &Emulator) -> u8 {
-    if emulator.mode == Mode::CGB {
+    if emulator.mode == Mode::CGB && !vram_locked(emulator) {
         colors::get_cgb_ocpd(&emulator.gpu.registers.palettes)
     }
     else {
@@ -238,6 +346,7 @@ pub fn get_cgb_ocpd(emulator: &Emulator) -> u8 {
 
 pub fn set_cgb_ocpd(emulator: &mut Emulator, value: u8) {
     if emulator.mode == Mode::CGB {
+        let value = if vram_locked(emulator) { colors::get_cgb_ocpd(&emulator.gpu.registers.palettes) } else { value };
         colors::set_cgb_ocpd(&mut emulator.gpu.registers.palettes, value);
     }
 }
@@ -277,6 +386,22 @@ pub fn set_video_ram_byte(emulator: &mut Emulator, index: u16, value: u8) {
     emulator.gpu.video_ram[calculated_index as usize] = value;
 }
 
+/*
+    The PPU fetches tiles, and on the CGB palette colors, throughout pixel transfer,
+    so CPU reads return 0xFF and writes are dropped. The DMG has no palette RAM and
+    its palette registers stay writable, which is what mid-line BGP effects rely on.
+    A scanline is drawn in one go when pixel transfer ends, so without the lock a
+    write anywhere in mode 3 would land on the whole line rather than being blocked.
+*/
+pub fn vram_locked(emulator: &Emulator) -> bool {
+    emulator.accuracy.vram_conflicts && lcd_enabled(emulator) && emulator.gpu.mode == VRAM_MODE
+}
+
+// The PPU reads OAM during OAM scan and pixel transfer.
+pub fn oam_locked(emulator: &Emulator) -> bool {
+    lcd_enabled(emulator) && (emulator.gpu.mode == OAM_MODE || emulator.gpu.mode == VRAM_MODE)
+}
+
 pub fn get_object_attribute_memory_byte(emulator: &Emulator, index: u16) -> u8 {
     emulator.gpu.object_attribute_memory[index as usize]
 }
@@ -315,11 +440,43 @@ pub fn set_cgb_opri(emulator: &mut Emulator, value: u8) {
     }
 }
 
+// Bit 7 is unused and always reads back as set.
+pub fn get_stat(emulator: &Emulator) -> u8 {
+    emulator.gpu.registers.stat | 0b10000000
+}
+
+// The mode and coincidence bits are read-only.
+pub fn set_stat(emulator: &mut Emulator, value: u8) {
+    let stat = emulator.gpu.registers.stat;
+    emulator.gpu.registers.stat = (stat & !STAT_WRITABLE_BITS) | (value & STAT_WRITABLE_BITS);
+}
+
+pub fn set_lyc(emulator: &mut Emulator, value: u8) {
+    emulator.gpu.registers.lyc = value;
+    if get_lcd_enabled_mode(emulator.gpu.registers.lcdc) {
+        compare_ly_and_lyc(emulator);
+    }
+}
+
 pub fn get_lcdc(emulator: &Emulator) -> u8 {
     emulator.gpu.registers.lcdc
 }
 
+pub fn lcd_enabled(emulator: &Emulator) -> bool {
+    get_lcd_enabled_mode(emulator.gpu.registers.lcdc)
+}
+
+#[cfg(any(test, feature = "internals"))]
+pub fn set_lcd_listener(emulator: &mut Emulator, listener: Box<dyn LcdListener>) {
+    emulator.lcd_listener = listener;
+}
+
+/*
+    No frames are rendered while the LCD is off, so the blank frame is pushed
+    straight away rather than leaving the frontend showing a stale one.
+*/
 pub fn set_lcdc(emulator: &mut Emulator, value: u8) {
+    let was_enabled = lcd_enabled(emulator);
     emulator.gpu.registers.lcdc = value;
     let lcd_enabled = get_lcd_enabled_mode(emulator.gpu.registers.lcdc);
     if !lcd_enabled {
@@ -328,9 +485,19 @@ pub fn set_lcdc(emulator: &mut Emulator, value: u8) {
         emulator.gpu.mode_clock = 0;
         emulator.gpu.mode = HBLANK_MODE;
         emulator.gpu.registers.stat = (emulator.gpu.registers.stat & 0b11111100) | HBLANK_MODE;
-        emulator.gpu.frame_buffer = initialize_blank_frame();
+        emulator.gpu.frame_buffer.fill(0xFF);
+        emulator.gpu.indexed_frame_buffer.fill(0);
+        frame_hash::discard_frame(&mut emulator.gpu.frame_hash);
+        changed_scanlines::mark_all_changed(&mut emulator.gpu.changed_scanlines);
         emulator.gpu.sprite_buffer = Vec::new();
     }
+
+    if lcd_enabled != was_enabled {
+        if !lcd_enabled {
+            render_frame(emulator);
+        }
+        emulator.lcd_listener.lcd_changed(lcd_enabled);
+    }
 }
 
 pub fn set_key0(emulator: &mut Emulator, value: u8) {
@@ -348,12 +515,15 @@ pub fn has_dmg_compatability(emulator: &Emulator) -> bool {
 #[cfg(test)]
 mod tests;
 
-mod colors;
-mod constants;
+pub mod changed_scanlines;
+pub mod colors;
+pub mod constants;
+pub mod frame_hash;
 mod line_addressing;
 mod background;
 mod window;
 mod prioritization;
 pub mod scanline;
+pub mod scanline_registers;
 pub mod sprites;
 pub mod utils;
\ No newline at end of file
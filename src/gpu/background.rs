@@ -1,46 +1,64 @@
 use crate::emulator::{is_cgb, Emulator};
 use crate::gpu::has_dmg_compatability;
-use crate::gpu::colors::{as_dmg_bg_color_rgb, as_cgb_bg_color_rgb, calculate_color_id};
+use crate::gpu::colors::{as_dmg_bg_color_rgb, as_dmg_bg_palette_index, as_cgb_bg_color_rgb, as_cgb_bg_palette_index, decode_tile_row, Color};
 use crate::gpu::line_addressing::{calculate_bg_tile_map_index, calculate_tile_data_index, get_cgb_tile_attributes};
 use crate::gpu::prioritization::BackgroundPixel;
 use crate::gpu::utils::get_tile_line_bytes;
 
-pub fn read_bg_color(emulator: &Emulator, viewport_x: u8) -> BackgroundPixel {
-    let scx = emulator.gpu.registers.scx;
-    let scy = emulator.gpu.registers.scy;
-    let ly = emulator.gpu.registers.ly;
-    let lcdc = emulator.gpu.registers.lcdc;
+pub const TILE_WIDTH: usize = 8;
 
-    let x = scx.wrapping_add(viewport_x);
-    let y = scy.wrapping_add(ly);
-
-    let column_tile_offset = y / 8;
-    let row_tile_offset = x / 8;
-
-    let tile_map_index = calculate_bg_tile_map_index(lcdc, column_tile_offset, row_tile_offset);
+// Decodes all 8 pixels of a background or window tile's row at once, resolving each of its colors once.
+pub fn read_tile_row(emulator: &Emulator, tile_map_index: u16, row_offset: u8) -> [BackgroundPixel; TILE_WIDTH] {
+    let lcdc = emulator.gpu.registers.lcdc;
+    let palettes = &emulator.gpu.registers.palettes;
     let tile_index = emulator.gpu.video_ram[tile_map_index as usize];
     let tile_data_index = calculate_tile_data_index(lcdc, tile_index);
 
-    let row_offset = y % 8;
-    let bit_index = x % 8;
-
     if is_cgb(emulator) {
         let attributes = get_cgb_tile_attributes(emulator, tile_map_index);
         let (lsb_byte, msb_byte) = get_tile_line_bytes(&emulator.gpu, tile_data_index, row_offset, attributes.y_flip, attributes.from_bank_one);
 
         let dmg_compatible = has_dmg_compatability(emulator);
         let palette_number = if dmg_compatible { 0 } else { attributes.palette_number };
-        let color_id = calculate_color_id(bit_index, msb_byte, lsb_byte, attributes.x_flip);
-        let color = as_cgb_bg_color_rgb(&emulator.gpu.registers.palettes, palette_number, color_id, dmg_compatible);
+        let colors: [Color; 4] = std::array::from_fn(|color_id| as_cgb_bg_color_rgb(palettes, palette_number, color_id as u8, dmg_compatible));
+        let palette_indices: [u8; 4] = std::array::from_fn(|color_id| as_cgb_bg_palette_index(palettes, palette_number, color_id as u8, dmg_compatible));
 
-        BackgroundPixel { color, color_id, prioritize_bg: attributes.priority }
+        decode_tile_row(msb_byte, lsb_byte, attributes.x_flip)
+            .map(|color_id| BackgroundPixel { color: colors[color_id as usize], color_id, palette_index: palette_indices[color_id as usize], prioritize_bg: attributes.priority })
     }
     else {
         let (lsb_byte, msb_byte) = get_tile_line_bytes(&emulator.gpu, tile_data_index, row_offset, false, false);
+        let colors: [Color; 4] = std::array::from_fn(|color_id| as_dmg_bg_color_rgb(palettes, color_id as u8));
+        let palette_indices: [u8; 4] = std::array::from_fn(|color_id| as_dmg_bg_palette_index(palettes, color_id as u8));
+
+        decode_tile_row(msb_byte, lsb_byte, false)
+            .map(|color_id| BackgroundPixel { color: colors[color_id as usize], color_id, palette_index: palette_indices[color_id as usize], prioritize_bg: false })
+    }
+}
+
+pub fn read_bg_line(emulator: &Emulator, line: &mut [BackgroundPixel]) {
+    let scx = emulator.gpu.registers.scx;
+    let scy = emulator.gpu.registers.scy;
+    let ly = emulator.gpu.registers.ly;
+    let lcdc = emulator.gpu.registers.lcdc;
+
+    let y = scy.wrapping_add(ly);
+    let column_tile_offset = y / 8;
+    let row_offset = y % 8;
+
+    let mut viewport_x = 0;
+    let mut x = scx;
+
+    while viewport_x < line.len() {
+        let tile_map_index = calculate_bg_tile_map_index(lcdc, column_tile_offset, x / 8);
+        let tile_row = read_tile_row(emulator, tile_map_index, row_offset);
+
+        // The first tile is cut off by the fine scroll, and the last one by the edge of the screen.
+        let first_pixel = (x % 8) as usize;
+        let pixels = (TILE_WIDTH - first_pixel).min(line.len() - viewport_x);
+        line[viewport_x..viewport_x + pixels].copy_from_slice(&tile_row[first_pixel..first_pixel + pixels]);
 
-        let color_id = calculate_color_id(bit_index, msb_byte, lsb_byte, false);
-        let color = as_dmg_bg_color_rgb(&emulator.gpu.registers.palettes, color_id);
-        
-        BackgroundPixel { color, color_id, prioritize_bg: false }
+        viewport_x += pixels;
+        x = x.wrapping_add(pixels as u8);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,73 @@
+use crate::emulator::Emulator;
+use crate::gpu::constants::GB_SCREEN_HEIGHT;
+
+const SCREEN_HEIGHT: usize = GB_SCREEN_HEIGHT as usize;
+
+/*
+    Tracks which scanlines of the frame buffer changed since the previous frame,
+    so frontends on slow displays (SPI LCDs, terminals) can redraw only those rows.
+    Pixels are compared as they're written, so a line only counts as changed if
+    it actually looks different, not just because it was drawn again.
+*/
+#[derive(Debug)]
+pub struct ChangedScanlinesState {
+    drawing: [bool; SCREEN_HEIGHT],
+    last_frame: [bool; SCREEN_HEIGHT]
+}
+
+pub fn initialize_changed_scanlines() -> ChangedScanlinesState {
+    ChangedScanlinesState {
+        drawing: [true; SCREEN_HEIGHT],
+        last_frame: [true; SCREEN_HEIGHT]
+    }
+}
+
+pub fn mark_scanline_changed(state: &mut ChangedScanlinesState, ly: u8) {
+    if let Some(changed) = state.drawing.get_mut(ly as usize) {
+        *changed = true;
+    }
+}
+
+// E.g. when the frame buffer is cleared by switching the LCD off, or an overlay is drawn over it.
+pub fn mark_all_changed(state: &mut ChangedScanlinesState) {
+    state.drawing = [true; SCREEN_HEIGHT];
+}
+
+pub fn complete_frame(emulator: &mut Emulator) {
+    let state = &mut emulator.gpu.changed_scanlines;
+    state.last_frame = state.drawing;
+    state.drawing = [false; SCREEN_HEIGHT];
+}
+
+// Rows of the last completed frame that differ from the frame before it, from top to bottom.
+pub fn get_changed_scanlines(emulator: &Emulator) -> Vec<u8> {
+    let state = &emulator.gpu.changed_scanlines;
+    (0..SCREEN_HEIGHT as u8).filter(|ly| state.last_frame[*ly as usize]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::initialize_screenless_emulator;
+    use super::*;
+
+    #[test]
+    fn should_report_every_scanline_for_first_frame() {
+        let mut emulator = initialize_screenless_emulator();
+        complete_frame(&mut emulator);
+        assert_eq!(get_changed_scanlines(&emulator).len(), SCREEN_HEIGHT);
+    }
+
+    #[test]
+    fn should_only_report_scanlines_changed_since_last_frame() {
+        let mut emulator = initialize_screenless_emulator();
+        complete_frame(&mut emulator);
+
+        mark_scanline_changed(&mut emulator.gpu.changed_scanlines, 3);
+        mark_scanline_changed(&mut emulator.gpu.changed_scanlines, 100);
+        complete_frame(&mut emulator);
+        assert_eq!(get_changed_scanlines(&emulator), vec![3, 100]);
+
+        complete_frame(&mut emulator);
+        assert!(get_changed_scanlines(&emulator).is_empty());
+    }
+}
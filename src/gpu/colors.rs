@@ -13,6 +13,21 @@ pub const CGB_PALETTES: usize = 8;
 
 const MONOCHROME_COLORS: [Color; 4] = [WHITE, LIGHT_GRAY, DARK_GRAY, BLACK];
 
+/*
+    Raw CGB colors look oversaturated on modern displays, as the original LCDs
+    blended the channels together and had a much flatter response. The GBC and
+    GBA curves approximate how each handheld's screen displayed them.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorCorrection {
+    Raw,
+    Gbc,
+    Gba
+}
+
+const GBA_LCD_GAMMA: f32 = 4.0;
+const GBA_OUTPUT_GAMMA: f32 = 2.2;
+
 #[derive(Debug)]
 pub struct Palettes {
     pub bgp: u8,
@@ -21,7 +36,8 @@ pub struct Palettes {
     pub cgb_bcpd: [u8; COLORS_PER_PALETTE * CGB_PALETTES * 2],
     pub cgb_ocpd: [u8; COLORS_PER_PALETTE * CGB_PALETTES * 2],
     pub cgb_bcps: u8,
-    pub cgb_ocps: u8
+    pub cgb_ocps: u8,
+    pub color_correction: ColorCorrection
 }
 
 pub fn initialize_palettes() -> Palettes {
@@ -32,7 +48,8 @@ pub fn initialize_palettes() -> Palettes {
         cgb_bcpd: [0; COLORS_PER_PALETTE * CGB_PALETTES * 2],
         cgb_ocpd: [0; COLORS_PER_PALETTE * CGB_PALETTES * 2],
         cgb_bcps: 0,
-        cgb_ocps: 0
+        cgb_ocps: 0,
+        color_correction: ColorCorrection::Raw
     }
 }
 
@@ -43,6 +60,25 @@ pub fn calculate_color_id(bit_index: u8, msb_byte: u8, lsb_byte: u8, x_flip: boo
     (msb * 2) + lsb
 }
 
+// Interleaves the bits of a byte with zeros, so bit n moves to bit 2n.
+fn spread_bits(byte: u8) -> u16 {
+    let mut bits = byte as u16;
+    bits = (bits | (bits << 4)) & 0x0F0F;
+    bits = (bits | (bits << 2)) & 0x3333;
+    (bits | (bits << 1)) & 0x5555
+}
+
+/*
+    Decodes the color IDs of a whole tile row, from left to right. Rather than testing
+    each pixel's bit in both bytes, the two bitplanes are interleaved into one word
+    holding every pixel's 2-bit color ID, which are then read off in turn.
+*/
+pub fn decode_tile_row(msb_byte: u8, lsb_byte: u8, x_flip: bool) -> [u8; 8] {
+    let (msb_byte, lsb_byte) = if x_flip { (msb_byte.reverse_bits(), lsb_byte.reverse_bits()) } else { (msb_byte, lsb_byte) };
+    let color_ids = (spread_bits(msb_byte) << 1) | spread_bits(lsb_byte);
+    std::array::from_fn(|pixel| ((color_ids >> ((7 - pixel) * 2)) & 0b11) as u8)
+}
+
 fn as_bg_color_key(color_id: u8, palette: u8) -> u8 {
     match color_id {
         0b11 => (palette & 0b11000000) >> 6,
@@ -90,21 +126,70 @@ pub fn as_dmg_obj_color_rgb(palettes: &Palettes, palette_number: u8, color_id: u
     maybe_key.map(|key| MONOCHROME_COLORS[key as usize])
 }
 
+/*
+    Indexed frames record which palette entry each pixel was drawn with: on the DMG the shade (0 is
+    white, 3 is black) picked by BGP, OBP0 or OBP1, and on the CGB the color's entry in palette RAM,
+    with the 32 background colors first and the 32 object colors after them.
+*/
+pub const CGB_OBJ_PALETTE_INDEX_START: u8 = 32;
+
+pub fn as_dmg_bg_palette_index(palettes: &Palettes, color_id: u8) -> u8 {
+    as_dmg_bg_color_key(palettes, color_id)
+}
+
+pub fn as_dmg_obj_palette_index(palettes: &Palettes, palette_number: u8, color_id: u8) -> u8 {
+    as_dmg_obj_color_key(palettes, palette_number, color_id).unwrap_or(0)
+}
+
+pub fn as_cgb_bg_palette_index(palettes: &Palettes, palette_number: u8, color_id: u8, dmg_compatible: bool) -> u8 {
+    let entry = if dmg_compatible { as_dmg_bg_color_key(palettes, color_id) } else { color_id };
+    palette_number * COLORS_PER_PALETTE as u8 + entry
+}
+
+pub fn as_cgb_obj_palette_index(palettes: &Palettes, palette_number: u8, color_id: u8, dmg_compatible: bool) -> u8 {
+    let entry = if dmg_compatible { as_dmg_obj_palette_index(palettes, palette_number, color_id) } else { color_id };
+    CGB_OBJ_PALETTE_INDEX_START + palette_number * COLORS_PER_PALETTE as u8 + entry
+}
+
 fn calculate_cgb_palette_data_index(palette_number: u8, color_id: u8) -> usize {
     ((palette_number as usize * COLORS_PER_PALETTE) + color_id as usize) * 2
 }
 
-fn rgb555_as_color(rgb555: u16) -> Color {
+fn scale_channel(channel: u16) -> u8 {
+    // Takes the five bits of a color channel and scales them to eight bits.
+    ((channel * 0xFF) / 31) as u8
+}
+
+fn correct_gbc_color(red: u16, green: u16, blue: u16) -> Color {
+    let corrected_red = (red * 26 + green * 4 + blue * 2).min(960) >> 2;
+    let corrected_green = (green * 24 + blue * 8).min(960) >> 2;
+    let corrected_blue = (red * 6 + green * 4 + blue * 22).min(960) >> 2;
+    [corrected_red as u8, corrected_green as u8, corrected_blue as u8, 0xFF]
+}
+
+fn correct_gba_color(red: u16, green: u16, blue: u16) -> Color {
+    let linear_red = (red as f32 / 31.0).powf(GBA_LCD_GAMMA);
+    let linear_green = (green as f32 / 31.0).powf(GBA_LCD_GAMMA);
+    let linear_blue = (blue as f32 / 31.0).powf(GBA_LCD_GAMMA);
+
+    let as_output = |mixed: f32| ((mixed / 255.0).powf(1.0 / GBA_OUTPUT_GAMMA) * (255.0 * 255.0 / 280.0)) as u8;
+
+    let corrected_red = as_output(255.0 * linear_red + 50.0 * linear_green);
+    let corrected_green = as_output(10.0 * linear_red + 230.0 * linear_green + 30.0 * linear_blue);
+    let corrected_blue = as_output(50.0 * linear_red + 10.0 * linear_green + 220.0 * linear_blue);
+    [corrected_red, corrected_green, corrected_blue, 0xFF]
+}
+
+fn rgb555_as_color(rgb555: u16, color_correction: ColorCorrection) -> Color {
     let red = rgb555 & 0b11111;
     let green = (rgb555 >> 5) & 0b11111;
     let blue = (rgb555 >> 10) & 0b11111;
 
-    // Takes the five bits of each color channel and scales them to eight bits.
-    let scaled_red = (red * 0xFF) / 31;
-    let scaled_green = (green * 0xFF) / 31;
-    let scaled_blue = (blue * 0xFF) / 31;
-
-    [scaled_red as u8, scaled_green as u8, scaled_blue as u8, 0xFF]
+    match color_correction {
+        ColorCorrection::Raw => [scale_channel(red), scale_channel(green), scale_channel(blue), 0xFF],
+        ColorCorrection::Gbc => correct_gbc_color(red, green, blue),
+        ColorCorrection::Gba => correct_gba_color(red, green, blue)
+    }
 }
 
 fn lookup_cgb_background_palette(palettes: &Palettes, palette_number: u8, color_id: u8) -> u16 {
@@ -125,7 +210,7 @@ pub fn as_cgb_bg_color_rgb(palettes: &Palettes, palette_number: u8, color_id: u8
     else {
         lookup_cgb_background_palette(palettes, palette_number, color_id)
     };
-    rgb555_as_color(palette)
+    rgb555_as_color(palette, palettes.color_correction)
 }
 
 pub fn as_cgb_obj_color_rgb(palettes: &Palettes, palette_number: u8, color_id: u8, dmg_compatible: bool) -> Option<Color> {
@@ -141,7 +226,7 @@ pub fn as_cgb_obj_color_rgb(palettes: &Palettes, palette_number: u8, color_id: u
             }
         }
     };
-    maybe_palette.map(rgb555_as_color)
+    maybe_palette.map(|palette| rgb555_as_color(palette, palettes.color_correction))
 }
 
 pub fn get_cgb_bcps(palettes: &Palettes) -> u8 {
@@ -172,7 +257,7 @@ pub fn set_cgb_bcpd(palettes: &mut Palettes, value: u8) {
     let should_auto_increment = is_bit_set(palettes.cgb_bcps, 7);
     
     palettes.cgb_bcps = if should_auto_increment {
-        (palettes.cgb_bcps + 1) | 0x80
+        (palettes.cgb_bcps.wrapping_add(1) & 0x3F) | 0x80
     }
     else {
         palettes.cgb_bcps
@@ -191,7 +276,7 @@ pub fn set_cgb_ocpd(palettes: &mut Palettes, value: u8) {
     let should_auto_increment = is_bit_set(palettes.cgb_ocps, 7);
 
     palettes.cgb_ocps = if should_auto_increment {
-        (palettes.cgb_ocps + 1) | 0x80
+        (palettes.cgb_ocps.wrapping_add(1) & 0x3F) | 0x80
     }
     else {
         palettes.cgb_ocps
@@ -439,4 +524,38 @@ mod tests {
 
         assert_eq!(color, None);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn should_blend_channels_with_gbc_color_correction() {
+        assert_eq!(rgb555_as_color(0x7FFF, ColorCorrection::Gbc), [0xF0, 0xF0, 0xF0, 0xFF]);
+        assert_eq!(rgb555_as_color(0x001F, ColorCorrection::Gbc), [0xC9, 0x00, 0x2E, 0xFF]);
+    }
+
+    #[test]
+    fn should_desaturate_colors_with_gba_color_correction() {
+        assert_eq!(rgb555_as_color(0x0000, ColorCorrection::Gba), [0x00, 0x00, 0x00, 0xFF]);
+
+        let red = rgb555_as_color(0x001F, ColorCorrection::Gba);
+        assert!(red[0] > red[1] && red[1] > 0);
+    }
+
+    #[test]
+    fn should_apply_selected_color_correction_to_palette_lookups() {
+        let mut palettes = initialize_palettes();
+        setup_test_background_palettes(&mut palettes);
+        palettes.color_correction = ColorCorrection::Gbc;
+
+        let color = as_cgb_bg_color_rgb(&palettes, 3, 1, false);
+        assert_ne!(color, [0x73, 0xBD, 0xDE, 0xFF]);
+    }
+
+    #[test]
+    fn should_decode_tile_row_like_individual_pixels() {
+        for (msb_byte, lsb_byte) in [(0x7E, 0x3C), (0x5E, 0x42), (0xA5, 0x0F), (0xFF, 0x00)] {
+            for x_flip in [false, true] {
+                let expected: Vec<u8> = (0..8).map(|bit_index| calculate_color_id(bit_index, msb_byte, lsb_byte, x_flip)).collect();
+                assert_eq!(decode_tile_row(msb_byte, lsb_byte, x_flip).to_vec(), expected);
+            }
+        }
+    }
+}
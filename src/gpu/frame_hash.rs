@@ -0,0 +1,89 @@
+use crate::emulator::Emulator;
+
+const FNV_OFFSET_BASIS: u64 = 0xCBF29CE484222325;
+const FNV_PRIME: u64 = 0x100000001B3;
+
+#[derive(Debug)]
+pub struct FrameHashState {
+    pub enabled: bool,
+    pub running_hash: u64,
+    pub last_frame_hash: Option<u64>
+}
+
+pub fn initialize_frame_hash() -> FrameHashState {
+    FrameHashState {
+        enabled: false,
+        running_hash: FNV_OFFSET_BASIS,
+        last_frame_hash: None
+    }
+}
+
+pub fn set_frame_hashing(emulator: &mut Emulator, enabled: bool) {
+    emulator.gpu.frame_hash = initialize_frame_hash();
+    emulator.gpu.frame_hash.enabled = enabled;
+}
+
+/*
+    Frames are hashed a scanline at a time with FNV-1a as they're written, so
+    finishing a frame costs nothing extra. The overlay is drawn afterwards and
+    isn't part of the hash.
+*/
+pub fn hash_scanline(frame_hash: &mut FrameHashState, scanline: &[u8]) {
+    if frame_hash.enabled {
+        frame_hash.running_hash = scanline.iter().fold(frame_hash.running_hash, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(FNV_PRIME)
+        });
+    }
+}
+
+// Discards a partially drawn frame, e.g. when the LCD is switched off midway.
+pub fn discard_frame(frame_hash: &mut FrameHashState) {
+    frame_hash.running_hash = FNV_OFFSET_BASIS;
+}
+
+pub fn complete_frame(emulator: &mut Emulator) {
+    let frame_hash = &mut emulator.gpu.frame_hash;
+    if frame_hash.enabled {
+        frame_hash.last_frame_hash = Some(frame_hash.running_hash);
+        frame_hash.running_hash = FNV_OFFSET_BASIS;
+    }
+}
+
+// Returns the hash of the last completed frame, if hashing was enabled while it was drawn.
+pub fn get_frame_hash(emulator: &Emulator) -> Option<u64> {
+    emulator.gpu.frame_hash.last_frame_hash
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::initialize_screenless_emulator;
+    use super::*;
+
+    fn draw_frame(emulator: &mut Emulator, scanline: &[u8]) {
+        for _ in 0..144 {
+            hash_scanline(&mut emulator.gpu.frame_hash, scanline);
+        }
+        complete_frame(emulator);
+    }
+
+    #[test]
+    fn should_not_hash_frames_unless_enabled() {
+        let mut emulator = initialize_screenless_emulator();
+        draw_frame(&mut emulator, &[0xFF; 640]);
+        assert_eq!(get_frame_hash(&emulator), None);
+    }
+
+    #[test]
+    fn should_produce_same_hash_for_identical_frames() {
+        let mut emulator = initialize_screenless_emulator();
+        set_frame_hashing(&mut emulator, true);
+
+        draw_frame(&mut emulator, &[0xFF; 640]);
+        let first_hash = get_frame_hash(&emulator);
+        draw_frame(&mut emulator, &[0xFF; 640]);
+        assert_eq!(get_frame_hash(&emulator), first_hash);
+
+        draw_frame(&mut emulator, &[0xA9; 640]);
+        assert_ne!(get_frame_hash(&emulator), first_hash);
+    }
+}
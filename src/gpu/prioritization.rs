@@ -1,36 +1,40 @@
 use crate::gpu::colors::{Color, WHITE};
 
+#[derive(Clone, Copy, Default)]
 pub struct BackgroundPixel {
     pub color: Color,
     pub color_id: u8,
+    pub palette_index: u8,
     pub prioritize_bg: bool
 }
 
 pub struct SpritePixel {
     pub color: Color,
+    pub palette_index: u8,
     pub prioritize_bg: bool
 }
 
-pub fn resolve_highest_priority_pixel(cgb_mode: bool, lcdc_bg_and_window_priority: bool, bg_pixel: BackgroundPixel, maybe_sprite_pixel: Option<SpritePixel>) -> Color {
+// Returns the color to draw along with its palette index, for indexed frames.
+pub fn resolve_highest_priority_pixel(cgb_mode: bool, lcdc_bg_and_window_priority: bool, bg_pixel: BackgroundPixel, maybe_sprite_pixel: Option<SpritePixel>) -> (Color, u8) {
     match maybe_sprite_pixel {
         Some(sprite_pixel) if !cgb_mode => {
             if (bg_pixel.color_id == 0 && sprite_pixel.prioritize_bg) || !sprite_pixel.prioritize_bg {
-                sprite_pixel.color
+                (sprite_pixel.color, sprite_pixel.palette_index)
             }
             else {
-                bg_pixel.color
+                (bg_pixel.color, bg_pixel.palette_index)
             }
         },
         Some(sprite_pixel) => {
             if bg_pixel.color_id == 0 || !lcdc_bg_and_window_priority || (!bg_pixel.prioritize_bg && !sprite_pixel.prioritize_bg) {
-                sprite_pixel.color
+                (sprite_pixel.color, sprite_pixel.palette_index)
             }
             else {
-                bg_pixel.color
+                (bg_pixel.color, bg_pixel.palette_index)
             }
         },
-        _ if !cgb_mode && !lcdc_bg_and_window_priority => WHITE,
-        _ => bg_pixel.color
+        _ if !cgb_mode && !lcdc_bg_and_window_priority => (WHITE, 0),
+        _ => (bg_pixel.color, bg_pixel.palette_index)
     }
 }
 
@@ -43,8 +47,8 @@ mod tests {
     fn should_return_background_pixel_if_no_sprite() {
         let cgb_mode = false;
         let lcdc_bg_and_window_priority = true;
-        let bg_pixel = BackgroundPixel { color: LIGHT_GRAY, color_id: 1, prioritize_bg: false };
-        let pixel = resolve_highest_priority_pixel(cgb_mode, lcdc_bg_and_window_priority, bg_pixel, None);
+        let bg_pixel = BackgroundPixel { color: LIGHT_GRAY, color_id: 1, palette_index: 1, prioritize_bg: false };
+        let (pixel, _) = resolve_highest_priority_pixel(cgb_mode, lcdc_bg_and_window_priority, bg_pixel, None);
         assert_eq!(pixel, LIGHT_GRAY);
     }
 
@@ -52,8 +56,8 @@ mod tests {
     fn should_return_white_if_lcdc_bg_window_priority_is_false_in_dmg_mode() {
         let cgb_mode = false;
         let lcdc_bg_and_window_priority = false;
-        let bg_pixel = BackgroundPixel { color: LIGHT_GRAY, color_id: 1, prioritize_bg: false };
-        let pixel = resolve_highest_priority_pixel(cgb_mode, lcdc_bg_and_window_priority, bg_pixel, None);
+        let bg_pixel = BackgroundPixel { color: LIGHT_GRAY, color_id: 1, palette_index: 1, prioritize_bg: false };
+        let (pixel, _) = resolve_highest_priority_pixel(cgb_mode, lcdc_bg_and_window_priority, bg_pixel, None);
         assert_eq!(pixel, WHITE); 
     }
 
@@ -61,9 +65,9 @@ mod tests {
     fn should_prioritize_sprite_when_background_uses_color_id_zero_in_dmg_mode() {
         let cgb_mode = false;
         let lcdc_bg_and_window_priority = true;
-        let bg_pixel = BackgroundPixel { color: WHITE, color_id: 0, prioritize_bg: false };
-        let sprite_pixel = SpritePixel { color: LIGHT_GRAY, prioritize_bg: true };
-        let pixel = resolve_highest_priority_pixel(cgb_mode, lcdc_bg_and_window_priority, bg_pixel, Some(sprite_pixel));
+        let bg_pixel = BackgroundPixel { color: WHITE, color_id: 0, palette_index: 0, prioritize_bg: false };
+        let sprite_pixel = SpritePixel { color: LIGHT_GRAY, palette_index: 1, prioritize_bg: true };
+        let (pixel, _) = resolve_highest_priority_pixel(cgb_mode, lcdc_bg_and_window_priority, bg_pixel, Some(sprite_pixel));
         assert_eq!(pixel, LIGHT_GRAY);
     }
 
@@ -71,9 +75,9 @@ mod tests {
     fn should_prioritize_sprite_when_prioritize_bg_is_false_in_dmg_mode() {
         let cgb_mode = false;
         let lcdc_bg_and_window_priority = true;
-        let bg_pixel = BackgroundPixel { color: DARK_GRAY, color_id: 2, prioritize_bg: false };
-        let sprite_pixel = SpritePixel { color: LIGHT_GRAY, prioritize_bg: false };
-        let pixel = resolve_highest_priority_pixel(cgb_mode, lcdc_bg_and_window_priority, bg_pixel, Some(sprite_pixel));
+        let bg_pixel = BackgroundPixel { color: DARK_GRAY, color_id: 2, palette_index: 2, prioritize_bg: false };
+        let sprite_pixel = SpritePixel { color: LIGHT_GRAY, palette_index: 1, prioritize_bg: false };
+        let (pixel, _) = resolve_highest_priority_pixel(cgb_mode, lcdc_bg_and_window_priority, bg_pixel, Some(sprite_pixel));
         assert_eq!(pixel, LIGHT_GRAY);
     }
 
@@ -81,9 +85,9 @@ mod tests {
     fn should_prioritize_background_when_prioritize_bg_is_true_in_dmg_mode() {
         let cgb_mode = false;
         let lcdc_bg_and_window_priority = true;
-        let bg_pixel = BackgroundPixel { color: DARK_GRAY, color_id: 2, prioritize_bg: false };
-        let sprite_pixel = SpritePixel { color: LIGHT_GRAY, prioritize_bg: true };
-        let pixel = resolve_highest_priority_pixel(cgb_mode, lcdc_bg_and_window_priority, bg_pixel, Some(sprite_pixel));
+        let bg_pixel = BackgroundPixel { color: DARK_GRAY, color_id: 2, palette_index: 2, prioritize_bg: false };
+        let sprite_pixel = SpritePixel { color: LIGHT_GRAY, palette_index: 1, prioritize_bg: true };
+        let (pixel, _) = resolve_highest_priority_pixel(cgb_mode, lcdc_bg_and_window_priority, bg_pixel, Some(sprite_pixel));
         assert_eq!(pixel, DARK_GRAY);
     }
 
@@ -91,9 +95,9 @@ mod tests {
     fn should_prioritize_sprite_when_background_uses_color_id_zero_in_cgb_mode() {
         let cgb_mode = true;
         let lcdc_bg_and_window_priority = true;
-        let bg_pixel = BackgroundPixel { color: WHITE, color_id: 0, prioritize_bg: true };
-        let sprite_pixel = SpritePixel { color: LIGHT_GRAY, prioritize_bg: true };
-        let pixel = resolve_highest_priority_pixel(cgb_mode, lcdc_bg_and_window_priority, bg_pixel, Some(sprite_pixel));
+        let bg_pixel = BackgroundPixel { color: WHITE, color_id: 0, palette_index: 0, prioritize_bg: true };
+        let sprite_pixel = SpritePixel { color: LIGHT_GRAY, palette_index: 1, prioritize_bg: true };
+        let (pixel, _) = resolve_highest_priority_pixel(cgb_mode, lcdc_bg_and_window_priority, bg_pixel, Some(sprite_pixel));
         assert_eq!(pixel, LIGHT_GRAY);
     }
 
@@ -101,9 +105,9 @@ mod tests {
     fn should_prioritize_sprite_when_lcdc_bg_window_priority_is_false_in_cgb_mode() {
         let cgb_mode = true;
         let lcdc_bg_and_window_priority = false;
-        let bg_pixel = BackgroundPixel { color: DARK_GRAY, color_id: 2, prioritize_bg: true };
-        let sprite_pixel = SpritePixel { color: LIGHT_GRAY, prioritize_bg: true };
-        let pixel = resolve_highest_priority_pixel(cgb_mode, lcdc_bg_and_window_priority, bg_pixel, Some(sprite_pixel));
+        let bg_pixel = BackgroundPixel { color: DARK_GRAY, color_id: 2, palette_index: 2, prioritize_bg: true };
+        let sprite_pixel = SpritePixel { color: LIGHT_GRAY, palette_index: 1, prioritize_bg: true };
+        let (pixel, _) = resolve_highest_priority_pixel(cgb_mode, lcdc_bg_and_window_priority, bg_pixel, Some(sprite_pixel));
         assert_eq!(pixel, LIGHT_GRAY);
     }
 
@@ -111,9 +115,9 @@ mod tests {
     fn should_prioritize_sprite_if_neither_prioritize_bg_flags_are_true() {
         let cgb_mode = true;
         let lcdc_bg_and_window_priority = true;
-        let bg_pixel = BackgroundPixel { color: DARK_GRAY, color_id: 2, prioritize_bg: false };
-        let sprite_pixel = SpritePixel { color: LIGHT_GRAY, prioritize_bg: false };
-        let pixel = resolve_highest_priority_pixel(cgb_mode, lcdc_bg_and_window_priority, bg_pixel, Some(sprite_pixel));
+        let bg_pixel = BackgroundPixel { color: DARK_GRAY, color_id: 2, palette_index: 2, prioritize_bg: false };
+        let sprite_pixel = SpritePixel { color: LIGHT_GRAY, palette_index: 1, prioritize_bg: false };
+        let (pixel, _) = resolve_highest_priority_pixel(cgb_mode, lcdc_bg_and_window_priority, bg_pixel, Some(sprite_pixel));
         assert_eq!(pixel, LIGHT_GRAY); 
     }
 
@@ -121,9 +125,9 @@ mod tests {
     fn should_prioritize_background_if_at_least_one_prioritize_bg_flag_is_true() {
         let cgb_mode = true;
         let lcdc_bg_and_window_priority = true;
-        let bg_pixel = BackgroundPixel { color: DARK_GRAY, color_id: 2, prioritize_bg: true };
-        let sprite_pixel = SpritePixel { color: LIGHT_GRAY, prioritize_bg: false };
-        let pixel = resolve_highest_priority_pixel(cgb_mode, lcdc_bg_and_window_priority, bg_pixel, Some(sprite_pixel));
+        let bg_pixel = BackgroundPixel { color: DARK_GRAY, color_id: 2, palette_index: 2, prioritize_bg: true };
+        let sprite_pixel = SpritePixel { color: LIGHT_GRAY, palette_index: 1, prioritize_bg: false };
+        let (pixel, _) = resolve_highest_priority_pixel(cgb_mode, lcdc_bg_and_window_priority, bg_pixel, Some(sprite_pixel));
         assert_eq!(pixel, DARK_GRAY);  
     }
 }
\ No newline at end of file
@@ -1,9 +1,12 @@
 use crate::emulator::{Emulator, Mode, in_color_bios};
+use crate::gpu::FrameFormat;
+use crate::gpu::changed_scanlines::mark_scanline_changed;
+use crate::gpu::frame_hash::hash_scanline;
 use crate::gpu::constants::{GB_SCREEN_WIDTH, BYTES_PER_COLOR};
 use crate::gpu::sprites::read_sprite_pixel_color;
-use crate::gpu::background::read_bg_color;
-use crate::gpu::prioritization::resolve_highest_priority_pixel;
-use crate::gpu::window::read_window_color;
+use crate::gpu::background::read_bg_line;
+use crate::gpu::prioritization::{resolve_highest_priority_pixel, BackgroundPixel};
+use crate::gpu::window::read_window_line;
 use crate::gpu::utils::get_bg_and_window_enabled_mode;
 
 pub fn write_scanline(emulator: &mut Emulator) {
@@ -11,24 +14,45 @@ pub fn write_scanline(emulator: &mut Emulator) {
     let lcdc = emulator.gpu.registers.lcdc;
 
     if !in_color_bios(emulator) {
+        let mut bg_line = [BackgroundPixel::default(); GB_SCREEN_WIDTH as usize];
+        read_bg_line(emulator, &mut bg_line);
+        read_window_line(emulator, &mut bg_line);
+
+        let mut line_changed = false;
+
         for viewport_x in 0..GB_SCREEN_WIDTH as u8 {
-            let bg_pixel = read_window_color(emulator, viewport_x)
-                .unwrap_or(read_bg_color(emulator, viewport_x));
+            let bg_pixel = bg_line[viewport_x as usize];
 
             let maybe_sprite_pixel = read_sprite_pixel_color(emulator, viewport_x);
 
             let cgb_mode = emulator.mode == Mode::CGB;
             let lcdc_bg_and_window_priority = get_bg_and_window_enabled_mode(lcdc);
-            let color = resolve_highest_priority_pixel(cgb_mode, lcdc_bg_and_window_priority, bg_pixel, maybe_sprite_pixel);
+            let (color, palette_index) = resolve_highest_priority_pixel(cgb_mode, lcdc_bg_and_window_priority, bg_pixel, maybe_sprite_pixel);
 
             let pixel_position = ly as u32 * GB_SCREEN_WIDTH + viewport_x as u32;
             let pixel_index = (pixel_position * BYTES_PER_COLOR) as usize;
 
-            emulator.gpu.frame_buffer[pixel_index] = color[0];
-            emulator.gpu.frame_buffer[pixel_index + 1] = color[1];
-            emulator.gpu.frame_buffer[pixel_index + 2] = color[2];
-            emulator.gpu.frame_buffer[pixel_index + 3] = color[3];
-        } 
+            let pixel = &mut emulator.gpu.frame_buffer[pixel_index..pixel_index + BYTES_PER_COLOR as usize];
+            if *pixel != color {
+                pixel.copy_from_slice(&color);
+                line_changed = true;
+            }
+
+            if emulator.gpu.frame_format == FrameFormat::Indexed {
+                emulator.gpu.indexed_frame_buffer[pixel_position as usize] = palette_index;
+            }
+        }
+
+        if line_changed {
+            mark_scanline_changed(&mut emulator.gpu.changed_scanlines, ly);
+        }
+
+        if emulator.gpu.frame_hash.enabled {
+            let line_start = (ly as u32 * GB_SCREEN_WIDTH * BYTES_PER_COLOR) as usize;
+            let line_end = line_start + (GB_SCREEN_WIDTH * BYTES_PER_COLOR) as usize;
+            let gpu = &mut emulator.gpu;
+            hash_scanline(&mut gpu.frame_hash, &gpu.frame_buffer[line_start..line_end]);
+        }
     }
 }
 
@@ -704,4 +704,71 @@ fn should_prioritize_background_colors_when_lcdc_bit_1_is_off() {
     assert_that(frame_buffer)
         .at_starting_coordinates((0, 0))
         .has_pixels(&[BLACK, LIGHT_GRAY, WHITE, WHITE, WHITE, WHITE, LIGHT_GRAY, BLACK]);
-}
\ No newline at end of file
+}
+
+#[test]
+fn should_write_shade_indices_to_indexed_frame_buffer() {
+    let mut emulator = initialize_test_emulator();
+    crate::gpu::set_frame_format(&mut emulator, FrameFormat::Indexed);
+
+    initialize_monochrome_palettes(&mut emulator.gpu.registers.palettes);
+
+    write_tile_to_bg_memory(&mut emulator, 0, SAMPLE_TILE_A);
+
+    emulator.gpu.registers.ly = 0;
+    emulator.gpu.registers.lcdc = 0b10000011;
+
+    write_scanline(&mut emulator);
+
+    assert_eq!(emulator.gpu.indexed_frame_buffer[0..8], [3, 1, 0, 0, 0, 0, 1, 3]);
+}
+
+#[test]
+fn should_write_palette_ram_indices_to_indexed_frame_buffer_in_color_mode() {
+    let mut emulator = initialize_test_emulator();
+    emulator.mode = Mode::CGB;
+    crate::gpu::set_frame_format(&mut emulator, FrameFormat::Indexed);
+
+    initialize_color_palettes(&mut emulator.gpu.registers.palettes);
+
+    write_tile_to_bg_memory(&mut emulator, 0, SAMPLE_TILE_A);
+    write_tile_attributes(&mut emulator, 0, 0b00000001);
+    write_tile_to_obj_memory(&mut emulator, 1, SAMPLE_TILE_B);
+    write_sprite_to_sprite_buffer(&mut emulator, Sprite {
+        y_pos: 0,
+        x_pos: 8,
+        tile_index: 1,
+        priority: false,
+        y_flip: false,
+        x_flip: false,
+        dmg_palette: 0,
+        oam_index: 0,
+        cgb_from_bank_one: false,
+        cgb_palette: 2
+    });
+
+    emulator.gpu.registers.ly = 0;
+    emulator.gpu.registers.lcdc = 0b10000011;
+
+    write_scanline(&mut emulator);
+
+    // Background palette 1, then object palette 2 over the second tile.
+    assert_eq!(emulator.gpu.indexed_frame_buffer[0..8], [4, 6, 7, 7, 7, 7, 6, 4]);
+    assert_eq!(emulator.gpu.indexed_frame_buffer[8..16], [41; 8]);
+}
+
+#[test]
+fn should_hash_written_scanlines() {
+    let mut emulator = initialize_test_emulator();
+    crate::gpu::frame_hash::set_frame_hashing(&mut emulator, true);
+    let blank_hash = emulator.gpu.frame_hash.running_hash;
+
+    initialize_monochrome_palettes(&mut emulator.gpu.registers.palettes);
+    write_tile_to_bg_memory(&mut emulator, 0, SAMPLE_TILE_A);
+    emulator.gpu.registers.ly = 0;
+    emulator.gpu.registers.lcdc = 0b10000011;
+
+    write_scanline(&mut emulator);
+
+    assert_ne!(emulator.gpu.frame_hash.running_hash, blank_hash);
+}
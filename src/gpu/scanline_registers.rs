@@ -0,0 +1,81 @@
+use crate::emulator::Emulator;
+use crate::gpu::constants::GB_SCREEN_HEIGHT;
+
+const SCREEN_HEIGHT: usize = GB_SCREEN_HEIGHT as usize;
+
+/*
+    The scroll and window position each scanline of the last frame was drawn with.
+    Games change these mid-frame for parallax and wobble effects, so frontends doing
+    motion interpolation or CRT effects need them per line rather than the values
+    left over at VBlank. Lines are recorded as they're drawn, so lines not drawn
+    while the LCD was off keep the values from the frame before.
+*/
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScanlineRegisters {
+    pub scx: u8,
+    pub scy: u8,
+    pub wx: u8,
+    pub wy: u8
+}
+
+#[derive(Debug)]
+pub struct ScanlineRegistersState {
+    drawing: [ScanlineRegisters; SCREEN_HEIGHT],
+    last_frame: [ScanlineRegisters; SCREEN_HEIGHT]
+}
+
+pub fn initialize_scanline_registers() -> ScanlineRegistersState {
+    ScanlineRegistersState {
+        drawing: [ScanlineRegisters::default(); SCREEN_HEIGHT],
+        last_frame: [ScanlineRegisters::default(); SCREEN_HEIGHT]
+    }
+}
+
+pub fn record_scanline(emulator: &mut Emulator) {
+    let registers = &emulator.gpu.registers;
+    if let Some(line) = emulator.gpu.scanline_registers.drawing.get_mut(registers.ly as usize) {
+        *line = ScanlineRegisters {
+            scx: registers.scx,
+            scy: registers.scy,
+            wx: registers.wx,
+            wy: registers.wy
+        };
+    }
+}
+
+pub fn complete_frame(emulator: &mut Emulator) {
+    let state = &mut emulator.gpu.scanline_registers;
+    state.last_frame = state.drawing;
+}
+
+// One entry per row of the last completed frame, from top to bottom.
+pub fn get_scanline_registers(emulator: &Emulator) -> &[ScanlineRegisters] {
+    &emulator.gpu.scanline_registers.last_frame
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::initialize_screenless_emulator;
+    use super::*;
+
+    #[test]
+    fn should_keep_registers_each_scanline_was_drawn_with() {
+        let mut emulator = initialize_screenless_emulator();
+
+        emulator.gpu.registers.ly = 10;
+        emulator.gpu.registers.scx = 4;
+        record_scanline(&mut emulator);
+        emulator.gpu.registers.ly = 11;
+        emulator.gpu.registers.scx = 8;
+        emulator.gpu.registers.wy = 0x40;
+        record_scanline(&mut emulator);
+        assert_eq!(get_scanline_registers(&emulator)[10], ScanlineRegisters::default());
+
+        complete_frame(&mut emulator);
+
+        let lines = get_scanline_registers(&emulator);
+        assert_eq!(lines.len(), SCREEN_HEIGHT);
+        assert_eq!(lines[10], ScanlineRegisters { scx: 4, scy: 0, wx: 0, wy: 0 });
+        assert_eq!(lines[11], ScanlineRegisters { scx: 8, scy: 0, wx: 0, wy: 0x40 });
+    }
+}
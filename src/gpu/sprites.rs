@@ -1,6 +1,6 @@
 use crate::emulator::{is_cgb, Emulator, Mode};
 use crate::gpu::has_dmg_compatability;
-use crate::gpu::colors::{as_cgb_obj_color_rgb, as_dmg_obj_color_rgb, Color, calculate_color_id};
+use crate::gpu::colors::{as_cgb_obj_color_rgb, as_cgb_obj_palette_index, as_dmg_obj_color_rgb, as_dmg_obj_palette_index, Color, calculate_color_id};
 use crate::gpu::prioritization::SpritePixel;
 use crate::gpu::utils::{get_obj_enabled_mode, get_obj_size_mode, get_tile_line_bytes};
 use crate::utils::{get_bit, is_bit_set};
@@ -86,6 +86,7 @@ pub fn collect_scanline_sprites(emulator: &Emulator) -> Vec<Sprite> {
     let lcdc = emulator.gpu.registers.lcdc;
 
     let eight_by_sixteen_mode = get_obj_size_mode(lcdc);
+    let sprite_limit = if emulator.accuracy.remove_sprite_limit { TOTAL_SPRITES as usize } else { SPRITE_LIMIT_PER_SCANLINE };
 
     for sprite_number in 0..TOTAL_SPRITES {
         let sprite = pull_sprite(emulator, sprite_number);
@@ -95,7 +96,7 @@ pub fn collect_scanline_sprites(emulator: &Emulator) -> Vec<Sprite> {
         if within_scanline(sprite.y_pos, y_int, eight_by_sixteen_mode) {
             sprites.push(sprite);
 
-            if sprites.len() == SPRITE_LIMIT_PER_SCANLINE {
+            if sprites.len() == sprite_limit {
                 break;
             }
         }
@@ -125,7 +126,8 @@ fn lookup_possible_sprites(emulator: &Emulator, x: u8, y: u8, eight_by_sixteen_m
     found_sprites
 }
 
-pub fn calculate_sprite_pixel_color(emulator: &Emulator, sprite: &Sprite, x: u8, y: u8) -> Option<Color> {
+// Returns the pixel's color along with its palette index, for indexed frames.
+pub fn calculate_sprite_pixel_color(emulator: &Emulator, sprite: &Sprite, x: u8, y: u8) -> Option<(Color, u8)> {
     let y_int = y as i16;
     let x_int  = x as i16;
 
@@ -147,11 +149,13 @@ pub fn calculate_sprite_pixel_color(emulator: &Emulator, sprite: &Sprite, x: u8,
             let color_id = calculate_color_id(column_offset as u8, msb_byte, lsb_byte, sprite.x_flip);
             
             as_cgb_obj_color_rgb(&emulator.gpu.registers.palettes, palette_number, color_id, dmg_compatible)
+                .map(|color| (color, as_cgb_obj_palette_index(&emulator.gpu.registers.palettes, palette_number, color_id, dmg_compatible)))
         }
         else {            
             let color_id = calculate_color_id(column_offset as u8, msb_byte, lsb_byte, sprite.x_flip);
             
-            as_dmg_obj_color_rgb(&emulator.gpu.registers.palettes, sprite.dmg_palette, color_id) 
+            as_dmg_obj_color_rgb(&emulator.gpu.registers.palettes, sprite.dmg_palette, color_id)
+                .map(|color| (color, as_dmg_obj_palette_index(&emulator.gpu.registers.palettes, sprite.dmg_palette, color_id)))
         }
     }
     else {
@@ -159,8 +163,8 @@ pub fn calculate_sprite_pixel_color(emulator: &Emulator, sprite: &Sprite, x: u8,
     } 
 }
 
-fn resolve_highest_priority_sprite<'a>(emulator: &Emulator, sprites: Vec<&'a Sprite>, x: u8, y: u8) -> Option<(&'a Sprite, Option<Color>)> {
-    let mut maybe_highest_priority: Option<(&'a Sprite, Option<Color>)> = None;
+fn resolve_highest_priority_sprite<'a>(emulator: &Emulator, sprites: Vec<&'a Sprite>, x: u8, y: u8) -> Option<(&'a Sprite, Option<(Color, u8)>)> {
+    let mut maybe_highest_priority: Option<(&'a Sprite, Option<(Color, u8)>)> = None;
     let cgb_mode = emulator.mode == Mode::CGB;
     let oam_location_prioritization = cgb_mode && !is_bit_set(emulator.gpu.registers.cgb_opri, CGB_OPRI_PRIORITY_BIT);
 
@@ -217,7 +221,7 @@ pub fn read_sprite_pixel_color(emulator: &Emulator, viewport_x: u8) -> Option<Sp
         match resolve_highest_priority_sprite(emulator, possible_sprites, viewport_x, ly) {
             Some((highest_priority_sprite, maybe_color)) => {
                 let prioritize_bg = highest_priority_sprite.priority;
-                maybe_color.map(|color| SpritePixel { color, prioritize_bg })
+                maybe_color.map(|(color, palette_index)| SpritePixel { color, palette_index, prioritize_bg })
             },
             _ => None
         }
@@ -229,6 +233,7 @@ pub fn read_sprite_pixel_color(emulator: &Emulator, viewport_x: u8) -> Option<Sp
 
 #[cfg(test)]
 mod tests {
+    use crate::accuracy::{set_accuracy_profile, set_sprite_limit_removed, AccuracyProfile};
     use crate::emulator::initialize_screenless_emulator;
     use super::*;
 
@@ -278,6 +283,22 @@ mod tests {
         assert_eq!(sprites[9].y_pos, 0);
     }
 
+    #[test]
+    fn should_get_every_sprite_on_line_when_limit_is_removed() {
+        let mut emulator = initialize_screenless_emulator();
+        set_accuracy_profile(&mut emulator, AccuracyProfile::Fast);
+        set_sprite_limit_removed(&mut emulator, true);
+
+        emulator.gpu.registers.ly = 0;
+        for sprite_number in 0..12 {
+            write_sprite(&mut emulator, sprite_number, 16, sprite_number * 8, 0);
+        }
+
+        let sprites = collect_scanline_sprites(&emulator);
+
+        assert_eq!(sprites.len(), 12);
+    }
+
     #[test]
     fn should_parse_sprite_attributes_correctly() {
         let mut emulator = initialize_screenless_emulator();
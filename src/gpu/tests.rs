@@ -1,4 +1,5 @@
 use crate::emulator::initialize_screenless_emulator;
+use crate::mmu;
 use super::*;
 
 fn initialize_test_emulator() -> Emulator {
@@ -93,6 +94,52 @@ fn should_move_back_to_oam_mode_from_vblank_at_correct_time() {
     assert_eq!(emulator.gpu.registers.ly, 0);
 }
 
+#[test]
+fn should_reset_ly_early_on_last_scanline() {
+    let mut emulator = initialize_test_emulator();
+    emulator.gpu.mode = 1;
+    emulator.gpu.registers.ly = 152;
+    emulator.gpu.mode_clock = 452;
+    step(&mut emulator);
+    assert_eq!(emulator.gpu.registers.ly, 153);
+    step(&mut emulator);
+    assert_eq!(emulator.gpu.registers.ly, 0);
+    assert_eq!(emulator.gpu.mode, 1);
+}
+
+#[test]
+fn should_reset_ly_early_on_last_scanline_in_double_speed_mode() {
+    let mut emulator = initialize_test_emulator();
+    emulator.speed_switch.cgb_double_speed = true;
+    emulator.gpu.mode = 1;
+    emulator.gpu.registers.ly = 152;
+    emulator.gpu.mode_clock = 454;
+    step(&mut emulator);
+    assert_eq!(emulator.gpu.registers.ly, 153);
+    assert_eq!(emulator.gpu.mode_clock, 0);
+    step(&mut emulator);
+    assert_eq!(emulator.gpu.registers.ly, 0);
+    assert_eq!(emulator.gpu.mode_clock, 2);
+}
+
+#[test]
+fn should_fire_lyc_interrupt_for_line_zero_once_on_last_scanline() {
+    let mut emulator = initialize_test_emulator();
+    emulator.gpu.mode = 1;
+    emulator.gpu.registers.ly = 153;
+    emulator.gpu.registers.lyc = 0;
+    emulator.gpu.registers.stat = 0b01000001;
+    step(&mut emulator);
+    assert_eq!(emulator.interrupts.flags, 0x02);
+
+    emulator.interrupts.flags = 0;
+    emulator.gpu.mode_clock = 452;
+    step(&mut emulator);
+    assert_eq!(emulator.gpu.mode, 2);
+    assert_eq!(emulator.gpu.registers.ly, 0);
+    assert_eq!(emulator.interrupts.flags, 0);
+}
+
 #[test]
 fn should_update_stat_register_with_mode_2_status() {
     let mut emulator = initialize_test_emulator();
@@ -303,4 +350,100 @@ fn should_set_byte_in_bank_0_of_video_ram() {
     set_cgb_vbk(&mut emulator, 0);
     set_video_ram_byte(&mut emulator, 0x1802, 0xA1);
     assert_eq!(emulator.gpu.video_ram[0x1802], 0xA1);
-}
\ No newline at end of file
+}
+
+#[test]
+fn should_block_cpu_video_ram_access_during_pixel_transfer() {
+    let mut emulator = initialize_test_emulator();
+    emulator.gpu.video_ram[0x1800] = 0xA1;
+    emulator.gpu.mode = 3;
+    mmu::write_byte(&mut emulator, 0x9800, 0x42);
+    assert_eq!(mmu::read_byte(&mut emulator, 0x9800), 0xFF);
+    assert_eq!(emulator.gpu.video_ram[0x1800], 0xA1);
+
+    emulator.gpu.mode = 0;
+    mmu::write_byte(&mut emulator, 0x9800, 0x42);
+    assert_eq!(mmu::read_byte(&mut emulator, 0x9800), 0x42);
+}
+
+#[test]
+fn should_allow_video_ram_access_during_pixel_transfer_without_vram_conflicts() {
+    let mut emulator = initialize_test_emulator();
+    emulator.accuracy.vram_conflicts = false;
+    emulator.gpu.mode = 3;
+    mmu::write_byte(&mut emulator, 0x9800, 0x42);
+    assert_eq!(mmu::read_byte(&mut emulator, 0x9800), 0x42);
+}
+
+#[test]
+fn should_block_palette_ram_writes_but_advance_index_during_pixel_transfer() {
+    let mut emulator = initialize_test_emulator();
+    emulator.mode = Mode::CGB;
+    emulator.gpu.mode = 3;
+    set_cgb_bcps(&mut emulator, 0x80);
+    set_cgb_bcpd(&mut emulator, 0x1F);
+    assert_eq!(emulator.gpu.registers.palettes.cgb_bcpd[0], 0);
+    assert_eq!(get_cgb_bcpd(&emulator), 0xFF);
+    assert_eq!(get_cgb_bcps(&emulator) & 0x3F, 1);
+
+    emulator.gpu.mode = 0;
+    set_cgb_bcpd(&mut emulator, 0x1F);
+    assert_eq!(emulator.gpu.registers.palettes.cgb_bcpd[1], 0x1F);
+}
+
+struct RecordingLcdListener {
+    transitions: std::sync::Arc<std::sync::Mutex<Vec<bool>>>
+}
+
+impl LcdListener for RecordingLcdListener {
+    fn lcd_changed(&mut self, enabled: bool) {
+        self.transitions.lock().unwrap().push(enabled);
+    }
+}
+
+#[test]
+fn should_report_lcd_transitions() {
+    let mut emulator = initialize_test_emulator();
+    let transitions = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    set_lcd_listener(&mut emulator, Box::new(RecordingLcdListener { transitions: transitions.clone() }));
+
+    set_lcdc(&mut emulator, 0x00);
+    set_lcdc(&mut emulator, 0x00);
+    assert!(!lcd_enabled(&emulator));
+    set_lcdc(&mut emulator, 0x91);
+    set_lcdc(&mut emulator, 0x93);
+    assert!(lcd_enabled(&emulator));
+
+    assert_eq!(*transitions.lock().unwrap(), vec![false, true]);
+}
+
+static BLANK_FRAME_RENDERED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+#[test]
+fn should_render_blank_frame_when_lcd_is_turned_off() {
+    let mut emulator = initialize_test_emulator();
+    emulator.gpu.frame_buffer[0] = 0x00;
+    emulator.render = |frame_buffer| {
+        let blank = frame_buffer.iter().all(|byte| *byte == 0xFF);
+        BLANK_FRAME_RENDERED.store(blank, std::sync::atomic::Ordering::SeqCst);
+    };
+    set_lcdc(&mut emulator, 0x00);
+    assert!(BLANK_FRAME_RENDERED.load(std::sync::atomic::Ordering::SeqCst));
+}
+
+static RENDERED_SCANLINES: std::sync::Mutex<Vec<(u8, usize)>> = std::sync::Mutex::new(Vec::new());
+
+#[test]
+fn should_render_each_scanline_as_it_is_drawn() {
+    let mut emulator = initialize_test_emulator();
+    emulator.gpu.mode = OAM_MODE;
+    emulator.cpu.clock.instruction_clock_cycles = 4;
+    emulator.render_scanline = Some(|ly, line| RENDERED_SCANLINES.lock().unwrap().push((ly, line.len())));
+
+    while emulator.gpu.frame_count == 0 {
+        step(&mut emulator);
+    }
+
+    let expected: Vec<(u8, usize)> = (0..GB_SCREEN_HEIGHT as u8).map(|ly| (ly, 640)).collect();
+    assert_eq!(*RENDERED_SCANLINES.lock().unwrap(), expected);
+}
@@ -0,0 +1,274 @@
+use crate::boot;
+use crate::emulator::{is_cgb, Emulator};
+use crate::mmu;
+
+/*
+    Boots a cartridge without running boot ROM code. The logo and header checksum
+    are still checked unless the options say otherwise, and a cartridge that fails
+    the check hangs with its logo on screen like it would on hardware. The logo is
+    drawn from the cartridge header the same way the DMG boot ROM draws it,
+    optionally scrolling it down and playing the chime before handing over to the
+    cartridge. The CGB boot animation isn't reproduced, so CGB models show the DMG
+    one in grayscale.
+
+    While the animation plays, the CPU sits halted on a boot ROM page filled with
+    HALT, so the rest of the system keeps running in step with it.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HleBootOptions {
+    pub animate: bool,
+    // Off boots homebrew and test fixtures with a missing logo or a wrong header checksum.
+    pub check_header: bool
+}
+
+impl Default for HleBootOptions {
+    fn default() -> Self {
+        HleBootOptions { animate: true, check_header: true }
+    }
+}
+
+#[derive(Debug)]
+pub struct HleBootState {
+    pub active: bool,
+    pub header_valid: bool,
+    frame: u32
+}
+
+pub fn initialize_hle_boot() -> HleBootState {
+    HleBootState {
+        active: false,
+        header_valid: false,
+        frame: 0
+    }
+}
+
+pub const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E
+];
+// The CGB boot ROM only checks the top half of the logo.
+const CGB_CHECKED_LOGO_BYTES: usize = 0x18;
+const REGISTERED_MARK_TILE: [u8; 8] = [0x3C, 0x42, 0xB9, 0xA5, 0xB9, 0xA5, 0x42, 0x3C];
+
+const LOGO_ADDRESS: u16 = 0x0104;
+const HEADER_CHECKSUM_START: u16 = 0x0134;
+const HEADER_CHECKSUM_ADDRESS: u16 = 0x014D;
+const HALT_OPCODE: u8 = 0x76;
+
+const LOGO_TILES_ADDRESS: u16 = 0x8010;
+const REGISTERED_MARK_TILE_INDEX: u8 = 0x19;
+const LOGO_TOP_ROW_ADDRESS: u16 = 0x9904;
+const LOGO_BOTTOM_ROW_ADDRESS: u16 = 0x9924;
+const LOGO_TILES_PER_ROW: u16 = 12;
+
+// The logo scrolls down one line a frame from here, then holds while the chime plays.
+const SCROLL_START: u8 = 0x64;
+const FIRST_NOTE_FRAME: u32 = SCROLL_START as u32;
+const SECOND_NOTE_FRAME: u32 = FIRST_NOTE_FRAME + 6;
+const HANDOVER_FRAME: u32 = FIRST_NOTE_FRAME + 60;
+
+fn read_rom(emulator: &Emulator, address: u16) -> u8 {
+    emulator.memory.cartridge_mapper.read_rom(address)
+}
+
+pub fn header_checksum_valid(emulator: &Emulator) -> bool {
+    let checksum = (HEADER_CHECKSUM_START..HEADER_CHECKSUM_ADDRESS)
+        .fold(0u8, |checksum, address| checksum.wrapping_sub(read_rom(emulator, address)).wrapping_sub(1));
+    checksum == read_rom(emulator, HEADER_CHECKSUM_ADDRESS)
+}
+
+pub fn logo_valid(emulator: &Emulator) -> bool {
+    let checked_bytes = if is_cgb(emulator) { CGB_CHECKED_LOGO_BYTES } else { NINTENDO_LOGO.len() };
+    NINTENDO_LOGO[..checked_bytes].iter()
+        .enumerate()
+        .all(|(index, byte)| read_rom(emulator, LOGO_ADDRESS + index as u16) == *byte)
+}
+
+// Stretches four logo pixels to eight, as the logo is drawn at twice its size.
+fn double_pixels(nibble: u8) -> u8 {
+    (0..4).fold(0, |row, bit| if nibble & (1 << bit) != 0 { row | (0b11 << (bit * 2)) } else { row })
+}
+
+fn draw_logo(emulator: &mut Emulator) {
+    for address in 0x8000..0xA000 {
+        mmu::write_byte(emulator, address, 0);
+    }
+
+    // Each nibble of the logo becomes two identical rows of a tile, leaving the high bit plane clear.
+    let mut address = LOGO_TILES_ADDRESS;
+    for index in 0..NINTENDO_LOGO.len() as u16 {
+        let byte = read_rom(emulator, LOGO_ADDRESS + index);
+        for nibble in [byte >> 4, byte & 0x0F] {
+            let row = double_pixels(nibble);
+            mmu::write_byte(emulator, address, row);
+            mmu::write_byte(emulator, address + 2, row);
+            address += 4;
+        }
+    }
+
+    for (index, row) in REGISTERED_MARK_TILE.iter().enumerate() {
+        mmu::write_byte(emulator, 0x8000 + REGISTERED_MARK_TILE_INDEX as u16 * 16 + index as u16 * 2, *row);
+    }
+
+    for index in 0..LOGO_TILES_PER_ROW {
+        mmu::write_byte(emulator, LOGO_TOP_ROW_ADDRESS + index, index as u8 + 1);
+        mmu::write_byte(emulator, LOGO_BOTTOM_ROW_ADDRESS + index, (index + LOGO_TILES_PER_ROW) as u8 + 1);
+    }
+    mmu::write_byte(emulator, LOGO_TOP_ROW_ADDRESS + LOGO_TILES_PER_ROW, REGISTERED_MARK_TILE_INDEX);
+
+    if is_cgb(emulator) {
+        // White, then black for the three shades BGP maps the logo to.
+        mmu::write_byte(emulator, 0xFF68, 0x80);
+        for byte in [0xFF, 0x7F, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00] {
+            mmu::write_byte(emulator, 0xFF69, byte);
+        }
+    }
+
+    mmu::write_byte(emulator, 0xFF47, 0xFC);
+    mmu::write_byte(emulator, 0xFF40, 0x91);
+}
+
+fn play_note(emulator: &mut Emulator, frequency_low: u8) {
+    mmu::write_byte(emulator, 0xFF13, frequency_low);
+    mmu::write_byte(emulator, 0xFF14, 0x87);
+}
+
+fn set_up_chime(emulator: &mut Emulator) {
+    mmu::write_byte(emulator, 0xFF26, 0x80);
+    mmu::write_byte(emulator, 0xFF11, 0x80);
+    mmu::write_byte(emulator, 0xFF12, 0xF3);
+    mmu::write_byte(emulator, 0xFF25, 0xF3);
+    mmu::write_byte(emulator, 0xFF24, 0x77);
+}
+
+fn hand_over(emulator: &mut Emulator) {
+    emulator.hle_boot.active = false;
+    emulator.cpu.halted = false;
+    // Drops the HALT fetched from the boot ROM page, so execution resumes at the entry point.
+    emulator.cpu.registers.opcode = 0x00;
+    boot::apply_post_boot_state(emulator);
+}
+
+// Must be called after the ROM is loaded, in place of running the boot ROM.
+pub fn start(emulator: &mut Emulator, options: HleBootOptions) {
+    let bios_size = emulator.memory.bios.len();
+    emulator.memory.bios = vec![HALT_OPCODE; bios_size];
+    emulator.memory.in_bios = true;
+    emulator.hle_boot = HleBootState {
+        active: true,
+        header_valid: !options.check_header || (logo_valid(emulator) && header_checksum_valid(emulator)),
+        frame: 0
+    };
+
+    draw_logo(emulator);
+
+    if options.animate {
+        mmu::write_byte(emulator, 0xFF42, SCROLL_START);
+        set_up_chime(emulator);
+    }
+    else if emulator.hle_boot.header_valid {
+        hand_over(emulator);
+    }
+}
+
+pub fn step(emulator: &mut Emulator, frame_completed: bool) {
+    if !emulator.hle_boot.active || !frame_completed {
+        return;
+    }
+
+    let frame = emulator.hle_boot.frame + 1;
+    emulator.hle_boot.frame = frame;
+
+    if frame <= SCROLL_START as u32 {
+        mmu::write_byte(emulator, 0xFF42, SCROLL_START - frame as u8);
+    }
+
+    // A cartridge that failed the check stays on the logo, like the boot ROM locking up.
+    match frame {
+        FIRST_NOTE_FRAME => play_note(emulator, 0x83),
+        SECOND_NOTE_FRAME => play_note(emulator, 0xC1),
+        HANDOVER_FRAME if emulator.hle_boot.header_valid => hand_over(emulator),
+        _ => ()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::Core;
+    use crate::emulator::initialize_screenless_emulator;
+    use crate::mmu::constants::*;
+    use crate::mmu::effects::empty_cartridge_effects;
+    use crate::mmu::test_utils::*;
+    use super::*;
+
+    fn setup_emulator(logo: &[u8]) -> Emulator {
+        let mut rom = build_rom(CART_TYPE_ROM_ONLY, ROM_SIZE_64KB, RAM_SIZE_0KB);
+        rom[LOGO_ADDRESS as usize..LOGO_ADDRESS as usize + logo.len()].copy_from_slice(logo);
+        rom[HEADER_CHECKSUM_ADDRESS as usize] = rom[HEADER_CHECKSUM_START as usize..HEADER_CHECKSUM_ADDRESS as usize]
+            .iter()
+            .fold(0u8, |checksum, byte| checksum.wrapping_sub(*byte).wrapping_sub(1));
+        // JR -2 at the entry point.
+        rom[0x100..0x102].copy_from_slice(&[0x18, 0xFE]);
+
+        let mut emulator = initialize_screenless_emulator();
+        mmu::load_rom_buffer(&mut emulator.memory, rom, empty_cartridge_effects()).unwrap();
+        emulator
+    }
+
+    #[test]
+    fn should_draw_logo_and_hand_over_immediately_without_animation() {
+        let mut emulator = setup_emulator(&NINTENDO_LOGO);
+        start(&mut emulator, HleBootOptions { animate: false, check_header: true });
+
+        assert!(!emulator.hle_boot.active);
+        assert!(!emulator.memory.in_bios);
+        assert_eq!(emulator.cpu.registers.program_counter, 0x100);
+        // The top of the first logo tile, from the high nibble of 0xCE.
+        assert_eq!(mmu::read_byte(&mut emulator, 0x8010), 0xF0);
+        assert_eq!(mmu::read_byte(&mut emulator, 0x9904), 0x01);
+    }
+
+    #[test]
+    fn should_scroll_logo_before_handing_over() {
+        let mut emulator = setup_emulator(&NINTENDO_LOGO);
+        start(&mut emulator, HleBootOptions::default());
+
+        for _ in 0..10 {
+            emulator.run_frame();
+        }
+        assert!(emulator.hle_boot.active);
+        assert_eq!(emulator.gpu.registers.scy, SCROLL_START - 10);
+
+        for _ in 10..HANDOVER_FRAME + 1 {
+            emulator.run_frame();
+        }
+        assert!(!emulator.hle_boot.active);
+        assert!(!emulator.memory.in_bios);
+        assert_eq!(emulator.gpu.registers.scy, 0);
+        assert!(emulator.cpu.registers.program_counter >= 0x100);
+    }
+
+    #[test]
+    fn should_lock_up_when_logo_does_not_match() {
+        let mut emulator = setup_emulator(&[0xFF; 48]);
+        start(&mut emulator, HleBootOptions { animate: false, check_header: true });
+
+        for _ in 0..HANDOVER_FRAME + 10 {
+            emulator.run_frame();
+        }
+        assert!(emulator.hle_boot.active);
+        assert!(!emulator.hle_boot.header_valid);
+        assert!(emulator.memory.in_bios);
+    }
+
+    #[test]
+    fn should_boot_invalid_header_when_check_is_skipped() {
+        let mut emulator = setup_emulator(&[0xFF; 48]);
+        start(&mut emulator, HleBootOptions { animate: false, check_header: false });
+
+        assert!(!emulator.hle_boot.active);
+        assert!(!emulator.memory.in_bios);
+        assert_eq!(emulator.cpu.registers.program_counter, 0x100);
+    }
+}
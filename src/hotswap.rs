@@ -0,0 +1,149 @@
+use std::io;
+use std::mem;
+
+use crate::apu::register_log;
+use crate::emulated_rtc;
+use crate::emulator::{initialize_emulator, CartridgeEffects, CartridgeHeader, Emulator};
+use crate::gpu;
+use crate::mmu;
+use crate::rom_database;
+use crate::stats;
+
+/*
+    Swapping cartridges on a running emulator. Pulling a cartridge out leaves the
+    cartridge bus floating, so the CPU reads 0xFF until another one goes in. A new
+    cartridge always starts with a fresh mapper. The console can either stay
+    powered, which multicart menus and the "swap trick" rely on to run one game's
+    code with another's RAM and registers still in place, or be switched off and
+    on again so the boot ROM runs.
+
+    Frontend settings (render callback, audio configuration, debugging tools, etc.)
+    survive a power cycle, as they would if the player flipped the power switch.
+*/
+pub fn eject_cartridge(emulator: &mut Emulator) {
+    mmu::remove_cartridge(&mut emulator.memory);
+    emulator.rom_database.active_entry = None;
+}
+
+pub fn insert_cartridge(emulator: &mut Emulator, rom: &[u8], cartridge_effects: Box<dyn CartridgeEffects>, keep_powered: bool) -> io::Result<CartridgeHeader> {
+    if keep_powered {
+        rom_database::insert_rom(emulator, rom.to_vec(), cartridge_effects)
+    }
+    else {
+        let header = rom_database::insert_rom(emulator, rom.to_vec(), cartridge_effects)?;
+        power_cycle(emulator);
+        rom_database::apply_quirks(emulator);
+        Ok(header)
+    }
+}
+
+// Resets the console hardware and the cartridge's bank registers to power-on state, keeping the inserted cartridge.
+pub fn power_cycle(emulator: &mut Emulator) {
+    let fresh = initialize_emulator(emulator.render);
+    let mut memory = fresh.memory;
+    mem::swap(&mut memory.cartridge_mapper, &mut emulator.memory.cartridge_mapper);
+    mem::swap(&mut memory.processor_test_ram, &mut emulator.memory.processor_test_ram);
+
+    emulator.cpu = fresh.cpu;
+    emulator.interrupts = fresh.interrupts;
+    emulator.timers = fresh.timers;
+    emulator.memory = memory;
+    emulator.memory.cartridge_mapper.reset_registers();
+    emulator.dma = fresh.dma;
+    emulator.hdma = fresh.hdma;
+    emulator.keys = fresh.keys;
+    emulator.speed_switch = fresh.speed_switch;
+    emulator.hle_boot = fresh.hle_boot;
+    mmu::load_bios(emulator);
+
+    let gpu = mem::replace(&mut emulator.gpu, fresh.gpu);
+    emulator.gpu.registers.palettes.color_correction = gpu.registers.palettes.color_correction;
+    emulator.gpu.frame_hash = gpu.frame_hash;
+    emulator.gpu.changed_scanlines = gpu.changed_scanlines;
+    emulator.gpu.frame_count = gpu.frame_count;
+    gpu::set_frame_format(emulator, gpu.frame_format);
+
+    let apu = mem::replace(&mut emulator.apu, fresh.apu);
+    emulator.apu.sample_rate = apu.sample_rate;
+    emulator.apu.enqueue_rate = apu.enqueue_rate;
+    emulator.apu.audio_buffer_size = apu.audio_buffer_size;
+    emulator.apu.filter = apu.filter;
+    emulator.apu.register_log = apu.register_log;
+    emulator.apu.left_sample_queue = apu.left_sample_queue;
+    emulator.apu.right_sample_queue = apu.right_sample_queue;
+
+    let serial = mem::replace(&mut emulator.serial, fresh.serial);
+    emulator.serial.serial_exchange = serial.serial_exchange;
+    emulator.serial.device = serial.device;
+    emulator.serial.logger = serial.logger;
+
+    stats::sync_clock_reference(emulator);
+    emulated_rtc::sync_clock_reference(emulator);
+    register_log::sync_clock_reference(emulator);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mmu::effects::empty_cartridge_effects;
+    use crate::mmu::constants::*;
+    use crate::mmu::test_utils::*;
+    use super::*;
+
+    fn build_rom_with_marker(marker: u8) -> Vec<u8> {
+        let mut rom = build_rom(CART_TYPE_MBC1, ROM_SIZE_64KB, RAM_SIZE_0KB);
+        rom[0x4000] = marker;
+        rom
+    }
+
+    #[test]
+    fn should_read_open_bus_after_eject() {
+        let mut emulator = setup_emulator_with_rom(build_rom_with_marker(0xA1));
+        eject_cartridge(&mut emulator);
+        assert_eq!(mmu::read_byte(&mut emulator, 0x4000), 0xFF);
+        assert_eq!(mmu::read_byte(&mut emulator, 0xA000), 0xFF);
+    }
+
+    #[test]
+    fn should_keep_running_when_inserted_while_powered() {
+        let mut emulator = setup_emulator_with_rom(build_rom_with_marker(0xA1));
+        mmu::write_byte(&mut emulator, 0x2000, 0x02);
+        emulator.cpu.registers.program_counter = 0x1234;
+
+        eject_cartridge(&mut emulator);
+        insert_cartridge(&mut emulator, &build_rom_with_marker(0xB2), empty_cartridge_effects(), true).unwrap();
+
+        assert!(!emulator.memory.in_bios);
+        assert_eq!(emulator.cpu.registers.program_counter, 0x1234);
+        assert_eq!(emulator.memory.cartridge_mapper.get_rom_bank(), 1);
+        assert_eq!(mmu::read_byte(&mut emulator, 0x4000), 0xB2);
+    }
+
+    #[test]
+    fn should_run_boot_rom_when_power_cycled() {
+        let mut emulator = setup_emulator_with_rom(build_rom_with_marker(0xA1));
+        gpu::set_frame_format(&mut emulator, gpu::FrameFormat::Indexed);
+
+        insert_cartridge(&mut emulator, &build_rom_with_marker(0xB2), empty_cartridge_effects(), false).unwrap();
+
+        assert!(emulator.memory.in_bios);
+        assert_eq!(emulator.cpu.registers.program_counter, 0);
+        assert_eq!(emulator.gpu.frame_format, gpu::FrameFormat::Indexed);
+        assert_eq!(mmu::read_byte(&mut emulator, 0x4000), 0xB2);
+    }
+
+    #[test]
+    fn should_reset_bank_registers_and_serial_state_when_power_cycled() {
+        let mut emulator = setup_emulator_with_rom(build_rom_with_marker(0xA1));
+        mmu::write_byte(&mut emulator, 0x2000, 0x02);
+        emulator.serial.data = 0x42;
+        emulator.serial.transfer_enabled = true;
+        emulator.serial.bits_transferred = 3;
+
+        power_cycle(&mut emulator);
+
+        assert_eq!(emulator.memory.cartridge_mapper.get_rom_bank(), 1);
+        assert_eq!(emulator.serial.data, 0);
+        assert!(!emulator.serial.transfer_enabled);
+        assert_eq!(emulator.serial.bits_transferred, 0);
+    }
+}
@@ -0,0 +1,165 @@
+use crate::keys::{JoypadState, Key};
+use crate::netplay;
+
+// Named by position so the same defaults suit Xbox, PlayStation and Switch pads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GamepadButton {
+    South,
+    East,
+    West,
+    North,
+    LeftShoulder,
+    RightShoulder,
+    LeftTrigger,
+    RightTrigger,
+    Select,
+    Start,
+    LeftStick,
+    RightStick,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+    Home
+}
+
+// Buttons in the order of the W3C "standard" gamepad mapping, which browsers use for known controllers.
+const STANDARD_BUTTONS: [GamepadButton; 17] = [
+    GamepadButton::South,
+    GamepadButton::East,
+    GamepadButton::West,
+    GamepadButton::North,
+    GamepadButton::LeftShoulder,
+    GamepadButton::RightShoulder,
+    GamepadButton::LeftTrigger,
+    GamepadButton::RightTrigger,
+    GamepadButton::Select,
+    GamepadButton::Start,
+    GamepadButton::LeftStick,
+    GamepadButton::RightStick,
+    GamepadButton::DPadUp,
+    GamepadButton::DPadDown,
+    GamepadButton::DPadLeft,
+    GamepadButton::DPadRight,
+    GamepadButton::Home
+];
+
+// How far the stick has to be pushed, from 0 to 1, before it counts as a d-pad press.
+pub const DEFAULT_STICK_THRESHOLD: f32 = 0.5;
+
+impl GamepadButton {
+    pub fn from_standard_index(index: usize) -> Option<GamepadButton> {
+        STANDARD_BUTTONS.get(index).copied()
+    }
+}
+
+/*
+    Translates controller buttons into Game Boy keys. The defaults follow the
+    Game Boy's own layout, with A to the right of B: the east face button is A and
+    the south one is B, so they sit where A and B are on most pads. The left stick
+    also works as a d-pad. Several buttons can be bound to the same key.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct GamepadMapping {
+    pub bindings: Vec<(GamepadButton, Key)>,
+    pub left_stick_enabled: bool,
+    pub stick_threshold: f32
+}
+
+impl Default for GamepadMapping {
+    fn default() -> Self {
+        GamepadMapping {
+            bindings: vec![
+                (GamepadButton::DPadUp, Key::Up),
+                (GamepadButton::DPadDown, Key::Down),
+                (GamepadButton::DPadLeft, Key::Left),
+                (GamepadButton::DPadRight, Key::Right),
+                (GamepadButton::East, Key::A),
+                (GamepadButton::North, Key::A),
+                (GamepadButton::South, Key::B),
+                (GamepadButton::West, Key::B),
+                (GamepadButton::Start, Key::Start),
+                (GamepadButton::Select, Key::Select)
+            ],
+            left_stick_enabled: true,
+            stick_threshold: DEFAULT_STICK_THRESHOLD
+        }
+    }
+}
+
+impl GamepadMapping {
+    pub fn key_for(&self, button: GamepadButton) -> Option<Key> {
+        self.bindings.iter().find(|(bound, _)| *bound == button).map(|(_, key)| *key)
+    }
+
+    // Replaces whatever the button was bound to.
+    pub fn bind(&mut self, button: GamepadButton, key: Key) {
+        self.unbind(button);
+        self.bindings.push((button, key));
+    }
+
+    pub fn unbind(&mut self, button: GamepadButton) {
+        self.bindings.retain(|(bound, _)| *bound != button);
+    }
+
+    // The left stick's axes go from -1 to 1, with negative values being left and up.
+    pub fn joypad_state(&self, pressed_buttons: &[GamepadButton], left_stick: (f32, f32)) -> JoypadState {
+        let mut keys: Vec<Key> = pressed_buttons.iter().filter_map(|button| self.key_for(*button)).collect();
+
+        if self.left_stick_enabled {
+            let (x, y) = left_stick;
+            if x <= -self.stick_threshold {
+                keys.push(Key::Left);
+            }
+            else if x >= self.stick_threshold {
+                keys.push(Key::Right);
+            }
+            if y <= -self.stick_threshold {
+                keys.push(Key::Up);
+            }
+            else if y >= self.stick_threshold {
+                keys.push(Key::Down);
+            }
+        }
+
+        JoypadState::from_keys(&keys)
+    }
+}
+
+// Keys that changed between two polls of a controller, each with whether it's now pressed.
+pub fn key_changes(previous: JoypadState, current: JoypadState) -> Vec<(Key, bool)> {
+    netplay::INPUT_KEYS.iter()
+        .filter(|key| previous.is_pressed(**key) != current.is_pressed(**key))
+        .map(|key| (*key, current.is_pressed(*key)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_map_standard_buttons_and_stick() {
+        let mapping = GamepadMapping::default();
+        let pressed = [GamepadButton::from_standard_index(1).unwrap(), GamepadButton::from_standard_index(9).unwrap()];
+
+        let state = mapping.joypad_state(&pressed, (-0.8, 0.2));
+
+        assert_eq!(state, JoypadState::from_keys(&[Key::A, Key::Start, Key::Left]));
+        assert_eq!(GamepadButton::from_standard_index(17), None);
+    }
+
+    #[test]
+    fn should_remap_buttons_and_report_changes() {
+        let mut mapping = GamepadMapping::default();
+        mapping.bind(GamepadButton::East, Key::Select);
+        mapping.unbind(GamepadButton::South);
+
+        let previous = mapping.joypad_state(&[GamepadButton::South], (0.0, 0.0));
+        let current = mapping.joypad_state(&[GamepadButton::East], (0.0, 0.0));
+
+        assert_eq!(previous, JoypadState::default());
+        assert_eq!(key_changes(previous, current), vec![(Key::Select, true)]);
+        assert_eq!(key_changes(current, previous), vec![(Key::Select, false)]);
+    }
+}
@@ -0,0 +1,107 @@
+use core::fmt::Debug;
+
+use crate::emulator::Emulator;
+use crate::keys::JoypadState;
+use crate::netplay;
+
+/*
+    An alternative to pressing and releasing keys whenever the frontend gets an
+    event: the emulator asks for the joypad state as each frame starts and holds it
+    for the whole frame. Input then only changes at frame boundaries, which is what
+    deterministic replays, netplay and libretro's retro_input_poll expect.
+
+    No frames start while the LCD is off, so the last polled state stays held until
+    it's back on.
+*/
+pub trait InputPoller: Send {
+    fn poll_input(&mut self, frame_number: u64) -> JoypadState;
+}
+
+impl<F: FnMut(u64) -> JoypadState + Send> InputPoller for F {
+    fn poll_input(&mut self, frame_number: u64) -> JoypadState {
+        self(frame_number)
+    }
+}
+
+impl Debug for dyn InputPoller {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "InputPoller")
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct InputPollingState {
+    pub poller: Option<Box<dyn InputPoller>>,
+    last_polled_frame: Option<u64>
+}
+
+pub fn initialize_input_polling() -> InputPollingState {
+    InputPollingState::default()
+}
+
+pub fn set_input_poller(emulator: &mut Emulator, poller: Option<Box<dyn InputPoller>>) {
+    emulator.input_polling = InputPollingState { poller, last_polled_frame: None };
+}
+
+pub fn step(emulator: &mut Emulator) {
+    let frame_number = emulator.gpu.frame_count;
+    let state = &mut emulator.input_polling;
+
+    if state.last_polled_frame != Some(frame_number) {
+        if let Some(poller) = state.poller.as_mut() {
+            let joypad_state = poller.poll_input(frame_number);
+            state.last_polled_frame = Some(frame_number);
+            netplay::apply_input(emulator, joypad_state.mask);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::builder::EmulatorBuilder;
+    use crate::core::Core;
+    use crate::keys::Key;
+    use crate::mmu::constants::*;
+    use crate::mmu::test_utils::*;
+    use super::*;
+
+    fn setup_emulator() -> Emulator {
+        let rom = build_rom(CART_TYPE_ROM_ONLY, ROM_SIZE_64KB, RAM_SIZE_0KB);
+        let (emulator, _) = EmulatorBuilder::new().with_rom(&rom).skip_boot_rom().build().unwrap();
+        emulator
+    }
+
+    #[test]
+    fn should_poll_once_per_frame_and_hold_input() {
+        let mut emulator = setup_emulator();
+        let polled_frames = Arc::new(Mutex::new(Vec::new()));
+        let recorded_frames = polled_frames.clone();
+        set_input_poller(&mut emulator, Some(Box::new(move |frame_number: u64| {
+            recorded_frames.lock().unwrap().push(frame_number);
+            let keys: &[Key] = if frame_number.is_multiple_of(2) { &[Key::A] } else { &[] };
+            JoypadState::from_keys(keys)
+        })));
+
+        emulator.run_frame();
+        let start_frame = polled_frames.lock().unwrap()[0];
+        let a_pressed = emulator.keys.select_buttons & 0x1 == 0;
+        assert_eq!(a_pressed, start_frame.is_multiple_of(2));
+
+        emulator.run_frame();
+        assert_eq!(*polled_frames.lock().unwrap(), vec![start_frame, start_frame + 1]);
+        assert_eq!(emulator.keys.select_buttons & 0x1 == 0, !a_pressed);
+    }
+
+    #[test]
+    fn should_leave_last_input_held_when_poller_is_removed() {
+        let mut emulator = setup_emulator();
+        set_input_poller(&mut emulator, Some(Box::new(|_| JoypadState::from_keys(&[Key::Start]))));
+        emulator.run_frame();
+        set_input_poller(&mut emulator, None);
+        emulator.run_frame();
+
+        assert_eq!(emulator.keys.select_buttons & 0x8, 0);
+    }
+}
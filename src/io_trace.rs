@@ -0,0 +1,238 @@
+#[cfg(any(test, feature = "internals"))]
+use std::io::{self, Error, ErrorKind};
+
+use crate::emulator::Emulator;
+#[cfg(any(test, feature = "internals"))]
+use crate::emulator;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoAccessKind {
+    Read,
+    Write
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoAccess {
+    // T-cycles since tracing started.
+    pub cycle: u64,
+    pub kind: IoAccessKind,
+    pub address: u16,
+    pub value: u8
+}
+
+#[derive(Debug, Default)]
+pub struct IoTraceState {
+    pub enabled: bool,
+    pub accesses: Vec<IoAccess>,
+    elapsed_cycles: u64,
+    last_total_clock_cycles: u32
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg(any(test, feature = "internals"))]
+pub struct IoDivergence {
+    // Position of the first access that differs, in both traces.
+    pub index: usize,
+    pub expected: Option<IoAccess>,
+    pub actual: Option<IoAccess>,
+    pub instructions_executed: u64,
+    pub program_counter: u16
+}
+
+pub fn initialize_io_trace() -> IoTraceState {
+    IoTraceState::default()
+}
+
+fn is_io_address(address: u16) -> bool {
+    (0xFF00..=0xFF7F).contains(&address) || address == 0xFFFF
+}
+
+/*
+    Logs every read and write the CPU makes to the I/O registers (FF00-FF7F and
+    IE), with the T-cycle it happened on. Comparing the log with one taken from
+    another emulator running the same ROM from power on shows where the two first
+    disagree on what a register held or when it was accessed, which is usually the
+    quickest way to find a timing bug. Accesses by OAM DMA and HDMA aren't logged.
+*/
+#[cfg(any(test, feature = "internals"))]
+pub fn start_io_trace(emulator: &mut Emulator) {
+    emulator.io_trace = IoTraceState {
+        enabled: true,
+        accesses: Vec::new(),
+        elapsed_cycles: 0,
+        last_total_clock_cycles: emulator.cpu.clock.total_clock_cycles
+    };
+}
+
+#[cfg(any(test, feature = "internals"))]
+pub fn stop_io_trace(emulator: &mut Emulator) {
+    emulator.io_trace.enabled = false;
+}
+
+// Overclock bursts and reverse stepping rewind the CPU clock, which must not leave a gap in the trace.
+pub fn sync_clock_reference(emulator: &mut Emulator) {
+    emulator.io_trace.last_total_clock_cycles = emulator.cpu.clock.total_clock_cycles;
+}
+
+pub fn record_access(emulator: &mut Emulator, kind: IoAccessKind, address: u16, value: u8) {
+    if emulator.io_trace.enabled && is_io_address(address) {
+        let total_clock_cycles = emulator.cpu.clock.total_clock_cycles;
+        let trace = &mut emulator.io_trace;
+        trace.elapsed_cycles += total_clock_cycles.wrapping_sub(trace.last_total_clock_cycles) as u64;
+        trace.last_total_clock_cycles = total_clock_cycles;
+        trace.accesses.push(IoAccess { cycle: trace.elapsed_cycles, kind, address, value });
+    }
+}
+
+#[cfg(any(test, feature = "internals"))]
+fn invalid_line(line_number: usize, line: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("Invalid I/O trace line {}: {}", line_number, line))
+}
+
+#[cfg(any(test, feature = "internals"))]
+fn parse_line(line: &str) -> Option<IoAccess> {
+    let mut fields = line.split_whitespace();
+    let cycle = fields.next()?.parse().ok()?;
+    let kind = match fields.next()? {
+        "R" => IoAccessKind::Read,
+        "W" => IoAccessKind::Write,
+        _ => return None
+    };
+    let address = u16::from_str_radix(fields.next()?, 16).ok()?;
+    let value = u8::from_str_radix(fields.next()?, 16).ok()?;
+
+    if fields.next().is_some() {
+        return None;
+    }
+
+    Some(IoAccess { cycle, kind, address, value })
+}
+
+/*
+    Traces are text with one access per line: the cycle in decimal, R or W, then
+    the address and value in hex, e.g. "1024 W FF40 91". Blank lines and lines
+    starting with '#' are skipped. Logs from other emulators (e.g. a SameBoy build
+    with I/O logging patched in) need converting to this format first.
+*/
+#[cfg(any(test, feature = "internals"))]
+pub fn parse_io_trace(text: &str) -> io::Result<Vec<IoAccess>> {
+    text.lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line_number, line)| parse_line(line).ok_or_else(|| invalid_line(line_number, line)))
+        .collect()
+}
+
+#[cfg(any(test, feature = "internals"))]
+pub fn format_io_trace(accesses: &[IoAccess]) -> String {
+    accesses.iter()
+        .map(|access| {
+            let kind = match access.kind {
+                IoAccessKind::Read => "R",
+                IoAccessKind::Write => "W"
+            };
+            format!("{} {} {:04X} {:02X}\n", access.cycle, kind, access.address, access.value)
+        })
+        .collect()
+}
+
+#[cfg(any(test, feature = "internals"))]
+fn find_mismatch(expected: &[IoAccess], actual: &[IoAccess], from: usize) -> Option<usize> {
+    (from..actual.len()).find(|index| expected.get(*index) != actual.get(*index))
+}
+
+/*
+    Traces the emulator from its current state and checks each access against the
+    expected trace as it happens, stopping at the first one that differs. Running
+    out of instructions, or running past the end of the expected trace, counts as
+    a match. The expected trace must have been taken from the same starting point.
+*/
+#[cfg(any(test, feature = "internals"))]
+pub fn compare_io_trace(emulator: &mut Emulator, expected: &[IoAccess], max_instructions: u64) -> Option<IoDivergence> {
+    start_io_trace(emulator);
+    let mut compared = 0;
+    let mut divergence = None;
+
+    for instructions_executed in 1..=max_instructions {
+        let program_counter = emulator.cpu.registers.program_counter;
+        emulator::step(emulator);
+
+        let actual = &emulator.io_trace.accesses;
+        let checked = actual.len().min(expected.len());
+        if let Some(index) = find_mismatch(expected, &actual[..checked], compared) {
+            divergence = Some(IoDivergence {
+                index,
+                expected: expected.get(index).copied(),
+                actual: actual.get(index).copied(),
+                instructions_executed,
+                program_counter
+            });
+            break;
+        }
+
+        compared = checked;
+        if compared == expected.len() {
+            break;
+        }
+    }
+
+    stop_io_trace(emulator);
+    divergence
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::builder::EmulatorBuilder;
+    use crate::mmu::constants::*;
+    use crate::mmu::test_utils::*;
+    use super::*;
+
+    fn build_emulator(rom: &[u8]) -> Emulator {
+        let (emulator, _) = EmulatorBuilder::new().with_rom(rom).skip_boot_rom().build().unwrap();
+        emulator
+    }
+
+    fn build_rom_writing(value: u8) -> Vec<u8> {
+        let mut rom = build_rom(CART_TYPE_ROM_ONLY, ROM_SIZE_64KB, RAM_SIZE_0KB);
+        // LD A, value; LDH (SCX), A; LDH A, (SCX).
+        rom[0x100..0x106].copy_from_slice(&[0x3E, value, 0xE0, 0x43, 0xF0, 0x43]);
+        rom
+    }
+
+    #[test]
+    fn should_round_trip_trace_text() {
+        let mut emulator = build_emulator(&build_rom_writing(0x12));
+        start_io_trace(&mut emulator);
+        for _ in 0..4 {
+            emulator::step(&mut emulator);
+        }
+
+        let accesses = emulator.io_trace.accesses.clone();
+        assert_eq!(accesses.len(), 2);
+        assert_eq!((accesses[0].kind, accesses[0].address, accesses[0].value), (IoAccessKind::Write, 0xFF43, 0x12));
+        assert_eq!((accesses[1].kind, accesses[1].address, accesses[1].value), (IoAccessKind::Read, 0xFF43, 0x12));
+        assert_eq!(parse_io_trace(&format_io_trace(&accesses)).unwrap(), accesses);
+        assert!(parse_io_trace("# comment\n\n12 X FF40 91").is_err());
+    }
+
+    #[test]
+    fn should_report_first_diverging_access() {
+        let mut reference = build_emulator(&build_rom_writing(0x12));
+        start_io_trace(&mut reference);
+        for _ in 0..4 {
+            emulator::step(&mut reference);
+        }
+        let expected = reference.io_trace.accesses.clone();
+
+        let mut matching = build_emulator(&build_rom_writing(0x12));
+        assert_eq!(compare_io_trace(&mut matching, &expected, 100), None);
+
+        let mut diverging = build_emulator(&build_rom_writing(0x34));
+        let divergence = compare_io_trace(&mut diverging, &expected, 100).unwrap();
+        assert_eq!(divergence.index, 0);
+        assert_eq!(divergence.expected.unwrap().value, 0x12);
+        assert_eq!(divergence.actual.unwrap().value, 0x34);
+        assert_eq!(divergence.program_counter, 0x103);
+    }
+}
@@ -1,6 +1,11 @@
 use crate::emulator::Emulator;
+use crate::frame_metadata;
+use crate::netplay;
+use crate::replay;
+use crate::reverse_step;
 use crate::utils::{reset_bit, set_bit};
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Key {
     Down,
     Up,
@@ -12,6 +17,22 @@ pub enum Key {
     A
 }
 
+// Buttons held during a frame, with a bit per key in the same order as netplay input masks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct JoypadState {
+    pub mask: u8
+}
+
+impl JoypadState {
+    pub fn from_keys(keys: &[Key]) -> JoypadState {
+        JoypadState { mask: netplay::as_input_mask(keys) }
+    }
+
+    pub fn is_pressed(&self, key: Key) -> bool {
+        self.mask & netplay::as_input_mask(&[key]) != 0
+    }
+}
+
 #[derive(Debug)]
 pub struct KeyState {
     pub column: u8,
@@ -37,6 +58,10 @@ pub fn initialize_keys() -> KeyState {
     }
 }
 
+pub fn joypad_state(emulator: &Emulator) -> JoypadState {
+    JoypadState { mask: !((emulator.keys.directional_buttons << 4) | (emulator.keys.select_buttons & 0xF)) }
+}
+
 pub fn write_joyp_byte(key_state: &mut KeyState, value: u8) {
     key_state.column = value & 0x30;
 }
@@ -58,6 +83,10 @@ fn fire_joyp_interrupt(emulator: &mut Emulator) {
 }
 
 pub fn handle_key_press(emulator: &mut Emulator, key: &Key) {
+    replay::record_input(emulator, *key, true);
+    reverse_step::record_input(emulator, *key, true);
+    frame_metadata::record_press(emulator, *key);
+
     match key {
         Key::Down =>
             emulator.keys.directional_buttons = reset_bit(emulator.keys.directional_buttons, DOWN_BIT),
@@ -81,6 +110,9 @@ pub fn handle_key_press(emulator: &mut Emulator, key: &Key) {
 }
 
 pub fn handle_key_release(emulator: &mut Emulator, key: &Key) {
+    replay::record_input(emulator, *key, false);
+    reverse_step::record_input(emulator, *key, false);
+
     match key {
         Key::Down =>
             emulator.keys.directional_buttons = set_bit(emulator.keys.directional_buttons, DOWN_BIT),
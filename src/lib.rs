@@ -1,13 +1,108 @@
-pub mod cpu;
-pub mod mmu;
-pub mod gpu;
-pub mod dma;
-pub mod apu;
-pub mod utils;
-pub mod keys;
-pub mod emulator;
+/*
+    The emulator's building blocks are only public with the "internals" feature.
+    Frontends are expected to go through GameBoy and the prelude instead.
+
+    Without the feature, anything GameBoy doesn't use is reported as dead code.
+    Tools that nothing else in the crate uses, like the GBS player, are listed
+    separately so that's silenced for the whole module. Elsewhere, items that
+    only internals users need are compiled out one by one, and kept in test
+    builds when their tests need them.
+*/
+macro_rules! internal_modules {
+    (tools: $($module:ident),*) => {
+        $(
+            #[cfg(feature = "internals")]
+            pub mod $module;
+            #[cfg(not(feature = "internals"))]
+            #[allow(dead_code)]
+            mod $module;
+        )*
+    };
+    ($($module:ident),*) => {
+        $(
+            #[cfg(feature = "internals")]
+            pub mod $module;
+            #[cfg(not(feature = "internals"))]
+            mod $module;
+        )*
+    };
+}
+
+internal_modules!(
+    cpu,
+    mmu,
+    gpu,
+    dma,
+    apu,
+    utils,
+    keys,
+    emulator,
+    speed_switch,
+    serial,
+    cheats,
+    savestate,
+    replay,
+    save_slots,
+    overlay,
+    stats,
+    rumble,
+    sensors,
+    accuracy,
+    pause,
+    boot,
+    builder,
+    debug_hooks,
+    patches,
+    symbols,
+    breakpoints,
+    call_stack,
+    code_data_log,
+    watches,
+    reverse_step,
+    profiler,
+    autosave,
+    snapshot,
+    netplay,
+    pacing,
+    overclock,
+    unmapped_writes,
+    rom_database,
+    hle_boot,
+    emulated_rtc,
+    hotswap,
+    input_polling,
+    frame_metadata,
+    io_trace,
+    game_config,
+    save_bundle,
+    input_mapping
+);
+
+internal_modules!(tools:
+    desync,
+    gbs
+);
+
+#[cfg(feature = "gdb")]
+pub mod gdb_stub;
+
+#[cfg(feature = "terminal")]
+pub mod terminal;
+
+#[cfg(feature = "bus-observer")]
+pub mod bus_observer;
+
+#[cfg(feature = "debug-json")]
+pub mod debug_json;
+
 pub mod wasm;
-pub mod speed_switch;
-pub mod serial;
-pub mod cheats;
-mod bios;
\ No newline at end of file
+pub mod core;
+pub mod audio_sink;
+pub mod clock;
+pub mod compatibility;
+pub mod cycles;
+pub mod gameboy;
+pub mod prelude;
+mod bios;
+
+pub use crate::gameboy::GameBoy;
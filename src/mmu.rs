@@ -1,36 +1,84 @@
 use crate::bios::{CGB_BOOT, DMG_BOOTIX};
+use crate::clock::SharedClock;
 use crate::mmu::cartridge::{initialize_cartridge_mapper, CartridgeMapper};
 use crate::{apu, cheats, dma, gpu, serial};
+use crate::apu::register_log;
 use crate::cpu::hdma;
 use crate::emulator::{is_cgb, Emulator};
 use crate::mmu::effects::empty_cartridge_effects;
 use crate::speed_switch;
 use crate::keys;
+use crate::rumble;
+use crate::watches;
+use crate::autosave;
+use crate::rom_database;
+use crate::unmapped_writes;
 use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
 
-pub use crate::mmu::cartridge::CartridgeHeader;
+pub use crate::mmu::cartridge::{CartridgeHeader, CartridgeOverrides};
 pub use crate::mmu::effects::CartridgeEffects;
 pub use crate::mmu::mbc3::RTCState;
+pub(crate) use crate::mmu::mbc1::is_multicart;
 
 pub struct Memory {
     pub in_bios: bool,
     pub bios: Vec<u8>,
-    pub working_ram: [u8; 0x10000],
+    pub working_ram: [u8; WORKING_RAM_SIZE],
     pub zero_page_ram: [u8; 0x80],
     pub svbk: u8,
     pub cartridge_mapper: Box<dyn CartridgeMapper>,
-    pub processor_test_ram: [u8; 0xFFFF]
+    pub cartridge_ram_version: CartridgeRamVersion,
+    // Flat 64KB address space for CPU tests, only allocated in processor test mode.
+    pub processor_test_ram: Vec<u8>
 }
 
+/*
+    Identifies the contents of the cartridge RAM (and MBC6 flash), so snapshots can skip copying it
+    when it hasn't changed since they were taken. Writes through the bus count up from the current
+    epoch, while anything that replaces or edits the RAM directly starts a new epoch. Epochs are unique
+    across emulators, so two equal versions always refer to the same RAM contents.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CartridgeRamVersion {
+    epoch: u64,
+    writes: u64
+}
+
+impl CartridgeRamVersion {
+    pub fn new() -> CartridgeRamVersion {
+        static NEXT_EPOCH: AtomicU64 = AtomicU64::new(0);
+        CartridgeRamVersion {
+            epoch: NEXT_EPOCH.fetch_add(1, Ordering::Relaxed),
+            writes: 0
+        }
+    }
+}
+
+impl Default for CartridgeRamVersion {
+    fn default() -> CartridgeRamVersion {
+        CartridgeRamVersion::new()
+    }
+}
+
+// Must be called whenever the cartridge RAM is modified other than through the bus.
+pub fn invalidate_cartridge_ram_version(memory: &mut Memory) {
+    memory.cartridge_ram_version = CartridgeRamVersion::new();
+}
+
+// Eight 4KB banks on the CGB, of which the DMG only uses the first two.
+pub const WORKING_RAM_SIZE: usize = 0x8000;
+
 pub fn initialize_memory() -> Memory {
     Memory {
         in_bios: true,
         bios: [0; 0x100].to_vec(),
-        working_ram: [0; 0x10000],
+        working_ram: [0; WORKING_RAM_SIZE],
         zero_page_ram: [0; 0x80],
         svbk: 0,
         cartridge_mapper: initialize_cartridge_mapper(empty_cartridge_effects()),
-        processor_test_ram: [0; 0xFFFF]
+        cartridge_ram_version: CartridgeRamVersion::new(),
+        processor_test_ram: Vec::new()
     }
 }
 
@@ -58,6 +106,24 @@ pub fn get_working_ram_bank(emulator: &Emulator) -> u8 {
     }
 }
 
+/*
+    Reads from 0xFEA0-0xFEFF depend on the hardware. The DMG returns 0x00, or
+    0xFF while the PPU is reading OAM. Later CGB revisions and the AGB repeat the
+    high nibble of the address' low byte (e.g. 0xFEB4 reads 0xBB).
+*/
+fn read_prohibited_area(emulator: &Emulator, address: u16) -> u8 {
+    if is_cgb(emulator) {
+        let nibble = ((address & 0xF0) >> 4) as u8;
+        (nibble << 4) | nibble
+    }
+    else if gpu::oam_locked(emulator) {
+        0xFF
+    }
+    else {
+        0x00
+    }
+}
+
 fn calculate_working_ram_index(emulator: &Emulator, address: u16) -> usize {
     let localized_index = address & 0x1FFF;
     if localized_index <= 0xFFF {
@@ -70,9 +136,72 @@ fn calculate_working_ram_index(emulator: &Emulator, address: u16) -> usize {
     }
 }
 
+// The value the CPU reads from an I/O register in FF00-FF7F, without any side effects.
+pub fn read_io_register(emulator: &Emulator, address: u16) -> u8 {
+    io_registers::apply_read_mask(address, read_io_register_state(emulator, address))
+}
+
+fn read_io_register_state(emulator: &Emulator, address: u16) -> u8 {
+    match address & 0xFF {
+        _ if !is_cgb(emulator) && io_registers::is_cgb_only(address) => 0xFF,
+        0x00 => keys::read_joyp_byte(&emulator.keys),
+        0x01 => serial::get_data(emulator),
+        // The clock speed bit only exists on the CGB.
+        0x02 if !is_cgb(emulator) => serial::get_control(emulator) | 0b10,
+        0x02 => serial::get_control(emulator),
+        0x10 => emulator.apu.channel1.sweep.initial_settings,
+        0x11 => emulator.apu.channel1.length.initial_settings,
+        0x12 => emulator.apu.channel1.envelope.initial_settings,
+        0x14 => emulator.apu.channel1.period.high,
+        0x16 => emulator.apu.channel2.length.initial_settings,
+        0x17 => emulator.apu.channel2.envelope.initial_settings,
+        0x19 => emulator.apu.channel2.period.high,
+        0x1A => if emulator.apu.channel3.dac_enabled { 0b10000000 } else { 0 },
+        0x1C => emulator.apu.channel3.volume,
+        0x1E => emulator.apu.channel3.period.high,
+        0x21 => emulator.apu.channel4.envelope.initial_settings,
+        0x22 => emulator.apu.channel4.polynomial,
+        0x23 => emulator.apu.channel4.control,
+        0x24 => emulator.apu.master_volume,
+        0x25 => emulator.apu.sound_panning,
+        0x26 => apu::get_audio_master_control(emulator),
+        0x30..=0x3F => apu::get_wave_ram_byte(emulator, (address & 0xF) as u8),
+        0x40 => gpu::get_lcdc(emulator),
+        0x41 => gpu::get_stat(emulator),
+        0x42 => emulator.gpu.registers.scy,
+        0x43 => emulator.gpu.registers.scx,
+        0x44 => emulator.gpu.registers.ly,
+        0x45 => emulator.gpu.registers.lyc,
+        0x46 => dma::get_source(emulator),
+        0x47 => emulator.gpu.registers.palettes.bgp,
+        0x48 => emulator.gpu.registers.palettes.obp0,
+        0x49 => emulator.gpu.registers.palettes.obp1,
+        0x4A => emulator.gpu.registers.wy,
+        0x4B => emulator.gpu.registers.wx,
+        0x4C => gpu::get_key0(emulator),
+        0x4D => speed_switch::get_key1(emulator),
+        0x4F => gpu::get_cgb_vbk(emulator),
+        0x55 => hdma::get_hdma5(emulator),
+        0x68 => gpu::get_cgb_bcps(emulator),
+        0x69 => gpu::get_cgb_bcpd(emulator),
+        0x6A => gpu::get_cgb_ocps(emulator),
+        0x6B => gpu::get_cgb_ocpd(emulator),
+        0x6C => gpu::get_cgb_opri(emulator),
+        0x70 => emulator.memory.svbk,
+        0x76 => apu::get_pcm12(emulator),
+        0x77 => apu::get_pcm34(emulator),
+        0x0F => emulator.interrupts.flags,
+        0x04 => emulator.timers.divider,
+        0x05 => emulator.timers.counter,
+        0x06 => emulator.timers.modulo,
+        0x07 => emulator.timers.control,
+        _ => 0xFF
+    }
+}
+
 pub fn read_byte(emulator: &mut Emulator, address: u16) -> u8 {
     if emulator.processor_test_mode {
-        emulator.memory.processor_test_ram[address as usize]
+        emulator.memory.processor_test_ram.get(address as usize).copied().unwrap_or(0)
     }
     else {
         let byte = if address_accessible(emulator, address) {
@@ -80,6 +209,7 @@ pub fn read_byte(emulator: &mut Emulator, address: u16) -> u8 {
                 0x0000 if address <= 0x00FE && emulator.memory.in_bios => {
                     if address == 0x00FE {
                         emulator.memory.in_bios = false;
+                        rom_database::apply_compatibility_palette(emulator);
                     }
                     emulator.memory.bios[address as usize]
                 },
@@ -88,6 +218,7 @@ pub fn read_byte(emulator: &mut Emulator, address: u16) -> u8 {
                 },
                 0x0000..=0x7FFF =>
                     emulator.memory.cartridge_mapper.read_rom(address),
+                0x8000..=0x9FFF if gpu::vram_locked(emulator) => 0xFF,
                 0x8000..=0x9FFF =>
                     gpu::get_video_ram_byte(emulator, address & 0x1FFF),
                 0xA000..=0xBFFF =>
@@ -102,58 +233,10 @@ pub fn read_byte(emulator: &mut Emulator, address: u16) -> u8 {
                         emulator.memory.working_ram[index]
                     },
                     0xE00 if address < 0xFEA0 => gpu::get_object_attribute_memory_byte(emulator, address & 0xFF),
+                    0xE00 => read_prohibited_area(emulator, address),
                     0xF00 if address == 0xFFFF => emulator.interrupts.enabled,
                     0xF00 if address >= 0xFF80 => emulator.memory.zero_page_ram[(address & 0x7F) as usize],
-                    _ => match address & 0xFF {
-                        0x00 => keys::read_joyp_byte(&emulator.keys),
-                        0x01 => serial::get_data(emulator),
-                        0x02 => serial::get_control(emulator),
-                        0x10 => emulator.apu.channel1.sweep.initial_settings | 0b10000000,
-                        0x11 => emulator.apu.channel1.length.initial_settings | 0b00111111,
-                        0x12 => emulator.apu.channel1.envelope.initial_settings,
-                        0x14 => emulator.apu.channel1.period.high | 0b10111111,
-                        0x16 => emulator.apu.channel2.length.initial_settings | 0b00111111,
-                        0x17 => emulator.apu.channel2.envelope.initial_settings,
-                        0x19 => emulator.apu.channel2.period.high | 0b10111111,
-                        0x1A => if emulator.apu.channel3.dac_enabled { 0b11111111 } else { 0b01111111 },
-                        0x1C => emulator.apu.channel3.volume | 0b10011111,
-                        0x1E => emulator.apu.channel3.period.high | 0b10111111,
-                        0x21 => emulator.apu.channel4.envelope.initial_settings,
-                        0x22 => emulator.apu.channel4.polynomial,
-                        0x23 => emulator.apu.channel4.control | 0b10111111,
-                        0x24 => emulator.apu.master_volume,
-                        0x25 => emulator.apu.sound_panning,
-                        0x26 => apu::get_audio_master_control(&emulator),
-                        0x30..=0x3F => apu::get_wave_ram_byte(&emulator, (address & 0xF) as u8),
-                        0x40 => gpu::get_lcdc(emulator),
-                        0x41 => emulator.gpu.registers.stat,
-                        0x42 => emulator.gpu.registers.scy,
-                        0x43 => emulator.gpu.registers.scx,
-                        0x44 => emulator.gpu.registers.ly,
-                        0x45 => emulator.gpu.registers.lyc,
-                        0x46 => dma::get_source(emulator),
-                        0x47 => emulator.gpu.registers.palettes.bgp,
-                        0x48 => emulator.gpu.registers.palettes.obp0,
-                        0x49 => emulator.gpu.registers.palettes.obp1,
-                        0x4A => emulator.gpu.registers.wy,
-                        0x4B => emulator.gpu.registers.wx,
-                        0x4C => gpu::get_key0(emulator),
-                        0x4D => speed_switch::get_key1(emulator),
-                        0x4F => gpu::get_cgb_vbk(emulator),
-                        0x55 => hdma::get_hdma5(emulator),
-                        0x68 => gpu::get_cgb_bcps(emulator),
-                        0x69 => gpu::get_cgb_bcpd(emulator),
-                        0x6A => gpu::get_cgb_ocps(emulator),
-                        0x6B => gpu::get_cgb_ocpd(emulator),
-                        0x6C => gpu::get_cgb_opri(emulator),
-                        0x70 => if is_cgb(emulator) { emulator.memory.svbk } else { 0xFF },
-                        0x0F => emulator.interrupts.flags,
-                        0x04 => emulator.timers.divider,
-                        0x05 => emulator.timers.counter,
-                        0x06 => emulator.timers.modulo,
-                        0x07 => emulator.timers.control,
-                        _ => 0xFF
-                    }
+                    _ => read_io_register(emulator, address)
                 },
                 _ => 0x00,
             }
@@ -168,17 +251,31 @@ pub fn read_byte(emulator: &mut Emulator, address: u16) -> u8 {
 
 pub fn write_byte(emulator: &mut Emulator, address: u16, value: u8) {
     if emulator.processor_test_mode {
-        emulator.memory.processor_test_ram[address as usize] = value;
+        if let Some(byte) = emulator.memory.processor_test_ram.get_mut(address as usize) {
+            *byte = value;
+        }
     }
     else {
         if address_accessible(emulator, address) {
+            let value = watches::apply_freeze(emulator, address, value);
+            register_log::record_write(emulator, address, value);
+
             match address & 0xF000 {
-                0x0000..=0x7FFF =>
-                    emulator.memory.cartridge_mapper.write_rom(address, value),
+                0x0000..=0x7FFF => {
+                    // Some mappers (e.g. TAMA5) write their RAM through registers in the ROM area.
+                    emulator.memory.cartridge_ram_version.writes += 1;
+                    emulator.memory.cartridge_mapper.write_rom(address, value);
+                    rumble::sync(emulator);
+                    autosave::record_rom_write(emulator, address, value);
+                },
+                0x8000..=0x9FFF if gpu::vram_locked(emulator) => (),
                 0x8000..=0x9FFF =>
                     gpu::set_video_ram_byte(emulator, address & 0x1FFF, value),
-                0xA000..=0xBFFF =>
-                    emulator.memory.cartridge_mapper.write_ram(address & 0x1FFF, value),
+                0xA000..=0xBFFF => {
+                    emulator.memory.cartridge_ram_version.writes += 1;
+                    emulator.memory.cartridge_mapper.write_ram(address & 0x1FFF, value);
+                    autosave::record_ram_write(emulator);
+                },
                 0xC000..=0xEFFF => {
                     let index = calculate_working_ram_index(emulator, address);
                     emulator.memory.working_ram[index] = value;
@@ -189,9 +286,13 @@ pub fn write_byte(emulator: &mut Emulator, address: u16, value: u8) {
                         emulator.memory.working_ram[index] = value;
                     },
                     0xE00 if address < 0xFEA0 => gpu::set_object_attribute_memory_byte(emulator, address & 0xFF, value),
+                    0xE00 => (), // Writes to the prohibited area are ignored.
                     0xF00 if address == 0xFFFF => emulator.interrupts.enabled = value,
                     0xF00 if address >= 0xFF80 => emulator.memory.zero_page_ram[(address & 0x7F) as usize] = value,
                     _ => match address & 0xFF {
+                        // CGB registers are unmapped on the DMG, so writing to them does nothing.
+                        _ if !is_cgb(emulator) && io_registers::is_cgb_only(address) =>
+                            unmapped_writes::record_write(emulator, address, value),
                         0x00 => keys::write_joyp_byte(&mut emulator.keys, value),
                         0x01 => serial::set_data(emulator, value),
                         0x02 => serial::set_control(emulator, value),
@@ -218,11 +319,11 @@ pub fn write_byte(emulator: &mut Emulator, address: u16, value: u8) {
                         0x26 => apu::set_audio_master_control(emulator, value),
                         0x30..=0x3F => apu::set_wave_ram_byte(emulator, (address & 0xF) as u8, value),
                         0x40 => gpu::set_lcdc(emulator, value),
-                        0x41 => emulator.gpu.registers.stat = value,
+                        0x41 => gpu::set_stat(emulator, value),
                         0x42 => emulator.gpu.registers.scy = value,
                         0x43 => emulator.gpu.registers.scx = value,
-                        0x44 => emulator.gpu.registers.ly = value,
-                        0x45 => emulator.gpu.registers.lyc = value,
+                        0x44 => (), // LY is read-only
+                        0x45 => gpu::set_lyc(emulator, value),
                         0x46 => dma::start_dma(emulator, value),
                         0x47 => emulator.gpu.registers.palettes.bgp = value,
                         0x48 => emulator.gpu.registers.palettes.obp0 = value,
@@ -242,11 +343,7 @@ pub fn write_byte(emulator: &mut Emulator, address: u16, value: u8) {
                         0x6A => gpu::set_cgb_ocps(emulator, value),
                         0x6B => gpu::set_cgb_ocpd(emulator, value),
                         0x6C => gpu::set_cgb_opri(emulator, value),
-                        0x70 => {
-                            if is_cgb(emulator) {
-                                emulator.memory.svbk = value;
-                            }
-                        },
+                        0x70 => emulator.memory.svbk = value,
                         0x0F => emulator.interrupts.flags = value,
                         0x04 => {
                             emulator.timers.divider = value;
@@ -256,7 +353,8 @@ pub fn write_byte(emulator: &mut Emulator, address: u16, value: u8) {
                         0x05 => emulator.timers.counter = value,
                         0x06 => emulator.timers.modulo = value,
                         0x07 => emulator.timers.control = value,
-                        _ => ()
+                        0x50 => (), // The boot ROM is unmapped once it runs to the end.
+                        _ => unmapped_writes::record_write(emulator, address, value)
                     }
                 },
                 _ => (),
@@ -265,17 +363,28 @@ pub fn write_byte(emulator: &mut Emulator, address: u16, value: u8) {
     }
 }
 
+fn insert_mapper(memory: &mut Memory, mapper: Box<dyn CartridgeMapper>) -> CartridgeHeader {
+    let header = mapper.get_cartridge().header.clone();
+    memory.cartridge_mapper = mapper;
+    invalidate_cartridge_ram_version(memory);
+    header
+}
+
+// Leaves the cartridge bus open, as if no cartridge were inserted.
+pub fn remove_cartridge(memory: &mut Memory) {
+    memory.cartridge_mapper = initialize_cartridge_mapper(empty_cartridge_effects());
+    invalidate_cartridge_ram_version(memory);
+}
+
+#[cfg(any(test, feature = "internals"))]
 pub fn load_rom_buffer(memory: &mut Memory, buffer: Vec<u8>, cartridge_effects: Box<dyn CartridgeEffects>) -> io::Result<CartridgeHeader> {
-    let cartridge_result = cartridge::load_rom_buffer(buffer, cartridge_effects); 
-    match cartridge_result {
-        Ok(mapper) => {
-            let cartridge = mapper.get_cartridge();
-            let header = cartridge.header.clone();
-            memory.cartridge_mapper = mapper;
-            Ok(header)
-        },
-        Err(e) => Err(e)
-    }
+    let mapper = cartridge::load_rom_buffer(buffer, cartridge_effects)?;
+    Ok(insert_mapper(memory, mapper))
+}
+
+pub fn load_rom_buffer_with_overrides(memory: &mut Memory, buffer: Vec<u8>, cartridge_effects: Box<dyn CartridgeEffects>, overrides: CartridgeOverrides, clock: SharedClock) -> io::Result<CartridgeHeader> {
+    let mapper = cartridge::load_rom_buffer_with_overrides(buffer, cartridge_effects, overrides, clock)?;
+    Ok(insert_mapper(memory, mapper))
 }
 
 pub fn get_cartridge_ram(memory: &Memory) -> Vec<u8> {
@@ -285,10 +394,44 @@ pub fn get_cartridge_ram(memory: &Memory) -> Vec<u8> {
 
 pub fn set_cartridge_ram(memory: &mut Memory, buffer: Vec<u8>) {
     memory.cartridge_mapper.set_cartridge_ram(buffer);
+    invalidate_cartridge_ram_version(memory);
+}
+
+pub fn get_battery_save(memory: &Memory) -> Vec<u8> {
+    let mut save = get_cartridge_ram(memory);
+    if let Some(flash) = memory.cartridge_mapper.get_flash() {
+        save.extend_from_slice(flash);
+    }
+    if let Some(rtc_state) = memory.cartridge_mapper.get_rtc_state() {
+        save.extend(mbc3::encode_rtc_footer(rtc_state));
+    }
+    save
+}
+
+pub fn set_battery_save(memory: &mut Memory, mut buffer: Vec<u8>) {
+    let ram_size = memory.cartridge_mapper.get_cartridge().ram.len();
+    let has_rtc = memory.cartridge_mapper.get_rtc_state().is_some();
+
+    if let Some(flash) = memory.cartridge_mapper.get_flash_mut() {
+        if buffer.len() == ram_size + flash.len() {
+            flash.copy_from_slice(&buffer.split_off(ram_size));
+        }
+    }
+
+    if has_rtc && buffer.len() > ram_size {
+        let footer = buffer.split_off(ram_size);
+        if let Some(rtc_state) = mbc3::decode_rtc_footer(&footer) {
+            memory.cartridge_mapper.set_rtc_state(rtc_state);
+        }
+    }
+
+    set_cartridge_ram(memory, buffer);
 }
 
 #[cfg(test)]
 pub mod test_utils {
+    use crate::builder::EmulatorBuilder;
+    use crate::emulator::Emulator;
     use crate::mmu::cartridge::*;
     use crate::mmu::constants::*;
 
@@ -301,6 +444,17 @@ pub mod test_utils {
         rom_buffer[RAM_SIZE_ADDRESS] = ram_size_index;
         rom_buffer
     }
+
+    // A 64KB cartridge of the given type, started at its entry point.
+    pub fn setup_emulator(cartridge_type: u8, ram_size_index: u8) -> Emulator {
+        setup_emulator_with_rom(build_rom(cartridge_type, ROM_SIZE_64KB, ram_size_index))
+    }
+
+    // For tests that patch the ROM or need more banks than setup_emulator gives them.
+    pub fn setup_emulator_with_rom(rom: Vec<u8>) -> Emulator {
+        let (emulator, _) = EmulatorBuilder::new().with_rom(&rom).skip_boot_rom().build().unwrap();
+        emulator
+    }
 }
 
 #[cfg(test)]
@@ -308,10 +462,16 @@ mod tests;
 
 pub mod constants;
 pub mod effects;
+pub mod io_registers;
+pub mod ram_editor;
 mod cartridge;
 mod huc1;
+mod m161;
 mod mbc1;
 mod mbc3;
 mod mbc5;
+mod mbc6;
 mod mbc_rom_only;
+mod tama5;
+mod wisdom_tree;
 mod bank_utils;
\ No newline at end of file
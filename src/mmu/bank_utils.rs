@@ -1,11 +1,18 @@
+// ROMs can be shorter than their header claims, so anything past the end of the buffer reads as open bus.
+pub fn unbanked_read(rom: &[u8], address: u16) -> u8 {
+    rom.get(address as usize).copied().unwrap_or(0xFF)
+}
+
 pub fn banked_read(rom: &Vec<u8>, bank_size: u32, address: u16, bank: u16) -> u8 {
     let base_location = bank as u32 * bank_size;
     let calculated_address = base_location + ((address as u32 & (bank_size - 1)) as u32);
-    rom[calculated_address as usize]
+    rom.get(calculated_address as usize).copied().unwrap_or(0xFF)
 }
 
 pub fn banked_write(rom: &mut Vec<u8>, bank_size: u32, address: u16, bank: u16, value: u8) {
     let base_location = bank as u32 * bank_size;
     let calculated_address = base_location + ((address as u32 & (bank_size - 1)) as u32);
-    rom[calculated_address as usize] = value;
-}
\ No newline at end of file
+    if let Some(byte) = rom.get_mut(calculated_address as usize) {
+        *byte = value;
+    }
+}
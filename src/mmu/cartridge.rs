@@ -1,13 +1,20 @@
-use core::panic;
 use std::io;
 
+use crate::clock::{system_clock, SharedClock};
+use crate::savestate::{StateReader, StateWriter};
+use crate::sensors::SensorReadings;
+
 use crate::mmu::constants::*;
 use crate::mmu::effects::CartridgeEffects;
 use crate::mmu::huc1::initialize_huc1;
+use crate::mmu::m161::initialize_m161;
 use crate::mmu::mbc1::initialize_mbc1;
-use crate::mmu::mbc3::initialize_mbc3;
+use crate::mmu::mbc3::{initialize_mbc3, RTCState};
 use crate::mmu::mbc5::initialize_mbc5;
+use crate::mmu::mbc6::initialize_mbc6;
 use crate::mmu::mbc_rom_only::initialize_mbc_rom_only;
+use crate::mmu::tama5::initialize_tama5;
+use crate::mmu::wisdom_tree::initialize_wisdom_tree;
 
 #[derive(Debug, Clone)]
 pub struct CartridgeHeader {
@@ -16,7 +23,8 @@ pub struct CartridgeHeader {
     pub max_banks: u16,
     pub max_ram_banks: u8,
     pub title: String,
-    pub has_battery: bool
+    pub has_battery: bool,
+    pub global_checksum: u16
 }
 
 #[derive(Debug)]
@@ -24,20 +32,60 @@ pub struct Cartridge {
     pub rom: Vec<u8>,
     pub ram: Vec<u8>,
     pub header: CartridgeHeader,
-    pub effects: Box<dyn CartridgeEffects>
+    pub effects: Box<dyn CartridgeEffects>,
+    pub clock: SharedClock
 }
 
-pub trait CartridgeMapper: std::fmt::Debug {
+pub trait CartridgeMapper: std::fmt::Debug + Send {
     fn read_rom(&self, address: u16) -> u8;
     fn write_rom(&mut self, address: u16, value: u8);
     fn read_ram(&self, address: u16) -> u8;
     fn write_ram(&mut self, address: u16, value: u8);
     fn get_cartridge(&self) -> &Cartridge;
     fn set_cartridge_ram(&mut self, ram: Vec<u8>);
+    fn get_cartridge_ram_mut(&mut self) -> &mut [u8];
+    fn get_rom_bank(&self) -> u16;
     fn get_ram_bank(&self) -> u8;
+    fn serialize_state(&self, writer: &mut StateWriter);
+    fn deserialize_state(&mut self, reader: &mut StateReader) -> io::Result<()>;
+
+    fn get_rtc_state(&self) -> Option<&RTCState> {
+        None
+    }
+
+    fn set_rtc_state(&mut self, _: RTCState) {}
+
+    fn rumble_active(&self) -> bool {
+        false
+    }
+
+    fn ram_enabled(&self) -> bool {
+        false
+    }
+
+    // Battery-backed flash kept apart from the cartridge RAM, as on MBC6.
+    fn get_flash(&self) -> Option<&[u8]> {
+        None
+    }
+
+    fn get_flash_mut(&mut self) -> Option<&mut [u8]> {
+        None
+    }
+
+    // The MBC1 0x6000 register: 0 for simple ROM banking, 1 for advanced banking.
+    fn banking_mode(&self) -> u8 {
+        0
+    }
+
+    fn update_sensors(&mut self, _: &SensorReadings) {}
+
+    fn set_clock(&mut self, _: SharedClock) {}
+
+    // Back to power-on bank selection. Cartridge RAM, flash and the RTC keep their contents.
+    fn reset_registers(&mut self) {}
 }
 
-const SUPPORTED_CARTRIDGE_TYPES: [u8; 16] = [CART_TYPE_ROM_ONLY,
+const SUPPORTED_CARTRIDGE_TYPES: [u8; 18] = [CART_TYPE_ROM_ONLY,
     CART_TYPE_MBC1,
     CART_TYPE_MBC1_WITH_RAM,
     CART_TYPE_MBC1_WITH_RAM_PLUS_BATTERY,
@@ -52,6 +100,8 @@ const SUPPORTED_CARTRIDGE_TYPES: [u8; 16] = [CART_TYPE_ROM_ONLY,
     CART_TYPE_MBC5_RUMBLE,
     CART_TYPE_MBC5_RUMBLE_RAM,
     CART_TYPE_MBC5_RUMBLE_RAM_BATTERY,
+    CART_TYPE_MBC6,
+    CART_TYPE_TAMA5,
     CART_TYPE_HUC1_RAM_BATTERY];
 
 pub fn initialize_cartridge(effects: Box<dyn CartridgeEffects>) -> Cartridge {
@@ -64,9 +114,11 @@ pub fn initialize_cartridge(effects: Box<dyn CartridgeEffects>) -> Cartridge {
             max_banks: 0,
             max_ram_banks: 0,
             title: String::from(""),
-            has_battery: false
+            has_battery: false,
+            global_checksum: 0
         },
-        effects
+        effects,
+        clock: system_clock()
     }
 }
 
@@ -78,6 +130,9 @@ fn cartridge_type_supported(type_code: u8) -> bool {
     SUPPORTED_CARTRIDGE_TYPES.contains(&type_code)
 }
 
+// Only indices up to 0x08 (8MB) are defined, which keeps the bank count within a u16.
+const MAX_ROM_SIZE_INDEX: u8 = 0x08;
+
 pub fn as_max_banks(rom_size_index: u8) -> u16 {
     (2 as u16).pow(rom_size_index as u32 + 1)
 }
@@ -117,6 +172,14 @@ fn is_mbc5(type_code: u8) -> bool {
         | CART_TYPE_MBC5_RUMBLE_RAM_BATTERY)
 }
 
+fn is_mbc6(type_code: u8) -> bool {
+    type_code == CART_TYPE_MBC6
+}
+
+fn is_tama5(type_code: u8) -> bool {
+    type_code == CART_TYPE_TAMA5
+}
+
 fn is_huc1(type_code: u8) -> bool {
     type_code == CART_TYPE_HUC1_RAM_BATTERY
 }
@@ -127,21 +190,25 @@ fn is_battery_backed(type_code: u8) -> bool {
         | CART_TYPE_MBC3_TIMER_RAM_BATTERY
         | CART_TYPE_MBC3_RAM_BATTERY
         | CART_TYPE_MBC5_RAM_BATTERY
+        | CART_TYPE_MBC6
+        | CART_TYPE_TAMA5
         | CART_TYPE_HUC1_RAM_BATTERY)
 }
 
-fn set_ram_size(cartridge: &mut Cartridge) {
-    let ram_size_index = cartridge.rom[RAM_SIZE_ADDRESS];
-    let ram_size = match ram_size_index {
-        0x0 => 0,
-        0x1 => 0x800,
-        0x2 => 0x2000,
-        0x3 => 0x8000,
-        0x4 => 0x20000,
-        0x5 => 0x10000,
-        _ => panic!("Unsupported RAM size index: {}", ram_size_index),
-    };
-    cartridge.ram.resize(ram_size as usize, 0);
+fn as_ram_size(ram_size_index: u8) -> Option<u32> {
+    match ram_size_index {
+        0x0 => Some(0),
+        0x1 => Some(0x800),
+        0x2 => Some(0x2000),
+        0x3 => Some(0x8000),
+        0x4 => Some(0x20000),
+        0x5 => Some(0x10000),
+        _ => None
+    }
+}
+
+fn invalid_header_error(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
 }
 
 fn is_cgb_compatability_flag(index: usize, byte: u8) -> bool {
@@ -182,8 +249,34 @@ pub fn convert_cartridge_type_to_text(type_code: u8) -> String {
     }.to_string()
 }
 
-fn as_mapper(cartridge: Cartridge, type_code: u8) -> Box<dyn CartridgeMapper> {
-    if is_mbc_rom_only(type_code) {
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum UnlicensedMapper {
+    WisdomTree,
+    M161
+}
+
+fn contains_bytes(buffer: &[u8], bytes: &[u8]) -> bool {
+    buffer.windows(bytes.len()).any(|window| window == bytes)
+}
+
+// Unlicensed cartridges have headers claiming some other mapper, so they're recognized by their contents instead.
+fn detect_unlicensed_mapper(buffer: &[u8], type_code: u8, title: &str) -> Option<UnlicensedMapper> {
+    if type_code == CART_TYPE_ROM_ONLY && buffer.len() > 0x8000
+        && (contains_bytes(buffer, b"WISDOM TREE") || contains_bytes(buffer, b"WISDOM\x00TREE")) {
+        Some(UnlicensedMapper::WisdomTree)
+    } else if type_code == CART_TYPE_MBC3_TIMER_RAM_BATTERY && title == "TETRIS SET" {
+        Some(UnlicensedMapper::M161)
+    } else {
+        None
+    }
+}
+
+fn as_mapper(cartridge: Cartridge, type_code: u8, unlicensed_mapper: Option<UnlicensedMapper>) -> io::Result<Box<dyn CartridgeMapper>> {
+    let mapper: Box<dyn CartridgeMapper> = if unlicensed_mapper == Some(UnlicensedMapper::WisdomTree) {
+        Box::new(initialize_wisdom_tree(cartridge))
+    } else if unlicensed_mapper == Some(UnlicensedMapper::M161) {
+        Box::new(initialize_m161(cartridge))
+    } else if is_mbc_rom_only(type_code) {
         Box::new(initialize_mbc_rom_only(cartridge))
     } else if is_huc1(type_code) {
         Box::new(initialize_huc1(cartridge))
@@ -193,16 +286,36 @@ fn as_mapper(cartridge: Cartridge, type_code: u8) -> Box<dyn CartridgeMapper> {
         Box::new(initialize_mbc3(cartridge))
     } else if is_mbc5(type_code) {
         Box::new(initialize_mbc5(cartridge))
+    } else if is_mbc6(type_code) {
+        Box::new(initialize_mbc6(cartridge))
+    } else if is_tama5(type_code) {
+        Box::new(initialize_tama5(cartridge))
     } else {
-        panic!("Unsupported cartridge type: {}", type_code);
-    }
+        return Err(invalid_header_error(format!("Unsupported cartridge type: {}", type_code)));
+    };
+    Ok(mapper)
+}
+
+// Header values to use instead of the ones in the ROM, for cartridges whose headers are wrong.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CartridgeOverrides {
+    pub type_code: Option<u8>,
+    pub ram_size: Option<u32>,
+    pub has_battery: Option<bool>
 }
 
+#[cfg(any(test, feature = "internals"))]
 pub fn load_rom_buffer(buffer: Vec<u8>, effects: Box<dyn CartridgeEffects>) -> io::Result<Box<dyn CartridgeMapper>> {
-    if buffer.len() > ENTRY_POINT_ADDRESS {
-        let type_code = buffer[CARTRIDGE_TYPE_ADDRESS];
+    load_rom_buffer_with_overrides(buffer, effects, CartridgeOverrides::default(), system_clock())
+}
+
+pub fn load_rom_buffer_with_overrides(buffer: Vec<u8>, effects: Box<dyn CartridgeEffects>, overrides: CartridgeOverrides, clock: SharedClock) -> io::Result<Box<dyn CartridgeMapper>> {
+    if buffer.len() >= HEADER_END_ADDRESS {
+        let type_code = overrides.type_code.unwrap_or(buffer[CARTRIDGE_TYPE_ADDRESS]);
         let sgb_support = buffer[SGB_SUPPORT_ADDRESS] == 0x03;
         let rom_size = buffer[ROM_SIZE_ADDRESS];
+        let ram_size_index = buffer[RAM_SIZE_ADDRESS];
+        let global_checksum = ((buffer[GLOBAL_CHECKSUM_ADDRESS] as u16) << 8) | buffer[GLOBAL_CHECKSUM_ADDRESS + 1] as u16;
 
         let title_bytes = &buffer[TITLE_START_ADDRESS..=TITLE_END_ADDRESS];
         let title = title_bytes
@@ -212,7 +325,17 @@ pub fn load_rom_buffer(buffer: Vec<u8>, effects: Box<dyn CartridgeEffects>) -> i
             .map(|(_, &b)| b as char)
             .collect::<String>();
 
-        if cartridge_type_supported(type_code) {
+        if rom_size > MAX_ROM_SIZE_INDEX {
+            return Err(invalid_header_error(format!("Unsupported ROM size index: {}", rom_size)));
+        }
+
+        let Some(ram_size) = overrides.ram_size.or(as_ram_size(ram_size_index)) else {
+            return Err(invalid_header_error(format!("Unsupported RAM size index: {}", ram_size_index)));
+        };
+
+        let unlicensed_mapper = detect_unlicensed_mapper(&buffer, type_code, &title);
+
+        if cartridge_type_supported(type_code) || unlicensed_mapper.is_some() {
             let mut cartridge = Cartridge {
                 rom: buffer,
                 ram: Vec::new(),
@@ -222,29 +345,26 @@ pub fn load_rom_buffer(buffer: Vec<u8>, effects: Box<dyn CartridgeEffects>) -> i
                     max_banks: as_max_banks(rom_size),
                     max_ram_banks: 0,
                     title,
-                    has_battery: is_battery_backed(type_code)
+                    has_battery: overrides.has_battery.unwrap_or(unlicensed_mapper.is_none() && is_battery_backed(type_code)),
+                    global_checksum
                 },
-                effects
+                effects,
+                clock
             };
 
-            let maybe_loaded_ram = cartridge.effects.load_ram(&cartridge.header.title);
-            if maybe_loaded_ram.is_some() {
-                cartridge.ram = maybe_loaded_ram.unwrap();
-            }
-            else {
-                set_ram_size(&mut cartridge);
+            match cartridge.effects.load_ram(&cartridge.header.title) {
+                Some(loaded_ram) => cartridge.ram = loaded_ram,
+                None => cartridge.ram.resize(ram_size as usize, 0)
             }
 
             cartridge.header.max_ram_banks = as_max_ram_banks(cartridge.ram.len() as u32);
 
-            let mapper = as_mapper(cartridge, type_code);
-
-            Ok(mapper)
+            as_mapper(cartridge, type_code, unlicensed_mapper)
         } else {
             let given_cartridge_type = convert_cartridge_type_to_text(type_code);
 
             let error_message = format!(r#"Sorry, but Retro Boy currently only supports
-                ROM-only, MBC1, MBC3, MBC5, or HuC1 cartridges.
+                ROM-only, MBC1, MBC3, MBC5, MBC6, TAMA5, or HuC1 cartridges.
                 The cartridge you provided is of type {}."#, given_cartridge_type);
 
             Err(io::Error::new(io::ErrorKind::Other, error_message))
@@ -258,6 +378,7 @@ pub fn load_rom_buffer(buffer: Vec<u8>, effects: Box<dyn CartridgeEffects>) -> i
 #[cfg(test)]
 pub mod test_utils {
     use super::*;
+    use crate::clock::ManualClock;
     use crate::mmu::effects::empty_cartridge_effects;
     use crate::mmu::test_utils::*;
 
@@ -268,6 +389,7 @@ pub mod test_utils {
 
     pub fn build_cartridge_mapper_with_effects(cartridge_type: u8, rom_size_index: u8, ram_size_index: u8, cartridge_effects: Box<dyn CartridgeEffects>) -> Box<dyn CartridgeMapper> {
         let rom_buffer = build_rom(cartridge_type, rom_size_index, ram_size_index);
-        load_rom_buffer(rom_buffer, cartridge_effects).unwrap()
+        let clock = ManualClock::new(0.0).shared();
+        load_rom_buffer_with_overrides(rom_buffer, cartridge_effects, CartridgeOverrides::default(), clock).unwrap()
     }
 }
\ No newline at end of file
@@ -1,8 +1,11 @@
+#[cfg(feature = "internals")]
 pub const ENTRY_POINT_ADDRESS: usize = 0x100;
 pub const SGB_SUPPORT_ADDRESS: usize = 0x146;
 pub const CARTRIDGE_TYPE_ADDRESS: usize = 0x147;
 pub const ROM_SIZE_ADDRESS: usize = 0x148;
 pub const RAM_SIZE_ADDRESS: usize = 0x149;
+pub const GLOBAL_CHECKSUM_ADDRESS: usize = 0x14E;
+pub const HEADER_END_ADDRESS: usize = 0x150;
 
 pub const CART_TYPE_ROM_ONLY: u8 = 0x0;
 pub const CART_TYPE_MBC1: u8 = 0x1;
@@ -19,6 +22,8 @@ pub const CART_TYPE_MBC5_RAM_BATTERY: u8 = 0x1B;
 pub const CART_TYPE_MBC5_RUMBLE: u8 = 0x1C;
 pub const CART_TYPE_MBC5_RUMBLE_RAM: u8 = 0x1D;
 pub const CART_TYPE_MBC5_RUMBLE_RAM_BATTERY: u8 = 0x1E;
+pub const CART_TYPE_MBC6: u8 = 0x20;
+pub const CART_TYPE_TAMA5: u8 = 0xFD;
 pub const CART_TYPE_HUC1_RAM_BATTERY: u8 = 0xFF;
 
 pub const TITLE_START_ADDRESS: usize = 0x134;
@@ -26,18 +31,30 @@ pub const TITLE_END_ADDRESS: usize = 0x143;
 
 pub const CGB_COMPATABILITY_INDEX: usize = 15;
 
+#[cfg(feature = "internals")]
 pub const ROM_SIZE_32KB: u8 = 0x0;
+#[cfg(any(test, feature = "internals"))]
 pub const ROM_SIZE_64KB: u8 = 0x1;
+#[cfg(any(test, feature = "internals"))]
 pub const ROM_SIZE_128KB: u8 = 0x2;
+#[cfg(any(test, feature = "internals"))]
 pub const ROM_SIZE_256KB: u8 = 0x3;
+#[cfg(any(test, feature = "internals"))]
 pub const ROM_SIZE_512KB: u8 = 0x4;
+#[cfg(any(test, feature = "internals"))]
 pub const ROM_SIZE_1MB: u8 = 0x5;
+#[cfg(any(test, feature = "internals"))]
 pub const ROM_SIZE_2MB: u8 = 0x6;
+#[cfg(feature = "internals")]
 pub const ROM_SIZE_4MB: u8 = 0x7;
 pub const ROM_SIZE_8MB: u8 = 0x8;
 
+#[cfg(any(test, feature = "internals"))]
 pub const RAM_SIZE_0KB: u8 = 0x0;
+#[cfg(any(test, feature = "internals"))]
 pub const RAM_SIZE_2KB: u8 = 0x1;
 pub const RAM_SIZE_8KB: u8 = 0x2;
+#[cfg(any(test, feature = "internals"))]
 pub const RAM_SIZE_32KB: u8 = 0x3;
+#[cfg(any(test, feature = "internals"))]
 pub const RAM_SIZE_128KB: u8 = 0x4;
\ No newline at end of file
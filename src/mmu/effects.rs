@@ -1,8 +1,7 @@
 use core::fmt::Debug;
 use crate::mmu::mbc3::RTCState; 
 
-pub trait CartridgeEffects {
-    fn current_time_millis(&self) -> f64;
+pub trait CartridgeEffects: Send {
     fn load_rtc_state(&self, key: &str) -> Option<RTCState>;
     fn save_rtc_state(&self, key: &str, state: &RTCState);
     fn load_ram(&self, key: &str) -> Option<Vec<u8>>;
@@ -12,10 +11,6 @@ pub trait CartridgeEffects {
 pub struct EmptyCartridgeEffects;
 
 impl CartridgeEffects for EmptyCartridgeEffects {
-    fn current_time_millis(&self) -> f64 {
-        0.0
-    }
-
     fn load_rtc_state(&self, _: &str) -> Option<RTCState> {
         None
     }
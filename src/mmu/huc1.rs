@@ -1,5 +1,11 @@
-use crate::mmu::bank_utils::{banked_read, banked_write};
+use crate::mmu::bank_utils::{banked_read, banked_write, unbanked_read};
 use crate::mmu::cartridge::{Cartridge, CartridgeMapper};
+use crate::savestate::{StateReader, StateWriter};
+use crate::sensors::SensorReadings;
+use std::io;
+
+// The IR receiver only reports whether it sees light, not how much.
+const IR_LIGHT_THRESHOLD: f32 = 0.5;
 
 #[derive(Debug)]
 #[derive(PartialEq)]
@@ -13,6 +19,7 @@ pub struct HUC1 {
     cartridge: Cartridge,
     mode: HUC1Mode,
     ir_transmitter: bool,
+    ir_light_detected: bool,
     rom_bank_number: u8,
     ram_bank_number: u8,
 }
@@ -22,6 +29,7 @@ pub fn initialize_huc1(cartridge: Cartridge) -> HUC1 {
         cartridge,
         mode: HUC1Mode::RAM,
         ir_transmitter: false,
+        ir_light_detected: false,
         rom_bank_number: 1,
         ram_bank_number: 0,
     }
@@ -31,7 +39,7 @@ impl CartridgeMapper for HUC1 {
     fn read_rom(&self, address: u16) -> u8 {
         match address {
             0x0000..=0x3FFF =>
-                self.cartridge.rom[address as usize],
+                unbanked_read(&self.cartridge.rom, address),
             0x4000..=0x7FFF => {
                 banked_read(&self.cartridge.rom, 0x4000, address, self.rom_bank_number as u16)
             },
@@ -65,7 +73,7 @@ impl CartridgeMapper for HUC1 {
         if self.mode == HUC1Mode::RAM && self.cartridge.header.max_ram_banks > 0 {
             banked_read(&self.cartridge.ram, 0x2000, address, self.ram_bank_number as u16)
         } else if self.mode == HUC1Mode::IR {
-            0xC0
+            if self.ir_light_detected { 0xC1 } else { 0xC0 }
         } else {
             0xFF
         }
@@ -91,9 +99,43 @@ impl CartridgeMapper for HUC1 {
         self.cartridge.ram = ram;
     }
     
+    fn get_cartridge_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.cartridge.ram
+    }
+
+    fn get_rom_bank(&self) -> u16 {
+        self.rom_bank_number as u16
+    }
+
     fn get_ram_bank(&self) -> u8 {
         self.ram_bank_number
     }
+
+    fn reset_registers(&mut self) {
+        self.mode = HUC1Mode::RAM;
+        self.ir_transmitter = false;
+        self.rom_bank_number = 1;
+        self.ram_bank_number = 0;
+    }
+
+    fn serialize_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.mode == HUC1Mode::IR);
+        writer.write_bool(self.ir_transmitter);
+        writer.write_u8(self.rom_bank_number);
+        writer.write_u8(self.ram_bank_number);
+    }
+
+    fn deserialize_state(&mut self, reader: &mut StateReader) -> io::Result<()> {
+        self.mode = if reader.read_bool()? { HUC1Mode::IR } else { HUC1Mode::RAM };
+        self.ir_transmitter = reader.read_bool()?;
+        self.rom_bank_number = reader.read_u8()?;
+        self.ram_bank_number = reader.read_u8()?;
+        Ok(())
+    }
+
+    fn update_sensors(&mut self, readings: &SensorReadings) {
+        self.ir_light_detected = readings.ambient_light >= IR_LIGHT_THRESHOLD;
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,238 @@
+use crate::emulator::{is_cgb, Emulator};
+use crate::mmu;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoRegister {
+    pub address: u16,
+    pub name: &'static str,
+    pub value: u8,
+    pub read_mask: u8
+}
+
+/*
+    The read mask holds the bits that reflect register state when read back.
+    The remaining bits are either unused or write-only and read as 1.
+*/
+struct IoRegisterDefinition {
+    address: u16,
+    name: &'static str,
+    read_mask: u8,
+    cgb_only: bool
+}
+
+const fn register(address: u16, name: &'static str, read_mask: u8) -> IoRegisterDefinition {
+    IoRegisterDefinition { address, name, read_mask, cgb_only: false }
+}
+
+const fn cgb_register(address: u16, name: &'static str, read_mask: u8) -> IoRegisterDefinition {
+    IoRegisterDefinition { address, name, read_mask, cgb_only: true }
+}
+
+const IO_REGISTERS: [IoRegisterDefinition; 75] = [
+    register(0xFF00, "P1", 0x3F),
+    register(0xFF01, "SB", 0xFF),
+    register(0xFF02, "SC", 0x83),
+    register(0xFF04, "DIV", 0xFF),
+    register(0xFF05, "TIMA", 0xFF),
+    register(0xFF06, "TMA", 0xFF),
+    register(0xFF07, "TAC", 0x07),
+    register(0xFF0F, "IF", 0x1F),
+    register(0xFF10, "NR10", 0x7F),
+    register(0xFF11, "NR11", 0xC0),
+    register(0xFF12, "NR12", 0xFF),
+    register(0xFF13, "NR13", 0x00),
+    register(0xFF14, "NR14", 0x40),
+    register(0xFF16, "NR21", 0xC0),
+    register(0xFF17, "NR22", 0xFF),
+    register(0xFF18, "NR23", 0x00),
+    register(0xFF19, "NR24", 0x40),
+    register(0xFF1A, "NR30", 0x80),
+    register(0xFF1B, "NR31", 0x00),
+    register(0xFF1C, "NR32", 0x60),
+    register(0xFF1D, "NR33", 0x00),
+    register(0xFF1E, "NR34", 0x40),
+    register(0xFF20, "NR41", 0x00),
+    register(0xFF21, "NR42", 0xFF),
+    register(0xFF22, "NR43", 0xFF),
+    register(0xFF23, "NR44", 0x40),
+    register(0xFF24, "NR50", 0xFF),
+    register(0xFF25, "NR51", 0xFF),
+    register(0xFF26, "NR52", 0x8F),
+    register(0xFF30, "WAVE0", 0xFF),
+    register(0xFF31, "WAVE1", 0xFF),
+    register(0xFF32, "WAVE2", 0xFF),
+    register(0xFF33, "WAVE3", 0xFF),
+    register(0xFF34, "WAVE4", 0xFF),
+    register(0xFF35, "WAVE5", 0xFF),
+    register(0xFF36, "WAVE6", 0xFF),
+    register(0xFF37, "WAVE7", 0xFF),
+    register(0xFF38, "WAVE8", 0xFF),
+    register(0xFF39, "WAVE9", 0xFF),
+    register(0xFF3A, "WAVEA", 0xFF),
+    register(0xFF3B, "WAVEB", 0xFF),
+    register(0xFF3C, "WAVEC", 0xFF),
+    register(0xFF3D, "WAVED", 0xFF),
+    register(0xFF3E, "WAVEE", 0xFF),
+    register(0xFF3F, "WAVEF", 0xFF),
+    register(0xFF40, "LCDC", 0xFF),
+    register(0xFF41, "STAT", 0x7F),
+    register(0xFF42, "SCY", 0xFF),
+    register(0xFF43, "SCX", 0xFF),
+    register(0xFF44, "LY", 0xFF),
+    register(0xFF45, "LYC", 0xFF),
+    register(0xFF46, "DMA", 0xFF),
+    register(0xFF47, "BGP", 0xFF),
+    register(0xFF48, "OBP0", 0xFF),
+    register(0xFF49, "OBP1", 0xFF),
+    register(0xFF4A, "WY", 0xFF),
+    register(0xFF4B, "WX", 0xFF),
+    cgb_register(0xFF4C, "KEY0", 0xFF),
+    cgb_register(0xFF4D, "KEY1", 0x81),
+    cgb_register(0xFF4F, "VBK", 0x01),
+    cgb_register(0xFF51, "HDMA1", 0x00),
+    cgb_register(0xFF52, "HDMA2", 0x00),
+    cgb_register(0xFF53, "HDMA3", 0x00),
+    cgb_register(0xFF54, "HDMA4", 0x00),
+    cgb_register(0xFF55, "HDMA5", 0xFF),
+    cgb_register(0xFF56, "RP", 0xC3),
+    cgb_register(0xFF68, "BCPS", 0xBF),
+    cgb_register(0xFF69, "BCPD", 0xFF),
+    cgb_register(0xFF6A, "OCPS", 0xBF),
+    cgb_register(0xFF6B, "OCPD", 0xFF),
+    cgb_register(0xFF6C, "OPRI", 0x01),
+    cgb_register(0xFF70, "SVBK", 0x07),
+    cgb_register(0xFF76, "PCM12", 0xFF),
+    cgb_register(0xFF77, "PCM34", 0xFF),
+    register(0xFFFF, "IE", 0xFF)
+];
+
+// Indexed by the low bits of FF00-FF7F. Unmapped addresses have no readable bits.
+const IO_READ_MASKS: [u8; 0x80] = build_read_masks();
+
+const fn build_read_masks() -> [u8; 0x80] {
+    let mut masks = [0; 0x80];
+    let mut index = 0;
+    while index < IO_REGISTERS.len() {
+        let definition = &IO_REGISTERS[index];
+        if definition.address < 0xFF80 {
+            masks[(definition.address & 0x7F) as usize] = definition.read_mask;
+        }
+        index += 1;
+    }
+    masks
+}
+
+// One bit per address in FF00-FF7F.
+const CGB_ONLY_ADDRESSES: u128 = build_cgb_only_addresses();
+
+const fn build_cgb_only_addresses() -> u128 {
+    let mut addresses = 0;
+    let mut index = 0;
+    while index < IO_REGISTERS.len() {
+        let definition = &IO_REGISTERS[index];
+        if definition.cgb_only {
+            addresses |= 1 << (definition.address & 0x7F);
+        }
+        index += 1;
+    }
+    addresses
+}
+
+// Whether a register in FF00-FF7F only exists on the CGB, and so is unmapped in DMG mode.
+pub fn is_cgb_only(address: u16) -> bool {
+    (CGB_ONLY_ADDRESSES >> (address & 0x7F)) & 1 == 1
+}
+
+// Sets the bits of a value read from FF00-FF7F that are unused or write-only, as they read on hardware.
+pub fn apply_read_mask(address: u16, value: u8) -> u8 {
+    value | !IO_READ_MASKS[(address & 0x7F) as usize]
+}
+
+/*
+    Lists every mapped I/O register along with the value the CPU would currently
+    read from it, for debugger views and tests. CGB-only registers are left out
+    when running in DMG mode.
+*/
+pub fn io_registers(emulator: &mut Emulator) -> Vec<IoRegister> {
+    let cgb_mode = is_cgb(emulator);
+    IO_REGISTERS.iter()
+        .filter(|definition| cgb_mode || !definition.cgb_only)
+        .map(|definition| IoRegister {
+            address: definition.address,
+            name: definition.name,
+            value: mmu::read_byte(emulator, definition.address),
+            read_mask: definition.read_mask
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::{initialize_screenless_emulator, Mode};
+    use super::*;
+
+    fn find_register(registers: &[IoRegister], name: &str) -> Option<IoRegister> {
+        registers.iter().find(|register| register.name == name).copied()
+    }
+
+    #[test]
+    fn should_list_registers_sorted_by_address() {
+        let mut emulator = initialize_screenless_emulator();
+        let registers = io_registers(&mut emulator);
+        assert!(registers.windows(2).all(|pair| pair[0].address < pair[1].address));
+    }
+
+    #[test]
+    fn should_report_current_register_values() {
+        let mut emulator = initialize_screenless_emulator();
+        emulator.memory.in_bios = false;
+        mmu::write_byte(&mut emulator, 0xFF42, 0x2A);
+        mmu::write_byte(&mut emulator, 0xFF07, 0x05);
+
+        let registers = io_registers(&mut emulator);
+        assert_eq!(find_register(&registers, "SCY").unwrap().value, 0x2A);
+
+        let tac = find_register(&registers, "TAC").unwrap();
+        assert_eq!(tac.address, 0xFF07);
+        assert_eq!(tac.read_mask, 0x07);
+    }
+
+    fn assert_unused_bits_read_as_set(emulator: &mut Emulator) {
+        for register in io_registers(emulator) {
+            assert_eq!(register.value | register.read_mask, 0xFF, "{} read as {:02X}", register.name, register.value);
+        }
+    }
+
+    #[test]
+    fn should_read_unused_bits_of_every_register_as_set() {
+        for mode in [Mode::DMG, Mode::CGB] {
+            let mut emulator = initialize_screenless_emulator();
+            emulator.mode = mode;
+            emulator.memory.in_bios = false;
+            assert_unused_bits_read_as_set(&mut emulator);
+
+            for definition in IO_REGISTERS.iter().filter(|definition| definition.address != 0xFF46) {
+                mmu::write_byte(&mut emulator, definition.address, 0x00);
+            }
+            assert_unused_bits_read_as_set(&mut emulator);
+        }
+    }
+
+    #[test]
+    fn should_flag_cgb_only_addresses() {
+        assert!(is_cgb_only(0xFF4F));
+        assert!(is_cgb_only(0xFF56));
+        assert!(is_cgb_only(0xFF70));
+        assert!(!is_cgb_only(0xFF40));
+        assert!(!is_cgb_only(0xFF7F));
+    }
+
+    #[test]
+    fn should_only_list_cgb_registers_in_cgb_mode() {
+        let mut emulator = initialize_screenless_emulator();
+        assert_eq!(find_register(&io_registers(&mut emulator), "SVBK"), None);
+
+        emulator.mode = Mode::CGB;
+        assert!(find_register(&io_registers(&mut emulator), "SVBK").is_some());
+    }
+}
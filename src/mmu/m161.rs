@@ -0,0 +1,100 @@
+use crate::mmu::bank_utils::banked_read;
+use crate::mmu::cartridge::{Cartridge, CartridgeMapper};
+use crate::savestate::{StateReader, StateWriter};
+use std::io;
+
+/*
+    M161 is used by the Mani 4 in 1 multicart. The menu picks a game by selecting
+    one of eight 32KB banks, after which the selection is locked until the console
+    is reset, so the chosen game can't switch away from itself.
+*/
+#[derive(Debug)]
+pub struct M161 {
+    cartridge: Cartridge,
+    rom_bank_number: u8,
+    locked: bool
+}
+
+pub fn initialize_m161(cartridge: Cartridge) -> M161 {
+    M161 {
+        cartridge,
+        rom_bank_number: 0,
+        locked: false
+    }
+}
+
+impl CartridgeMapper for M161 {
+    fn read_rom(&self, address: u16) -> u8 {
+        banked_read(&self.cartridge.rom, 0x8000, address, self.rom_bank_number as u16)
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        if (0x4000..=0x5FFF).contains(&address) && !self.locked {
+            self.rom_bank_number = value & 0x7;
+            self.locked = true;
+        }
+    }
+
+    fn read_ram(&self, _: u16) -> u8 {
+        0xFF
+    }
+
+    fn write_ram(&mut self, _: u16, _: u8) {}
+
+    fn get_cartridge(&self) -> &Cartridge {
+        &self.cartridge
+    }
+
+    fn set_cartridge_ram(&mut self, _: Vec<u8>) {}
+
+    fn get_cartridge_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.cartridge.ram
+    }
+
+    // Reported in 16KB banks, as symbol files number them.
+    fn get_rom_bank(&self) -> u16 {
+        self.rom_bank_number as u16 * 2 + 1
+    }
+
+    fn get_ram_bank(&self) -> u8 {
+        0
+    }
+
+    fn reset_registers(&mut self) {
+        self.rom_bank_number = 0;
+        self.locked = false;
+    }
+
+    fn serialize_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.rom_bank_number);
+        writer.write_bool(self.locked);
+    }
+
+    fn deserialize_state(&mut self, reader: &mut StateReader) -> io::Result<()> {
+        self.rom_bank_number = reader.read_u8()?;
+        self.locked = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mmu::cartridge::*;
+    use crate::mmu::constants::*;
+    use crate::mmu::effects::empty_cartridge_effects;
+    use crate::mmu::test_utils::*;
+
+    #[test]
+    fn select_bank_only_once() {
+        let mut rom = build_rom(CART_TYPE_MBC3_TIMER_RAM_BATTERY, ROM_SIZE_256KB, RAM_SIZE_0KB);
+        rom[TITLE_START_ADDRESS..TITLE_START_ADDRESS + 10].copy_from_slice(b"TETRIS SET");
+        rom[0x18005] = 0xA1;
+        let mut mapper = load_rom_buffer(rom, empty_cartridge_effects()).unwrap();
+
+        mapper.write_rom(0x4000, 0x3);
+        assert_eq!(mapper.read_rom(0x0005), 0xA1);
+
+        mapper.write_rom(0x4000, 0x0);
+        assert_eq!(mapper.read_rom(0x0005), 0xA1);
+    }
+}
@@ -1,6 +1,9 @@
-use crate::mmu::bank_utils::{banked_read, banked_write};
+use crate::hle_boot::NINTENDO_LOGO;
+use crate::mmu::bank_utils::{banked_read, banked_write, unbanked_read};
 use crate::mmu::cartridge::{Cartridge, CartridgeMapper};
 use crate::mmu::constants::*;
+use crate::savestate::{StateReader, StateWriter};
+use std::io;
 
 #[derive(Debug)]
 #[derive(PartialEq)]
@@ -16,10 +19,28 @@ pub struct MBC1 {
     rom_bank_number: u8,
     ram_bank_number: u8,
     mode: MBCMode,
+    multicart: bool
+}
+
+// Each game on a multicart takes 16 banks, the first of which holds its own header.
+const MULTICART_GAME_BANKS: usize = 0x10;
+const MULTICART_LOGO_ADDRESS: usize = MULTICART_GAME_BANKS * 0x4000 + 0x104;
+
+/*
+    MBC1M multicarts (e.g. Mortal Kombat I & II, Bomberman Collection) wire the
+    upper two bank bits one bit lower than a regular MBC1, so each game gets 16
+    banks. The menu starts a game by switching to banking mode 1, which also maps
+    the game's first bank into 0000-3FFF. Their headers just say MBC1, so they're
+    recognized by the second game's logo at the start of bank 0x10.
+*/
+pub fn is_multicart(cartridge: &Cartridge) -> bool {
+    let logo_range = MULTICART_LOGO_ADDRESS..MULTICART_LOGO_ADDRESS + NINTENDO_LOGO.len();
+    cartridge.header.max_banks == 64 && cartridge.rom.get(logo_range) == Some(&NINTENDO_LOGO[..])
 }
 
 pub fn initialize_mbc1(cartridge: Cartridge) -> MBC1 {
     MBC1 {
+        multicart: is_multicart(&cartridge),
         cartridge,
         ram_enabled: false,
         rom_bank_number: 1,
@@ -40,8 +61,10 @@ fn battery_supported(cartridge: &Cartridge) -> bool {
 impl CartridgeMapper for MBC1 {
     fn read_rom(&self, address: u16) -> u8 {
         match address {
+            0x0000..=0x3FFF if self.multicart && self.mode == MBCMode::RAM =>
+                banked_read(&self.cartridge.rom, 0x4000, address, (self.rom_bank_number & 0x30) as u16),
             0x0000..=0x3FFF =>
-                self.cartridge.rom[address as usize],
+                unbanked_read(&self.cartridge.rom, address),
             0x4000..=0x7FFF => {
                 banked_read(&self.cartridge.rom, 0x4000, address, self.rom_bank_number as u16)
             },
@@ -56,6 +79,17 @@ impl CartridgeMapper for MBC1 {
                     self.ram_enabled = (value & 0xF) == 0x0A;
                 }
             },
+            0x2000..=0x3FFF if self.multicart => {
+                let masked_value = value & 0x1F;
+                let bank_value = if masked_value == 0 { 1 } else { masked_value };
+                self.rom_bank_number = (self.rom_bank_number & 0x30) | (bank_value & 0x0F);
+            },
+            0x4000..=0x5FFF if self.multicart => {
+                self.rom_bank_number = ((value & 0x3) << 4) | (self.rom_bank_number & 0x0F);
+            },
+            0x6000..=0x7FFF if self.multicart => {
+                self.mode = if value & 0x1 == 1 { MBCMode::RAM } else { MBCMode::ROM };
+            },
             0x2000..=0x3FFF => {
                 let masked_value = value & 0x1F;
                 let mut bank_value = if masked_value == 0 { 1 as u8 } else { masked_value };
@@ -106,13 +140,52 @@ impl CartridgeMapper for MBC1 {
         self.cartridge.ram = ram;
     }
     
+    fn get_cartridge_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.cartridge.ram
+    }
+
+    fn get_rom_bank(&self) -> u16 {
+        self.rom_bank_number as u16
+    }
+
     fn get_ram_bank(&self) -> u8 {
         self.ram_bank_number
     }
+
+    fn ram_enabled(&self) -> bool {
+        self.ram_enabled
+    }
+
+    fn banking_mode(&self) -> u8 {
+        if self.mode == MBCMode::RAM { 1 } else { 0 }
+    }
+
+    fn reset_registers(&mut self) {
+        self.ram_enabled = false;
+        self.rom_bank_number = 1;
+        self.ram_bank_number = 0;
+        self.mode = MBCMode::ROM;
+    }
+
+    fn serialize_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.ram_enabled);
+        writer.write_u8(self.rom_bank_number);
+        writer.write_u8(self.ram_bank_number);
+        writer.write_bool(self.mode == MBCMode::RAM);
+    }
+
+    fn deserialize_state(&mut self, reader: &mut StateReader) -> io::Result<()> {
+        self.ram_enabled = reader.read_bool()?;
+        self.rom_bank_number = reader.read_u8()?;
+        self.ram_bank_number = reader.read_u8()?;
+        self.mode = if reader.read_bool()? { MBCMode::RAM } else { MBCMode::ROM };
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::hle_boot::NINTENDO_LOGO;
     use crate::mmu::cartridge::*;
     use crate::mmu::cartridge::test_utils::*;
     use crate::mmu::constants::*;
@@ -199,6 +272,36 @@ mod tests {
         assert_eq!(byte, 0xA1);
     }
 
+    #[test]
+    fn maps_multicart_games_with_upper_bank_bits_one_bit_lower() {
+        let mut rom = build_rom(CART_TYPE_MBC1, ROM_SIZE_1MB, RAM_SIZE_0KB);
+        rom[0x40104..0x40134].copy_from_slice(&NINTENDO_LOGO);
+        rom[0x40005] = 0xA1;
+        rom[0x48005] = 0xB2;
+        let mut mapper = load_rom_buffer(rom, empty_cartridge_effects()).unwrap();
+
+        mapper.write_rom(0x4000, 0x01);
+        mapper.write_rom(0x6000, 0x01);
+        mapper.write_rom(0x2000, 0x02);
+
+        assert_eq!(mapper.read_rom(0x0005), 0xA1);
+        assert_eq!(mapper.read_rom(0x4005), 0xB2);
+        assert_eq!(mapper.get_rom_bank(), 0x12);
+    }
+
+    #[test]
+    fn treats_large_roms_without_a_second_logo_as_regular_mbc1() {
+        let mut rom = build_rom(CART_TYPE_MBC1, ROM_SIZE_1MB, RAM_SIZE_0KB);
+        rom[0x48005] = 0xB2;
+        let mut mapper = load_rom_buffer(rom, empty_cartridge_effects()).unwrap();
+
+        mapper.write_rom(0x4000, 0x01);
+        mapper.write_rom(0x2000, 0x02);
+
+        assert_eq!(mapper.get_rom_bank(), 0x22);
+        assert_ne!(mapper.read_rom(0x4005), 0xB2);
+    }
+
     #[test]
     fn reads_bank_zero_as_bank_one() {
         let mut rom = build_rom(CART_TYPE_MBC1, ROM_SIZE_64KB, RAM_SIZE_2KB);
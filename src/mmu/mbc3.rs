@@ -1,8 +1,11 @@
-use crate::mmu::bank_utils::{banked_read, banked_write};
+use crate::clock::SharedClock;
+use crate::mmu::bank_utils::{banked_read, banked_write, unbanked_read};
 use crate::mmu::cartridge::{Cartridge, CartridgeMapper};
 use crate::mmu::constants::*;
+use crate::savestate::{StateReader, StateWriter};
+use std::io;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct RTCState {
     pub milliseconds: u16,
     pub seconds: u8,
@@ -36,7 +39,7 @@ pub fn initialize_mbc3(cartridge: Cartridge) -> MBC3 {
             minutes: 0,
             hours: 0,
             days: 0,
-            base_timestamp: 0.0,
+            base_timestamp: cartridge.clock.current_time_millis(),
             halted: false,
             day_carry: false,
         }),
@@ -45,6 +48,74 @@ pub fn initialize_mbc3(cartridge: Cartridge) -> MBC3 {
      }
 }
 
+pub const RTC_FOOTER_SIZE: usize = 48;
+const LEGACY_RTC_FOOTER_SIZE: usize = 44;
+
+/*
+    BGB and VBA-M append the clock to the end of .sav files as five 32-bit registers
+    (seconds, minutes, hours, day low, day high), a latched copy of those registers
+    and the UNIX timestamp of when the save was written. Older versions of VBA
+    wrote the timestamp as 32 bits instead of 64.
+*/
+pub fn encode_rtc_footer(rtc_state: &RTCState) -> Vec<u8> {
+    let mut day_high = ((rtc_state.days >> 8) & 0x01) as u32;
+    if rtc_state.halted {
+        day_high |= 0x40;
+    }
+    if rtc_state.day_carry {
+        day_high |= 0x80;
+    }
+
+    let registers = [
+        rtc_state.seconds as u32,
+        rtc_state.minutes as u32,
+        rtc_state.hours as u32,
+        (rtc_state.days & 0xFF) as u32,
+        day_high
+    ];
+
+    let mut footer = Vec::with_capacity(RTC_FOOTER_SIZE);
+    // The clock registers are only updated when latched, so they double as the latched copy.
+    for _ in 0..2 {
+        for register in registers {
+            footer.extend_from_slice(&register.to_le_bytes());
+        }
+    }
+
+    let timestamp_seconds = (rtc_state.base_timestamp / 1000.0) as u64;
+    footer.extend_from_slice(&timestamp_seconds.to_le_bytes());
+    footer
+}
+
+pub fn decode_rtc_footer(footer: &[u8]) -> Option<RTCState> {
+    if footer.len() != RTC_FOOTER_SIZE && footer.len() != LEGACY_RTC_FOOTER_SIZE {
+        return None;
+    }
+
+    let read_u32 = |index: usize| {
+        let offset = index * 4;
+        u32::from_le_bytes([footer[offset], footer[offset + 1], footer[offset + 2], footer[offset + 3]])
+    };
+
+    let day_high = read_u32(4);
+    let timestamp_seconds = if footer.len() == RTC_FOOTER_SIZE {
+        (read_u32(11) as u64) << 32 | read_u32(10) as u64
+    } else {
+        read_u32(10) as u64
+    };
+
+    Some(RTCState {
+        milliseconds: 0,
+        seconds: read_u32(0) as u8 & INVALID_MAX_SECONDS,
+        minutes: read_u32(1) as u8 & INVALID_MAX_MINUTES,
+        hours: read_u32(2) as u8 & INVALID_MAX_HOURS,
+        days: ((day_high as u16 & 0x01) << 8) | (read_u32(3) as u16 & 0xFF),
+        base_timestamp: timestamp_seconds as f64 * 1000.0,
+        halted: day_high & 0x40 != 0,
+        day_carry: day_high & 0x80 != 0
+    })
+}
+
 const INVALID_MAX_SECONDS: u8 = 63;
 const INVALID_MAX_MINUTES: u8 = 63;
 const INVALID_MAX_HOURS: u8 = 31;
@@ -125,7 +196,7 @@ impl CartridgeMapper for MBC3 {
     fn read_rom(&self, address: u16) -> u8 {
         match address {
             0x0000..=0x3FFF =>
-                self.cartridge.rom[address as usize],
+                unbanked_read(&self.cartridge.rom, address),
             0x4000..=0x7FFF => {
                 banked_read(&self.cartridge.rom, 0x4000, address, self.rom_bank_number as u16)
             },
@@ -152,7 +223,7 @@ impl CartridgeMapper for MBC3 {
             0x6000..=0x7FFF => {
                 if timer_supported(&self.cartridge) {
                     if self.rtc_latch == 0x00 && value == 0x01 {
-                        let current_time = self.cartridge.effects.current_time_millis();
+                        let current_time = self.cartridge.clock.current_time_millis();
 
                         if !self.rtc_state.halted {
                             let elapsed_ms = current_time - self.rtc_state.base_timestamp;
@@ -231,7 +302,7 @@ impl CartridgeMapper for MBC3 {
                     }
             
                     if self.ram_rtc_selection >= 0x08 && self.ram_rtc_selection <= 0x0C {
-                        let current_time = self.cartridge.effects.current_time_millis();
+                        let current_time = self.cartridge.clock.current_time_millis();
                         
                         if !self.rtc_state.halted {
                             let elapsed_ms = current_time - self.rtc_state.base_timestamp;
@@ -257,13 +328,92 @@ impl CartridgeMapper for MBC3 {
         self.cartridge.ram = ram;
     }
 
+    fn get_cartridge_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.cartridge.ram
+    }
+
+    fn get_rom_bank(&self) -> u16 {
+        self.rom_bank_number as u16
+    }
+
     fn get_ram_bank(&self) -> u8 {
         self.ram_rtc_selection
     }
+
+    fn ram_enabled(&self) -> bool {
+        self.ram_rtc_enabled
+    }
+
+    fn reset_registers(&mut self) {
+        self.rom_bank_number = 1;
+        self.ram_rtc_enabled = false;
+        self.ram_rtc_selection = 0;
+        self.rtc_latch = 0xFF;
+    }
+
+    fn serialize_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.rom_bank_number);
+        writer.write_bool(self.ram_rtc_enabled);
+        writer.write_u8(self.ram_rtc_selection);
+        writer.write_u8(self.rtc_latch);
+        writer.write_u16(self.rtc_state.milliseconds);
+        writer.write_u8(self.rtc_state.seconds);
+        writer.write_u8(self.rtc_state.minutes);
+        writer.write_u8(self.rtc_state.hours);
+        writer.write_u16(self.rtc_state.days);
+        writer.write_f64(self.rtc_state.base_timestamp);
+        writer.write_bool(self.rtc_state.halted);
+        writer.write_bool(self.rtc_state.day_carry);
+    }
+
+    fn deserialize_state(&mut self, reader: &mut StateReader) -> io::Result<()> {
+        self.rom_bank_number = reader.read_u8()?;
+        self.ram_rtc_enabled = reader.read_bool()?;
+        self.ram_rtc_selection = reader.read_u8()?;
+        self.rtc_latch = reader.read_u8()?;
+        self.rtc_state.milliseconds = reader.read_u16()?;
+        self.rtc_state.seconds = reader.read_u8()?;
+        self.rtc_state.minutes = reader.read_u8()?;
+        self.rtc_state.hours = reader.read_u8()?;
+        self.rtc_state.days = reader.read_u16()?;
+        self.rtc_state.base_timestamp = reader.read_f64()?;
+        self.rtc_state.halted = reader.read_bool()?;
+        self.rtc_state.day_carry = reader.read_bool()?;
+        Ok(())
+    }
+
+    fn get_rtc_state(&self) -> Option<&RTCState> {
+        if timer_supported(&self.cartridge) {
+            Some(&self.rtc_state)
+        }
+        else {
+            None
+        }
+    }
+
+    fn set_rtc_state(&mut self, rtc_state: RTCState) {
+        if timer_supported(&self.cartridge) {
+            self.rtc_state = rtc_state;
+            self.save_rtc_state();
+        }
+    }
+
+    // Time that passed on the old clock is kept, and the new one takes over from now.
+    fn set_clock(&mut self, clock: SharedClock) {
+        if timer_supported(&self.cartridge) && !self.rtc_state.halted {
+            let elapsed_ms = self.cartridge.clock.current_time_millis() - self.rtc_state.base_timestamp;
+            if elapsed_ms > 0.0 {
+                self.update_rtc_time_registers(elapsed_ms);
+            }
+        }
+        self.cartridge.clock = clock;
+        self.rtc_state.base_timestamp = self.cartridge.clock.current_time_millis();
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::clock::ManualClock;
     use crate::mmu::cartridge::*;
     use crate::mmu::cartridge::test_utils::*;
     use crate::mmu::constants::*;
@@ -274,10 +424,6 @@ mod tests {
     struct FakeCartridgeEffects;
 
     impl CartridgeEffects for FakeCartridgeEffects {
-        fn current_time_millis(&self) -> f64 {
-            0.0
-        }
-    
         fn save_rtc_state(&self, _: &str, _: &RTCState) {}
     
         fn load_rtc_state(&self, _: &str) -> Option<RTCState> {
@@ -430,6 +576,39 @@ mod tests {
         assert_eq!(byte, 0x0A);
     }
 
+    fn latch_minutes(mapper: &mut Box<dyn CartridgeMapper>) -> u8 {
+        mapper.write_rom(0x0000, 0xA);
+        mapper.write_rom(0x4000, 0x9);
+        mapper.write_rom(0x6000, 0x0);
+        mapper.write_rom(0x6000, 0x1);
+        mapper.read_ram(0x0000)
+    }
+
+    #[test]
+    fn advances_rtc_with_clock() {
+        let clock = ManualClock::new(0.0);
+        let rom = build_rom(CART_TYPE_MBC3_TIMER_RAM_BATTERY, ROM_SIZE_64KB, RAM_SIZE_2KB);
+        let mut mapper = load_rom_buffer_with_overrides(rom, fake_cartridge_effects(), CartridgeOverrides::default(), clock.shared()).unwrap();
+
+        clock.advance_millis(61_000.0);
+
+        assert_eq!(latch_minutes(&mut mapper), 0x0B);
+    }
+
+    #[test]
+    fn keeps_elapsed_time_when_clock_is_replaced() {
+        let clock = ManualClock::new(0.0);
+        let rom = build_rom(CART_TYPE_MBC3_TIMER_RAM_BATTERY, ROM_SIZE_64KB, RAM_SIZE_2KB);
+        let mut mapper = load_rom_buffer_with_overrides(rom, fake_cartridge_effects(), CartridgeOverrides::default(), clock.shared()).unwrap();
+
+        clock.advance_millis(120_000.0);
+        let replacement = ManualClock::new(5_000_000.0);
+        mapper.set_clock(replacement.shared());
+        replacement.advance_millis(60_000.0);
+
+        assert_eq!(latch_minutes(&mut mapper), 0x0D);
+    }
+
     #[test]
     fn does_not_read_from_minutes_rtc_register_if_not_supported() {
         let mut mapper = build_cartridge_mapper_with_effects(CART_TYPE_MBC3_RAM_BATTERY,
@@ -488,4 +667,83 @@ mod tests {
         let byte = mapper.read_ram(0x0000);
         assert_eq!(byte, 0xFF);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn encodes_rtc_footer_in_bgb_format() {
+        let rtc_state = RTCState {
+            milliseconds: 500,
+            seconds: 12,
+            minutes: 34,
+            hours: 5,
+            days: 0x1AB,
+            base_timestamp: 1_700_000_000_000.0,
+            halted: true,
+            day_carry: false,
+        };
+
+        let footer = encode_rtc_footer(&rtc_state);
+
+        assert_eq!(footer.len(), RTC_FOOTER_SIZE);
+        assert_eq!(&footer[0..4], &[12, 0, 0, 0]);
+        assert_eq!(&footer[12..16], &[0xAB, 0, 0, 0]);
+        assert_eq!(&footer[16..20], &[0x41, 0, 0, 0]);
+        assert_eq!(&footer[20..24], &[12, 0, 0, 0]);
+        assert_eq!(&footer[40..48], &1_700_000_000u64.to_le_bytes());
+    }
+
+    #[test]
+    fn decodes_rtc_footer() {
+        let rtc_state = RTCState {
+            milliseconds: 0,
+            seconds: 59,
+            minutes: 1,
+            hours: 23,
+            days: 0x100,
+            base_timestamp: 1_600_000_000_000.0,
+            halted: false,
+            day_carry: true,
+        };
+
+        let decoded = decode_rtc_footer(&encode_rtc_footer(&rtc_state)).unwrap();
+        assert_eq!(decoded, rtc_state);
+    }
+
+    #[test]
+    fn decodes_legacy_rtc_footer_with_32_bit_timestamp() {
+        let mut footer = encode_rtc_footer(&RTCState {
+            milliseconds: 0,
+            seconds: 1,
+            minutes: 2,
+            hours: 3,
+            days: 4,
+            base_timestamp: 1_000_000_000_000.0,
+            halted: false,
+            day_carry: false,
+        });
+        footer.truncate(44);
+
+        let decoded = decode_rtc_footer(&footer).unwrap();
+        assert_eq!(decoded.days, 4);
+        assert_eq!(decoded.base_timestamp, 1_000_000_000_000.0);
+    }
+
+    #[test]
+    fn rejects_footer_with_unexpected_size() {
+        assert!(decode_rtc_footer(&[0; 40]).is_none());
+    }
+
+    #[test]
+    fn exposes_rtc_state_only_for_timer_cartridges() {
+        let timer_mapper = build_cartridge_mapper_with_effects(CART_TYPE_MBC3_TIMER_RAM_BATTERY,
+            ROM_SIZE_64KB,
+            RAM_SIZE_2KB,
+            fake_cartridge_effects());
+        let mapper = build_cartridge_mapper_with_effects(CART_TYPE_MBC3_RAM_BATTERY,
+            ROM_SIZE_64KB,
+            RAM_SIZE_2KB,
+            fake_cartridge_effects());
+
+        assert!(timer_mapper.get_rtc_state().is_some());
+        assert!(mapper.get_rtc_state().is_none());
+    }
+}
@@ -1,6 +1,8 @@
-use crate::mmu::bank_utils::{banked_read, banked_write};
+use crate::mmu::bank_utils::{banked_read, banked_write, unbanked_read};
 use crate::mmu::cartridge::{Cartridge, CartridgeMapper};
 use crate::mmu::constants::*;
+use crate::savestate::{StateReader, StateWriter};
+use std::io;
 
 #[derive(Debug)]
 pub struct MBC5 {
@@ -57,7 +59,7 @@ impl CartridgeMapper for MBC5 {
     fn read_rom(&self, address: u16) -> u8 {
         match address {
             0x0000..=0x3FFF =>
-                self.cartridge.rom[address as usize],
+                unbanked_read(&self.cartridge.rom, address),
             0x4000..=0x7FFF => {
                 banked_read(&self.cartridge.rom, 0x4000, address, self.rom_bank_number)
             },
@@ -82,7 +84,7 @@ impl CartridgeMapper for MBC5 {
             },
             0x4000..=0x5FFF => {
                 let next_ram_bank_number = if rumble_supported(&self.cartridge) {
-                    self.rumble = (value & 0x8) == 0x1;
+                    self.rumble = (value & 0x8) != 0;
                     value & 0x7
                 }
                 else {
@@ -120,9 +122,47 @@ impl CartridgeMapper for MBC5 {
         self.cartridge.ram = ram;
     }
 
+    fn get_cartridge_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.cartridge.ram
+    }
+
+    fn get_rom_bank(&self) -> u16 {
+        self.rom_bank_number
+    }
+
     fn get_ram_bank(&self) -> u8 {
         self.ram_bank_number
     }
+
+    fn ram_enabled(&self) -> bool {
+        self.ram_enabled
+    }
+
+    fn reset_registers(&mut self) {
+        self.ram_enabled = false;
+        self.rumble = false;
+        self.rom_bank_number = 1;
+        self.ram_bank_number = 0;
+    }
+
+    fn serialize_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.ram_enabled);
+        writer.write_bool(self.rumble);
+        writer.write_u16(self.rom_bank_number);
+        writer.write_u8(self.ram_bank_number);
+    }
+
+    fn deserialize_state(&mut self, reader: &mut StateReader) -> io::Result<()> {
+        self.ram_enabled = reader.read_bool()?;
+        self.rumble = reader.read_bool()?;
+        self.rom_bank_number = reader.read_u16()?;
+        self.ram_bank_number = reader.read_u8()?;
+        Ok(())
+    }
+
+    fn rumble_active(&self) -> bool {
+        self.rumble
+    }
 }
 
 #[cfg(test)]
@@ -265,6 +305,25 @@ mod tests {
         assert_eq!(second_byte, 0xCC);
     }
 
+    #[test]
+    fn turns_rumble_motor_on_and_off() {
+        let mut mapper = build_cartridge_mapper(CART_TYPE_MBC5_RUMBLE_RAM_BATTERY, ROM_SIZE_64KB, RAM_SIZE_128KB);
+        assert!(!mapper.rumble_active());
+
+        mapper.write_rom(0x4000, 0x8);
+        assert!(mapper.rumble_active());
+
+        mapper.write_rom(0x4000, 0x0);
+        assert!(!mapper.rumble_active());
+    }
+
+    #[test]
+    fn ignores_rumble_bit_without_rumble_motor() {
+        let mut mapper = build_cartridge_mapper(CART_TYPE_MBC5_RAM_BATTERY, ROM_SIZE_64KB, RAM_SIZE_128KB);
+        mapper.write_rom(0x4000, 0x8);
+        assert!(!mapper.rumble_active());
+    }
+
 
     #[test]
     fn only_allow_reading_from_ram_if_it_is_enabled() {
@@ -0,0 +1,404 @@
+use crate::mmu::bank_utils::{banked_read, banked_write, unbanked_read};
+use crate::mmu::cartridge::{Cartridge, CartridgeMapper};
+use crate::savestate::{StateReader, StateWriter};
+use std::io;
+
+/*
+    MBC6 splits the switchable ROM area into two 8KB halves (4000-5FFF and
+    6000-7FFF) and external RAM into two 4KB halves (A000-AFFF and B000-BFFF),
+    each with its own bank register. Either ROM half can map the 1MB flash chip
+    instead of ROM. Flash writes use the usual unlock sequence (AA to 5555, 55 to
+    2AAA) followed by a program or erase command, which is all Net de Get needs.
+    The flash is battery-backed like the RAM, and saved under its own key.
+*/
+const ROM_BANK_SIZE: u32 = 0x2000;
+const RAM_BANK_SIZE: u32 = 0x1000;
+const FLASH_SIZE: usize = 0x100000;
+const FLASH_SECTOR_SIZE: usize = 0x20000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FlashCommand {
+    Idle,
+    Unlocked,
+    Ready,
+    Program,
+    EraseArmed,
+    EraseUnlocked,
+    EraseReady
+}
+
+#[derive(Debug, Clone, Copy)]
+struct BankSelection {
+    rom_bank_number: u8,
+    flash_selected: bool,
+    ram_bank_number: u8
+}
+
+#[derive(Debug)]
+pub struct MBC6 {
+    cartridge: Cartridge,
+    flash: Vec<u8>,
+    ram_enabled: bool,
+    flash_enabled: bool,
+    flash_write_enabled: bool,
+    flash_command: FlashCommand,
+    // 4000-5FFF and A000-AFFF use the first selection, 6000-7FFF and B000-BFFF the second.
+    banks: [BankSelection; 2]
+}
+
+fn flash_key(cartridge: &Cartridge) -> String {
+    format!("{}-flash", cartridge.header.title)
+}
+
+pub fn initialize_mbc6(cartridge: Cartridge) -> MBC6 {
+    let flash = cartridge.effects.load_ram(&flash_key(&cartridge))
+        .filter(|flash| flash.len() == FLASH_SIZE)
+        .unwrap_or_else(|| vec![0xFF; FLASH_SIZE]);
+
+    MBC6 {
+        cartridge,
+        flash,
+        ram_enabled: false,
+        flash_enabled: false,
+        flash_write_enabled: false,
+        flash_command: FlashCommand::Idle,
+        banks: [
+            BankSelection { rom_bank_number: 0, flash_selected: false, ram_bank_number: 0 },
+            BankSelection { rom_bank_number: 0, flash_selected: false, ram_bank_number: 0 }
+        ]
+    }
+}
+
+fn rom_half(address: u16) -> usize {
+    if address < 0x6000 { 0 } else { 1 }
+}
+
+fn ram_half(address: u16) -> usize {
+    if address & 0x1000 == 0 { 0 } else { 1 }
+}
+
+fn flash_offset(bank: u8, address: u16) -> usize {
+    bank as usize * ROM_BANK_SIZE as usize + (address as usize & (ROM_BANK_SIZE as usize - 1))
+}
+
+impl MBC6 {
+    fn max_rom_banks(&self) -> u16 {
+        self.cartridge.header.max_banks * 2
+    }
+
+    fn max_ram_banks(&self) -> usize {
+        self.cartridge.ram.len() / RAM_BANK_SIZE as usize
+    }
+
+    fn set_rom_bank_number(&mut self, half: usize, value: u8) {
+        let next_rom_bank_number = value & 0x7F;
+        if self.banks[half].flash_selected || (next_rom_bank_number as u16) < self.max_rom_banks() {
+            self.banks[half].rom_bank_number = next_rom_bank_number;
+        }
+    }
+
+    fn set_ram_bank_number(&mut self, half: usize, value: u8) {
+        let next_ram_bank_number = value & 0x7;
+        if (next_ram_bank_number as usize) < self.max_ram_banks() {
+            self.banks[half].ram_bank_number = next_ram_bank_number;
+        }
+    }
+
+    fn write_flash(&mut self, offset: usize, value: u8) {
+        let command_address = offset & 0x7FFF;
+
+        self.flash_command = match (self.flash_command, command_address, value) {
+            (FlashCommand::Program, _, _) => {
+                // Programming can only clear bits, setting them again takes an erase.
+                self.flash[offset] &= value;
+                self.save_flash();
+                FlashCommand::Idle
+            },
+            (_, _, 0xF0) => FlashCommand::Idle,
+            (FlashCommand::Idle, 0x5555, 0xAA) => FlashCommand::Unlocked,
+            (FlashCommand::Unlocked, 0x2AAA, 0x55) => FlashCommand::Ready,
+            (FlashCommand::Ready, 0x5555, 0xA0) => FlashCommand::Program,
+            (FlashCommand::Ready, 0x5555, 0x80) => FlashCommand::EraseArmed,
+            (FlashCommand::EraseArmed, 0x5555, 0xAA) => FlashCommand::EraseUnlocked,
+            (FlashCommand::EraseUnlocked, 0x2AAA, 0x55) => FlashCommand::EraseReady,
+            (FlashCommand::EraseReady, 0x5555, 0x10) => {
+                self.flash.fill(0xFF);
+                self.save_flash();
+                FlashCommand::Idle
+            },
+            (FlashCommand::EraseReady, _, 0x30) => {
+                let sector_start = offset - offset % FLASH_SECTOR_SIZE;
+                self.flash[sector_start..sector_start + FLASH_SECTOR_SIZE].fill(0xFF);
+                self.save_flash();
+                FlashCommand::Idle
+            },
+            _ => FlashCommand::Idle
+        };
+    }
+
+    fn save_flash(&self) {
+        self.cartridge.effects.save_ram(&flash_key(&self.cartridge), &self.flash);
+    }
+}
+
+fn flash_command_to_u8(command: FlashCommand) -> u8 {
+    match command {
+        FlashCommand::Idle => 0,
+        FlashCommand::Unlocked => 1,
+        FlashCommand::Ready => 2,
+        FlashCommand::Program => 3,
+        FlashCommand::EraseArmed => 4,
+        FlashCommand::EraseUnlocked => 5,
+        FlashCommand::EraseReady => 6
+    }
+}
+
+fn flash_command_from_u8(value: u8) -> FlashCommand {
+    match value {
+        1 => FlashCommand::Unlocked,
+        2 => FlashCommand::Ready,
+        3 => FlashCommand::Program,
+        4 => FlashCommand::EraseArmed,
+        5 => FlashCommand::EraseUnlocked,
+        6 => FlashCommand::EraseReady,
+        _ => FlashCommand::Idle
+    }
+}
+
+impl CartridgeMapper for MBC6 {
+    fn read_rom(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF =>
+                unbanked_read(&self.cartridge.rom, address),
+            0x4000..=0x7FFF => {
+                let bank = self.banks[rom_half(address)];
+                if bank.flash_selected {
+                    self.flash[flash_offset(bank.rom_bank_number, address)]
+                }
+                else {
+                    banked_read(&self.cartridge.rom, ROM_BANK_SIZE, address, bank.rom_bank_number as u16)
+                }
+            },
+            _ => panic!("Invalid ROM address: {:#X}", address),
+        }
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x03FF => {
+                self.ram_enabled = (value & 0xF) == 0x0A;
+            },
+            0x0400..=0x07FF => self.set_ram_bank_number(0, value),
+            0x0800..=0x0BFF => self.set_ram_bank_number(1, value),
+            0x0C00..=0x0FFF => {
+                self.flash_enabled = (value & 0x1) != 0;
+            },
+            0x1000..=0x1FFF => {
+                self.flash_write_enabled = (value & 0x1) != 0;
+            },
+            0x2000..=0x27FF => self.set_rom_bank_number(0, value),
+            0x2800..=0x2FFF => {
+                self.banks[0].flash_selected = value == 0x08;
+            },
+            0x3000..=0x37FF => self.set_rom_bank_number(1, value),
+            0x3800..=0x3FFF => {
+                self.banks[1].flash_selected = value == 0x08;
+            },
+            0x4000..=0x7FFF => {
+                let bank = self.banks[rom_half(address)];
+                if bank.flash_selected && self.flash_enabled && self.flash_write_enabled {
+                    self.write_flash(flash_offset(bank.rom_bank_number, address), value);
+                }
+            },
+            _ => panic!("Invalid ROM address: {:#X}", address),
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if self.ram_enabled {
+            let bank = self.banks[ram_half(address)];
+            banked_read(&self.cartridge.ram, RAM_BANK_SIZE, address, bank.ram_bank_number as u16)
+        } else {
+            0xFF
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if self.ram_enabled {
+            let bank = self.banks[ram_half(address)];
+            banked_write(&mut self.cartridge.ram, RAM_BANK_SIZE, address, bank.ram_bank_number as u16, value);
+            self.cartridge.effects.save_ram(&self.cartridge.header.title, &self.cartridge.ram);
+        }
+    }
+
+    fn get_cartridge(&self) -> &Cartridge {
+        &self.cartridge
+    }
+
+    fn set_cartridge_ram(&mut self, ram: Vec<u8>) {
+        self.cartridge.ram = ram;
+    }
+
+    fn get_cartridge_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.cartridge.ram
+    }
+
+    fn get_flash(&self) -> Option<&[u8]> {
+        Some(&self.flash)
+    }
+
+    fn get_flash_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.flash)
+    }
+
+    // Banks are reported for the lower halves, in MBC6's own 8KB and 4KB units.
+    fn get_rom_bank(&self) -> u16 {
+        self.banks[0].rom_bank_number as u16
+    }
+
+    fn get_ram_bank(&self) -> u8 {
+        self.banks[0].ram_bank_number
+    }
+
+    fn reset_registers(&mut self) {
+        self.ram_enabled = false;
+        self.flash_enabled = false;
+        self.flash_write_enabled = false;
+        self.flash_command = FlashCommand::Idle;
+        self.banks = [
+            BankSelection { rom_bank_number: 0, flash_selected: false, ram_bank_number: 0 },
+            BankSelection { rom_bank_number: 0, flash_selected: false, ram_bank_number: 0 }
+        ];
+    }
+
+    // The flash is saved with the cartridge RAM instead, see savestate.rs.
+    fn serialize_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.ram_enabled);
+        writer.write_bool(self.flash_enabled);
+        writer.write_bool(self.flash_write_enabled);
+        writer.write_u8(flash_command_to_u8(self.flash_command));
+        for bank in &self.banks {
+            writer.write_u8(bank.rom_bank_number);
+            writer.write_bool(bank.flash_selected);
+            writer.write_u8(bank.ram_bank_number);
+        }
+    }
+
+    fn deserialize_state(&mut self, reader: &mut StateReader) -> io::Result<()> {
+        self.ram_enabled = reader.read_bool()?;
+        self.flash_enabled = reader.read_bool()?;
+        self.flash_write_enabled = reader.read_bool()?;
+        self.flash_command = flash_command_from_u8(reader.read_u8()?);
+        for bank in self.banks.iter_mut() {
+            bank.rom_bank_number = reader.read_u8()?;
+            bank.flash_selected = reader.read_bool()?;
+            bank.ram_bank_number = reader.read_u8()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mmu::cartridge::*;
+    use crate::mmu::cartridge::test_utils::*;
+    use crate::mmu::constants::*;
+    use crate::mmu::effects::{CartridgeEffects, empty_cartridge_effects};
+    use crate::mmu::test_utils::*;
+    use crate::mmu::RTCState;
+
+    // Has a saved flash image filled with 0x42, and no saved RAM.
+    struct SavedFlashEffects;
+
+    impl CartridgeEffects for SavedFlashEffects {
+        fn save_rtc_state(&self, _: &str, _: &RTCState) {}
+
+        fn load_rtc_state(&self, _: &str) -> Option<RTCState> {
+            None
+        }
+
+        fn load_ram(&self, key: &str) -> Option<Vec<u8>> {
+            key.ends_with("-flash").then(|| vec![0x42; 0x100000])
+        }
+
+        fn save_ram(&self, _: &str, _: &[u8]) {}
+    }
+
+    fn enable_flash_writes(mapper: &mut Box<dyn CartridgeMapper>) {
+        mapper.write_rom(0x0C00, 0x1);
+        mapper.write_rom(0x1000, 0x1);
+        mapper.write_rom(0x2800, 0x08);
+        mapper.write_rom(0x3800, 0x08);
+    }
+
+    fn unlock_flash(mapper: &mut Box<dyn CartridgeMapper>) {
+        // 5555 is offset 1555 of flash bank 2, and 2AAA is offset 0AAA of flash bank 1.
+        mapper.write_rom(0x2000, 0x2);
+        mapper.write_rom(0x5555, 0xAA);
+        mapper.write_rom(0x2000, 0x1);
+        mapper.write_rom(0x4AAA, 0x55);
+        mapper.write_rom(0x2000, 0x2);
+    }
+
+    #[test]
+    fn bank_rom_halves_independently() {
+        let mut rom = build_rom(CART_TYPE_MBC6, ROM_SIZE_128KB, RAM_SIZE_32KB);
+        rom[0x6005] = 0xA1;
+        rom[0xA005] = 0xB2;
+        let mut mapper = load_rom_buffer(rom, empty_cartridge_effects()).unwrap();
+
+        mapper.write_rom(0x2000, 0x3);
+        mapper.write_rom(0x3000, 0x5);
+
+        assert_eq!(mapper.read_rom(0x4005), 0xA1);
+        assert_eq!(mapper.read_rom(0x6005), 0xB2);
+    }
+
+    #[test]
+    fn bank_ram_halves_independently() {
+        let mut mapper = build_cartridge_mapper(CART_TYPE_MBC6, ROM_SIZE_128KB, RAM_SIZE_32KB);
+        mapper.write_rom(0x0000, 0xA);
+        mapper.write_rom(0x0400, 0x2);
+        mapper.write_rom(0x0800, 0x2);
+
+        mapper.write_ram(0x0005, 0xCC);
+        assert_eq!(mapper.read_ram(0x1005), 0xCC);
+
+        mapper.write_rom(0x0800, 0x3);
+        assert_eq!(mapper.read_ram(0x1005), 0x00);
+        assert_eq!(mapper.read_ram(0x0005), 0xCC);
+    }
+
+    #[test]
+    fn program_and_erase_flash() {
+        let mut mapper = build_cartridge_mapper(CART_TYPE_MBC6, ROM_SIZE_128KB, RAM_SIZE_32KB);
+        enable_flash_writes(&mut mapper);
+        mapper.write_rom(0x3000, 0x4);
+        assert_eq!(mapper.read_rom(0x6010), 0xFF);
+
+        // Writes without the unlock sequence are ignored.
+        mapper.write_rom(0x6010, 0x12);
+        assert_eq!(mapper.read_rom(0x6010), 0xFF);
+
+        unlock_flash(&mut mapper);
+        mapper.write_rom(0x5555, 0xA0);
+        mapper.write_rom(0x6010, 0x12);
+        assert_eq!(mapper.read_rom(0x6010), 0x12);
+
+        unlock_flash(&mut mapper);
+        mapper.write_rom(0x5555, 0x80);
+        unlock_flash(&mut mapper);
+        mapper.write_rom(0x6000, 0x30);
+        assert_eq!(mapper.read_rom(0x6010), 0xFF);
+    }
+
+    #[test]
+    fn load_flash_from_cartridge_effects() {
+        let rom = build_rom(CART_TYPE_MBC6, ROM_SIZE_128KB, RAM_SIZE_32KB);
+        let mut mapper = load_rom_buffer(rom, Box::new(SavedFlashEffects)).unwrap();
+        mapper.write_rom(0x2800, 0x08);
+
+        assert_eq!(mapper.read_rom(0x4010), 0x42);
+        assert_eq!(mapper.get_cartridge().ram.len(), 0x8000);
+    }
+}
@@ -1,4 +1,7 @@
+use crate::mmu::bank_utils::unbanked_read;
 use crate::mmu::cartridge::{Cartridge, CartridgeMapper};
+use crate::savestate::{StateReader, StateWriter};
+use std::io;
 
 #[derive(Debug)]
 pub struct MBCRomOnly {
@@ -13,7 +16,7 @@ pub fn initialize_mbc_rom_only(cartridge: Cartridge) -> MBCRomOnly {
 
 impl CartridgeMapper for MBCRomOnly {
     fn read_rom(&self, address: u16) -> u8 {
-        self.cartridge.rom[address as usize]
+        unbanked_read(&self.cartridge.rom, address)
     }
 
     fn write_rom(&mut self, _: u16, _: u8) {
@@ -36,7 +39,21 @@ impl CartridgeMapper for MBCRomOnly {
         ()
     }
 
+    fn get_cartridge_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.cartridge.ram
+    }
+
+    fn get_rom_bank(&self) -> u16 {
+        1
+    }
+
     fn get_ram_bank(&self) -> u8 {
         0
     }
+
+    fn serialize_state(&self, _: &mut StateWriter) {}
+
+    fn deserialize_state(&mut self, _: &mut StateReader) -> io::Result<()> {
+        Ok(())
+    }
 }
@@ -0,0 +1,140 @@
+use std::io;
+
+use crate::mmu::{self, Memory};
+
+/*
+    Access to cartridge RAM for save editors, which can be used while the game is
+    running. Banked accessors address RAM the way the game sees it (an 8KB bank and
+    an offset within it) so edits land where the game expects them, and raw accessors
+    cover the whole buffer as it's laid out in a save file. Edits on battery-backed
+    cartridges are persisted the same way the game's own writes are.
+*/
+pub const RAM_BANK_SIZE: usize = 0x2000;
+
+fn out_of_range_error(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn persist(memory: &Memory) {
+    let cartridge = memory.cartridge_mapper.get_cartridge();
+    if cartridge.header.has_battery {
+        cartridge.effects.save_ram(&cartridge.header.title, &cartridge.ram);
+    }
+}
+
+fn as_banked_index(memory: &Memory, bank: u8, offset: u16) -> io::Result<usize> {
+    let ram_size = cartridge_ram(memory).len();
+    let offset = offset as usize & (RAM_BANK_SIZE - 1);
+    let index = bank as usize * RAM_BANK_SIZE + offset;
+
+    if index < ram_size {
+        Ok(index)
+    }
+    else {
+        Err(out_of_range_error(format!("RAM bank {} offset {:#06X} is outside of {} bytes of cartridge RAM", bank, offset, ram_size)))
+    }
+}
+
+pub fn cartridge_ram(memory: &Memory) -> &[u8] {
+    &memory.cartridge_mapper.get_cartridge().ram
+}
+
+#[cfg(any(test, feature = "internals"))]
+pub fn ram_bank_count(memory: &Memory) -> usize {
+    cartridge_ram(memory).len().div_ceil(RAM_BANK_SIZE)
+}
+
+// The bank the game currently has mapped at A000-BFFF.
+#[cfg(any(test, feature = "internals"))]
+pub fn mapped_ram_bank(memory: &Memory) -> u8 {
+    memory.cartridge_mapper.get_ram_bank()
+}
+
+#[cfg(any(test, feature = "internals"))]
+pub fn read_banked_ram(memory: &Memory, bank: u8, offset: u16) -> io::Result<u8> {
+    let index = as_banked_index(memory, bank, offset)?;
+    Ok(cartridge_ram(memory)[index])
+}
+
+pub fn write_banked_ram(memory: &mut Memory, bank: u8, offset: u16, value: u8) -> io::Result<()> {
+    let index = as_banked_index(memory, bank, offset)?;
+    memory.cartridge_mapper.get_cartridge_ram_mut()[index] = value;
+    mmu::invalidate_cartridge_ram_version(memory);
+    persist(memory);
+    Ok(())
+}
+
+// Writes through whichever bank the game currently has mapped, e.g. to poke a value it's reading right now.
+#[cfg(any(test, feature = "internals"))]
+pub fn write_mapped_ram(memory: &mut Memory, address: u16, value: u8) -> io::Result<()> {
+    if !(0xA000..=0xBFFF).contains(&address) {
+        return Err(out_of_range_error(format!("{:#06X} is outside of cartridge RAM", address)));
+    }
+    let bank = mapped_ram_bank(memory);
+    write_banked_ram(memory, bank, address, value)
+}
+
+#[cfg(any(test, feature = "internals"))]
+pub fn write_raw_ram(memory: &mut Memory, offset: usize, bytes: &[u8]) -> io::Result<()> {
+    let ram_size = cartridge_ram(memory).len();
+    match offset.checked_add(bytes.len()) {
+        Some(end) if end <= ram_size => {
+            memory.cartridge_mapper.get_cartridge_ram_mut()[offset..end].copy_from_slice(bytes);
+            mmu::invalidate_cartridge_ram_version(memory);
+            persist(memory);
+            Ok(())
+        },
+        _ => Err(out_of_range_error(format!("{} bytes at {:#X} don't fit in {} bytes of cartridge RAM", bytes.len(), offset, ram_size)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::initialize_screenless_emulator;
+    use crate::mmu;
+    use crate::mmu::constants::*;
+    use crate::mmu::effects::empty_cartridge_effects;
+    use crate::mmu::test_utils::*;
+    use super::*;
+
+    fn setup_emulator() -> crate::emulator::Emulator {
+        let mut emulator = initialize_screenless_emulator();
+        let rom = build_rom(CART_TYPE_MBC5_RAM_BATTERY, ROM_SIZE_64KB, RAM_SIZE_32KB);
+        mmu::load_rom_buffer(&mut emulator.memory, rom, empty_cartridge_effects()).unwrap();
+        emulator.memory.in_bios = false;
+        emulator
+    }
+
+    #[test]
+    fn should_write_banked_ram_where_game_reads_it() {
+        let mut emulator = setup_emulator();
+        write_banked_ram(&mut emulator.memory, 2, 0x0010, 0x42).unwrap();
+        assert_eq!(cartridge_ram(&emulator.memory)[2 * RAM_BANK_SIZE + 0x10], 0x42);
+
+        mmu::write_byte(&mut emulator, 0x0000, 0x0A);
+        mmu::write_byte(&mut emulator, 0x4000, 0x02);
+        assert_eq!(mmu::read_byte(&mut emulator, 0xA010), 0x42);
+    }
+
+    #[test]
+    fn should_write_through_mapped_bank() {
+        let mut emulator = setup_emulator();
+        mmu::write_byte(&mut emulator, 0x4000, 0x03);
+        assert_eq!(mapped_ram_bank(&emulator.memory), 3);
+
+        write_mapped_ram(&mut emulator.memory, 0xB000, 0x99).unwrap();
+        assert_eq!(read_banked_ram(&emulator.memory, 3, 0x1000).unwrap(), 0x99);
+        assert!(write_mapped_ram(&mut emulator.memory, 0xC000, 0x99).is_err());
+    }
+
+    #[test]
+    fn should_reject_edits_outside_of_cartridge_ram() {
+        let mut emulator = setup_emulator();
+        assert_eq!(ram_bank_count(&emulator.memory), 4);
+        assert!(write_banked_ram(&mut emulator.memory, 4, 0x0000, 0x01).is_err());
+        assert!(write_raw_ram(&mut emulator.memory, 0x7FFF, &[0x01, 0x02]).is_err());
+
+        write_raw_ram(&mut emulator.memory, 0x7FFE, &[0x01, 0x02]).unwrap();
+        assert_eq!(&cartridge_ram(&emulator.memory)[0x7FFE..], &[0x01, 0x02]);
+    }
+}
@@ -0,0 +1,336 @@
+use crate::clock::SharedClock;
+use crate::mmu::bank_utils::{banked_read, unbanked_read};
+use crate::mmu::cartridge::{Cartridge, CartridgeMapper};
+use crate::mmu::mbc3::RTCState;
+use crate::savestate::{StateReader, StateWriter};
+use std::io;
+
+/*
+    TAMA5 is only used by Game de Hakken!! Tamagotchi Osutchi to Mesutchi. Instead
+    of mapping registers into the ROM area, everything goes through A000-A001: the
+    game selects a register by writing its index to A001 and then reads or writes a
+    nibble at A000. Writing the low address register runs whatever command the
+    address and data registers hold, which is how its 32 bytes of RAM and its clock
+    are accessed.
+*/
+const REGISTER_BANK_LOW: u8 = 0x0;
+const REGISTER_BANK_HIGH: u8 = 0x1;
+const REGISTER_WRITE_LOW: u8 = 0x4;
+const REGISTER_WRITE_HIGH: u8 = 0x5;
+const REGISTER_ADDRESS_HIGH: u8 = 0x6;
+const REGISTER_ADDRESS_LOW: u8 = 0x7;
+const REGISTER_ACTIVE: u8 = 0xA;
+const REGISTER_READ_LOW: u8 = 0xC;
+const REGISTER_READ_HIGH: u8 = 0xD;
+
+const COMMAND_RAM_WRITE: u8 = 0x0;
+const COMMAND_RAM_READ: u8 = 0x1;
+const COMMAND_RTC: u8 = 0x2;
+
+const RTC_STOP: u8 = 0x0;
+const RTC_START: u8 = 0x1;
+const RTC_MINUTES: u8 = 0x4;
+const RTC_HOURS: u8 = 0x5;
+
+const RAM_SIZE: usize = 0x20;
+
+#[derive(Debug)]
+pub struct TAMA5 {
+    cartridge: Cartridge,
+    registers: [u8; 8],
+    selected_register: u8,
+    rtc_state: RTCState
+}
+
+pub fn initialize_tama5(mut cartridge: Cartridge) -> TAMA5 {
+    if cartridge.ram.len() < RAM_SIZE {
+        cartridge.ram.resize(RAM_SIZE, 0);
+    }
+
+    let rtc_state_key = format!("{}-rtc", cartridge.header.title);
+    TAMA5 {
+        registers: [0; 8],
+        selected_register: 0,
+        rtc_state: cartridge.effects.load_rtc_state(&rtc_state_key).unwrap_or(RTCState {
+            milliseconds: 0,
+            seconds: 0,
+            minutes: 0,
+            hours: 0,
+            days: 0,
+            base_timestamp: cartridge.clock.current_time_millis(),
+            halted: false,
+            day_carry: false,
+        }),
+        cartridge
+    }
+}
+
+fn to_bcd(value: u8) -> u8 {
+    ((value / 10) << 4) | (value % 10)
+}
+
+fn from_bcd(value: u8) -> u8 {
+    (value >> 4) * 10 + (value & 0xF)
+}
+
+impl TAMA5 {
+    fn rom_bank_number(&self) -> u16 {
+        ((self.registers[REGISTER_BANK_HIGH as usize] as u16 & 0x1) << 4) | self.registers[REGISTER_BANK_LOW as usize] as u16
+    }
+
+    fn ram_address(&self) -> usize {
+        (((self.registers[REGISTER_ADDRESS_HIGH as usize] << 4) & 0x10) | self.registers[REGISTER_ADDRESS_LOW as usize]) as usize
+    }
+
+    fn command(&self) -> u8 {
+        self.registers[REGISTER_ADDRESS_HIGH as usize] >> 1
+    }
+
+    fn data(&self) -> u8 {
+        (self.registers[REGISTER_WRITE_HIGH as usize] << 4) | self.registers[REGISTER_WRITE_LOW as usize]
+    }
+
+    // Brings the clock up to date with the cartridge clock, the same way MBC3 does when latched.
+    fn update_rtc(&mut self) {
+        let current_time = self.cartridge.clock.current_time_millis();
+        if !self.rtc_state.halted {
+            let elapsed_ms = current_time - self.rtc_state.base_timestamp;
+            if elapsed_ms > 0.0 {
+                let milliseconds = self.rtc_state.milliseconds as u64 + elapsed_ms as u64;
+                let seconds = self.rtc_state.seconds as u64 + milliseconds / 1000;
+                let minutes = self.rtc_state.minutes as u64 + seconds / 60;
+                let hours = self.rtc_state.hours as u64 + minutes / 60;
+                let days = self.rtc_state.days as u64 + hours / 24;
+
+                self.rtc_state.milliseconds = (milliseconds % 1000) as u16;
+                self.rtc_state.seconds = (seconds % 60) as u8;
+                self.rtc_state.minutes = (minutes % 60) as u8;
+                self.rtc_state.hours = (hours % 24) as u8;
+                self.rtc_state.days = (days % 512) as u16;
+            }
+        }
+        self.rtc_state.base_timestamp = current_time;
+    }
+
+    fn save_rtc_state(&self) {
+        let key = format!("{}-rtc", self.cartridge.header.title);
+        self.cartridge.effects.save_rtc_state(&key, &self.rtc_state);
+    }
+
+    fn run_command(&mut self) {
+        let address = self.ram_address();
+        let data = self.data();
+
+        match self.command() {
+            COMMAND_RAM_WRITE => {
+                self.cartridge.ram[address] = data;
+                self.cartridge.effects.save_ram(&self.cartridge.header.title, &self.cartridge.ram);
+            },
+            COMMAND_RTC => {
+                self.update_rtc();
+                match address as u8 {
+                    RTC_STOP => self.rtc_state.halted = true,
+                    RTC_START => self.rtc_state.halted = false,
+                    RTC_MINUTES => {
+                        self.rtc_state.minutes = from_bcd(data) % 60;
+                        self.rtc_state.seconds = 0;
+                        self.rtc_state.milliseconds = 0;
+                    },
+                    RTC_HOURS => self.rtc_state.hours = from_bcd(data) % 24,
+                    _ => {}
+                }
+                self.save_rtc_state();
+            },
+            _ => {}
+        }
+    }
+
+    fn read_result(&self) -> u8 {
+        match self.command() {
+            COMMAND_RAM_READ => self.cartridge.ram[self.ram_address()],
+            COMMAND_RTC => match self.ram_address() as u8 {
+                RTC_MINUTES => to_bcd(self.rtc_state.minutes),
+                RTC_HOURS => to_bcd(self.rtc_state.hours),
+                _ => 0
+            },
+            _ => 0
+        }
+    }
+}
+
+impl CartridgeMapper for TAMA5 {
+    fn read_rom(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF =>
+                unbanked_read(&self.cartridge.rom, address),
+            0x4000..=0x7FFF => {
+                banked_read(&self.cartridge.rom, 0x4000, address, self.rom_bank_number())
+            },
+            _ => panic!("Invalid ROM address: {:#X}", address),
+        }
+    }
+
+    // All of TAMA5's registers are in the RAM area.
+    fn write_rom(&mut self, _: u16, _: u8) {}
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if address & 0x1FFF != 0 {
+            return 0xFF;
+        }
+
+        match self.selected_register {
+            REGISTER_ACTIVE => 0xF1,
+            REGISTER_READ_LOW => 0xF0 | (self.read_result() & 0xF),
+            REGISTER_READ_HIGH => 0xF0 | (self.read_result() >> 4),
+            _ => 0xF0
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        match address & 0x1FFF {
+            0x0000 if (self.selected_register as usize) < self.registers.len() => {
+                self.registers[self.selected_register as usize] = value & 0xF;
+                if self.selected_register == REGISTER_ADDRESS_LOW {
+                    self.run_command();
+                }
+            },
+            0x0001 => self.selected_register = value & 0xF,
+            _ => {}
+        }
+    }
+
+    fn get_cartridge(&self) -> &Cartridge {
+        &self.cartridge
+    }
+
+    fn set_cartridge_ram(&mut self, mut ram: Vec<u8>) {
+        ram.resize(RAM_SIZE, 0);
+        self.cartridge.ram = ram;
+    }
+
+    fn get_cartridge_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.cartridge.ram
+    }
+
+    fn get_rom_bank(&self) -> u16 {
+        self.rom_bank_number()
+    }
+
+    fn get_ram_bank(&self) -> u8 {
+        0
+    }
+
+    fn reset_registers(&mut self) {
+        self.registers = [0; 8];
+        self.selected_register = 0;
+    }
+
+    fn serialize_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.registers);
+        writer.write_u8(self.selected_register);
+        writer.write_u16(self.rtc_state.milliseconds);
+        writer.write_u8(self.rtc_state.seconds);
+        writer.write_u8(self.rtc_state.minutes);
+        writer.write_u8(self.rtc_state.hours);
+        writer.write_u16(self.rtc_state.days);
+        writer.write_f64(self.rtc_state.base_timestamp);
+        writer.write_bool(self.rtc_state.halted);
+    }
+
+    fn deserialize_state(&mut self, reader: &mut StateReader) -> io::Result<()> {
+        reader.read_into(&mut self.registers)?;
+        self.selected_register = reader.read_u8()?;
+        self.rtc_state.milliseconds = reader.read_u16()?;
+        self.rtc_state.seconds = reader.read_u8()?;
+        self.rtc_state.minutes = reader.read_u8()?;
+        self.rtc_state.hours = reader.read_u8()?;
+        self.rtc_state.days = reader.read_u16()?;
+        self.rtc_state.base_timestamp = reader.read_f64()?;
+        self.rtc_state.halted = reader.read_bool()?;
+        Ok(())
+    }
+
+    fn get_rtc_state(&self) -> Option<&RTCState> {
+        Some(&self.rtc_state)
+    }
+
+    fn set_rtc_state(&mut self, rtc_state: RTCState) {
+        self.rtc_state = rtc_state;
+        self.save_rtc_state();
+    }
+    fn set_clock(&mut self, clock: SharedClock) {
+        self.update_rtc();
+        self.cartridge.clock = clock;
+        self.rtc_state.base_timestamp = self.cartridge.clock.current_time_millis();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mmu::cartridge::*;
+    use crate::mmu::cartridge::test_utils::*;
+    use crate::mmu::constants::*;
+    use crate::mmu::effects::empty_cartridge_effects;
+    use crate::mmu::test_utils::*;
+    use super::*;
+
+    fn write_register(mapper: &mut Box<dyn CartridgeMapper>, register: u8, value: u8) {
+        mapper.write_ram(0x0001, register);
+        mapper.write_ram(0x0000, value);
+    }
+
+    fn read_register(mapper: &mut Box<dyn CartridgeMapper>, register: u8) -> u8 {
+        mapper.write_ram(0x0001, register);
+        mapper.read_ram(0x0000) & 0xF
+    }
+
+    fn run_command(mapper: &mut Box<dyn CartridgeMapper>, command: u8, address: u8, data: u8) {
+        write_register(mapper, REGISTER_WRITE_LOW, data & 0xF);
+        write_register(mapper, REGISTER_WRITE_HIGH, data >> 4);
+        write_register(mapper, REGISTER_ADDRESS_HIGH, (command << 1) | (address >> 4));
+        write_register(mapper, REGISTER_ADDRESS_LOW, address & 0xF);
+    }
+
+    #[test]
+    fn report_chip_as_active() {
+        let mut mapper = build_cartridge_mapper(CART_TYPE_TAMA5, ROM_SIZE_512KB, RAM_SIZE_0KB);
+        assert_eq!(read_register(&mut mapper, REGISTER_ACTIVE), 0x1);
+    }
+
+    #[test]
+    fn set_rom_bank_number() {
+        let mut rom = build_rom(CART_TYPE_TAMA5, ROM_SIZE_512KB, RAM_SIZE_0KB);
+        rom[0x12 * 0x4000 + 0x5] = 0xA1;
+        let mut mapper = load_rom_buffer(rom, empty_cartridge_effects()).unwrap();
+
+        write_register(&mut mapper, REGISTER_BANK_LOW, 0x2);
+        write_register(&mut mapper, REGISTER_BANK_HIGH, 0x1);
+
+        assert_eq!(mapper.read_rom(0x4005), 0xA1);
+    }
+
+    #[test]
+    fn write_and_read_ram_through_commands() {
+        let mut mapper = build_cartridge_mapper(CART_TYPE_TAMA5, ROM_SIZE_512KB, RAM_SIZE_0KB);
+
+        run_command(&mut mapper, COMMAND_RAM_WRITE, 0x13, 0x5C);
+        run_command(&mut mapper, COMMAND_RAM_READ, 0x13, 0x00);
+
+        assert_eq!(read_register(&mut mapper, REGISTER_READ_LOW), 0xC);
+        assert_eq!(read_register(&mut mapper, REGISTER_READ_HIGH), 0x5);
+        assert_eq!(mapper.get_cartridge().ram[0x13], 0x5C);
+    }
+
+    #[test]
+    fn set_clock_through_commands() {
+        let mut mapper = build_cartridge_mapper(CART_TYPE_TAMA5, ROM_SIZE_512KB, RAM_SIZE_0KB);
+
+        run_command(&mut mapper, COMMAND_RTC, RTC_HOURS, 0x17);
+        run_command(&mut mapper, COMMAND_RTC, RTC_MINUTES, 0x42);
+
+        assert_eq!(read_register(&mut mapper, REGISTER_READ_LOW), 0x2);
+        assert_eq!(read_register(&mut mapper, REGISTER_READ_HIGH), 0x4);
+        let rtc_state = mapper.get_rtc_state().unwrap();
+        assert_eq!((rtc_state.hours, rtc_state.minutes), (17, 42));
+    }
+}
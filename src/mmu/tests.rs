@@ -86,7 +86,7 @@ fn setup_emulator_with_test_memory() -> Emulator {
     emulator.apu.enabled = true;
     emulator.apu.sound_panning = 0xF2;
     emulator.apu.master_volume = 0xC1;
-    emulator.apu.audio_buffer_clock = 84;
+    emulator.apu.sample_phase = 84 * 44100;
 
     emulator.apu.channel1.sweep.initial_settings = 0xDD;
     emulator.apu.channel1.length.initial_settings = 0xB0;
@@ -212,7 +212,7 @@ fn reads_from_interrupts_enabled_register() {
 #[test]
 fn reads_from_interrupt_flags_register() {
     let mut emulator= setup_emulator_with_test_memory();
-    assert_eq!(read_byte(&mut emulator, 0xFF0F), 0xA);
+    assert_eq!(read_byte(&mut emulator, 0xFF0F), 0xEA);
 }
 
 #[test]
@@ -236,7 +236,7 @@ fn reads_from_timer_modulo_register() {
 #[test]
 fn reads_from_timer_control_register() {
     let mut emulator= setup_emulator_with_test_memory();
-    assert_eq!(read_byte(&mut emulator, 0xFF07), 0x07);
+    assert_eq!(read_byte(&mut emulator, 0xFF07), 0xFF);
 }
 
 #[test]
@@ -309,10 +309,31 @@ fn writes_obp1_value_to_gpu() {
     assert_eq!(read_byte(&mut emulator, 0xFF49), 0x1B);
 }
 
+#[test]
+fn ignores_writes_to_ly() {
+    let mut emulator = setup_emulator_with_test_memory();
+    write_byte(&mut emulator, 0xFF44, 0x00);
+    assert_eq!(read_byte(&mut emulator, 0xFF44), 0x2B);
+}
+
+#[test]
+fn only_writes_interrupt_source_bits_of_stat() {
+    let mut emulator = setup_emulator_with_test_memory();
+    write_byte(&mut emulator, 0xFF41, 0x0F);
+    assert_eq!(read_byte(&mut emulator, 0xFF41), 0x8A);
+}
+
+#[test]
+fn updates_coincidence_flag_when_writing_lyc() {
+    let mut emulator = setup_emulator_with_test_memory();
+    write_byte(&mut emulator, 0xFF45, 0x2B);
+    assert_eq!(read_byte(&mut emulator, 0xFF41) & 0x04, 0x04);
+}
+
 #[test]
 fn reads_joyp_register() {
     let mut emulator = setup_emulator_with_test_memory();
-    assert_eq!(read_byte(&mut emulator, 0xFF00), 0x14);
+    assert_eq!(read_byte(&mut emulator, 0xFF00), 0xD4);
 }
 
 #[test]
@@ -477,9 +498,324 @@ fn reads_from_ch4_control() {
     assert_eq!(read_byte(&mut emulator, 0xFF23), 0xFF);
 }
 
+#[test]
+fn reads_channel_outputs_from_pcm_registers_in_cgb_mode() {
+    let mut emulator = setup_emulator_with_test_memory();
+    emulator.mode = Mode::CGB;
+    emulator.apu.channel1.enabled = true;
+    emulator.apu.channel1.length.initial_settings = 0b11000000;
+    emulator.apu.channel1.wave_duty_position = 2;
+    emulator.apu.channel1.envelope.current_volume = 0xA;
+    emulator.apu.channel4.enabled = true;
+    emulator.apu.channel4.lfsr = 0x01;
+    emulator.apu.channel4.envelope.current_volume = 0x3;
+
+    assert_eq!(read_byte(&mut emulator, 0xFF76), 0x0A);
+    assert_eq!(read_byte(&mut emulator, 0xFF77), 0x30);
+
+    write_byte(&mut emulator, 0xFF76, 0x55);
+    assert_eq!(read_byte(&mut emulator, 0xFF76), 0x0A);
+}
+
+#[test]
+fn reads_ff_from_pcm_registers_in_dmg_mode() {
+    let mut emulator = setup_emulator_with_test_memory();
+    emulator.apu.channel1.enabled = true;
+    emulator.apu.channel1.envelope.current_volume = 0xF;
+    assert_eq!(read_byte(&mut emulator, 0xFF76), 0xFF);
+    assert_eq!(read_byte(&mut emulator, 0xFF77), 0xFF);
+}
+
 #[test]
 fn reads_from_key1() {
     let mut emulator = setup_emulator_with_test_memory();
     emulator.mode = Mode::CGB;
-    assert_eq!(read_byte(&mut emulator, 0xFF4D), 0x0);
-}
\ No newline at end of file
+    assert_eq!(read_byte(&mut emulator, 0xFF4D), 0x7E);
+}
+
+#[test]
+fn appends_rtc_footer_to_battery_save_for_timer_cartridges() {
+    let mut memory = initialize_memory();
+    let rom = build_rom(CART_TYPE_MBC3_TIMER_RAM_BATTERY, ROM_SIZE_64KB, RAM_SIZE_8KB);
+    load_rom_buffer(&mut memory, rom, empty_cartridge_effects()).unwrap();
+
+    let save = get_battery_save(&memory);
+    assert_eq!(save.len(), 0x2000 + mbc3::RTC_FOOTER_SIZE);
+}
+
+#[test]
+fn does_not_append_rtc_footer_to_battery_save_without_timer() {
+    let mut memory = initialize_memory();
+    let rom = build_rom(CART_TYPE_MBC1_WITH_RAM_PLUS_BATTERY, ROM_SIZE_64KB, RAM_SIZE_8KB);
+    load_rom_buffer(&mut memory, rom, empty_cartridge_effects()).unwrap();
+
+    let save = get_battery_save(&memory);
+    assert_eq!(save.len(), 0x2000);
+}
+
+#[test]
+fn loads_rtc_footer_from_battery_save() {
+    let mut memory = initialize_memory();
+    let rom = build_rom(CART_TYPE_MBC3_TIMER_RAM_BATTERY, ROM_SIZE_64KB, RAM_SIZE_8KB);
+    load_rom_buffer(&mut memory, rom, empty_cartridge_effects()).unwrap();
+
+    let mut save = vec![0x5A; 0x2000];
+    save.extend(mbc3::encode_rtc_footer(&RTCState {
+        milliseconds: 0,
+        seconds: 30,
+        minutes: 20,
+        hours: 10,
+        days: 300,
+        base_timestamp: 1_650_000_000_000.0,
+        halted: false,
+        day_carry: false,
+    }));
+    set_battery_save(&mut memory, save);
+
+    let rtc_state = memory.cartridge_mapper.get_rtc_state().unwrap();
+    assert_eq!(rtc_state.minutes, 20);
+    assert_eq!(rtc_state.days, 300);
+    assert_eq!(get_cartridge_ram(&memory), vec![0x5A; 0x2000]);
+}
+
+#[test]
+fn appends_flash_to_battery_save_for_flash_cartridges() {
+    let mut memory = initialize_memory();
+    let rom = build_rom(CART_TYPE_MBC6, ROM_SIZE_128KB, RAM_SIZE_32KB);
+    load_rom_buffer(&mut memory, rom, empty_cartridge_effects()).unwrap();
+
+    let mut save = get_battery_save(&memory);
+    assert_eq!(save.len(), 0x8000 + 0x100000);
+
+    save[0x8000 + 0x10] = 0x12;
+    set_battery_save(&mut memory, save);
+    assert_eq!(memory.cartridge_mapper.get_flash().unwrap()[0x10], 0x12);
+    assert_eq!(get_cartridge_ram(&memory).len(), 0x8000);
+}
+
+const APU_POWERED_OFF_READ_VALUES: [(u16, u8); 21] = [
+    (0xFF10, 0x80), (0xFF11, 0x3F), (0xFF12, 0x00), (0xFF13, 0xFF), (0xFF14, 0xBF),
+    (0xFF15, 0xFF), (0xFF16, 0x3F), (0xFF17, 0x00), (0xFF18, 0xFF), (0xFF19, 0xBF),
+    (0xFF1A, 0x7F), (0xFF1B, 0xFF), (0xFF1C, 0x9F), (0xFF1D, 0xFF), (0xFF1E, 0xBF),
+    (0xFF1F, 0xFF), (0xFF20, 0xFF), (0xFF21, 0x00), (0xFF22, 0x00), (0xFF23, 0xBF),
+    (0xFF24, 0x00)
+];
+
+fn power_off_apu_after_filling_registers(emulator: &mut Emulator) {
+    write_byte(emulator, 0xFF26, 0x80);
+    for address in 0xFF10..=0xFF25 {
+        write_byte(emulator, address, 0xFF);
+    }
+    write_byte(emulator, 0xFF26, 0x00);
+}
+
+#[test]
+fn clears_apu_registers_when_powered_off() {
+    let mut emulator = setup_emulator_with_test_memory();
+    power_off_apu_after_filling_registers(&mut emulator);
+
+    for (address, expected) in APU_POWERED_OFF_READ_VALUES {
+        assert_eq!(read_byte(&mut emulator, address), expected, "register {:#X}", address);
+    }
+    assert_eq!(read_byte(&mut emulator, 0xFF25), 0x00);
+    assert_eq!(read_byte(&mut emulator, 0xFF26), 0x70);
+}
+
+#[test]
+fn ignores_apu_register_writes_when_powered_off() {
+    let mut emulator = setup_emulator_with_test_memory();
+    power_off_apu_after_filling_registers(&mut emulator);
+
+    for address in 0xFF10..=0xFF25 {
+        write_byte(&mut emulator, address, 0xFF);
+    }
+
+    for (address, expected) in APU_POWERED_OFF_READ_VALUES {
+        assert_eq!(read_byte(&mut emulator, address), expected, "register {:#X}", address);
+    }
+    assert_eq!(read_byte(&mut emulator, 0xFF25), 0x00);
+    assert_eq!(read_byte(&mut emulator, 0xFF26), 0x70);
+}
+
+#[test]
+fn keeps_wave_ram_writable_when_apu_is_powered_off() {
+    let mut emulator = setup_emulator_with_test_memory();
+    power_off_apu_after_filling_registers(&mut emulator);
+    write_byte(&mut emulator, 0xFF30, 0x5A);
+    assert_eq!(read_byte(&mut emulator, 0xFF30), 0x5A);
+}
+
+#[test]
+fn allows_length_writes_on_dmg_when_apu_is_powered_off() {
+    let mut emulator = setup_emulator_with_test_memory();
+    power_off_apu_after_filling_registers(&mut emulator);
+
+    write_byte(&mut emulator, 0xFF11, 0xC5);
+    write_byte(&mut emulator, 0xFF1B, 0x10);
+
+    assert_eq!(emulator.apu.channel1.length.timer, 64 - 5);
+    assert_eq!(emulator.apu.channel3.length.timer, 256 - 0x10);
+    assert_eq!(read_byte(&mut emulator, 0xFF11), 0x3F);
+}
+
+#[test]
+fn ignores_length_writes_on_cgb_when_apu_is_powered_off() {
+    let mut emulator = setup_emulator_with_test_memory();
+    emulator.mode = Mode::CGB;
+    power_off_apu_after_filling_registers(&mut emulator);
+
+    write_byte(&mut emulator, 0xFF11, 0xC5);
+
+    assert_eq!(emulator.apu.channel1.length.timer, 0);
+}
+
+#[test]
+fn rejects_roms_too_short_to_hold_a_header() {
+    let mut memory = initialize_memory();
+    for length in [0, 0x100, 0x14F] {
+        assert!(load_rom_buffer(&mut memory, vec![0; length], empty_cartridge_effects()).is_err());
+    }
+}
+
+#[test]
+fn rejects_unsupported_rom_and_ram_size_indices() {
+    let mut memory = initialize_memory();
+    for size_index in 0..=0xFF {
+        let mut rom = build_rom(CART_TYPE_MBC5_RAM_BATTERY, ROM_SIZE_64KB, RAM_SIZE_8KB);
+        rom[ROM_SIZE_ADDRESS] = size_index;
+        assert_eq!(load_rom_buffer(&mut memory, rom, empty_cartridge_effects()).is_ok(), size_index <= 0x08);
+
+        let rom = build_rom(CART_TYPE_MBC5_RAM_BATTERY, ROM_SIZE_64KB, size_index);
+        assert_eq!(load_rom_buffer(&mut memory, rom, empty_cartridge_effects()).is_ok(), size_index <= 0x05);
+    }
+}
+
+#[test]
+fn reads_open_bus_from_banks_missing_from_short_roms() {
+    let cartridge_types = [CART_TYPE_ROM_ONLY, CART_TYPE_MBC1, CART_TYPE_MBC3, CART_TYPE_MBC5, CART_TYPE_HUC1_RAM_BATTERY];
+
+    for cartridge_type in cartridge_types {
+        for rom_size_index in 0..=0x08 {
+            let mut emulator = initialize_screenless_emulator();
+            emulator.memory.in_bios = false;
+
+            // The header claims up to 8MB, but only the first 32KB are present.
+            let mut rom = build_rom(cartridge_type, ROM_SIZE_64KB, RAM_SIZE_8KB);
+            rom.truncate(0x8000);
+            rom[ROM_SIZE_ADDRESS] = rom_size_index;
+            load_rom_buffer(&mut emulator.memory, rom, empty_cartridge_effects()).unwrap();
+
+            for bank in [0x00, 0x01, 0x05, 0x3F, 0xFF] {
+                write_byte(&mut emulator, 0x2000, bank);
+                write_byte(&mut emulator, 0x4000, bank);
+                read_byte(&mut emulator, 0x4000);
+                read_byte(&mut emulator, 0x7FFF);
+                read_byte(&mut emulator, 0xA000);
+            }
+
+            // Bank 5 is only selectable once the header claims at least 8 banks.
+            write_byte(&mut emulator, 0x2000, 0x05);
+            if cartridge_type != CART_TYPE_ROM_ONLY && rom_size_index >= 0x02 {
+                assert_eq!(read_byte(&mut emulator, 0x4000), 0xFF);
+            }
+        }
+    }
+}
+
+#[test]
+fn mirrors_echo_ram_writes_into_working_ram() {
+    let mut emulator = initialize_screenless_emulator();
+    write_byte(&mut emulator, 0xE010, 0x12);
+    assert_eq!(read_byte(&mut emulator, 0xC010), 0x12);
+
+    write_byte(&mut emulator, 0xDDFF, 0x34);
+    assert_eq!(read_byte(&mut emulator, 0xFDFF), 0x34);
+}
+
+#[test]
+fn mirrors_switchable_working_ram_bank_through_echo_ram() {
+    let mut emulator = initialize_screenless_emulator();
+    emulator.mode = Mode::CGB;
+    write_byte(&mut emulator, 0xFF70, 0x03);
+    write_byte(&mut emulator, 0xF100, 0x56);
+
+    assert_eq!(read_byte(&mut emulator, 0xD100), 0x56);
+    write_byte(&mut emulator, 0xFF70, 0x02);
+    assert_eq!(read_byte(&mut emulator, 0xF100), 0x00);
+}
+
+#[test]
+fn reads_zero_from_prohibited_area_on_dmg() {
+    let mut emulator = initialize_screenless_emulator();
+    write_byte(&mut emulator, 0xFEA0, 0x12);
+    assert_eq!(read_byte(&mut emulator, 0xFEA0), 0x00);
+    assert_eq!(read_byte(&mut emulator, 0xFEFF), 0x00);
+}
+
+#[test]
+fn reads_ff_from_prohibited_area_on_dmg_while_oam_is_locked() {
+    let mut emulator = initialize_screenless_emulator();
+    emulator.gpu.registers.lcdc = 0x80;
+    emulator.gpu.mode = 2;
+    assert_eq!(read_byte(&mut emulator, 0xFEC0), 0xFF);
+}
+
+#[test]
+fn repeats_address_nibble_in_prohibited_area_on_cgb() {
+    let mut emulator = initialize_screenless_emulator();
+    emulator.mode = Mode::CGB;
+    write_byte(&mut emulator, 0xFEB4, 0x12);
+    assert_eq!(read_byte(&mut emulator, 0xFEB4), 0xBB);
+    assert_eq!(read_byte(&mut emulator, 0xFEFF), 0xFF);
+    assert_eq!(read_byte(&mut emulator, 0xFEA0), 0xAA);
+}
+
+#[test]
+fn reads_unused_bits_of_joypad_timer_and_interrupt_registers_as_set() {
+    let mut emulator = initialize_screenless_emulator();
+    emulator.memory.in_bios = false;
+    write_byte(&mut emulator, 0xFF00, 0x30);
+    write_byte(&mut emulator, 0xFF07, 0x00);
+    write_byte(&mut emulator, 0xFF0F, 0x00);
+    write_byte(&mut emulator, 0xFF02, 0x00);
+
+    assert_eq!(read_byte(&mut emulator, 0xFF00), 0xFF);
+    assert_eq!(read_byte(&mut emulator, 0xFF07), 0xF8);
+    assert_eq!(read_byte(&mut emulator, 0xFF0F), 0xE0);
+    assert_eq!(read_byte(&mut emulator, 0xFF02), 0x7E);
+
+    emulator.mode = Mode::CGB;
+    assert_eq!(read_byte(&mut emulator, 0xFF02), 0x7C);
+}
+
+#[test]
+fn reads_unused_bits_of_sound_registers_as_set() {
+    let mut emulator = initialize_screenless_emulator();
+    emulator.memory.in_bios = false;
+    write_byte(&mut emulator, 0xFF26, 0x80);
+    for address in [0xFF10, 0xFF11, 0xFF1A, 0xFF1C, 0xFF23] {
+        write_byte(&mut emulator, address, 0x00);
+    }
+
+    assert_eq!(read_byte(&mut emulator, 0xFF10), 0x80);
+    assert_eq!(read_byte(&mut emulator, 0xFF11), 0x3F);
+    assert_eq!(read_byte(&mut emulator, 0xFF13), 0xFF);
+    assert_eq!(read_byte(&mut emulator, 0xFF1A), 0x7F);
+    assert_eq!(read_byte(&mut emulator, 0xFF1C), 0x9F);
+    assert_eq!(read_byte(&mut emulator, 0xFF23), 0xBF);
+    assert_eq!(read_byte(&mut emulator, 0xFF26), 0xF0);
+}
+
+#[test]
+fn ignores_cgb_registers_in_dmg_mode() {
+    let mut emulator = initialize_screenless_emulator();
+    emulator.memory.in_bios = false;
+    for address in [0xFF4C, 0xFF4D, 0xFF4F, 0xFF51, 0xFF55, 0xFF56, 0xFF68, 0xFF69, 0xFF6C, 0xFF70] {
+        write_byte(&mut emulator, address, 0x01);
+        assert_eq!(read_byte(&mut emulator, address), 0xFF);
+    }
+
+    assert_eq!(emulator.gpu.registers.key0, 0x00);
+    assert_eq!(emulator.memory.svbk, 0x00);
+    assert_eq!(emulator.hdma.hdma1, 0x00);
+}
@@ -0,0 +1,95 @@
+use crate::mmu::bank_utils::banked_read;
+use crate::mmu::cartridge::{Cartridge, CartridgeMapper};
+use crate::savestate::{StateReader, StateWriter};
+use std::io;
+
+/*
+    Wisdom Tree's unlicensed games swap the whole 32KB ROM area at once. The bank
+    number is taken from the low bits of the address written to in 0000-3FFF rather
+    than from the value written. The header claims to be ROM-only, so the cartridge
+    is recognized by the "WISDOM TREE" string in the ROM instead.
+*/
+#[derive(Debug)]
+pub struct WisdomTree {
+    cartridge: Cartridge,
+    rom_bank_number: u8
+}
+
+pub fn initialize_wisdom_tree(cartridge: Cartridge) -> WisdomTree {
+    WisdomTree {
+        cartridge,
+        rom_bank_number: 0
+    }
+}
+
+impl CartridgeMapper for WisdomTree {
+    fn read_rom(&self, address: u16) -> u8 {
+        banked_read(&self.cartridge.rom, 0x8000, address, self.rom_bank_number as u16)
+    }
+
+    fn write_rom(&mut self, address: u16, _: u8) {
+        if address <= 0x3FFF {
+            self.rom_bank_number = (address & 0x3F) as u8;
+        }
+    }
+
+    fn read_ram(&self, _: u16) -> u8 {
+        0xFF
+    }
+
+    fn write_ram(&mut self, _: u16, _: u8) {}
+
+    fn get_cartridge(&self) -> &Cartridge {
+        &self.cartridge
+    }
+
+    fn set_cartridge_ram(&mut self, _: Vec<u8>) {}
+
+    fn get_cartridge_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.cartridge.ram
+    }
+
+    // Reported in 16KB banks, as symbol files number them.
+    fn get_rom_bank(&self) -> u16 {
+        self.rom_bank_number as u16 * 2 + 1
+    }
+
+    fn get_ram_bank(&self) -> u8 {
+        0
+    }
+
+    fn reset_registers(&mut self) {
+        self.rom_bank_number = 0;
+    }
+
+    fn serialize_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.rom_bank_number);
+    }
+
+    fn deserialize_state(&mut self, reader: &mut StateReader) -> io::Result<()> {
+        self.rom_bank_number = reader.read_u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mmu::cartridge::*;
+    use crate::mmu::constants::*;
+    use crate::mmu::effects::empty_cartridge_effects;
+    use crate::mmu::test_utils::*;
+
+    #[test]
+    fn switch_whole_rom_area_using_written_address() {
+        let mut rom = build_rom(CART_TYPE_ROM_ONLY, ROM_SIZE_128KB, RAM_SIZE_0KB);
+        rom[0x200..0x20B].copy_from_slice(b"WISDOM TREE");
+        rom[0x10005] = 0xA1;
+        rom[0x14005] = 0xB2;
+        let mut mapper = load_rom_buffer(rom, empty_cartridge_effects()).unwrap();
+
+        mapper.write_rom(0x0002, 0xFF);
+
+        assert_eq!(mapper.read_rom(0x0005), 0xA1);
+        assert_eq!(mapper.read_rom(0x4005), 0xB2);
+    }
+}
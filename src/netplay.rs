@@ -0,0 +1,341 @@
+use std::collections::VecDeque;
+use std::io::{self, Error, ErrorKind};
+
+use crate::core::Core;
+use crate::emulator::Emulator;
+use crate::keys::{self, Key};
+use crate::snapshot::{self, StateSnapshot};
+
+pub const DEFAULT_MAX_PREDICTION_FRAMES: u64 = 8;
+
+// Input masks have a bit per key, in this order from the highest bit down.
+pub const INPUT_KEYS: [Key; 8] = [Key::Down, Key::Up, Key::Left, Key::Right, Key::Start, Key::Select, Key::B, Key::A];
+
+// What the session needs from each player's emulator, implemented by both Emulator and GameBoy.
+pub trait Rollback: Core {
+    fn clone_state(&self, snapshot: &mut StateSnapshot);
+    fn restore_state(&mut self, snapshot: &StateSnapshot) -> io::Result<()>;
+    fn set_input(&mut self, mask: u8);
+}
+
+/*
+    GGPO-style rollback for link cable games, with one emulator per player all run
+    locally. Each frame runs straight away with the local player's input, and remote
+    players are predicted to keep holding whatever they last sent. When a remote
+    input arrives that differs from what was predicted, every emulator is rolled back
+    to the snapshot taken before that frame and the frames since are run again with
+    the right inputs, all before the next frame is shown.
+
+    Snapshots are kept from the oldest frame not every player has confirmed yet, so
+    the session refuses to run more than max_prediction_frames past it.
+*/
+#[derive(Debug)]
+struct FrameRecord {
+    frame: u64,
+    inputs: Vec<u8>,
+    // Each emulator as it was before the frame ran.
+    snapshots: Vec<StateSnapshot>
+}
+
+#[derive(Debug)]
+pub struct RollbackSession {
+    pub local_player: usize,
+    pub max_prediction_frames: u64,
+    pub frames_resimulated: u64,
+    frame: u64,
+    history: VecDeque<FrameRecord>,
+    // Inputs received for each player, starting at the oldest frame in the history.
+    inputs: Vec<VecDeque<u8>>,
+    received_frames: Vec<u64>,
+    last_inputs: Vec<u8>,
+    rollback_frame: Option<u64>,
+    spare_snapshots: Vec<Vec<StateSnapshot>>
+}
+
+pub fn as_input_mask(pressed_keys: &[Key]) -> u8 {
+    INPUT_KEYS.iter().enumerate()
+        .filter(|(_, key)| pressed_keys.contains(key))
+        .fold(0, |mask, (index, _)| mask | (0x80 >> index))
+}
+
+fn pressed_input_mask(emulator: &Emulator) -> u8 {
+    keys::joypad_state(emulator).mask
+}
+
+pub fn apply_input(emulator: &mut Emulator, mask: u8) {
+    let changed = pressed_input_mask(emulator) ^ mask;
+    for (index, key) in INPUT_KEYS.iter().enumerate() {
+        let bit = 0x80 >> index;
+        if changed & bit != 0 {
+            if mask & bit != 0 {
+                keys::handle_key_press(emulator, key);
+            }
+            else {
+                keys::handle_key_release(emulator, key);
+            }
+        }
+    }
+}
+
+impl Rollback for Emulator {
+    fn clone_state(&self, snapshot: &mut StateSnapshot) {
+        snapshot::clone_state(self, snapshot);
+    }
+
+    fn restore_state(&mut self, snapshot: &StateSnapshot) -> io::Result<()> {
+        snapshot::restore_state(self, snapshot)
+    }
+
+    fn set_input(&mut self, mask: u8) {
+        apply_input(self, mask);
+    }
+}
+
+impl RollbackSession {
+    pub fn new(players: usize, local_player: usize) -> RollbackSession {
+        RollbackSession {
+            local_player,
+            max_prediction_frames: DEFAULT_MAX_PREDICTION_FRAMES,
+            frames_resimulated: 0,
+            frame: 0,
+            history: VecDeque::new(),
+            inputs: vec![VecDeque::new(); players],
+            received_frames: vec![0; players],
+            last_inputs: vec![0; players],
+            rollback_frame: None,
+            spare_snapshots: Vec::new()
+        }
+    }
+
+    pub fn players(&self) -> usize {
+        self.inputs.len()
+    }
+
+    // The next frame to run.
+    pub fn current_frame(&self) -> u64 {
+        self.frame
+    }
+
+    // The first frame that isn't known to be final yet.
+    pub fn confirmed_frame(&self) -> u64 {
+        self.received_frames.iter().copied().min().unwrap_or(0)
+    }
+
+    /*
+        How many frames ahead of the slowest remote player this session is. Frontends
+        should slow down slightly while it's positive so both sides stay in step,
+        rather than one side having to roll back much more often than the other.
+    */
+    pub fn frame_advantage(&self) -> i64 {
+        let remote_frame = self.received_frames.iter().enumerate()
+            .filter(|(player, _)| *player != self.local_player)
+            .map(|(_, frame)| *frame)
+            .min()
+            .unwrap_or(self.frame);
+        self.frame as i64 - remote_frame as i64
+    }
+
+    fn base_frame(&self) -> u64 {
+        self.history.front().map(|record| record.frame).unwrap_or(self.frame)
+    }
+
+    fn input_for(&self, player: usize, frame: u64) -> u8 {
+        let index = (frame - self.base_frame()) as usize;
+        self.inputs[player].get(index).copied().unwrap_or(self.last_inputs[player])
+    }
+
+    fn add_input(&mut self, player: usize, frame: u64, mask: u8) -> io::Result<()> {
+        if player >= self.players() {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("Invalid player: {}", player)));
+        }
+
+        // Inputs are sent over and over until acknowledged, so repeats are expected.
+        if frame < self.received_frames[player] {
+            return Ok(());
+        }
+
+        if frame != self.received_frames[player] {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("Expected input for frame {} but got frame {}.", self.received_frames[player], frame)));
+        }
+
+        if frame < self.frame {
+            let record = &self.history[(frame - self.base_frame()) as usize];
+            if record.inputs[player] != mask {
+                self.rollback_frame = Some(self.rollback_frame.map_or(frame, |rollback_frame| rollback_frame.min(frame)));
+            }
+        }
+
+        self.inputs[player].push_back(mask);
+        self.received_frames[player] += 1;
+        self.last_inputs[player] = mask;
+        Ok(())
+    }
+
+    // Adds the local player's input for the current frame, which should also be sent to the other players.
+    pub fn add_local_input(&mut self, mask: u8) -> io::Result<u64> {
+        let frame = self.frame;
+        self.add_input(self.local_player, frame, mask)?;
+        Ok(frame)
+    }
+
+    pub fn add_remote_input(&mut self, player: usize, frame: u64, mask: u8) -> io::Result<()> {
+        if player == self.local_player {
+            return Err(Error::new(ErrorKind::InvalidInput, "Remote input can't be added for the local player."));
+        }
+        self.add_input(player, frame, mask)
+    }
+
+    fn run_frame<R: Rollback>(&mut self, emulators: &mut [R]) {
+        let mut snapshots = self.spare_snapshots.pop().unwrap_or_default();
+        snapshots.resize_with(emulators.len(), StateSnapshot::new);
+
+        let inputs: Vec<u8> = (0..self.players()).map(|player| self.input_for(player, self.frame)).collect();
+        for (player, emulator) in emulators.iter_mut().enumerate() {
+            emulator.clone_state(&mut snapshots[player]);
+            emulator.set_input(inputs[player]);
+            emulator.run_frame();
+        }
+
+        self.history.push_back(FrameRecord { frame: self.frame, inputs, snapshots });
+        self.frame += 1;
+    }
+
+    fn roll_back<R: Rollback>(&mut self, emulators: &mut [R], frame: u64) -> io::Result<()> {
+        let index = (frame - self.base_frame()) as usize;
+        for (emulator, snapshot) in emulators.iter_mut().zip(&self.history[index].snapshots) {
+            emulator.restore_state(snapshot)?;
+        }
+
+        let end_frame = self.frame;
+        for record in self.history.drain(index..) {
+            self.spare_snapshots.push(record.snapshots);
+        }
+        self.frame = frame;
+
+        while self.frame < end_frame {
+            self.run_frame(emulators);
+        }
+        self.frames_resimulated += end_frame - frame;
+        Ok(())
+    }
+
+    // Snapshots and inputs from before the confirmed frame can never be rolled back to again.
+    fn discard_confirmed_frames(&mut self) {
+        let confirmed_frame = self.confirmed_frame().min(self.frame);
+        while self.history.front().is_some_and(|record| record.frame < confirmed_frame) {
+            if let Some(record) = self.history.pop_front() {
+                self.spare_snapshots.push(record.snapshots);
+            }
+            for inputs in &mut self.inputs {
+                inputs.pop_front();
+            }
+        }
+    }
+
+    /*
+        Runs the next frame on every emulator, rolling back first if a remote input
+        has arrived that was mispredicted. Fails with WouldBlock when the session has
+        run as far ahead of the remote players as it's allowed to, in which case the
+        frontend should wait for their inputs before trying again.
+    */
+    pub fn advance_frame<R: Rollback>(&mut self, emulators: &mut [R]) -> io::Result<()> {
+        if emulators.len() != self.players() {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("Expected {} emulators but got {}.", self.players(), emulators.len())));
+        }
+
+        if self.received_frames[self.local_player] <= self.frame {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("Local input for frame {} hasn't been added.", self.frame)));
+        }
+
+        if let Some(frame) = self.rollback_frame.take() {
+            self.roll_back(emulators, frame)?;
+        }
+        self.discard_confirmed_frames();
+
+        if self.frame - self.confirmed_frame().min(self.frame) >= self.max_prediction_frames {
+            return Err(Error::new(ErrorKind::WouldBlock, "Waiting for remote input."));
+        }
+
+        self.run_frame(emulators);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mmu::constants::*;
+    use crate::mmu::test_utils::*;
+    use super::*;
+
+    fn setup_emulators(count: usize) -> Vec<Emulator> {
+        (0..count).map(|_| setup_emulator(CART_TYPE_MBC1, RAM_SIZE_0KB)).collect()
+    }
+
+    fn snapshots(emulators: &[Emulator]) -> Vec<StateSnapshot> {
+        emulators.iter().map(snapshot::take_snapshot).collect()
+    }
+
+    #[test]
+    fn should_convert_keys_to_input_mask() {
+        assert_eq!(as_input_mask(&[Key::A, Key::Down]), 0x81);
+
+        let mut emulators = setup_emulators(1);
+        apply_input(&mut emulators[0], 0x81);
+        assert_eq!(pressed_input_mask(&emulators[0]), 0x81);
+        apply_input(&mut emulators[0], 0x08);
+        assert_eq!(pressed_input_mask(&emulators[0]), 0x08);
+    }
+
+    #[test]
+    fn should_roll_back_and_resimulate_mispredicted_frames() {
+        let remote_inputs = [0x00, 0x01, 0x01, 0x03];
+
+        let mut reference = setup_emulators(2);
+        let mut reference_session = RollbackSession::new(2, 0);
+        for (frame, remote_input) in remote_inputs.iter().enumerate() {
+            reference_session.add_local_input(0x80).unwrap();
+            reference_session.add_remote_input(1, frame as u64, *remote_input).unwrap();
+            reference_session.advance_frame(&mut reference).unwrap();
+        }
+
+        let mut emulators = setup_emulators(2);
+        let mut session = RollbackSession::new(2, 0);
+        session.add_remote_input(1, 0, remote_inputs[0]).unwrap();
+        for _ in 0..remote_inputs.len() {
+            session.add_local_input(0x80).unwrap();
+            session.advance_frame(&mut emulators).unwrap();
+        }
+        assert_eq!(session.frame_advantage(), 3);
+
+        for (frame, remote_input) in remote_inputs.iter().enumerate().skip(1) {
+            session.add_remote_input(1, frame as u64, *remote_input).unwrap();
+        }
+        session.add_local_input(0x80).unwrap();
+        session.advance_frame(&mut emulators).unwrap();
+        reference_session.add_local_input(0x80).unwrap();
+        reference_session.add_remote_input(1, 4, 0x03).unwrap();
+        reference_session.advance_frame(&mut reference).unwrap();
+
+        assert_eq!(session.frames_resimulated, 3);
+        assert_eq!(snapshots(&emulators), snapshots(&reference));
+    }
+
+    #[test]
+    fn should_not_run_past_prediction_window() {
+        let mut emulators = setup_emulators(2);
+        let mut session = RollbackSession::new(2, 1);
+        session.max_prediction_frames = 2;
+
+        for _ in 0..2 {
+            session.add_local_input(0).unwrap();
+            session.advance_frame(&mut emulators).unwrap();
+        }
+        session.add_local_input(0).unwrap();
+        assert_eq!(session.advance_frame(&mut emulators).unwrap_err().kind(), ErrorKind::WouldBlock);
+
+        session.add_remote_input(0, 0, 0).unwrap();
+        session.advance_frame(&mut emulators).unwrap();
+        assert_eq!(session.current_frame(), 3);
+        assert_eq!(session.confirmed_frame(), 1);
+    }
+}
@@ -0,0 +1,140 @@
+use crate::apu::register_log;
+use crate::cpu;
+use crate::cpu::interrupts;
+use crate::cycles::T_CYCLES_PER_FRAME;
+use crate::emulator::Emulator;
+use crate::io_trace;
+
+/*
+    Lag-reduction overclock: once a frame is complete, the CPU gets extra cycles
+    while the PPU, APU, timers and serial port stand still. Games that slow down
+    because their main loop can't finish within a frame (e.g. Zelda DX with many
+    enemies on screen) get to catch up, while the frame rate and audio pitch stay
+    unchanged since nothing else sees the extra time pass. OAM and HDMA transfers
+    keep running so code waiting on them doesn't read a locked bus.
+
+    This is not something the hardware can do. Games that time work by counting
+    instructions, or that expect a fixed amount of CPU time between two interrupts,
+    can glitch or desync (e.g. replays and netplay sessions recorded without it),
+    so it should stay off unless the player asks for it.
+*/
+pub const MAX_EXTRA_CYCLES_PER_FRAME: u32 = T_CYCLES_PER_FRAME * 4;
+
+#[derive(Debug)]
+pub struct OverclockState {
+    pub extra_cycles_per_frame: u32,
+    pub running: bool
+}
+
+pub fn initialize_overclock() -> OverclockState {
+    OverclockState {
+        extra_cycles_per_frame: 0,
+        running: false
+    }
+}
+
+pub fn set_extra_cycles_per_frame(emulator: &mut Emulator, cycles: u32) {
+    emulator.overclock.extra_cycles_per_frame = cycles.min(MAX_EXTRA_CYCLES_PER_FRAME);
+}
+
+pub fn is_running(emulator: &Emulator) -> bool {
+    emulator.overclock.running
+}
+
+pub fn step(emulator: &mut Emulator, frame_completed: bool) {
+    let extra_cycles = emulator.overclock.extra_cycles_per_frame;
+    if !frame_completed || extra_cycles == 0 {
+        return;
+    }
+
+    emulator.overclock.running = true;
+    let start_clock_cycles = emulator.cpu.clock.total_clock_cycles;
+    let mut elapsed = 0;
+
+    while elapsed < extra_cycles {
+        // Nothing can wake a halted CPU while the rest of the console is paused.
+        if emulator.cpu.halted && !interrupts::interrupts_fired(emulator) {
+            break;
+        }
+        cpu::opcodes::step(emulator);
+        elapsed = emulator.cpu.clock.total_clock_cycles.wrapping_sub(start_clock_cycles);
+    }
+
+    // The RTC, stats and callers running a cycle budget all go by the CPU clock, so the burst is taken back off it.
+    emulator.cpu.clock.total_clock_cycles = start_clock_cycles;
+    register_log::sync_clock_reference(emulator);
+    io_trace::sync_clock_reference(emulator);
+    emulator.overclock.running = false;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clock::ManualClock;
+    use crate::emulated_rtc;
+    use crate::emulator::{self, Emulator};
+    use crate::mmu::constants::*;
+    use crate::mmu::test_utils::*;
+    use super::*;
+
+    fn run_frame(emulator: &mut Emulator) {
+        let frame_count = emulator.gpu.frame_count;
+        while emulator.gpu.frame_count == frame_count {
+            emulator::step(emulator);
+        }
+    }
+
+    #[test]
+    fn should_give_cpu_extra_cycles_without_advancing_ppu_or_timers() {
+        let mut emulator = setup_emulator(CART_TYPE_ROM_ONLY, RAM_SIZE_0KB);
+        set_extra_cycles_per_frame(&mut emulator, 8192);
+        run_frame(&mut emulator);
+
+        let clock_cycles = emulator.cpu.clock.total_clock_cycles;
+        let program_counter = emulator.cpu.registers.program_counter;
+        let divider = emulator.timers.divider;
+        let ly = emulator.gpu.registers.ly;
+        step(&mut emulator, true);
+
+        // The test ROM is all NOPs, which take four T-cycles each.
+        assert!(emulator.cpu.registers.program_counter.wrapping_sub(program_counter) >= 8192 / 4);
+        assert_eq!(emulator.cpu.clock.total_clock_cycles, clock_cycles);
+        assert_eq!(emulator.timers.divider, divider);
+        assert_eq!(emulator.gpu.registers.ly, ly);
+        assert!(!is_running(&emulator));
+    }
+
+    #[test]
+    fn should_stop_early_when_cpu_halts() {
+        let mut emulator = setup_emulator(CART_TYPE_ROM_ONLY, RAM_SIZE_0KB);
+        set_extra_cycles_per_frame(&mut emulator, 8192);
+        emulator.cpu.halted = true;
+        emulator.interrupts.enabled = 0;
+
+        let program_counter = emulator.cpu.registers.program_counter;
+        step(&mut emulator, true);
+        assert_eq!(emulator.cpu.registers.program_counter, program_counter);
+    }
+
+    #[test]
+    fn should_not_advance_emulated_rtc_faster_when_overclocked() {
+        let run_frames = |extra_cycles| {
+            let mut emulator = setup_emulator(CART_TYPE_MBC3_TIMER_RAM_BATTERY, RAM_SIZE_8KB);
+            emulator::set_clock(&mut emulator, ManualClock::new(0.0).shared());
+            emulated_rtc::set_emulated_rtc(&mut emulator, Some(1.0));
+            set_extra_cycles_per_frame(&mut emulator, extra_cycles);
+            for _ in 0..10 {
+                run_frame(&mut emulator);
+            }
+            emulator.clock.current_time_millis()
+        };
+
+        assert_eq!(run_frames(MAX_EXTRA_CYCLES_PER_FRAME), run_frames(0));
+    }
+
+    #[test]
+    fn should_cap_extra_cycles() {
+        let mut emulator = setup_emulator(CART_TYPE_ROM_ONLY, RAM_SIZE_0KB);
+        set_extra_cycles_per_frame(&mut emulator, u32::MAX);
+        assert_eq!(emulator.overclock.extra_cycles_per_frame, MAX_EXTRA_CYCLES_PER_FRAME);
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+
+use crate::emulator::Emulator;
+use crate::gpu::changed_scanlines;
+use crate::gpu::constants::{BYTES_PER_COLOR, GB_SCREEN_HEIGHT, GB_SCREEN_WIDTH};
+use crate::overlay::font::{glyph, GLYPH_SIZE};
+
+pub type OverlayColor = [u8; 4];
+
+pub const WHITE: OverlayColor = [0xFF, 0xFF, 0xFF, 0xFF];
+pub const YELLOW: OverlayColor = [0xFF, 0xE0, 0x40, 0xFF];
+const SHADOW: OverlayColor = [0x00, 0x00, 0x00, 0xFF];
+
+// About three seconds at ~59.73 frames per second.
+pub const DEFAULT_MESSAGE_FRAMES: u32 = 180;
+
+const MAX_VISIBLE_MESSAGES: usize = 4;
+const MARGIN: u32 = 2;
+
+#[derive(Debug)]
+pub struct OverlayMessage {
+    pub text: String,
+    pub color: OverlayColor,
+    pub frames_remaining: u32
+}
+
+#[derive(Debug)]
+pub struct OverlayLabel {
+    pub text: String,
+    pub x: u32,
+    pub y: u32,
+    pub color: OverlayColor
+}
+
+#[derive(Debug)]
+pub struct OverlayState {
+    pub enabled: bool,
+    pub messages: Vec<OverlayMessage>,
+    pub labels: HashMap<String, OverlayLabel>
+}
+
+pub fn initialize_overlay() -> OverlayState {
+    OverlayState {
+        enabled: true,
+        messages: Vec::new(),
+        labels: HashMap::new()
+    }
+}
+
+pub fn show_message(emulator: &mut Emulator, text: &str, frames: u32) {
+    emulator.overlay.messages.push(OverlayMessage {
+        text: text.to_string(),
+        color: WHITE,
+        frames_remaining: frames
+    });
+
+    if emulator.overlay.messages.len() > MAX_VISIBLE_MESSAGES {
+        emulator.overlay.messages.remove(0);
+    }
+}
+
+pub fn set_label(emulator: &mut Emulator, label_id: &str, x: u32, y: u32, text: &str) {
+    emulator.overlay.labels.insert(label_id.to_string(), OverlayLabel {
+        text: text.to_string(),
+        x,
+        y,
+        color: YELLOW
+    });
+}
+
+pub fn remove_label(emulator: &mut Emulator, label_id: &str) {
+    emulator.overlay.labels.remove(label_id);
+}
+
+#[cfg(feature = "internals")]
+pub fn clear_overlay(emulator: &mut Emulator) {
+    emulator.overlay.messages.clear();
+    emulator.overlay.labels.clear();
+}
+
+fn set_pixel(frame_buffer: &mut [u8], x: i32, y: i32, color: &OverlayColor) {
+    if x >= 0 && y >= 0 && (x as u32) < GB_SCREEN_WIDTH && (y as u32) < GB_SCREEN_HEIGHT {
+        let index = ((y as u32 * GB_SCREEN_WIDTH + x as u32) * BYTES_PER_COLOR) as usize;
+        if let Some(pixel) = frame_buffer.get_mut(index..index + BYTES_PER_COLOR as usize) {
+            pixel.copy_from_slice(color);
+        }
+    }
+}
+
+fn draw_glyph(frame_buffer: &mut [u8], x: i32, y: i32, character: char, color: &OverlayColor) {
+    let rows = glyph(character);
+
+    // A drop shadow keeps text legible on top of both light and dark backgrounds.
+    for (shadow_offset, pixel_color) in [(1, &SHADOW), (0, color)] {
+        for (row_index, row) in rows.iter().enumerate() {
+            for column in 0..GLYPH_SIZE {
+                if row & (1 << column) != 0 {
+                    set_pixel(frame_buffer,
+                        x + column as i32 + shadow_offset,
+                        y + row_index as i32 + shadow_offset,
+                        pixel_color);
+                }
+            }
+        }
+    }
+}
+
+pub fn draw_text(frame_buffer: &mut [u8], x: i32, y: i32, text: &str, color: &OverlayColor) {
+    for (index, character) in text.chars().enumerate() {
+        draw_glyph(frame_buffer, x + (index as u32 * GLYPH_SIZE) as i32, y, character, color);
+    }
+}
+
+/*
+    Called once per frame right before the frame buffer is handed to the frontend.
+    The PPU redraws every line of the next frame, so drawing directly into the
+    frame buffer doesn't leak the overlay into emulated state.
+*/
+pub fn draw_overlay(emulator: &mut Emulator) {
+    if !emulator.overlay.enabled {
+        return;
+    }
+
+    if !emulator.overlay.labels.is_empty() || !emulator.overlay.messages.is_empty() {
+        changed_scanlines::mark_all_changed(&mut emulator.gpu.changed_scanlines);
+    }
+
+    let overlay = &mut emulator.overlay;
+    let frame_buffer = &mut emulator.gpu.frame_buffer;
+
+    for label in overlay.labels.values() {
+        draw_text(frame_buffer, label.x as i32, label.y as i32, &label.text, &label.color);
+    }
+
+    let mut y = (GB_SCREEN_HEIGHT - MARGIN - GLYPH_SIZE) as i32;
+    for message in overlay.messages.iter().rev() {
+        draw_text(frame_buffer, MARGIN as i32, y, &message.text, &message.color);
+        y -= (GLYPH_SIZE + 1) as i32;
+    }
+
+    for message in overlay.messages.iter_mut() {
+        message.frames_remaining = message.frames_remaining.saturating_sub(1);
+    }
+    overlay.messages.retain(|message| message.frames_remaining > 0);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::initialize_screenless_emulator;
+    use super::*;
+
+    fn pixel_at(emulator: &Emulator, x: u32, y: u32) -> &[u8] {
+        let index = ((y * GB_SCREEN_WIDTH + x) * BYTES_PER_COLOR) as usize;
+        &emulator.gpu.frame_buffer[index..index + 4]
+    }
+
+    #[test]
+    fn should_draw_glyph_pixels_into_frame_buffer() {
+        let mut emulator = initialize_screenless_emulator();
+        set_label(&mut emulator, "fps", 0, 0, "|");
+        draw_overlay(&mut emulator);
+
+        // The first row of '|' sets bits three and four.
+        assert_eq!(pixel_at(&emulator, 3, 0), YELLOW);
+        assert_eq!(pixel_at(&emulator, 4, 0), YELLOW);
+        assert_eq!(pixel_at(&emulator, 5, 1), SHADOW);
+        assert_eq!(pixel_at(&emulator, 0, 0), [0xFF, 0xFF, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn should_clip_text_drawn_off_screen() {
+        let mut emulator = initialize_screenless_emulator();
+        set_label(&mut emulator, "fps", 156, 140, "WWWW");
+        draw_overlay(&mut emulator);
+        assert_eq!(emulator.gpu.frame_buffer.len(), (GB_SCREEN_WIDTH * GB_SCREEN_HEIGHT * BYTES_PER_COLOR) as usize);
+    }
+
+    #[test]
+    fn should_expire_messages_after_their_duration() {
+        let mut emulator = initialize_screenless_emulator();
+        show_message(&mut emulator, "State saved", 2);
+
+        draw_overlay(&mut emulator);
+        assert_eq!(emulator.overlay.messages.len(), 1);
+
+        draw_overlay(&mut emulator);
+        assert!(emulator.overlay.messages.is_empty());
+    }
+
+    #[test]
+    fn should_limit_number_of_visible_messages() {
+        let mut emulator = initialize_screenless_emulator();
+        for index in 0..6 {
+            show_message(&mut emulator, &format!("Message {}", index), DEFAULT_MESSAGE_FRAMES);
+        }
+        assert_eq!(emulator.overlay.messages.len(), MAX_VISIBLE_MESSAGES);
+        assert_eq!(emulator.overlay.messages[0].text, "Message 2");
+    }
+
+    #[test]
+    fn should_not_draw_when_disabled() {
+        let mut emulator = initialize_screenless_emulator();
+        emulator.overlay.enabled = false;
+        set_label(&mut emulator, "fps", 0, 0, "|");
+        draw_overlay(&mut emulator);
+        assert_eq!(pixel_at(&emulator, 3, 0), [0xFF, 0xFF, 0xFF, 0xFF]);
+    }
+}
+
+pub mod font;
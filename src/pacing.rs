@@ -0,0 +1,114 @@
+use crate::cycles::{T_CYCLES_PER_FRAME, T_CYCLES_PER_SECOND};
+use crate::emulator::HardwareModel;
+
+// The original Super Game Boy derives its clock from the SNES, running it about 2.4% fast.
+const SGB_CPU_CLOCK_RATE: f64 = 4295454.0;
+
+// When the host falls further behind than this, frames are dropped instead of caught up.
+pub const DEFAULT_MAX_CATCH_UP_FRAMES: u32 = 4;
+
+pub fn frame_rate(model: HardwareModel) -> f64 {
+    match model {
+        HardwareModel::SGB => SGB_CPU_CLOCK_RATE / T_CYCLES_PER_FRAME as f64,
+        _ => T_CYCLES_PER_SECOND as f64 / T_CYCLES_PER_FRAME as f64
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PacingStep {
+    // Nothing is due yet, so the frontend should sleep for this long.
+    Sleep { millis: f64 },
+    RunFrames { frames: u32 }
+}
+
+/*
+    Paces frames against a host clock for frontends that don't sync to audio or
+    vsync. Deadlines are kept on an absolute schedule from the first frame, rather
+    than sleeping a frame's duration after each one, so oversleeping or a slow frame
+    is made up on the next ones instead of the game slowly drifting behind. If the
+    host stalls for longer than max_catch_up_frames (e.g. the window was dragged),
+    the schedule restarts from now rather than fast forwarding to catch up.
+*/
+#[derive(Debug, Clone)]
+pub struct FramePacer {
+    pub frame_duration_millis: f64,
+    pub max_catch_up_frames: u32,
+    pub dropped_frames: u64,
+    next_frame_millis: Option<f64>
+}
+
+impl FramePacer {
+    pub fn new(frames_per_second: f64) -> FramePacer {
+        FramePacer {
+            frame_duration_millis: 1000.0 / frames_per_second,
+            max_catch_up_frames: DEFAULT_MAX_CATCH_UP_FRAMES,
+            dropped_frames: 0,
+            next_frame_millis: None
+        }
+    }
+
+    pub fn for_model(model: HardwareModel) -> FramePacer {
+        FramePacer::new(frame_rate(model))
+    }
+
+    // Restarts the schedule, e.g. after unpausing.
+    pub fn reset(&mut self) {
+        self.next_frame_millis = None;
+    }
+
+    pub fn next_step(&mut self, now_millis: f64) -> PacingStep {
+        let next_frame_millis = *self.next_frame_millis.get_or_insert(now_millis);
+
+        if now_millis < next_frame_millis {
+            return PacingStep::Sleep { millis: next_frame_millis - now_millis };
+        }
+
+        let due_frames = ((now_millis - next_frame_millis) / self.frame_duration_millis).floor() as u64 + 1;
+        let frames = if due_frames > self.max_catch_up_frames as u64 {
+            self.dropped_frames += due_frames - 1;
+            self.next_frame_millis = Some(now_millis + self.frame_duration_millis);
+            1
+        }
+        else {
+            self.next_frame_millis = Some(next_frame_millis + due_frames as f64 * self.frame_duration_millis);
+            due_frames as u32
+        };
+
+        PacingStep::RunFrames { frames }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_use_faster_frame_rate_for_super_game_boy() {
+        assert!((frame_rate(HardwareModel::DMG) - 59.7275).abs() < 0.0001);
+        assert!((frame_rate(HardwareModel::SGB) - 61.1679).abs() < 0.0001);
+        assert_eq!(frame_rate(HardwareModel::SGB2), frame_rate(HardwareModel::CGB));
+    }
+
+    #[test]
+    fn should_keep_absolute_schedule_when_oversleeping() {
+        let mut pacer = FramePacer::new(50.0);
+
+        assert_eq!(pacer.next_step(0.0), PacingStep::RunFrames { frames: 1 });
+        assert_eq!(pacer.next_step(5.0), PacingStep::Sleep { millis: 15.0 });
+        // Waking up late only shortens the next wait.
+        assert_eq!(pacer.next_step(23.0), PacingStep::RunFrames { frames: 1 });
+        assert_eq!(pacer.next_step(23.0), PacingStep::Sleep { millis: 17.0 });
+        assert_eq!(pacer.next_step(61.0), PacingStep::RunFrames { frames: 2 });
+        assert_eq!(pacer.next_step(61.0), PacingStep::Sleep { millis: 19.0 });
+    }
+
+    #[test]
+    fn should_drop_frames_after_long_stall() {
+        let mut pacer = FramePacer::new(50.0);
+        pacer.next_step(0.0);
+
+        assert_eq!(pacer.next_step(1000.0), PacingStep::RunFrames { frames: 1 });
+        assert_eq!(pacer.dropped_frames, 49);
+        assert_eq!(pacer.next_step(1000.0), PacingStep::Sleep { millis: 20.0 });
+    }
+}
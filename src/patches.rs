@@ -0,0 +1,321 @@
+use std::io;
+
+/*
+    ROM hacks and translations are distributed as IPS or BPS patches against the
+    original ROM. Patches are applied to the ROM buffer before it's loaded, so the
+    rest of the emulator only ever sees the patched ROM.
+*/
+
+const IPS_HEADER: &[u8] = b"PATCH";
+const IPS_FOOTER: &[u8] = b"EOF";
+const BPS_HEADER: &[u8] = b"BPS1";
+const BPS_FOOTER_LENGTH: usize = 12;
+
+fn invalid_patch_error(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Invalid patch: {}", message))
+}
+
+pub fn crc32(buffer: &[u8]) -> u32 {
+    let mut crc = 0xFFFFFFFF;
+    for byte in buffer {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB88320 & mask);
+        }
+    }
+    !crc
+}
+
+pub fn apply_patch(rom: &[u8], patch: &[u8]) -> io::Result<Vec<u8>> {
+    if patch.starts_with(IPS_HEADER) {
+        apply_ips_patch(rom, patch)
+    }
+    else if patch.starts_with(BPS_HEADER) {
+        apply_bps_patch(rom, patch)
+    }
+    else {
+        Err(invalid_patch_error("unrecognized patch format"))
+    }
+}
+
+struct PatchReader<'a> {
+    buffer: &'a [u8],
+    position: usize
+}
+
+impl<'a> PatchReader<'a> {
+    fn new(buffer: &'a [u8], position: usize) -> PatchReader<'a> {
+        PatchReader { buffer, position }
+    }
+
+    fn read_bytes(&mut self, length: usize) -> io::Result<&'a [u8]> {
+        let end = self.position.checked_add(length)
+            .filter(|end| *end <= self.buffer.len())
+            .ok_or_else(|| invalid_patch_error("unexpected end of patch"))?;
+        let bytes = &self.buffer[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> io::Result<u8> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_big_endian(&mut self, length: usize) -> io::Result<usize> {
+        Ok(self.read_bytes(length)?.iter().fold(0, |value, byte| (value << 8) | *byte as usize))
+    }
+
+    // BPS numbers are variable length, with each continuation byte offset so encodings are unique.
+    fn read_number(&mut self) -> io::Result<usize> {
+        let mut value: usize = 0;
+        let mut shift: usize = 1;
+        loop {
+            let byte = self.read_u8()?;
+            value = ((byte & 0x7F) as usize).checked_mul(shift)
+                .and_then(|increment| value.checked_add(increment))
+                .ok_or_else(|| invalid_patch_error("number too large"))?;
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift = shift.checked_shl(7).ok_or_else(|| invalid_patch_error("number too large"))?;
+            value = value.checked_add(shift).ok_or_else(|| invalid_patch_error("number too large"))?;
+        }
+    }
+}
+
+pub fn apply_ips_patch(rom: &[u8], patch: &[u8]) -> io::Result<Vec<u8>> {
+    if !patch.starts_with(IPS_HEADER) {
+        return Err(invalid_patch_error("missing IPS header"));
+    }
+
+    let mut patched_rom = rom.to_vec();
+    let mut reader = PatchReader::new(patch, IPS_HEADER.len());
+
+    loop {
+        let offset_bytes = reader.read_bytes(3)?;
+        if offset_bytes == IPS_FOOTER {
+            break;
+        }
+        let offset = offset_bytes.iter().fold(0, |value, byte| (value << 8) | *byte as usize);
+
+        let length = reader.read_big_endian(2)?;
+        let (length, data) = if length == 0 {
+            // Run-length encoded record, repeating a single byte.
+            let run_length = reader.read_big_endian(2)?;
+            let value = reader.read_u8()?;
+            (run_length, vec![value; run_length])
+        }
+        else {
+            (length, reader.read_bytes(length)?.to_vec())
+        };
+
+        if patched_rom.len() < offset + length {
+            patched_rom.resize(offset + length, 0);
+        }
+        patched_rom[offset..offset + length].copy_from_slice(&data);
+    }
+
+    // Some patches follow the footer with the size to truncate the ROM to.
+    if let Ok(truncated_length) = reader.read_big_endian(3) {
+        patched_rom.truncate(truncated_length);
+    }
+
+    Ok(patched_rom)
+}
+
+fn read_footer_crc(patch: &[u8], index: usize) -> u32 {
+    let start = patch.len() - BPS_FOOTER_LENGTH + index * 4;
+    u32::from_le_bytes([patch[start], patch[start + 1], patch[start + 2], patch[start + 3]])
+}
+
+fn apply_relative_offset(position: usize, encoded_offset: usize) -> io::Result<usize> {
+    let distance = encoded_offset >> 1;
+    let result = if encoded_offset & 1 != 0 { position.checked_sub(distance) } else { position.checked_add(distance) };
+    result.ok_or_else(|| invalid_patch_error("copy offset out of range"))
+}
+
+pub fn apply_bps_patch(rom: &[u8], patch: &[u8]) -> io::Result<Vec<u8>> {
+    if !patch.starts_with(BPS_HEADER) || patch.len() < BPS_HEADER.len() + BPS_FOOTER_LENGTH {
+        return Err(invalid_patch_error("missing BPS header"));
+    }
+
+    if crc32(&patch[..patch.len() - 4]) != read_footer_crc(patch, 2) {
+        return Err(invalid_patch_error("patch checksum mismatch"));
+    }
+
+    let mut reader = PatchReader::new(&patch[..patch.len() - BPS_FOOTER_LENGTH], BPS_HEADER.len());
+    let source_size = reader.read_number()?;
+    let target_size = reader.read_number()?;
+    let metadata_size = reader.read_number()?;
+    reader.read_bytes(metadata_size)?;
+
+    if rom.len() != source_size || crc32(rom) != read_footer_crc(patch, 0) {
+        return Err(invalid_patch_error("patch doesn't match this ROM"));
+    }
+
+    let mut target = Vec::new();
+    let mut source_position = 0;
+    let mut target_position = 0;
+
+    while reader.position < reader.buffer.len() {
+        let command = reader.read_number()?;
+        let length = (command >> 2) + 1;
+
+        if target.len() + length > target_size {
+            return Err(invalid_patch_error("output larger than target size"));
+        }
+
+        match command & 3 {
+            0 => {
+                let bytes = rom.get(target.len()..target.len() + length)
+                    .ok_or_else(|| invalid_patch_error("source read out of range"))?;
+                target.extend_from_slice(bytes);
+            },
+            1 => {
+                target.extend_from_slice(reader.read_bytes(length)?);
+            },
+            2 => {
+                source_position = apply_relative_offset(source_position, reader.read_number()?)?;
+                let bytes = rom.get(source_position..source_position + length)
+                    .ok_or_else(|| invalid_patch_error("source copy out of range"))?;
+                target.extend_from_slice(bytes);
+                source_position += length;
+            },
+            _ => {
+                target_position = apply_relative_offset(target_position, reader.read_number()?)?;
+                if target_position >= target.len() {
+                    return Err(invalid_patch_error("target copy out of range"));
+                }
+                // Copies byte by byte, as the source range may overlap the bytes being written.
+                for _ in 0..length {
+                    target.push(target[target_position]);
+                    target_position += 1;
+                }
+            }
+        }
+    }
+
+    if target.len() != target_size || crc32(&target) != read_footer_crc(patch, 1) {
+        return Err(invalid_patch_error("patched ROM checksum mismatch"));
+    }
+
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_number(mut value: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        loop {
+            let byte = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                bytes.push(byte | 0x80);
+                return bytes;
+            }
+            bytes.push(byte);
+            value -= 1;
+        }
+    }
+
+    const SOURCE_READ: usize = 0;
+    const TARGET_READ: usize = 1;
+    const SOURCE_COPY: usize = 2;
+    const TARGET_COPY: usize = 3;
+
+    fn encode_action(action: usize, length: usize) -> Vec<u8> {
+        encode_number(((length - 1) << 2) | action)
+    }
+
+    fn build_bps_patch(source: &[u8], target: &[u8], actions: &[u8]) -> Vec<u8> {
+        let mut patch = BPS_HEADER.to_vec();
+        patch.extend(encode_number(source.len()));
+        patch.extend(encode_number(target.len()));
+        patch.extend(encode_number(0));
+        patch.extend_from_slice(actions);
+        patch.extend(crc32(source).to_le_bytes());
+        patch.extend(crc32(target).to_le_bytes());
+        patch.extend(crc32(&patch).to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn should_calculate_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    }
+
+    #[test]
+    fn should_apply_ips_records() {
+        let rom = vec![0; 8];
+        let mut patch = IPS_HEADER.to_vec();
+        patch.extend_from_slice(&[0x00, 0x00, 0x02, 0x00, 0x02, 0xAA, 0xBB]);
+        patch.extend_from_slice(&[0x00, 0x00, 0x06, 0x00, 0x00, 0x00, 0x04, 0xCC]);
+        patch.extend_from_slice(IPS_FOOTER);
+
+        let patched_rom = apply_patch(&rom, &patch).unwrap();
+        assert_eq!(patched_rom, vec![0x00, 0x00, 0xAA, 0xBB, 0x00, 0x00, 0xCC, 0xCC, 0xCC, 0xCC]);
+    }
+
+    #[test]
+    fn should_truncate_rom_after_ips_footer() {
+        let mut patch = IPS_HEADER.to_vec();
+        patch.extend_from_slice(IPS_FOOTER);
+        patch.extend_from_slice(&[0x00, 0x00, 0x04]);
+        assert_eq!(apply_patch(&[1; 8], &patch).unwrap(), vec![1; 4]);
+    }
+
+    #[test]
+    fn should_reject_truncated_ips_patch() {
+        let mut patch = IPS_HEADER.to_vec();
+        patch.extend_from_slice(&[0x00, 0x00, 0x02, 0x00, 0x04, 0xAA]);
+        assert!(apply_patch(&[0; 8], &patch).is_err());
+    }
+
+    #[test]
+    fn should_apply_bps_actions() {
+        let source = b"Hello World".to_vec();
+        let target = b"Hello Hello GB".to_vec();
+
+        let mut actions = Vec::new();
+        // Source read "Hello ", source copy "Hello " from the start, target read "GB".
+        actions.extend(encode_action(SOURCE_READ, 6));
+        actions.extend(encode_action(SOURCE_COPY, 6));
+        actions.extend(encode_number(0));
+        actions.extend(encode_action(TARGET_READ, 2));
+        actions.extend_from_slice(b"GB");
+
+        let patch = build_bps_patch(&source, &target, &actions);
+        assert_eq!(apply_patch(&source, &patch).unwrap(), target);
+    }
+
+    #[test]
+    fn should_apply_overlapping_bps_target_copy() {
+        let source = vec![0; 4];
+        let target = b"ABABABAB".to_vec();
+
+        let mut actions = Vec::new();
+        actions.extend(encode_action(TARGET_READ, 2));
+        actions.extend_from_slice(b"AB");
+        actions.extend(encode_action(TARGET_COPY, 6));
+        actions.extend(encode_number(0));
+
+        let patch = build_bps_patch(&source, &target, &actions);
+        assert_eq!(apply_patch(&source, &patch).unwrap(), target);
+    }
+
+    #[test]
+    fn should_reject_bps_patch_for_different_rom() {
+        let source = b"Hello World".to_vec();
+        let target = b"Hello".to_vec();
+        let patch = build_bps_patch(&source, &target, &encode_action(SOURCE_READ, 5));
+
+        assert!(apply_patch(b"Hello There", &patch).is_err());
+
+        let mut corrupted_patch = patch.clone();
+        corrupted_patch[5] ^= 0xFF;
+        assert!(apply_patch(&source, &corrupted_patch).is_err());
+    }
+}
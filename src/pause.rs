@@ -0,0 +1,85 @@
+use crate::emulator::Emulator;
+use crate::gpu;
+
+#[derive(Debug, Default)]
+pub struct PauseState {
+    pub requested: bool,
+    pub paused: bool
+}
+
+pub fn initialize_pause() -> PauseState {
+    PauseState::default()
+}
+
+/*
+    Pausing mid-frame would leave a half-drawn frame buffer and an audio queue that
+    doesn't line up with it, so a request only takes effect once the next frame is
+    complete. With the LCD off there's no frame in progress and it applies right away.
+*/
+pub fn request_pause(emulator: &mut Emulator) {
+    emulator.pause.requested = true;
+}
+
+pub fn resume(emulator: &mut Emulator) {
+    emulator.pause.requested = false;
+    emulator.pause.paused = false;
+}
+
+pub fn is_paused(emulator: &Emulator) -> bool {
+    emulator.pause.paused
+}
+
+pub fn step(emulator: &mut Emulator, frame_completed: bool) {
+    if emulator.pause.requested && (frame_completed || !gpu::lcd_enabled(emulator)) {
+        emulator.pause.requested = false;
+        emulator.pause.paused = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator;
+    use crate::mmu;
+    use crate::mmu::constants::*;
+    use crate::mmu::test_utils::*;
+    use super::*;
+
+    #[test]
+    fn should_pause_at_next_vblank() {
+        let mut emulator = setup_emulator(CART_TYPE_MBC1, RAM_SIZE_0KB);
+        let frame_count = emulator.gpu.frame_count;
+        request_pause(&mut emulator);
+
+        while !is_paused(&emulator) {
+            emulator::step(&mut emulator);
+        }
+
+        assert_eq!(emulator.gpu.frame_count, frame_count + 1);
+        assert_eq!(emulator.gpu.registers.ly, 144);
+    }
+
+    #[test]
+    fn should_not_advance_while_paused() {
+        let mut emulator = setup_emulator(CART_TYPE_MBC1, RAM_SIZE_0KB);
+        emulator.pause.paused = true;
+        let program_counter = emulator.cpu.registers.program_counter;
+
+        emulator::step(&mut emulator);
+        let (left_samples, _) = emulator::step_until_next_audio_buffer(&mut emulator);
+        assert!(left_samples.is_empty());
+        assert_eq!(emulator.cpu.registers.program_counter, program_counter);
+
+        resume(&mut emulator);
+        emulator::step(&mut emulator);
+        assert_ne!(emulator.cpu.registers.program_counter, program_counter);
+    }
+
+    #[test]
+    fn should_pause_immediately_with_lcd_off() {
+        let mut emulator = setup_emulator(CART_TYPE_MBC1, RAM_SIZE_0KB);
+        mmu::write_byte(&mut emulator, 0xFF40, 0x00);
+        request_pause(&mut emulator);
+        emulator::step(&mut emulator);
+        assert!(is_paused(&emulator));
+    }
+}
@@ -0,0 +1,11 @@
+// Everything a typical frontend needs, without importing any internal modules.
+pub use crate::audio_sink::{AudioSink, SampleBuffer, SharedSampleBuffer};
+pub use crate::clock::{Clock, ManualClock, SharedClock, SystemClock};
+pub use crate::compatibility::{run_compatibility_check, CompatibilityReport};
+pub use crate::core::Core;
+pub use crate::emulator::{AccuracyProfile, BarcodeScanner, CartridgeEffects, CartridgeHeader, ColorCorrection, CyclesRun, FrameMetadata, GameConfig, HardwareModel, HleBootOptions, InputPoller, IoRegister, JoypadState, RTCState, ScanlineRegisters, SerialDevice, StateSnapshot, Thumbnail, UnmappedWrite};
+pub use crate::gameboy::GameBoy;
+pub use crate::input_mapping::{key_changes, GamepadButton, GamepadMapping};
+pub use crate::keys::Key;
+pub use crate::netplay::{as_input_mask, Rollback, RollbackSession};
+pub use crate::pacing::{FramePacer, PacingStep};
@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+
+use crate::cpu;
+use crate::emulator::Emulator;
+use crate::symbols;
+
+/*
+    Exact profiler for homebrew developers. Every instruction's clock cycles are
+    attributed to its banked address while the profiler runs, and reports group them
+    by function when a symbol file is loaded. A function is the closest label before
+    an address, with RGBDS local labels (e.g. "Main.loop") folded into their parent,
+    so a hot loop shows up under the routine that contains it. Cycles spent halted
+    are reported separately, as they're time the game spent idle rather than code.
+*/
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProfileCounters {
+    pub cycles: u64,
+    pub instructions: u64
+}
+
+#[derive(Debug, Default)]
+pub struct ProfilerState {
+    pub enabled: bool,
+    // Counters by bank and address.
+    pub counters: HashMap<(u16, u16), ProfileCounters>,
+    pub halted_cycles: u64
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileEntry {
+    pub name: String,
+    pub bank: u16,
+    pub address: u16,
+    pub cycles: u64,
+    pub instructions: u64
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileReport {
+    // Sorted from most to fewest cycles.
+    pub entries: Vec<ProfileEntry>,
+    pub total_cycles: u64,
+    pub halted_cycles: u64
+}
+
+pub struct InstructionStart {
+    bank: u16,
+    address: u16,
+    halted: bool,
+    total_clock_cycles: u32
+}
+
+pub fn initialize_profiler() -> ProfilerState {
+    ProfilerState::default()
+}
+
+pub fn start_profiler(emulator: &mut Emulator) {
+    emulator.profiler.enabled = true;
+}
+
+pub fn stop_profiler(emulator: &mut Emulator) {
+    emulator.profiler.enabled = false;
+}
+
+#[cfg(feature = "internals")]
+pub fn reset_profiler(emulator: &mut Emulator) {
+    emulator.profiler.counters.clear();
+    emulator.profiler.halted_cycles = 0;
+}
+
+// Called before each instruction, noting where it is so its cycles can be attributed once it's done.
+pub fn instruction_start(emulator: &Emulator) -> Option<InstructionStart> {
+    if !emulator.profiler.enabled {
+        return None;
+    }
+
+    let address = cpu::current_instruction_address(emulator);
+    Some(InstructionStart {
+        bank: symbols::current_bank(emulator, address),
+        address,
+        halted: emulator.cpu.halted,
+        total_clock_cycles: emulator.cpu.clock.total_clock_cycles
+    })
+}
+
+pub fn step(emulator: &mut Emulator, start: Option<InstructionStart>) {
+    if let Some(start) = start {
+        let cycles = emulator.cpu.clock.total_clock_cycles.wrapping_sub(start.total_clock_cycles) as u64;
+        let profiler = &mut emulator.profiler;
+        if start.halted {
+            profiler.halted_cycles += cycles;
+        }
+        else {
+            let counters = profiler.counters.entry((start.bank, start.address)).or_default();
+            counters.cycles += cycles;
+            counters.instructions += 1;
+        }
+    }
+}
+
+fn function_name(name: &str) -> &str {
+    name.split('.').next().unwrap_or(name)
+}
+
+pub fn profile_report(emulator: &Emulator) -> ProfileReport {
+    let mut grouped: HashMap<(u16, u16), ProfileEntry> = HashMap::new();
+
+    for ((bank, address), counters) in &emulator.profiler.counters {
+        let function = symbols::symbol_before(&emulator.symbols, *bank, *address)
+            .map(|symbol| function_name(&symbol.name))
+            .and_then(|name| symbols::address_of(&emulator.symbols, name).map(|location| (name, location)));
+
+        let (name, (bank, address)) = match function {
+            Some((name, location)) => (name.to_string(), location),
+            None => (format!("{:02X}:{:04X}", bank, address), (*bank, *address))
+        };
+
+        let entry = grouped.entry((bank, address)).or_insert_with(|| ProfileEntry { name, bank, address, cycles: 0, instructions: 0 });
+        entry.cycles += counters.cycles;
+        entry.instructions += counters.instructions;
+    }
+
+    let mut entries: Vec<ProfileEntry> = grouped.into_values().collect();
+    entries.sort_by(|a, b| b.cycles.cmp(&a.cycles).then((a.bank, a.address).cmp(&(b.bank, b.address))));
+
+    let halted_cycles = emulator.profiler.halted_cycles;
+    let total_cycles = entries.iter().map(|entry| entry.cycles).sum::<u64>() + halted_cycles;
+    ProfileReport { entries, total_cycles, halted_cycles }
+}
+
+// A plain text table of the hottest functions, for logging or showing in a frontend.
+pub fn format_profile_report(report: &ProfileReport, limit: usize) -> Vec<String> {
+    let percentage = |cycles: u64| if report.total_cycles == 0 { 0.0 } else { cycles as f64 * 100.0 / report.total_cycles as f64 };
+
+    let mut lines: Vec<String> = report.entries.iter().take(limit)
+        .map(|entry| format!("{:>6.2}% {:>12} cycles {:>10} instructions  {}", percentage(entry.cycles), entry.cycles, entry.instructions, entry.name))
+        .collect();
+    lines.push(format!("{:>6.2}% {:>12} cycles halted", percentage(report.halted_cycles), report.halted_cycles));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::{self, initialize_screenless_emulator};
+    use crate::mmu;
+    use crate::mmu::constants::*;
+    use crate::mmu::effects::empty_cartridge_effects;
+    use crate::mmu::test_utils::*;
+    use super::*;
+
+    const SYMBOLS: &str = "\
+00:0150 Main
+00:0153 Main.loop
+00:0160 Delay
+";
+
+    fn setup_emulator() -> Emulator {
+        let mut emulator = initialize_screenless_emulator();
+        let mut rom = build_rom(CART_TYPE_MBC1, ROM_SIZE_64KB, RAM_SIZE_0KB);
+
+        // Main: ld b,$00 / .loop: call Delay; dec b; jr .loop ... Delay: ret
+        rom[0x150..0x158].copy_from_slice(&[0x06, 0x00, 0x00, 0xCD, 0x60, 0x01, 0x05, 0x18]);
+        rom[0x158] = 0xFA;
+        rom[0x160] = 0xC9;
+        mmu::load_rom_buffer(&mut emulator.memory, rom, empty_cartridge_effects()).unwrap();
+        emulator.memory.in_bios = false;
+        emulator.cpu.registers.program_counter = 0x151;
+        emulator.cpu.registers.opcode = 0x06;
+        emulator.cpu.registers.stack_pointer = 0xDFFE;
+        emulator
+    }
+
+    fn run(emulator: &mut Emulator, instructions: usize) {
+        for _ in 0..instructions {
+            emulator::step(emulator);
+        }
+    }
+
+    #[test]
+    fn should_attribute_cycles_to_instruction_addresses() {
+        let mut emulator = setup_emulator();
+        start_profiler(&mut emulator);
+        run(&mut emulator, 6);
+
+        let counters = &emulator.profiler.counters;
+        assert_eq!(counters[&(0, 0x150)], ProfileCounters { cycles: 8, instructions: 1 });
+        assert_eq!(counters[&(0, 0x153)], ProfileCounters { cycles: 24, instructions: 1 });
+        assert_eq!(counters[&(0, 0x160)], ProfileCounters { cycles: 16, instructions: 1 });
+    }
+
+    #[test]
+    fn should_group_report_by_function_when_symbols_loaded() {
+        let mut emulator = setup_emulator();
+        crate::symbols::load_symbols(&mut emulator, SYMBOLS).unwrap();
+        start_profiler(&mut emulator);
+        run(&mut emulator, 2 + 5 * 4);
+
+        let report = profile_report(&emulator);
+        let names: Vec<&str> = report.entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, vec!["Main", "Delay"]);
+        assert_eq!(report.entries[0].instructions, 2 + 5 * 3);
+        assert_eq!(report.entries[1].instructions, 5);
+        assert_eq!(report.total_cycles, report.entries.iter().map(|entry| entry.cycles).sum::<u64>());
+    }
+
+    #[test]
+    fn should_report_addresses_without_symbols() {
+        let mut emulator = setup_emulator();
+        start_profiler(&mut emulator);
+        run(&mut emulator, 1);
+        stop_profiler(&mut emulator);
+        run(&mut emulator, 10);
+
+        let report = profile_report(&emulator);
+        assert_eq!(report.entries.len(), 1);
+        assert_eq!(report.entries[0].name, "00:0150");
+        assert_eq!(format_profile_report(&report, 10).len(), 2);
+    }
+}
@@ -0,0 +1,305 @@
+use std::collections::VecDeque;
+use std::io::{self, Error};
+#[cfg(any(test, feature = "internals"))]
+use std::io::ErrorKind;
+
+use crate::emulator::Emulator;
+use crate::keys::Key;
+use crate::savestate::{self, StateWriter};
+#[cfg(any(test, feature = "internals"))]
+use crate::{emulator, keys, savestate::StateReader};
+
+const REPLAY_MAGIC: &[u8; 4] = b"RBRP";
+const REPLAY_VERSION: u8 = 1;
+
+// Roughly ten seconds at the Game Boy's refresh rate of ~59.73 frames per second.
+pub const DEFAULT_REPLAY_WINDOW_FRAMES: u32 = 598;
+
+const KEYFRAMES_RETAINED: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReplayInput {
+    pub step: u64,
+    pub key: Key,
+    pub pressed: bool
+}
+
+struct Keyframe {
+    state: Vec<u8>,
+    step: u64
+}
+
+pub struct ReplayState {
+    pub enabled: bool,
+    pub window_frames: u32,
+    steps: u64,
+    frames_since_keyframe: u32,
+    keyframes: VecDeque<Keyframe>,
+    inputs: VecDeque<ReplayInput>
+}
+
+pub fn initialize_replay() -> ReplayState {
+    ReplayState {
+        enabled: false,
+        window_frames: DEFAULT_REPLAY_WINDOW_FRAMES,
+        steps: 0,
+        frames_since_keyframe: 0,
+        keyframes: VecDeque::new(),
+        inputs: VecDeque::new()
+    }
+}
+
+fn keyframe_interval(replay: &ReplayState) -> u32 {
+    (replay.window_frames / 2).max(1)
+}
+
+fn capture_keyframe(emulator: &mut Emulator) {
+    let state = savestate::encode_state(emulator);
+    let replay = &mut emulator.replay;
+
+    replay.keyframes.push_back(Keyframe { state, step: replay.steps });
+    replay.frames_since_keyframe = 0;
+
+    /*
+        Keyframes are taken every half window and the oldest one is always the starting
+        point of an export, so keeping three guarantees at least a full window of history.
+    */
+    while replay.keyframes.len() > KEYFRAMES_RETAINED {
+        replay.keyframes.pop_front();
+    }
+
+    if let Some(oldest) = replay.keyframes.front() {
+        let oldest_step = oldest.step;
+        while replay.inputs.front().is_some_and(|input| input.step < oldest_step) {
+            replay.inputs.pop_front();
+        }
+    }
+}
+
+pub fn enable_replay_buffer(emulator: &mut Emulator, window_frames: u32) {
+    emulator.replay = initialize_replay();
+    emulator.replay.enabled = true;
+    emulator.replay.window_frames = window_frames;
+    capture_keyframe(emulator);
+}
+
+pub fn disable_replay_buffer(emulator: &mut Emulator) {
+    emulator.replay = initialize_replay();
+}
+
+pub fn record_input(emulator: &mut Emulator, key: Key, pressed: bool) {
+    let replay = &mut emulator.replay;
+    if replay.enabled {
+        replay.inputs.push_back(ReplayInput { step: replay.steps, key, pressed });
+    }
+}
+
+pub fn step(emulator: &mut Emulator, frame_completed: bool) {
+    if emulator.replay.enabled {
+        emulator.replay.steps += 1;
+
+        if frame_completed {
+            emulator.replay.frames_since_keyframe += 1;
+            if emulator.replay.frames_since_keyframe >= keyframe_interval(&emulator.replay) {
+                capture_keyframe(emulator);
+            }
+        }
+    }
+}
+
+fn as_key_byte(key: Key) -> u8 {
+    match key {
+        Key::Down => 0,
+        Key::Up => 1,
+        Key::Left => 2,
+        Key::Right => 3,
+        Key::Start => 4,
+        Key::Select => 5,
+        Key::B => 6,
+        Key::A => 7
+    }
+}
+
+#[cfg(any(test, feature = "internals"))]
+fn as_key(key_byte: u8) -> io::Result<Key> {
+    match key_byte {
+        0 => Ok(Key::Down),
+        1 => Ok(Key::Up),
+        2 => Ok(Key::Left),
+        3 => Ok(Key::Right),
+        4 => Ok(Key::Start),
+        5 => Ok(Key::Select),
+        6 => Ok(Key::B),
+        7 => Ok(Key::A),
+        _ => Err(Error::new(ErrorKind::InvalidData, format!("Invalid key in replay: {}", key_byte)))
+    }
+}
+
+pub fn export_replay(emulator: &Emulator) -> io::Result<Vec<u8>> {
+    let replay = &emulator.replay;
+
+    let keyframe = replay.keyframes.front()
+        .filter(|_| replay.enabled)
+        .ok_or_else(|| Error::other("The replay buffer is not enabled."))?;
+
+    let inputs: Vec<&ReplayInput> = replay.inputs.iter()
+        .filter(|input| input.step >= keyframe.step)
+        .collect();
+
+    let mut writer = StateWriter::new();
+    writer.write_bytes(REPLAY_MAGIC);
+    writer.write_u8(REPLAY_VERSION);
+    writer.write_sized_bytes(&keyframe.state);
+    writer.write_u32(inputs.len() as u32);
+    for input in inputs {
+        writer.write_u64(input.step - keyframe.step);
+        writer.write_u8(as_key_byte(input.key));
+        writer.write_bool(input.pressed);
+    }
+    writer.write_u64(replay.steps - keyframe.step);
+
+    Ok(writer.into_bytes())
+}
+
+#[cfg(any(test, feature = "internals"))]
+pub struct ReplayPlayback {
+    pub inputs: Vec<ReplayInput>,
+    pub length_in_steps: u64,
+    pub position: u64,
+    next_input: usize
+}
+
+#[cfg(any(test, feature = "internals"))]
+pub fn load_replay(emulator: &mut Emulator, data: &[u8]) -> io::Result<ReplayPlayback> {
+    let mut reader = StateReader::new(data);
+
+    if reader.read_bytes(REPLAY_MAGIC.len())? != REPLAY_MAGIC {
+        return Err(Error::new(ErrorKind::InvalidData, "Data is not a Retro Boy replay."));
+    }
+
+    let version = reader.read_u8()?;
+    if version != REPLAY_VERSION {
+        return Err(Error::new(ErrorKind::InvalidData, format!("Unsupported replay version: {}", version)));
+    }
+
+    let state = reader.read_sized_bytes()?;
+
+    let input_count = reader.read_u32()?;
+    let mut inputs = Vec::new();
+    for _ in 0..input_count {
+        let step = reader.read_u64()?;
+        let key = as_key(reader.read_u8()?)?;
+        let pressed = reader.read_bool()?;
+        inputs.push(ReplayInput { step, key, pressed });
+    }
+
+    let length_in_steps = reader.read_u64()?;
+
+    savestate::decode_state(emulator, state)?;
+
+    Ok(ReplayPlayback {
+        inputs,
+        length_in_steps,
+        position: 0,
+        next_input: 0
+    })
+}
+
+#[cfg(any(test, feature = "internals"))]
+pub fn playback_finished(playback: &ReplayPlayback) -> bool {
+    playback.position >= playback.length_in_steps
+}
+
+#[cfg(any(test, feature = "internals"))]
+pub fn step_playback(emulator: &mut Emulator, playback: &mut ReplayPlayback) {
+    while let Some(input) = playback.inputs.get(playback.next_input) {
+        if input.step > playback.position {
+            break;
+        }
+
+        if input.pressed {
+            keys::handle_key_press(emulator, &input.key);
+        }
+        else {
+            keys::handle_key_release(emulator, &input.key);
+        }
+
+        playback.next_input += 1;
+    }
+
+    emulator::step(emulator);
+    playback.position += 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mmu::constants::*;
+    use crate::mmu::test_utils::*;
+    use super::*;
+
+    fn build_joypad_copy_rom() -> Vec<u8> {
+        let mut rom = build_rom(CART_TYPE_MBC1_WITH_RAM, ROM_SIZE_64KB, RAM_SIZE_8KB);
+
+        // Endless loop that copies the joypad register into working RAM: ld a,($FF00); ld ($C000),a; jr -7
+        let program = [0xF0, 0x00, 0xEA, 0x00, 0xC0, 0x18, 0xF9];
+        rom[0x100..0x100 + program.len()].copy_from_slice(&program);
+        rom
+    }
+
+    fn run_frames(emulator: &mut Emulator, frames: u32) {
+        let target = emulator.gpu.frame_count + frames as u64;
+        while emulator.gpu.frame_count < target {
+            emulator::step(emulator);
+        }
+    }
+
+    #[test]
+    fn should_fail_to_export_when_disabled() {
+        let emulator = setup_emulator_with_rom(build_joypad_copy_rom());
+        assert!(export_replay(&emulator).is_err());
+    }
+
+    #[test]
+    fn should_only_retain_inputs_within_window() {
+        let mut emulator = setup_emulator_with_rom(build_joypad_copy_rom());
+        enable_replay_buffer(&mut emulator, 4);
+
+        keys::handle_key_press(&mut emulator, &Key::A);
+        run_frames(&mut emulator, 10);
+        keys::handle_key_press(&mut emulator, &Key::B);
+
+        assert_eq!(emulator.replay.keyframes.len(), KEYFRAMES_RETAINED);
+        assert_eq!(emulator.replay.inputs.len(), 1);
+        assert_eq!(emulator.replay.inputs[0].key, Key::B);
+    }
+
+    #[test]
+    fn should_reproduce_recorded_session_when_played_back() {
+        let mut emulator = setup_emulator_with_rom(build_joypad_copy_rom());
+        enable_replay_buffer(&mut emulator, 4);
+
+        run_frames(&mut emulator, 5);
+        keys::handle_key_press(&mut emulator, &Key::Start);
+        run_frames(&mut emulator, 1);
+        keys::handle_key_release(&mut emulator, &Key::Start);
+        keys::handle_key_press(&mut emulator, &Key::Right);
+        run_frames(&mut emulator, 1);
+
+        let expected_state = savestate::encode_state(&emulator);
+        let replay = export_replay(&emulator).unwrap();
+
+        let mut playback_emulator = setup_emulator_with_rom(build_joypad_copy_rom());
+        let mut playback = load_replay(&mut playback_emulator, &replay).unwrap();
+        while !playback_finished(&playback) {
+            step_playback(&mut playback_emulator, &mut playback);
+        }
+
+        assert_eq!(savestate::encode_state(&playback_emulator), expected_state);
+    }
+
+    #[test]
+    fn should_reject_data_that_is_not_a_replay() {
+        let mut emulator = setup_emulator_with_rom(build_joypad_copy_rom());
+        assert!(load_replay(&mut emulator, b"RBSS\x01").is_err());
+    }
+}
@@ -0,0 +1,459 @@
+use std::collections::VecDeque;
+use std::io::{self, Error, ErrorKind};
+use std::mem;
+
+use crate::apu::register_log;
+use crate::breakpoints::{self, Breakpoint};
+#[cfg(feature = "bus-observer")]
+use crate::bus_observer::BusObserver;
+use crate::emulator::{self, Emulator};
+use crate::gpu::{LcdListener, NoopLcdListener};
+use crate::input_polling::InputPoller;
+use crate::io_trace;
+use crate::keys::{self, Key};
+use crate::serial::SerialDevice;
+use crate::snapshot::{self, StateSnapshot};
+use crate::stats;
+
+/*
+    Reverse stepping for the debugger. Like the replay buffer, it keeps snapshot
+    checkpoints along with the inputs pressed since, but at instruction granularity:
+    a checkpoint is taken every CHECKPOINT_INTERVAL instructions. Stepping back
+    restores the closest checkpoint before the target instruction and runs forward
+    to it again, which is exact since emulation is deterministic given the inputs.
+
+    Only the newest checkpoint is kept as a full snapshot. Older ones are stored as
+    the bytes that differ from the checkpoint after them, which between checkpoints
+    this close together is a small fraction of a full state.
+*/
+pub const CHECKPOINT_INTERVAL: u64 = 64;
+pub const DEFAULT_HISTORY_INSTRUCTIONS: u64 = 512;
+
+#[derive(Debug)]
+enum CheckpointState {
+    Full(Vec<u8>),
+    Delta { length: usize, runs: Vec<(usize, Vec<u8>)> }
+}
+
+#[derive(Debug)]
+struct Checkpoint {
+    instruction: u64,
+    state: CheckpointState
+}
+
+#[derive(Debug)]
+struct RecordedInput {
+    instruction: u64,
+    key: Key,
+    pressed: bool
+}
+
+#[derive(Debug)]
+pub struct ReverseStepState {
+    pub enabled: bool,
+    pub history_instructions: u64,
+    pub instruction_count: u64,
+    checkpoints: VecDeque<Checkpoint>,
+    inputs: VecDeque<RecordedInput>
+}
+
+pub fn initialize_reverse_step() -> ReverseStepState {
+    ReverseStepState {
+        enabled: false,
+        history_instructions: DEFAULT_HISTORY_INSTRUCTIONS,
+        instruction_count: 0,
+        checkpoints: VecDeque::new(),
+        inputs: VecDeque::new()
+    }
+}
+
+fn encode_delta(state: &[u8], next_state: &[u8]) -> CheckpointState {
+    if state.len() != next_state.len() {
+        return CheckpointState::Full(state.to_vec());
+    }
+
+    let mut runs: Vec<(usize, Vec<u8>)> = Vec::new();
+    for (offset, (byte, next_byte)) in state.iter().zip(next_state).enumerate() {
+        if byte == next_byte {
+            continue;
+        }
+        match runs.last_mut() {
+            Some((start, bytes)) if *start + bytes.len() == offset => bytes.push(*byte),
+            _ => runs.push((offset, vec![*byte]))
+        }
+    }
+
+    CheckpointState::Delta { length: state.len(), runs }
+}
+
+fn apply_delta(next_state: &[u8], checkpoint_state: &CheckpointState) -> Vec<u8> {
+    match checkpoint_state {
+        CheckpointState::Full(state) => state.clone(),
+        CheckpointState::Delta { length, runs } => {
+            let mut state = next_state.to_vec();
+            state.resize(*length, 0);
+            for (offset, bytes) in runs {
+                state[*offset..*offset + bytes.len()].copy_from_slice(bytes);
+            }
+            state
+        }
+    }
+}
+
+fn capture_checkpoint(emulator: &mut Emulator) {
+    let state = snapshot::take_snapshot(emulator).into_bytes();
+    let reverse_step = &mut emulator.reverse_step;
+
+    if let Some(previous) = reverse_step.checkpoints.back_mut() {
+        if let CheckpointState::Full(previous_state) = &previous.state {
+            previous.state = encode_delta(previous_state, &state);
+        }
+    }
+
+    reverse_step.checkpoints.push_back(Checkpoint { instruction: reverse_step.instruction_count, state: CheckpointState::Full(state) });
+
+    // The oldest checkpoint is kept while the one after it is still needed to reach the full history.
+    let oldest_needed = reverse_step.instruction_count.saturating_sub(reverse_step.history_instructions);
+    while reverse_step.checkpoints.get(1).is_some_and(|checkpoint| checkpoint.instruction <= oldest_needed) {
+        reverse_step.checkpoints.pop_front();
+    }
+
+    if let Some(oldest) = reverse_step.checkpoints.front() {
+        let oldest_instruction = oldest.instruction;
+        while reverse_step.inputs.front().is_some_and(|input| input.instruction < oldest_instruction) {
+            reverse_step.inputs.pop_front();
+        }
+    }
+}
+
+pub fn enable_reverse_step(emulator: &mut Emulator, history_instructions: u64) {
+    emulator.reverse_step = initialize_reverse_step();
+    emulator.reverse_step.enabled = true;
+    emulator.reverse_step.history_instructions = history_instructions;
+    capture_checkpoint(emulator);
+}
+
+pub fn disable_reverse_step(emulator: &mut Emulator) {
+    emulator.reverse_step = initialize_reverse_step();
+}
+
+pub fn record_input(emulator: &mut Emulator, key: Key, pressed: bool) {
+    let reverse_step = &mut emulator.reverse_step;
+    if reverse_step.enabled {
+        reverse_step.inputs.push_back(RecordedInput { instruction: reverse_step.instruction_count, key, pressed });
+    }
+}
+
+pub fn step(emulator: &mut Emulator) {
+    if emulator.reverse_step.enabled {
+        emulator.reverse_step.instruction_count += 1;
+        if emulator.reverse_step.instruction_count.is_multiple_of(CHECKPOINT_INTERVAL) {
+            capture_checkpoint(emulator);
+        }
+    }
+}
+
+// How many instructions back the history currently reaches.
+pub fn available_reverse_steps(emulator: &Emulator) -> u64 {
+    let reverse_step = &emulator.reverse_step;
+    reverse_step.checkpoints.front()
+        .map(|oldest| reverse_step.instruction_count - oldest.instruction)
+        .unwrap_or(0)
+}
+
+fn restore_checkpoint(emulator: &mut Emulator, index: usize) -> io::Result<()> {
+    let checkpoints = &mut emulator.reverse_step.checkpoints;
+    let mut state = match &checkpoints.back().map(|checkpoint| &checkpoint.state) {
+        Some(CheckpointState::Full(state)) => state.clone(),
+        _ => return Err(Error::new(ErrorKind::InvalidData, "Reverse step history is missing its newest checkpoint."))
+    };
+
+    for checkpoint in checkpoints.range(index..checkpoints.len() - 1).rev() {
+        state = apply_delta(&state, &checkpoint.state);
+    }
+
+    // Checkpoints after the restored one are in the future now, and are recaptured as emulation runs forward again.
+    checkpoints.truncate(index + 1);
+    let instruction = checkpoints[index].instruction;
+    let state = StateSnapshot::from_bytes(state);
+    snapshot::restore_state(emulator, &state)?;
+    emulator.reverse_step.checkpoints[index].state = CheckpointState::Full(state.into_bytes());
+    emulator.reverse_step.instruction_count = instruction;
+    Ok(())
+}
+
+/*
+    Replaying runs instructions the frontend has already seen, so everything that
+    reaches outside the emulator is suspended on the way: frames, audio, serial
+    devices and logs, input polling, LCD and bus listeners and replay recording.
+    The profiler, I/O trace, stats and unmapped write tracking don't count the
+    replayed instructions a second time.
+    So is everything that could stop the run early, like breakpoints, debug hooks
+    and pause requests.
+*/
+struct SuspendedHooks {
+    breakpoints: Vec<Breakpoint>,
+    was_paused: bool,
+    pause_requested: bool,
+    debug_hooks_enabled: bool,
+    render: fn(&[u8]),
+    render_scanline: Option<fn(u8, &[u8])>,
+    lcd_listener: Box<dyn LcdListener>,
+    input_poller: Option<Box<dyn InputPoller>>,
+    serial_device: Option<Box<dyn SerialDevice>>,
+    serial_output: Vec<u8>,
+    left_samples: Vec<f32>,
+    right_samples: Vec<f32>,
+    register_log_enabled: bool,
+    replay_enabled: bool,
+    profiler_enabled: bool,
+    io_trace_enabled: bool,
+    stats_enabled: bool,
+    unmapped_writes_enabled: bool,
+    #[cfg(feature = "bus-observer")]
+    bus_observer: Option<Box<dyn BusObserver>>
+}
+
+fn suspend_hooks(emulator: &mut Emulator) -> SuspendedHooks {
+    SuspendedHooks {
+        breakpoints: mem::take(&mut emulator.breakpoints.breakpoints),
+        was_paused: mem::take(&mut emulator.pause.paused),
+        pause_requested: mem::take(&mut emulator.pause.requested),
+        debug_hooks_enabled: mem::take(&mut emulator.debug_hooks.enabled),
+        render: mem::replace(&mut emulator.render, |_| {}),
+        render_scanline: emulator.render_scanline.take(),
+        lcd_listener: mem::replace(&mut emulator.lcd_listener, Box::new(NoopLcdListener)),
+        input_poller: emulator.input_polling.poller.take(),
+        serial_device: emulator.serial.device.take(),
+        serial_output: mem::take(&mut emulator.serial.logger.output),
+        left_samples: mem::take(&mut emulator.apu.left_sample_queue),
+        right_samples: mem::take(&mut emulator.apu.right_sample_queue),
+        register_log_enabled: mem::take(&mut emulator.apu.register_log.enabled),
+        replay_enabled: mem::take(&mut emulator.replay.enabled),
+        profiler_enabled: mem::take(&mut emulator.profiler.enabled),
+        io_trace_enabled: mem::take(&mut emulator.io_trace.enabled),
+        stats_enabled: mem::take(&mut emulator.stats.enabled),
+        unmapped_writes_enabled: mem::take(&mut emulator.unmapped_writes.enabled),
+        #[cfg(feature = "bus-observer")]
+        bus_observer: emulator.bus_observer.take()
+    }
+}
+
+fn resume_hooks(emulator: &mut Emulator, hooks: SuspendedHooks) {
+    emulator.breakpoints.breakpoints = hooks.breakpoints;
+    emulator.pause.paused = hooks.was_paused;
+    emulator.pause.requested = hooks.pause_requested;
+    emulator.debug_hooks.enabled = hooks.debug_hooks_enabled;
+    emulator.render = hooks.render;
+    emulator.render_scanline = hooks.render_scanline;
+    emulator.lcd_listener = hooks.lcd_listener;
+    emulator.input_polling.poller = hooks.input_poller;
+    emulator.serial.device = hooks.serial_device;
+    emulator.serial.logger.output = hooks.serial_output;
+    emulator.apu.left_sample_queue = hooks.left_samples;
+    emulator.apu.right_sample_queue = hooks.right_samples;
+    emulator.apu.register_log.enabled = hooks.register_log_enabled;
+    emulator.replay.enabled = hooks.replay_enabled;
+    emulator.profiler.enabled = hooks.profiler_enabled;
+    emulator.io_trace.enabled = hooks.io_trace_enabled;
+    emulator.stats.enabled = hooks.stats_enabled;
+    emulator.unmapped_writes.enabled = hooks.unmapped_writes_enabled;
+    stats::sync_clock_reference(emulator);
+    io_trace::sync_clock_reference(emulator);
+    register_log::sync_clock_reference(emulator);
+    #[cfg(feature = "bus-observer")]
+    {
+        emulator.bus_observer = hooks.bus_observer;
+    }
+}
+
+// Inputs are replayed from the history rather than recorded again.
+fn replay_inputs(emulator: &mut Emulator, target_instruction: u64) -> io::Result<()> {
+    let inputs = mem::take(&mut emulator.reverse_step.inputs);
+    let mut next_input = inputs.iter().position(|input| input.instruction >= emulator.reverse_step.instruction_count)
+        .unwrap_or(inputs.len());
+    let mut result = Ok(());
+
+    while emulator.reverse_step.instruction_count < target_instruction {
+        while let Some(input) = inputs.get(next_input).filter(|input| input.instruction <= emulator.reverse_step.instruction_count) {
+            if input.pressed {
+                keys::handle_key_press(emulator, &input.key);
+            }
+            else {
+                keys::handle_key_release(emulator, &input.key);
+            }
+            next_input += 1;
+        }
+
+        let instruction_count = emulator.reverse_step.instruction_count;
+        emulator::step(emulator);
+        if emulator.reverse_step.instruction_count == instruction_count {
+            result = Err(Error::other("Reverse step replay stopped before reaching the target instruction."));
+            break;
+        }
+    }
+
+    emulator.reverse_step.inputs = inputs;
+    emulator.reverse_step.inputs.retain(|input| input.instruction < target_instruction);
+    result
+}
+
+fn replay_until(emulator: &mut Emulator, target_instruction: u64) -> io::Result<()> {
+    let hooks = suspend_hooks(emulator);
+    let result = replay_inputs(emulator, target_instruction);
+    resume_hooks(emulator, hooks);
+    breakpoints::skip_current_breakpoint(emulator);
+    result
+}
+
+pub fn reverse_step(emulator: &mut Emulator, instructions: u64) -> io::Result<()> {
+    if !emulator.reverse_step.enabled {
+        return Err(Error::new(ErrorKind::Unsupported, "Reverse stepping is not enabled."));
+    }
+
+    let available = available_reverse_steps(emulator);
+    if instructions > available {
+        return Err(Error::new(ErrorKind::InvalidInput,
+            format!("Can't step back {} instructions, only {} are in the history.", instructions, available)));
+    }
+
+    let target_instruction = emulator.reverse_step.instruction_count - instructions;
+    let index = emulator.reverse_step.checkpoints.iter()
+        .rposition(|checkpoint| checkpoint.instruction <= target_instruction)
+        .unwrap_or(0);
+
+    restore_checkpoint(emulator, index)?;
+    replay_until(emulator, target_instruction)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::debug_hooks;
+    use crate::emulator::initialize_screenless_emulator;
+    use crate::mmu;
+    use crate::mmu::constants::*;
+    use crate::mmu::effects::empty_cartridge_effects;
+    use crate::mmu::test_utils::*;
+    use crate::pause;
+    use crate::profiler;
+    use super::*;
+
+    fn setup_emulator() -> Emulator {
+        // Endless loop counting up in working RAM and copying the joypad: ld hl,$C000; inc (hl); ld a,($FF00); ld ($C001),a; jr -8
+        setup_emulator_with_program(&[0x21, 0x00, 0xC0, 0x34, 0xF0, 0x00, 0xEA, 0x01, 0xC0, 0x18, 0xF8])
+    }
+
+    fn setup_emulator_with_program(program: &[u8]) -> Emulator {
+        let mut emulator = initialize_screenless_emulator();
+        let mut rom = build_rom(CART_TYPE_MBC1, ROM_SIZE_64KB, RAM_SIZE_0KB);
+        rom[0x100..0x100 + program.len()].copy_from_slice(program);
+        mmu::load_rom_buffer(&mut emulator.memory, rom, empty_cartridge_effects()).unwrap();
+        emulator.memory.in_bios = false;
+        emulator.cpu.registers.program_counter = 0x100;
+        emulator.cpu.registers.opcode = 0x00;
+        emulator
+    }
+
+    fn run(emulator: &mut Emulator, instructions: usize) {
+        for _ in 0..instructions {
+            emulator::step(emulator);
+        }
+    }
+
+    fn counter(emulator: &mut Emulator) -> u8 {
+        mmu::read_byte(emulator, 0xC000)
+    }
+
+    #[test]
+    fn should_step_back_to_earlier_instruction() {
+        let mut emulator = setup_emulator();
+        enable_reverse_step(&mut emulator, DEFAULT_HISTORY_INSTRUCTIONS);
+
+        run(&mut emulator, 300);
+        let program_counter = emulator.cpu.registers.program_counter;
+        let count = counter(&mut emulator);
+
+        run(&mut emulator, 5);
+        reverse_step(&mut emulator, 5).unwrap();
+        assert_eq!(emulator.cpu.registers.program_counter, program_counter);
+        assert_eq!(counter(&mut emulator), count);
+        assert_eq!(emulator.reverse_step.instruction_count, 300);
+
+        reverse_step(&mut emulator, 100).unwrap();
+        assert_eq!(counter(&mut emulator), count - 25);
+    }
+
+    #[test]
+    fn should_replay_inputs_when_stepping_forward_from_checkpoint() {
+        let mut emulator = setup_emulator();
+        enable_reverse_step(&mut emulator, DEFAULT_HISTORY_INSTRUCTIONS);
+        mmu::write_byte(&mut emulator, 0xFF00, 0x10);
+
+        run(&mut emulator, 70);
+        keys::handle_key_press(&mut emulator, &Key::A);
+        run(&mut emulator, 30);
+        let joypad = mmu::read_byte(&mut emulator, 0xC001);
+        assert_eq!(joypad & 0x01, 0);
+
+        reverse_step(&mut emulator, 10).unwrap();
+        run(&mut emulator, 10);
+        assert_eq!(mmu::read_byte(&mut emulator, 0xC001), joypad);
+    }
+
+    #[test]
+    fn should_not_count_replayed_instructions_in_profiler_or_stats() {
+        let mut emulator = setup_emulator();
+        enable_reverse_step(&mut emulator, DEFAULT_HISTORY_INSTRUCTIONS);
+        profiler::start_profiler(&mut emulator);
+        run(&mut emulator, 100);
+
+        let total_cycles = profiler::profile_report(&emulator).total_cycles;
+        let instructions_retired = stats::get_stats(&emulator).instructions_retired;
+        reverse_step(&mut emulator, 5).unwrap();
+
+        assert_eq!(profiler::profile_report(&emulator).total_cycles, total_cycles);
+        assert_eq!(stats::get_stats(&emulator).instructions_retired, instructions_retired);
+        assert!(emulator.profiler.enabled);
+        assert!(emulator.stats.enabled);
+    }
+
+    #[test]
+    fn should_limit_history() {
+        let mut emulator = setup_emulator();
+        enable_reverse_step(&mut emulator, 128);
+        run(&mut emulator, 1000);
+
+        let available = available_reverse_steps(&emulator);
+        assert!((128..128 + CHECKPOINT_INTERVAL).contains(&available));
+        assert!(reverse_step(&mut emulator, available + 1).is_err());
+        assert!(reverse_step(&mut emulator, available).is_ok());
+    }
+
+    #[test]
+    fn should_stay_paused_after_stepping_back() {
+        let mut emulator = setup_emulator();
+        enable_reverse_step(&mut emulator, DEFAULT_HISTORY_INSTRUCTIONS);
+        run(&mut emulator, 100);
+        emulator.pause.paused = true;
+
+        reverse_step(&mut emulator, 1).unwrap();
+        assert!(pause::is_paused(&emulator));
+        assert_eq!(emulator.reverse_step.instruction_count, 99);
+    }
+
+    #[test]
+    fn should_replay_past_debug_breakpoints_and_pause_requests() {
+        // ld b,b; jr -3
+        let mut emulator = setup_emulator_with_program(&[0x40, 0x18, 0xFD]);
+        enable_reverse_step(&mut emulator, DEFAULT_HISTORY_INSTRUCTIONS);
+        run(&mut emulator, 100);
+        debug_hooks::set_debug_hooks_enabled(&mut emulator, true);
+        pause::request_pause(&mut emulator);
+
+        reverse_step(&mut emulator, 50).unwrap();
+        assert_eq!(emulator.reverse_step.instruction_count, 50);
+        assert!(!pause::is_paused(&emulator));
+        assert!(emulator.pause.requested);
+        assert!(emulator.debug_hooks.enabled);
+    }
+}
@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+use std::io;
+use std::sync::OnceLock;
+
+use crate::boot;
+use crate::emulator::{self, as_mode, is_cgb, CartridgeEffects, CartridgeHeader, Emulator, HardwareModel, Mode};
+use crate::game_config;
+use crate::gpu;
+use crate::mmu;
+use crate::mmu::CartridgeOverrides;
+use crate::patches::crc32;
+
+/*
+    Plenty of cartridges shipped with headers that don't match their hardware, such
+    as the wrong RAM size or a missing battery flag. Known games are looked up by the
+    CRC32 of the whole ROM when loaded, and their entry overrides what the header
+    says. Users can load their own entries on top of the bundled ones, in the format
+    described in rom_database.txt.
+*/
+const EMBEDDED_DATABASE: &str = include_str!("rom_database.txt");
+
+// The largest cartridge RAM we support, 16 banks of 8KB.
+const MAX_RAM_SIZE: u32 = 0x20000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quirk {
+    DmgOnly,
+    SkipBootRom
+}
+
+// RGB555 colors given to DMG games on the CGB, in place of the ones the boot ROM picks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompatibilityPalette {
+    pub background: [u16; 4],
+    pub obp0: [u16; 4],
+    pub obp1: [u16; 4]
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RomDatabaseEntry {
+    pub crc32: u32,
+    pub overrides: CartridgeOverrides,
+    pub palette: Option<CompatibilityPalette>,
+    pub quirks: Vec<Quirk>
+}
+
+pub type RomDatabase = HashMap<u32, RomDatabaseEntry>;
+
+#[derive(Debug)]
+pub struct RomDatabaseState {
+    pub enabled: bool,
+    pub user_entries: RomDatabase,
+    pub active_entry: Option<RomDatabaseEntry>
+}
+
+pub fn initialize_rom_database() -> RomDatabaseState {
+    RomDatabaseState {
+        enabled: true,
+        user_entries: HashMap::new(),
+        active_entry: None
+    }
+}
+
+fn invalid_entry_error(line_number: usize, message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Invalid ROM database entry on line {}: {}", line_number, message))
+}
+
+fn parse_hex(text: &str, max: u32, line_number: usize, name: &str) -> io::Result<u32> {
+    u32::from_str_radix(text, 16).ok()
+        .filter(|value| *value <= max)
+        .ok_or_else(|| invalid_entry_error(line_number, &format!("invalid {}", name)))
+}
+
+pub fn parse_palette(text: &str, line_number: usize) -> io::Result<CompatibilityPalette> {
+    let colors = text.split(',')
+        .map(|color| parse_hex(color, 0x7FFF, line_number, "palette color").map(|color| color as u16))
+        .collect::<io::Result<Vec<u16>>>()?;
+
+    let palette_colors = |index: usize| [colors[index], colors[index + 1], colors[index + 2], colors[index + 3]];
+
+    match colors.len() {
+        4 => Ok(CompatibilityPalette { background: palette_colors(0), obp0: palette_colors(0), obp1: palette_colors(0) }),
+        12 => Ok(CompatibilityPalette { background: palette_colors(0), obp0: palette_colors(4), obp1: palette_colors(8) }),
+        _ => Err(invalid_entry_error(line_number, "palettes need 4 or 12 colors"))
+    }
+}
+
+fn parse_quirk(text: &str, line_number: usize) -> io::Result<Quirk> {
+    match text {
+        "dmg_only" => Ok(Quirk::DmgOnly),
+        "skip_boot_rom" => Ok(Quirk::SkipBootRom),
+        _ => Err(invalid_entry_error(line_number, &format!("unknown quirk {}", text)))
+    }
+}
+
+fn parse_entry_line(line: &str, line_number: usize) -> io::Result<RomDatabaseEntry> {
+    let mut parts = line.split_whitespace();
+    let crc32 = parse_hex(parts.next().unwrap_or_default(), u32::MAX, line_number, "CRC32")?;
+    let mut entry = RomDatabaseEntry { crc32, ..RomDatabaseEntry::default() };
+
+    for part in parts {
+        let (key, value) = part.split_once('=')
+            .ok_or_else(|| invalid_entry_error(line_number, "expected key=value"))?;
+        match key {
+            "mapper" => entry.overrides.type_code = Some(parse_hex(value, 0xFF, line_number, "mapper")? as u8),
+            "ram" => {
+                let ram_size = parse_hex(value, MAX_RAM_SIZE, line_number, "RAM size")?;
+                if ram_size != 0x800 && ram_size % 0x2000 != 0 {
+                    return Err(invalid_entry_error(line_number, "RAM size must be 800 or a multiple of 2000"));
+                }
+                entry.overrides.ram_size = Some(ram_size);
+            },
+            "battery" => entry.overrides.has_battery = match value {
+                "yes" => Some(true),
+                "no" => Some(false),
+                _ => return Err(invalid_entry_error(line_number, "battery must be yes or no"))
+            },
+            "palette" => entry.palette = Some(parse_palette(value, line_number)?),
+            "quirks" => entry.quirks = value.split(',')
+                .map(|quirk| parse_quirk(quirk, line_number))
+                .collect::<io::Result<Vec<Quirk>>>()?,
+            _ => return Err(invalid_entry_error(line_number, &format!("unknown key {}", key)))
+        }
+    }
+
+    Ok(entry)
+}
+
+pub fn parse_rom_database(text: &str) -> io::Result<RomDatabase> {
+    let mut database = HashMap::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let line = line.split(';').next().unwrap_or_default().trim();
+        if !line.is_empty() {
+            let entry = parse_entry_line(line, index + 1)?;
+            database.insert(entry.crc32, entry);
+        }
+    }
+
+    Ok(database)
+}
+
+pub fn load_rom_database(emulator: &mut Emulator, text: &str) -> io::Result<()> {
+    emulator.rom_database.user_entries = parse_rom_database(text)?;
+    Ok(())
+}
+
+pub fn set_rom_database_enabled(emulator: &mut Emulator, enabled: bool) {
+    emulator.rom_database.enabled = enabled;
+}
+
+// Parsed the first time a ROM is loaded and kept for every load after.
+fn embedded_database() -> &'static RomDatabase {
+    static DATABASE: OnceLock<RomDatabase> = OnceLock::new();
+    DATABASE.get_or_init(|| parse_rom_database(EMBEDDED_DATABASE).unwrap_or_default())
+}
+
+pub fn find_entry(emulator: &Emulator, rom: &[u8]) -> Option<RomDatabaseEntry> {
+    if !emulator.rom_database.enabled {
+        return None;
+    }
+
+    let crc32 = crc32(rom);
+    emulator.rom_database.user_entries.get(&crc32).cloned()
+        .or_else(|| embedded_database().get(&crc32).cloned())
+}
+
+pub fn has_quirk(emulator: &Emulator, quirk: Quirk) -> bool {
+    emulator.rom_database.active_entry.as_ref()
+        .is_some_and(|entry| entry.quirks.contains(&quirk))
+}
+
+// Loads a ROM with the overrides and quirks from its database entry, if it has one.
+pub fn load_rom(emulator: &mut Emulator, rom: Vec<u8>, cartridge_effects: Box<dyn CartridgeEffects>) -> io::Result<CartridgeHeader> {
+    let header = insert_rom(emulator, rom, cartridge_effects)?;
+    apply_quirks(emulator);
+    Ok(header)
+}
+
+// Only applies the header overrides, leaving the rest of the console as it is.
+pub fn insert_rom(emulator: &mut Emulator, rom: Vec<u8>, cartridge_effects: Box<dyn CartridgeEffects>) -> io::Result<CartridgeHeader> {
+    let entry = find_entry(emulator, &rom);
+    let overrides = entry.as_ref().map(|entry| entry.overrides).unwrap_or_default();
+    let rom_crc32 = crc32(&rom);
+    let header = mmu::load_rom_buffer_with_overrides(&mut emulator.memory, rom, cartridge_effects, overrides, emulator.clock.clone())?;
+    emulator.rom_database.active_entry = entry;
+    game_config::apply_game_config(emulator, rom_crc32);
+    Ok(header)
+}
+
+pub fn apply_quirks(emulator: &mut Emulator) {
+    if has_quirk(emulator, Quirk::DmgOnly) && as_mode(emulator.model) == Mode::CGB {
+        emulator::set_hardware_model(emulator, HardwareModel::DMG);
+    }
+
+    if has_quirk(emulator, Quirk::SkipBootRom) {
+        boot::apply_post_boot_state(emulator);
+    }
+}
+
+fn write_colors(data: &mut [u8], colors: &[u16; 4]) {
+    for (bytes, color) in data.chunks_exact_mut(2).zip(colors) {
+        bytes.copy_from_slice(&color.to_le_bytes());
+    }
+}
+
+// Called once the boot ROM hands over to the cartridge, as it sets up the palettes for DMG games.
+pub fn apply_compatibility_palette(emulator: &mut Emulator) {
+    let palette = game_config::active_palette(emulator)
+        .or_else(|| emulator.rom_database.active_entry.as_ref().and_then(|entry| entry.palette));
+    if let Some(palette) = palette {
+        if is_cgb(emulator) && gpu::has_dmg_compatability(emulator) {
+            let palettes = &mut emulator.gpu.registers.palettes;
+            write_colors(&mut palettes.cgb_bcpd[..8], &palette.background);
+            write_colors(&mut palettes.cgb_ocpd[..8], &palette.obp0);
+            write_colors(&mut palettes.cgb_ocpd[8..16], &palette.obp1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::initialize_screenless_emulator;
+    use crate::mmu::constants::*;
+    use crate::mmu::effects::empty_cartridge_effects;
+    use crate::mmu::test_utils::*;
+    use super::*;
+
+    #[test]
+    fn should_parse_bundled_database() {
+        assert!(parse_rom_database(EMBEDDED_DATABASE).is_ok());
+    }
+
+    #[test]
+    fn should_parse_entries_and_report_invalid_lines() {
+        let database = parse_rom_database("; comment\n1234ABCD mapper=1B ram=2000 battery=no palette=7FFF,56B5,294A,0000 quirks=dmg_only\n").unwrap();
+        let entry = &database[&0x1234ABCD];
+        assert_eq!(entry.overrides, CartridgeOverrides { type_code: Some(0x1B), ram_size: Some(0x2000), has_battery: Some(false) });
+        assert_eq!(entry.palette.unwrap().obp1, [0x7FFF, 0x56B5, 0x294A, 0x0000]);
+        assert_eq!(entry.quirks, vec![Quirk::DmgOnly]);
+
+        let error = parse_rom_database("1234ABCD\n1234ABCE ram=1000").unwrap_err();
+        assert_eq!(error.to_string(), "Invalid ROM database entry on line 2: RAM size must be 800 or a multiple of 2000");
+    }
+
+    #[test]
+    fn should_override_header_of_known_rom() {
+        let mut emulator = initialize_screenless_emulator();
+        emulator::set_hardware_model(&mut emulator, HardwareModel::CGB);
+        let rom = build_rom(CART_TYPE_MBC1, ROM_SIZE_64KB, RAM_SIZE_0KB);
+        load_rom_database(&mut emulator, &format!("{:08X} ram=2000 battery=yes palette=001F,03E0,7C00,0000 quirks=skip_boot_rom", crc32(&rom))).unwrap();
+
+        let header = load_rom(&mut emulator, rom, empty_cartridge_effects()).unwrap();
+
+        assert!(header.has_battery);
+        assert_eq!(header.max_ram_banks, 1);
+        assert!(!emulator.memory.in_bios);
+        assert_eq!(&emulator.gpu.registers.palettes.cgb_bcpd[..4], &[0x1F, 0x00, 0xE0, 0x03]);
+    }
+}
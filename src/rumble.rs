@@ -0,0 +1,79 @@
+use crate::emulator::Emulator;
+
+pub trait RumbleListener: Send {
+    fn rumble_changed(&mut self, active: bool);
+}
+
+pub struct NoopRumbleListener;
+
+impl RumbleListener for NoopRumbleListener {
+    fn rumble_changed(&mut self, _: bool) {}
+}
+
+pub struct RumbleState {
+    pub active: bool,
+    pub listener: Box<dyn RumbleListener>
+}
+
+pub fn initialize_rumble() -> RumbleState {
+    RumbleState {
+        active: false,
+        listener: Box::new(NoopRumbleListener)
+    }
+}
+
+#[cfg(any(test, feature = "internals"))]
+pub fn set_rumble_listener(emulator: &mut Emulator, listener: Box<dyn RumbleListener>) {
+    emulator.rumble.listener = listener;
+}
+
+/*
+    Games toggle the motor bit rapidly to vary the rumble strength, so
+    listeners are only notified of transitions rather than every write.
+*/
+pub fn sync(emulator: &mut Emulator) {
+    let active = emulator.memory.cartridge_mapper.rumble_active();
+    if active != emulator.rumble.active {
+        emulator.rumble.active = active;
+        emulator.rumble.listener.rumble_changed(active);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use crate::emulator::initialize_screenless_emulator;
+    use crate::mmu;
+    use crate::mmu::constants::*;
+    use crate::mmu::effects::empty_cartridge_effects;
+    use crate::mmu::test_utils::*;
+    use super::*;
+
+    struct RecordingRumbleListener {
+        transitions: Arc<Mutex<Vec<bool>>>
+    }
+
+    impl RumbleListener for RecordingRumbleListener {
+        fn rumble_changed(&mut self, active: bool) {
+            self.transitions.lock().unwrap().push(active);
+        }
+    }
+
+    #[test]
+    fn should_report_rumble_transitions() {
+        let mut emulator = initialize_screenless_emulator();
+        let rom = build_rom(CART_TYPE_MBC5_RUMBLE_RAM_BATTERY, ROM_SIZE_64KB, RAM_SIZE_128KB);
+        mmu::load_rom_buffer(&mut emulator.memory, rom, empty_cartridge_effects()).unwrap();
+        emulator.memory.in_bios = false;
+
+        let transitions = Arc::new(Mutex::new(Vec::new()));
+        set_rumble_listener(&mut emulator, Box::new(RecordingRumbleListener { transitions: transitions.clone() }));
+
+        mmu::write_byte(&mut emulator, 0x4000, 0x8);
+        mmu::write_byte(&mut emulator, 0x4000, 0x9);
+        mmu::write_byte(&mut emulator, 0x4000, 0x1);
+        mmu::write_byte(&mut emulator, 0x4000, 0x0);
+
+        assert_eq!(*transitions.lock().unwrap(), vec![true, false]);
+    }
+}
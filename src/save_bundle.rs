@@ -0,0 +1,247 @@
+use std::io::{self, Error, ErrorKind};
+
+use crate::patches::crc32;
+use crate::save_slots::{SaveSlotManager, SaveStorage};
+
+/*
+    Bundles a game's battery save and every savestate slot into one zip file, so
+    browser users can download their progress and upload it again on another
+    machine. Entries are named after their storage keys and stored uncompressed,
+    which keeps this free of a compression library; savestates are small enough
+    that it hardly matters. Only uncompressed zips, like the ones exported here,
+    can be imported.
+*/
+const LOCAL_HEADER_SIGNATURE: u32 = 0x04034B50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x02014B50;
+const END_OF_DIRECTORY_SIGNATURE: u32 = 0x06054B50;
+
+const LOCAL_HEADER_LENGTH: usize = 30;
+const CENTRAL_HEADER_LENGTH: usize = 46;
+const END_OF_DIRECTORY_LENGTH: usize = 22;
+
+const ZIP_VERSION: u16 = 20;
+const STORED_METHOD: u16 = 0;
+// 1980-01-01, the earliest date zip can hold. Entries aren't timestamped.
+const DOS_DATE: u16 = 0x21;
+
+struct ZipEntry {
+    name: String,
+    data: Vec<u8>
+}
+
+fn invalid_bundle_error(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("Invalid save bundle: {}", message))
+}
+
+fn push_u16(buffer: &mut Vec<u8>, value: u16) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_u32(buffer: &mut Vec<u8>, value: u32) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+fn read_u16(buffer: &[u8], offset: usize) -> io::Result<u16> {
+    buffer.get(offset..offset + 2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+        .ok_or_else(|| invalid_bundle_error("unexpected end of file"))
+}
+
+fn read_u32(buffer: &[u8], offset: usize) -> io::Result<u32> {
+    buffer.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .ok_or_else(|| invalid_bundle_error("unexpected end of file"))
+}
+
+fn push_entry_fields(buffer: &mut Vec<u8>, entry: &ZipEntry) {
+    push_u16(buffer, 0);
+    push_u16(buffer, STORED_METHOD);
+    push_u16(buffer, 0);
+    push_u16(buffer, DOS_DATE);
+    push_u32(buffer, crc32(&entry.data));
+    push_u32(buffer, entry.data.len() as u32);
+    push_u32(buffer, entry.data.len() as u32);
+    push_u16(buffer, entry.name.len() as u16);
+    push_u16(buffer, 0);
+}
+
+fn write_zip(entries: &[ZipEntry]) -> Vec<u8> {
+    let mut zip = Vec::new();
+    let mut central_directory = Vec::new();
+
+    for entry in entries {
+        let local_header_offset = zip.len() as u32;
+
+        push_u32(&mut zip, LOCAL_HEADER_SIGNATURE);
+        push_u16(&mut zip, ZIP_VERSION);
+        push_entry_fields(&mut zip, entry);
+        zip.extend_from_slice(entry.name.as_bytes());
+        zip.extend_from_slice(&entry.data);
+
+        push_u32(&mut central_directory, CENTRAL_HEADER_SIGNATURE);
+        push_u16(&mut central_directory, ZIP_VERSION);
+        push_u16(&mut central_directory, ZIP_VERSION);
+        push_entry_fields(&mut central_directory, entry);
+        // Comment length, disk number, internal and external attributes.
+        push_u16(&mut central_directory, 0);
+        push_u16(&mut central_directory, 0);
+        push_u16(&mut central_directory, 0);
+        push_u32(&mut central_directory, 0);
+        push_u32(&mut central_directory, local_header_offset);
+        central_directory.extend_from_slice(entry.name.as_bytes());
+    }
+
+    let central_directory_offset = zip.len() as u32;
+    zip.extend_from_slice(&central_directory);
+
+    push_u32(&mut zip, END_OF_DIRECTORY_SIGNATURE);
+    push_u16(&mut zip, 0);
+    push_u16(&mut zip, 0);
+    push_u16(&mut zip, entries.len() as u16);
+    push_u16(&mut zip, entries.len() as u16);
+    push_u32(&mut zip, central_directory.len() as u32);
+    push_u32(&mut zip, central_directory_offset);
+    push_u16(&mut zip, 0);
+    zip
+}
+
+fn find_end_of_directory(zip: &[u8]) -> io::Result<usize> {
+    (0..=zip.len().saturating_sub(END_OF_DIRECTORY_LENGTH))
+        .rev()
+        .find(|offset| read_u32(zip, *offset).ok() == Some(END_OF_DIRECTORY_SIGNATURE))
+        .ok_or_else(|| invalid_bundle_error("not a zip file"))
+}
+
+fn read_zip(zip: &[u8]) -> io::Result<Vec<ZipEntry>> {
+    let end_of_directory = find_end_of_directory(zip)?;
+    let entry_count = read_u16(zip, end_of_directory + 10)?;
+    let mut offset = read_u32(zip, end_of_directory + 16)? as usize;
+    let mut entries = Vec::new();
+
+    for _ in 0..entry_count {
+        if read_u32(zip, offset)? != CENTRAL_HEADER_SIGNATURE {
+            return Err(invalid_bundle_error("corrupt central directory"));
+        }
+
+        let method = read_u16(zip, offset + 10)?;
+        let expected_crc32 = read_u32(zip, offset + 16)?;
+        let size = read_u32(zip, offset + 20)? as usize;
+        let name_length = read_u16(zip, offset + 28)? as usize;
+        let extra_length = read_u16(zip, offset + 30)? as usize;
+        let comment_length = read_u16(zip, offset + 32)? as usize;
+        let local_header_offset = read_u32(zip, offset + 42)? as usize;
+        let name_start = offset + CENTRAL_HEADER_LENGTH;
+        let name = zip.get(name_start..name_start + name_length)
+            .and_then(|name| String::from_utf8(name.to_vec()).ok())
+            .ok_or_else(|| invalid_bundle_error("invalid entry name"))?;
+
+        if method != STORED_METHOD {
+            return Err(invalid_bundle_error(&format!("{} is compressed", name)));
+        }
+
+        let local_name_length = read_u16(zip, local_header_offset + 26)? as usize;
+        let local_extra_length = read_u16(zip, local_header_offset + 28)? as usize;
+        let data_start = local_header_offset + LOCAL_HEADER_LENGTH + local_name_length + local_extra_length;
+        let data = zip.get(data_start..data_start + size)
+            .ok_or_else(|| invalid_bundle_error("unexpected end of file"))?
+            .to_vec();
+
+        if crc32(&data) != expected_crc32 {
+            return Err(invalid_bundle_error(&format!("{} is corrupt", name)));
+        }
+
+        entries.push(ZipEntry { name, data });
+        offset = name_start + name_length + extra_length + comment_length;
+    }
+
+    Ok(entries)
+}
+
+fn as_key_prefix<S: SaveStorage>(manager: &SaveSlotManager<S>) -> String {
+    format!("{}.", manager.namespace())
+}
+
+pub fn export_save_bundle<S: SaveStorage>(manager: &SaveSlotManager<S>) -> io::Result<Vec<u8>> {
+    let mut keys = manager.storage.list(&as_key_prefix(manager))?;
+    keys.sort();
+
+    let mut entries = Vec::new();
+    for name in keys {
+        if let Some(data) = manager.storage.read(&name)? {
+            entries.push(ZipEntry { name, data });
+        }
+    }
+
+    Ok(write_zip(&entries))
+}
+
+// Writes the bundle's saves to storage, replacing existing ones, and returns how many there were.
+pub fn import_save_bundle<S: SaveStorage>(manager: &mut SaveSlotManager<S>, bundle: &[u8]) -> io::Result<usize> {
+    let prefix = as_key_prefix(manager);
+    let entries: Vec<ZipEntry> = read_zip(bundle)?
+        .into_iter()
+        .filter(|entry| entry.name.starts_with(&prefix))
+        .collect();
+
+    if entries.is_empty() {
+        return Err(Error::new(ErrorKind::NotFound, "The save bundle has no saves for this game."));
+    }
+
+    for entry in &entries {
+        manager.storage.write(&entry.name, &entry.data)?;
+    }
+
+    Ok(entries.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::{initialize_screenless_emulator, CartridgeHeader, Emulator};
+    use crate::mmu;
+    use crate::mmu::constants::*;
+    use crate::mmu::effects::empty_cartridge_effects;
+    use crate::mmu::test_utils::*;
+    use crate::save_slots::test_utils::*;
+    use super::*;
+
+    fn setup_emulator() -> (Emulator, CartridgeHeader) {
+        let mut emulator = initialize_screenless_emulator();
+        let rom = build_rom(CART_TYPE_MBC1_WITH_RAM_PLUS_BATTERY, ROM_SIZE_64KB, RAM_SIZE_8KB);
+        let header = mmu::load_rom_buffer(&mut emulator.memory, rom, empty_cartridge_effects()).unwrap();
+        (emulator, header)
+    }
+
+    #[test]
+    fn should_round_trip_battery_save_and_slots() {
+        let (mut emulator, header) = setup_emulator();
+        let mut manager = SaveSlotManager::new(MemorySaveStorage::default(), &header);
+        mmu::set_cartridge_ram(&mut emulator.memory, vec![0x42; 0x2000]);
+        manager.save_battery(&emulator).unwrap();
+        manager.save_state(&emulator, 0).unwrap();
+        manager.save_state(&emulator, 2).unwrap();
+        manager.storage.write("OTHER-0000.sav", &[1]).unwrap();
+
+        let bundle = export_save_bundle(&manager).unwrap();
+
+        let mut imported = SaveSlotManager::new(MemorySaveStorage::default(), &header);
+        assert_eq!(import_save_bundle(&mut imported, &bundle).unwrap(), 3);
+        assert_eq!(imported.storage.entries.len(), 3);
+        assert_eq!(imported.list_slots().unwrap(), vec![0, 2]);
+
+        mmu::set_cartridge_ram(&mut emulator.memory, vec![0; 0x2000]);
+        assert!(imported.load_battery(&mut emulator).unwrap());
+        assert_eq!(mmu::get_cartridge_ram(&emulator.memory)[0x100], 0x42);
+    }
+
+    #[test]
+    fn should_reject_corrupt_bundles() {
+        let (emulator, header) = setup_emulator();
+        let mut manager = SaveSlotManager::new(MemorySaveStorage::default(), &header);
+        manager.save_battery(&emulator).unwrap();
+        let mut bundle = export_save_bundle(&manager).unwrap();
+        bundle[LOCAL_HEADER_LENGTH + manager.namespace().len() + 4] ^= 0xFF;
+
+        assert!(import_save_bundle(&mut manager, &bundle).is_err());
+        assert!(import_save_bundle(&mut manager, b"not a zip").is_err());
+    }
+}
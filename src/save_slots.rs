@@ -0,0 +1,341 @@
+#[cfg(any(test, feature = "internals"))]
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+#[cfg(any(test, feature = "internals"))]
+use std::path::PathBuf;
+
+use crate::emulator::{CartridgeHeader, Emulator};
+use crate::mmu;
+use crate::savestate::{self, Thumbnail};
+
+pub trait SaveStorage {
+    fn list(&self, prefix: &str) -> io::Result<Vec<String>>;
+    fn read(&self, key: &str) -> io::Result<Option<Vec<u8>>>;
+    fn write(&mut self, key: &str, data: &[u8]) -> io::Result<()>;
+    fn delete(&mut self, key: &str) -> io::Result<()>;
+}
+
+const STATE_SLOT_SUFFIX: &str = ".state";
+const BATTERY_SAVE_SUFFIX: &str = ".sav";
+
+/*
+    Titles alone are not unique (many homebrew and prototype ROMs share them, and
+    later revisions of a game keep the same title), so saves are namespaced by the
+    title together with the global checksum from the cartridge header.
+*/
+pub fn as_save_namespace(header: &CartridgeHeader) -> String {
+    let sanitized_title: String = header.title
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("{}-{:04X}", sanitized_title, header.global_checksum)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg(any(test, feature = "internals"))]
+pub struct SaveSlotPreview {
+    pub slot: u32,
+    pub thumbnail: Option<Thumbnail>
+}
+
+pub struct SaveSlotManager<S: SaveStorage> {
+    pub storage: S,
+    namespace: String
+}
+
+impl<S: SaveStorage> SaveSlotManager<S> {
+    pub fn new(storage: S, header: &CartridgeHeader) -> SaveSlotManager<S> {
+        SaveSlotManager {
+            storage,
+            namespace: as_save_namespace(header)
+        }
+    }
+
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    fn state_key(&self, slot: u32) -> String {
+        format!("{}.slot{}{}", self.namespace, slot, STATE_SLOT_SUFFIX)
+    }
+
+    fn battery_key(&self) -> String {
+        format!("{}{}", self.namespace, BATTERY_SAVE_SUFFIX)
+    }
+
+    pub fn list_slots(&self) -> io::Result<Vec<u32>> {
+        let prefix = format!("{}.slot", self.namespace);
+        let mut slots: Vec<u32> = self.storage.list(&prefix)?
+            .iter()
+            .filter_map(|key| key.strip_prefix(&prefix))
+            .filter_map(|key| key.strip_suffix(STATE_SLOT_SUFFIX))
+            .filter_map(|slot| slot.parse().ok())
+            .collect();
+        slots.sort_unstable();
+        Ok(slots)
+    }
+
+    pub fn slot_thumbnail(&self, slot: u32) -> io::Result<Option<Thumbnail>> {
+        match self.storage.read(&self.state_key(slot))? {
+            Some(state) => savestate::read_thumbnail(&state),
+            None => Err(Error::new(ErrorKind::NotFound, format!("Save slot {} is empty.", slot)))
+        }
+    }
+
+    // Lists slots along with their thumbnails, for frontends showing a slot picker.
+    #[cfg(any(test, feature = "internals"))]
+    pub fn list_slot_previews(&self) -> io::Result<Vec<SaveSlotPreview>> {
+        self.list_slots()?
+            .into_iter()
+            .map(|slot| Ok(SaveSlotPreview { slot, thumbnail: self.slot_thumbnail(slot)? }))
+            .collect()
+    }
+
+    pub fn save_state(&mut self, emulator: &Emulator, slot: u32) -> io::Result<()> {
+        let state = savestate::encode_state(emulator);
+        let key = self.state_key(slot);
+        self.storage.write(&key, &state)
+    }
+
+    pub fn load_state(&self, emulator: &mut Emulator, slot: u32) -> io::Result<()> {
+        match self.storage.read(&self.state_key(slot))? {
+            Some(state) => savestate::decode_state(emulator, &state),
+            None => Err(Error::new(ErrorKind::NotFound, format!("Save slot {} is empty.", slot)))
+        }
+    }
+
+    pub fn delete_state(&mut self, slot: u32) -> io::Result<()> {
+        let key = self.state_key(slot);
+        self.storage.delete(&key)
+    }
+
+    pub fn save_battery(&mut self, emulator: &Emulator) -> io::Result<()> {
+        let save = mmu::get_battery_save(&emulator.memory);
+        let key = self.battery_key();
+        self.storage.write(&key, &save)
+    }
+
+    #[cfg(any(test, feature = "internals"))]
+    pub fn load_battery(&self, emulator: &mut Emulator) -> io::Result<bool> {
+        match self.storage.read(&self.battery_key())? {
+            Some(save) => {
+                mmu::set_battery_save(&mut emulator.memory, save);
+                Ok(true)
+            }
+            None => Ok(false)
+        }
+    }
+
+    #[cfg(feature = "internals")]
+    pub fn delete_battery(&mut self) -> io::Result<()> {
+        let key = self.battery_key();
+        self.storage.delete(&key)
+    }
+}
+
+#[cfg(any(test, feature = "internals"))]
+pub struct FileSaveStorage {
+    pub directory: PathBuf
+}
+
+#[cfg(any(test, feature = "internals"))]
+impl FileSaveStorage {
+    pub fn new(directory: impl Into<PathBuf>) -> FileSaveStorage {
+        FileSaveStorage {
+            directory: directory.into()
+        }
+    }
+
+    fn path(&self, key: &str) -> io::Result<PathBuf> {
+        if key.is_empty() || key.contains(['/', '\\']) || key.starts_with('.') {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("Invalid save key: {}", key)));
+        }
+        Ok(self.directory.join(key))
+    }
+}
+
+#[cfg(any(test, feature = "internals"))]
+impl SaveStorage for FileSaveStorage {
+    fn list(&self, prefix: &str) -> io::Result<Vec<String>> {
+        if !self.directory.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut keys = Vec::new();
+        for entry in fs::read_dir(&self.directory)? {
+            let entry = entry?;
+            if let Some(name) = entry.file_name().to_str() {
+                if name.starts_with(prefix) && entry.file_type()?.is_file() {
+                    keys.push(name.to_string());
+                }
+            }
+        }
+        Ok(keys)
+    }
+
+    fn read(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        match fs::read(self.path(key)?) {
+            Ok(data) => Ok(Some(data)),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error)
+        }
+    }
+
+    fn write(&mut self, key: &str, data: &[u8]) -> io::Result<()> {
+        let path = self.path(key)?;
+        fs::create_dir_all(&self.directory)?;
+
+        // Write to a temporary file first so a crash mid-write can't corrupt an existing save.
+        let temporary_path = path.with_extension("tmp");
+        fs::write(&temporary_path, data)?;
+        fs::rename(&temporary_path, &path)
+    }
+
+    fn delete(&mut self, key: &str) -> io::Result<()> {
+        match fs::remove_file(self.path(key)?) {
+            Err(error) if error.kind() != ErrorKind::NotFound => Err(error),
+            _ => Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test_utils {
+    use std::collections::BTreeMap;
+    use std::io;
+    use crate::save_slots::SaveStorage;
+
+    #[derive(Default)]
+    pub struct MemorySaveStorage {
+        pub entries: BTreeMap<String, Vec<u8>>
+    }
+
+    impl SaveStorage for MemorySaveStorage {
+        fn list(&self, prefix: &str) -> io::Result<Vec<String>> {
+            Ok(self.entries.keys().filter(|key| key.starts_with(prefix)).cloned().collect())
+        }
+
+        fn read(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+            Ok(self.entries.get(key).cloned())
+        }
+
+        fn write(&mut self, key: &str, data: &[u8]) -> io::Result<()> {
+            self.entries.insert(key.to_string(), data.to_vec());
+            Ok(())
+        }
+
+        fn delete(&mut self, key: &str) -> io::Result<()> {
+            self.entries.remove(key);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::initialize_screenless_emulator;
+    use crate::mmu::constants::*;
+    use crate::mmu::effects::empty_cartridge_effects;
+    use crate::mmu::test_utils::*;
+    use crate::save_slots::test_utils::*;
+    use super::*;
+
+    fn setup_emulator(title: &str, global_checksum: u16) -> (Emulator, CartridgeHeader) {
+        let mut emulator = initialize_screenless_emulator();
+        let mut rom = build_rom(CART_TYPE_MBC1_WITH_RAM_PLUS_BATTERY, ROM_SIZE_64KB, RAM_SIZE_8KB);
+        rom[TITLE_START_ADDRESS..TITLE_START_ADDRESS + title.len()].copy_from_slice(title.as_bytes());
+        rom[GLOBAL_CHECKSUM_ADDRESS] = (global_checksum >> 8) as u8;
+        rom[GLOBAL_CHECKSUM_ADDRESS + 1] = global_checksum as u8;
+        let header = mmu::load_rom_buffer(&mut emulator.memory, rom, empty_cartridge_effects()).unwrap();
+        (emulator, header)
+    }
+
+    #[test]
+    fn should_namespace_saves_by_title_and_global_checksum() {
+        let (_, header) = setup_emulator("POKEMON RED", 0x91E6);
+        assert_eq!(as_save_namespace(&header), "POKEMON_RED-91E6");
+    }
+
+    #[test]
+    fn should_save_list_and_load_state_slots() {
+        let (mut emulator, header) = setup_emulator("TETRIS", 0x1234);
+        let mut manager = SaveSlotManager::new(MemorySaveStorage::default(), &header);
+
+        emulator.cpu.registers.a = 0x55;
+        manager.save_state(&emulator, 3).unwrap();
+        emulator.cpu.registers.a = 0x66;
+        manager.save_state(&emulator, 1).unwrap();
+
+        assert_eq!(manager.list_slots().unwrap(), vec![1, 3]);
+
+        manager.load_state(&mut emulator, 3).unwrap();
+        assert_eq!(emulator.cpu.registers.a, 0x55);
+
+        manager.delete_state(3).unwrap();
+        assert_eq!(manager.list_slots().unwrap(), vec![1]);
+        assert!(manager.load_state(&mut emulator, 3).is_err());
+    }
+
+    #[test]
+    fn should_list_slots_with_thumbnails() {
+        let (mut emulator, header) = setup_emulator("TETRIS", 0x1234);
+        let mut manager = SaveSlotManager::new(MemorySaveStorage::default(), &header);
+
+        emulator.gpu.frame_buffer.fill(0x40);
+        manager.save_state(&emulator, 2).unwrap();
+
+        let previews = manager.list_slot_previews().unwrap();
+        assert_eq!(previews.len(), 1);
+        assert_eq!(previews[0].slot, 2);
+
+        let thumbnail = previews[0].thumbnail.as_ref().unwrap();
+        assert_eq!((thumbnail.width, thumbnail.height), (80, 72));
+        assert_eq!(thumbnail.pixels.len(), 80 * 72 * 4);
+        assert!(thumbnail.pixels.iter().all(|&value| value == 0x40));
+    }
+
+    #[test]
+    fn should_not_list_slots_belonging_to_other_roms() {
+        let (emulator, header) = setup_emulator("TETRIS", 0x1234);
+        let (_, other_header) = setup_emulator("TETRIS", 0x4321);
+
+        let mut manager = SaveSlotManager::new(MemorySaveStorage::default(), &header);
+        manager.save_state(&emulator, 0).unwrap();
+
+        let other_manager = SaveSlotManager::new(manager.storage, &other_header);
+        assert!(other_manager.list_slots().unwrap().is_empty());
+    }
+
+    #[test]
+    fn should_save_and_load_battery_ram() {
+        let (mut emulator, header) = setup_emulator("ZELDA", 0xABCD);
+        let mut manager = SaveSlotManager::new(MemorySaveStorage::default(), &header);
+
+        assert!(!manager.load_battery(&mut emulator).unwrap());
+
+        let mut ram = vec![0; 0x2000];
+        ram[0x10] = 0x99;
+        mmu::set_cartridge_ram(&mut emulator.memory, ram);
+        manager.save_battery(&emulator).unwrap();
+
+        mmu::set_cartridge_ram(&mut emulator.memory, vec![0; 0x2000]);
+        assert!(manager.load_battery(&mut emulator).unwrap());
+        assert_eq!(mmu::get_cartridge_ram(&emulator.memory)[0x10], 0x99);
+    }
+
+    #[test]
+    fn should_store_saves_as_files() {
+        let directory = std::env::temp_dir().join(format!("retroboy-save-slots-{}", std::process::id()));
+        let mut storage = FileSaveStorage::new(&directory);
+
+        storage.write("GAME-0000.slot0.state", &[1, 2, 3]).unwrap();
+        assert_eq!(storage.read("GAME-0000.slot0.state").unwrap(), Some(vec![1, 2, 3]));
+        assert_eq!(storage.list("GAME-0000").unwrap(), vec!["GAME-0000.slot0.state"]);
+
+        storage.delete("GAME-0000.slot0.state").unwrap();
+        assert_eq!(storage.read("GAME-0000.slot0.state").unwrap(), None);
+        assert!(storage.write("../escape", &[0]).is_err());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
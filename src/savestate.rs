@@ -0,0 +1,1064 @@
+use crate::apu::CPU_RATE;
+use crate::apu::envelope::Envelope;
+use crate::apu::length::Length;
+use crate::apu::noise::NoiseChannel;
+use crate::apu::period::Period;
+use crate::apu::pulse::PulseChannel;
+use crate::apu::register_log;
+use crate::apu::sweep::Sweep;
+use crate::apu::wave::WaveChannel;
+use crate::cpu::hdma::VRAMTransferMode;
+use crate::emulated_rtc;
+use crate::emulator::{Emulator, Mode};
+use crate::gpu::constants::{BYTES_PER_COLOR, GB_SCREEN_HEIGHT, GB_SCREEN_WIDTH};
+use crate::gpu::sprites::Sprite;
+use crate::mmu::{self, WORKING_RAM_SIZE};
+use crate::savestate::bess::BessBuffers;
+use crate::stats;
+use std::io::{self, Error, ErrorKind};
+
+const SAVESTATE_MAGIC: &[u8; 4] = b"RBSS";
+pub const SAVESTATE_VERSION: u8 = 6;
+// Version 1 states are the same apart from not having a thumbnail.
+const THUMBNAIL_VERSION: u8 = 2;
+// Earlier states only kept whole T-cycles since the last audio sample, in a byte.
+const SAMPLE_PHASE_VERSION: u8 = 3;
+// Earlier states padded working RAM out to 64KB, as it used to be over-allocated.
+const COMPACT_WORKING_RAM_VERSION: u8 = 4;
+// Earlier states didn't keep the lag frame count, so it starts over when loading one.
+const LAG_FRAME_VERSION: u8 = 5;
+// Earlier states kept the MBC6 flash at the end of the mapper state, rather than after the cartridge RAM.
+const CARTRIDGE_FLASH_VERSION: u8 = 6;
+const THUMBNAIL_SCALE: u32 = 2;
+const UNUSED_WORKING_RAM_SIZE: usize = 0x10000 - WORKING_RAM_SIZE;
+
+// A downscaled RGBA screenshot of the frame on screen when a state was saved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Thumbnail {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>
+}
+
+pub struct StateWriter {
+    buffer: Vec<u8>
+}
+
+impl StateWriter {
+    pub fn new() -> StateWriter {
+        StateWriter {
+            buffer: Vec::new()
+        }
+    }
+
+    // Writes into an existing buffer, reusing its capacity.
+    pub fn with_buffer(buffer: Vec<u8>) -> StateWriter {
+        StateWriter::with_buffer_prefix(buffer, 0)
+    }
+
+    // Like with_buffer, but keeps the first `length` bytes and writes after them.
+    pub fn with_buffer_prefix(mut buffer: Vec<u8>, length: usize) -> StateWriter {
+        buffer.truncate(length);
+        StateWriter { buffer }
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.buffer.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.buffer.push(if value { 1 } else { 0 });
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_i16(&mut self, value: i16) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_f32(&mut self, value: f32) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_f64(&mut self, value: f64) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    pub fn write_sized_bytes(&mut self, bytes: &[u8]) {
+        self.write_u32(bytes.len() as u32);
+        self.write_bytes(bytes);
+    }
+
+    pub fn write_string(&mut self, value: &str) {
+        self.write_sized_bytes(value.as_bytes());
+    }
+
+    pub fn position(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buffer
+    }
+}
+
+impl Default for StateWriter {
+    fn default() -> Self {
+        StateWriter::new()
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize
+}
+
+fn truncated_state_error() -> Error {
+    Error::new(ErrorKind::InvalidData, "Savestate data is truncated.")
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> StateReader<'a> {
+        StateReader {
+            data,
+            position: 0
+        }
+    }
+
+    pub fn read_bytes(&mut self, length: usize) -> io::Result<&'a [u8]> {
+        let end = self.position.checked_add(length).ok_or_else(truncated_state_error)?;
+        if end > self.data.len() {
+            return Err(truncated_state_error());
+        }
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.read_bytes(N)?);
+        Ok(array)
+    }
+
+    pub fn read_u8(&mut self) -> io::Result<u8> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> io::Result<bool> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_i16(&mut self) -> io::Result<i16> {
+        Ok(i16::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_f32(&mut self) -> io::Result<f32> {
+        Ok(f32::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_f64(&mut self) -> io::Result<f64> {
+        Ok(f64::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_into(&mut self, destination: &mut [u8]) -> io::Result<()> {
+        destination.copy_from_slice(self.read_bytes(destination.len())?);
+        Ok(())
+    }
+
+    pub fn read_sized_bytes(&mut self) -> io::Result<&'a [u8]> {
+        let length = self.read_u32()? as usize;
+        self.read_bytes(length)
+    }
+
+    pub fn read_string(&mut self) -> io::Result<String> {
+        let bytes = self.read_sized_bytes()?;
+        String::from_utf8(bytes.to_vec())
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "Savestate contains an invalid string."))
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.position
+    }
+}
+
+fn write_cpu(writer: &mut StateWriter, emulator: &Emulator) {
+    let registers = &emulator.cpu.registers;
+    writer.write_u8(registers.a);
+    writer.write_u8(registers.b);
+    writer.write_u8(registers.c);
+    writer.write_u8(registers.d);
+    writer.write_u8(registers.e);
+    writer.write_u8(registers.h);
+    writer.write_u8(registers.l);
+    writer.write_u8(registers.f);
+    writer.write_u8(registers.opcode);
+    writer.write_u16(registers.program_counter);
+    writer.write_u16(registers.stack_pointer);
+
+    writer.write_u8(emulator.cpu.clock.instruction_clock_cycles);
+    writer.write_u32(emulator.cpu.clock.total_clock_cycles);
+    writer.write_bool(emulator.cpu.halted);
+    writer.write_bool(emulator.cpu.halt_bug);
+    writer.write_u8(emulator.cpu.interrupts.enable_delay);
+    writer.write_u8(emulator.cpu.interrupts.disable_delay);
+    writer.write_bool(emulator.cpu.interrupts.enabled);
+
+    writer.write_u8(emulator.interrupts.enabled);
+    writer.write_u8(emulator.interrupts.flags);
+}
+
+fn read_cpu(reader: &mut StateReader, emulator: &mut Emulator) -> io::Result<()> {
+    let registers = &mut emulator.cpu.registers;
+    registers.a = reader.read_u8()?;
+    registers.b = reader.read_u8()?;
+    registers.c = reader.read_u8()?;
+    registers.d = reader.read_u8()?;
+    registers.e = reader.read_u8()?;
+    registers.h = reader.read_u8()?;
+    registers.l = reader.read_u8()?;
+    registers.f = reader.read_u8()?;
+    registers.opcode = reader.read_u8()?;
+    registers.program_counter = reader.read_u16()?;
+    registers.stack_pointer = reader.read_u16()?;
+
+    emulator.cpu.clock.instruction_clock_cycles = reader.read_u8()?;
+    emulator.cpu.clock.total_clock_cycles = reader.read_u32()?;
+    emulator.cpu.halted = reader.read_bool()?;
+    emulator.cpu.halt_bug = reader.read_bool()?;
+    emulator.cpu.interrupts.enable_delay = reader.read_u8()?;
+    emulator.cpu.interrupts.disable_delay = reader.read_u8()?;
+    emulator.cpu.interrupts.enabled = reader.read_bool()?;
+
+    emulator.interrupts.enabled = reader.read_u8()?;
+    emulator.interrupts.flags = reader.read_u8()?;
+    Ok(())
+}
+
+fn write_timers(writer: &mut StateWriter, emulator: &Emulator) {
+    let timers = &emulator.timers;
+    writer.write_u8(timers.m_cycles_clock);
+    writer.write_u8(timers.divider_clock);
+    writer.write_u8(timers.base_clock);
+    writer.write_u8(timers.divider);
+    writer.write_u8(timers.counter);
+    writer.write_u8(timers.modulo);
+    writer.write_u8(timers.control);
+}
+
+fn read_timers(reader: &mut StateReader, emulator: &mut Emulator) -> io::Result<()> {
+    let timers = &mut emulator.timers;
+    timers.m_cycles_clock = reader.read_u8()?;
+    timers.divider_clock = reader.read_u8()?;
+    timers.base_clock = reader.read_u8()?;
+    timers.divider = reader.read_u8()?;
+    timers.counter = reader.read_u8()?;
+    timers.modulo = reader.read_u8()?;
+    timers.control = reader.read_u8()?;
+    Ok(())
+}
+
+fn write_memory(writer: &mut StateWriter, emulator: &Emulator, buffers: &mut BessBuffers, include_cartridge_ram: bool) {
+    writer.write_bool(emulator.memory.in_bios);
+    buffers.ram = writer.position();
+    writer.write_bytes(&emulator.memory.working_ram);
+    buffers.hram = writer.position();
+    writer.write_bytes(&emulator.memory.zero_page_ram);
+    writer.write_u8(emulator.memory.svbk);
+
+    if include_cartridge_ram {
+        let cartridge = emulator.memory.cartridge_mapper.get_cartridge();
+        writer.write_sized_bytes(&cartridge.ram);
+        buffers.cartridge_ram = writer.position() - cartridge.ram.len();
+        write_cartridge_flash(writer, emulator);
+    }
+    emulator.memory.cartridge_mapper.serialize_state(writer);
+}
+
+fn read_memory(reader: &mut StateReader, emulator: &mut Emulator, version: u8, include_cartridge_ram: bool) -> io::Result<()> {
+    emulator.memory.in_bios = reader.read_bool()?;
+    reader.read_into(&mut emulator.memory.working_ram)?;
+    if version < COMPACT_WORKING_RAM_VERSION {
+        reader.read_bytes(UNUSED_WORKING_RAM_SIZE)?;
+    }
+    reader.read_into(&mut emulator.memory.zero_page_ram)?;
+    emulator.memory.svbk = reader.read_u8()?;
+
+    if include_cartridge_ram {
+        apply_cartridge_ram(emulator, reader.read_sized_bytes()?);
+        if version >= CARTRIDGE_FLASH_VERSION {
+            apply_cartridge_flash(emulator, reader.read_bytes(cartridge_flash_size(emulator))?);
+        }
+    }
+    emulator.memory.cartridge_mapper.deserialize_state(reader)?;
+
+    if version < CARTRIDGE_FLASH_VERSION {
+        apply_cartridge_flash(emulator, reader.read_bytes(cartridge_flash_size(emulator))?);
+    }
+    Ok(())
+}
+
+pub fn apply_cartridge_ram(emulator: &mut Emulator, ram: &[u8]) {
+    // Copying into the existing RAM avoids an allocation, which matters for frequent snapshots.
+    let cartridge_ram = emulator.memory.cartridge_mapper.get_cartridge_ram_mut();
+    if cartridge_ram.len() == ram.len() {
+        cartridge_ram.copy_from_slice(ram);
+    }
+    else {
+        emulator.memory.cartridge_mapper.set_cartridge_ram(ram.to_vec());
+    }
+    mmu::invalidate_cartridge_ram_version(&mut emulator.memory);
+}
+
+pub fn cartridge_flash_size(emulator: &Emulator) -> usize {
+    emulator.memory.cartridge_mapper.get_flash().map_or(0, |flash| flash.len())
+}
+
+pub fn write_cartridge_flash(writer: &mut StateWriter, emulator: &Emulator) {
+    if let Some(flash) = emulator.memory.cartridge_mapper.get_flash() {
+        writer.write_bytes(flash);
+    }
+}
+
+pub fn apply_cartridge_flash(emulator: &mut Emulator, flash: &[u8]) {
+    if let Some(cartridge_flash) = emulator.memory.cartridge_mapper.get_flash_mut() {
+        cartridge_flash.copy_from_slice(flash);
+        mmu::invalidate_cartridge_ram_version(&mut emulator.memory);
+    }
+}
+
+fn write_sprite(writer: &mut StateWriter, sprite: &Sprite) {
+    writer.write_i16(sprite.y_pos);
+    writer.write_i16(sprite.x_pos);
+    writer.write_u8(sprite.tile_index);
+    writer.write_bool(sprite.priority);
+    writer.write_bool(sprite.y_flip);
+    writer.write_bool(sprite.x_flip);
+    writer.write_u8(sprite.dmg_palette);
+    writer.write_u16(sprite.oam_index);
+    writer.write_bool(sprite.cgb_from_bank_one);
+    writer.write_u8(sprite.cgb_palette);
+}
+
+fn read_sprite(reader: &mut StateReader) -> io::Result<Sprite> {
+    Ok(Sprite {
+        y_pos: reader.read_i16()?,
+        x_pos: reader.read_i16()?,
+        tile_index: reader.read_u8()?,
+        priority: reader.read_bool()?,
+        y_flip: reader.read_bool()?,
+        x_flip: reader.read_bool()?,
+        dmg_palette: reader.read_u8()?,
+        oam_index: reader.read_u16()?,
+        cgb_from_bank_one: reader.read_bool()?,
+        cgb_palette: reader.read_u8()?
+    })
+}
+
+fn write_gpu(writer: &mut StateWriter, emulator: &Emulator, buffers: &mut BessBuffers) {
+    let gpu = &emulator.gpu;
+    writer.write_u8(gpu.mode);
+    writer.write_u16(gpu.mode_clock);
+
+    let registers = &gpu.registers;
+    writer.write_u8(registers.lcdc);
+    writer.write_u8(registers.scy);
+    writer.write_u8(registers.scx);
+    writer.write_u8(registers.wx);
+    writer.write_u8(registers.wy);
+    writer.write_u8(registers.wly);
+    writer.write_u8(registers.ly);
+    writer.write_u8(registers.lyc);
+    writer.write_u8(registers.stat);
+    writer.write_u8(registers.cgb_vbk);
+    writer.write_u8(registers.cgb_opri);
+    writer.write_u8(registers.key0);
+
+    let palettes = &registers.palettes;
+    writer.write_u8(palettes.bgp);
+    writer.write_u8(palettes.obp0);
+    writer.write_u8(palettes.obp1);
+    buffers.background_palettes = writer.position();
+    writer.write_bytes(&palettes.cgb_bcpd);
+    buffers.object_palettes = writer.position();
+    writer.write_bytes(&palettes.cgb_ocpd);
+    writer.write_u8(palettes.cgb_bcps);
+    writer.write_u8(palettes.cgb_ocps);
+
+    writer.write_u8(gpu.sprite_buffer.len() as u8);
+    for sprite in &gpu.sprite_buffer {
+        write_sprite(writer, sprite);
+    }
+
+    buffers.vram = writer.position();
+    writer.write_bytes(&gpu.video_ram);
+    buffers.oam = writer.position();
+    writer.write_bytes(&gpu.object_attribute_memory);
+}
+
+fn read_gpu(reader: &mut StateReader, emulator: &mut Emulator) -> io::Result<()> {
+    let gpu = &mut emulator.gpu;
+    gpu.mode = reader.read_u8()?;
+    gpu.mode_clock = reader.read_u16()?;
+
+    let registers = &mut gpu.registers;
+    registers.lcdc = reader.read_u8()?;
+    registers.scy = reader.read_u8()?;
+    registers.scx = reader.read_u8()?;
+    registers.wx = reader.read_u8()?;
+    registers.wy = reader.read_u8()?;
+    registers.wly = reader.read_u8()?;
+    registers.ly = reader.read_u8()?;
+    registers.lyc = reader.read_u8()?;
+    registers.stat = reader.read_u8()?;
+    registers.cgb_vbk = reader.read_u8()?;
+    registers.cgb_opri = reader.read_u8()?;
+    registers.key0 = reader.read_u8()?;
+
+    let palettes = &mut registers.palettes;
+    palettes.bgp = reader.read_u8()?;
+    palettes.obp0 = reader.read_u8()?;
+    palettes.obp1 = reader.read_u8()?;
+    reader.read_into(&mut palettes.cgb_bcpd)?;
+    reader.read_into(&mut palettes.cgb_ocpd)?;
+    palettes.cgb_bcps = reader.read_u8()?;
+    palettes.cgb_ocps = reader.read_u8()?;
+
+    let sprite_count = reader.read_u8()?;
+    gpu.sprite_buffer.clear();
+    for _ in 0..sprite_count {
+        let sprite = read_sprite(reader)?;
+        gpu.sprite_buffer.push(sprite);
+    }
+
+    reader.read_into(&mut gpu.video_ram)?;
+    reader.read_into(&mut gpu.object_attribute_memory)?;
+    Ok(())
+}
+
+fn write_length(writer: &mut StateWriter, length: &Length) {
+    writer.write_u8(length.initial_settings);
+    writer.write_u16(length.timer);
+}
+
+fn read_length(reader: &mut StateReader, length: &mut Length) -> io::Result<()> {
+    length.initial_settings = reader.read_u8()?;
+    length.timer = reader.read_u16()?;
+    Ok(())
+}
+
+fn write_envelope(writer: &mut StateWriter, envelope: &Envelope) {
+    writer.write_u8(envelope.initial_settings);
+    writer.write_u8(envelope.current_volume);
+    writer.write_u8(envelope.timer);
+}
+
+fn read_envelope(reader: &mut StateReader, envelope: &mut Envelope) -> io::Result<()> {
+    envelope.initial_settings = reader.read_u8()?;
+    envelope.current_volume = reader.read_u8()?;
+    envelope.timer = reader.read_u8()?;
+    Ok(())
+}
+
+fn write_period(writer: &mut StateWriter, period: &Period) {
+    writer.write_u8(period.low);
+    writer.write_u8(period.high);
+    writer.write_u16(period.divider);
+    writer.write_bool(period.reloaded);
+}
+
+fn read_period(reader: &mut StateReader, period: &mut Period) -> io::Result<()> {
+    period.low = reader.read_u8()?;
+    period.high = reader.read_u8()?;
+    period.divider = reader.read_u16()?;
+    period.reloaded = reader.read_bool()?;
+    Ok(())
+}
+
+fn write_sweep(writer: &mut StateWriter, sweep: &Sweep) {
+    writer.write_u8(sweep.initial_settings);
+    writer.write_bool(sweep.enabled);
+    writer.write_u16(sweep.shadow_frequency);
+    writer.write_u8(sweep.timer);
+    writer.write_bool(sweep.frequency_calculated);
+}
+
+fn read_sweep(reader: &mut StateReader, sweep: &mut Sweep) -> io::Result<()> {
+    sweep.initial_settings = reader.read_u8()?;
+    sweep.enabled = reader.read_bool()?;
+    sweep.shadow_frequency = reader.read_u16()?;
+    sweep.timer = reader.read_u8()?;
+    sweep.frequency_calculated = reader.read_bool()?;
+    Ok(())
+}
+
+fn write_pulse_channel(writer: &mut StateWriter, channel: &PulseChannel) {
+    writer.write_bool(channel.enabled);
+    writer.write_bool(channel.dac_enabled);
+    writer.write_u8(channel.wave_duty_position);
+    write_sweep(writer, &channel.sweep);
+    write_length(writer, &channel.length);
+    write_envelope(writer, &channel.envelope);
+    write_period(writer, &channel.period);
+}
+
+fn read_pulse_channel(reader: &mut StateReader, channel: &mut PulseChannel) -> io::Result<()> {
+    channel.enabled = reader.read_bool()?;
+    channel.dac_enabled = reader.read_bool()?;
+    channel.wave_duty_position = reader.read_u8()?;
+    read_sweep(reader, &mut channel.sweep)?;
+    read_length(reader, &mut channel.length)?;
+    read_envelope(reader, &mut channel.envelope)?;
+    read_period(reader, &mut channel.period)
+}
+
+fn write_wave_channel(writer: &mut StateWriter, channel: &WaveChannel) {
+    writer.write_bool(channel.enabled);
+    writer.write_bool(channel.dac_enabled);
+    write_length(writer, &channel.length);
+    writer.write_u8(channel.volume);
+    write_period(writer, &channel.period);
+    writer.write_u8(channel.wave_position);
+    writer.write_bytes(&channel.wave_pattern_ram);
+}
+
+fn read_wave_channel(reader: &mut StateReader, channel: &mut WaveChannel) -> io::Result<()> {
+    channel.enabled = reader.read_bool()?;
+    channel.dac_enabled = reader.read_bool()?;
+    read_length(reader, &mut channel.length)?;
+    channel.volume = reader.read_u8()?;
+    read_period(reader, &mut channel.period)?;
+    channel.wave_position = reader.read_u8()?;
+    reader.read_into(&mut channel.wave_pattern_ram)
+}
+
+fn write_noise_channel(writer: &mut StateWriter, channel: &NoiseChannel) {
+    writer.write_bool(channel.enabled);
+    writer.write_bool(channel.dac_enabled);
+    write_length(writer, &channel.length);
+    write_envelope(writer, &channel.envelope);
+    writer.write_u8(channel.polynomial);
+    writer.write_u16(channel.lfsr);
+    writer.write_u8(channel.control);
+    writer.write_u16(channel.period_divider);
+    writer.write_u16(channel.instruction_cycles);
+}
+
+fn read_noise_channel(reader: &mut StateReader, channel: &mut NoiseChannel) -> io::Result<()> {
+    channel.enabled = reader.read_bool()?;
+    channel.dac_enabled = reader.read_bool()?;
+    read_length(reader, &mut channel.length)?;
+    read_envelope(reader, &mut channel.envelope)?;
+    channel.polynomial = reader.read_u8()?;
+    channel.lfsr = reader.read_u16()?;
+    channel.control = reader.read_u8()?;
+    channel.period_divider = reader.read_u16()?;
+    channel.instruction_cycles = reader.read_u16()?;
+    Ok(())
+}
+
+fn write_apu(writer: &mut StateWriter, emulator: &Emulator) {
+    let apu = &emulator.apu;
+    writer.write_bool(apu.enabled);
+    writer.write_u8(apu.sound_panning);
+    writer.write_u8(apu.master_volume);
+    write_pulse_channel(writer, &apu.channel1);
+    write_pulse_channel(writer, &apu.channel2);
+    write_wave_channel(writer, &apu.channel3);
+    write_noise_channel(writer, &apu.channel4);
+    writer.write_u8(apu.divider_apu);
+    writer.write_u8(apu.last_divider_time);
+    writer.write_u32(apu.sample_phase);
+    writer.write_u8(apu.channel_clock);
+    writer.write_f32(apu.summed_channel1_sample);
+    writer.write_f32(apu.summed_channel2_sample);
+    writer.write_f32(apu.summed_channel3_sample);
+    writer.write_f32(apu.summed_channel4_sample);
+}
+
+fn read_apu(reader: &mut StateReader, emulator: &mut Emulator, version: u8) -> io::Result<()> {
+    let apu = &mut emulator.apu;
+    apu.enabled = reader.read_bool()?;
+    apu.sound_panning = reader.read_u8()?;
+    apu.master_volume = reader.read_u8()?;
+    read_pulse_channel(reader, &mut apu.channel1)?;
+    read_pulse_channel(reader, &mut apu.channel2)?;
+    read_wave_channel(reader, &mut apu.channel3)?;
+    read_noise_channel(reader, &mut apu.channel4)?;
+    apu.divider_apu = reader.read_u8()?;
+    apu.last_divider_time = reader.read_u8()?;
+    /*
+        A sample is due as soon as the phase reaches the clock rate, so anything above
+        it can only come from a corrupt state. Older states counted whole T-cycles, which
+        can overshoot when loaded at a higher sample rate, so those are clamped instead.
+    */
+    apu.sample_phase = if version >= SAMPLE_PHASE_VERSION {
+        let sample_phase = reader.read_u32()?;
+        if sample_phase >= CPU_RATE {
+            return Err(Error::new(ErrorKind::InvalidData, format!("Invalid audio sample phase: {}", sample_phase)));
+        }
+        sample_phase
+    }
+    else {
+        (reader.read_u8()? as u32 * apu.sample_rate).min(CPU_RATE - 1)
+    };
+    apu.channel_clock = reader.read_u8()?;
+    apu.summed_channel1_sample = reader.read_f32()?;
+    apu.summed_channel2_sample = reader.read_f32()?;
+    apu.summed_channel3_sample = reader.read_f32()?;
+    apu.summed_channel4_sample = reader.read_f32()?;
+
+    // Samples generated before the state was captured shouldn't be played back after loading it.
+    apu.left_sample_queue.clear();
+    apu.right_sample_queue.clear();
+    Ok(())
+}
+
+fn write_peripherals(writer: &mut StateWriter, emulator: &Emulator) {
+    writer.write_u8(emulator.keys.column);
+    writer.write_u8(emulator.keys.select_buttons);
+    writer.write_u8(emulator.keys.directional_buttons);
+
+    writer.write_u16(emulator.dma.source);
+    writer.write_u8(emulator.dma.offset);
+    writer.write_u8(emulator.dma.delay);
+    writer.write_bool(emulator.dma.in_progress);
+
+    let hdma = &emulator.hdma;
+    writer.write_u8(hdma.hdma1);
+    writer.write_u8(hdma.hdma2);
+    writer.write_u8(hdma.hdma3);
+    writer.write_u8(hdma.hdma4);
+    writer.write_u16(hdma.offset);
+    writer.write_u8(hdma.transfer_length);
+    writer.write_bool(hdma.transfer_mode == VRAMTransferMode::HBlank);
+    writer.write_bool(hdma.in_progress);
+    writer.write_bool(hdma.completed);
+    writer.write_bool(hdma.hblank_started);
+
+    let serial = &emulator.serial;
+    writer.write_u8(serial.data);
+    writer.write_u16(serial.clock);
+    writer.write_bool(serial.is_high_speed_clock);
+    writer.write_bool(serial.is_master);
+    writer.write_bool(serial.transfer_enabled);
+    writer.write_u8(serial.bits_transferred);
+
+    writer.write_bool(emulator.speed_switch.cgb_double_speed);
+    writer.write_bool(emulator.speed_switch.armed);
+}
+
+fn read_peripherals(reader: &mut StateReader, emulator: &mut Emulator) -> io::Result<()> {
+    emulator.keys.column = reader.read_u8()?;
+    emulator.keys.select_buttons = reader.read_u8()?;
+    emulator.keys.directional_buttons = reader.read_u8()?;
+
+    emulator.dma.source = reader.read_u16()?;
+    emulator.dma.offset = reader.read_u8()?;
+    emulator.dma.delay = reader.read_u8()?;
+    emulator.dma.in_progress = reader.read_bool()?;
+
+    let hdma = &mut emulator.hdma;
+    hdma.hdma1 = reader.read_u8()?;
+    hdma.hdma2 = reader.read_u8()?;
+    hdma.hdma3 = reader.read_u8()?;
+    hdma.hdma4 = reader.read_u8()?;
+    hdma.offset = reader.read_u16()?;
+    hdma.transfer_length = reader.read_u8()?;
+    hdma.transfer_mode = if reader.read_bool()? { VRAMTransferMode::HBlank } else { VRAMTransferMode::GeneralPurpose };
+    hdma.in_progress = reader.read_bool()?;
+    hdma.completed = reader.read_bool()?;
+    hdma.hblank_started = reader.read_bool()?;
+
+    let serial = &mut emulator.serial;
+    serial.data = reader.read_u8()?;
+    serial.clock = reader.read_u16()?;
+    serial.is_high_speed_clock = reader.read_bool()?;
+    serial.is_master = reader.read_bool()?;
+    serial.transfer_enabled = reader.read_bool()?;
+    serial.bits_transferred = reader.read_u8()?;
+
+    emulator.speed_switch.cgb_double_speed = reader.read_bool()?;
+    emulator.speed_switch.armed = reader.read_bool()?;
+    Ok(())
+}
+
+fn write_lag_frames(writer: &mut StateWriter, emulator: &Emulator) {
+    writer.write_u64(emulator.frame_metadata.lag_frames);
+    writer.write_bool(emulator.frame_metadata.joypad_read);
+}
+
+fn read_lag_frames(reader: &mut StateReader, emulator: &mut Emulator, version: u8) -> io::Result<()> {
+    if version >= LAG_FRAME_VERSION {
+        emulator.frame_metadata.lag_frames = reader.read_u64()?;
+        emulator.frame_metadata.joypad_read = reader.read_bool()?;
+    }
+    else {
+        reset_lag_frames(emulator);
+    }
+    Ok(())
+}
+
+fn reset_lag_frames(emulator: &mut Emulator) {
+    emulator.frame_metadata.lag_frames = 0;
+    emulator.frame_metadata.joypad_read = false;
+}
+
+pub fn as_mode_byte(mode: &Mode) -> u8 {
+    match mode {
+        Mode::DMG => 0,
+        Mode::CGB => 1
+    }
+}
+
+// Each thumbnail pixel is the average of a square of frame pixels.
+pub fn capture_thumbnail(emulator: &Emulator) -> Thumbnail {
+    let width = GB_SCREEN_WIDTH / THUMBNAIL_SCALE;
+    let height = GB_SCREEN_HEIGHT / THUMBNAIL_SCALE;
+    let frame = &emulator.gpu.frame_buffer;
+    let mut pixels = Vec::with_capacity((width * height * BYTES_PER_COLOR) as usize);
+
+    for y in 0..height {
+        for x in 0..width {
+            for channel in 0..BYTES_PER_COLOR {
+                let mut sum = 0;
+                for offset_y in 0..THUMBNAIL_SCALE {
+                    for offset_x in 0..THUMBNAIL_SCALE {
+                        let frame_x = x * THUMBNAIL_SCALE + offset_x;
+                        let frame_y = y * THUMBNAIL_SCALE + offset_y;
+                        sum += frame[((frame_y * GB_SCREEN_WIDTH + frame_x) * BYTES_PER_COLOR + channel) as usize] as u32;
+                    }
+                }
+                pixels.push((sum / (THUMBNAIL_SCALE * THUMBNAIL_SCALE)) as u8);
+            }
+        }
+    }
+
+    Thumbnail { width, height, pixels }
+}
+
+fn write_thumbnail(writer: &mut StateWriter, thumbnail: &Thumbnail) {
+    writer.write_u16(thumbnail.width as u16);
+    writer.write_u16(thumbnail.height as u16);
+    writer.write_sized_bytes(&thumbnail.pixels);
+}
+
+fn read_thumbnail_section(reader: &mut StateReader) -> io::Result<Thumbnail> {
+    Ok(Thumbnail {
+        width: reader.read_u16()? as u32,
+        height: reader.read_u16()? as u32,
+        pixels: reader.read_sized_bytes()?.to_vec()
+    })
+}
+
+fn read_header<'a>(state: &'a [u8]) -> io::Result<(StateReader<'a>, u8)> {
+    let mut reader = StateReader::new(state);
+
+    if reader.read_bytes(SAVESTATE_MAGIC.len())? != SAVESTATE_MAGIC {
+        return Err(Error::new(ErrorKind::InvalidData, "Data is not a Retro Boy savestate."));
+    }
+
+    let version = reader.read_u8()?;
+    if version == 0 || version > SAVESTATE_VERSION {
+        return Err(Error::new(ErrorKind::InvalidData, format!("Unsupported savestate version: {}", version)));
+    }
+
+    Ok((reader, version))
+}
+
+/*
+    Reads just the thumbnail, which comes right after the header, so frontends can
+    show previews of save slots without loading them. States saved before thumbnails
+    were added don't have one.
+*/
+pub fn read_thumbnail(state: &[u8]) -> io::Result<Option<Thumbnail>> {
+    let (mut reader, version) = read_header(state)?;
+    if version < THUMBNAIL_VERSION {
+        return Ok(None);
+    }
+
+    reader.read_u8()?;
+    reader.read_string()?;
+    read_thumbnail_section(&mut reader).map(Some)
+}
+
+fn cartridge_title(emulator: &Emulator) -> &str {
+    &emulator.memory.cartridge_mapper.get_cartridge().header.title
+}
+
+// The emulator state itself, shared by savestates and in-memory snapshots.
+fn write_all_sections(writer: &mut StateWriter, emulator: &Emulator, include_cartridge_ram: bool) -> BessBuffers {
+    let mut buffers = BessBuffers::default();
+    write_cpu(writer, emulator);
+    write_timers(writer, emulator);
+    write_memory(writer, emulator, &mut buffers, include_cartridge_ram);
+    write_gpu(writer, emulator, &mut buffers);
+    write_apu(writer, emulator);
+    write_peripherals(writer, emulator);
+    write_lag_frames(writer, emulator);
+    buffers
+}
+
+fn read_all_sections(reader: &mut StateReader, emulator: &mut Emulator, version: u8, include_cartridge_ram: bool) -> io::Result<()> {
+    read_cpu(reader, emulator)?;
+    read_timers(reader, emulator)?;
+    read_memory(reader, emulator, version, include_cartridge_ram)?;
+    read_gpu(reader, emulator)?;
+    read_apu(reader, emulator, version)?;
+    read_peripherals(reader, emulator)?;
+    read_lag_frames(reader, emulator, version)
+}
+
+pub fn write_sections(writer: &mut StateWriter, emulator: &Emulator) -> BessBuffers {
+    write_all_sections(writer, emulator, true)
+}
+
+pub fn read_sections(reader: &mut StateReader, emulator: &mut Emulator, version: u8) -> io::Result<()> {
+    read_all_sections(reader, emulator, version, true)
+}
+
+// Snapshots keep the cartridge RAM apart from the rest of the state, so it can be left alone when unchanged.
+pub fn write_sections_without_cartridge_ram(writer: &mut StateWriter, emulator: &Emulator) {
+    write_all_sections(writer, emulator, false);
+}
+
+pub fn read_sections_without_cartridge_ram(reader: &mut StateReader, emulator: &mut Emulator, version: u8) -> io::Result<()> {
+    read_all_sections(reader, emulator, version, false)
+}
+
+/*
+    Savestates hold everything needed to resume emulation at an instruction boundary. The ROM itself,
+    registered cheats, and frontend configuration (render callback, sample rate, etc.) are not
+    included, so a state can only be loaded into an emulator that has the same cartridge loaded.
+    A BESS footer follows the Retro Boy data so other emulators can load the state too.
+*/
+pub fn encode_state(emulator: &Emulator) -> Vec<u8> {
+    let mut writer = StateWriter::new();
+
+    writer.write_bytes(SAVESTATE_MAGIC);
+    writer.write_u8(SAVESTATE_VERSION);
+    writer.write_u8(as_mode_byte(&emulator.mode));
+    writer.write_string(cartridge_title(emulator));
+    write_thumbnail(&mut writer, &capture_thumbnail(emulator));
+
+    let buffers = write_sections(&mut writer, emulator);
+    bess::write_bess_footer(&mut writer, emulator, &buffers);
+
+    writer.into_bytes()
+}
+
+fn apply_state(emulator: &mut Emulator, state: &[u8]) -> io::Result<()> {
+    if !state.starts_with(SAVESTATE_MAGIC) && bess::has_bess_footer(state) {
+        bess::apply_bess_state(emulator, state)?;
+        reset_lag_frames(emulator);
+        stats::sync_clock_reference(emulator);
+        emulated_rtc::sync_clock_reference(emulator);
+        register_log::sync_clock_reference(emulator);
+        return Ok(());
+    }
+
+    let (mut reader, version) = read_header(state)?;
+
+    if reader.read_u8()? != as_mode_byte(&emulator.mode) {
+        return Err(Error::new(ErrorKind::InvalidData, "Savestate was created in a different mode."));
+    }
+
+    if reader.read_string()? != cartridge_title(emulator) {
+        return Err(Error::new(ErrorKind::InvalidData, "Savestate belongs to a different cartridge."));
+    }
+
+    if version >= THUMBNAIL_VERSION {
+        read_thumbnail_section(&mut reader)?;
+    }
+
+    read_sections(&mut reader, emulator, version)?;
+
+    stats::sync_clock_reference(emulator);
+    emulated_rtc::sync_clock_reference(emulator);
+    register_log::sync_clock_reference(emulator);
+    Ok(())
+}
+
+pub fn decode_state(emulator: &mut Emulator, state: &[u8]) -> io::Result<()> {
+    // Keep a copy of the current state so a corrupt savestate can't leave the emulator half-loaded.
+    let previous_state = encode_state(emulator);
+
+    apply_state(emulator, state).or_else(|error| {
+        apply_state(emulator, &previous_state)?;
+        Err(error)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::initialize_screenless_emulator;
+    use crate::mmu;
+    use crate::mmu::constants::*;
+    use crate::mmu::effects::empty_cartridge_effects;
+    use crate::mmu::test_utils::*;
+    use super::*;
+
+    fn setup_emulator() -> Emulator {
+        let mut emulator = initialize_screenless_emulator();
+        let rom = build_rom(CART_TYPE_MBC1_WITH_RAM, ROM_SIZE_64KB, RAM_SIZE_8KB);
+        mmu::load_rom_buffer(&mut emulator.memory, rom, empty_cartridge_effects()).unwrap();
+        emulator
+    }
+
+    #[test]
+    fn should_restore_cpu_registers() {
+        let mut emulator = setup_emulator();
+        emulator.cpu.registers.a = 0x3E;
+        emulator.cpu.registers.program_counter = 0x1234;
+        emulator.cpu.registers.stack_pointer = 0xDFF0;
+
+        let state = encode_state(&emulator);
+
+        emulator.cpu.registers.a = 0;
+        emulator.cpu.registers.program_counter = 0;
+        emulator.cpu.registers.stack_pointer = 0;
+
+        decode_state(&mut emulator, &state).unwrap();
+
+        assert_eq!(emulator.cpu.registers.a, 0x3E);
+        assert_eq!(emulator.cpu.registers.program_counter, 0x1234);
+        assert_eq!(emulator.cpu.registers.stack_pointer, 0xDFF0);
+    }
+
+    #[test]
+    fn should_restore_memory_and_mapper_state() {
+        let mut emulator = setup_emulator();
+        mmu::write_byte(&mut emulator, 0x0000, 0xA);
+        mmu::write_byte(&mut emulator, 0xA010, 0x77);
+        emulator.memory.working_ram[0x100] = 0x42;
+        emulator.gpu.video_ram[0x1800] = 0x19;
+
+        let state = encode_state(&emulator);
+
+        mmu::write_byte(&mut emulator, 0xA010, 0x00);
+        mmu::write_byte(&mut emulator, 0x0000, 0x0);
+        emulator.memory.working_ram[0x100] = 0;
+        emulator.gpu.video_ram[0x1800] = 0;
+
+        decode_state(&mut emulator, &state).unwrap();
+
+        assert_eq!(mmu::read_byte(&mut emulator, 0xA010), 0x77);
+        assert_eq!(emulator.memory.working_ram[0x100], 0x42);
+        assert_eq!(emulator.gpu.video_ram[0x1800], 0x19);
+    }
+
+    #[test]
+    fn should_restore_lag_frame_count() {
+        let mut emulator = setup_emulator();
+        emulator.frame_metadata.lag_frames = 12;
+        let state = encode_state(&emulator);
+
+        emulator.frame_metadata.lag_frames = 30;
+        decode_state(&mut emulator, &state).unwrap();
+        assert_eq!(emulator.frame_metadata.lag_frames, 12);
+    }
+
+    #[test]
+    fn should_load_states_with_padded_working_ram() {
+        let mut emulator = setup_emulator();
+        for (index, byte) in emulator.memory.working_ram.iter_mut().enumerate() {
+            *byte = (index / 0x100) as u8;
+        }
+        let state = encode_state(&emulator);
+
+        let ram_end = state.windows(WORKING_RAM_SIZE)
+            .position(|bytes| bytes == &emulator.memory.working_ram[..])
+            .unwrap() + WORKING_RAM_SIZE;
+        let mut old_state = state[..ram_end].to_vec();
+        old_state[SAVESTATE_MAGIC.len()] = COMPACT_WORKING_RAM_VERSION - 1;
+        old_state.extend_from_slice(&[0; UNUSED_WORKING_RAM_SIZE]);
+        old_state.extend_from_slice(&state[ram_end..]);
+
+        emulator.memory.working_ram.fill(0);
+        decode_state(&mut emulator, &old_state).unwrap();
+        assert_eq!(emulator.memory.working_ram[0x7F00], 0x7F);
+        assert_eq!(encode_state(&emulator), state);
+    }
+
+    #[test]
+    fn should_reject_truncated_state_without_modifying_emulator() {
+        let mut emulator = setup_emulator();
+        emulator.cpu.registers.b = 0x11;
+        let state = encode_state(&emulator);
+
+        emulator.cpu.registers.b = 0x22;
+        let result = decode_state(&mut emulator, &state[..state.len() / 2]);
+
+        assert!(result.is_err());
+        assert_eq!(emulator.cpu.registers.b, 0x22);
+    }
+
+    #[test]
+    fn should_reject_state_with_invalid_sample_phase() {
+        let mut emulator = setup_emulator();
+        emulator.apu.sample_phase = CPU_RATE;
+        let state = encode_state(&emulator);
+
+        emulator.apu.sample_phase = 0;
+        assert!(decode_state(&mut emulator, &state).is_err());
+        assert_eq!(emulator.apu.sample_phase, 0);
+    }
+
+    #[test]
+    fn should_reject_state_from_different_mode() {
+        let mut emulator = setup_emulator();
+        let state = encode_state(&emulator);
+        emulator.mode = Mode::CGB;
+        assert!(decode_state(&mut emulator, &state).is_err());
+    }
+
+    #[test]
+    fn should_reject_data_that_is_not_a_savestate() {
+        let mut emulator = setup_emulator();
+        assert!(decode_state(&mut emulator, &[0x1, 0x2, 0x3, 0x4, 0x5]).is_err());
+    }
+
+    #[test]
+    fn should_embed_downscaled_thumbnail() {
+        let mut emulator = setup_emulator();
+        // Alternate black and white columns average out to grey.
+        for (index, pixel) in emulator.gpu.frame_buffer.chunks_mut(4).enumerate() {
+            pixel.fill(if index.is_multiple_of(2) { 0xFF } else { 0x00 });
+        }
+
+        let state = encode_state(&emulator);
+        let thumbnail = read_thumbnail(&state).unwrap().unwrap();
+
+        assert_eq!((thumbnail.width, thumbnail.height), (80, 72));
+        assert!(thumbnail.pixels.iter().all(|&value| value == 0x7F));
+        assert!(decode_state(&mut emulator, &state).is_ok());
+    }
+}
+
+pub mod bess;
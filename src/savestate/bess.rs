@@ -0,0 +1,422 @@
+use std::io::{self, Error, ErrorKind};
+
+use crate::cpu;
+use crate::emulator::{is_cgb, Emulator, Mode};
+use crate::mmu;
+use crate::mmu::constants::*;
+use crate::savestate::StateWriter;
+
+/*
+    BESS (Best Effort Save State) is a block format that SameBoy, Emulicious and
+    others append to their own savestates, so the common parts of a state can be
+    loaded across emulators. Blocks are a four letter name, a 32-bit length and
+    their contents, and the state ends with the offset of the first block followed
+    by "BESS". The CORE block points at RAM, VRAM and the other memory buffers by
+    offset, so they point into the Retro Boy data in front of the blocks instead of
+    being written twice.
+
+    Only CPU registers, I/O registers, memory and MBC bank registers are exchanged.
+    Anything else, like the exact PPU or APU timing, starts fresh when a state
+    from another emulator is loaded.
+*/
+const BESS_MAGIC: &[u8; 4] = b"BESS";
+const BESS_MAJOR_VERSION: u16 = 1;
+const BESS_MINOR_VERSION: u16 = 1;
+const CORE_BLOCK_SIZE: usize = 0xD0;
+const FOOTER_SIZE: usize = 8;
+
+const DMG_RAM_SIZE: usize = 0x2000;
+const CGB_RAM_SIZE: usize = 0x8000;
+const DMG_VRAM_SIZE: usize = 0x2000;
+const CGB_VRAM_SIZE: usize = 0x4000;
+const OAM_SIZE: usize = 0xA0;
+const HRAM_SIZE: usize = 0x7F;
+const PALETTE_SIZE: usize = 0x40;
+
+// Where the buffers the CORE block refers to were written in the Retro Boy part of the state.
+#[derive(Debug, Default)]
+pub struct BessBuffers {
+    pub ram: usize,
+    pub vram: usize,
+    pub cartridge_ram: usize,
+    pub oam: usize,
+    pub hram: usize,
+    pub background_palettes: usize,
+    pub object_palettes: usize
+}
+
+struct Block<'a> {
+    name: &'a [u8],
+    data: &'a [u8]
+}
+
+fn invalid_bess_error(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("Invalid BESS savestate: {}", message))
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> usize {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]]) as usize
+}
+
+fn write_block(writer: &mut StateWriter, name: &[u8; 4], data: &[u8]) {
+    writer.write_bytes(name);
+    writer.write_u32(data.len() as u32);
+    writer.write_bytes(data);
+}
+
+fn model_identifier(emulator: &Emulator) -> &'static [u8; 4] {
+    match emulator.mode {
+        Mode::DMG => b"GD  ",
+        Mode::CGB => b"CC  "
+    }
+}
+
+fn io_register_snapshot(emulator: &Emulator) -> [u8; 0x80] {
+    let mut registers = [0xFF; 0x80];
+    for (index, register) in registers.iter_mut().enumerate() {
+        *register = mmu::read_io_register(emulator, 0xFF00 + index as u16);
+    }
+    // FF50 isn't readable, but BESS uses it to tell whether the boot ROM has finished.
+    registers[0x50] = if emulator.memory.in_bios { 0x00 } else { 0x01 };
+    registers
+}
+
+fn encode_core_block(emulator: &Emulator, buffers: &BessBuffers) -> Vec<u8> {
+    let registers = &emulator.cpu.registers;
+    let mut writer = StateWriter::new();
+
+    writer.write_u16(BESS_MAJOR_VERSION);
+    writer.write_u16(BESS_MINOR_VERSION);
+    writer.write_bytes(model_identifier(emulator));
+
+    writer.write_u16(cpu::current_instruction_address(emulator));
+    writer.write_u16(((registers.a as u16) << 8) | registers.f as u16);
+    writer.write_u16(((registers.b as u16) << 8) | registers.c as u16);
+    writer.write_u16(((registers.d as u16) << 8) | registers.e as u16);
+    writer.write_u16(((registers.h as u16) << 8) | registers.l as u16);
+    writer.write_u16(registers.stack_pointer);
+    writer.write_bool(emulator.cpu.interrupts.enabled);
+    writer.write_u8(emulator.interrupts.enabled);
+    writer.write_u8(if emulator.cpu.halted { 1 } else { 0 });
+    writer.write_u8(0);
+    writer.write_bytes(&io_register_snapshot(emulator));
+
+    let cgb_mode = is_cgb(emulator);
+    let cartridge_ram_size = emulator.memory.cartridge_mapper.get_cartridge().ram.len();
+    let buffer_sizes = [
+        (if cgb_mode { CGB_RAM_SIZE } else { DMG_RAM_SIZE }, buffers.ram),
+        (if cgb_mode { CGB_VRAM_SIZE } else { DMG_VRAM_SIZE }, buffers.vram),
+        (cartridge_ram_size, buffers.cartridge_ram),
+        (OAM_SIZE, buffers.oam),
+        (HRAM_SIZE, buffers.hram),
+        (if cgb_mode { PALETTE_SIZE } else { 0 }, buffers.background_palettes),
+        (if cgb_mode { PALETTE_SIZE } else { 0 }, buffers.object_palettes)
+    ];
+    for (size, offset) in buffer_sizes {
+        writer.write_u32(size as u32);
+        writer.write_u32(offset as u32);
+    }
+
+    writer.into_bytes()
+}
+
+// The register writes that put the mapper back in its current banking state.
+fn encode_mbc_block(emulator: &Emulator) -> Vec<u8> {
+    let mapper = &emulator.memory.cartridge_mapper;
+    let rom_bank = mapper.get_rom_bank();
+    let ram_bank = mapper.get_ram_bank();
+
+    let ram_enable = (0x0000, if mapper.ram_enabled() { 0x0A } else { 0x00 });
+    let cartridge = mapper.get_cartridge();
+
+    let writes: Vec<(u16, u8)> = match cartridge.header.type_code {
+        CART_TYPE_MBC1 | CART_TYPE_MBC1_WITH_RAM | CART_TYPE_MBC1_WITH_RAM_PLUS_BATTERY if mmu::is_multicart(cartridge) =>
+            vec![ram_enable, (0x4000, (rom_bank >> 4) as u8), (0x2000, rom_bank as u8 & 0x0F), (0x6000, mapper.banking_mode())],
+        /*
+            The upper ROM bank bits and the RAM bank share the 0x4000 register, which
+            one a write lands in depends on the banking mode, so both modes are
+            selected in turn before the real one is restored.
+        */
+        CART_TYPE_MBC1 | CART_TYPE_MBC1_WITH_RAM | CART_TYPE_MBC1_WITH_RAM_PLUS_BATTERY =>
+            vec![ram_enable, (0x6000, 1), (0x4000, ram_bank), (0x6000, 0), (0x4000, (rom_bank >> 5) as u8),
+                (0x2000, rom_bank as u8 & 0x1F), (0x6000, mapper.banking_mode())],
+        CART_TYPE_MBC3 | CART_TYPE_MBC3_RAM | CART_TYPE_MBC3_RAM_BATTERY
+            | CART_TYPE_MBC3_TIMER_BATTERY | CART_TYPE_MBC3_TIMER_RAM_BATTERY =>
+            vec![ram_enable, (0x2000, rom_bank as u8), (0x4000, ram_bank)],
+        CART_TYPE_MBC5 | CART_TYPE_MBC5_RAM | CART_TYPE_MBC5_RAM_BATTERY
+            | CART_TYPE_MBC5_RUMBLE | CART_TYPE_MBC5_RUMBLE_RAM | CART_TYPE_MBC5_RUMBLE_RAM_BATTERY =>
+            vec![ram_enable, (0x2000, rom_bank as u8), (0x3000, (rom_bank >> 8) as u8), (0x4000, ram_bank)],
+        _ => Vec::new()
+    };
+
+    let mut writer = StateWriter::new();
+    for (address, value) in writes {
+        writer.write_u16(address);
+        writer.write_u8(value);
+    }
+    writer.into_bytes()
+}
+
+fn encode_info_block(emulator: &Emulator) -> Vec<u8> {
+    let rom = &emulator.memory.cartridge_mapper.get_cartridge().rom;
+    let mut info = vec![0; 0x12];
+    if rom.len() >= HEADER_END_ADDRESS {
+        info[..0x10].copy_from_slice(&rom[TITLE_START_ADDRESS..=TITLE_END_ADDRESS]);
+        info[0x10..].copy_from_slice(&rom[GLOBAL_CHECKSUM_ADDRESS..GLOBAL_CHECKSUM_ADDRESS + 2]);
+    }
+    info
+}
+
+pub fn write_bess_footer(writer: &mut StateWriter, emulator: &Emulator, buffers: &BessBuffers) {
+    let first_block = writer.position();
+
+    let name = format!("Retro Boy {}", env!("CARGO_PKG_VERSION"));
+    write_block(writer, b"NAME", name.as_bytes());
+    write_block(writer, b"INFO", &encode_info_block(emulator));
+    write_block(writer, b"CORE", &encode_core_block(emulator, buffers));
+    write_block(writer, b"MBC ", &encode_mbc_block(emulator));
+    write_block(writer, b"END ", &[]);
+
+    writer.write_u32(first_block as u32);
+    writer.write_bytes(BESS_MAGIC);
+}
+
+pub fn has_bess_footer(state: &[u8]) -> bool {
+    state.len() >= FOOTER_SIZE && &state[state.len() - 4..] == BESS_MAGIC
+}
+
+fn read_blocks(state: &[u8]) -> io::Result<Vec<Block<'_>>> {
+    let footer_start = state.len() - FOOTER_SIZE;
+    let mut offset = read_u32(state, footer_start);
+    let mut blocks = Vec::new();
+
+    loop {
+        if offset + 8 > footer_start {
+            return Err(invalid_bess_error("blocks run past the footer"));
+        }
+        let name = &state[offset..offset + 4];
+        let length = read_u32(state, offset + 4);
+        let data_start = offset + 8;
+        if data_start + length > footer_start {
+            return Err(invalid_bess_error("block is truncated"));
+        }
+
+        if name == b"END " {
+            return Ok(blocks);
+        }
+        blocks.push(Block { name, data: &state[data_start..data_start + length] });
+        offset = data_start + length;
+    }
+}
+
+fn buffer<'a>(state: &'a [u8], core: &[u8], index: usize) -> io::Result<&'a [u8]> {
+    let size = read_u32(core, 0x98 + index * 8);
+    let offset = read_u32(core, 0x9C + index * 8);
+    state.get(offset..offset + size).ok_or_else(|| invalid_bess_error("buffer is outside of the state"))
+}
+
+fn copy_buffer(destination: &mut [u8], source: &[u8]) {
+    let length = destination.len().min(source.len());
+    destination[..length].copy_from_slice(&source[..length]);
+}
+
+fn apply_io_registers(emulator: &mut Emulator, registers: &[u8]) {
+    // Sound registers are only writable while the APU is on, and writes are ignored while it's off.
+    mmu::write_byte(emulator, 0xFF26, registers[0x26]);
+
+    for (index, value) in registers.iter().enumerate() {
+        let value = *value;
+        match index {
+            // Trigger and transfer start bits would restart what the registers describe.
+            0x02 | 0x14 | 0x19 | 0x1E | 0x23 => mmu::write_byte(emulator, 0xFF00 + index as u16, value & 0x7F),
+            0x00 | 0x01 | 0x04..=0x07 | 0x0F | 0x10..=0x13 | 0x16..=0x18 | 0x1A..=0x1D | 0x20..=0x22
+                | 0x24 | 0x25 | 0x30..=0x3F | 0x41..=0x43 | 0x45 | 0x47..=0x4B | 0x4F | 0x51..=0x54
+                | 0x68 | 0x6A | 0x6C | 0x70 => mmu::write_byte(emulator, 0xFF00 + index as u16, value),
+            _ => {}
+        }
+    }
+
+    // LCDC and LY are set directly, as writing them turns the LCD off or is ignored.
+    emulator.gpu.registers.lcdc = registers[0x40];
+    emulator.gpu.registers.ly = registers[0x44];
+    emulator.gpu.mode = registers[0x41] & 0x3;
+    emulator.gpu.mode_clock = 0;
+    emulator.speed_switch.cgb_double_speed = is_cgb(emulator) && registers[0x4D] & 0x80 != 0;
+    emulator.memory.in_bios = registers[0x50] == 0;
+}
+
+fn apply_core_block(emulator: &mut Emulator, state: &[u8], core: &[u8]) -> io::Result<()> {
+    if core.len() < CORE_BLOCK_SIZE {
+        return Err(invalid_bess_error("CORE block is too short"));
+    }
+    if read_u16(core, 0) != BESS_MAJOR_VERSION {
+        return Err(invalid_bess_error(&format!("unsupported version {}", read_u16(core, 0))));
+    }
+
+    let cgb_state = core[4] == b'C';
+    if cgb_state != is_cgb(emulator) {
+        return Err(Error::new(ErrorKind::InvalidData, "Savestate was created in a different mode."));
+    }
+
+    copy_buffer(&mut emulator.memory.working_ram, buffer(state, core, 0)?);
+    copy_buffer(&mut emulator.gpu.video_ram, buffer(state, core, 1)?);
+    copy_buffer(emulator.memory.cartridge_mapper.get_cartridge_ram_mut(), buffer(state, core, 2)?);
+    mmu::invalidate_cartridge_ram_version(&mut emulator.memory);
+    copy_buffer(&mut emulator.gpu.object_attribute_memory, buffer(state, core, 3)?);
+    copy_buffer(&mut emulator.memory.zero_page_ram, buffer(state, core, 4)?);
+    copy_buffer(&mut emulator.gpu.registers.palettes.cgb_bcpd, buffer(state, core, 5)?);
+    copy_buffer(&mut emulator.gpu.registers.palettes.cgb_ocpd, buffer(state, core, 6)?);
+
+    apply_io_registers(emulator, &core[0x18..0x98]);
+
+    let registers = &mut emulator.cpu.registers;
+    let [a, f] = read_u16(core, 0x0A).to_be_bytes();
+    let [b, c] = read_u16(core, 0x0C).to_be_bytes();
+    let [d, e] = read_u16(core, 0x0E).to_be_bytes();
+    let [h, l] = read_u16(core, 0x10).to_be_bytes();
+    (registers.a, registers.f, registers.b, registers.c) = (a, f & 0xF0, b, c);
+    (registers.d, registers.e, registers.h, registers.l) = (d, e, h, l);
+    registers.stack_pointer = read_u16(core, 0x12);
+    emulator.cpu.interrupts.enabled = core[0x14] != 0;
+    emulator.cpu.interrupts.enable_delay = 0;
+    emulator.cpu.interrupts.disable_delay = 0;
+    emulator.interrupts.enabled = core[0x15];
+    emulator.cpu.halted = core[0x16] != 0;
+
+    // Fetch the instruction at the program counter, as the CPU would have before the state was saved.
+    let program_counter = read_u16(core, 0x08);
+    emulator.cpu.registers.opcode = mmu::read_byte(emulator, program_counter);
+    emulator.cpu.registers.program_counter = program_counter.wrapping_add(1);
+    Ok(())
+}
+
+fn apply_mbc_block(emulator: &mut Emulator, data: &[u8]) {
+    for write in data.chunks_exact(3) {
+        let address = read_u16(write, 0);
+        match address {
+            0x0000..=0x7FFF => emulator.memory.cartridge_mapper.write_rom(address, write[2]),
+            0xA000..=0xBFFF => emulator.memory.cartridge_mapper.write_ram(address & 0x1FFF, write[2]),
+            _ => {}
+        }
+    }
+}
+
+// Loads the parts of a BESS state that Retro Boy shares with other emulators.
+pub fn apply_bess_state(emulator: &mut Emulator, state: &[u8]) -> io::Result<()> {
+    let blocks = read_blocks(state)?;
+
+    if let Some(info) = blocks.iter().find(|block| block.name == b"INFO") {
+        if info.data.len() >= 0x10 && encode_info_block(emulator)[..0x10] != info.data[..0x10] {
+            return Err(Error::new(ErrorKind::InvalidData, "Savestate belongs to a different cartridge."));
+        }
+    }
+
+    let core = blocks.iter().find(|block| block.name == b"CORE")
+        .ok_or_else(|| invalid_bess_error("missing CORE block"))?;
+    apply_core_block(emulator, state, core.data)?;
+
+    if let Some(mbc) = blocks.iter().find(|block| block.name == b"MBC ") {
+        apply_mbc_block(emulator, mbc.data);
+        mmu::invalidate_cartridge_ram_version(&mut emulator.memory);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mmu::test_utils::*;
+    use crate::savestate::{decode_state, encode_state};
+    use super::*;
+
+    fn build_titled_rom() -> Vec<u8> {
+        let mut rom = build_rom(CART_TYPE_MBC5_RAM, ROM_SIZE_128KB, RAM_SIZE_8KB);
+        rom[TITLE_START_ADDRESS..TITLE_START_ADDRESS + 4].copy_from_slice(b"BESS");
+        rom
+    }
+
+    // Another emulator's state, with the BESS blocks but none of the Retro Boy data before them being readable.
+    fn as_foreign_state(mut state: Vec<u8>) -> Vec<u8> {
+        state[..4].copy_from_slice(b"XXXX");
+        state
+    }
+
+    #[test]
+    fn should_append_bess_blocks_to_savestate() {
+        let mut emulator = setup_emulator_with_rom(build_titled_rom());
+        emulator.cpu.registers.program_counter = 0x0151;
+        emulator.cpu.registers.a = 0x12;
+        emulator.cpu.registers.f = 0x80;
+
+        let state = encode_state(&emulator);
+        assert!(has_bess_footer(&state));
+
+        let blocks = read_blocks(&state).unwrap();
+        let names: Vec<&[u8]> = blocks.iter().map(|block| block.name).collect();
+        assert_eq!(names, vec![b"NAME" as &[u8], b"INFO", b"CORE", b"MBC "]);
+
+        let core = blocks[2].data;
+        assert_eq!(core.len(), CORE_BLOCK_SIZE);
+        assert_eq!(&core[4..8], b"GD  ");
+        assert_eq!(read_u16(core, 0x08), 0x0150);
+        assert_eq!(read_u16(core, 0x0A), 0x1280);
+    }
+
+    #[test]
+    fn should_load_state_through_bess_blocks() {
+        let mut emulator = setup_emulator_with_rom(build_titled_rom());
+        mmu::write_byte(&mut emulator, 0x0000, 0x0A);
+        mmu::write_byte(&mut emulator, 0x2000, 0x05);
+        mmu::write_byte(&mut emulator, 0xA010, 0x77);
+        mmu::write_byte(&mut emulator, 0xC123, 0x42);
+        mmu::write_byte(&mut emulator, 0xFF42, 0x19);
+        emulator.gpu.video_ram[0x1800] = 0x33;
+        emulator.cpu.registers.b = 0x9A;
+        emulator.cpu.registers.stack_pointer = 0xDFF0;
+        let state = as_foreign_state(encode_state(&emulator));
+
+        let mut other_emulator = setup_emulator_with_rom(build_titled_rom());
+        decode_state(&mut other_emulator, &state).unwrap();
+
+        assert_eq!(mmu::read_byte(&mut other_emulator, 0xA010), 0x77);
+        assert_eq!(mmu::read_byte(&mut other_emulator, 0xC123), 0x42);
+        assert_eq!(mmu::read_byte(&mut other_emulator, 0xFF42), 0x19);
+        assert_eq!(other_emulator.memory.cartridge_mapper.get_rom_bank(), 5);
+        assert_eq!(other_emulator.gpu.video_ram[0x1800], 0x33);
+        assert_eq!(other_emulator.cpu.registers.b, 0x9A);
+        assert_eq!(other_emulator.cpu.registers.stack_pointer, 0xDFF0);
+    }
+
+    #[test]
+    fn should_restore_mbc1_banking_mode_through_bess_blocks() {
+        let rom = build_rom(CART_TYPE_MBC1_WITH_RAM_PLUS_BATTERY, ROM_SIZE_1MB, RAM_SIZE_32KB);
+        let mut emulator = setup_emulator_with_rom(rom.clone());
+        mmu::write_byte(&mut emulator, 0x0000, 0x0A);
+        mmu::write_byte(&mut emulator, 0x4000, 0x01);
+        mmu::write_byte(&mut emulator, 0x2000, 0x03);
+        mmu::write_byte(&mut emulator, 0x6000, 0x01);
+        mmu::write_byte(&mut emulator, 0x4000, 0x02);
+        let state = as_foreign_state(encode_state(&emulator));
+
+        let mut other_emulator = setup_emulator_with_rom(rom);
+        decode_state(&mut other_emulator, &state).unwrap();
+
+        let mapper = &other_emulator.memory.cartridge_mapper;
+        assert_eq!(mapper.get_rom_bank(), 0x23);
+        assert_eq!(mapper.get_ram_bank(), 2);
+        assert_eq!(mapper.banking_mode(), 1);
+        assert!(mapper.ram_enabled());
+    }
+
+    #[test]
+    fn should_reject_bess_state_for_different_cartridge() {
+        let emulator = setup_emulator_with_rom(build_titled_rom());
+        let state = as_foreign_state(encode_state(&emulator));
+
+        let mut other_emulator = setup_emulator_with_rom(build_rom(CART_TYPE_MBC5_RAM, ROM_SIZE_128KB, RAM_SIZE_8KB));
+        assert!(decode_state(&mut other_emulator, &state).is_err());
+    }
+}
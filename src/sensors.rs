@@ -0,0 +1,141 @@
+use crate::emulator::Emulator;
+
+/*
+    Host-side source for cartridges with built-in sensors. Accelerometer values are
+    measured in g along the screen axes (positive X tilts right, positive Y tilts
+    towards the player) and ambient light ranges from 0.0 (dark) to 1.0 (bright).
+*/
+pub trait SensorInput: Send {
+    fn accelerometer_x(&self) -> f32;
+    fn accelerometer_y(&self) -> f32;
+    fn ambient_light(&self) -> f32;
+}
+
+pub struct NoSensorInput;
+
+impl SensorInput for NoSensorInput {
+    fn accelerometer_x(&self) -> f32 {
+        0.0
+    }
+
+    fn accelerometer_y(&self) -> f32 {
+        0.0
+    }
+
+    fn ambient_light(&self) -> f32 {
+        0.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SensorReadings {
+    pub accelerometer_x: f32,
+    pub accelerometer_y: f32,
+    pub ambient_light: f32
+}
+
+// Fixed readings double as an input, which is handy for frontends that push values from events.
+impl SensorInput for SensorReadings {
+    fn accelerometer_x(&self) -> f32 {
+        self.accelerometer_x
+    }
+
+    fn accelerometer_y(&self) -> f32 {
+        self.accelerometer_y
+    }
+
+    fn ambient_light(&self) -> f32 {
+        self.ambient_light
+    }
+}
+
+pub struct SensorState {
+    pub readings: SensorReadings,
+    pub input: Box<dyn SensorInput>
+}
+
+pub fn initialize_sensors() -> SensorState {
+    SensorState {
+        readings: SensorReadings::default(),
+        input: Box::new(NoSensorInput)
+    }
+}
+
+pub fn set_sensor_input(emulator: &mut Emulator, input: Box<dyn SensorInput>) {
+    emulator.sensors.input = input;
+    sample(emulator);
+}
+
+/*
+    Sensors are sampled once per frame rather than on every cartridge access, so
+    reads stay cheap and a game sees consistent values within a frame.
+*/
+pub fn sample(emulator: &mut Emulator) {
+    let input = &emulator.sensors.input;
+    let readings = SensorReadings {
+        accelerometer_x: input.accelerometer_x(),
+        accelerometer_y: input.accelerometer_y(),
+        ambient_light: input.ambient_light().clamp(0.0, 1.0)
+    };
+
+    emulator.sensors.readings = readings;
+    emulator.memory.cartridge_mapper.update_sensors(&readings);
+}
+
+pub fn step(emulator: &mut Emulator, frame_completed: bool) {
+    if frame_completed {
+        sample(emulator);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::initialize_screenless_emulator;
+    use crate::mmu;
+    use crate::mmu::constants::*;
+    use crate::mmu::test_utils::*;
+    use super::*;
+
+    struct BrightRoom;
+
+    impl SensorInput for BrightRoom {
+        fn accelerometer_x(&self) -> f32 {
+            0.25
+        }
+
+        fn accelerometer_y(&self) -> f32 {
+            -0.5
+        }
+
+        fn ambient_light(&self) -> f32 {
+            1.5
+        }
+    }
+
+    #[test]
+    fn should_sample_sensor_input_on_frame_completion() {
+        let mut emulator = initialize_screenless_emulator();
+        emulator.sensors.input = Box::new(BrightRoom);
+
+        step(&mut emulator, false);
+        assert_eq!(emulator.sensors.readings, SensorReadings::default());
+
+        step(&mut emulator, true);
+        assert_eq!(emulator.sensors.readings, SensorReadings {
+            accelerometer_x: 0.25,
+            accelerometer_y: -0.5,
+            ambient_light: 1.0
+        });
+    }
+
+    #[test]
+    fn should_forward_light_readings_to_huc1_receiver() {
+        let mut emulator = setup_emulator(CART_TYPE_HUC1_RAM_BATTERY, RAM_SIZE_8KB);
+
+        mmu::write_byte(&mut emulator, 0x0000, 0xE);
+        assert_eq!(mmu::read_byte(&mut emulator, 0xA000), 0xC0);
+
+        set_sensor_input(&mut emulator, Box::new(BrightRoom));
+        assert_eq!(mmu::read_byte(&mut emulator, 0xA000), 0xC1);
+    }
+}
@@ -1,6 +1,24 @@
 use crate::emulator::{is_cgb, Emulator};
+use crate::serial::logger::{initialize_serial_logger, SerialLogger};
 use crate::utils::is_bit_set;
 
+/*
+    A peripheral plugged into the link port. Bits are exchanged on every pulse of the
+    clock, whichever side provides it. Devices with their own clock (the Barcode Boy,
+    the Workboy keyboard, etc.) are stepped alongside the Game Boy and decide when to
+    pulse, so they can set their own timing and keep clocking full duplex even while
+    the game isn't listening. A Game Boy that hasn't started a transfer on the
+    external clock ignores the pulse, and the device reads the idle line instead.
+*/
+pub trait SerialDevice: Send {
+    fn exchange_bit(&mut self, outgoing: bool) -> bool;
+
+    // Called every cycle, returning whether the device pulses the clock.
+    fn step_clock(&mut self) -> bool {
+        false
+    }
+}
+
 pub struct SerialState {
     pub data: u8,
     pub clock: u16,
@@ -8,7 +26,9 @@ pub struct SerialState {
     pub is_master: bool,
     pub transfer_enabled: bool,
     pub bits_transferred: u8,
-    pub serial_exchange: fn(bool) -> bool
+    pub serial_exchange: fn(bool) -> bool,
+    pub device: Option<Box<dyn SerialDevice>>,
+    pub logger: SerialLogger
 }
 
 fn serial_disconnected_exchange(_: bool) -> bool {
@@ -25,7 +45,9 @@ pub fn initialize_serial() -> SerialState {
         is_master: false,
         transfer_enabled: false,
         bits_transferred: 0,
-        serial_exchange: serial_disconnected_exchange
+        serial_exchange: serial_disconnected_exchange,
+        device: None,
+        logger: initialize_serial_logger()
     }
 }
 
@@ -44,15 +66,30 @@ fn fire_serial_interrupt(emulator: &mut Emulator) {
 fn exchange_bits(emulator: &mut Emulator) {
     let outgoing_bit = is_bit_set(emulator.serial.data, 7);
     emulator.serial.data <<= 1;
-    let incoming_bit = (emulator.serial.serial_exchange)(outgoing_bit);
+    logger::record_bit(&mut emulator.serial.logger, outgoing_bit);
+    let incoming_bit = match emulator.serial.device.as_mut() {
+        Some(device) => device.exchange_bit(outgoing_bit),
+        None => (emulator.serial.serial_exchange)(outgoing_bit)
+    };
     if incoming_bit {
         emulator.serial.data |= 1;
     }
 }
 
+fn complete_bit(emulator: &mut Emulator) {
+    emulator.serial.bits_transferred += 1;
+    if emulator.serial.bits_transferred >= 8 {
+        emulator.serial.transfer_enabled = false;
+        emulator.serial.bits_transferred = 0;
+        logger::complete_byte(&mut emulator.serial.logger);
+        fire_serial_interrupt(emulator);
+    }
+}
+
 /*
-    This is a very bare bones serial implementation that always assumes there is no
-    serial device connected and doesn't know how to operate in slave mode.
+    This is a very bare bones serial implementation. Without a device connected it
+    assumes nothing is plugged in, and other linked hardware that drives the clock
+    byte by byte (e.g. the DMG-07 adapter) calls exchange_external_byte.
 */
 pub fn step(emulator: &mut Emulator) {
     if emulator.serial.transfer_enabled && emulator.serial.is_master {
@@ -61,19 +98,62 @@ pub fn step(emulator: &mut Emulator) {
         let clock_rate = get_m_cycle_clock_rate(emulator);
         if emulator.serial.clock >= clock_rate {
             emulator.serial.clock = 0;
-
             exchange_bits(emulator);
+            complete_bit(emulator);
+        }
+    }
+    else if !emulator.serial.is_master {
+        step_device_clock(emulator);
+    }
+}
 
-            emulator.serial.bits_transferred += 1;
-            if emulator.serial.bits_transferred >= 8 {
-                emulator.serial.transfer_enabled = false;
-                emulator.serial.bits_transferred = 0;
-                fire_serial_interrupt(emulator);
+fn step_device_clock(emulator: &mut Emulator) {
+    let listening = emulator.serial.transfer_enabled;
+    if let Some(device) = emulator.serial.device.as_mut() {
+        if device.step_clock() {
+            if listening {
+                exchange_bits(emulator);
+                complete_bit(emulator);
+            }
+            else {
+                device.exchange_bit(true);
             }
         }
     }
 }
 
+pub fn connect_serial_device(emulator: &mut Emulator, device: Box<dyn SerialDevice>) {
+    emulator.serial.device = Some(device);
+}
+
+pub fn disconnect_serial_device(emulator: &mut Emulator) {
+    emulator.serial.device = None;
+}
+
+/*
+    Shifts a whole byte in from a device driving the clock, returning the byte shifted
+    out. A Game Boy that hasn't started a transfer on the external clock ignores the
+    clock, so the device reads the idle line instead.
+*/
+#[cfg(feature = "internals")]
+pub fn exchange_external_byte(emulator: &mut Emulator, incoming: u8) -> u8 {
+    if !emulator.serial.transfer_enabled || emulator.serial.is_master {
+        return 0xFF;
+    }
+
+    let outgoing = emulator.serial.data;
+    for bit in (0..8).rev() {
+        logger::record_bit(&mut emulator.serial.logger, is_bit_set(outgoing, bit));
+    }
+    logger::complete_byte(&mut emulator.serial.logger);
+
+    emulator.serial.data = incoming;
+    emulator.serial.transfer_enabled = false;
+    emulator.serial.bits_transferred = 0;
+    fire_serial_interrupt(emulator);
+    outgoing
+}
+
 pub fn get_data(emulator: &Emulator) -> u8 {
     emulator.serial.data
 }
@@ -104,6 +184,25 @@ mod tests {
     use crate::emulator::{initialize_screenless_emulator, Mode};
     use super::*;
 
+    // Clocks a byte out on every other cycle.
+    struct ClockedDevice {
+        outgoing: u8,
+        cycles: u32
+    }
+
+    impl SerialDevice for ClockedDevice {
+        fn exchange_bit(&mut self, _: bool) -> bool {
+            let incoming = is_bit_set(self.outgoing, 7);
+            self.outgoing <<= 1;
+            incoming
+        }
+
+        fn step_clock(&mut self) -> bool {
+            self.cycles += 1;
+            self.cycles.is_multiple_of(2)
+        }
+    }
+
     #[test]
     fn should_get_control_byte() {
         let mut emulator = initialize_screenless_emulator();
@@ -204,4 +303,39 @@ mod tests {
         assert_eq!(emulator.serial.transfer_enabled, false);
         assert_eq!(emulator.interrupts.flags, 0x08);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn should_exchange_bits_full_duplex_on_device_clock() {
+        let mut emulator = initialize_screenless_emulator();
+        connect_serial_device(&mut emulator, Box::new(ClockedDevice { outgoing: 0xA5, cycles: 0 }));
+        set_data(&mut emulator, 0xF0);
+        set_control(&mut emulator, 0x80);
+
+        for _ in 0..16 {
+            step(&mut emulator);
+        }
+
+        assert_eq!(get_data(&emulator), 0xA5);
+        assert!(!emulator.serial.transfer_enabled);
+        assert_eq!(emulator.interrupts.flags, 0x08);
+    }
+
+    #[test]
+    fn should_ignore_device_clock_until_transfer_starts() {
+        let mut emulator = initialize_screenless_emulator();
+        connect_serial_device(&mut emulator, Box::new(ClockedDevice { outgoing: 0x00, cycles: 0 }));
+        set_data(&mut emulator, 0x42);
+
+        for _ in 0..16 {
+            step(&mut emulator);
+        }
+
+        assert_eq!(get_data(&emulator), 0x42);
+        assert_eq!(emulator.interrupts.flags, 0);
+    }
+}
+
+pub mod barcode_boy;
+#[cfg(feature = "internals")]
+pub mod dmg07;
+pub mod logger;
@@ -0,0 +1,198 @@
+use std::collections::VecDeque;
+use std::io::{self, Error, ErrorKind};
+use std::sync::{Arc, Mutex};
+
+use crate::serial::SerialDevice;
+
+/*
+    The Barcode Boy reads JAN-13 barcodes for a handful of Japanese games (Battle
+    Space, Monster Maker, Kattobi Road, Family Jockey 2 and Famista 3). Games check
+    it's connected with a handshake clocked by the Game Boy: they send $10 $07 $10 $07
+    and the reader echoes the bytes back one transfer late, answering $FF to the
+    first two. Afterwards the game waits in slave mode, and each scanned barcode is
+    clocked in by the reader as STX ($02), the 13 digits in ASCII, then ETX ($03).
+*/
+const HANDSHAKE: [u8; 4] = [0x10, 0x07, 0x10, 0x07];
+const HANDSHAKE_RESPONSE: [u8; 4] = [0xFF, 0xFF, 0x10, 0x07];
+const START_OF_TEXT: u8 = 0x02;
+const END_OF_TEXT: u8 = 0x03;
+const BARCODE_LENGTH: usize = 13;
+// The reader sends at the Game Boy's normal serial speed, pausing between bytes so the game can start its next transfer.
+const BIT_INTERVAL: u16 = 512;
+const BYTE_GAP: u16 = BIT_INTERVAL * 8;
+
+pub struct BarcodeBoy {
+    handshake_index: usize,
+    connected: bool,
+    outgoing_byte: u8,
+    incoming_byte: u8,
+    bits_transferred: u8,
+    clock: u16,
+    sending: bool,
+    pending: VecDeque<u8>,
+    scans: Arc<Mutex<VecDeque<String>>>
+}
+
+// Frontends keep the scanner to feed barcodes to the reader once it's plugged in.
+#[derive(Clone)]
+pub struct BarcodeScanner {
+    scans: Arc<Mutex<VecDeque<String>>>
+}
+
+pub fn initialize_barcode_boy() -> (BarcodeBoy, BarcodeScanner) {
+    let scans = Arc::new(Mutex::new(VecDeque::new()));
+    let barcode_boy = BarcodeBoy {
+        handshake_index: 0,
+        connected: false,
+        outgoing_byte: 0,
+        incoming_byte: HANDSHAKE_RESPONSE[0],
+        bits_transferred: 0,
+        clock: 0,
+        sending: false,
+        pending: VecDeque::new(),
+        scans: scans.clone()
+    };
+    (barcode_boy, BarcodeScanner { scans })
+}
+
+impl BarcodeScanner {
+    pub fn scan(&self, barcode: &str) -> io::Result<()> {
+        if barcode.len() != BARCODE_LENGTH || !barcode.bytes().all(|byte| byte.is_ascii_digit()) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("Barcodes must be {} digits: {}", BARCODE_LENGTH, barcode)));
+        }
+        self.scans.lock().map_err(|_| Error::other("Barcode scanner is unavailable."))?.push_back(barcode.to_string());
+        Ok(())
+    }
+}
+
+impl BarcodeBoy {
+    #[cfg(feature = "internals")]
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    fn complete_handshake_byte(&mut self, byte: u8) {
+        if byte == HANDSHAKE[self.handshake_index] {
+            self.handshake_index += 1;
+        }
+        else {
+            self.handshake_index = if byte == HANDSHAKE[0] { 1 } else { 0 };
+        }
+
+        if self.handshake_index == HANDSHAKE.len() {
+            self.connected = true;
+            self.handshake_index = 0;
+        }
+        self.incoming_byte = HANDSHAKE_RESPONSE[self.handshake_index];
+    }
+}
+
+impl SerialDevice for BarcodeBoy {
+    fn exchange_bit(&mut self, outgoing: bool) -> bool {
+        if self.bits_transferred == 0 && self.sending {
+            self.incoming_byte = self.pending.pop_front().unwrap_or(0xFF);
+        }
+
+        let incoming = self.incoming_byte & (0x80 >> self.bits_transferred) != 0;
+        self.outgoing_byte = (self.outgoing_byte << 1) | outgoing as u8;
+        self.bits_transferred += 1;
+
+        if self.bits_transferred == 8 {
+            self.bits_transferred = 0;
+            if self.sending {
+                self.sending = false;
+                self.incoming_byte = HANDSHAKE_RESPONSE[0];
+            }
+            else {
+                self.complete_handshake_byte(self.outgoing_byte);
+            }
+        }
+        incoming
+    }
+
+    fn step_clock(&mut self) -> bool {
+        if !self.connected {
+            return false;
+        }
+
+        if self.bits_transferred == 0 && !self.sending {
+            if self.pending.is_empty() {
+                let Some(barcode) = self.scans.lock().ok().and_then(|mut scans| scans.pop_front()) else {
+                    return false;
+                };
+                self.pending.push_back(START_OF_TEXT);
+                self.pending.extend(barcode.bytes());
+                self.pending.push_back(END_OF_TEXT);
+            }
+
+            self.clock += 1;
+            if self.clock < BYTE_GAP {
+                return false;
+            }
+            self.clock = 0;
+            self.sending = true;
+            return true;
+        }
+
+        if !self.sending {
+            return false;
+        }
+
+        self.clock += 1;
+        if self.clock < BIT_INTERVAL {
+            return false;
+        }
+        self.clock = 0;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::{initialize_screenless_emulator, Emulator};
+    use crate::serial;
+    use super::*;
+
+    fn transfer(emulator: &mut Emulator, control: u8, outgoing: u8) -> u8 {
+        serial::set_data(emulator, outgoing);
+        serial::set_control(emulator, control);
+        while emulator.serial.transfer_enabled {
+            serial::step(emulator);
+        }
+        serial::get_data(emulator)
+    }
+
+    #[test]
+    fn should_echo_handshake_one_byte_late() {
+        let mut emulator = initialize_screenless_emulator();
+        let (barcode_boy, _) = initialize_barcode_boy();
+        serial::connect_serial_device(&mut emulator, Box::new(barcode_boy));
+
+        let responses: Vec<u8> = HANDSHAKE.iter().map(|byte| transfer(&mut emulator, 0x81, *byte)).collect();
+        assert_eq!(responses, HANDSHAKE_RESPONSE);
+    }
+
+    #[test]
+    fn should_clock_scanned_barcode_into_game_boy() {
+        let mut emulator = initialize_screenless_emulator();
+        let (barcode_boy, scanner) = initialize_barcode_boy();
+        serial::connect_serial_device(&mut emulator, Box::new(barcode_boy));
+
+        for byte in HANDSHAKE {
+            transfer(&mut emulator, 0x81, byte);
+        }
+        scanner.scan("4902370501476").unwrap();
+
+        let received: Vec<u8> = (0..BARCODE_LENGTH + 2).map(|_| transfer(&mut emulator, 0x80, 0x00)).collect();
+        assert_eq!(received[0], START_OF_TEXT);
+        assert_eq!(&received[1..=BARCODE_LENGTH], b"4902370501476");
+        assert_eq!(received[BARCODE_LENGTH + 1], END_OF_TEXT);
+    }
+
+    #[test]
+    fn should_reject_invalid_barcodes() {
+        let (_, scanner) = initialize_barcode_boy();
+        assert!(scanner.scan("12345").is_err());
+        assert!(scanner.scan("49023705014AB").is_err());
+    }
+}
@@ -0,0 +1,284 @@
+use std::io::{self, Error, ErrorKind};
+
+use crate::cycles::T_CYCLES_PER_FRAME;
+use crate::emulator::{self, Emulator};
+use crate::serial;
+
+/*
+    The DMG-07 4-player adapter drives the serial clock for up to four Game Boys,
+    which all transfer in slave mode. It starts in the ping phase, repeatedly sending
+    each Game Boy a packet of a header followed by three copies of a status byte
+    (its player number in the low bits, and which players have answered in the high
+    nibble). Player 1 answers with the RATE and SIZE it wants, and ends the phase by
+    answering a whole packet with $AA, which the adapter confirms with four $CC bytes.
+
+    In the transmission phase the adapter repeatedly sends a round of 4 * SIZE bytes
+    holding every player's packet from the previous round, while collecting each
+    player's next packet from the first SIZE bytes they send back. Player 1 sending
+    nothing but $FF for a whole round returns the adapter to the ping phase.
+
+    Bytes are exchanged at the speed of the standard serial clock rather than the
+    RATE requested by the game, which only affects how long a round takes.
+*/
+pub const MAX_PLAYERS: usize = 4;
+
+const PING_HEADER: u8 = 0xFE;
+const PING_ACK: u8 = 0x88;
+const START_TRANSMISSION: u8 = 0xAA;
+const TRANSMISSION_CONFIRMATION: u8 = 0xCC;
+const RESTART: u8 = 0xFF;
+const PACKET_LENGTH: usize = 4;
+
+// 8 bits at 8192Hz.
+const BYTE_INTERVAL_CYCLES: u32 = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdapterPhase {
+    Ping,
+    Confirmation,
+    Transmission
+}
+
+#[derive(Debug)]
+pub struct Dmg07Adapter {
+    pub phase: AdapterPhase,
+    pub rate: u8,
+    pub size: u8,
+    connected_players: u8,
+    byte_index: usize,
+    start_requests: usize,
+    restart_requests: usize,
+    round: Vec<u8>,
+    next_round: Vec<u8>,
+    cycles_until_byte: u32,
+    overshoot_cycles: [u32; MAX_PLAYERS]
+}
+
+pub fn initialize_dmg07_adapter() -> Dmg07Adapter {
+    Dmg07Adapter {
+        phase: AdapterPhase::Ping,
+        rate: 0,
+        size: 1,
+        connected_players: 0,
+        byte_index: 0,
+        start_requests: 0,
+        restart_requests: 0,
+        round: Vec::new(),
+        next_round: Vec::new(),
+        cycles_until_byte: BYTE_INTERVAL_CYCLES,
+        overshoot_cycles: [0; MAX_PLAYERS]
+    }
+}
+
+fn status_byte(adapter: &Dmg07Adapter, player: usize) -> u8 {
+    (adapter.connected_players << 4) | (player as u8 + 1)
+}
+
+fn round_length(adapter: &Dmg07Adapter) -> usize {
+    adapter.size.max(1) as usize * MAX_PLAYERS
+}
+
+fn start_transmission(adapter: &mut Dmg07Adapter) {
+    adapter.phase = AdapterPhase::Transmission;
+    adapter.byte_index = 0;
+    adapter.restart_requests = 0;
+    adapter.round = vec![0; round_length(adapter)];
+    adapter.next_round = vec![0; round_length(adapter)];
+}
+
+fn restart_ping(adapter: &mut Dmg07Adapter) {
+    adapter.phase = AdapterPhase::Ping;
+    adapter.byte_index = 0;
+    adapter.start_requests = 0;
+    adapter.connected_players = 0;
+}
+
+fn exchange_ping_byte(adapter: &mut Dmg07Adapter, emulators: &mut [Emulator]) {
+    let index = adapter.byte_index;
+
+    for (player, emulator) in emulators.iter_mut().enumerate() {
+        let outgoing = if index == 0 { PING_HEADER } else { status_byte(adapter, player) };
+        let response = serial::exchange_external_byte(emulator, outgoing);
+
+        if index == 0 && response == PING_ACK {
+            adapter.connected_players |= 1 << player;
+        }
+
+        if player == 0 {
+            match (index, response) {
+                (_, START_TRANSMISSION) => adapter.start_requests += 1,
+                (2, rate) => adapter.rate = rate,
+                (3, size) => adapter.size = size,
+                _ => ()
+            }
+            if response != START_TRANSMISSION {
+                adapter.start_requests = 0;
+            }
+        }
+    }
+
+    adapter.byte_index = (index + 1) % PACKET_LENGTH;
+    if adapter.start_requests >= PACKET_LENGTH {
+        adapter.phase = AdapterPhase::Confirmation;
+        adapter.byte_index = 0;
+    }
+}
+
+fn exchange_confirmation_byte(adapter: &mut Dmg07Adapter, emulators: &mut [Emulator]) {
+    for emulator in emulators.iter_mut() {
+        serial::exchange_external_byte(emulator, TRANSMISSION_CONFIRMATION);
+    }
+
+    adapter.byte_index += 1;
+    if adapter.byte_index >= PACKET_LENGTH {
+        start_transmission(adapter);
+    }
+}
+
+fn exchange_transmission_byte(adapter: &mut Dmg07Adapter, emulators: &mut [Emulator]) {
+    let index = adapter.byte_index;
+    let size = adapter.size.max(1) as usize;
+    let outgoing = adapter.round[index];
+
+    for (player, emulator) in emulators.iter_mut().enumerate() {
+        let response = serial::exchange_external_byte(emulator, outgoing);
+
+        if index < size {
+            adapter.next_round[player * size + index] = response;
+        }
+
+        if player == 0 {
+            adapter.restart_requests = if response == RESTART { adapter.restart_requests + 1 } else { 0 };
+        }
+    }
+
+    adapter.byte_index += 1;
+    if adapter.byte_index >= round_length(adapter) {
+        adapter.byte_index = 0;
+        std::mem::swap(&mut adapter.round, &mut adapter.next_round);
+        adapter.next_round.fill(0);
+
+        if adapter.restart_requests >= round_length(adapter) {
+            restart_ping(adapter);
+        }
+    }
+}
+
+pub fn exchange_byte(adapter: &mut Dmg07Adapter, emulators: &mut [Emulator]) {
+    match adapter.phase {
+        AdapterPhase::Ping => exchange_ping_byte(adapter, emulators),
+        AdapterPhase::Confirmation => exchange_confirmation_byte(adapter, emulators),
+        AdapterPhase::Transmission => exchange_transmission_byte(adapter, emulators)
+    }
+}
+
+fn run_cycles(emulator: &mut Emulator, cycles: u32) -> u32 {
+    let start_clock_cycles = emulator.cpu.clock.total_clock_cycles;
+    let mut elapsed = 0;
+    while elapsed < cycles {
+        emulator::step(emulator);
+        elapsed = emulator.cpu.clock.total_clock_cycles.wrapping_sub(start_clock_cycles);
+    }
+    elapsed - cycles
+}
+
+/*
+    Runs every linked Game Boy for a frame's worth of cycles, stopping them all at
+    each serial clock so the adapter exchanges bytes with them in lockstep.
+*/
+pub fn run_frame(adapter: &mut Dmg07Adapter, emulators: &mut [Emulator]) -> io::Result<()> {
+    if emulators.is_empty() || emulators.len() > MAX_PLAYERS {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("The DMG-07 links 1 to {} Game Boys, not {}.", MAX_PLAYERS, emulators.len())));
+    }
+
+    let mut elapsed = 0;
+    while elapsed < T_CYCLES_PER_FRAME {
+        let cycles = adapter.cycles_until_byte.min(T_CYCLES_PER_FRAME - elapsed);
+
+        for (player, emulator) in emulators.iter_mut().enumerate() {
+            let overshoot = adapter.overshoot_cycles[player];
+            adapter.overshoot_cycles[player] = if overshoot >= cycles {
+                overshoot - cycles
+            }
+            else {
+                run_cycles(emulator, cycles - overshoot)
+            };
+        }
+
+        elapsed += cycles;
+        adapter.cycles_until_byte -= cycles;
+        if adapter.cycles_until_byte == 0 {
+            exchange_byte(adapter, emulators);
+            adapter.cycles_until_byte = BYTE_INTERVAL_CYCLES;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::initialize_screenless_emulator;
+    use super::*;
+
+    fn setup_emulators(count: usize) -> Vec<Emulator> {
+        (0..count).map(|_| initialize_screenless_emulator()).collect()
+    }
+
+    // Every Game Boy starts an external clock transfer with the given byte ready to send.
+    fn exchange(adapter: &mut Dmg07Adapter, emulators: &mut [Emulator], outgoing: &[u8]) -> Vec<u8> {
+        for (emulator, byte) in emulators.iter_mut().zip(outgoing) {
+            serial::set_data(emulator, *byte);
+            serial::set_control(emulator, 0x80);
+        }
+        exchange_byte(adapter, emulators);
+        emulators.iter().map(serial::get_data).collect()
+    }
+
+    #[test]
+    fn should_send_ping_packets_with_player_numbers() {
+        let mut adapter = initialize_dmg07_adapter();
+        let mut emulators = setup_emulators(2);
+
+        assert_eq!(exchange(&mut adapter, &mut emulators, &[PING_ACK, PING_ACK]), vec![PING_HEADER, PING_HEADER]);
+        assert_eq!(exchange(&mut adapter, &mut emulators, &[PING_ACK, PING_ACK]), vec![0x31, 0x32]);
+        assert_eq!(exchange(&mut adapter, &mut emulators, &[0x10, 0x00]), vec![0x31, 0x32]);
+        assert_eq!(exchange(&mut adapter, &mut emulators, &[0x02, 0x00]), vec![0x31, 0x32]);
+
+        assert_eq!(adapter.rate, 0x10);
+        assert_eq!(adapter.size, 0x02);
+        assert_eq!(emulators[0].interrupts.flags & 0x8, 0x8);
+    }
+
+    #[test]
+    fn should_relay_packets_between_players_once_transmission_starts() {
+        let mut adapter = initialize_dmg07_adapter();
+        adapter.size = 1;
+        let mut emulators = setup_emulators(2);
+
+        for _ in 0..PACKET_LENGTH {
+            exchange(&mut adapter, &mut emulators, &[START_TRANSMISSION, 0]);
+        }
+        assert_eq!(adapter.phase, AdapterPhase::Confirmation);
+        for _ in 0..PACKET_LENGTH {
+            assert_eq!(exchange(&mut adapter, &mut emulators, &[0, 0]), vec![TRANSMISSION_CONFIRMATION; 2]);
+        }
+        assert_eq!(adapter.phase, AdapterPhase::Transmission);
+
+        exchange(&mut adapter, &mut emulators, &[0x11, 0x22]);
+        for _ in 1..MAX_PLAYERS {
+            exchange(&mut adapter, &mut emulators, &[0, 0]);
+        }
+
+        let received: Vec<Vec<u8>> = (0..MAX_PLAYERS).map(|_| exchange(&mut adapter, &mut emulators, &[0, 0])).collect();
+        assert_eq!(received, vec![vec![0x11, 0x11], vec![0x22, 0x22], vec![0, 0], vec![0, 0]]);
+    }
+
+    #[test]
+    fn should_return_idle_line_from_game_boy_not_transferring() {
+        let mut emulator = initialize_screenless_emulator();
+        serial::set_data(&mut emulator, 0x42);
+        assert_eq!(serial::exchange_external_byte(&mut emulator, 0x12), 0xFF);
+        assert_eq!(serial::get_data(&emulator), 0x42);
+    }
+}
@@ -0,0 +1,96 @@
+use crate::emulator::Emulator;
+
+/*
+    Captures every byte the game shifts out over the link port. Blargg's test ROMs
+    print their results this way, and homebrew can use it for printf debugging.
+    Only the most recent output is kept so a game that talks over the link cable
+    for hours doesn't grow it forever.
+*/
+pub const MAX_SERIAL_OUTPUT_LENGTH: usize = 0x10000;
+
+#[derive(Debug, Default)]
+pub struct SerialLogger {
+    pub output: Vec<u8>,
+    outgoing_byte: u8
+}
+
+pub fn initialize_serial_logger() -> SerialLogger {
+    SerialLogger::default()
+}
+
+pub fn record_bit(logger: &mut SerialLogger, bit: bool) {
+    logger.outgoing_byte = (logger.outgoing_byte << 1) | bit as u8;
+}
+
+pub fn complete_byte(logger: &mut SerialLogger) {
+    if logger.output.len() >= MAX_SERIAL_OUTPUT_LENGTH {
+        logger.output.drain(..MAX_SERIAL_OUTPUT_LENGTH / 2);
+    }
+    logger.output.push(logger.outgoing_byte);
+    logger.outgoing_byte = 0;
+}
+
+pub fn serial_output(emulator: &Emulator) -> String {
+    String::from_utf8_lossy(&emulator.serial.logger.output).into_owned()
+}
+
+pub fn take_serial_output(emulator: &mut Emulator) -> String {
+    let output = serial_output(emulator);
+    emulator.serial.logger.output.clear();
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::initialize_screenless_emulator;
+    use crate::serial;
+    use super::*;
+
+    fn transmit(emulator: &mut Emulator, byte: u8) {
+        serial::set_data(emulator, byte);
+        serial::set_control(emulator, 0x81);
+        while emulator.serial.transfer_enabled {
+            serial::step(emulator);
+        }
+    }
+
+    #[test]
+    fn should_capture_transmitted_bytes() {
+        let mut emulator = initialize_screenless_emulator();
+        for byte in b"Passed\n" {
+            transmit(&mut emulator, *byte);
+        }
+        assert_eq!(serial_output(&emulator), "Passed\n");
+    }
+
+    #[test]
+    fn should_clear_output_once_taken() {
+        let mut emulator = initialize_screenless_emulator();
+        transmit(&mut emulator, b'A');
+        assert_eq!(take_serial_output(&mut emulator), "A");
+        assert_eq!(serial_output(&emulator), "");
+    }
+
+    #[test]
+    fn should_not_capture_bytes_while_waiting_for_external_clock() {
+        let mut emulator = initialize_screenless_emulator();
+        serial::set_data(&mut emulator, b'A');
+        serial::set_control(&mut emulator, 0x80);
+        for _ in 0..0x2000 {
+            serial::step(&mut emulator);
+        }
+        assert!(emulator.serial.logger.output.is_empty());
+    }
+
+    #[test]
+    fn should_keep_most_recent_output() {
+        let mut logger = initialize_serial_logger();
+        for _ in 0..MAX_SERIAL_OUTPUT_LENGTH {
+            complete_byte(&mut logger);
+        }
+        record_bit(&mut logger, true);
+        complete_byte(&mut logger);
+        assert_eq!(logger.output.len(), MAX_SERIAL_OUTPUT_LENGTH / 2 + 1);
+        assert_eq!(logger.output.last(), Some(&1));
+    }
+}
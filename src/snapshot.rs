@@ -0,0 +1,230 @@
+use std::io::{self, Error, ErrorKind};
+use std::mem;
+
+use crate::apu::register_log;
+use crate::emulated_rtc;
+use crate::emulator::Emulator;
+use crate::mmu::CartridgeRamVersion;
+use crate::savestate::{self, StateReader, StateWriter};
+use crate::stats;
+
+/*
+    In-memory snapshots for run-ahead, rewind and rollback, which capture thousands
+    of states a minute. Unlike savestates they have no header, thumbnail or BESS
+    footer, and capturing into an existing snapshot reuses its buffer, so once it has
+    grown to fit a state no further allocations are needed. Snapshots aren't meant to
+    be stored: they can only be restored into the emulator they were taken from (or
+    one running the same cartridge and mode).
+
+    The cartridge RAM (up to 128KB, and rarely written) and MBC6 flash (1MB) come
+    first, so capturing into a snapshot that already holds the same RAM only rewrites
+    what follows it, and restoring a snapshot into an emulator whose RAM hasn't
+    changed since leaves the RAM alone.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct StateSnapshot {
+    data: Vec<u8>,
+    cartridge_ram_version: Option<CartridgeRamVersion>
+}
+
+impl StateSnapshot {
+    pub fn new() -> StateSnapshot {
+        StateSnapshot::default()
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> StateSnapshot {
+        StateSnapshot { data, cartridge_ram_version: None }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    // The length of the mode byte, cartridge RAM and flash, if they already match the emulator's.
+    fn reusable_prefix(&self, emulator: &Emulator) -> usize {
+        let ram_size = emulator.memory.cartridge_mapper.get_cartridge().ram.len();
+        let prefix = 5 + ram_size + savestate::cartridge_flash_size(emulator);
+        let same_ram = self.cartridge_ram_version == Some(emulator.memory.cartridge_ram_version);
+        let same_mode = self.data.first() == Some(&savestate::as_mode_byte(&emulator.mode));
+
+        if same_ram && same_mode && self.data.len() >= prefix && self.data[1..5] == (ram_size as u32).to_le_bytes() {
+            prefix
+        }
+        else {
+            0
+        }
+    }
+}
+
+// Snapshots are equal if they hold the same state, however their cartridge RAM was captured.
+impl PartialEq for StateSnapshot {
+    fn eq(&self, other: &StateSnapshot) -> bool {
+        self.data == other.data
+    }
+}
+
+impl Eq for StateSnapshot {}
+
+pub fn clone_state(emulator: &Emulator, snapshot: &mut StateSnapshot) {
+    let prefix = snapshot.reusable_prefix(emulator);
+    let mut writer = StateWriter::with_buffer_prefix(mem::take(&mut snapshot.data), prefix);
+    if prefix == 0 {
+        writer.write_u8(savestate::as_mode_byte(&emulator.mode));
+        writer.write_sized_bytes(&emulator.memory.cartridge_mapper.get_cartridge().ram);
+        savestate::write_cartridge_flash(&mut writer, emulator);
+    }
+    savestate::write_sections_without_cartridge_ram(&mut writer, emulator);
+    snapshot.data = writer.into_bytes();
+    snapshot.cartridge_ram_version = Some(emulator.memory.cartridge_ram_version);
+}
+
+pub fn take_snapshot(emulator: &Emulator) -> StateSnapshot {
+    let mut snapshot = StateSnapshot::new();
+    clone_state(emulator, &mut snapshot);
+    snapshot
+}
+
+pub fn restore_state(emulator: &mut Emulator, snapshot: &StateSnapshot) -> io::Result<()> {
+    if snapshot.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidInput, "Snapshot is empty."));
+    }
+
+    let mut reader = StateReader::new(&snapshot.data);
+    if reader.read_u8()? != savestate::as_mode_byte(&emulator.mode) {
+        return Err(Error::new(ErrorKind::InvalidData, "Snapshot was taken in a different mode."));
+    }
+    let cartridge_ram = reader.read_sized_bytes()?;
+    let cartridge_flash = reader.read_bytes(savestate::cartridge_flash_size(emulator))?;
+
+    // Keep a copy of the current state so a corrupt snapshot can't leave the emulator half-restored.
+    // The cartridge RAM and flash are only replaced once everything else has been read, so they aren't included.
+    let mut previous_state = StateWriter::new();
+    savestate::write_sections_without_cartridge_ram(&mut previous_state, emulator);
+    let previous_state = previous_state.into_bytes();
+
+    savestate::read_sections_without_cartridge_ram(&mut reader, emulator, savestate::SAVESTATE_VERSION).or_else(|error| {
+        let mut reader = StateReader::new(&previous_state);
+        savestate::read_sections_without_cartridge_ram(&mut reader, emulator, savestate::SAVESTATE_VERSION)?;
+        Err(error)
+    })?;
+
+    if snapshot.cartridge_ram_version != Some(emulator.memory.cartridge_ram_version) {
+        savestate::apply_cartridge_ram(emulator, cartridge_ram);
+        savestate::apply_cartridge_flash(emulator, cartridge_flash);
+    }
+    stats::sync_clock_reference(emulator);
+    emulated_rtc::sync_clock_reference(emulator);
+    register_log::sync_clock_reference(emulator);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::{self, Mode};
+    use crate::mmu;
+    use crate::mmu::constants::*;
+    use crate::mmu::ram_editor;
+    use crate::mmu::test_utils::*;
+    use super::*;
+
+    #[test]
+    fn should_restore_emulator_to_snapshot() {
+        let mut emulator = setup_emulator(CART_TYPE_MBC1_WITH_RAM, RAM_SIZE_8KB);
+        mmu::write_byte(&mut emulator, 0x0000, 0xA);
+        mmu::write_byte(&mut emulator, 0xA010, 0x77);
+        let snapshot = take_snapshot(&emulator);
+        let program_counter = emulator.cpu.registers.program_counter;
+
+        for _ in 0..1000 {
+            emulator::step(&mut emulator);
+        }
+        mmu::write_byte(&mut emulator, 0xA010, 0x00);
+
+        restore_state(&mut emulator, &snapshot).unwrap();
+        assert_eq!(emulator.cpu.registers.program_counter, program_counter);
+        assert_eq!(mmu::read_byte(&mut emulator, 0xA010), 0x77);
+        assert_eq!(take_snapshot(&emulator), snapshot);
+    }
+
+    #[test]
+    fn should_reuse_snapshot_buffer() {
+        let mut emulator = setup_emulator(CART_TYPE_MBC1_WITH_RAM, RAM_SIZE_8KB);
+        let mut snapshot = take_snapshot(&emulator);
+        let buffer = snapshot.as_bytes().as_ptr();
+
+        emulator::step(&mut emulator);
+        clone_state(&emulator, &mut snapshot);
+
+        assert_eq!(snapshot.as_bytes().as_ptr(), buffer);
+    }
+
+    #[test]
+    fn should_capture_cartridge_ram_changes_into_reused_snapshot() {
+        let mut emulator = setup_emulator(CART_TYPE_MBC1_WITH_RAM, RAM_SIZE_8KB);
+        mmu::write_byte(&mut emulator, 0x0000, 0xA);
+        let mut snapshot = take_snapshot(&emulator);
+
+        mmu::write_byte(&mut emulator, 0xA010, 0x55);
+        clone_state(&emulator, &mut snapshot);
+        ram_editor::write_raw_ram(&mut emulator.memory, 0x11, &[0x66]).unwrap();
+        clone_state(&emulator, &mut snapshot);
+
+        mmu::write_byte(&mut emulator, 0xA010, 0x00);
+        mmu::write_byte(&mut emulator, 0xA011, 0x00);
+        restore_state(&mut emulator, &snapshot).unwrap();
+        assert_eq!(mmu::read_byte(&mut emulator, 0xA010), 0x55);
+        assert_eq!(mmu::read_byte(&mut emulator, 0xA011), 0x66);
+        assert_eq!(snapshot, StateSnapshot::from_bytes(take_snapshot(&emulator).into_bytes()));
+    }
+
+    #[test]
+    fn should_restore_flash_without_keeping_it_in_mapper_state() {
+        let mut emulator = setup_emulator_with_rom(build_rom(CART_TYPE_MBC6, ROM_SIZE_128KB, RAM_SIZE_32KB));
+        let mut snapshot = take_snapshot(&emulator);
+
+        emulator.memory.cartridge_mapper.get_flash_mut().unwrap()[0x10] = 0x12;
+        mmu::invalidate_cartridge_ram_version(&mut emulator.memory);
+        clone_state(&emulator, &mut snapshot);
+
+        emulator.memory.cartridge_mapper.get_flash_mut().unwrap()[0x10] = 0xFF;
+        mmu::invalidate_cartridge_ram_version(&mut emulator.memory);
+        restore_state(&mut emulator, &snapshot).unwrap();
+        assert_eq!(emulator.memory.cartridge_mapper.get_flash().unwrap()[0x10], 0x12);
+
+        let mut mapper_state = StateWriter::new();
+        emulator.memory.cartridge_mapper.serialize_state(&mut mapper_state);
+        assert!(mapper_state.into_bytes().len() < 0x100);
+    }
+
+    #[test]
+    fn should_leave_emulator_unchanged_when_snapshot_is_corrupt() {
+        let mut emulator = setup_emulator(CART_TYPE_MBC1_WITH_RAM, RAM_SIZE_8KB);
+        let mut state = take_snapshot(&emulator).into_bytes();
+        state.truncate(state.len() - 16);
+
+        for _ in 0..1000 {
+            emulator::step(&mut emulator);
+        }
+        let current = take_snapshot(&emulator);
+
+        assert!(restore_state(&mut emulator, &StateSnapshot::from_bytes(state)).is_err());
+        assert_eq!(take_snapshot(&emulator), current);
+    }
+
+    #[test]
+    fn should_reject_snapshot_from_different_mode() {
+        let mut emulator = setup_emulator(CART_TYPE_MBC1_WITH_RAM, RAM_SIZE_8KB);
+        let snapshot = take_snapshot(&emulator);
+        emulator.mode = Mode::CGB;
+        assert!(restore_state(&mut emulator, &snapshot).is_err());
+        assert!(restore_state(&mut emulator, &StateSnapshot::new()).is_err());
+    }
+}
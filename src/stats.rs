@@ -0,0 +1,205 @@
+use std::collections::VecDeque;
+
+use crate::apu::CPU_RATE;
+use crate::emulator::Emulator;
+
+pub const DEFAULT_STATS_WINDOW_MILLIS: f64 = 2000.0;
+
+#[derive(Debug, Clone, Copy)]
+struct StatsSample {
+    host_time_millis: f64,
+    frames: u64,
+    instructions: u64,
+    emulated_cycles: u64,
+    audio_underruns: u64,
+    audio_overruns: u64
+}
+
+#[derive(Debug)]
+pub struct StatsState {
+    pub enabled: bool,
+    pub window_millis: f64,
+    instructions_retired: u64,
+    emulated_cycles: u64,
+    last_total_clock_cycles: u32,
+    audio_underruns: u64,
+    audio_overruns: u64,
+    samples: VecDeque<StatsSample>
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct EmulatorStats {
+    pub frames_emulated: u64,
+    pub instructions_retired: u64,
+    pub audio_underruns: u64,
+    pub audio_overruns: u64,
+    // The remaining fields are measured over the sliding window and stay at zero until
+    // the frontend has reported at least two host timestamps.
+    pub frames_per_second: f64,
+    pub instructions_per_second: f64,
+    pub speed_ratio: f64,
+    pub window_audio_underruns: u64,
+    pub window_audio_overruns: u64
+}
+
+pub fn initialize_stats() -> StatsState {
+    StatsState {
+        enabled: true,
+        window_millis: DEFAULT_STATS_WINDOW_MILLIS,
+        instructions_retired: 0,
+        emulated_cycles: 0,
+        last_total_clock_cycles: 0,
+        audio_underruns: 0,
+        audio_overruns: 0,
+        samples: VecDeque::new()
+    }
+}
+
+pub fn step(emulator: &mut Emulator, was_halted: bool) {
+    if !emulator.stats.enabled {
+        return;
+    }
+
+    let total_clock_cycles = emulator.cpu.clock.total_clock_cycles;
+    let stats = &mut emulator.stats;
+
+    if !was_halted {
+        stats.instructions_retired += 1;
+    }
+
+    stats.emulated_cycles += total_clock_cycles.wrapping_sub(stats.last_total_clock_cycles) as u64;
+    stats.last_total_clock_cycles = total_clock_cycles;
+}
+
+// Loading a savestate rewinds the CPU clock, which must not be counted as emulated time.
+pub fn sync_clock_reference(emulator: &mut Emulator) {
+    emulator.stats.last_total_clock_cycles = emulator.cpu.clock.total_clock_cycles;
+}
+
+pub fn record_audio_underrun(emulator: &mut Emulator) {
+    emulator.stats.audio_underruns += 1;
+}
+
+pub fn record_audio_overrun(emulator: &mut Emulator) {
+    emulator.stats.audio_overruns += 1;
+}
+
+/*
+    The core has no notion of wall-clock time (std::time isn't available on wasm),
+    so frontends report the host time periodically, e.g. once per audio buffer or
+    once per rendered frame. Rates are measured between the oldest and newest
+    timestamps within the window.
+*/
+pub fn record_host_time(emulator: &mut Emulator, host_time_millis: f64) {
+    let sample = StatsSample {
+        host_time_millis,
+        frames: emulator.gpu.frame_count,
+        instructions: emulator.stats.instructions_retired,
+        emulated_cycles: emulator.stats.emulated_cycles,
+        audio_underruns: emulator.stats.audio_underruns,
+        audio_overruns: emulator.stats.audio_overruns
+    };
+
+    let stats = &mut emulator.stats;
+    stats.samples.push_back(sample);
+
+    while stats.samples.len() > 2 && stats.samples.front()
+        .is_some_and(|oldest| host_time_millis - oldest.host_time_millis > stats.window_millis) {
+        stats.samples.pop_front();
+    }
+}
+
+pub fn get_stats(emulator: &Emulator) -> EmulatorStats {
+    let stats = &emulator.stats;
+
+    let mut result = EmulatorStats {
+        frames_emulated: emulator.gpu.frame_count,
+        instructions_retired: stats.instructions_retired,
+        audio_underruns: stats.audio_underruns,
+        audio_overruns: stats.audio_overruns,
+        ..EmulatorStats::default()
+    };
+
+    if let (Some(oldest), Some(newest)) = (stats.samples.front(), stats.samples.back()) {
+        let elapsed_millis = newest.host_time_millis - oldest.host_time_millis;
+        if elapsed_millis > 0.0 {
+            let elapsed_seconds = elapsed_millis / 1000.0;
+            let emulated_seconds = (newest.emulated_cycles - oldest.emulated_cycles) as f64 / CPU_RATE as f64;
+
+            result.frames_per_second = (newest.frames - oldest.frames) as f64 / elapsed_seconds;
+            result.instructions_per_second = (newest.instructions - oldest.instructions) as f64 / elapsed_seconds;
+            result.speed_ratio = emulated_seconds / elapsed_seconds;
+            result.window_audio_underruns = newest.audio_underruns - oldest.audio_underruns;
+            result.window_audio_overruns = newest.audio_overruns - oldest.audio_overruns;
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::initialize_screenless_emulator;
+    use super::*;
+
+    fn emulate(emulator: &mut Emulator, frames: u64, cycles: u32, instructions: u64) {
+        emulator.gpu.frame_count += frames;
+        emulator.cpu.clock.total_clock_cycles = emulator.cpu.clock.total_clock_cycles.wrapping_add(cycles);
+        step(emulator, true);
+        for _ in 0..instructions {
+            step(emulator, false);
+        }
+    }
+
+    #[test]
+    fn should_report_totals_without_host_time() {
+        let mut emulator = initialize_screenless_emulator();
+        emulate(&mut emulator, 3, 16, 4);
+        step(&mut emulator, true);
+        record_audio_underrun(&mut emulator);
+
+        let stats = get_stats(&emulator);
+        assert_eq!(stats.frames_emulated, 3);
+        assert_eq!(stats.instructions_retired, 4);
+        assert_eq!(stats.audio_underruns, 1);
+        assert_eq!(stats.speed_ratio, 0.0);
+    }
+
+    #[test]
+    fn should_measure_rates_over_window() {
+        let mut emulator = initialize_screenless_emulator();
+        record_host_time(&mut emulator, 1000.0);
+        emulate(&mut emulator, 30, CPU_RATE / 2, 100);
+        record_audio_overrun(&mut emulator);
+        record_host_time(&mut emulator, 2000.0);
+
+        let stats = get_stats(&emulator);
+        assert_eq!(stats.frames_per_second, 30.0);
+        assert_eq!(stats.instructions_per_second, 100.0);
+        assert_eq!(stats.speed_ratio, 0.5);
+        assert_eq!(stats.window_audio_overruns, 1);
+    }
+
+    #[test]
+    fn should_drop_samples_outside_of_window() {
+        let mut emulator = initialize_screenless_emulator();
+        record_host_time(&mut emulator, 0.0);
+        emulate(&mut emulator, 600, CPU_RATE * 10, 0);
+        record_host_time(&mut emulator, 10000.0);
+        emulate(&mut emulator, 60, CPU_RATE, 0);
+        record_host_time(&mut emulator, 11000.0);
+
+        let stats = get_stats(&emulator);
+        assert_eq!(stats.frames_per_second, 60.0);
+        assert_eq!(stats.speed_ratio, 1.0);
+    }
+
+    #[test]
+    fn should_handle_wrapping_clock() {
+        let mut emulator = initialize_screenless_emulator();
+        emulator.cpu.clock.total_clock_cycles = u32::MAX - 1;
+        emulator.stats.last_total_clock_cycles = u32::MAX - 1;
+        emulate(&mut emulator, 0, 4, 1);
+        assert_eq!(emulator.stats.emulated_cycles, 4);
+    }
+}
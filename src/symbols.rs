@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::io;
+
+use crate::emulator::Emulator;
+use crate::mmu;
+
+/*
+    Symbol files map labels from a game's source to the banked addresses they were
+    assembled to. RGBDS writes one "BB:AAAA Label" line per symbol, and wla-dx writes
+    the same format under a [labels] section alongside other sections we don't need.
+    Addresses are only unique within a bank, so lookups take the bank the address is
+    mapped from as well.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub bank: u16,
+    pub address: u16,
+    pub name: String
+}
+
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+    // Sorted by bank and address, so the closest label before an address can be found.
+    symbols: Vec<Symbol>,
+    by_name: HashMap<String, (u16, u16)>
+}
+
+pub fn initialize_symbols() -> SymbolTable {
+    SymbolTable::default()
+}
+
+fn invalid_symbol_error(line_number: usize, message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Invalid symbol on line {}: {}", line_number, message))
+}
+
+fn parse_symbol_line(line: &str, line_number: usize) -> io::Result<Symbol> {
+    let mut parts = line.split_whitespace();
+    let location = parts.next().unwrap_or_default();
+    let name = parts.next().ok_or_else(|| invalid_symbol_error(line_number, "missing label"))?;
+
+    let (bank, address) = location.split_once(':')
+        .ok_or_else(|| invalid_symbol_error(line_number, "expected BB:AAAA"))?;
+    let bank = u16::from_str_radix(bank, 16).map_err(|_| invalid_symbol_error(line_number, "invalid bank"))?;
+    let address = u16::from_str_radix(address, 16).map_err(|_| invalid_symbol_error(line_number, "invalid address"))?;
+
+    Ok(Symbol { bank, address, name: name.to_string() })
+}
+
+pub fn parse_symbol_file(text: &str) -> io::Result<SymbolTable> {
+    let mut symbols = Vec::new();
+    let mut in_labels_section = true;
+
+    for (index, line) in text.lines().enumerate() {
+        let line = line.split(';').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if line.starts_with('[') {
+            in_labels_section = line.eq_ignore_ascii_case("[labels]");
+        }
+        else if in_labels_section {
+            symbols.push(parse_symbol_line(line, index + 1)?);
+        }
+    }
+
+    symbols.sort_by_key(|symbol| (symbol.bank, symbol.address));
+
+    let by_name = symbols.iter()
+        .map(|symbol| (symbol.name.clone(), (symbol.bank, symbol.address)))
+        .collect();
+
+    Ok(SymbolTable { symbols, by_name })
+}
+
+pub fn load_symbols(emulator: &mut Emulator, text: &str) -> io::Result<()> {
+    emulator.symbols = parse_symbol_file(text)?;
+    Ok(())
+}
+
+#[cfg(feature = "internals")]
+pub fn clear_symbols(emulator: &mut Emulator) {
+    emulator.symbols = initialize_symbols();
+}
+
+// The bank an address is currently mapped from. Unbanked regions are reported as bank 0, as symbol files list them.
+pub fn current_bank(emulator: &Emulator, address: u16) -> u16 {
+    match address {
+        0x4000..=0x7FFF => emulator.memory.cartridge_mapper.get_rom_bank(),
+        0x8000..=0x9FFF => emulator.gpu.registers.cgb_vbk as u16 & 0x1,
+        0xA000..=0xBFFF => emulator.memory.cartridge_mapper.get_ram_bank() as u16,
+        0xD000..=0xDFFF => mmu::get_working_ram_bank(emulator) as u16,
+        _ => 0
+    }
+}
+
+#[cfg(any(test, feature = "internals"))]
+pub fn label_at(table: &SymbolTable, bank: u16, address: u16) -> Option<&str> {
+    let index = table.symbols.binary_search_by_key(&(bank, address), |symbol| (symbol.bank, symbol.address)).ok()?;
+    Some(&table.symbols[index].name)
+}
+
+pub fn address_of(table: &SymbolTable, name: &str) -> Option<(u16, u16)> {
+    table.by_name.get(name).copied()
+}
+
+// The closest label at or before an address in the same bank.
+pub fn symbol_before(table: &SymbolTable, bank: u16, address: u16) -> Option<&Symbol> {
+    let following = table.symbols.partition_point(|symbol| (symbol.bank, symbol.address) <= (bank, address));
+    table.symbols[..following].last().filter(|symbol| symbol.bank == bank)
+}
+
+// Names an address relative to the closest label before it in the same bank, e.g. "Main+3".
+pub fn describe_address(table: &SymbolTable, bank: u16, address: u16) -> Option<String> {
+    let symbol = symbol_before(table, bank, address)?;
+    let offset = address - symbol.address;
+    if offset == 0 {
+        Some(symbol.name.clone())
+    }
+    else {
+        Some(format!("{}+{}", symbol.name, offset))
+    }
+}
+
+pub fn describe_current_address(emulator: &Emulator, address: u16) -> Option<String> {
+    describe_address(&emulator.symbols, current_bank(emulator, address), address)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mmu::constants::*;
+    use crate::mmu::test_utils::*;
+    use super::*;
+
+    const RGBDS_SYMBOLS: &str = "\
+; File generated by rgblink
+00:0150 Start
+00:0153 Start.loop
+01:4000 BankedRoutine
+02:4000 OtherBankedRoutine
+00:c000 wBuffer
+";
+
+    const WLA_SYMBOLS: &str = "\
+; wla symbolic information file
+[labels]
+0000:0150 Start
+0001:4000 BankedRoutine
+
+[definitions]
+00000010 SOME_CONSTANT
+";
+
+    #[test]
+    fn should_parse_rgbds_symbol_file() {
+        let table = parse_symbol_file(RGBDS_SYMBOLS).unwrap();
+        assert_eq!(label_at(&table, 0, 0x0150), Some("Start"));
+        assert_eq!(label_at(&table, 1, 0x4000), Some("BankedRoutine"));
+        assert_eq!(label_at(&table, 2, 0x4000), Some("OtherBankedRoutine"));
+        assert_eq!(label_at(&table, 3, 0x4000), None);
+        assert_eq!(address_of(&table, "wBuffer"), Some((0, 0xC000)));
+    }
+
+    #[test]
+    fn should_only_read_labels_from_wla_symbol_file() {
+        let table = parse_symbol_file(WLA_SYMBOLS).unwrap();
+        assert_eq!(label_at(&table, 1, 0x4000), Some("BankedRoutine"));
+        assert_eq!(address_of(&table, "SOME_CONSTANT"), None);
+    }
+
+    #[test]
+    fn should_describe_address_relative_to_closest_label() {
+        let table = parse_symbol_file(RGBDS_SYMBOLS).unwrap();
+        assert_eq!(describe_address(&table, 0, 0x0153).as_deref(), Some("Start.loop"));
+        assert_eq!(describe_address(&table, 0, 0x0155).as_deref(), Some("Start.loop+2"));
+        assert_eq!(describe_address(&table, 0, 0x0100), None);
+        assert_eq!(describe_address(&table, 2, 0x3FFF), None);
+    }
+
+    #[test]
+    fn should_reject_malformed_symbol_line() {
+        assert!(parse_symbol_file("00:0150").is_err());
+        assert!(parse_symbol_file("0150 Start").is_err());
+        assert!(parse_symbol_file("zz:0150 Start").is_err());
+    }
+
+    #[test]
+    fn should_describe_address_using_mapped_rom_bank() {
+        let mut emulator = setup_emulator(CART_TYPE_MBC5, RAM_SIZE_0KB);
+        load_symbols(&mut emulator, RGBDS_SYMBOLS).unwrap();
+
+        mmu::write_byte(&mut emulator, 0x2000, 0x02);
+        assert_eq!(describe_current_address(&emulator, 0x4001).as_deref(), Some("OtherBankedRoutine+1"));
+        assert_eq!(describe_current_address(&emulator, 0xC000).as_deref(), Some("wBuffer"));
+    }
+}
@@ -0,0 +1,131 @@
+use std::fmt::Write;
+
+/*
+    Converts RGBA frames into text for terminal frontends. Half blocks give each
+    character cell two vertically stacked pixels in 24-bit ANSI color, so the full
+    screen fits in 160x72 cells. Braille packs 2x4 pixels into each character as
+    on/off dots, for terminals without color support, fitting in 80x36 cells.
+*/
+const BYTES_PER_PIXEL: usize = 4;
+const UPPER_HALF_BLOCK: char = '▀';
+const BRAILLE_BASE: u32 = 0x2800;
+// Braille dot bits, indexed by row and then column within the 2x4 cell.
+const BRAILLE_DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+const RESET: &str = "\x1b[0m";
+
+type Rgb = (u8, u8, u8);
+
+fn pixel(frame: &[u8], width: u32, x: u32, y: u32) -> Option<Rgb> {
+    let index = (y * width + x) as usize * BYTES_PER_PIXEL;
+    frame.get(index..index + 3).map(|rgb| (rgb[0], rgb[1], rgb[2]))
+}
+
+fn is_dark((red, green, blue): Rgb) -> bool {
+    // Rec. 601 luma, scaled by 1000.
+    (red as u32 * 299 + green as u32 * 587 + blue as u32 * 114) < 128 * 1000
+}
+
+fn write_half_block_row(output: &mut String, frame: &[u8], width: u32, height: u32, row: u32) {
+    let mut last_colors = None;
+
+    for x in 0..width {
+        let top = pixel(frame, width, x, row * 2).unwrap_or((0, 0, 0));
+        // A frame with an odd height leaves the bottom half of its last row empty.
+        let bottom = if row * 2 + 1 < height { pixel(frame, width, x, row * 2 + 1).unwrap_or((0, 0, 0)) } else { (0, 0, 0) };
+
+        // Consecutive cells often share colors, so escape codes are only written when they change.
+        if last_colors != Some((top, bottom)) {
+            let _ = write!(output, "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m", top.0, top.1, top.2, bottom.0, bottom.1, bottom.2);
+            last_colors = Some((top, bottom));
+        }
+        output.push(UPPER_HALF_BLOCK);
+    }
+
+    output.push_str(RESET);
+}
+
+pub fn half_block_rows(height: u32) -> u32 {
+    height.div_ceil(2)
+}
+
+pub fn render_half_blocks(frame: &[u8], width: u32, height: u32) -> String {
+    let mut output = String::new();
+    for row in 0..half_block_rows(height) {
+        write_half_block_row(&mut output, frame, width, height, row);
+        output.push('\n');
+    }
+    output
+}
+
+/*
+    Redraws only the character rows covering the given scanlines (e.g. the changed
+    scanlines reported for the last frame), moving the cursor to each one. Rows
+    are numbered from the top of the terminal, starting at top_row.
+*/
+pub fn render_half_block_updates(frame: &[u8], width: u32, height: u32, changed_scanlines: &[u8], top_row: u32) -> String {
+    let mut rows: Vec<u32> = changed_scanlines.iter().map(|ly| *ly as u32 / 2).filter(|row| *row < half_block_rows(height)).collect();
+    rows.dedup();
+
+    let mut output = String::new();
+    for row in rows {
+        let _ = write!(output, "\x1b[{};1H", top_row + row);
+        write_half_block_row(&mut output, frame, width, height, row);
+    }
+    output
+}
+
+pub fn render_braille(frame: &[u8], width: u32, height: u32) -> String {
+    let mut output = String::new();
+
+    for cell_y in 0..height.div_ceil(4) {
+        for cell_x in 0..width.div_ceil(2) {
+            let mut dots = 0;
+            for (dot_y, row) in BRAILLE_DOTS.iter().enumerate() {
+                for (dot_x, dot) in row.iter().enumerate() {
+                    let x = cell_x * 2 + dot_x as u32;
+                    let y = cell_y * 4 + dot_y as u32;
+                    if x < width && y < height && pixel(frame, width, x, y).is_some_and(is_dark) {
+                        dots |= dot;
+                    }
+                }
+            }
+            output.push(char::from_u32(BRAILLE_BASE + dots).unwrap_or(' '));
+        }
+        output.push('\n');
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
+    const BLACK: [u8; 4] = [0x00, 0x00, 0x00, 0xFF];
+
+    fn build_frame(pixels: &[[u8; 4]]) -> Vec<u8> {
+        pixels.concat()
+    }
+
+    #[test]
+    fn should_render_two_pixels_per_half_block() {
+        let frame = build_frame(&[BLACK, BLACK, WHITE, WHITE]);
+        let output = render_half_blocks(&frame, 2, 2);
+        assert_eq!(output, "\x1b[38;2;0;0;0m\x1b[48;2;255;255;255m▀▀\x1b[0m\n");
+    }
+
+    #[test]
+    fn should_only_redraw_rows_with_changed_scanlines() {
+        let frame = build_frame(&[WHITE; 8]);
+        let output = render_half_block_updates(&frame, 2, 4, &[2, 3], 5);
+        assert!(output.starts_with("\x1b[6;1H"));
+        assert_eq!(output.matches('▀').count(), 2);
+    }
+
+    #[test]
+    fn should_render_dark_pixels_as_braille_dots() {
+        let frame = build_frame(&[BLACK, WHITE, WHITE, WHITE, WHITE, WHITE, WHITE, BLACK]);
+        assert_eq!(render_braille(&frame, 2, 4), "\u{2881}\n");
+    }
+}
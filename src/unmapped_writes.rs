@@ -0,0 +1,103 @@
+use crate::emulator::Emulator;
+
+/*
+    Records writes to I/O addresses (FF00-FF7F) the emulator doesn't implement, to
+    quickly see whether a game that misbehaves depends on a missing register (e.g.
+    infrared on FF56). Only enabled on request, as games routinely write to unused
+    addresses when clearing the I/O area.
+*/
+const IO_ADDRESS_BASE: u16 = 0xFF00;
+const IO_REGISTER_COUNT: usize = 0x80;
+
+#[derive(Debug)]
+pub struct UnmappedWriteState {
+    pub enabled: bool,
+    counts: [u32; IO_REGISTER_COUNT],
+    last_values: [u8; IO_REGISTER_COUNT]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnmappedWrite {
+    pub address: u16,
+    pub count: u32,
+    pub last_value: u8
+}
+
+pub fn initialize_unmapped_writes() -> UnmappedWriteState {
+    UnmappedWriteState {
+        enabled: false,
+        counts: [0; IO_REGISTER_COUNT],
+        last_values: [0; IO_REGISTER_COUNT]
+    }
+}
+
+pub fn set_unmapped_write_tracking(emulator: &mut Emulator, enabled: bool) {
+    emulator.unmapped_writes.enabled = enabled;
+}
+
+pub fn reset_unmapped_writes(emulator: &mut Emulator) {
+    emulator.unmapped_writes.counts.fill(0);
+    emulator.unmapped_writes.last_values.fill(0);
+}
+
+pub fn record_write(emulator: &mut Emulator, address: u16, value: u8) {
+    let state = &mut emulator.unmapped_writes;
+    if state.enabled {
+        let index = (address.wrapping_sub(IO_ADDRESS_BASE) as usize) & (IO_REGISTER_COUNT - 1);
+        state.counts[index] = state.counts[index].saturating_add(1);
+        state.last_values[index] = value;
+    }
+}
+
+// Addresses written to since tracking started, in address order.
+pub fn get_unmapped_writes(emulator: &Emulator) -> Vec<UnmappedWrite> {
+    let state = &emulator.unmapped_writes;
+    state.counts.iter()
+        .enumerate()
+        .filter(|(_, count)| **count > 0)
+        .map(|(index, count)| UnmappedWrite {
+            address: IO_ADDRESS_BASE + index as u16,
+            count: *count,
+            last_value: state.last_values[index]
+        })
+        .collect()
+}
+
+pub fn describe_unmapped_writes(emulator: &Emulator) -> Vec<String> {
+    get_unmapped_writes(emulator).iter()
+        .map(|write| format!("{:#06X} ({} writes, last {:#04X})", write.address, write.count, write.last_value))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::initialize_screenless_emulator;
+    use crate::mmu;
+    use super::*;
+
+    #[test]
+    fn should_count_writes_to_unimplemented_io_registers() {
+        let mut emulator = initialize_screenless_emulator();
+        set_unmapped_write_tracking(&mut emulator, true);
+
+        mmu::write_byte(&mut emulator, 0xFF56, 0xC0);
+        mmu::write_byte(&mut emulator, 0xFF56, 0xC1);
+        mmu::write_byte(&mut emulator, 0xFF42, 0x10);
+        mmu::write_byte(&mut emulator, 0xFF80, 0x10);
+
+        assert_eq!(get_unmapped_writes(&emulator), vec![UnmappedWrite { address: 0xFF56, count: 2, last_value: 0xC1 }]);
+        assert_eq!(describe_unmapped_writes(&emulator), vec!["0xFF56 (2 writes, last 0xC1)"]);
+    }
+
+    #[test]
+    fn should_not_record_writes_unless_enabled() {
+        let mut emulator = initialize_screenless_emulator();
+        mmu::write_byte(&mut emulator, 0xFF72, 0x01);
+        assert!(get_unmapped_writes(&emulator).is_empty());
+
+        set_unmapped_write_tracking(&mut emulator, true);
+        mmu::write_byte(&mut emulator, 0xFF72, 0x01);
+        reset_unmapped_writes(&mut emulator);
+        assert!(get_unmapped_writes(&emulator).is_empty());
+    }
+}
@@ -1,5 +1,12 @@
 pub mod api;
+pub mod base64;
+pub mod browser_storage;
 pub mod emulator_settings;
+#[cfg(target_arch = "wasm32")]
+pub mod indexed_db;
+#[cfg(target_arch = "wasm32")]
+pub mod local_storage;
 pub mod rom_metadata;
 pub mod wasm_cartridge_effects;
+pub mod wasm_emulator_stats;
 pub mod wasm_rtc_state;
\ No newline at end of file
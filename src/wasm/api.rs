@@ -1,14 +1,45 @@
+use crate::apu::register_log;
+use crate::autosave::{self, AutosavePolicy};
+use crate::breakpoints;
+use crate::call_stack;
 use crate::cheats;
+use crate::code_data_log;
+use crate::debug_hooks;
+use crate::emulated_rtc;
 use crate::emulator;
 use crate::emulator::Emulator;
-use crate::emulator::Mode;
 use crate::emulator::CartridgeHeader;
-use crate::keys::{self, Key};
+use crate::game_config;
+use crate::gpu;
+use crate::hotswap;
+use crate::input_mapping::{self, GamepadButton, GamepadMapping};
+use crate::keys::{self, JoypadState, Key};
+use crate::mmu;
+use crate::overlay;
+use crate::pause;
+use crate::profiler;
+use crate::replay;
+use crate::reverse_step;
+use crate::save_bundle;
+use crate::savestate;
+use crate::sensors::{self, SensorReadings};
+use crate::rom_database;
+use crate::serial::logger;
+use crate::stats;
+use crate::symbols;
+use crate::unmapped_writes;
+use crate::watches;
+use crate::save_slots::SaveSlotManager;
 use crate::wasm::emulator_settings::EmulatorSettings;
+use crate::wasm::browser_storage::{self, BrowserSaveStorage};
+#[cfg(target_arch = "wasm32")]
+use crate::wasm::indexed_db::IndexedDbSaveStorage;
 use crate::wasm::rom_metadata::{RomMetadata, RomMetadataResult};
 use crate::wasm::wasm_cartridge_effects::WasmCartridgeEffects;
+use crate::wasm::wasm_emulator_stats::WasmEmulatorStats;
 use crate::wasm::wasm_rtc_state::WasmRTCState;
 use std::cell::RefCell;
+use std::io;
 use wasm_bindgen::prelude::*;
 
 #[wasm_bindgen]
@@ -44,14 +75,21 @@ fn initialize_web_emulator() -> Emulator {
 
 thread_local! {
     pub static EMULATOR: RefCell<Emulator> = RefCell::new(initialize_web_emulator());
+    static GAMEPAD_MAPPING: RefCell<GamepadMapping> = RefCell::new(GamepadMapping::default());
+    static GAMEPAD_STATE: RefCell<JoypadState> = RefCell::new(JoypadState::default());
 }
 
 extern crate console_error_panic_hook;
 
-fn as_mode(mode_text: &str) -> Mode {
+fn as_hardware_model(mode_text: &str) -> emulator::HardwareModel {
     match mode_text {
-        "DMG" => emulator::Mode::DMG,
-        "CGB" => emulator::Mode::CGB,
+        "DMG0" => emulator::HardwareModel::DMG0,
+        "DMG" => emulator::HardwareModel::DMG,
+        "MGB" => emulator::HardwareModel::MGB,
+        "SGB" => emulator::HardwareModel::SGB,
+        "SGB2" => emulator::HardwareModel::SGB2,
+        "CGB" => emulator::HardwareModel::CGB,
+        "AGB" => emulator::HardwareModel::AGB,
         _ => panic!("Unsupported mode: {}", mode_text)
     }
 }
@@ -60,6 +98,77 @@ fn as_rom_metadata(header: CartridgeHeader) -> RomMetadata {
     RomMetadata::new(header.title, header.has_battery)
 }
 
+// Call before initializeEmulator, as the database is consulted when the ROM is loaded.
+#[wasm_bindgen(js_name = loadRomDatabase)]
+pub fn load_rom_database(text: &str) -> Option<String> {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        rom_database::load_rom_database(&mut emulator, text).err()
+            .map(|error| error.to_string())
+    })
+}
+
+// Call before initializeEmulator, as configs are applied when the ROM is loaded.
+#[wasm_bindgen(js_name = loadGameConfigs)]
+pub fn load_game_configs(text: &str) -> Option<String> {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        game_config::load_game_configs(&mut emulator, text).err()
+            .map(|error| error.to_string())
+    })
+}
+
+#[wasm_bindgen(js_name = exportGameConfigs)]
+pub fn export_game_configs() -> String {
+    EMULATOR.with(|emulator_cell| {
+        let emulator = emulator_cell.borrow();
+        game_config::format_game_configs(&emulator.game_config.configs)
+    })
+}
+
+// Keeps the loaded game configs in browser storage, next to the saves.
+#[wasm_bindgen(js_name = storeGameConfigs)]
+pub fn store_game_configs() -> Option<String> {
+    EMULATOR.with(|emulator_cell| {
+        let emulator = emulator_cell.borrow();
+        browser_storage::browser_save_storage()
+            .and_then(|mut storage| game_config::store_game_configs(&mut storage, &emulator.game_config.configs)).err()
+            .map(|error| error.to_string())
+    })
+}
+
+// Call before initializeEmulator, like loadGameConfigs.
+#[wasm_bindgen(js_name = restoreGameConfigs)]
+pub fn restore_game_configs() -> Option<String> {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        match browser_storage::browser_save_storage().and_then(|storage| game_config::restore_game_configs(&storage)) {
+            Ok(configs) => {
+                emulator.game_config.configs = configs;
+                None
+            },
+            Err(error) => Some(error.to_string())
+        }
+    })
+}
+
+#[cfg(feature = "debug-json")]
+#[wasm_bindgen(js_name = debugJson)]
+pub fn debug_json() -> String {
+    EMULATOR.with(|emulator_cell| {
+        let emulator = emulator_cell.borrow();
+        crate::debug_json::debug_json(&emulator)
+    })
+}
+
+#[wasm_bindgen(js_name = setRomDatabaseEnabled)]
+pub fn set_rom_database_enabled(enabled: bool) {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        rom_database::set_rom_database_enabled(&mut emulator, enabled);
+    })
+}
+
 #[wasm_bindgen(js_name = initializeEmulator)]
 pub fn initialize_emulator(rom_buffer: &[u8], settings: EmulatorSettings) -> RomMetadataResult {
     EMULATOR.with(|emulator_cell: &RefCell<Emulator>| {
@@ -67,7 +176,7 @@ pub fn initialize_emulator(rom_buffer: &[u8], settings: EmulatorSettings) -> Rom
 
         let mut emulator = emulator_cell.borrow_mut();
 
-        emulator::set_mode(&mut emulator, as_mode(settings.mode().as_str()));
+        emulator::set_hardware_model(&mut emulator, as_hardware_model(settings.mode().as_str()));
 
         emulator::set_sample_rate(&mut emulator, settings.audio_sample_rate());
 
@@ -86,6 +195,452 @@ pub fn initialize_emulator(rom_buffer: &[u8], settings: EmulatorSettings) -> Rom
     })
 }
 
+#[wasm_bindgen(js_name = ejectCartridge)]
+pub fn eject_cartridge() {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        hotswap::eject_cartridge(&mut emulator);
+    })
+}
+
+#[wasm_bindgen(js_name = insertCartridge)]
+pub fn insert_cartridge(rom_buffer: &[u8], keep_powered: bool) -> RomMetadataResult {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        match hotswap::insert_cartridge(&mut emulator, rom_buffer, Box::new(WasmCartridgeEffects {}), keep_powered) {
+            Ok(header) => RomMetadataResult::new(None, Some(as_rom_metadata(header))),
+            Err(error) => RomMetadataResult::new(Some(error.to_string()), None)
+        }
+    })
+}
+
+#[wasm_bindgen(js_name = setHighPassFilter)]
+pub fn set_high_pass_filter(filter_text: &str) {
+    let high_pass_filter = match filter_text {
+        "DMG" => emulator::HighPassFilter::Dmg,
+        "CGB" => emulator::HighPassFilter::Cgb,
+        "NONE" => emulator::HighPassFilter::None,
+        _ => panic!("Unsupported high-pass filter: {}", filter_text)
+    };
+
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        emulator::set_high_pass_filter(&mut emulator, high_pass_filter);
+    })
+}
+
+#[wasm_bindgen(js_name = setColorCorrection)]
+pub fn set_color_correction(correction_text: &str) {
+    let color_correction = match correction_text {
+        "RAW" => emulator::ColorCorrection::Raw,
+        "GBC" => emulator::ColorCorrection::Gbc,
+        "GBA" => emulator::ColorCorrection::Gba,
+        _ => panic!("Unsupported color correction: {}", correction_text)
+    };
+
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        emulator::set_color_correction(&mut emulator, color_correction);
+    })
+}
+
+#[wasm_bindgen(js_name = setAccuracyProfile)]
+pub fn set_accuracy_profile(profile_text: &str) {
+    let profile = match profile_text {
+        "FAST" => emulator::AccuracyProfile::Fast,
+        "BALANCED" => emulator::AccuracyProfile::Balanced,
+        _ => panic!("Unsupported accuracy profile: {}", profile_text)
+    };
+
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        emulator::set_accuracy_profile(&mut emulator, profile);
+    })
+}
+
+#[wasm_bindgen(js_name = setSpriteLimitRemoved)]
+pub fn set_sprite_limit_removed(removed: bool) {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        emulator::set_sprite_limit_removed(&mut emulator, removed);
+    })
+}
+
+#[wasm_bindgen(js_name = setExtraCpuCyclesPerFrame)]
+pub fn set_extra_cpu_cycles_per_frame(cycles: u32) {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        emulator::set_extra_cpu_cycles_per_frame(&mut emulator, cycles);
+    })
+}
+
+#[wasm_bindgen(js_name = setAudioBufferSize)]
+pub fn set_audio_buffer_size(samples: usize) {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        emulator::set_audio_buffer_size(&mut emulator, samples);
+    })
+}
+
+#[wasm_bindgen(js_name = getAudioBufferLatencyMillis)]
+pub fn get_audio_buffer_latency_millis() -> f64 {
+    EMULATOR.with(|emulator_cell| {
+        let emulator = emulator_cell.borrow();
+        emulator::get_audio_buffer_latency_millis(&emulator)
+    })
+}
+
+#[wasm_bindgen(js_name = getQueuedAudioMillis)]
+pub fn get_queued_audio_millis() -> f64 {
+    EMULATOR.with(|emulator_cell| {
+        let emulator = emulator_cell.borrow();
+        emulator::get_queued_audio_millis(&emulator)
+    })
+}
+
+#[wasm_bindgen(js_name = getChangedScanlines)]
+pub fn get_changed_scanlines() -> Vec<u8> {
+    EMULATOR.with(|emulator_cell| {
+        let emulator = emulator_cell.borrow();
+        emulator::get_changed_scanlines(&emulator)
+    })
+}
+
+// SCX, SCY, WX and WY for each row of the last frame, four bytes per row.
+#[wasm_bindgen(js_name = getScanlineRegisters)]
+pub fn get_scanline_registers() -> Vec<u8> {
+    EMULATOR.with(|emulator_cell| {
+        let emulator = emulator_cell.borrow();
+        emulator::get_scanline_registers(&emulator)
+            .iter()
+            .flat_map(|line| [line.scx, line.scy, line.wx, line.wy])
+            .collect()
+    })
+}
+
+// Input mask of the buttons held while the last frame was drawn, highest bit Down through lowest bit A.
+#[wasm_bindgen(js_name = getFrameJoypadState)]
+pub fn get_frame_joypad_state() -> u8 {
+    EMULATOR.with(|emulator_cell| {
+        let emulator = emulator_cell.borrow();
+        emulator::get_frame_metadata(&emulator).joypad.mask
+    })
+}
+
+#[wasm_bindgen(js_name = wasLastFrameLagged)]
+pub fn was_last_frame_lagged() -> bool {
+    EMULATOR.with(|emulator_cell| {
+        let emulator = emulator_cell.borrow();
+        emulator::get_frame_metadata(&emulator).lagged
+    })
+}
+
+// Returned as f64 like the emulator stats, so JavaScript gets a number rather than a BigInt.
+#[wasm_bindgen(js_name = getLagFrameCount)]
+pub fn get_lag_frame_count() -> f64 {
+    EMULATOR.with(|emulator_cell| {
+        let emulator = emulator_cell.borrow();
+        emulator::get_lag_frame_count(&emulator) as f64
+    })
+}
+
+#[wasm_bindgen(js_name = isLcdEnabled)]
+pub fn is_lcd_enabled() -> bool {
+    EMULATOR.with(|emulator_cell| {
+        let emulator = emulator_cell.borrow();
+        gpu::lcd_enabled(&emulator)
+    })
+}
+
+#[wasm_bindgen(js_name = requestPause)]
+pub fn request_pause() {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        pause::request_pause(&mut emulator);
+    })
+}
+
+#[wasm_bindgen(js_name = resumeEmulator)]
+pub fn resume_emulator() {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        pause::resume(&mut emulator);
+    })
+}
+
+#[wasm_bindgen(js_name = isPaused)]
+pub fn is_paused() -> bool {
+    EMULATOR.with(|emulator_cell| {
+        let emulator = emulator_cell.borrow();
+        pause::is_paused(&emulator)
+    })
+}
+
+#[wasm_bindgen(js_name = takeSerialOutput)]
+pub fn take_serial_output() -> String {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        logger::take_serial_output(&mut emulator)
+    })
+}
+
+#[wasm_bindgen(js_name = setDebugHooksEnabled)]
+pub fn set_debug_hooks_enabled(enabled: bool) {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        debug_hooks::set_debug_hooks_enabled(&mut emulator, enabled);
+    })
+}
+
+#[wasm_bindgen(js_name = loadSymbols)]
+pub fn load_symbols(text: &str) -> Option<String> {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        symbols::load_symbols(&mut emulator, text).err()
+            .map(|error| error.to_string())
+    })
+}
+
+#[wasm_bindgen(js_name = describeAddress)]
+pub fn describe_address(address: u16) -> Option<String> {
+    EMULATOR.with(|emulator_cell| {
+        let emulator = emulator_cell.borrow();
+        symbols::describe_current_address(&emulator, address)
+    })
+}
+
+#[wasm_bindgen(js_name = addBreakpoint)]
+pub fn add_breakpoint(address: u16, condition: Option<String>) -> Result<u32, String> {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        breakpoints::add_breakpoint(&mut emulator, address, None, condition.as_deref())
+            .map_err(|error| error.to_string())
+    })
+}
+
+#[wasm_bindgen(js_name = addSymbolBreakpoint)]
+pub fn add_symbol_breakpoint(name: &str, condition: Option<String>) -> Result<u32, String> {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        breakpoints::add_symbol_breakpoint(&mut emulator, name, condition.as_deref())
+            .map_err(|error| error.to_string())
+    })
+}
+
+#[wasm_bindgen(js_name = removeBreakpoint)]
+pub fn remove_breakpoint(id: u32) -> bool {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        breakpoints::remove_breakpoint(&mut emulator, id)
+    })
+}
+
+#[wasm_bindgen(js_name = takeBreakpointHit)]
+pub fn take_breakpoint_hit() -> Option<u32> {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        breakpoints::take_breakpoint_hit(&mut emulator)
+    })
+}
+
+#[wasm_bindgen(js_name = setCallStackEnabled)]
+pub fn set_call_stack_enabled(enabled: bool) {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        call_stack::set_call_stack_enabled(&mut emulator, enabled);
+    })
+}
+
+#[wasm_bindgen(js_name = getCallStack)]
+pub fn get_call_stack() -> Vec<String> {
+    EMULATOR.with(|emulator_cell| {
+        let emulator = emulator_cell.borrow();
+        call_stack::describe_call_stack(&emulator)
+    })
+}
+
+#[wasm_bindgen(js_name = setEmulatedRtc)]
+pub fn set_emulated_rtc(multiplier: Option<f64>) {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        emulated_rtc::set_emulated_rtc(&mut emulator, multiplier);
+    })
+}
+
+#[wasm_bindgen(js_name = setUnmappedWriteTracking)]
+pub fn set_unmapped_write_tracking(enabled: bool) {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        unmapped_writes::set_unmapped_write_tracking(&mut emulator, enabled);
+    })
+}
+
+#[wasm_bindgen(js_name = resetUnmappedWrites)]
+pub fn reset_unmapped_writes() {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        unmapped_writes::reset_unmapped_writes(&mut emulator);
+    })
+}
+
+#[wasm_bindgen(js_name = getUnmappedWrites)]
+pub fn get_unmapped_writes() -> Vec<String> {
+    EMULATOR.with(|emulator_cell| {
+        let emulator = emulator_cell.borrow();
+        unmapped_writes::describe_unmapped_writes(&emulator)
+    })
+}
+
+#[wasm_bindgen(js_name = startCodeDataLog)]
+pub fn start_code_data_log() {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        code_data_log::start_code_data_log(&mut emulator);
+    })
+}
+
+#[wasm_bindgen(js_name = stopCodeDataLog)]
+pub fn stop_code_data_log() {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        code_data_log::stop_code_data_log(&mut emulator);
+    })
+}
+
+#[wasm_bindgen(js_name = exportCodeDataLog)]
+pub fn export_code_data_log() -> Vec<u8> {
+    EMULATOR.with(|emulator_cell| {
+        let emulator = emulator_cell.borrow();
+        code_data_log::export_code_data_log(&emulator)
+    })
+}
+
+#[wasm_bindgen(js_name = freezeAddress)]
+pub fn freeze_address(address: u16, value: u8) -> Option<String> {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        watches::freeze_address(&mut emulator, address, value).err()
+            .map(|error| error.to_string())
+    })
+}
+
+#[wasm_bindgen(js_name = unfreezeAddress)]
+pub fn unfreeze_address(address: u16) {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        watches::unfreeze_address(&mut emulator, address);
+    })
+}
+
+#[wasm_bindgen(js_name = addWatch)]
+pub fn add_watch(expression: &str) -> Result<u32, String> {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        watches::add_watch(&mut emulator, expression)
+            .map_err(|error| error.to_string())
+    })
+}
+
+#[wasm_bindgen(js_name = removeWatch)]
+pub fn remove_watch(id: u32) {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        watches::remove_watch(&mut emulator, id);
+    })
+}
+
+// Flattened as id, value pairs.
+#[wasm_bindgen(js_name = evaluateWatches)]
+pub fn evaluate_watches() -> Vec<f64> {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        watches::evaluate_watches(&mut emulator).into_iter()
+            .flat_map(|(id, value)| [id as f64, value as f64])
+            .collect()
+    })
+}
+
+#[wasm_bindgen(js_name = enableReverseStep)]
+pub fn enable_reverse_step(history_instructions: u32) {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        reverse_step::enable_reverse_step(&mut emulator, history_instructions as u64);
+    })
+}
+
+#[wasm_bindgen(js_name = disableReverseStep)]
+pub fn disable_reverse_step() {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        reverse_step::disable_reverse_step(&mut emulator);
+    })
+}
+
+#[wasm_bindgen(js_name = reverseStep)]
+pub fn reverse_step(instructions: u32) -> Option<String> {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        reverse_step::reverse_step(&mut emulator, instructions as u64).err()
+            .map(|error| error.to_string())
+    })
+}
+
+#[wasm_bindgen(js_name = startProfiler)]
+pub fn start_profiler() {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        profiler::start_profiler(&mut emulator);
+    })
+}
+
+#[wasm_bindgen(js_name = stopProfiler)]
+pub fn stop_profiler() {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        profiler::stop_profiler(&mut emulator);
+    })
+}
+
+#[wasm_bindgen(js_name = getProfileReport)]
+pub fn get_profile_report(limit: usize) -> Vec<String> {
+    EMULATOR.with(|emulator_cell| {
+        let emulator = emulator_cell.borrow();
+        profiler::format_profile_report(&profiler::profile_report(&emulator), limit)
+    })
+}
+
+#[wasm_bindgen(js_name = takeDebugMessages)]
+pub fn take_debug_messages() -> Vec<String> {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        debug_hooks::take_debug_messages(&mut emulator)
+    })
+}
+
+#[wasm_bindgen(js_name = startAudioLog)]
+pub fn start_audio_log() {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        register_log::start_register_log(&mut emulator);
+    })
+}
+
+#[wasm_bindgen(js_name = stopAudioLog)]
+pub fn stop_audio_log() {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        register_log::stop_register_log(&mut emulator);
+    })
+}
+
+#[wasm_bindgen(js_name = exportVgm)]
+pub fn export_vgm() -> Vec<u8> {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        register_log::export_vgm(&mut emulator)
+    })
+}
+
 #[wasm_bindgen(js_name = resetEmulator)]
 pub fn reset_emulator() {
     EMULATOR.with(|emulator_cell| {
@@ -98,6 +653,8 @@ pub fn step_until_next_audio_buffer() {
     EMULATOR.with(|emulator_cell| {
         let mut emulator = emulator_cell.borrow_mut();
 
+        stats::record_host_time(&mut emulator, current_time_millis());
+
         let audio_buffers = emulator::step_until_next_audio_buffer(&mut emulator);
         let left_samples_slice = audio_buffers.0;
         let right_samples_slice = audio_buffers.1;
@@ -106,6 +663,16 @@ pub fn step_until_next_audio_buffer() {
     })
 }
 
+// Fills the AudioWorklet's output channels in place, e.g. from its process() callback. Returns the samples emulated.
+#[wasm_bindgen(js_name = fillAudioBuffer)]
+pub fn fill_audio_buffer(left: &mut [f32], right: &mut [f32]) -> usize {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        stats::record_host_time(&mut emulator, current_time_millis());
+        emulator::fill_audio_buffers(&mut emulator, left, right)
+    })
+}
+
 const UP_CODE: &str = "Up";
 const DOWN_CODE: &str = "Down";
 const LEFT_CODE: &str = "Left";
@@ -149,6 +716,49 @@ pub fn release_key(key_code: &str) {
     });
 }
 
+// Takes a navigator.getGamepads() entry as its pressed flags (one per button, in standard mapping order) and axes.
+#[wasm_bindgen(js_name = updateGamepad)]
+pub fn update_gamepad(pressed_buttons: &[u8], axes: &[f32]) {
+    let buttons: Vec<GamepadButton> = pressed_buttons.iter().enumerate()
+        .filter(|(_, pressed)| **pressed != 0)
+        .filter_map(|(index, _)| GamepadButton::from_standard_index(index))
+        .collect();
+    let left_stick = (axes.first().copied().unwrap_or(0.0), axes.get(1).copied().unwrap_or(0.0));
+    let current = GAMEPAD_MAPPING.with(|mapping| mapping.borrow().joypad_state(&buttons, left_stick));
+    let previous = GAMEPAD_STATE.with(|state| state.replace(current));
+
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        for (key, pressed) in input_mapping::key_changes(previous, current) {
+            if pressed {
+                keys::handle_key_press(&mut emulator, &key);
+            }
+            else {
+                keys::handle_key_release(&mut emulator, &key);
+            }
+        }
+    })
+}
+
+// Binds a standard mapping button to a key code as used by pressKey, or unbinds it when no key code is given.
+#[wasm_bindgen(js_name = setGamepadBinding)]
+pub fn set_gamepad_binding(button_index: usize, key_code: Option<String>) {
+    if let Some(button) = GamepadButton::from_standard_index(button_index) {
+        GAMEPAD_MAPPING.with(|mapping| {
+            let mut mapping = mapping.borrow_mut();
+            match key_code.as_deref().and_then(as_maybe_key) {
+                Some(key) => mapping.bind(button, key),
+                None => mapping.unbind(button)
+            }
+        })
+    }
+}
+
+#[wasm_bindgen(js_name = resetGamepadBindings)]
+pub fn reset_gamepad_bindings() {
+    GAMEPAD_MAPPING.with(|mapping| *mapping.borrow_mut() = GamepadMapping::default());
+}
+
 #[wasm_bindgen(js_name = validateGamesharkCode)]
 pub fn validate_gameshark_code(cheat: &str) -> Option<String> {
     cheats::validate_gameshark_code(cheat)
@@ -181,4 +791,251 @@ pub fn unregister_cheat(cheat_id: &str) {
         let mut emulator = emulator_cell.borrow_mut();
         cheats::unregister_cheat(&mut emulator, cheat_id)
     })
-}
\ No newline at end of file
+}
+
+#[wasm_bindgen(js_name = enableReplayBuffer)]
+pub fn enable_replay_buffer() {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        replay::enable_replay_buffer(&mut emulator, replay::DEFAULT_REPLAY_WINDOW_FRAMES)
+    })
+}
+
+#[wasm_bindgen(js_name = disableReplayBuffer)]
+pub fn disable_replay_buffer() {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        replay::disable_replay_buffer(&mut emulator)
+    })
+}
+
+#[wasm_bindgen(js_name = exportReplay)]
+pub fn export_replay() -> Option<Vec<u8>> {
+    EMULATOR.with(|emulator_cell| {
+        let emulator = emulator_cell.borrow();
+        match replay::export_replay(&emulator) {
+            Ok(replay) => Some(replay),
+            Err(error) => {
+                log(&format!("Error exporting replay: {}", error));
+                None
+            }
+        }
+    })
+}
+
+// From now on, keeps save slots, battery saves and game configs in the named IndexedDB database rather than localStorage.
+// Existing localStorage saves are not copied over.
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen(js_name = useIndexedDbStorage)]
+pub async fn use_indexed_db_storage(database_name: String) -> Result<(), JsValue> {
+    let storage = IndexedDbSaveStorage::open(&database_name).await
+        .map_err(|error| JsValue::from_str(&error.to_string()))?;
+    browser_storage::use_indexed_db_storage(storage);
+    Ok(())
+}
+
+fn with_save_slot_manager<T>(emulator: &Emulator, action: impl FnOnce(SaveSlotManager<BrowserSaveStorage>) -> io::Result<T>) -> io::Result<T> {
+    let storage = browser_storage::browser_save_storage()?;
+    let header = &emulator.memory.cartridge_mapper.get_cartridge().header;
+    action(SaveSlotManager::new(storage, header))
+}
+
+#[wasm_bindgen(js_name = listSaveSlots)]
+pub fn list_save_slots() -> Vec<u32> {
+    EMULATOR.with(|emulator_cell| {
+        let emulator = emulator_cell.borrow();
+        with_save_slot_manager(&emulator, |manager| manager.list_slots()).unwrap_or_else(|error| {
+            log(&format!("Error listing save slots: {}", error));
+            Vec::new()
+        })
+    })
+}
+
+// Thumbnails are RGBA at half the screen's resolution (80x72), or empty for states saved without one.
+#[wasm_bindgen(js_name = getSaveSlotThumbnail)]
+pub fn get_save_slot_thumbnail(slot: u32) -> Option<Vec<u8>> {
+    EMULATOR.with(|emulator_cell| {
+        let emulator = emulator_cell.borrow();
+        match with_save_slot_manager(&emulator, |manager| manager.slot_thumbnail(slot)) {
+            Ok(thumbnail) => Some(thumbnail.map(|thumbnail| thumbnail.pixels).unwrap_or_default()),
+            Err(error) => {
+                log(&format!("Error reading save slot thumbnail: {}", error));
+                None
+            }
+        }
+    })
+}
+
+#[wasm_bindgen(js_name = saveStateToSlot)]
+pub fn save_state_to_slot(slot: u32) -> Option<String> {
+    EMULATOR.with(|emulator_cell| {
+        let emulator = emulator_cell.borrow();
+        with_save_slot_manager(&emulator, |mut manager| manager.save_state(&emulator, slot)).err()
+            .map(|error| error.to_string())
+    })
+}
+
+#[wasm_bindgen(js_name = loadStateFromSlot)]
+pub fn load_state_from_slot(slot: u32) -> Option<String> {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        let storage = match browser_storage::browser_save_storage() {
+            Ok(storage) => storage,
+            Err(error) => return Some(error.to_string())
+        };
+        let manager = SaveSlotManager::new(storage, &emulator.memory.cartridge_mapper.get_cartridge().header);
+        manager.load_state(&mut emulator, slot).err()
+            .map(|error| error.to_string())
+    })
+}
+
+#[wasm_bindgen(js_name = setAutosavePolicy)]
+pub fn set_autosave_policy(interval_millis: Option<f64>, flush_on_ram_disable: bool) {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        autosave::set_autosave_policy(&mut emulator, AutosavePolicy { interval_millis, flush_on_ram_disable });
+    })
+}
+
+// Meant to be called every frame. Flushes battery RAM to local storage when it's due.
+#[wasm_bindgen(js_name = autosaveBatteryRam)]
+pub fn autosave_battery_ram() -> Option<String> {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        let storage = match browser_storage::browser_save_storage() {
+            Ok(storage) => storage,
+            Err(error) => return Some(error.to_string())
+        };
+        let mut manager = SaveSlotManager::new(storage, &emulator.memory.cartridge_mapper.get_cartridge().header);
+        autosave::autosave(&mut emulator, &mut manager, current_time_millis()).err()
+            .map(|error| error.to_string())
+    })
+}
+
+#[wasm_bindgen(js_name = deleteSaveSlot)]
+pub fn delete_save_slot(slot: u32) -> Option<String> {
+    EMULATOR.with(|emulator_cell| {
+        let emulator = emulator_cell.borrow();
+        with_save_slot_manager(&emulator, |mut manager| manager.delete_state(slot)).err()
+            .map(|error| error.to_string())
+    })
+}
+
+// Battery RAM as a .sav file, with the RTC appended for cartridges that have one.
+#[wasm_bindgen(js_name = exportBatteryRam)]
+pub fn export_battery_ram() -> Vec<u8> {
+    EMULATOR.with(|emulator_cell| {
+        let emulator = emulator_cell.borrow();
+        mmu::get_battery_save(&emulator.memory)
+    })
+}
+
+#[wasm_bindgen(js_name = importBatteryRam)]
+pub fn import_battery_ram(save: &[u8]) {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        mmu::set_battery_save(&mut emulator.memory, save.to_vec());
+    })
+}
+
+#[wasm_bindgen(js_name = exportState)]
+pub fn export_state() -> Vec<u8> {
+    EMULATOR.with(|emulator_cell| {
+        let emulator = emulator_cell.borrow();
+        savestate::encode_state(&emulator)
+    })
+}
+
+#[wasm_bindgen(js_name = importState)]
+pub fn import_state(state: &[u8]) -> Option<String> {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        savestate::decode_state(&mut emulator, state).err()
+            .map(|error| error.to_string())
+    })
+}
+
+// A zip of the battery save and every savestate slot in local storage for the loaded game.
+#[wasm_bindgen(js_name = exportSaveBundle)]
+pub fn export_save_bundle() -> Option<Vec<u8>> {
+    EMULATOR.with(|emulator_cell| {
+        let emulator = emulator_cell.borrow();
+        match with_save_slot_manager(&emulator, |manager| save_bundle::export_save_bundle(&manager)) {
+            Ok(bundle) => Some(bundle),
+            Err(error) => {
+                log(&format!("Error exporting saves: {}", error));
+                None
+            }
+        }
+    })
+}
+
+// Replaces saves in local storage with the ones in the bundle. Load the battery save again afterwards to use it.
+#[wasm_bindgen(js_name = importSaveBundle)]
+pub fn import_save_bundle(bundle: &[u8]) -> Option<String> {
+    EMULATOR.with(|emulator_cell| {
+        let emulator = emulator_cell.borrow();
+        with_save_slot_manager(&emulator, |mut manager| save_bundle::import_save_bundle(&mut manager, bundle)).err()
+            .map(|error| error.to_string())
+    })
+}
+
+#[wasm_bindgen(js_name = setSensorReadings)]
+pub fn set_sensor_readings(accelerometer_x: f32, accelerometer_y: f32, ambient_light: f32) {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        sensors::set_sensor_input(&mut emulator, Box::new(SensorReadings {
+            accelerometer_x,
+            accelerometer_y,
+            ambient_light
+        }))
+    })
+}
+
+#[wasm_bindgen(js_name = showOverlayMessage)]
+pub fn show_overlay_message(text: &str) {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        overlay::show_message(&mut emulator, text, overlay::DEFAULT_MESSAGE_FRAMES)
+    })
+}
+
+#[wasm_bindgen(js_name = setOverlayLabel)]
+pub fn set_overlay_label(label_id: &str, x: u32, y: u32, text: &str) {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        overlay::set_label(&mut emulator, label_id, x, y, text)
+    })
+}
+
+#[wasm_bindgen(js_name = removeOverlayLabel)]
+pub fn remove_overlay_label(label_id: &str) {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        overlay::remove_label(&mut emulator, label_id)
+    })
+}
+
+#[wasm_bindgen(js_name = getEmulatorStats)]
+pub fn get_emulator_stats() -> WasmEmulatorStats {
+    EMULATOR.with(|emulator_cell| {
+        let emulator = emulator_cell.borrow();
+        emulator::stats(&emulator).into()
+    })
+}
+
+#[wasm_bindgen(js_name = recordAudioUnderrun)]
+pub fn record_audio_underrun() {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        stats::record_audio_underrun(&mut emulator)
+    })
+}
+
+#[wasm_bindgen(js_name = recordAudioOverrun)]
+pub fn record_audio_overrun() {
+    EMULATOR.with(|emulator_cell| {
+        let mut emulator = emulator_cell.borrow_mut();
+        stats::record_audio_overrun(&mut emulator)
+    })
+}
@@ -0,0 +1,73 @@
+use std::io::{self, Error, ErrorKind};
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/*
+    localStorage only holds strings, so data is base64 encoded the same way the
+    web frontend already stores cartridge RAM.
+*/
+pub fn encode_base64(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b0 = chunk[0] as u32;
+        let b1 = chunk.get(1).copied().unwrap_or(0) as u32;
+        let b2 = chunk.get(2).copied().unwrap_or(0) as u32;
+        let triple = (b0 << 16) | (b1 << 8) | b2;
+
+        encoded.push(BASE64_ALPHABET[(triple >> 18) as usize & 0x3F] as char);
+        encoded.push(BASE64_ALPHABET[(triple >> 12) as usize & 0x3F] as char);
+        encoded.push(if chunk.len() > 1 { BASE64_ALPHABET[(triple >> 6) as usize & 0x3F] as char } else { '=' });
+        encoded.push(if chunk.len() > 2 { BASE64_ALPHABET[triple as usize & 0x3F] as char } else { '=' });
+    }
+    encoded
+}
+
+fn decode_base64_char(c: u8) -> io::Result<u32> {
+    BASE64_ALPHABET.iter()
+        .position(|&b| b == c)
+        .map(|index| index as u32)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Stored save data is not valid base64."))
+}
+
+pub fn decode_base64(encoded: &str) -> io::Result<Vec<u8>> {
+    let bytes = encoded.trim_end_matches('=').as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len() * 3 / 4);
+    for chunk in bytes.chunks(4) {
+        if chunk.len() < 2 {
+            return Err(Error::new(ErrorKind::InvalidData, "Stored save data is not valid base64."));
+        }
+
+        let mut triple = 0;
+        for (index, &c) in chunk.iter().enumerate() {
+            triple |= decode_base64_char(c)? << (18 - index * 6);
+        }
+
+        decoded.push((triple >> 16) as u8);
+        if chunk.len() > 2 {
+            decoded.push((triple >> 8) as u8);
+        }
+        if chunk.len() > 3 {
+            decoded.push(triple as u8);
+        }
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_round_trip_base64() {
+        for data in [vec![], vec![0x4D], vec![0x4D, 0x61], vec![0x4D, 0x61, 0x6E], vec![0xFF, 0x00, 0x80, 0x7F, 0x01]] {
+            assert_eq!(decode_base64(&encode_base64(&data)).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn should_encode_base64_compatible_with_btoa() {
+        assert_eq!(encode_base64(b"Man"), "TWFu");
+        assert_eq!(encode_base64(b"Ma"), "TWE=");
+        assert_eq!(encode_base64(b"M"), "TQ==");
+    }
+}
@@ -0,0 +1,87 @@
+use crate::save_slots::SaveStorage;
+#[cfg(target_arch = "wasm32")]
+use crate::wasm::indexed_db::IndexedDbSaveStorage;
+#[cfg(target_arch = "wasm32")]
+use crate::wasm::local_storage::LocalSaveStorage;
+#[cfg(target_arch = "wasm32")]
+use std::cell::RefCell;
+use std::io;
+
+#[cfg(target_arch = "wasm32")]
+thread_local! {
+    static INDEXED_DB_STORAGE: RefCell<Option<IndexedDbSaveStorage>> = const { RefCell::new(None) };
+}
+
+/*
+    Saves go to IndexedDB once it has been opened with use_indexed_db_storage, and
+    to localStorage until then. Neither exists outside the browser, so native builds
+    of the wasm API get storage that fails every access.
+*/
+pub enum BrowserSaveStorage {
+    #[cfg(target_arch = "wasm32")]
+    Local(LocalSaveStorage),
+    #[cfg(target_arch = "wasm32")]
+    IndexedDb(IndexedDbSaveStorage),
+    #[cfg(not(target_arch = "wasm32"))]
+    Unavailable
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn use_indexed_db_storage(storage: IndexedDbSaveStorage) {
+    INDEXED_DB_STORAGE.with(|cell| *cell.borrow_mut() = Some(storage));
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn browser_save_storage() -> io::Result<BrowserSaveStorage> {
+    match INDEXED_DB_STORAGE.with(|cell| cell.borrow().clone()) {
+        Some(storage) => Ok(BrowserSaveStorage::IndexedDb(storage)),
+        None => LocalSaveStorage::new().map(BrowserSaveStorage::Local)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn browser_save_storage() -> io::Result<BrowserSaveStorage> {
+    Ok(BrowserSaveStorage::Unavailable)
+}
+
+impl BrowserSaveStorage {
+    fn storage(&self) -> io::Result<&dyn SaveStorage> {
+        match self {
+            #[cfg(target_arch = "wasm32")]
+            BrowserSaveStorage::Local(storage) => Ok(storage),
+            #[cfg(target_arch = "wasm32")]
+            BrowserSaveStorage::IndexedDb(storage) => Ok(storage),
+            #[cfg(not(target_arch = "wasm32"))]
+            BrowserSaveStorage::Unavailable => Err(io::Error::other("Browser storage is only available in the browser."))
+        }
+    }
+
+    fn storage_mut(&mut self) -> io::Result<&mut dyn SaveStorage> {
+        match self {
+            #[cfg(target_arch = "wasm32")]
+            BrowserSaveStorage::Local(storage) => Ok(storage),
+            #[cfg(target_arch = "wasm32")]
+            BrowserSaveStorage::IndexedDb(storage) => Ok(storage),
+            #[cfg(not(target_arch = "wasm32"))]
+            BrowserSaveStorage::Unavailable => Err(io::Error::other("Browser storage is only available in the browser."))
+        }
+    }
+}
+
+impl SaveStorage for BrowserSaveStorage {
+    fn list(&self, prefix: &str) -> io::Result<Vec<String>> {
+        self.storage()?.list(prefix)
+    }
+
+    fn read(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        self.storage()?.read(key)
+    }
+
+    fn write(&mut self, key: &str, data: &[u8]) -> io::Result<()> {
+        self.storage_mut()?.write(key, data)
+    }
+
+    fn delete(&mut self, key: &str) -> io::Result<()> {
+        self.storage_mut()?.delete(key)
+    }
+}
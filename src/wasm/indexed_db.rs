@@ -0,0 +1,102 @@
+use crate::save_slots::SaveStorage;
+use js_sys::{Array, Promise, Uint8Array};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::io::{self, Error};
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{IdbDatabase, IdbObjectStore, IdbRequest, IdbTransactionMode};
+
+const DATABASE_VERSION: u32 = 1;
+const OBJECT_STORE_NAME: &str = "saves";
+
+/*
+    localStorage is capped at a few megabytes, which a handful of savestates fill
+    up, so browsers that have IndexedDB can keep saves there instead. IndexedDB
+    can only be used asynchronously while SaveStorage is synchronous, so every
+    entry is read into memory once when the database is opened. After that reads
+    come from memory and writes go to memory straight away, with the database
+    updated in the background. IndexedDB runs writes to the same store in the
+    order they were made, so the database ends up matching memory.
+*/
+#[derive(Clone)]
+pub struct IndexedDbSaveStorage {
+    database: IdbDatabase,
+    entries: Rc<RefCell<BTreeMap<String, Vec<u8>>>>
+}
+
+fn as_io_error(_: JsValue) -> Error {
+    Error::other("Unable to access IndexedDB.")
+}
+
+async fn wait_for_request(request: &IdbRequest) -> io::Result<JsValue> {
+    let promise = Promise::new(&mut |resolve, reject| {
+        request.set_onsuccess(Some(&resolve));
+        request.set_onerror(Some(&reject));
+    });
+    JsFuture::from(promise).await.map_err(as_io_error)?;
+    request.result().map_err(as_io_error)
+}
+
+impl IndexedDbSaveStorage {
+    pub async fn open(database_name: &str) -> io::Result<IndexedDbSaveStorage> {
+        let factory = web_sys::window()
+            .ok_or_else(|| Error::other("IndexedDB is only available in the browser."))?
+            .indexed_db()
+            .map_err(as_io_error)?
+            .ok_or_else(|| Error::other("IndexedDB is not available."))?;
+
+        let open_request = factory.open_with_u32(database_name, DATABASE_VERSION).map_err(as_io_error)?;
+        let upgrade_request = open_request.clone();
+        let on_upgrade_needed = Closure::once(move |_: JsValue| {
+            if let Ok(result) = upgrade_request.result() {
+                let _ = result.unchecked_into::<IdbDatabase>().create_object_store(OBJECT_STORE_NAME);
+            }
+        });
+        open_request.set_onupgradeneeded(Some(on_upgrade_needed.as_ref().unchecked_ref()));
+        let database: IdbDatabase = wait_for_request(&open_request).await?.unchecked_into();
+
+        let store = object_store(&database, IdbTransactionMode::Readonly)?;
+        let keys: Array = wait_for_request(&store.get_all_keys().map_err(as_io_error)?).await?.unchecked_into();
+        let values: Array = wait_for_request(&store.get_all().map_err(as_io_error)?).await?.unchecked_into();
+
+        let entries = keys.iter()
+            .zip(values.iter())
+            .filter_map(|(key, value)| Some((key.as_string()?, Uint8Array::new(&value).to_vec())))
+            .collect();
+
+        Ok(IndexedDbSaveStorage { database, entries: Rc::new(RefCell::new(entries)) })
+    }
+}
+
+fn object_store(database: &IdbDatabase, mode: IdbTransactionMode) -> io::Result<IdbObjectStore> {
+    database.transaction_with_str_and_mode(OBJECT_STORE_NAME, mode)
+        .and_then(|transaction| transaction.object_store(OBJECT_STORE_NAME))
+        .map_err(as_io_error)
+}
+
+impl SaveStorage for IndexedDbSaveStorage {
+    fn list(&self, prefix: &str) -> io::Result<Vec<String>> {
+        Ok(self.entries.borrow().keys().filter(|key| key.starts_with(prefix)).cloned().collect())
+    }
+
+    fn read(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.entries.borrow().get(key).cloned())
+    }
+
+    fn write(&mut self, key: &str, data: &[u8]) -> io::Result<()> {
+        let store = object_store(&self.database, IdbTransactionMode::Readwrite)?;
+        store.put_with_key(&Uint8Array::from(data), &JsValue::from_str(key)).map_err(as_io_error)?;
+        self.entries.borrow_mut().insert(key.to_string(), data.to_vec());
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &str) -> io::Result<()> {
+        let store = object_store(&self.database, IdbTransactionMode::Readwrite)?;
+        store.delete(&JsValue::from_str(key)).map_err(as_io_error)?;
+        self.entries.borrow_mut().remove(key);
+        Ok(())
+    }
+}
@@ -0,0 +1,53 @@
+use crate::save_slots::SaveStorage;
+use crate::wasm::base64::{decode_base64, encode_base64};
+use std::io::{self, Error};
+use web_sys::Storage;
+
+fn as_io_error(_: wasm_bindgen::JsValue) -> Error {
+    Error::other("Unable to access localStorage.")
+}
+
+pub struct LocalSaveStorage {
+    storage: Storage
+}
+
+impl LocalSaveStorage {
+    pub fn new() -> io::Result<LocalSaveStorage> {
+        let window = web_sys::window()
+            .ok_or_else(|| Error::other("localStorage is only available in the browser."))?;
+        let storage = window.local_storage()
+            .map_err(as_io_error)?
+            .ok_or_else(|| Error::other("localStorage is not available."))?;
+        Ok(LocalSaveStorage { storage })
+    }
+}
+
+impl SaveStorage for LocalSaveStorage {
+    fn list(&self, prefix: &str) -> io::Result<Vec<String>> {
+        let length = self.storage.length().map_err(as_io_error)?;
+        let mut keys = Vec::new();
+        for index in 0..length {
+            if let Some(key) = self.storage.key(index).map_err(as_io_error)? {
+                if key.starts_with(prefix) {
+                    keys.push(key);
+                }
+            }
+        }
+        Ok(keys)
+    }
+
+    fn read(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        match self.storage.get_item(key).map_err(as_io_error)? {
+            Some(encoded) => decode_base64(&encoded).map(Some),
+            None => Ok(None)
+        }
+    }
+
+    fn write(&mut self, key: &str, data: &[u8]) -> io::Result<()> {
+        self.storage.set_item(key, &encode_base64(data)).map_err(as_io_error)
+    }
+
+    fn delete(&mut self, key: &str) -> io::Result<()> {
+        self.storage.remove_item(key).map_err(as_io_error)
+    }
+}
@@ -1,15 +1,11 @@
 use crate::emulator::CartridgeEffects;
 use crate::emulator::RTCState;
-use crate::wasm::api::{current_time_millis, load_rtc_state, save_rtc_state, load_ram, save_ram};
+use crate::wasm::api::{load_rtc_state, save_rtc_state, load_ram, save_ram};
 use crate::wasm::wasm_rtc_state::WasmRTCState;
 
 pub struct WasmCartridgeEffects;
 
 impl CartridgeEffects for WasmCartridgeEffects {
-    fn current_time_millis(&self) -> f64 {
-        current_time_millis()
-    }
-
     fn load_rtc_state(&self, key: &str) -> Option<RTCState> {
         load_rtc_state(key).map(|state| {
             RTCState {
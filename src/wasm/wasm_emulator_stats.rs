@@ -0,0 +1,51 @@
+use crate::stats::EmulatorStats;
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+pub struct WasmEmulatorStats {
+    stats: EmulatorStats
+}
+
+#[wasm_bindgen]
+impl WasmEmulatorStats {
+    #[wasm_bindgen(getter, js_name = framesEmulated)]
+    pub fn frames_emulated(&self) -> f64 {
+        self.stats.frames_emulated as f64
+    }
+
+    #[wasm_bindgen(getter, js_name = instructionsRetired)]
+    pub fn instructions_retired(&self) -> f64 {
+        self.stats.instructions_retired as f64
+    }
+
+    #[wasm_bindgen(getter, js_name = framesPerSecond)]
+    pub fn frames_per_second(&self) -> f64 {
+        self.stats.frames_per_second
+    }
+
+    #[wasm_bindgen(getter, js_name = instructionsPerSecond)]
+    pub fn instructions_per_second(&self) -> f64 {
+        self.stats.instructions_per_second
+    }
+
+    #[wasm_bindgen(getter, js_name = speedRatio)]
+    pub fn speed_ratio(&self) -> f64 {
+        self.stats.speed_ratio
+    }
+
+    #[wasm_bindgen(getter, js_name = audioUnderruns)]
+    pub fn audio_underruns(&self) -> f64 {
+        self.stats.window_audio_underruns as f64
+    }
+
+    #[wasm_bindgen(getter, js_name = audioOverruns)]
+    pub fn audio_overruns(&self) -> f64 {
+        self.stats.window_audio_overruns as f64
+    }
+}
+
+impl From<EmulatorStats> for WasmEmulatorStats {
+    fn from(stats: EmulatorStats) -> Self {
+        WasmEmulatorStats { stats }
+    }
+}
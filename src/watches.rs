@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::io;
+
+use crate::breakpoints::condition::{evaluate, parse_condition, Condition};
+use crate::emulator::Emulator;
+use crate::mmu;
+
+/*
+    Debugger watches. Watch expressions use the same syntax as breakpoint conditions
+    and are evaluated whenever the frontend asks, e.g. to show "[HL]" or "BC + 2" each
+    time the emulator pauses.
+
+    Frozen addresses keep a fixed value no matter what the game writes there, the way
+    trainers lock a life counter. Writes to a frozen address are replaced with the
+    frozen value, so the game always reads back what was frozen. Only RAM can be
+    frozen, as I/O registers have side effects on write.
+*/
+#[derive(Debug)]
+pub struct WatchExpression {
+    pub id: u32,
+    pub expression: String,
+    condition: Condition
+}
+
+#[derive(Debug, Default)]
+pub struct WatchState {
+    pub expressions: Vec<WatchExpression>,
+    pub frozen: HashMap<u16, u8>,
+    next_id: u32
+}
+
+pub fn initialize_watches() -> WatchState {
+    WatchState::default()
+}
+
+pub fn add_watch(emulator: &mut Emulator, expression: &str) -> io::Result<u32> {
+    let condition = parse_condition(expression)?;
+    let watches = &mut emulator.watches;
+    watches.next_id += 1;
+    watches.expressions.push(WatchExpression { id: watches.next_id, expression: expression.to_string(), condition });
+    Ok(watches.next_id)
+}
+
+pub fn remove_watch(emulator: &mut Emulator, id: u32) {
+    emulator.watches.expressions.retain(|watch| watch.id != id);
+}
+
+// The current value of each watch expression, by watch id.
+pub fn evaluate_watches(emulator: &mut Emulator) -> Vec<(u32, i64)> {
+    let expressions = std::mem::take(&mut emulator.watches.expressions);
+    let values = expressions.iter()
+        .map(|watch| (watch.id, evaluate(emulator, &watch.condition)))
+        .collect();
+    emulator.watches.expressions = expressions;
+    values
+}
+
+fn is_freezable(address: u16) -> bool {
+    matches!(address, 0x8000..=0xFDFF | 0xFF80..=0xFFFE)
+}
+
+pub fn freeze_address(emulator: &mut Emulator, address: u16, value: u8) -> io::Result<()> {
+    if !is_freezable(address) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{:#06X} is not RAM and can't be frozen", address)));
+    }
+    emulator.watches.frozen.insert(address, value);
+    mmu::write_byte(emulator, address, value);
+    Ok(())
+}
+
+pub fn unfreeze_address(emulator: &mut Emulator, address: u16) {
+    emulator.watches.frozen.remove(&address);
+}
+
+pub fn apply_freeze(emulator: &Emulator, address: u16, value: u8) -> u8 {
+    let frozen = &emulator.watches.frozen;
+    if frozen.is_empty() {
+        value
+    }
+    else {
+        frozen.get(&address).copied().unwrap_or(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mmu::constants::*;
+    use crate::mmu::test_utils::*;
+    use super::*;
+
+    #[test]
+    fn should_keep_frozen_value_after_writes() {
+        let mut emulator = setup_emulator(CART_TYPE_ROM_ONLY, RAM_SIZE_0KB);
+        freeze_address(&mut emulator, 0xC0A0, 0x63).unwrap();
+        assert_eq!(mmu::read_byte(&mut emulator, 0xC0A0), 0x63);
+
+        mmu::write_byte(&mut emulator, 0xC0A0, 0x00);
+        assert_eq!(mmu::read_byte(&mut emulator, 0xC0A0), 0x63);
+
+        unfreeze_address(&mut emulator, 0xC0A0);
+        mmu::write_byte(&mut emulator, 0xC0A0, 0x00);
+        assert_eq!(mmu::read_byte(&mut emulator, 0xC0A0), 0x00);
+    }
+
+    #[test]
+    fn should_not_freeze_io_registers() {
+        let mut emulator = setup_emulator(CART_TYPE_ROM_ONLY, RAM_SIZE_0KB);
+        assert!(freeze_address(&mut emulator, 0xFF46, 0xC0).is_err());
+        assert!(freeze_address(&mut emulator, 0x2000, 0x01).is_err());
+        assert!(emulator.watches.frozen.is_empty());
+    }
+
+    #[test]
+    fn should_evaluate_watch_expressions() {
+        let mut emulator = setup_emulator(CART_TYPE_ROM_ONLY, RAM_SIZE_0KB);
+        let memory_watch = add_watch(&mut emulator, "[0xC000] + 1").unwrap();
+        let register_watch = add_watch(&mut emulator, "A").unwrap();
+        assert!(add_watch(&mut emulator, "[0xC000").is_err());
+
+        mmu::write_byte(&mut emulator, 0xC000, 0x41);
+        emulator.cpu.registers.a = 0x07;
+        assert_eq!(evaluate_watches(&mut emulator), vec![(memory_watch, 0x42), (register_watch, 0x07)]);
+
+        remove_watch(&mut emulator, memory_watch);
+        assert_eq!(evaluate_watches(&mut emulator), vec![(register_watch, 0x07)]);
+    }
+}
@@ -0,0 +1,87 @@
+// Runs the mooneye LCD-on and STAT acceptance ROMs. The ROMs aren't bundled, so these are ignored by default.
+// Run with `MOONEYE_ROMS_PATH=<mooneye build directory> cargo test --features internals --test mooneye -- --ignored`.
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use retroboy::builder::EmulatorBuilder;
+use retroboy::core::Core;
+use retroboy::debug_hooks;
+use retroboy::emulator::{Emulator, HardwareModel};
+
+// Used when MOONEYE_ROMS_PATH isn't set.
+const MOONEYE_ROMS_PATH: &str = "../mooneye-test-suite/build";
+
+// Every mooneye test finishes well within this, so running out means the ROM hung.
+const MAX_FRAMES: u32 = 60 * 60;
+
+// A passing ROM loads the first Fibonacci numbers into B, C, D, E, H and L before its `ld b,b` breakpoint.
+const PASS_REGISTERS: [u8; 6] = [3, 5, 8, 13, 21, 34];
+
+fn rom_path(name: &str) -> PathBuf {
+    let roms_path = env::var("MOONEYE_ROMS_PATH").unwrap_or_else(|_| MOONEYE_ROMS_PATH.to_string());
+    PathBuf::from(roms_path).join(name)
+}
+
+fn result_registers(emulator: &Emulator) -> [u8; 6] {
+    let registers = &emulator.cpu.registers;
+    [registers.b, registers.c, registers.d, registers.e, registers.h, registers.l]
+}
+
+fn run_mooneye_rom(name: &str, model: HardwareModel) {
+    let path = rom_path(name);
+    let rom = fs::read(&path).unwrap_or_else(|error| panic!("couldn't read {}: {}", path.display(), error));
+
+    let (mut emulator, _) = EmulatorBuilder::new()
+        .with_hardware_model(model)
+        .with_rom(&rom)
+        .build()
+        .expect("ROM should load");
+    debug_hooks::set_debug_hooks_enabled(&mut emulator, true);
+
+    for _ in 0..MAX_FRAMES {
+        emulator.run_frame();
+        if debug_hooks::take_breakpoint_address(&mut emulator).is_some() {
+            assert_eq!(result_registers(&emulator), PASS_REGISTERS, "{} failed", name);
+            return;
+        }
+    }
+
+    panic!("{} didn't finish within {} frames", name, MAX_FRAMES);
+}
+
+#[test]
+#[ignore = "needs the mooneye test ROMs"]
+fn should_pass_lcdon_timing() {
+    run_mooneye_rom("acceptance/ppu/lcdon_timing-GS.gb", HardwareModel::DMG);
+}
+
+#[test]
+#[ignore = "needs the mooneye test ROMs"]
+fn should_pass_lcdon_write_timing() {
+    run_mooneye_rom("acceptance/ppu/lcdon_write_timing-GS.gb", HardwareModel::DMG);
+}
+
+#[test]
+#[ignore = "needs the mooneye test ROMs"]
+fn should_pass_stat_irq_blocking() {
+    run_mooneye_rom("acceptance/ppu/stat_irq_blocking.gb", HardwareModel::DMG);
+}
+
+#[test]
+#[ignore = "needs the mooneye test ROMs"]
+fn should_pass_stat_lyc_onoff() {
+    run_mooneye_rom("acceptance/ppu/stat_lyc_onoff.gb", HardwareModel::DMG);
+}
+
+#[test]
+#[ignore = "needs the mooneye test ROMs"]
+fn should_pass_vblank_stat_intr() {
+    run_mooneye_rom("acceptance/ppu/vblank_stat_intr-GS.gb", HardwareModel::DMG);
+}
+
+#[test]
+#[ignore = "needs the mooneye test ROMs"]
+fn should_pass_vblank_stat_intr_on_cgb() {
+    run_mooneye_rom("misc/ppu/vblank_stat_intr-C.gb", HardwareModel::CGB);
+}